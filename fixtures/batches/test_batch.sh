@@ -0,0 +1,14 @@
+#!/bin/bash
+#tags = ["example"]
+#developer = [ "example", "https://example.org" ]
+#invocation = "batch"
+#ports_separator = ","
+#call_format = "bash {{script}} {{ip_file}} {{port}} {{ip_list}}"
+
+# Runs once with every host with open ports, listed in the file given first.
+file=$1
+ports=$2
+shift 2
+echo "hosts $@"
+echo "ports $ports"
+cat "$file"
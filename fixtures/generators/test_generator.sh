@@ -0,0 +1,10 @@
+#!/bin/bash
+#tags = ["generator", "example"]
+#developer = [ "example", "https://example.org" ]
+#call_format = "bash {{script}}"
+
+# Every non-empty line printed by a generator becomes a target.
+echo 127.0.0.1
+echo
+echo "  192.168.0.0/30  "
+echo localhost
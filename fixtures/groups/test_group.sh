@@ -0,0 +1,11 @@
+#!/bin/bash
+#tags = ["example"]
+#developer = [ "example", "https://example.org" ]
+#ports_separator = ","
+#call_format = "bash {{script}} {{ip}} {{port}} {{ipversion}}"
+#group_call_format = "bash {{script}} {{ip_file}} {{port}} {{ipversion}}"
+
+# Runs once per host, or with --group-scripts once per set of hosts with the
+# same open ports, listed in the file given first.
+echo "ports $2 ipv$3"
+cat "$1" 2>/dev/null || echo "$1"
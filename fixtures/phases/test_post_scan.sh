@@ -0,0 +1,9 @@
+#!/bin/bash
+#tags = ["example"]
+#developer = [ "example", "https://example.org" ]
+#phase = "post_scan"
+#call_format = "bash {{script}} {{hosts}} {{targets}}"
+
+# Runs once after the scan, with the results as JSON on stdin.
+echo "$1 of $2"
+cat
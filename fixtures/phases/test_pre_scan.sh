@@ -0,0 +1,8 @@
+#!/bin/bash
+#tags = ["example"]
+#developer = [ "example", "https://example.org" ]
+#phase = "pre_scan"
+#call_format = "bash {{script}} {{targets}}"
+
+# Runs once before the scan, a failure stops it.
+echo "Scanning $1"
@@ -0,0 +1,8 @@
+#!/bin/bash
+#tags = ["example"]
+#developer = [ "example", "https://example.org" ]
+#service = "postgres"
+#call_format = "bash {{script}} {{ip}} {{port}}"
+
+# Runs only against the ports --service-probes found PostgreSQL on.
+echo "postgres on $1 port $2"
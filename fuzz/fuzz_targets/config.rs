@@ -0,0 +1,14 @@
+//! The config file, `~/.rustscan.toml`.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use rustscan::config;
+use rustscan::input::Config;
+use rustscan::lenient;
+use std::path::Path;
+
+fuzz_target!(|data: &[u8]| {
+    let path = Path::new("config.toml");
+    let (content, _) = lenient::read_text(data, path);
+    let (config, issues) = config::parse::<Config>(&content, path);
+    assert!(config.is_some() || issues.iter().any(|issue| issue.fatal));
+});
@@ -0,0 +1,21 @@
+//! Host files and every other file read line by line.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use rustscan::lenient::{self, MAX_LINE_LENGTH};
+use std::path::Path;
+
+fuzz_target!(|data: &[u8]| {
+    let mut previous = 0;
+    for line in lenient::lines(data, Path::new("hosts.txt")) {
+        let number = match line {
+            Ok(line) => {
+                assert!(line.text.len() <= MAX_LINE_LENGTH);
+                assert!(!line.text.contains('\n'));
+                line.number
+            }
+            Err(issue) => issue.line.unwrap(),
+        };
+        assert_eq!(number, previous + 1);
+        previous = number;
+    }
+});
@@ -0,0 +1,9 @@
+//! The TOML headers of scripts.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use rustscan::scripts::ScriptFile;
+
+fuzz_target!(|data: &[u8]| {
+    let (script, issues) = ScriptFile::parse(data, "fuzz.sh".into());
+    assert!(script.is_some() || issues.iter().any(|issue| issue.fatal));
+});
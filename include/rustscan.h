@@ -0,0 +1,54 @@
+/*
+ * RustScan's scan engine as a C library.
+ *
+ * Build the shared library with:
+ *
+ *     cargo rustc --release --lib --features ffi --crate-type cdylib
+ *
+ * rustscan_start returns at once, the scan runs on a thread of its own.
+ * Poll it with rustscan_poll, collect open ports with rustscan_next while it
+ * runs and after it's done, and release it with rustscan_free. Every
+ * function may be called from any thread, but not on the same scan at once.
+ */
+#ifndef RUSTSCAN_H
+#define RUSTSCAN_H
+
+#include <stdint.h>
+
+#define RUSTSCAN_IP_LENGTH 46
+
+typedef struct RustScanScan RustScanScan;
+
+typedef struct RustScanOpenPort {
+    /* The address, NUL terminated. */
+    char ip[RUSTSCAN_IP_LENGTH];
+    uint16_t port;
+} RustScanOpenPort;
+
+/* Starts scanning targets, comma separated IPs, CIDRs or hosts, on ports
+ * like "22,80,8000-8100", or on every port if ports is NULL or empty. A
+ * batch_size or timeout_ms of 0 picks the command line's default. Returns
+ * NULL if the arguments are invalid, see rustscan_last_error. */
+RustScanScan *rustscan_start(const char *targets, const char *ports,
+                             uint32_t batch_size, uint32_t timeout_ms,
+                             uint8_t tries);
+
+/* Returns 1 once the scan is done, 0 while it runs, -1 if scan is NULL. */
+int rustscan_poll(RustScanScan *scan);
+
+/* Writes an open port not handed out yet to open and returns 1, returns 0
+ * if there's none right now and -1 if scan or open is NULL. */
+int rustscan_next(RustScanScan *scan, RustScanOpenPort *open);
+
+/* Stops the scan once the probes in flight are done. The ports found so far
+ * are still handed out by rustscan_next. */
+void rustscan_cancel(RustScanScan *scan);
+
+/* Cancels the scan, waits for it to stop and frees it. */
+void rustscan_free(RustScanScan *scan);
+
+/* Why the last call on this thread failed, or NULL. Valid until the next
+ * failing call on the same thread. */
+const char *rustscan_last_error(void);
+
+#endif
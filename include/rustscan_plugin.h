@@ -0,0 +1,45 @@
+/*
+ * RustScan plugin interface.
+ *
+ * A plugin is a shared library (.so, .dylib or .dll) placed in the plugins
+ * directory given with --plugins-dir. RustScan loads every library in that
+ * directory and calls rustscan_plugin_init, which must return a pointer to a
+ * RustScanPlugin that stays valid until the library is unloaded.
+ *
+ * Every hook is optional, set the ones the plugin doesn't need to NULL.
+ * Strings returned by hooks are handed back to free_string once RustScan is
+ * done with them, so they may be allocated with any allocator.
+ */
+#ifndef RUSTSCAN_PLUGIN_H
+#define RUSTSCAN_PLUGIN_H
+
+#include <stdint.h>
+
+#define RUSTSCAN_PLUGIN_ABI_VERSION 1
+
+typedef struct RustScanPlugin {
+    /* Must be RUSTSCAN_PLUGIN_ABI_VERSION. */
+    uint32_t abi_version;
+
+    /* Name shown in RustScan's output. */
+    const char *name;
+
+    /* Called before addresses are parsed. Returns extra targets (IPs, CIDRs
+     * or hosts) separated by newlines, or NULL. */
+    char *(*generate_targets)(void);
+
+    /* Called for every open port found. */
+    void (*on_open_port)(const char *ip, uint16_t port);
+
+    /* Called once the scan is done with the results as JSON:
+     * [{"ip": "127.0.0.1", "ports": [22, 80]}]
+     * Returns text for RustScan to print, or NULL. */
+    char *(*render_report)(const char *results_json);
+
+    /* Frees a string returned by one of the hooks above. */
+    void (*free_string)(char *string);
+} RustScanPlugin;
+
+const RustScanPlugin *rustscan_plugin_init(void);
+
+#endif
@@ -0,0 +1,43 @@
+"""Python bindings to the RustScan port scanner.
+
+``scan`` blocks until the scan is done, ``Scan`` gives open ports as they're
+found. ``scan_async`` and ``stream`` do the same for asyncio code, running
+the scan without blocking the event loop.
+"""
+import asyncio
+
+from ._rustscan import HostResult, Scan, __version__, scan
+
+__all__ = ["HostResult", "Scan", "scan", "scan_async", "stream", "__version__"]
+
+# How often stream() looks for new open ports.
+_POLL_INTERVAL = 0.05
+
+
+async def scan_async(targets, ports=None, **options):
+    """Like scan(), without blocking the event loop."""
+    found = Scan(targets, ports, **options)
+    try:
+        while not found.done:
+            await asyncio.sleep(_POLL_INTERVAL)
+        return found.results()
+    finally:
+        found.cancel()
+
+
+async def stream(targets, ports=None, **options):
+    """Yields (ip, port) of open ports as they're found.
+
+    Leaving the loop early, or cancelling the task, cancels the scan.
+    """
+    found = Scan(targets, ports, **options)
+    try:
+        while True:
+            done = found.done
+            while (open_port := found.poll()) is not None:
+                yield open_port
+            if done:
+                return
+            await asyncio.sleep(_POLL_INTERVAL)
+    finally:
+        found.cancel()
@@ -0,0 +1,41 @@
+from typing import AsyncIterator, Iterable, Iterator, List, Optional, Tuple, Union
+
+__version__: str
+
+class HostResult:
+    ip: str
+    ports: List[int]
+
+class Scan(Iterator[Tuple[str, int]]):
+    def __init__(
+        self,
+        targets: Union[str, List[str]],
+        ports: Optional[Iterable[int]] = None,
+        batch_size: Optional[int] = None,
+        timeout: Optional[float] = None,
+        tries: int = 1,
+        udp: bool = False,
+    ) -> None: ...
+    @property
+    def done(self) -> bool: ...
+    def cancel(self) -> None: ...
+    def poll(self) -> Optional[Tuple[str, int]]: ...
+    def results(self) -> List[HostResult]: ...
+    def __next__(self) -> Tuple[str, int]: ...
+    def __enter__(self) -> "Scan": ...
+    def __exit__(self, *args: object) -> bool: ...
+
+def scan(
+    targets: Union[str, List[str]],
+    ports: Optional[Iterable[int]] = None,
+    batch_size: Optional[int] = None,
+    timeout: Optional[float] = None,
+    tries: int = 1,
+    udp: bool = False,
+) -> List[HostResult]: ...
+async def scan_async(
+    targets: Union[str, List[str]], ports: Optional[Iterable[int]] = None, **options: object
+) -> List[HostResult]: ...
+def stream(
+    targets: Union[str, List[str]], ports: Optional[Iterable[int]] = None, **options: object
+) -> AsyncIterator[Tuple[str, int]]: ...
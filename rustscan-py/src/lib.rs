@@ -0,0 +1,224 @@
+//! Python bindings to the RustScan scan engine, the `rustscan._rustscan`
+//! extension module. The `rustscan` package in `python/` wraps it with
+//! asyncio helpers, see its `__init__.py`.
+//!
+//! ```python
+//! import rustscan
+//!
+//! for host in rustscan.scan(["192.168.1.0/24"], ports=range(1, 1025)):
+//!     print(host.ip, host.ports)
+//!
+//! with rustscan.Scan("scanme.example.org") as scan:
+//!     for ip, port in scan:  # as they're found
+//!         print(ip, port)
+//! ```
+//!
+//! Scans run on a thread of their own and wait with the GIL released, so
+//! other Python threads keep running, and Ctrl-C cancels them.
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use rustscan::address::parse_targets;
+use rustscan::scanner::background::BackgroundScan;
+use rustscan::{ScanTiming, ScannerBuilder};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+/// How often a waiting scan checks for results and Ctrl-C.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Targets given as one string, comma separated, or a list of them.
+#[derive(FromPyObject)]
+enum Targets {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Targets {
+    fn resolve(self) -> Vec<IpAddr> {
+        let targets = match self {
+            Targets::One(targets) => vec![targets],
+            Targets::Many(targets) => targets,
+        };
+        parse_targets(targets.iter().flat_map(|target| target.split(',')))
+    }
+}
+
+/// The open ports of a host.
+#[pyclass(frozen, get_all, module = "rustscan")]
+#[derive(Debug, Clone)]
+struct HostResult {
+    ip: String,
+    ports: Vec<u16>,
+}
+
+#[pymethods]
+impl HostResult {
+    fn __repr__(&self) -> String {
+        format!("HostResult(ip={:?}, ports={:?})", self.ip, self.ports)
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.ip == other.ip && self.ports == other.ports
+    }
+}
+
+fn host_results(open: &[SocketAddr]) -> Vec<HostResult> {
+    let mut hosts: BTreeMap<IpAddr, Vec<u16>> = BTreeMap::new();
+    for socket in open {
+        hosts.entry(socket.ip()).or_default().push(socket.port());
+    }
+    hosts
+        .into_iter()
+        .map(|(ip, mut ports)| {
+            ports.sort_unstable();
+            HostResult {
+                ip: ip.to_string(),
+                ports,
+            }
+        })
+        .collect()
+}
+
+/// A running scan. Iterating it gives `(ip, port)` of open ports as they're
+/// found, `results()` waits for the scan and groups them per host.
+#[pyclass(module = "rustscan")]
+struct Scan {
+    scan: BackgroundScan,
+}
+
+impl Scan {
+    /// Waits until `ready` or the scan is done, checking for Ctrl-C, which
+    /// cancels the scan.
+    fn wait<T>(
+        &mut self,
+        py: Python<'_>,
+        mut ready: impl FnMut(&mut BackgroundScan) -> Option<T>,
+    ) -> PyResult<Option<T>> {
+        loop {
+            if let Some(value) = ready(&mut self.scan) {
+                return Ok(Some(value));
+            }
+            if self.scan.is_done() {
+                return Ok(None);
+            }
+            py.allow_threads(|| std::thread::sleep(POLL_INTERVAL));
+            if let Err(e) = py.check_signals() {
+                self.scan.cancel_token().cancel();
+                return Err(e);
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl Scan {
+    /// Starts scanning `targets`, IPs, CIDRs or hosts, on `ports`, or on
+    /// every port. `timeout` is in seconds.
+    #[new]
+    #[pyo3(signature = (targets, ports=None, batch_size=None, timeout=None, tries=1, udp=false))]
+    fn new(
+        targets: Targets,
+        ports: Option<Vec<u16>>,
+        batch_size: Option<usize>,
+        timeout: Option<f64>,
+        tries: u8,
+        udp: bool,
+    ) -> PyResult<Self> {
+        let ips = targets.resolve();
+        if ips.is_empty() {
+            return Err(PyValueError::new_err("no addresses found in targets"));
+        }
+        let default = ScanTiming::default();
+        let timeout = match timeout {
+            Some(timeout) => Duration::try_from_secs_f64(timeout)
+                .map_err(|e| PyValueError::new_err(format!("invalid timeout: {e}")))?,
+            None => default.timeout,
+        };
+        let mut builder = ScannerBuilder::new()
+            .targets(ips)
+            .timing(ScanTiming {
+                batch_size: batch_size.unwrap_or(default.batch_size).max(1),
+                timeout,
+                tries,
+            })
+            .udp(udp);
+        if let Some(ports) = ports {
+            if ports.contains(&0) {
+                return Err(PyValueError::new_err("port 0 can't be scanned"));
+            }
+            builder = builder.ports(ports);
+        }
+        let scan = BackgroundScan::start(builder.build())
+            .map_err(|e| PyRuntimeError::new_err(format!("could not start the scan: {e}")))?;
+        Ok(Self { scan })
+    }
+
+    /// Whether the scan is over, finished or cancelled.
+    #[getter]
+    fn done(&mut self) -> bool {
+        self.scan.is_done()
+    }
+
+    /// Stops the scan once the probes in flight are done, the ports found
+    /// so far are still given out.
+    fn cancel(&self) {
+        self.scan.cancel_token().cancel();
+    }
+
+    /// The next open port found, without waiting, or None.
+    fn poll(&mut self) -> Option<(String, u16)> {
+        self.scan
+            .next_open()
+            .map(|socket| (socket.ip().to_string(), socket.port()))
+    }
+
+    /// Waits for the scan to end and returns the open ports per host.
+    fn results(&mut self, py: Python<'_>) -> PyResult<Vec<HostResult>> {
+        self.wait(py, |_| None::<()>)?;
+        Ok(host_results(self.scan.open_ports().unwrap_or_default()))
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<(String, u16)>> {
+        let next = self.wait(py, BackgroundScan::next_open)?;
+        Ok(next.map(|socket| (socket.ip().to_string(), socket.port())))
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(&self, _exc_type: PyObject, _exc_value: PyObject, _traceback: PyObject) -> bool {
+        self.cancel();
+        false
+    }
+}
+
+/// Scans `targets` on `ports`, or every port, and returns the open ports
+/// per host once done.
+#[pyfunction]
+#[pyo3(signature = (targets, ports=None, batch_size=None, timeout=None, tries=1, udp=false))]
+fn scan(
+    py: Python<'_>,
+    targets: Targets,
+    ports: Option<Vec<u16>>,
+    batch_size: Option<usize>,
+    timeout: Option<f64>,
+    tries: u8,
+    udp: bool,
+) -> PyResult<Vec<HostResult>> {
+    Scan::new(targets, ports, batch_size, timeout, tries, udp)?.results(py)
+}
+
+#[pymodule]
+fn _rustscan(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<HostResult>()?;
+    module.add_class::<Scan>()?;
+    module.add_function(wrap_pyfunction!(scan, module)?)?;
+    module.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
@@ -0,0 +1,52 @@
+"""Run after `maturin develop` with `python -m unittest discover tests`."""
+import asyncio
+import socket
+import unittest
+
+import rustscan
+
+
+def listener():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen()
+    return server, server.getsockname()[1]
+
+
+class ScanTest(unittest.TestCase):
+    def setUp(self):
+        self.server, self.port = listener()
+        self.ports = [self.port, self.port + 1]
+
+    def tearDown(self):
+        self.server.close()
+
+    def test_scan(self):
+        results = rustscan.scan("127.0.0.1", ports=self.ports, timeout=0.5)
+        self.assertIsInstance(results[0], rustscan.HostResult)
+        self.assertEqual([(r.ip, r.ports) for r in results], [("127.0.0.1", [self.port])])
+
+    def test_iterates_open_ports(self):
+        with rustscan.Scan(["127.0.0.1"], ports=self.ports, timeout=0.5) as scan:
+            self.assertEqual(list(scan), [("127.0.0.1", self.port)])
+            self.assertTrue(scan.done)
+
+    def test_asyncio(self):
+        async def run():
+            results = await rustscan.scan_async("127.0.0.1", self.ports, timeout=0.5)
+            streamed = [p async for p in rustscan.stream("127.0.0.1", self.ports, timeout=0.5)]
+            return results, streamed
+
+        results, streamed = asyncio.run(run())
+        self.assertEqual(results[0].ports, [self.port])
+        self.assertEqual(streamed, [("127.0.0.1", self.port)])
+
+    def test_invalid_arguments(self):
+        with self.assertRaises(ValueError):
+            rustscan.scan("not a host..", ports=self.ports)
+        with self.assertRaises(ValueError):
+            rustscan.scan("127.0.0.1", ports=[0])
+
+
+if __name__ == "__main__":
+    unittest.main()
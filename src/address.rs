@@ -1,20 +1,96 @@
 //! Provides functions to parse input IP addresses, CIDRs or files.
 use std::collections::BTreeSet;
-use std::fs::{self, File};
-use std::io::{prelude::*, BufReader};
-use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::SocketAddr;
+use std::net::{IpAddr, ToSocketAddrs};
 use std::path::Path;
 use std::str::FromStr;
 
 use cidr_utils::cidr::{IpCidr, IpInet};
+#[cfg(not(target_arch = "wasm32"))]
 use hickory_resolver::{
     config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
     Resolver,
 };
 use log::debug;
 
+use crate::config::Issue;
 use crate::input::Opts;
-use crate::warning;
+use crate::{detail, lenient, warning};
+use once_cell::sync::Lazy;
+
+/// Stands in for the DNS resolver in wasm32 builds, which have no sockets
+/// to ask one with: there, only IPs and CIDRs are parsed.
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug)]
+pub struct Resolver;
+
+#[cfg(target_arch = "wasm32")]
+impl Resolver {
+    fn lookup_ip(&self, host: &str) -> std::io::Result<Vec<IpAddr>> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("can't resolve {host} without DNS"),
+        ))
+    }
+}
+
+/// Lines of a host file longer than the longest DNS name can't be hosts.
+const MAX_HOST_LENGTH: usize = 253;
+
+/// How many lines of a host file that gave no addresses are reported, the
+/// rest are only counted.
+const MAX_REPORTED_ISSUES: usize = 10;
+
+/// Which of the addresses a hostname resolves to are scanned, see
+/// `--prefer-ipv4`, `--prefer-ipv6` and `--only-ipv6`. Addresses given as
+/// IPs or CIDRs are always scanned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpPreference {
+    /// IPv4 and IPv6 alike.
+    Both,
+    /// IPv4 addresses, or IPv6 ones if there are none.
+    PreferIpv4,
+    /// IPv6 addresses, or IPv4 ones if there are none.
+    PreferIpv6,
+    /// IPv6 addresses only.
+    OnlyIpv6,
+}
+
+impl IpPreference {
+    pub fn from_opts(opts: &Opts) -> Self {
+        if opts.only_ipv6 {
+            Self::OnlyIpv6
+        } else if opts.prefer_ipv6 {
+            Self::PreferIpv6
+        } else if opts.prefer_ipv4 {
+            Self::PreferIpv4
+        } else {
+            Self::Both
+        }
+    }
+
+    /// The addresses of `ips` to scan.
+    pub fn pick(self, mut ips: Vec<IpAddr>) -> Vec<IpAddr> {
+        let preferred = match self {
+            Self::Both => return ips,
+            Self::OnlyIpv6 => {
+                ips.retain(IpAddr::is_ipv6);
+                return ips;
+            }
+            Self::PreferIpv4 => IpAddr::is_ipv4,
+            Self::PreferIpv6 => IpAddr::is_ipv6,
+        };
+        if ips.iter().any(preferred) {
+            ips.retain(preferred);
+        }
+        ips
+    }
+}
 
 /// Parses the string(s) into IP addresses.
 ///
@@ -34,9 +110,15 @@ pub fn parse_addresses(input: &Opts) -> Vec<IpAddr> {
     let mut ips: Vec<IpAddr> = Vec::new();
     let mut unresolved_addresses: Vec<&str> = Vec::new();
     let backup_resolver = get_resolver(&input.resolver);
+    let excluded_hosts = excluded_host_patterns(&input.exclude_addresses);
+    let preference = IpPreference::from_opts(input);
 
     for address in &input.addresses {
-        let parsed_ips = parse_address(address, &backup_resolver);
+        if is_excluded_host(address, &excluded_hosts) {
+            debug!("Excluding {address}, it matches an excluded host pattern");
+            continue;
+        }
+        let parsed_ips = parse_address(address, &backup_resolver, preference);
         if !parsed_ips.is_empty() {
             ips.extend(parsed_ips);
         } else {
@@ -58,14 +140,42 @@ pub fn parse_addresses(input: &Opts) -> Vec<IpAddr> {
             continue;
         }
 
-        if let Ok(x) = read_ips_from_file(file_path, &backup_resolver) {
-            ips.extend(x);
-        } else {
-            warning!(
+        if let Some(targets) = read_inventory(file_path) {
+            debug!("Inventory {file_path:?} lists {targets:?}");
+            for target in targets {
+                if !is_excluded_host(&target, &excluded_hosts) {
+                    ips.extend(parse_address(&target, &backup_resolver, preference));
+                }
+            }
+            continue;
+        }
+
+        match read_ips_from_file(file_path, &backup_resolver, &excluded_hosts, preference) {
+            Ok((file_ips, issues)) => {
+                ips.extend(file_ips);
+                for issue in issues.iter().take(MAX_REPORTED_ISSUES) {
+                    warning!(
+                        format!("Skipping {issue}."),
+                        input.greppable,
+                        input.accessible
+                    );
+                }
+                if issues.len() > MAX_REPORTED_ISSUES {
+                    warning!(
+                        format!(
+                            "Skipping {} more lines of {file_path:?}.",
+                            issues.len() - MAX_REPORTED_ISSUES
+                        ),
+                        input.greppable,
+                        input.accessible
+                    );
+                }
+            }
+            Err(_) => warning!(
                 format!("Host {file_path:?} could not be resolved."),
                 input.greppable,
                 input.accessible
-            );
+            ),
         }
     }
 
@@ -75,23 +185,102 @@ pub fn parse_addresses(input: &Opts) -> Vec<IpAddr> {
     let mut seen = BTreeSet::new();
     ips.retain(|ip| seen.insert(*ip) && !excluded_cidrs.iter().any(|cidr| cidr.contains(ip)));
 
+    if input.exclude_bogons {
+        let count = ips.len();
+        ips.retain(|ip| !is_bogon(ip));
+        if ips.len() < count {
+            detail!(
+                format!(
+                    "Dropped {} reserved addresses from the targets.",
+                    count - ips.len()
+                ),
+                input.greppable,
+                input.accessible
+            );
+        }
+    }
+
     ips
 }
 
+/// Ranges that shouldn't be reachable on the internet: private, shared,
+/// loopback, link-local, documentation, benchmarking, multicast and
+/// reserved networks.
+const BOGON_RANGES: [&str; 23] = [
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "::ffff:0:0/96",
+    "100::/64",
+    "2001:db8::/32",
+    "fc00::/7",
+    "fe80::/10",
+    "fec0::/10",
+    "ff00::/8",
+];
+
+static BOGONS: Lazy<Vec<IpCidr>> = Lazy::new(|| {
+    BOGON_RANGES
+        .iter()
+        .map(|range| IpCidr::from_str(range).expect("bogon ranges are valid CIDRs"))
+        .collect()
+});
+
+/// Whether `ip` is in a private or reserved range, see `--exclude-bogons`.
+///
+/// ```rust
+/// # use rustscan::address::is_bogon;
+/// assert!(is_bogon(&"192.168.1.1".parse().unwrap()));
+/// assert!(!is_bogon(&"1.1.1.1".parse().unwrap()));
+/// ```
+pub fn is_bogon(ip: &IpAddr) -> bool {
+    BOGONS.iter().any(|range| range.contains(ip))
+}
+
+/// Prints the IPs the addresses expand to, or only how many there are,
+/// without sending them any traffic. Returns whether there are any.
+#[cfg(not(tarpaulin_include))]
+pub fn expand(opts: &Opts, count: bool) -> bool {
+    let ips = parse_addresses(opts);
+    if count {
+        println!("{}", ips.len());
+    } else {
+        for ip in &ips {
+            println!("{ip}");
+        }
+    }
+    !ips.is_empty()
+}
+
 /// Given a string, parse it as a host, IP address, or CIDR.
 ///
 /// This allows us to pass files as hosts or cidr or IPs easily
 /// Call this every time you have a possible IP-or-host.
 ///
 /// If the address is a domain, we can self-resolve the domain locally
-/// or resolve it by dns resolver list.
+/// or resolve it by dns resolver list. A domain with both A and AAAA
+/// records gives an IP of each, so both stacks are scanned, unless
+/// `preference` picks one.
 ///
 /// ```rust
-/// # use rustscan::address::parse_address;
+/// # use rustscan::address::{parse_address, IpPreference};
 /// # use hickory_resolver::Resolver;
-/// let ips = parse_address("127.0.0.1", &Resolver::default().unwrap());
+/// let ips = parse_address("127.0.0.1", &Resolver::default().unwrap(), IpPreference::Both);
 /// ```
-pub fn parse_address(address: &str, resolver: &Resolver) -> Vec<IpAddr> {
+pub fn parse_address(address: &str, resolver: &Resolver, preference: IpPreference) -> Vec<IpAddr> {
     if let Ok(addr) = IpAddr::from_str(address) {
         // `address` is an IP string
         vec![addr]
@@ -102,15 +291,86 @@ pub fn parse_address(address: &str, resolver: &Resolver) -> Vec<IpAddr> {
         // `address` is a hostname or DNS name
         // attempt default DNS lookup
         match format!("{address}:80").to_socket_addrs() {
-            Ok(mut iter) => vec![iter.next().unwrap().ip()],
+            Ok(iter) => one_per_stack(preference.pick(iter.map(|socket| socket.ip()).collect())),
             // default lookup didn't work, so try again with the dedicated resolver
-            Err(_) => resolve_ips_from_host(address, resolver),
+            Err(_) => resolve_ips_from_host(address, resolver, preference),
+        }
+    }
+}
+
+/// Resolves `targets`, IPs, CIDRs or hosts, with the system's resolver and
+/// none of the command line's options, for library use. Duplicates are
+/// removed, targets that don't resolve are skipped.
+pub fn parse_targets<S: AsRef<str>>(targets: impl IntoIterator<Item = S>) -> Vec<IpAddr> {
+    let resolver = get_resolver(&None);
+    let mut seen = BTreeSet::new();
+    targets
+        .into_iter()
+        .flat_map(|target| {
+            let target = target.as_ref().trim();
+            if target.is_empty() {
+                Vec::new()
+            } else {
+                parse_address(target, &resolver, IpPreference::Both)
+            }
+        })
+        .filter(|ip| seen.insert(*ip))
+        .collect()
+}
+
+/// The first IPv4 and the first IPv6 address of `ips`.
+fn one_per_stack(ips: impl IntoIterator<Item = IpAddr>) -> Vec<IpAddr> {
+    let mut picked: Vec<IpAddr> = Vec::new();
+    for ip in ips {
+        if !picked.iter().any(|other| other.is_ipv4() == ip.is_ipv4()) {
+            picked.push(ip);
         }
     }
+    picked
+}
+
+/// The hostnames among the addresses that resolve to both an IPv4 and an
+/// IPv6 address, with those addresses.
+pub fn dual_stack_hosts(input: &Opts) -> Vec<(String, IpAddr, IpAddr)> {
+    let resolver = get_resolver(&input.resolver);
+    input
+        .addresses
+        .iter()
+        .filter(|address| {
+            IpAddr::from_str(address).is_err()
+                && IpInet::from_str(address).is_err()
+                && !Path::new(address).is_file()
+        })
+        .filter_map(|host| {
+            let ips = parse_address(host, &resolver, IpPreference::from_opts(input));
+            let v4 = ips.iter().find(|ip| ip.is_ipv4())?;
+            let v6 = ips.iter().find(|ip| ip.is_ipv6())?;
+            Some((host.clone(), *v4, *v6))
+        })
+        .collect()
+}
+
+/// Ports open on only the first and only the second of two addresses of
+/// the same host.
+pub fn stack_differences(first: &[u16], second: &[u16]) -> (Vec<u16>, Vec<u16>) {
+    let only = |ports: &[u16], other: &[u16]| {
+        let mut only: Vec<u16> = ports
+            .iter()
+            .filter(|port| !other.contains(port))
+            .copied()
+            .collect();
+        only.sort_unstable();
+        only
+    };
+    (only(first, second), only(second, first))
 }
 
 /// Uses DNS to get the IPS associated with host
-fn resolve_ips_from_host(source: &str, backup_resolver: &Resolver) -> Vec<IpAddr> {
+fn resolve_ips_from_host(
+    source: &str,
+    backup_resolver: &Resolver,
+    preference: IpPreference,
+) -> Vec<IpAddr> {
     let mut ips: Vec<IpAddr> = Vec::new();
 
     if let Ok(addrs) = source.to_socket_addrs() {
@@ -121,7 +381,7 @@ fn resolve_ips_from_host(source: &str, backup_resolver: &Resolver) -> Vec<IpAddr
         ips.extend(addrs.iter());
     }
 
-    ips
+    preference.pick(ips)
 }
 
 /// Parses excluded networks from a list of addresses.
@@ -131,6 +391,10 @@ fn resolve_ips_from_host(source: &str, backup_resolver: &Resolver) -> Vec<IpAddr
 /// 2. Single IP addresses (e.g. "192.168.0.1")
 /// 3. Hostnames that need to be resolved (e.g. "example.com")
 ///
+/// Wildcard hostname patterns (e.g. "*.internal.corp") aren't resolved,
+/// they're matched against the hostnames given as targets instead, see
+/// [`is_excluded_host`].
+///
 /// ```rust
 /// # use rustscan::address::parse_excluded_networks;
 /// # use hickory_resolver::Resolver;
@@ -144,12 +408,53 @@ pub fn parse_excluded_networks(
     exclude_addresses
         .iter()
         .flatten()
+        .filter(|addr| !addr.contains('*'))
         .flat_map(|addr| parse_single_excluded_address(addr, resolver))
         .collect()
 }
 
+/// The wildcard hostname patterns among the excluded addresses, lowercased.
+fn excluded_host_patterns(exclude_addresses: &Option<Vec<String>>) -> Vec<String> {
+    exclude_addresses
+        .iter()
+        .flatten()
+        .filter(|addr| addr.contains('*'))
+        .map(|addr| addr.trim().trim_end_matches('.').to_lowercase())
+        .collect()
+}
+
+/// Whether the target `host` matches one of the wildcard hostname
+/// `patterns`, where `*` stands for any run of characters. Hostnames are
+/// compared case-insensitively; IPs and CIDRs never match.
+///
+/// ```rust
+/// # use rustscan::address::is_excluded_host;
+/// let patterns = vec!["*.internal.corp".to_owned()];
+/// assert!(is_excluded_host("db.internal.corp", &patterns));
+/// assert!(!is_excluded_host("internal.corp.example.com", &patterns));
+/// ```
+pub fn is_excluded_host(host: &str, patterns: &[String]) -> bool {
+    let host = host.trim().trim_end_matches('.').to_lowercase();
+    if patterns.is_empty() || IpAddr::from_str(&host).is_ok() || IpInet::from_str(&host).is_ok() {
+        return false;
+    }
+    patterns
+        .iter()
+        .any(|pattern| matches_wildcard(pattern.as_bytes(), host.as_bytes()))
+}
+
+fn matches_wildcard(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| matches_wildcard(rest, &text[skip..])),
+        Some((c, rest)) => text
+            .split_first()
+            .is_some_and(|(t, text)| t == c && matches_wildcard(rest, text)),
+    }
+}
+
 /// Parses a single address into an IpCidr, handling CIDR notation, IP addresses, and hostnames.
-fn parse_single_excluded_address(addr: &str, resolver: &Resolver) -> Vec<IpCidr> {
+pub(crate) fn parse_single_excluded_address(addr: &str, resolver: &Resolver) -> Vec<IpCidr> {
     if let Ok(cidr) = IpCidr::from_str(addr) {
         return vec![cidr];
     }
@@ -158,7 +463,7 @@ fn parse_single_excluded_address(addr: &str, resolver: &Resolver) -> Vec<IpCidr>
         return vec![IpCidr::new_host(ip)];
     }
 
-    resolve_ips_from_host(addr, resolver)
+    resolve_ips_from_host(addr, resolver, IpPreference::Both)
         .into_iter()
         .map(IpCidr::new_host)
         .collect()
@@ -174,7 +479,8 @@ fn parse_single_excluded_address(addr: &str, resolver: &Resolver) -> Vec<IpCidr>
 ///       `/etc/resolv.conf` on *nix).
 ///    2. finally, build a CloudFlare-based resolver (default
 ///       behaviour).
-fn get_resolver(resolver: &Option<String>) -> Resolver {
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn get_resolver(resolver: &Option<String>) -> Resolver {
     match resolver {
         Some(r) => {
             let mut config = ResolverConfig::new();
@@ -202,7 +508,14 @@ fn get_resolver(resolver: &Option<String>) -> Resolver {
     }
 }
 
+/// Derive a DNS resolver, of which wasm32 builds have none.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn get_resolver(_resolver: &Option<String>) -> Resolver {
+    Resolver
+}
+
 /// Parses and input file of IPs for use in DNS resolution.
+#[cfg(not(target_arch = "wasm32"))]
 fn read_resolver_from_file(path: &str) -> Result<Vec<IpAddr>, std::io::Error> {
     let ips = fs::read_to_string(path)?
         .lines()
@@ -213,31 +526,196 @@ fn read_resolver_from_file(path: &str) -> Result<Vec<IpAddr>, std::io::Error> {
 }
 
 #[cfg(not(tarpaulin_include))]
-/// Parses an input file of IPs and uses those
+/// Parses an input file of IPs, CIDRs and hosts, one per line, skipping
+/// blank lines and `#` comments. Returns the addresses along with the lines
+/// that gave none, see [`lenient`].
 fn read_ips_from_file(
-    ips: &std::path::Path,
+    path: &Path,
     backup_resolver: &Resolver,
-) -> Result<Vec<IpAddr>, std::io::Error> {
-    let file = File::open(ips)?;
-    let reader = BufReader::new(file);
-
+    excluded_hosts: &[String],
+    preference: IpPreference,
+) -> Result<(Vec<IpAddr>, Vec<Issue>), std::io::Error> {
+    let file = File::open(path)?;
     let mut ips: Vec<IpAddr> = Vec::new();
+    let mut issues = Vec::new();
 
-    for address_line in reader.lines() {
-        if let Ok(address) = address_line {
-            ips.extend(parse_address(&address, backup_resolver));
-        } else {
-            debug!("Line in file is not valid");
+    for line in lenient::lines(BufReader::new(file), path) {
+        let line = match line {
+            Ok(line) => line,
+            Err(issue) => {
+                issues.push(issue);
+                continue;
+            }
+        };
+        let address = line.text.trim();
+        if address.is_empty() || address.starts_with('#') {
+            continue;
         }
+        if is_excluded_host(address, excluded_hosts) {
+            continue;
+        }
+        let issue = |key: &str, message: &str| Issue {
+            file: path.to_path_buf(),
+            line: Some(line.number),
+            key: key.to_owned(),
+            message: message.to_owned(),
+            suggestion: None,
+            fatal: false,
+        };
+        if address.len() > MAX_HOST_LENGTH {
+            issues.push(issue("", "a line too long to be a host"));
+            continue;
+        }
+        let parsed = parse_address(address, backup_resolver, preference);
+        if parsed.is_empty() {
+            issues.push(issue(address, "could not be resolved"));
+        }
+        ips.extend(parsed);
     }
 
-    Ok(ips)
+    Ok((ips, issues))
+}
+
+/// Attributes of Terraform resources holding addresses worth scanning.
+const TERRAFORM_ADDRESS_ATTRIBUTES: [&str; 7] = [
+    "public_ip",
+    "public_ip_address",
+    "ip_address",
+    "ipv4_address",
+    "ipv6_address",
+    "nat_ip",
+    "access_ip_v4",
+];
+
+/// Reads the targets of an Ansible inventory (INI or YAML) or a Terraform
+/// state file. Returns `None` for any other file.
+fn read_inventory(path: &Path) -> Option<Vec<String>> {
+    // Lines that aren't text are left blank, inventories rarely have any.
+    let (content, _) = lenient::read_text(BufReader::new(File::open(path).ok()?), path);
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+
+    match extension {
+        "tfstate" => parse_terraform_state(&content),
+        "yml" | "yaml" => parse_ansible_yaml(&content),
+        _ if is_ansible_ini(&content) => Some(parse_ansible_ini(&content)),
+        _ => parse_terraform_state(&content),
+    }
+}
+
+/// Collects the public addresses from the resources of a Terraform state.
+fn parse_terraform_state(content: &str) -> Option<Vec<String>> {
+    fn collect(value: &serde_json::Value, targets: &mut Vec<String>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    match value.as_str() {
+                        Some(address)
+                            if !address.is_empty()
+                                && TERRAFORM_ADDRESS_ATTRIBUTES.contains(&key.as_str()) =>
+                        {
+                            targets.push(address.to_owned());
+                        }
+                        _ => collect(value, targets),
+                    }
+                }
+            }
+            serde_json::Value::Array(values) => {
+                values.iter().for_each(|value| collect(value, targets));
+            }
+            _ => {}
+        }
+    }
+
+    let state: serde_json::Value = serde_json::from_str(content).ok()?;
+    state.get("terraform_version")?;
+    let mut targets = Vec::new();
+    for resource in state["resources"].as_array().into_iter().flatten() {
+        for instance in resource["instances"].as_array().into_iter().flatten() {
+            collect(&instance["attributes"], &mut targets);
+        }
+    }
+    Some(targets)
+}
+
+/// Whether the file has INI `[group]` headers, which plain host lists don't.
+fn is_ansible_ini(content: &str) -> bool {
+    content.lines().map(str::trim).any(|line| {
+        line.len() > 2
+            && line.starts_with('[')
+            && line.ends_with(']')
+            && line[1..line.len() - 1].parse::<IpAddr>().is_err()
+    })
+}
+
+/// Lists the hosts of an INI inventory, using `ansible_host` when set.
+fn parse_ansible_ini(content: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+    // Hosts outside of any group are valid too.
+    let mut in_hosts_section = true;
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') {
+            // [group:vars] and [group:children] don't list hosts.
+            in_hosts_section = !line.contains(':');
+            continue;
+        }
+        if !in_hosts_section {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let Some(host) = fields.next() else {
+            continue;
+        };
+        let ansible_host = fields.find_map(|field| field.strip_prefix("ansible_host="));
+        targets.push(ansible_host.unwrap_or(host).to_owned());
+    }
+    targets
+}
+
+/// Lists the hosts of a YAML inventory, using `ansible_host` when set.
+fn parse_ansible_yaml(content: &str) -> Option<Vec<String>> {
+    fn collect(group: &serde_yaml::Value, targets: &mut Vec<String>) {
+        let Some(group) = group.as_mapping() else {
+            return;
+        };
+        if let Some(hosts) = group.get("hosts").and_then(serde_yaml::Value::as_mapping) {
+            for (name, vars) in hosts {
+                let ansible_host = vars.get("ansible_host").and_then(serde_yaml::Value::as_str);
+                if let Some(host) = ansible_host.or(name.as_str()) {
+                    targets.push(host.to_owned());
+                }
+            }
+        }
+        if let Some(children) = group
+            .get("children")
+            .and_then(serde_yaml::Value::as_mapping)
+        {
+            children.values().for_each(|child| collect(child, targets));
+        }
+    }
+
+    let inventory: serde_yaml::Mapping = serde_yaml::from_str(content).ok()?;
+    let mut targets = Vec::new();
+    inventory
+        .values()
+        .for_each(|group| collect(group, &mut targets));
+    Some(targets)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{get_resolver, parse_addresses, Opts};
-    use std::net::Ipv4Addr;
+    use super::{
+        get_resolver, is_excluded_host, one_per_stack, parse_addresses, parse_targets,
+        read_ips_from_file, stack_differences, IpPreference, Opts,
+    };
+    use std::net::{IpAddr, Ipv4Addr};
 
     #[test]
     fn parse_correct_addresses() {
@@ -299,6 +777,90 @@ mod tests {
         );
     }
 
+    #[test]
+    fn hostnames_give_an_ip_per_stack() {
+        let ips: Vec<IpAddr> = ["10.0.0.1", "10.0.0.2", "2001:db8::1", "2001:db8::2"]
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        assert_eq!(one_per_stack(ips.iter().copied()), vec![ips[0], ips[2]]);
+        assert_eq!(one_per_stack(ips[..2].iter().copied()), vec![ips[0]]);
+    }
+
+    #[test]
+    fn preferences_pick_a_stack() {
+        let v4: IpAddr = "10.0.0.1".parse().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        assert_eq!(IpPreference::Both.pick(vec![v4, v6]), vec![v4, v6]);
+        assert_eq!(IpPreference::PreferIpv4.pick(vec![v6, v4]), vec![v4]);
+        assert_eq!(IpPreference::PreferIpv6.pick(vec![v4, v6]), vec![v6]);
+        assert_eq!(IpPreference::PreferIpv6.pick(vec![v4]), vec![v4]);
+        assert!(IpPreference::OnlyIpv6.pick(vec![v4]).is_empty());
+
+        let opts = Opts {
+            prefer_ipv4: true,
+            ..Default::default()
+        };
+        assert_eq!(IpPreference::from_opts(&opts), IpPreference::PreferIpv4);
+    }
+
+    #[test]
+    fn ports_open_on_one_stack() {
+        assert_eq!(
+            stack_differences(&[443, 22, 80], &[80, 8080, 443]),
+            (vec![22], vec![8080])
+        );
+        assert_eq!(stack_differences(&[80], &[80]), (vec![], vec![]));
+    }
+
+    #[test]
+    fn parse_addresses_with_hostname_pattern_exclusions() {
+        let opts = Opts {
+            addresses: vec!["localhost".to_owned(), "127.0.0.2".to_owned()],
+            exclude_addresses: Some(vec!["LOCAL*".to_owned()]),
+            ..Default::default()
+        };
+        let ips = parse_addresses(&opts);
+
+        assert_eq!(ips, [Ipv4Addr::new(127, 0, 0, 2)]);
+    }
+
+    #[test]
+    fn parse_addresses_without_bogons() {
+        let opts = Opts {
+            addresses: vec![
+                "10.0.0.1".to_owned(),
+                "1.1.1.1".to_owned(),
+                "224.0.0.0/30".to_owned(),
+                "fe80::1".to_owned(),
+                "2606:4700::1111".to_owned(),
+            ],
+            exclude_bogons: true,
+            ..Default::default()
+        };
+        let ips = parse_addresses(&opts);
+
+        assert_eq!(
+            ips,
+            [
+                "1.1.1.1".parse::<IpAddr>().unwrap(),
+                "2606:4700::1111".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn hostname_patterns() {
+        let patterns = vec!["*.internal.corp".to_owned(), "db-*.example.com".to_owned()];
+        assert!(is_excluded_host("a.b.internal.corp", &patterns));
+        assert!(is_excluded_host("Web.Internal.Corp.", &patterns));
+        assert!(is_excluded_host("db-01.example.com", &patterns));
+        assert!(!is_excluded_host("internal.corp", &patterns));
+        assert!(!is_excluded_host("web-01.example.com", &patterns));
+        assert!(!is_excluded_host("10.0.0.1", &["*".to_owned()]));
+        assert!(!is_excluded_host("anything", &[]));
+    }
+
     #[test]
     fn parse_addresses_with_incorrect_address_exclusions() {
         let opts = Opts {
@@ -367,6 +929,32 @@ mod tests {
         assert_eq!(ips.len(), 3);
     }
 
+    #[test]
+    fn parse_hostile_hosts_file() {
+        let path = std::env::temp_dir().join(format!("rustscan-hosts-{}", std::process::id()));
+        let mut content =
+            b"\xef\xbb\xbf10.0.0.1\r\n\r\n# office\n  10.0.0.2 \n10.0.\xff.3\n".to_vec();
+        content.extend(vec![b'a'; 300]);
+        content.extend(b"\n10.0.1.0/31\r\n");
+        std::fs::write(&path, content).unwrap();
+
+        let (ips, issues) =
+            read_ips_from_file(&path, &get_resolver(&None), &[], IpPreference::Both).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            ips,
+            [
+                Ipv4Addr::new(10, 0, 0, 1),
+                Ipv4Addr::new(10, 0, 0, 2),
+                Ipv4Addr::new(10, 0, 1, 0),
+                Ipv4Addr::new(10, 0, 1, 1)
+            ]
+        );
+        let lines: Vec<_> = issues.iter().map(|issue| issue.line).collect();
+        assert_eq!(lines, [Some(5), Some(6)]);
+    }
+
     #[test]
     fn parse_empty_hosts_file() {
         // Host file contains IP, Hosts, incorrect IPs, incorrect hosts
@@ -380,6 +968,49 @@ mod tests {
         assert_eq!(ips.len(), 0);
     }
 
+    #[test]
+    fn parse_ansible_inventories() {
+        for inventory in [
+            "fixtures/inventories/hosts.ini",
+            "fixtures/inventories/hosts.yml",
+        ] {
+            let opts = Opts {
+                addresses: vec![inventory.to_owned()],
+                ..Default::default()
+            };
+
+            let mut ips = parse_addresses(&opts);
+            ips.sort();
+
+            assert_eq!(
+                ips,
+                [
+                    Ipv4Addr::new(127, 0, 0, 1),
+                    Ipv4Addr::new(127, 0, 0, 2),
+                    Ipv4Addr::new(127, 0, 0, 3),
+                    Ipv4Addr::new(127, 0, 0, 5)
+                ],
+                "{}",
+                inventory
+            );
+        }
+    }
+
+    #[test]
+    fn parse_terraform_state() {
+        let opts = Opts {
+            addresses: vec!["fixtures/inventories/terraform.tfstate".to_owned()],
+            ..Default::default()
+        };
+
+        let ips = parse_addresses(&opts);
+
+        assert_eq!(
+            ips,
+            [Ipv4Addr::new(127, 0, 0, 1), Ipv4Addr::new(127, 0, 0, 3)]
+        );
+    }
+
     #[test]
     fn parse_naughty_host_file() {
         // Host file contains IP, Hosts, incorrect IPs, incorrect hosts
@@ -405,6 +1036,19 @@ mod tests {
         assert_eq!(ips.len(), 2_048);
     }
 
+    #[test]
+    fn parse_targets_without_opts() {
+        let ips = parse_targets(["127.0.0.1", " 192.168.0.0/31", "", "127.0.0.1"]);
+        assert_eq!(
+            ips,
+            [
+                Ipv4Addr::new(127, 0, 0, 1),
+                Ipv4Addr::new(192, 168, 0, 0),
+                Ipv4Addr::new(192, 168, 0, 1),
+            ]
+        );
+    }
+
     #[test]
     fn parse_overspecific_cidr() {
         // a canonical CIDR string has 0 in all host bits, but we want to treat any CIDR-like string as CIDR
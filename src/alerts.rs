@@ -0,0 +1,146 @@
+//! Alerts on ports open outside what a network is expected to expose.
+//!
+//! `--alert 10.0.0.0/24=80,443` raises an alert for every port found open on
+//! a host of 10.0.0.0/24 other than 80 and 443; ranges such as `8000-8100`
+//! are allowed too, and a rule with no ports alerts on any open port. Rules
+//! are checked once, against the open ports a scan found; there's no watch
+//! loop rescanning on its own, nor webhook or syslog sinks. Alerts are
+//! printed and kept in `alerts` in reports.
+use crate::policy::{network, port_range};
+use cidr_utils::cidr::IpCidr;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// The ports a network is expected to expose.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlertRule {
+    network: IpCidr,
+    allowed: Vec<RangeInclusive<u16>>,
+}
+
+impl AlertRule {
+    /// Whether `socket` being open breaks the rule.
+    pub fn breaks(&self, socket: &SocketAddr) -> bool {
+        self.network.contains(&socket.ip())
+            && !self
+                .allowed
+                .iter()
+                .any(|ports| ports.contains(&socket.port()))
+    }
+}
+
+impl FromStr for AlertRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let (network_text, ports) = rule.split_once('=').unwrap_or((rule, ""));
+        let network = network(network_text).map_err(|e| e.to_string())?;
+        let allowed = ports
+            .split(',')
+            .filter(|ports| !ports.trim().is_empty())
+            .map(|ports| port_range(ports, '-').map_err(|e| e.to_string()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { network, allowed })
+    }
+}
+
+impl fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.network)?;
+        if self.allowed.is_empty() {
+            return Ok(());
+        }
+        let allowed: Vec<String> = self
+            .allowed
+            .iter()
+            .map(|ports| {
+                if ports.start() == ports.end() {
+                    ports.start().to_string()
+                } else {
+                    format!("{}-{}", ports.start(), ports.end())
+                }
+            })
+            .collect();
+        write!(f, "={}", allowed.join(","))
+    }
+}
+
+impl<'de> Deserialize<'de> for AlertRule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rule = String::deserialize(deserializer)?;
+        rule.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for AlertRule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// An open port breaking a rule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alert {
+    pub socket: SocketAddr,
+    /// The rule broken, as given.
+    pub rule: String,
+}
+
+/// The open ports of `open` breaking `rules`, once per rule broken.
+pub fn check(rules: &[AlertRule], open: &[SocketAddr]) -> Vec<Alert> {
+    open.iter()
+        .flat_map(|socket| {
+            rules
+                .iter()
+                .filter(move |rule| rule.breaks(socket))
+                .map(move |rule| Alert {
+                    socket: *socket,
+                    rule: rule.to_string(),
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, AlertRule};
+    use std::net::SocketAddr;
+
+    #[test]
+    fn open_ports_outside_a_rule_alert() {
+        let rules: Vec<AlertRule> = ["10.0.0.0/24=80,443,8000-8100", "192.168.1.5"]
+            .iter()
+            .map(|rule| rule.parse().unwrap())
+            .collect();
+        assert_eq!(rules[0].to_string(), "10.0.0.0/24=80,443,8000-8100");
+
+        let open: Vec<SocketAddr> = [
+            "10.0.0.4:443",
+            "10.0.0.4:8080",
+            "10.0.0.4:22",
+            "10.0.1.4:22",
+            "192.168.1.5:80",
+        ]
+        .iter()
+        .map(|socket| socket.parse().unwrap())
+        .collect();
+        let alerts: Vec<String> = check(&rules, &open)
+            .into_iter()
+            .map(|alert| format!("{} {}", alert.socket, alert.rule))
+            .collect();
+        assert_eq!(
+            alerts,
+            [
+                "10.0.0.4:22 10.0.0.0/24=80,443,8000-8100",
+                "192.168.1.5:80 192.168.1.5"
+            ]
+        );
+
+        assert!("web=80".parse::<AlertRule>().is_err());
+        assert!("10.0.0.0/24=http".parse::<AlertRule>().is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Known-good open ports, so monitoring only surfaces new exposure.
+//!
+//! A baseline file lists the open ports that are accepted, one per line, as
+//! `host:port`. The host can be an IP, a CIDR or a hostname; IPv6 addresses
+//! with a port go in brackets (`[::1]:22`), and a host without a port
+//! accepts all of its ports:
+//!
+//! ```text
+//! # the web tier
+//! 10.0.0.0/24:443
+//! bastion.example.com:22
+//! [2001:db8::1]:80
+//! 10.0.1.5
+//! ```
+//!
+//! With `--baseline`, open ports in the file are marked `known` in the
+//! structured outputs; `--hide-known` drops them from the results
+//! altogether.
+use crate::address::{get_resolver, parse_single_excluded_address};
+use anyhow::{anyhow, Result};
+use cidr_utils::cidr::IpCidr;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+
+/// The accepted open ports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Baseline {
+    /// Networks and the port accepted on them, `None` for every port.
+    entries: Vec<(IpCidr, Option<u16>)>,
+}
+
+impl Baseline {
+    /// Reads the baseline file at `path`, resolving hostnames with
+    /// `resolver` (see `--resolver`).
+    pub fn read(path: &Path, resolver: &Option<String>) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read {}: {e}", path.display()))?;
+        Self::parse(&content, resolver)
+    }
+
+    fn parse(content: &str, resolver: &Option<String>) -> Result<Self> {
+        let mut resolver_cache = None;
+        let mut entries = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (host, port) =
+                split_host_port(line).map_err(|e| anyhow!("Line {}: {e}", number + 1))?;
+            let resolver = resolver_cache.get_or_insert_with(|| get_resolver(resolver));
+            let networks = parse_single_excluded_address(host, resolver);
+            if networks.is_empty() {
+                return Err(anyhow!("Line {}: {host} could not be resolved", number + 1));
+            }
+            entries.extend(networks.into_iter().map(|network| (network, port)));
+        }
+        Ok(Self { entries })
+    }
+
+    /// Whether `socket` is an accepted open port.
+    pub fn contains(&self, socket: &SocketAddr) -> bool {
+        self.entries.iter().any(|(network, port)| {
+            network.contains(&socket.ip()) && port.is_none_or(|port| port == socket.port())
+        })
+    }
+}
+
+/// Splits `host:port`, `[ipv6]:port` or a lone host.
+fn split_host_port(entry: &str) -> Result<(&str, Option<u16>)> {
+    let parse_port = |port: &str| {
+        port.parse::<u16>()
+            .map(Some)
+            .map_err(|_| anyhow!("{port} is not a port"))
+    };
+    if let Some(rest) = entry.strip_prefix('[') {
+        let (host, port) = rest
+            .split_once(']')
+            .ok_or_else(|| anyhow!("{entry} is missing a closing bracket"))?;
+        return match port.strip_prefix(':') {
+            Some(port) => Ok((host, parse_port(port)?)),
+            None if port.is_empty() => Ok((host, None)),
+            None => Err(anyhow!("{entry} should be [host]:port")),
+        };
+    }
+    // More than one colon without brackets is an IPv6 address alone.
+    match entry.split_once(':') {
+        Some((host, port)) if !port.contains(':') => Ok((host, parse_port(port)?)),
+        _ => Ok((entry, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_host_port, Baseline};
+
+    #[test]
+    fn split_entries() {
+        assert_eq!(
+            split_host_port("10.0.0.0/24:443").unwrap(),
+            ("10.0.0.0/24", Some(443))
+        );
+        assert_eq!(split_host_port("[::1]:22").unwrap(), ("::1", Some(22)));
+        assert_eq!(split_host_port("[::1]").unwrap(), ("::1", None));
+        assert_eq!(
+            split_host_port("2001:db8::1").unwrap(),
+            ("2001:db8::1", None)
+        );
+        assert_eq!(split_host_port("10.0.1.5").unwrap(), ("10.0.1.5", None));
+        assert!(split_host_port("10.0.0.1:ssh").is_err());
+        assert!(split_host_port("[::1:22").is_err());
+    }
+
+    #[test]
+    fn known_ports() {
+        let baseline = Baseline::parse(
+            "# accepted\n10.0.0.0/24:443\n[2001:db8::1]:80\n10.0.1.5 # jump host\n",
+            &None,
+        )
+        .unwrap();
+        assert!(baseline.contains(&"10.0.0.7:443".parse().unwrap()));
+        assert!(!baseline.contains(&"10.0.0.7:22".parse().unwrap()));
+        assert!(baseline.contains(&"[2001:db8::1]:80".parse().unwrap()));
+        assert!(baseline.contains(&"10.0.1.5:3389".parse().unwrap()));
+        assert!(!baseline.contains(&"10.0.2.1:443".parse().unwrap()));
+
+        assert!(Baseline::parse("10.0.0.1:http", &None).is_err());
+    }
+}
@@ -16,6 +16,7 @@
 //! // Print Benchmark Summary
 //! info!("{}", bm.summary());
 //! ```
+use crate::units;
 use std::time::Instant;
 
 /// A Benchmark struct to hold NamedTimers with name, start and end Instants,
@@ -44,8 +45,8 @@ impl Benchmark {
         for timer in &self.named_timers {
             if let Some(start) = timer.start {
                 if let Some(end) = timer.end {
-                    let runtime_secs = end.saturating_duration_since(start).as_secs_f32();
-                    summary.push_str(&format!("\n{0: <10} | {1: <10}s", timer.name, runtime_secs));
+                    let runtime = units::duration(end.saturating_duration_since(start));
+                    summary.push_str(&format!("\n{0: <10} | {1: <10}", timer.name, runtime));
                 }
             }
         }
@@ -85,8 +86,12 @@ fn benchmark() {
     test_timer.end();
     benchmarks.push(test_timer);
     benchmarks.push(NamedTimer::start("only_start"));
-    assert!(benchmarks
-        .summary()
-        .contains("\nRustScan Benchmark Summary\ntest       | 0."));
+    let summary = benchmarks.summary();
+    let line = summary
+        .lines()
+        .find(|line| line.starts_with("test       | "))
+        .unwrap();
+    assert!(summary.contains("\nRustScan Benchmark Summary\ntest       | "));
+    assert!(line.trim_end().ends_with("ms"), "{}", line);
     assert!(!benchmarks.summary().contains("only_start"));
 }
@@ -0,0 +1,445 @@
+//! Discovers public assets in cloud accounts to use as scan targets.
+//!
+//! Sources are given as `provider:key=value,...`, for example
+//! `aws:profile=prod,region=eu-west-1`, `gcp:project=web` or
+//! `azure:subscription=1234`. Rather than bundling every cloud SDK, the
+//! provider's own CLI (`aws`, `gcloud` or `az`) is run with the user's
+//! existing credentials and its JSON output is parsed.
+//!
+//! Every asset carries tags (its id, name and cloud labels) which are shown
+//! next to the open ports found on it.
+use anyhow::{anyhow, Result};
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::process::Command;
+use std::str::FromStr;
+
+/// A cloud provider RustScan can enumerate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    Aws,
+    Gcp,
+    Azure,
+}
+
+impl Provider {
+    /// Options each provider accepts.
+    fn options(self) -> &'static [&'static str] {
+        match self {
+            Provider::Aws => &["profile", "region"],
+            Provider::Gcp => &["project"],
+            Provider::Azure => &["subscription"],
+        }
+    }
+}
+
+/// Where to discover assets, parsed from `--cloud`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloudSource {
+    pub provider: Provider,
+    pub options: BTreeMap<String, String>,
+}
+
+impl FromStr for CloudSource {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (provider, options) = input.split_once(':').unwrap_or((input, ""));
+        let provider = match provider.to_lowercase().as_str() {
+            "aws" => Provider::Aws,
+            "gcp" => Provider::Gcp,
+            "azure" => Provider::Azure,
+            other => {
+                return Err(format!(
+                    "unknown cloud provider {other:?}, expected aws, gcp or azure"
+                ))
+            }
+        };
+
+        let mut parsed = BTreeMap::new();
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            let (key, value) = option
+                .split_once('=')
+                .ok_or_else(|| format!("cloud option {option:?} should be key=value"))?;
+            if !provider.options().contains(&key) {
+                return Err(format!(
+                    "unknown {provider:?} option {key:?}, expected one of {}",
+                    provider.options().join(", ")
+                ));
+            }
+            parsed.insert(key.to_owned(), value.to_owned());
+        }
+        Ok(Self {
+            provider,
+            options: parsed,
+        })
+    }
+}
+
+impl fmt::Display for CloudSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self.provider).to_lowercase())?;
+        let options: Vec<String> = self
+            .options
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        if !options.is_empty() {
+            write!(f, ":{}", options.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for CloudSource {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for CloudSource {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A discovered target and the tags describing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloudAsset {
+    /// An IP, or a hostname for load balancers.
+    pub target: String,
+    pub tags: Vec<String>,
+}
+
+/// Maps the IPs of assets to their tags so they can be shown with the
+/// results, resolving hostnames such as load balancers.
+pub fn tags_by_ip(assets: &[CloudAsset]) -> HashMap<IpAddr, Vec<String>> {
+    let mut tags = HashMap::new();
+    for asset in assets {
+        let ips: Vec<IpAddr> = match asset.target.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => (asset.target.as_str(), 0)
+                .to_socket_addrs()
+                .map(|addrs| addrs.map(|addr| addr.ip()).collect())
+                .unwrap_or_default(),
+        };
+        for ip in ips {
+            tags.entry(ip)
+                .or_insert_with(Vec::new)
+                .extend(asset.tags.iter().cloned());
+        }
+    }
+    tags
+}
+
+/// Enumerates the public assets of `source` through the provider's CLI.
+#[cfg(not(tarpaulin_include))]
+pub fn discover(source: &CloudSource) -> Result<Vec<CloudAsset>> {
+    let option = |key: &str, flag: &str| -> Vec<String> {
+        source
+            .options
+            .get(key)
+            .map(|value| vec![flag.to_owned(), value.clone()])
+            .unwrap_or_default()
+    };
+
+    match source.provider {
+        Provider::Aws => {
+            let mut extra = option("profile", "--profile");
+            extra.extend(option("region", "--region"));
+            let mut assets = parse_aws_instances(&run_cli(
+                "aws",
+                &["ec2", "describe-instances", "--output", "json"],
+                &extra,
+            )?);
+            assets.extend(parse_aws_load_balancers(&run_cli(
+                "aws",
+                &["elbv2", "describe-load-balancers", "--output", "json"],
+                &extra,
+            )?));
+            Ok(assets)
+        }
+        Provider::Gcp => {
+            let extra = option("project", "--project");
+            let mut assets = parse_gcp_instances(&run_cli(
+                "gcloud",
+                &["compute", "instances", "list", "--format=json"],
+                &extra,
+            )?);
+            assets.extend(parse_gcp_forwarding_rules(&run_cli(
+                "gcloud",
+                &["compute", "forwarding-rules", "list", "--format=json"],
+                &extra,
+            )?));
+            Ok(assets)
+        }
+        Provider::Azure => Ok(parse_azure_public_ips(&run_cli(
+            "az",
+            &["network", "public-ip", "list", "--output", "json"],
+            &option("subscription", "--subscription"),
+        )?)),
+    }
+}
+
+/// Runs a cloud CLI and parses its JSON output.
+#[cfg(not(tarpaulin_include))]
+fn run_cli(program: &str, args: &[&str], extra: &[String]) -> Result<Value> {
+    let output = Command::new(program)
+        .args(args)
+        .args(extra)
+        .output()
+        .map_err(|e| anyhow!("Could not run {program}, is its CLI installed? {e}"))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{program} {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Formats a map of cloud labels as `key=value` tags.
+fn label_tags(labels: &Value) -> impl Iterator<Item = String> + '_ {
+    labels
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| format!("{key}={}", value.as_str().unwrap_or_default()))
+}
+
+/// Public IPs of EC2 instances, from `aws ec2 describe-instances`.
+pub fn parse_aws_instances(json: &Value) -> Vec<CloudAsset> {
+    let instances = json["Reservations"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|reservation| reservation["Instances"].as_array().into_iter().flatten());
+
+    instances
+        .filter_map(|instance| {
+            let ip = instance["PublicIpAddress"].as_str()?;
+            let mut tags = vec!["aws:ec2".to_owned()];
+            if let Some(id) = instance["InstanceId"].as_str() {
+                tags.push(format!("id={id}"));
+            }
+            for tag in instance["Tags"].as_array().into_iter().flatten() {
+                if let (Some(key), Some(value)) = (tag["Key"].as_str(), tag["Value"].as_str()) {
+                    tags.push(format!("{key}={value}"));
+                }
+            }
+            Some(CloudAsset {
+                target: ip.to_owned(),
+                tags,
+            })
+        })
+        .collect()
+}
+
+/// Internet facing load balancers, from `aws elbv2 describe-load-balancers`.
+pub fn parse_aws_load_balancers(json: &Value) -> Vec<CloudAsset> {
+    json["LoadBalancers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|balancer| balancer["Scheme"] == "internet-facing")
+        .filter_map(|balancer| {
+            let mut tags = vec!["aws:elb".to_owned()];
+            if let Some(name) = balancer["LoadBalancerName"].as_str() {
+                tags.push(format!("name={name}"));
+            }
+            Some(CloudAsset {
+                target: balancer["DNSName"].as_str()?.to_owned(),
+                tags,
+            })
+        })
+        .collect()
+}
+
+/// External IPs of Compute Engine instances, from
+/// `gcloud compute instances list`.
+pub fn parse_gcp_instances(json: &Value) -> Vec<CloudAsset> {
+    let mut assets = Vec::new();
+    for instance in json.as_array().into_iter().flatten() {
+        let interfaces = instance["networkInterfaces"]
+            .as_array()
+            .into_iter()
+            .flatten();
+        let ips = interfaces
+            .flat_map(|interface| interface["accessConfigs"].as_array().into_iter().flatten())
+            .filter_map(|config| config["natIP"].as_str());
+        for ip in ips {
+            let mut tags = vec!["gcp:instance".to_owned()];
+            if let Some(name) = instance["name"].as_str() {
+                tags.push(format!("name={name}"));
+            }
+            tags.extend(label_tags(&instance["labels"]));
+            assets.push(CloudAsset {
+                target: ip.to_owned(),
+                tags,
+            });
+        }
+    }
+    assets
+}
+
+/// External load balancer IPs, from `gcloud compute forwarding-rules list`.
+pub fn parse_gcp_forwarding_rules(json: &Value) -> Vec<CloudAsset> {
+    json.as_array()
+        .into_iter()
+        .flatten()
+        .filter(|rule| rule["loadBalancingScheme"] == "EXTERNAL")
+        .filter_map(|rule| {
+            let mut tags = vec!["gcp:forwarding-rule".to_owned()];
+            if let Some(name) = rule["name"].as_str() {
+                tags.push(format!("name={name}"));
+            }
+            tags.extend(label_tags(&rule["labels"]));
+            Some(CloudAsset {
+                target: rule["IPAddress"].as_str()?.to_owned(),
+                tags,
+            })
+        })
+        .collect()
+}
+
+/// Allocated public IPs, from `az network public-ip list`.
+pub fn parse_azure_public_ips(json: &Value) -> Vec<CloudAsset> {
+    json.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|address| {
+            let mut tags = vec!["azure:public-ip".to_owned()];
+            if let Some(name) = address["name"].as_str() {
+                tags.push(format!("name={name}"));
+            }
+            if let Some(group) = address["resourceGroup"].as_str() {
+                tags.push(format!("resourceGroup={group}"));
+            }
+            tags.extend(label_tags(&address["tags"]));
+            Some(CloudAsset {
+                target: address["ipAddress"].as_str()?.to_owned(),
+                tags,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_cloud_sources() {
+        let source: CloudSource = "aws:profile=prod,region=eu-west-1".parse().unwrap();
+        assert_eq!(source.provider, Provider::Aws);
+        assert_eq!(source.options["profile"], "prod");
+        assert_eq!(source.to_string(), "aws:profile=prod,region=eu-west-1");
+
+        let source: CloudSource = "gcp".parse().unwrap();
+        assert_eq!(source.provider, Provider::Gcp);
+        assert!(source.options.is_empty());
+
+        assert!("digitalocean".parse::<CloudSource>().is_err());
+        assert!("aws:prod".parse::<CloudSource>().is_err());
+        assert!("azure:project=web".parse::<CloudSource>().is_err());
+    }
+
+    #[test]
+    fn parse_aws_output() {
+        let instances = json!({"Reservations": [{"Instances": [
+            {"InstanceId": "i-1", "PublicIpAddress": "203.0.113.1",
+             "Tags": [{"Key": "Name", "Value": "web"}]},
+            {"InstanceId": "i-2", "PrivateIpAddress": "10.0.0.2"}
+        ]}]});
+        assert_eq!(
+            parse_aws_instances(&instances),
+            vec![CloudAsset {
+                target: "203.0.113.1".to_owned(),
+                tags: vec!["aws:ec2".into(), "id=i-1".into(), "Name=web".into()],
+            }]
+        );
+
+        let balancers = json!({"LoadBalancers": [
+            {"LoadBalancerName": "public", "DNSName": "public.elb.amazonaws.com",
+             "Scheme": "internet-facing"},
+            {"LoadBalancerName": "private", "DNSName": "private.elb.amazonaws.com",
+             "Scheme": "internal"}
+        ]});
+        let assets = parse_aws_load_balancers(&balancers);
+        assert_eq!(assets.len(), 1);
+        assert_eq!(assets[0].target, "public.elb.amazonaws.com");
+    }
+
+    #[test]
+    fn parse_gcp_output() {
+        let instances = json!([
+            {"name": "web", "labels": {"env": "prod"}, "networkInterfaces": [
+                {"accessConfigs": [{"natIP": "198.51.100.7"}]}
+            ]},
+            {"name": "internal", "networkInterfaces": [{"networkIP": "10.0.0.3"}]}
+        ]);
+        assert_eq!(
+            parse_gcp_instances(&instances),
+            vec![CloudAsset {
+                target: "198.51.100.7".to_owned(),
+                tags: vec!["gcp:instance".into(), "name=web".into(), "env=prod".into()],
+            }]
+        );
+
+        let rules = json!([
+            {"name": "lb", "IPAddress": "198.51.100.8", "loadBalancingScheme": "EXTERNAL"},
+            {"name": "ilb", "IPAddress": "10.0.0.8", "loadBalancingScheme": "INTERNAL"}
+        ]);
+        let assets = parse_gcp_forwarding_rules(&rules);
+        assert_eq!(assets.len(), 1);
+        assert_eq!(assets[0].target, "198.51.100.8");
+    }
+
+    #[test]
+    fn tags_are_keyed_by_ip() {
+        let assets = vec![
+            CloudAsset {
+                target: "192.0.2.10".to_owned(),
+                tags: vec!["aws:ec2".into()],
+            },
+            CloudAsset {
+                target: "192.0.2.10".to_owned(),
+                tags: vec!["name=web".into()],
+            },
+        ];
+        let tags = tags_by_ip(&assets);
+        assert_eq!(
+            tags[&"192.0.2.10".parse::<IpAddr>().unwrap()],
+            vec!["aws:ec2", "name=web"]
+        );
+    }
+
+    #[test]
+    fn parse_azure_output() {
+        let addresses = json!([
+            {"name": "gateway", "resourceGroup": "prod", "ipAddress": "192.0.2.10",
+             "tags": {"team": "edge"}},
+            {"name": "unallocated", "resourceGroup": "prod"}
+        ]);
+        assert_eq!(
+            parse_azure_public_ips(&addresses),
+            vec![CloudAsset {
+                target: "192.0.2.10".to_owned(),
+                tags: vec![
+                    "azure:public-ip".into(),
+                    "name=gateway".into(),
+                    "resourceGroup=prod".into(),
+                    "team=edge".into()
+                ],
+            }]
+        );
+    }
+}
@@ -0,0 +1,842 @@
+//! Validates the configuration files RustScan reads.
+//!
+//! A plain toml error only says what went wrong at which byte. Here every
+//! problem is reported with the file, line and full key path, and typos of
+//! known keys or values come with a suggestion of what was probably meant:
+//!
+//! ```text
+//! /home/user/.rustscan.toml:3: `batchsize` is not a known key (did you mean `batch_size`?)
+//! ```
+//!
+//! It also scaffolds these files for new users with `rustscan config init`.
+#[cfg(feature = "scripts")]
+use crate::detail;
+#[cfg(feature = "scripts")]
+use crate::input::default_config_path;
+use crate::input::{resolve_config_path, Config, Opts};
+#[cfg(feature = "scripts")]
+use crate::scripts::ScriptConfig;
+use crate::{lenient, output, warning};
+use serde::de::{self, DeserializeOwned, Visitor};
+use std::fmt;
+use std::fs;
+#[cfg(feature = "scripts")]
+use std::io;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// The config file written by `rustscan config init`. Every option is
+/// commented out, values in the config file take precedence over command
+/// line arguments, so they should only be set deliberately.
+#[cfg_attr(not(feature = "scripts"), allow(dead_code))]
+static CONFIG_TEMPLATE: &str = r#"# RustScan configuration file.
+#
+# Every option is commented out and shows its default value. Uncomment an
+# option to use it for every scan. Values set here take precedence over the
+# command line arguments, so only set what you always want.
+
+# Addresses (CIDRs, IPs, hosts or host files) to scan.
+# addresses = ["127.0.0.1"]
+
+# Ports scanned when running with --top, or always when top is true.
+# top = true
+# ports = [22, 80, 443]
+
+# Ports only scanned over TCP or UDP, as with T: and U: in --ports.
+# tcp_ports = [22]
+# udp_ports = [53, 161]
+
+# Range of ports to scan.
+# range = { start = 1, end = 65535 }
+
+# Only output the open ports, don't run scripts.
+# greppable = false
+
+# Turn off features that negatively affect screen readers.
+# accessible = false
+
+# Hide the banner.
+# no_banner = false
+
+# How many ports are scanned at the same time.
+# batch_size = 4500
+
+# Milliseconds before a port is assumed to be closed.
+# timeout = 1500
+
+# How many tries before a port is assumed to be closed.
+# tries = 1
+
+# Raise the open file limit to this value.
+# ulimit = 5000
+
+# Comma separated DNS resolvers, or a file of them.
+# resolver = "1.1.1.1,8.8.8.8"
+
+# "Serial" or "Random".
+# scan_order = "Serial"
+
+# "None", "Default" (nmap) or "Custom" (see ~/.rustscan_scripts.toml).
+# scripts = "Default"
+
+# Extra arguments appended to the script command.
+# command = ["-A"]
+
+# Run scripts like nmap once per set of hosts with the same open ports.
+# group_scripts = false
+
+# Keep the -oA files of the default nmap script, and their merged XML.
+# nmap_output_dir = "nmap-results"
+
+# Ports to never scan.
+# exclude_ports = [9100]
+
+# CIDRs, IPs or hosts to never scan. Hostname patterns with * are matched
+# against the hostnames given as targets.
+# exclude_addresses = ["192.168.0.1", "*.internal.corp"]
+
+# Never scan private, loopback, multicast and other reserved addresses.
+# exclude_bogons = false
+
+# Which addresses of dual-stack hostnames to scan, both by default. Set at
+# most one of these.
+# prefer_ipv4 = true
+# prefer_ipv6 = true
+# only_ipv6 = true
+
+# File of targets and weights deciding what's scanned first, see --priorities.
+# priorities = "/home/me/priorities.txt"
+
+# Only scan inside this window, pausing outside it, see --window.
+# window = "Mon-Fri 22:00-06:00 Europe/Berlin"
+
+# Cap on the estimated traffic of the scan, see --max-bandwidth.
+# max_bandwidth = "5mbps"
+
+# Endpoint checked before and during the scan, pausing it while the route
+# is down, see --health-check.
+# health_check = "gateway.internal:443"
+
+# SSH jump host to connect to ports through, and how many ports it probes
+# at a time, see --via.
+# via = "ssh://admin@bastion.internal"
+# via_channels = 8
+
+# Scan UDP instead of TCP.
+# udp = false
+
+# SO_LINGER seconds applied before closing sockets of open ports.
+# linger = 0
+
+# Local ports to connect from.
+# local_port_range = { start = 40000, end = 60000 }
+
+# Ignore ALL_PROXY/HTTPS_PROXY/HTTP_PROXY for RustScan's HTTP requests.
+# no_proxy = false
+
+# Don't add scans to the history of 'rustscan history'.
+# no_history = false
+
+# Check for a newer RustScan release at startup (one anonymous HTTPS request).
+# update_check = false
+
+# Folder of native plugins to load.
+# plugins_dir = "/opt/rustscan/plugins"
+
+# Cloud accounts whose public IPs are scanned, see --cloud.
+# cloud = ["aws:profile=prod,region=eu-west-1"]
+
+# Kubeconfig of a cluster whose declared endpoints are scanned, see --k8s.
+# k8s = "/home/me/.kube/config"
+
+# Where results are exported to, see --output. Streams take the server and
+# topic (or subject) as kafka=http://rest-proxy:8082/topic or
+# nats=nats://localhost:4222/subject.
+# output = ["defectdojo=findings.json", "elastic=https://localhost:9200/rustscan"]
+
+# Kafka REST proxy and topic findings are published to, see --kafka-proxy.
+# Kafka is only reached through a REST proxy in front of the brokers.
+# kafka_proxy = "http://rest-proxy:8082"
+# kafka_topic = "rustscan"
+
+# NATS server and subject findings are published to, see --nats-server.
+# nats_server = "nats://localhost:4222"
+# nats_subject = "rustscan.ports"
+
+# Size past which appended outputs (json=results.jsonl.zst) are rotated.
+# output_rotate_size = "100M"
+
+# age recipient file outputs are encrypted to, see --encrypt-output.
+# encrypt_output = "age:age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p"
+
+# Tera template html outputs render instead of the built-in page, see
+# --report-template.
+# report_template = "/home/me/custom.html.tera"
+
+# Mask IPs and strip target hostnames in reports, see --redact.
+# redact = true
+
+# Durations in milliseconds and rates as whole numbers, see --raw-durations.
+# raw_durations = true
+
+# Run nuclei against the web servers found, see --nuclei, with these
+# templates and nuclei config file.
+# nuclei = true
+# nuclei_templates = "/home/me/nuclei-templates/http"
+# nuclei_config = "/home/me/.config/nuclei/config.yaml"
+
+# Flag services whose banner names a vulnerable version, see --vuln-hints,
+# with advisories from this file on top of the built-in ones.
+# vuln_hints = true
+# vuln_data = "/home/me/.config/rustscan/vulns.toml"
+
+# Try logging in without credentials to FTP, telnet, Redis and MongoDB,
+# see --default-creds. Only against systems you are authorized to test.
+# default_creds = true
+
+# Check FTP for anonymous logins, hosts for TFTP and the versions of
+# databases, see --service-probes.
+# service_probes = true
+
+# Identify PLCs and controllers behind Modbus, S7, DNP3 and BACnet, see
+# --ot-probes, and scan their ports one at a time without retries, see
+# --ot-safe.
+# ot_probes = true
+# ot_safe = true
+
+# Scan one port of a host at a time, waiting for each connection to be
+# closed and then for paranoid_delay milliseconds, see --paranoid.
+# paranoid = true
+# paranoid_delay = 1000
+
+# Scan the ports that make some printers and PLCs misbehave, which are
+# left out otherwise, see --allow-dangerous-ports.
+# allow_dangerous_ports = true
+
+# Stop scanning a host once this many of its ports are open, see
+# --max-open-per-host.
+# max_open_per_host = 1
+
+# Only find which hosts are up, probing a few common ports, see --mode.
+# mode = "Discovery"
+
+# Order of the hosts and ports in the results (Host, Port, Service or
+# Latency), and how to group the open ports (Subnet or Service), see
+# --sort-by and --group-by.
+# sort_by = "Port"
+# group_by = "Subnet"
+
+# States and services of the ports shown in results and reports, see
+# --show and --only-service.
+# show = ["open", "closed"]
+# only_service = ["http", "ssh"]
+
+# Bucket the file outputs and script output are uploaded to, see --upload.
+# upload = "s3://bucket/prefix/"
+
+# Server-side encryption of uploads: AES256, aws:kms, aws:kms:KEY_ID or a
+# Cloud KMS key name for gs:// buckets.
+# upload_sse = "aws:kms"
+
+# Labels attached to every result in structured outputs, see --label.
+# labels = ["env=prod", "ticket=SEC-123"]
+
+# Accepted open ports, one host:port per line, see --baseline. With
+# hide_known they're left out of the results.
+# baseline = "/home/me/baseline.txt"
+# hide_known = true
+
+# Firewall policy the open ports are compared to, see --policy.
+# policy = "/home/me/iptables-save.txt"
+
+# Alert on ports open on a network other than the ones given, see --alert.
+# alerts = ["10.0.0.0/24=80,443", "192.168.1.0/24=22"]
+
+# Print per-host probe counters after the scan, see --debug.
+# debug = true
+
+# Look up MAC addresses and vendors of local targets, see --mac.
+# mac = true
+# oui_file = "/usr/share/nmap/nmap-mac-prefixes"
+
+# Payloads sent to open TCP ports to confirm a service answers, see
+# --probe-payload.
+# probe_payloads = ["text:HEAD / HTTP/1.0\\r\\n\\r\\n", "443=hex:16030100"]
+
+# Show what open TCP ports send once connected, keeping banner_bytes of it,
+# see --banner.
+# banner = true
+# banner_bytes = 256
+
+# How TCP ports are probed, "Connect" or "Syn" (needs root or CAP_NET_RAW,
+# otherwise scans connect instead), see --tcp-engine.
+# tcp_engine = "Syn"
+
+# How UDP probes are sent, "Socket" or "Batched", see --udp-engine.
+# udp_engine = "Batched"
+
+# Bypass connection tracking for SYN scans with NOTRACK rules, removed on
+# exit, see --notrack.
+# notrack = true
+
+# Scan from inside this network namespace, see --netns.
+# netns = "vpn"
+
+# Keep no state about SYNs in flight, for very large sweeps, see --stateless.
+# stateless = true
+
+# Shuffle a stateless scan with this seed, resume it after this many sockets
+# and only scan one slice of it, see --shuffle-seed, --resume-index and
+# --shard.
+# shuffle_seed = 7
+# resume_index = 4096
+# shard = "3/10"
+
+# Stream open ports to shard files instead of keeping them in memory, see
+# --result-shards.
+# result_shards = "/var/tmp/sweep"
+
+# Scan with several threads, pinned to CPUs, see --threads.
+# threads = 4
+# cpu_affinity = "0-3"
+
+# Hosts accepting connections on every port are skipped, see
+# --verify-wildcards and --no-wildcard-check.
+# verify_wildcards = true
+# no_wildcard_check = false
+
+# Wake targets listed as 'host mac' pairs before scanning, see --wol.
+# wol = "/home/me/lab-macs.txt"
+# wol_wait = 5
+
+# Probe open ports for TLS and print their certificates, see --tls.
+# tls = true
+"#;
+
+/// The script config written by `rustscan config init`, `{directory}` is
+/// replaced with the scripts directory.
+#[cfg(feature = "scripts")]
+static SCRIPTS_CONFIG_TEMPLATE: &str = r#"# RustScan script configuration, used with --scripts custom.
+#
+# Only scripts whose tags are all listed here will run. Add "generator" to
+# run scripts tagged generator before the scan, their output becomes targets.
+tags = ["example"]
+
+# Where scripts are looked for.
+directory = "{directory}"
+
+# ports = ["80", "443"]
+# developer = ["you", "https://example.org"]
+"#;
+
+/// Example script written into the scripts directory.
+#[cfg(feature = "scripts")]
+static EXAMPLE_SCRIPT: &str = r#"#!/bin/sh
+#tags = ["example"]
+#developer = [ "example", "https://example.org" ]
+#ports_separator = ","
+#requires = ["sh"]
+#call_format = "sh {{script}} {{ip}} {{port}}"
+
+# The header above is read by RustScan up to the first line that isn't a
+# comment. {{script}}, {{ip}} and {{port}} are replaced before running, and
+# scripts whose requirements are missing are skipped.
+
+echo "Open ports on $1: $2"
+"#;
+
+/// A single problem found in a configuration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub key: String,
+    pub message: String,
+    pub suggestion: Option<String>,
+    /// Unknown keys are ignored when reading the config, so they are only
+    /// worth a warning, whereas invalid values abort the scan.
+    pub fatal: bool,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+        if self.key.is_empty() {
+            write!(f, ": {}", self.message)?;
+        } else {
+            write!(f, ": `{}` {}", self.key, self.message)?;
+        }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean `{suggestion}`?)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads and deserializes `content` into `T`, collecting every unknown key
+/// and, if deserialization fails, the invalid value as issues.
+pub fn parse<T: DeserializeOwned>(content: &str, file: &Path) -> (Option<T>, Vec<Issue>) {
+    let mut issues = Vec::new();
+    let deserializer = match toml::Deserializer::parse(content) {
+        Ok(deserializer) => deserializer,
+        Err(e) => {
+            issues.push(Issue {
+                file: file.to_path_buf(),
+                line: e.span().map(|span| line_of(content, span.start)),
+                key: String::new(),
+                message: e.message().trim().to_owned(),
+                suggestion: None,
+                fatal: true,
+            });
+            return (None, issues);
+        }
+    };
+
+    let known = field_names::<T>();
+    let mut unknown = Vec::new();
+    let result = serde_path_to_error::deserialize(serde_ignored::Deserializer::new(
+        deserializer,
+        &mut |path: serde_ignored::Path<'_>| unknown.push(key_path(&path.to_string())),
+    ));
+
+    for key in unknown {
+        let last = key.rsplit('.').next().unwrap_or(&key).to_owned();
+        issues.push(Issue {
+            file: file.to_path_buf(),
+            line: line_of_key(content, &last),
+            suggestion: closest(&last, known).map(ToOwned::to_owned),
+            key,
+            message: String::from("is not a known key"),
+            fatal: false,
+        });
+    }
+
+    match result {
+        Ok(value) => (Some(value), issues),
+        Err(e) => {
+            let key = key_path(&e.path().to_string());
+            let inner = e.into_inner();
+            let message = inner.message().trim().to_owned();
+            let candidates = expected_values(&message);
+            let found = message.split('`').nth(1).unwrap_or_default();
+            issues.push(Issue {
+                file: file.to_path_buf(),
+                line: inner
+                    .span()
+                    .map(|span| line_of(content, span.start))
+                    .or_else(|| line_of_key(content, key.rsplit('.').next().unwrap_or(&key))),
+                suggestion: closest(found, &candidates).map(ToOwned::to_owned),
+                key,
+                message: format!("has an invalid value: {message}"),
+                fatal: true,
+            });
+            (None, issues)
+        }
+    }
+}
+
+/// Reads the file at `path` with [`lenient`] and parses it like [`parse`].
+/// Lines that aren't text are skipped with an issue each.
+pub fn parse_file<T: DeserializeOwned>(path: &Path) -> (Option<T>, Vec<Issue>) {
+    match fs::File::open(path) {
+        Ok(file) => {
+            let (content, mut issues) = lenient::read_text(BufReader::new(file), path);
+            let (value, more) = parse::<T>(&content, path);
+            issues.extend(more);
+            (value, issues)
+        }
+        Err(e) => (
+            None,
+            vec![Issue {
+                file: path.to_path_buf(),
+                line: None,
+                key: String::new(),
+                message: format!("could not be read: {e}"),
+                suggestion: None,
+                fatal: true,
+            }],
+        ),
+    }
+}
+
+/// Validates the file at `path` as `T`, returns every issue found.
+pub fn validate_file<T: DeserializeOwned>(path: &Path) -> Vec<Issue> {
+    parse_file::<T>(path).1
+}
+
+/// Checks a file, see [`validate_file`].
+type Validator = fn(&Path) -> Vec<Issue>;
+
+/// Validates the config file and, if present, the script config file.
+/// Prints every issue found and returns whether both files are clean.
+pub fn validate(opts: &Opts) -> bool {
+    #[allow(unused_mut)]
+    let mut files: Vec<(PathBuf, Validator)> = vec![(
+        resolve_config_path(opts.config_path.clone()),
+        validate_file::<Config>,
+    )];
+    #[cfg(feature = "scripts")]
+    if let Ok(path) = ScriptConfig::config_path() {
+        files.push((path, validate_file::<ScriptConfig>));
+    }
+
+    let mut valid = true;
+    for (path, validate_file) in files {
+        if !path.exists() {
+            output!(
+                format!("{} does not exist, nothing to validate", path.display()),
+                false,
+                opts.accessible
+            );
+            continue;
+        }
+        let issues = validate_file(&path);
+        if issues.is_empty() {
+            output!(
+                format!("{} is valid", path.display()),
+                false,
+                opts.accessible
+            );
+        }
+        for issue in &issues {
+            warning!(issue, false, opts.accessible);
+        }
+        valid &= issues.is_empty();
+    }
+    valid
+}
+
+/// Writes the default config, script config and an example script, unless
+/// they already exist and `force` isn't set. Returns whether it succeeded.
+#[cfg(feature = "scripts")]
+pub fn init(opts: &Opts, force: bool) -> bool {
+    let config_path = opts.config_path.clone().unwrap_or_else(default_config_path);
+    let (Ok(scripts_config_path), Some(home_dir)) = (ScriptConfig::config_path(), dirs::home_dir())
+    else {
+        warning!(
+            "Could not infer the home directory.",
+            false,
+            opts.accessible
+        );
+        return false;
+    };
+    let scripts_dir = home_dir.join(".rustscan_scripts");
+
+    match write_scaffold(&config_path, &scripts_config_path, &scripts_dir, force) {
+        Ok(written) => {
+            for (path, created) in written {
+                if created {
+                    output!(format!("Wrote {}", path.display()), false, opts.accessible);
+                } else {
+                    detail!(
+                        format!(
+                            "{} already exists, use --force to overwrite it",
+                            path.display()
+                        ),
+                        false,
+                        opts.accessible
+                    );
+                }
+            }
+            true
+        }
+        Err(e) => {
+            warning!(
+                format!("Failed to write the configuration: {e}"),
+                false,
+                opts.accessible
+            );
+            false
+        }
+    }
+}
+
+/// Writes the scaffold files, returns each path and whether it was written.
+#[cfg(feature = "scripts")]
+fn write_scaffold(
+    config_path: &Path,
+    scripts_config_path: &Path,
+    scripts_dir: &Path,
+    force: bool,
+) -> io::Result<Vec<(PathBuf, bool)>> {
+    let scripts_config =
+        SCRIPTS_CONFIG_TEMPLATE.replace("{directory}", &scripts_dir.display().to_string());
+    let files = [
+        (config_path.to_path_buf(), CONFIG_TEMPLATE.to_owned()),
+        (scripts_config_path.to_path_buf(), scripts_config),
+        (scripts_dir.join("example.sh"), EXAMPLE_SCRIPT.to_owned()),
+    ];
+
+    let mut written = Vec::with_capacity(files.len());
+    for (path, content) in files {
+        if path.exists() && !force {
+            written.push((path, false));
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, content)?;
+        written.push((path, true));
+    }
+    Ok(written)
+}
+
+/// Turns a serde path like `range.?.start` into the key as written in
+/// the file, `range.start`, dropping the segments serde adds for options.
+fn key_path(path: &str) -> String {
+    path.split('.')
+        .filter(|segment| !segment.is_empty() && *segment != "?")
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// 1-based line number of the byte `offset` within `content`.
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+/// Finds the line a key is defined on, either as `key = ...` or `[key]`.
+fn line_of_key(content: &str, key: &str) -> Option<usize> {
+    content
+        .lines()
+        .position(|line| {
+            let line = line.trim_start();
+            let defines = |rest: &str| rest.trim_start().starts_with('=');
+            line.strip_prefix(key).is_some_and(defines)
+                || line
+                    .strip_prefix('"')
+                    .and_then(|l| l.strip_prefix(key))
+                    .and_then(|l| l.strip_prefix('"'))
+                    .is_some_and(defines)
+                || line
+                    .trim_start_matches('[')
+                    .trim_end()
+                    .trim_end_matches(']')
+                    .eq(key)
+        })
+        .map(|index| index + 1)
+}
+
+/// Pulls the backticked candidates out of a serde "expected one of" message,
+/// e.g. "unknown variant `Randm`, expected `Serial` or `Random`".
+fn expected_values(message: &str) -> Vec<&str> {
+    message
+        .split_once("expected")
+        .map(|(_, expected)| expected.split('`').skip(1).step_by(2).collect())
+        .unwrap_or_default()
+}
+
+/// Picks the candidate closest to `input`, if any is close enough to
+/// plausibly be what was meant.
+fn closest<'a>(input: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let input = input.to_lowercase();
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(&input, &candidate.to_lowercase()), *candidate))
+        .filter(|(distance, _)| *distance <= 2.max(input.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Returns the field names serde expects for the struct `T`.
+///
+/// serde doesn't expose these directly, but every derived struct passes
+/// them to `Deserializer::deserialize_struct`, so a deserializer that
+/// records them and bails out is enough to get hold of them.
+fn field_names<T: DeserializeOwned>() -> &'static [&'static str] {
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> de::Deserializer<'de> for FieldNames<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("fields recorded"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closest, field_names, parse, Issue, CONFIG_TEMPLATE};
+    #[cfg(feature = "scripts")]
+    use super::{write_scaffold, SCRIPTS_CONFIG_TEMPLATE};
+    use crate::input::Config;
+    #[cfg(feature = "scripts")]
+    use crate::scripts::{find_scripts, parse_scripts, ScriptConfig};
+    #[cfg(feature = "scripts")]
+    use std::fs;
+    use std::path::Path;
+
+    fn issues_of<T: serde::de::DeserializeOwned>(content: &str) -> Vec<Issue> {
+        parse::<T>(content, Path::new("test.toml")).1
+    }
+
+    #[test]
+    fn field_names_of_config() {
+        #[cfg(feature = "scripts")]
+        assert_eq!(
+            field_names::<ScriptConfig>(),
+            ["tags", "ports", "developer", "directory"]
+        );
+        assert!(field_names::<Config>().contains(&"batch_size"));
+    }
+
+    #[test]
+    fn valid_config_has_no_issues() {
+        let (config, issues) = parse::<Config>(
+            "addresses = [\"127.0.0.1\"]\nbatch_size = 100\n",
+            Path::new("test.toml"),
+        );
+        assert!(config.is_some());
+        assert!(issues.is_empty());
+    }
+
+    #[test]
+    fn unknown_key_is_reported_with_suggestion() {
+        let issues = issues_of::<Config>("greppable = true\nbatchsize = 100\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].key, "batchsize");
+        assert_eq!(issues[0].line, Some(2));
+        assert_eq!(issues[0].suggestion.as_deref(), Some("batch_size"));
+        assert!(!issues[0].fatal);
+        assert_eq!(
+            issues[0].to_string(),
+            "test.toml:2: `batchsize` is not a known key (did you mean `batch_size`?)"
+        );
+    }
+
+    #[test]
+    fn nested_unknown_key_has_full_path() {
+        let issues = issues_of::<Config>("[range]\nstart = 1\nend = 10\nstep = 2\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].key, "range.step");
+        assert_eq!(issues[0].line, Some(4));
+    }
+
+    #[test]
+    fn invalid_value_is_fatal() {
+        let issues = issues_of::<Config>("greppable = true\nbatch_size = \"many\"\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].key, "batch_size");
+        assert_eq!(issues[0].line, Some(2));
+        assert!(issues[0].fatal);
+    }
+
+    #[test]
+    fn invalid_variant_gets_suggestion() {
+        let issues = issues_of::<Config>("scan_order = \"Randon\"\n");
+        assert_eq!(issues[0].key, "scan_order");
+        assert_eq!(issues[0].suggestion.as_deref(), Some("Random"));
+    }
+
+    #[test]
+    #[cfg(feature = "scripts")]
+    fn syntax_error_has_line() {
+        let issues = issues_of::<ScriptConfig>("tags = [\"a\"]\ndirectory = \n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, Some(2));
+        assert!(issues[0].fatal);
+    }
+
+    #[test]
+    fn closest_ignores_unrelated_names() {
+        assert_eq!(closest("udpp", &["udp", "ulimit"]), Some("udp"));
+        assert_eq!(closest("foobar", &["udp", "ulimit"]), None);
+    }
+
+    #[test]
+    fn config_template_documents_every_key() {
+        let uncommented = CONFIG_TEMPLATE
+            .lines()
+            .filter_map(|line| line.strip_prefix("# "))
+            .filter(|line| line.contains(" = "))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (config, issues) = parse::<Config>(&uncommented, Path::new("template"));
+        assert!(config.is_some(), "{:?}", issues);
+        assert!(issues.is_empty(), "{:?}", issues);
+
+        for field in field_names::<Config>() {
+            assert!(
+                uncommented.contains(&format!("{field} = ")),
+                "{} is missing from the config template",
+                field
+            );
+        }
+        assert!(parse::<Config>(CONFIG_TEMPLATE, Path::new("template"))
+            .1
+            .is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "scripts")]
+    fn scaffold_is_written_and_usable() {
+        let dir = std::env::temp_dir().join(format!("rustscan-init-{}", std::process::id()));
+        let config_path = dir.join("config").join(".rustscan.toml");
+        let scripts_config_path = dir.join(".rustscan_scripts.toml");
+        let scripts_dir = dir.join(".rustscan_scripts");
+
+        let written =
+            write_scaffold(&config_path, &scripts_config_path, &scripts_dir, false).unwrap();
+        assert!(written.iter().all(|(_, created)| *created));
+
+        let content = fs::read_to_string(&scripts_config_path).unwrap();
+        let (scripts_config, issues) = parse::<ScriptConfig>(&content, &scripts_config_path);
+        assert!(issues.is_empty(), "{:?}", issues);
+        let scripts_dir_read = scripts_config.unwrap().directory.unwrap();
+        let (scripts, issues) = parse_scripts(find_scripts(scripts_dir_read.into()).unwrap());
+        assert!(issues.is_empty(), "{:?}", issues);
+        assert_eq!(scripts.len(), 1);
+        assert_eq!(scripts[0].tags, Some(vec!["example".to_owned()]));
+
+        // Existing files are kept unless forced.
+        let written =
+            write_scaffold(&config_path, &scripts_config_path, &scripts_dir, false).unwrap();
+        assert!(written.iter().all(|(_, created)| !*created));
+        let written =
+            write_scaffold(&config_path, &scripts_config_path, &scripts_dir, true).unwrap();
+        assert!(written.iter().all(|(_, created)| *created));
+
+        fs::remove_dir_all(dir).unwrap();
+        assert!(SCRIPTS_CONFIG_TEMPLATE.contains("{directory}"));
+    }
+}
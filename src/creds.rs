@@ -0,0 +1,328 @@
+//! Checks open ports for services that let anyone in.
+//!
+//! With `--default-creds`, once the scan is done, open ports of a few
+//! protocols on their well-known port get a single login attempt each:
+//!
+//! - FTP (21) logs in as `anonymous`,
+//! - telnet (23) is read until it asks for a login or shows a shell prompt,
+//!   without sending anything,
+//! - Redis (6379) is sent `PING` without `AUTH`,
+//! - MongoDB (27017) is asked to list its databases without logging in.
+//!
+//! No other credentials are tried. Attempts are made one at a time, at most
+//! one per [`ATTEMPT_INTERVAL`], and only whether they succeeded is kept:
+//! nothing the services send back is printed or stored. Logging in to
+//! systems without permission is illegal in most places, so a warning is
+//! printed before the first attempt.
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The time between the start of two login attempts.
+pub const ATTEMPT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The most read from a service before giving up on its answer.
+const ANSWER_LIMIT: u64 = 16 * 1024;
+
+/// What `--default-creds` tells the user before trying anything.
+pub const WARNING: &str = "--default-creds logs in to FTP, telnet, Redis and MongoDB services \
+     without credentials. Only use it against systems you are authorized to test";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    Ftp,
+    Telnet,
+    Redis,
+    MongoDb,
+}
+
+/// The ports attempts are made on, and their protocol.
+const PROTOCOLS: [(u16, Protocol); 4] = [
+    (21, Protocol::Ftp),
+    (23, Protocol::Telnet),
+    (6379, Protocol::Redis),
+    (27017, Protocol::MongoDb),
+];
+
+impl Protocol {
+    fn of(port: u16) -> Option<Self> {
+        PROTOCOLS
+            .iter()
+            .find(|(known, _)| *known == port)
+            .map(|(_, protocol)| *protocol)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Ftp => "ftp",
+            Self::Telnet => "telnet",
+            Self::Redis => "redis",
+            Self::MongoDb => "mongodb",
+        }
+    }
+
+    /// What is tried, for the user.
+    fn credentials(self) -> &'static str {
+        match self {
+            Self::Ftp => "anonymous",
+            Self::Telnet | Self::Redis | Self::MongoDb => "none",
+        }
+    }
+
+    /// Whether the service behind `stream` let us in.
+    fn login(self, stream: &TcpStream) -> io::Result<bool> {
+        match self {
+            Self::Ftp => ftp_anonymous(stream).map(|(_, logged_in)| logged_in),
+            Self::Telnet => {
+                let mut banner = Vec::new();
+                read_until_quiet(stream, &mut banner)?;
+                Ok(open_shell(&banner))
+            }
+            Self::Redis => {
+                let mut stream = stream;
+                stream.write_all(b"PING\r\n")?;
+                let mut answer = [0; 5];
+                stream.read_exact(&mut answer)?;
+                Ok(&answer == b"+PONG")
+            }
+            Self::MongoDb => mongodb_unauthenticated(stream),
+        }
+    }
+}
+
+/// Whether a login attempt succeeded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attempt {
+    pub socket: SocketAddr,
+    pub protocol: String,
+    /// The credentials tried, `none` when none were needed to get in.
+    pub credentials: String,
+    pub success: bool,
+}
+
+/// Reads FTP replies until the last line of one, returns its code and
+/// that line.
+fn ftp_reply(reader: &mut impl BufRead) -> io::Result<(u16, String)> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        // Multi-line replies go on until a line with the code and a space.
+        if line.as_bytes().get(3) == Some(&b' ') {
+            if let Ok(code) = line[..3].parse() {
+                return Ok((code, line.trim_end().to_owned()));
+            }
+        }
+    }
+}
+
+/// Logs in to the FTP server behind `stream` as `anonymous`, returns its
+/// greeting and whether it let us in.
+pub(crate) fn ftp_anonymous(stream: &TcpStream) -> io::Result<(String, bool)> {
+    let mut reader = BufReader::new(stream.take(ANSWER_LIMIT));
+    let mut writer = stream;
+    let (code, greeting) = ftp_reply(&mut reader)?;
+    if code != 220 {
+        return Ok((greeting, false));
+    }
+    writer.write_all(b"USER anonymous\r\n")?;
+    match ftp_reply(&mut reader)?.0 {
+        230 => return Ok((greeting, true)),
+        331 => {}
+        _ => return Ok((greeting, false)),
+    }
+    writer.write_all(b"PASS anonymous@\r\n")?;
+    let logged_in = ftp_reply(&mut reader)?.0 == 230;
+    let _ = writer.write_all(b"QUIT\r\n");
+    Ok((greeting, logged_in))
+}
+
+/// Reads into `answer` until the service stops sending, or sent too much.
+fn read_until_quiet(stream: &TcpStream, answer: &mut Vec<u8>) -> io::Result<()> {
+    match stream.take(ANSWER_LIMIT).read_to_end(answer) {
+        Ok(_) => Ok(()),
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether a telnet service shows a shell prompt without asking who's
+/// there.
+fn open_shell(banner: &[u8]) -> bool {
+    // Skips telnet option negotiation, IAC followed by two bytes.
+    let mut text = String::new();
+    let mut bytes = banner.iter();
+    while let Some(byte) = bytes.next() {
+        if *byte == 0xff {
+            bytes.nth(1);
+        } else if byte.is_ascii() {
+            text.push(char::from(*byte));
+        }
+    }
+    let lowercase = text.to_lowercase();
+    if ["login", "username", "user name", "password"]
+        .iter()
+        .any(|prompt| lowercase.contains(prompt))
+    {
+        return false;
+    }
+    text.trim_end().ends_with(['#', '$', '>'])
+}
+
+/// The MongoDB `command` on the `admin` database, as OP_MSG.
+fn mongodb_message(command: &str) -> Vec<u8> {
+    let mut document = Vec::new();
+    document.push(0x10);
+    document.extend_from_slice(command.as_bytes());
+    document.push(0);
+    document.extend_from_slice(&1_i32.to_le_bytes());
+    document.push(0x02);
+    document.extend_from_slice(b"$db\0");
+    document.extend_from_slice(&6_i32.to_le_bytes());
+    document.extend_from_slice(b"admin\0");
+    document.push(0);
+    let length = i32::try_from(document.len() + 4).unwrap_or(i32::MAX);
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&0_u32.to_le_bytes());
+    body.push(0);
+    body.extend_from_slice(&length.to_le_bytes());
+    body.extend_from_slice(&document);
+
+    let mut message = Vec::new();
+    let length = i32::try_from(body.len() + 16).unwrap_or(i32::MAX);
+    message.extend_from_slice(&length.to_le_bytes());
+    message.extend_from_slice(&1_i32.to_le_bytes());
+    message.extend_from_slice(&0_i32.to_le_bytes());
+    message.extend_from_slice(&2013_i32.to_le_bytes());
+    message.extend_from_slice(&body);
+    message
+}
+
+/// Sends `command` to the MongoDB server behind `stream`, returns its
+/// reply but the length that starts it.
+pub(crate) fn mongodb_command(stream: &TcpStream, command: &str) -> io::Result<Vec<u8>> {
+    let mut stream = stream;
+    stream.write_all(&mongodb_message(command))?;
+    let mut length = [0; 4];
+    stream.read_exact(&mut length)?;
+    let length = u64::from(u32::from_le_bytes(length)).saturating_sub(4);
+    let mut answer = Vec::new();
+    stream
+        .take(length.min(ANSWER_LIMIT))
+        .read_to_end(&mut answer)?;
+    Ok(answer)
+}
+
+fn mongodb_unauthenticated(stream: &TcpStream) -> io::Result<bool> {
+    let answer = mongodb_command(stream, "listDatabases")?;
+    // Only a successful listing has the size of the databases.
+    Ok(answer
+        .windows(b"totalSize".len())
+        .any(|window| window == b"totalSize"))
+}
+
+/// Makes one attempt on `socket`, if its port is one of [`PROTOCOLS`].
+fn attempt(socket: SocketAddr, timeout: Duration) -> Option<Attempt> {
+    let protocol = Protocol::of(socket.port())?;
+    let success = TcpStream::connect_timeout(&socket, timeout)
+        .and_then(|stream| {
+            stream.set_read_timeout(Some(timeout))?;
+            stream.set_write_timeout(Some(timeout))?;
+            protocol.login(&stream)
+        })
+        .unwrap_or_else(|e| {
+            debug!("{} login attempt on {socket} failed: {e}", protocol.name());
+            false
+        });
+    Some(Attempt {
+        socket,
+        protocol: protocol.name().to_owned(),
+        credentials: protocol.credentials().to_owned(),
+        success,
+    })
+}
+
+/// Tries the sockets of known protocols one at a time, at most one per
+/// `interval`.
+pub fn check(sockets: &[SocketAddr], timeout: Duration, interval: Duration) -> Vec<Attempt> {
+    let mut attempts = Vec::new();
+    let mut last: Option<Instant> = None;
+    for socket in sockets {
+        if Protocol::of(socket.port()).is_none() {
+            continue;
+        }
+        if let Some(last) = last {
+            thread::sleep(interval.saturating_sub(last.elapsed()));
+        }
+        last = Some(Instant::now());
+        attempts.extend(attempt(*socket, timeout));
+    }
+    attempts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{attempt, open_shell, Protocol};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn telnet_prompts_tell_open_shells() {
+        assert!(open_shell(b"\xff\xfd\x18\xff\xfb\x01BusyBox v1.19.4\r\n# "));
+        assert!(!open_shell(
+            b"\xff\xfd\x18Ubuntu 22.04 LTS\r\nrouter login: "
+        ));
+        assert!(!open_shell(b"Password: "));
+        assert!(!open_shell(b""));
+    }
+
+    #[test]
+    fn ftp_anonymous_login() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut commands = Vec::new();
+            writer
+                .write_all(b"220-Welcome\r\n220 FTP ready\r\n")
+                .unwrap();
+            for reply in ["331 Password required\r\n", "230 Logged in\r\n"] {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                commands.push(line);
+                writer.write_all(reply.as_bytes()).unwrap();
+            }
+            commands
+        });
+        // Attempts only go to well-known ports, so the protocol is called
+        // directly here.
+        let socket: SocketAddr = format!("127.0.0.1:{port}").parse().unwrap();
+        let stream = std::net::TcpStream::connect(socket).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert!(Protocol::Ftp.login(&stream).unwrap());
+        assert_eq!(
+            server.join().unwrap(),
+            vec!["USER anonymous\r\n", "PASS anonymous@\r\n"]
+        );
+        assert!(attempt(socket, Duration::from_millis(100)).is_none());
+    }
+}
@@ -0,0 +1,355 @@
+//! Runs scans in the background, detached from the terminal.
+//!
+//! `rustscan --detach ...` starts the same scan as a new process in its own
+//! session, so it survives the SSH session that started it, logs its output
+//! to a file and returns right away. Every detached scan gets a pidfile,
+//! `<pid>.json` in the cache directory, holding its PID, log file and
+//! command line. `rustscan status` lists them and `rustscan stop` ends one.
+//!
+//! Detaching relies on Unix sessions and signals, so it's only available
+//! there.
+use crate::input::{env_var, Opts};
+use crate::{detail, output, warning};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A detached scan, as recorded in its pidfile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetachedScan {
+    pub pid: u32,
+    pub started: DateTime<Utc>,
+    pub log: PathBuf,
+    pub args: Vec<String>,
+}
+
+/// The directory holding the pidfiles of detached scans.
+pub fn runs_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("rustscan")
+        .join("detached")
+}
+
+fn pidfile(dir: &Path, pid: u32) -> PathBuf {
+    dir.join(format!("{pid}.json"))
+}
+
+/// The arguments of the detached scan: the same ones, minus `--detach`.
+fn scan_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    args.into_iter().filter(|arg| arg != "--detach").collect()
+}
+
+/// The detached scans with a pidfile in `dir`, oldest first.
+pub fn list(dir: &Path) -> Vec<DetachedScan> {
+    let mut scans: Vec<DetachedScan> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| fs::read(entry.path()).ok())
+        .filter_map(|content| serde_json::from_slice(&content).ok())
+        .collect();
+    scans.sort_by_key(|scan| scan.started);
+    scans
+}
+
+/// Whether `pid` is still a running scan. Once a scan ends its PID can be
+/// reused by another program, so the process must run this executable too.
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(target) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks the process exists and can be signalled.
+    let exists = unsafe { libc::kill(target, 0) == 0 };
+    exists && runs_this_executable(pid)
+}
+
+/// Whether process `pid` runs the same executable as this one, even if it
+/// has been replaced on disk since it started.
+#[cfg(target_os = "linux")]
+fn runs_this_executable(pid: u32) -> bool {
+    let (Ok(exe), Ok(current)) = (
+        fs::read_link(format!("/proc/{pid}/exe")),
+        std::env::current_exe(),
+    ) else {
+        return false;
+    };
+    let exe = exe.to_string_lossy();
+    let current = current.to_string_lossy();
+    exe.strip_suffix(" (deleted)").unwrap_or(&exe)
+        == current.strip_suffix(" (deleted)").unwrap_or(&current)
+}
+
+/// Whether process `pid` runs an executable named like this one, as `ps`
+/// tells where there's no `/proc`.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn runs_this_executable(pid: u32) -> bool {
+    let Ok(output) = std::process::Command::new("ps")
+        .args(["-o", "command=", "-p", &pid.to_string()])
+        .output()
+    else {
+        return false;
+    };
+    let command = String::from_utf8_lossy(&output.stdout);
+    let name = |path: &Path| path.file_name().map(std::ffi::OsStr::to_os_string);
+    let program = command.split_whitespace().next().map(Path::new);
+    match (program.and_then(name), std::env::current_exe()) {
+        (Some(program), Ok(current)) => Some(program) == name(&current),
+        _ => false,
+    }
+}
+
+/// Starts the scan of `opts` again in the background, returns whether it
+/// started.
+#[cfg(unix)]
+#[cfg(not(tarpaulin_include))]
+pub fn detach(opts: &Opts) -> bool {
+    match spawn(opts) {
+        Ok(scan) => {
+            output!(
+                format!(
+                    "Scanning in the background as PID {}, logging to {}",
+                    scan.pid,
+                    scan.log.display()
+                ),
+                false,
+                opts.accessible
+            );
+            detail!(
+                "Check on it with 'rustscan status', end it with 'rustscan stop'",
+                false,
+                opts.accessible
+            );
+            true
+        }
+        Err(e) => {
+            warning!(
+                format!("Could not detach the scan: {e}"),
+                false,
+                opts.accessible
+            );
+            false
+        }
+    }
+}
+
+#[cfg(unix)]
+fn spawn(opts: &Opts) -> Result<DetachedScan> {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let dir = runs_dir();
+    fs::create_dir_all(&dir)?;
+    let started = Utc::now();
+    let log = opts
+        .log_file
+        .clone()
+        .unwrap_or_else(|| dir.join(format!("scan-{}.log", started.format("%Y%m%d-%H%M%S"))));
+    let stdout =
+        fs::File::create(&log).map_err(|e| anyhow!("Could not create {}: {e}", log.display()))?;
+    let stderr = stdout.try_clone()?;
+    let args = scan_args(std::env::args().skip(1));
+
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(&args)
+        .env_remove(env_var("detach"))
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr);
+    // A new session leaves the terminal behind, and its hangup with it.
+    // SAFETY: setsid is async-signal-safe, so it can run between fork and
+    // exec.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let child = command.spawn()?;
+
+    let scan = DetachedScan {
+        pid: child.id(),
+        started,
+        log,
+        args,
+    };
+    fs::write(pidfile(&dir, scan.pid), serde_json::to_vec_pretty(&scan)?)?;
+    Ok(scan)
+}
+
+/// Prints the detached scans, forgetting the ones that finished after
+/// printing them. Returns whether any is running.
+#[cfg(unix)]
+#[cfg(not(tarpaulin_include))]
+pub fn status(opts: &Opts) -> bool {
+    let dir = runs_dir();
+    let scans = list(&dir);
+    if scans.is_empty() {
+        output!("No detached scans", false, opts.accessible);
+        return false;
+    }
+    let mut running = false;
+    for scan in scans {
+        let state = if is_running(scan.pid) {
+            running = true;
+            "running"
+        } else {
+            let _ = fs::remove_file(pidfile(&dir, scan.pid));
+            "finished"
+        };
+        output!(
+            format!(
+                "PID {} {state}, started {}: rustscan {}",
+                scan.pid,
+                scan.started.to_rfc3339(),
+                scan.args.join(" ")
+            ),
+            false,
+            opts.accessible
+        );
+        detail!(
+            format!("    Log: {}", scan.log.display()),
+            false,
+            opts.accessible
+        );
+    }
+    running
+}
+
+/// Sends SIGTERM to the detached scan `pid`, or to the only one running.
+/// Returns whether a scan was stopped.
+#[cfg(unix)]
+#[cfg(not(tarpaulin_include))]
+pub fn stop(pid: Option<u32>, opts: &Opts) -> bool {
+    let dir = runs_dir();
+    let result = (|| {
+        let running: Vec<DetachedScan> = list(&dir)
+            .into_iter()
+            .filter(|scan| is_running(scan.pid))
+            .collect();
+        let scan = match (pid, running.as_slice()) {
+            (Some(pid), _) => running
+                .iter()
+                .find(|scan| scan.pid == pid)
+                .ok_or_else(|| anyhow!("No detached scan is running as PID {pid}"))?,
+            (None, [scan]) => scan,
+            (None, []) => return Err(anyhow!("No detached scan is running")),
+            (None, _) => {
+                return Err(anyhow!(
+                    "{} detached scans are running, pick one with its PID from 'rustscan status'",
+                    running.len()
+                ))
+            }
+        };
+        let target = libc::pid_t::try_from(scan.pid)?;
+        // SAFETY: kill has no memory safety requirements.
+        if unsafe { libc::kill(target, libc::SIGTERM) } != 0 {
+            return Err(anyhow!(
+                "Could not stop PID {}: {}",
+                scan.pid,
+                std::io::Error::last_os_error()
+            ));
+        }
+        let _ = fs::remove_file(pidfile(&dir, scan.pid));
+        Ok(scan.clone())
+    })();
+    match result {
+        Ok(scan) => {
+            output!(
+                format!(
+                    "Stopped PID {}, its log is {}",
+                    scan.pid,
+                    scan.log.display()
+                ),
+                false,
+                opts.accessible
+            );
+            true
+        }
+        Err(e) => {
+            warning!(format!("{e}"), false, opts.accessible);
+            false
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn unsupported(opts: &Opts) -> bool {
+    warning!(
+        "Detached scans are only supported on Unix",
+        false,
+        opts.accessible
+    );
+    false
+}
+
+#[cfg(not(unix))]
+pub fn detach(opts: &Opts) -> bool {
+    unsupported(opts)
+}
+
+#[cfg(not(unix))]
+pub fn status(opts: &Opts) -> bool {
+    unsupported(opts)
+}
+
+#[cfg(not(unix))]
+pub fn stop(_pid: Option<u32>, opts: &Opts) -> bool {
+    unsupported(opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{list, pidfile, scan_args, DetachedScan};
+    use chrono::{TimeZone, Utc};
+    use std::fs;
+
+    #[test]
+    fn detached_args_drop_detach() {
+        let args = ["-a", "10.0.0.0/24", "--detach", "-g"].map(String::from);
+        assert_eq!(scan_args(args), vec!["-a", "10.0.0.0/24", "-g"]);
+    }
+
+    #[test]
+    fn lists_pidfiles_oldest_first() {
+        let dir = std::env::temp_dir().join(format!("rustscan-detached-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (pid, hour) in [(200, 9), (100, 8)] {
+            let scan = DetachedScan {
+                pid,
+                started: Utc.with_ymd_and_hms(2024, 5, 1, hour, 0, 0).unwrap(),
+                log: dir.join(format!("{pid}.log")),
+                args: vec!["-a".to_owned(), "10.0.0.1".to_owned()],
+            };
+            fs::write(pidfile(&dir, pid), serde_json::to_vec(&scan).unwrap()).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "not a pidfile").unwrap();
+
+        let pids: Vec<u32> = list(&dir).iter().map(|scan| scan.pid).collect();
+        assert_eq!(pids, vec![100, 200]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn checks_processes_are_running() {
+        assert!(super::is_running(std::process::id()));
+        // A process running something else, as one reusing the PID of a
+        // finished scan would, isn't one.
+        let mut other = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        assert!(!super::is_running(other.id()));
+        other.kill().unwrap();
+        other.wait().unwrap();
+    }
+}
@@ -0,0 +1,356 @@
+//! Diagnoses the environment RustScan runs in.
+//!
+//! Most scans that go wrong do so because of the machine they run on rather
+//! than the target: a low file limit, nmap missing from `PATH`, a broken
+//! script directory or a resolver that can't be reached. `rustscan doctor`
+//! runs every check in turn and prints how to fix what it finds.
+use crate::address::get_resolver;
+use crate::config::validate_file;
+use crate::http::HttpClient;
+use crate::input::{resolve_config_path, Config, Opts};
+use crate::privileges::{self, Availability};
+#[cfg(feature = "scripts")]
+use crate::scripts::{find_scripts, parse_scripts, ScriptConfig};
+use crate::{detail, output, warning};
+
+#[cfg(feature = "scripts")]
+use std::fs;
+use std::net::{SocketAddr, UdpSocket};
+use std::process::Command;
+
+/// The outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// A single diagnostic with what was found and, when it isn't healthy,
+/// how to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Warn,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Fail,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Runs every check and prints the results, returns whether none failed.
+pub fn run(opts: &Opts) -> bool {
+    #[allow(unused_mut)]
+    let mut checks = vec![
+        check_file_limit(opts),
+        check_raw_sockets(),
+        check_nmap(),
+        check_config(opts),
+        check_resolver(opts),
+        check_proxy(opts),
+        check_ipv6(),
+    ];
+    #[cfg(feature = "scripts")]
+    checks.insert(3, check_scripts());
+    print_checks(&checks, opts.accessible);
+    !checks.iter().any(|check| check.status == Status::Fail)
+}
+
+fn print_checks(checks: &[Check], accessible: bool) {
+    for check in checks {
+        let line = format!("{}: {}", check.name, check.detail);
+        match check.status {
+            Status::Ok => output!(line, false, accessible),
+            Status::Warn | Status::Fail => warning!(line, false, accessible),
+        }
+        if let Some(fix) = &check.fix {
+            detail!(format!("    Fix: {fix}"), false, accessible);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn check_file_limit(opts: &Opts) -> Check {
+    const NAME: &str = "File limit";
+    match rlimit::Resource::NOFILE.get() {
+        Ok((soft, hard)) if soft < opts.batch_size as u64 => Check::warn(
+            NAME,
+            format!(
+                "soft limit {soft} (hard {hard}) is lower than the batch size {}",
+                opts.batch_size
+            ),
+            format!(
+                "run with '--ulimit {}' or raise it with 'ulimit -n {}'",
+                opts.batch_size, opts.batch_size
+            ),
+        ),
+        Ok((soft, hard)) => Check::ok(NAME, format!("soft limit {soft}, hard limit {hard}")),
+        Err(e) => Check::warn(
+            NAME,
+            format!("could not be read ({e})"),
+            "run 'ulimit -n' to check it manually",
+        ),
+    }
+}
+
+#[cfg(not(unix))]
+fn check_file_limit(_opts: &Opts) -> Check {
+    let limits = crate::system::SystemLimits::detect();
+    Check::ok(
+        "File limit",
+        format!(
+            "not enforced for sockets, {} concurrent sockets available",
+            limits.socket_ceiling()
+        ),
+    )
+}
+
+fn check_raw_sockets() -> Check {
+    const NAME: &str = "Raw sockets";
+    let availability = privileges::detect();
+    let (available, unavailable): (Vec<_>, Vec<_>) = availability
+        .iter()
+        .partition(|availability| availability.error.is_none());
+    let names = |features: &[&Availability]| {
+        features
+            .iter()
+            .map(|availability| availability.feature.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    match unavailable.first() {
+        None => Check::ok(NAME, format!("{} available", names(&available))),
+        Some(first) => Check::warn(
+            NAME,
+            format!(
+                "{} unavailable ({}), see 'rustscan setup-caps'",
+                names(&unavailable),
+                first
+                    .error
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default()
+            ),
+            privileges::how_to_enable(),
+        ),
+    }
+}
+
+fn check_nmap() -> Check {
+    const NAME: &str = "Nmap";
+    match Command::new("nmap").arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let version = stdout
+                .lines()
+                .find(|line| !line.trim().is_empty())
+                .unwrap_or("installed")
+                .trim()
+                .to_owned();
+            Check::ok(NAME, version)
+        }
+        _ => Check::fail(
+            NAME,
+            "not found in PATH, the default script will fail",
+            "install nmap (https://nmap.org/download) or scan with '--scripts none'",
+        ),
+    }
+}
+
+#[cfg(feature = "scripts")]
+fn check_scripts() -> Check {
+    const NAME: &str = "Scripts";
+    let config_path = match ScriptConfig::config_path() {
+        Ok(path) => path,
+        Err(e) => return Check::warn(NAME, e.to_string(), "set the HOME environment variable"),
+    };
+    if !config_path.exists() {
+        return Check::ok(
+            NAME,
+            format!(
+                "no {} found, only needed for '--scripts custom'",
+                config_path.display()
+            ),
+        );
+    }
+
+    let config = match fs::read_to_string(&config_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Ok(toml::from_str::<ScriptConfig>(&content)?))
+    {
+        Ok(config) => config,
+        Err(e) => {
+            return Check::fail(
+                NAME,
+                format!("{} is invalid: {e}", config_path.display()),
+                "fix the file, see fixtures/.rustscan_scripts.toml for an example",
+            )
+        }
+    };
+
+    let directory = config.scripts_dir().unwrap_or_default();
+    match find_scripts(directory.clone()) {
+        Ok(paths) => {
+            let found = paths.len();
+            let (parsed, issues) = parse_scripts(paths);
+            let parsed = parsed.len();
+            if parsed < found {
+                let first = issues
+                    .iter()
+                    .find(|issue| issue.fatal)
+                    .map(|issue| format!(", first: {issue}"))
+                    .unwrap_or_default();
+                Check::warn(
+                    NAME,
+                    format!(
+                        "{parsed} of {found} files in {} have valid script headers{first}",
+                        directory.display()
+                    ),
+                    "fix or move the skipped files, see 'rustscan scripts lint' for every problem",
+                )
+            } else {
+                Check::ok(
+                    NAME,
+                    format!("{parsed} scripts found in {}", directory.display()),
+                )
+            }
+        }
+        Err(e) => Check::fail(
+            NAME,
+            e.to_string(),
+            "create the directory or fix 'directory' in .rustscan_scripts.toml",
+        ),
+    }
+}
+
+fn check_config(opts: &Opts) -> Check {
+    const NAME: &str = "Config";
+    let config_path = resolve_config_path(opts.config_path.clone());
+    if !config_path.exists() {
+        return Check::ok(
+            NAME,
+            format!(
+                "no config file at {}, using defaults",
+                config_path.display()
+            ),
+        );
+    }
+    let issues = validate_file::<Config>(&config_path);
+    match issues.iter().find(|issue| issue.fatal) {
+        Some(issue) => Check::fail(
+            NAME,
+            issue.to_string(),
+            "fix the reported key, or run with '--no-config' to ignore the file",
+        ),
+        None if !issues.is_empty() => Check::warn(
+            NAME,
+            issues[0].to_string(),
+            "remove or rename the unknown keys, see 'rustscan config validate'",
+        ),
+        None => Check::ok(NAME, format!("{} is valid", config_path.display())),
+    }
+}
+
+fn check_resolver(opts: &Opts) -> Check {
+    const NAME: &str = "DNS resolver";
+    match get_resolver(&opts.resolver).lookup_ip("example.com.") {
+        Ok(lookup) if lookup.iter().next().is_some() => Check::ok(NAME, "resolved example.com"),
+        Ok(_) => Check::warn(
+            NAME,
+            "example.com resolved to no addresses",
+            "pick a different resolver with '--resolver 1.1.1.1'",
+        ),
+        Err(e) => Check::warn(
+            NAME,
+            format!("could not resolve example.com ({e})"),
+            "check your network, or pick a reachable resolver with '--resolver 1.1.1.1'",
+        ),
+    }
+}
+
+fn check_proxy(opts: &Opts) -> Check {
+    const NAME: &str = "HTTP proxy";
+    match HttpClient::from_opts(opts).proxy() {
+        Some(proxy) => Check::ok(NAME, format!("requests go through {proxy}")),
+        None if opts.no_proxy => Check::ok(NAME, "disabled with --no-proxy"),
+        None => Check::ok(NAME, "none configured, requests are sent directly"),
+    }
+}
+
+fn check_ipv6() -> Check {
+    const NAME: &str = "IPv6";
+    // Connecting a UDP socket sends nothing, it only asks the OS for a route.
+    let route = "[2001:4860:4860::8888]:53".parse::<SocketAddr>().unwrap();
+    match UdpSocket::bind("[::]:0").and_then(|socket| socket.connect(route)) {
+        Ok(()) => Check::ok(NAME, "a route to the IPv6 internet exists"),
+        Err(e) => Check::warn(
+            NAME,
+            format!("unavailable ({e})"),
+            "IPv6 targets can't be scanned until the host has an IPv6 address and route",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_config, check_file_limit, Status};
+    use crate::input::Opts;
+    use std::path::PathBuf;
+
+    #[test]
+    fn invalid_config_fails() {
+        let opts = Opts {
+            config_path: Some(PathBuf::from("fixtures/test_rustscan_scripts.toml")),
+            ..Default::default()
+        };
+        assert_eq!(check_config(&opts).status, Status::Fail);
+    }
+
+    #[test]
+    fn missing_config_is_ok() {
+        let opts = Opts {
+            config_path: Some(PathBuf::from("fixtures/does_not_exist.toml")),
+            ..Default::default()
+        };
+        assert_eq!(check_config(&opts).status, Status::Ok);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn huge_batch_size_warns_about_file_limit() {
+        let opts = Opts {
+            batch_size: usize::MAX,
+            ..Default::default()
+        };
+        let check = check_file_limit(&opts);
+        assert_eq!(check.status, Status::Warn);
+        assert!(check.fix.is_some());
+    }
+}
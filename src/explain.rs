@@ -0,0 +1,243 @@
+//! Explains why a single port shows up as open or not.
+//!
+//! `rustscan --explain host:port` probes one port `--tries` times, prints
+//! how every try ended (the connect result, the OS error and the round-trip
+//! time) and what that means: a refusal is a host answering that nothing
+//! listens, a timeout is a dropped probe, an unreachable error is an ICMP
+//! message or a missing route. It's meant for disputing false negatives,
+//! so the port is probed the way a scan would, with `--timeout` and
+//! `--udp`.
+use crate::address::{get_resolver, parse_address, IpPreference};
+use crate::generated::get_parsed_data;
+use crate::input::Opts;
+use crate::units;
+use crate::{detail, output, warning};
+use anyhow::{anyhow, Result};
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// What a single try says about the port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Open,
+    Closed,
+    Filtered,
+    Unreachable,
+    Failed,
+}
+
+impl Verdict {
+    /// Classifies how a try ended.
+    pub fn of(result: &io::Result<()>) -> Self {
+        match result {
+            Ok(()) => Self::Open,
+            Err(e) => match e.kind() {
+                io::ErrorKind::ConnectionRefused => Self::Closed,
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Self::Filtered,
+                io::ErrorKind::HostUnreachable | io::ErrorKind::NetworkUnreachable => {
+                    Self::Unreachable
+                }
+                _ => Self::Failed,
+            },
+        }
+    }
+
+    fn explanation(self, udp: bool, timeout: Duration) -> String {
+        match (self, udp) {
+            (Self::Open, false) => "open: the TCP handshake completed.".to_owned(),
+            (Self::Open, true) => "open: the port answered the UDP probe.".to_owned(),
+            (Self::Closed, false) => "closed: the host answered the SYN with a RST, so it's \
+                reachable but nothing listens on the port, or a firewall rejects it."
+                .to_owned(),
+            (Self::Closed, true) => "closed: an ICMP port unreachable came back, so the host \
+                is reachable but nothing listens on the port."
+                .to_owned(),
+            (Self::Filtered, false) => format!(
+                "filtered: nothing answered within {}. A firewall drops the probes, the \
+                host is down, or the round-trip time is longer than --timeout.",
+                units::duration(timeout)
+            ),
+            (Self::Filtered, true) => format!(
+                "open or filtered: nothing answered within {}. Many UDP services only \
+                answer probes in their own protocol, and firewalls drop the rest.",
+                units::duration(timeout)
+            ),
+            (Self::Unreachable, _) => "unreachable: an ICMP host or network unreachable came \
+                back, or there's no route to the host. Check the VPN or routing."
+                .to_owned(),
+            (Self::Failed, _) => "unknown: the probe failed on this machine before \
+                reaching the target, see the error above."
+                .to_owned(),
+        }
+    }
+}
+
+/// How a single try ended and how long it took.
+#[derive(Debug)]
+pub struct Attempt {
+    pub result: io::Result<()>,
+    pub rtt: Duration,
+}
+
+/// Probes `socket` once over TCP.
+pub fn probe_tcp(socket: SocketAddr, timeout: Duration) -> Attempt {
+    let start = Instant::now();
+    let result = TcpStream::connect_timeout(&socket, timeout).map(drop);
+    Attempt {
+        result,
+        rtt: start.elapsed(),
+    }
+}
+
+/// Probes `socket` once over UDP, with the payload a scan would send.
+pub fn probe_udp(socket: SocketAddr, timeout: Duration) -> Attempt {
+    let payload = get_parsed_data()
+        .iter()
+        .find(|(ports, _)| ports.contains(&socket.port()))
+        .map(|(_, payload)| payload.clone())
+        .unwrap_or_default();
+    let start = Instant::now();
+    let result = (|| {
+        let local: SocketAddr = match socket.ip() {
+            IpAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+            IpAddr::V6(_) => "[::]:0".parse().unwrap(),
+        };
+        let udp = UdpSocket::bind(local)?;
+        udp.connect(socket)?;
+        udp.set_read_timeout(Some(timeout))?;
+        udp.send(&payload)?;
+        udp.recv(&mut [0; 1024]).map(drop)
+    })();
+    Attempt {
+        result,
+        rtt: start.elapsed(),
+    }
+}
+
+fn parse_target(target: &str, opts: &Opts) -> Result<SocketAddr> {
+    let (host, port) = target
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("{target} should be host:port"))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port: u16 = port.parse().map_err(|_| anyhow!("{port} is not a port"))?;
+    let ip = parse_address(
+        host,
+        &get_resolver(&opts.resolver),
+        IpPreference::from_opts(opts),
+    )
+    .into_iter()
+    .next()
+    .ok_or_else(|| anyhow!("{host} could not be resolved"))?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Probes `target` and prints what every try ran into, returns whether the
+/// port is open.
+#[cfg(not(tarpaulin_include))]
+pub fn run(target: &str, opts: &Opts) -> bool {
+    let socket = match parse_target(target, opts) {
+        Ok(socket) => socket,
+        Err(e) => {
+            warning!(format!("{e}"), false, opts.accessible);
+            return false;
+        }
+    };
+    let timeout = Duration::from_millis(opts.timeout.into());
+    let protocol = if opts.udp { "UDP" } else { "TCP" };
+    output!(
+        format!(
+            "Probing {socket} over {protocol}, {} tries with a {} timeout",
+            opts.tries.max(1),
+            units::duration(timeout)
+        ),
+        false,
+        opts.accessible
+    );
+
+    let mut verdicts = Vec::new();
+    for nr_try in 1..=opts.tries.max(1) {
+        let attempt = if opts.udp {
+            probe_udp(socket, timeout)
+        } else {
+            probe_tcp(socket, timeout)
+        };
+        let outcome = match &attempt.result {
+            Ok(()) => "answered".to_owned(),
+            Err(e) => format!("{e} ({:?})", e.kind()),
+        };
+        detail!(
+            format!(
+                "Try {nr_try}: {outcome} after {}",
+                units::duration(attempt.rtt)
+            ),
+            false,
+            opts.accessible
+        );
+        let verdict = Verdict::of(&attempt.result);
+        verdicts.push(verdict);
+        if verdict == Verdict::Open {
+            break;
+        }
+    }
+
+    let last = *verdicts.last().unwrap_or(&Verdict::Failed);
+    output!(
+        format!("{socket} is {}", last.explanation(opts.udp, timeout)),
+        false,
+        opts.accessible
+    );
+    if verdicts.iter().any(|verdict| *verdict != last) {
+        warning!(
+            "The tries disagreed, which points at packet loss or rate limiting. Try a lower --batch-size or more --tries.",
+            false,
+            opts.accessible
+        );
+    }
+    last == Verdict::Open
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{probe_tcp, Verdict};
+    use std::io::{Error, ErrorKind};
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    #[test]
+    fn classifies_results() {
+        assert_eq!(Verdict::of(&Ok(())), Verdict::Open);
+        assert_eq!(
+            Verdict::of(&Err(Error::from(ErrorKind::ConnectionRefused))),
+            Verdict::Closed
+        );
+        assert_eq!(
+            Verdict::of(&Err(Error::from(ErrorKind::TimedOut))),
+            Verdict::Filtered
+        );
+        assert_eq!(
+            Verdict::of(&Err(Error::from(ErrorKind::HostUnreachable))),
+            Verdict::Unreachable
+        );
+        assert_eq!(
+            Verdict::of(&Err(Error::from(ErrorKind::PermissionDenied))),
+            Verdict::Failed
+        );
+    }
+
+    #[test]
+    fn probes_open_and_closed_ports() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap();
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let timeout = Duration::from_secs(1);
+        assert_eq!(Verdict::of(&probe_tcp(open, timeout).result), Verdict::Open);
+        assert_eq!(
+            Verdict::of(&probe_tcp(closed, timeout).result),
+            Verdict::Closed
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Analyst notes stored inside a JSON report.
+//!
+//! `rustscan annotate results.json --host 10.0.0.5 --note "approved exception"`
+//! adds a note to a host, or to one of its ports with `--port`. Notes stay
+//! in the report, survive `rustscan merge`, and are attached to the
+//! findings of their host and port by every output the report is sent to.
+use super::file::{self, FileOptions};
+use super::ScanReport;
+use crate::input::Opts;
+use crate::{output, warning};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// A note left on a host, or on one of its ports.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Note {
+    pub ip: IpAddr,
+    /// The port the note is about, or `None` for the whole host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    pub text: String,
+    /// Who left the note, going by the login name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    pub created: DateTime<Utc>,
+}
+
+impl Note {
+    /// Whether the note applies to `port` of `ip`.
+    pub fn applies_to(&self, ip: IpAddr, port: u16) -> bool {
+        self.ip == ip && self.port.is_none_or(|noted| noted == port)
+    }
+}
+
+impl ScanReport {
+    /// The text of the notes about `port` of `ip`.
+    pub fn notes_for(&self, ip: IpAddr, port: u16) -> Vec<&str> {
+        self.notes
+            .iter()
+            .filter(|note| note.applies_to(ip, port))
+            .map(|note| note.text.as_str())
+            .collect()
+    }
+
+    /// Adds a note, failing if the report has no results for the host and
+    /// port, which is most likely a typo.
+    pub fn annotate(&mut self, ip: IpAddr, port: Option<u16>, text: &str) -> Result<()> {
+        let host = self
+            .hosts
+            .iter()
+            .find(|host| host.ip == ip)
+            .ok_or_else(|| anyhow!("The report has no results for {ip}"))?;
+        if let Some(port) = port {
+            if !host.ports.contains(&port) {
+                return Err(anyhow!("Port {port} isn't open on {ip} in the report"));
+            }
+        }
+        self.notes.push(Note {
+            ip,
+            port,
+            text: text.to_owned(),
+            author: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .ok(),
+            created: Utc::now(),
+        });
+        Ok(())
+    }
+}
+
+/// Adds a note to the report in `path` and writes it back. Prints what it
+/// did and returns whether it succeeded.
+#[cfg(not(tarpaulin_include))]
+pub fn run(path: &Path, ip: IpAddr, port: Option<u16>, text: &str, opts: &Opts) -> bool {
+    let result = file::read(path).and_then(|content| {
+        let mut report: ScanReport = serde_json::from_slice(&content)
+            .map_err(|e| anyhow!("{} is not a RustScan JSON report: {e}", path.display()))?;
+        report.annotate(ip, port, text)?;
+        let json = serde_json::to_string_pretty(&report)?;
+        file::write(path, json.as_bytes(), &FileOptions::default())?;
+        Ok(report.notes.len())
+    });
+    match result {
+        Ok(count) => {
+            let target = port.map_or_else(|| ip.to_string(), |port| format!("{ip}:{port}"));
+            output!(
+                format!(
+                    "Added a note to {target} in {}, which now has {count} notes",
+                    path.display()
+                ),
+                false,
+                opts.accessible
+            );
+            true
+        }
+        Err(e) => {
+            warning!(format!("{e}"), false, opts.accessible);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::{HostReport, ScanReport};
+    use chrono::Utc;
+
+    #[test]
+    fn notes_apply_to_hosts_and_ports() {
+        let mut report = ScanReport::new(Utc::now(), false);
+        let ip = "10.0.0.5".parse().unwrap();
+        report.hosts.push(HostReport {
+            ip,
+            ports: vec![22, 443],
+            script_output: vec![],
+        });
+
+        report.annotate(ip, None, "approved exception").unwrap();
+        report.annotate(ip, Some(22), "bastion").unwrap();
+        assert!(report.annotate(ip, Some(80), "typo").is_err());
+        assert!(report
+            .annotate("10.0.0.6".parse().unwrap(), None, "typo")
+            .is_err());
+
+        assert_eq!(
+            report.notes_for(ip, 22),
+            vec!["approved exception", "bastion"]
+        );
+        assert_eq!(report.notes_for(ip, 443), vec!["approved exception"]);
+        let findings = report.findings();
+        assert_eq!(findings[0]["notes"][1], "bastion");
+        assert_eq!(findings[1]["notes"].as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,216 @@
+//! Exports open ports as DefectDojo findings.
+use super::file::{self, FileOptions};
+use super::{OutputSink, ScanReport};
+use crate::http::HttpClient;
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+/// Environment variable holding the DefectDojo API key.
+const API_KEY_VAR: &str = "DEFECTDOJO_API_KEY";
+
+/// The import path of DefectDojo's API.
+const IMPORT_PATH: &str = "/api/v2/import-scan/";
+
+/// The DefectDojo parser for [`findings`].
+const SCAN_TYPE: &str = "Generic Findings Import";
+
+const BOUNDARY: &str = "rustscan-defectdojo-boundary";
+
+/// Writes the findings to a file, or uploads them if the target is a URL.
+pub struct DefectDojoSink {
+    target: String,
+    client: HttpClient,
+    options: FileOptions,
+}
+
+impl DefectDojoSink {
+    pub fn new(target: String, client: HttpClient, options: FileOptions) -> Self {
+        Self {
+            target,
+            client,
+            options,
+        }
+    }
+}
+
+impl OutputSink for DefectDojoSink {
+    fn write(&mut self, report: &ScanReport) -> Result<()> {
+        let target = &self.target;
+        let findings = findings(report).to_string();
+        if !target.starts_with("http://") && !target.starts_with("https://") {
+            let path = self.options.path(target, report.started);
+            return file::write(&path, findings.as_bytes(), &self.options);
+        }
+
+        let api_key =
+            std::env::var(API_KEY_VAR).map_err(|_| anyhow!("{API_KEY_VAR} is not set"))?;
+        let (url, engagement) = import_url(target)?;
+        let authorization = format!("Token {api_key}");
+        self.client.post(
+            &url,
+            &format!("multipart/form-data; boundary={BOUNDARY}"),
+            &[("Authorization", authorization.as_str())],
+            &multipart_body(&engagement, &findings),
+        )?;
+        Ok(())
+    }
+}
+
+/// One informational finding per open port, in the Generic Findings Import
+/// format. Script output is attached to the findings of its host.
+pub fn findings(report: &ScanReport) -> Value {
+    let date = report.started.format("%Y-%m-%d").to_string();
+    let protocol = &report.protocol;
+    let tags: Vec<String> = report
+        .labels
+        .iter()
+        .map(|(key, value)| format!("{key}:{value}"))
+        .collect();
+    let findings: Vec<Value> = report
+        .hosts
+        .iter()
+        .flat_map(|host| {
+            let date = &date;
+            let tags = &tags;
+            host.ports.iter().map(move |port| {
+                let mut description = format!(
+                    "RustScan found {port}/{protocol} open on {} at {} (scan {}).",
+                    host.ip,
+                    report.started.to_rfc3339(),
+                    report.scan_id
+                );
+                for note in report.notes_for(host.ip, *port) {
+                    description.push_str("\n\nNote: ");
+                    description.push_str(note);
+                }
+                for output in &host.script_output {
+                    description.push_str("\n\n```\n");
+                    description.push_str(output.trim_end());
+                    description.push_str("\n```");
+                }
+                let mut tags = tags.clone();
+                if report.is_known(host.ip, *port) {
+                    tags.push("known".to_owned());
+                }
+                json!({
+                    "title": format!("Open port {port}/{protocol} on {}", host.ip),
+                    "description": description,
+                    "severity": "Info",
+                    "date": date,
+                    "active": true,
+                    "verified": false,
+                    "static_finding": false,
+                    "dynamic_finding": true,
+                    "vuln_id_from_tool": "open-port",
+                    "tags": tags,
+                    "unique_id_from_tool": format!("{}:{port}/{protocol}", host.ip),
+                    "endpoints": [{
+                        "host": host.ip.to_string(),
+                        "port": port,
+                        "protocol": protocol,
+                    }],
+                })
+            })
+        })
+        .collect();
+    json!({ "findings": findings })
+}
+
+/// Splits `https://dojo/?engagement=3` into the import URL and engagement.
+fn import_url(target: &str) -> Result<(String, String)> {
+    let (base, query) = target.split_once('?').unwrap_or((target, ""));
+    let engagement = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("engagement="))
+        .filter(|engagement| !engagement.is_empty())
+        .ok_or_else(|| anyhow!("{target} should name the engagement, as in ?engagement=3"))?;
+    let base = base
+        .trim_end_matches('/')
+        .trim_end_matches(IMPORT_PATH.trim_end_matches('/'));
+    Ok((format!("{base}{IMPORT_PATH}"), engagement.to_owned()))
+}
+
+fn multipart_body(engagement: &str, findings: &str) -> Vec<u8> {
+    let mut body = String::new();
+    for (name, value) in [
+        ("scan_type", SCAN_TYPE),
+        ("engagement", engagement),
+        ("active", "true"),
+        ("verified", "false"),
+    ] {
+        body.push_str(&format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+        ));
+    }
+    body.push_str(&format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"rustscan.json\"\r\n\
+         Content-Type: application/json\r\n\r\n{findings}\r\n--{BOUNDARY}--\r\n"
+    ));
+    body.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{findings, import_url, multipart_body};
+    use crate::export::{HostReport, ScanReport};
+    use chrono::{TimeZone, Utc};
+
+    fn report() -> ScanReport {
+        let mut report =
+            ScanReport::new(Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(), false);
+        report
+            .labels
+            .insert("ticket".to_owned(), "SEC-123".to_owned());
+        report.hosts.push(HostReport {
+            ip: "127.0.0.1".parse().unwrap(),
+            ports: vec![22, 80],
+            script_output: vec!["22/tcp open ssh\n".to_owned()],
+        });
+        report
+    }
+
+    #[test]
+    fn findings_per_open_port() {
+        let findings = findings(&report());
+        let findings = findings["findings"].as_array().unwrap();
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0]["title"], "Open port 22/tcp on 127.0.0.1");
+        assert_eq!(findings[0]["date"], "2024-05-01");
+        assert_eq!(findings[0]["severity"], "Info");
+        assert_eq!(findings[0]["tags"][0], "ticket:SEC-123");
+        assert_eq!(findings[1]["endpoints"][0]["port"], 80);
+        assert!(findings[1]["description"]
+            .as_str()
+            .unwrap()
+            .contains("```\n22/tcp open ssh\n```"));
+    }
+
+    #[test]
+    fn import_url_from_target() {
+        assert_eq!(
+            import_url("https://dojo.example.com/?engagement=3").unwrap(),
+            (
+                "https://dojo.example.com/api/v2/import-scan/".to_owned(),
+                "3".to_owned()
+            )
+        );
+        assert_eq!(
+            import_url("https://dojo.example.com/api/v2/import-scan/?x=1&engagement=7")
+                .unwrap()
+                .0,
+            "https://dojo.example.com/api/v2/import-scan/"
+        );
+        assert!(import_url("https://dojo.example.com/").is_err());
+    }
+
+    #[test]
+    fn multipart_body_holds_fields_and_file() {
+        let body = String::from_utf8(multipart_body("3", "{}")).unwrap();
+        assert!(body.contains("name=\"scan_type\"\r\n\r\nGeneric Findings Import\r\n"));
+        assert!(body.contains("name=\"engagement\"\r\n\r\n3\r\n"));
+        assert!(body.contains(
+            "filename=\"rustscan.json\"\r\nContent-Type: application/json\r\n\r\n{}\r\n"
+        ));
+        assert!(body.ends_with("--rustscan-defectdojo-boundary--\r\n"));
+    }
+}
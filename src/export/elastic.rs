@@ -0,0 +1,130 @@
+//! Bulk-indexes open ports into Elasticsearch or OpenSearch.
+use super::{OutputSink, ScanReport};
+use crate::http::HttpClient;
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+/// Environment variable holding an Elasticsearch API key, if needed.
+const API_KEY_VAR: &str = "ELASTIC_API_KEY";
+
+/// Indexes one document per open port into the index at the end of the URL.
+pub struct ElasticSink {
+    url: String,
+    client: HttpClient,
+}
+
+impl ElasticSink {
+    pub fn new(url: String, client: HttpClient) -> Self {
+        Self { url, client }
+    }
+}
+
+impl OutputSink for ElasticSink {
+    fn write(&mut self, report: &ScanReport) -> Result<()> {
+        let (bulk_url, index) = bulk_url(&self.url)?;
+        let authorization = std::env::var(API_KEY_VAR)
+            .ok()
+            .map(|key| format!("ApiKey {key}"));
+        let headers: Vec<(&str, &str)> = authorization
+            .iter()
+            .map(|authorization| ("Authorization", authorization.as_str()))
+            .collect();
+
+        let response = self.client.post(
+            &bulk_url,
+            "application/x-ndjson",
+            &headers,
+            bulk_body(&index, report).as_bytes(),
+        )?;
+        let response: Value = serde_json::from_str(&response)?;
+        if response["errors"] == true {
+            return Err(anyhow!("{index} rejected some documents: {response}"));
+        }
+        Ok(())
+    }
+}
+
+/// Splits `https://host:9200/index` into the bulk API URL and the index.
+fn bulk_url(url: &str) -> Result<(String, String)> {
+    let url = url.trim_end_matches('/');
+    let (base, index) = url
+        .rsplit_once('/')
+        .filter(|(base, index)| !index.is_empty() && !base.ends_with('/'))
+        .ok_or_else(|| anyhow!("{url} should end with the index, as in https://host:9200/index"))?;
+    Ok((format!("{base}/_bulk"), index.to_owned()))
+}
+
+/// The newline delimited bulk request indexing a document per open port.
+fn bulk_body(index: &str, report: &ScanReport) -> String {
+    let action = json!({ "index": { "_index": index } }).to_string();
+    let mut body = String::new();
+    for document in report.findings() {
+        body.push_str(&action);
+        body.push('\n');
+        body.push_str(&document.to_string());
+        body.push('\n');
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bulk_body, bulk_url, ElasticSink};
+    use crate::export::{serve_once, HostReport, OutputSink, ScanReport};
+    use crate::http::HttpClient;
+    use chrono::Utc;
+    use serde_json::Value;
+    use std::net::TcpListener;
+
+    fn report() -> ScanReport {
+        let mut report = ScanReport::new(Utc::now(), false);
+        report.hosts.push(HostReport {
+            ip: "127.0.0.1".parse().unwrap(),
+            ports: vec![22, 80],
+            script_output: vec![],
+        });
+        report
+    }
+
+    #[test]
+    fn bulk_url_from_index_url() {
+        assert_eq!(
+            bulk_url("https://localhost:9200/rustscan/").unwrap(),
+            (
+                "https://localhost:9200/_bulk".to_owned(),
+                "rustscan".to_owned()
+            )
+        );
+        assert!(bulk_url("https://localhost:9200").is_err());
+    }
+
+    #[test]
+    fn bulk_body_has_a_document_per_port() {
+        let report = report();
+        let body = bulk_body("rustscan", &report);
+        let lines: Vec<Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["index"]["_index"], "rustscan");
+        assert_eq!(lines[1]["port"], 22);
+        assert_eq!(lines[3]["port"], 80);
+        assert_eq!(lines[3]["scan_id"], report.scan_id.as_str());
+        assert_eq!(lines[3]["ip"], "127.0.0.1");
+    }
+
+    #[test]
+    fn posts_to_bulk_api() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/rustscan", listener.local_addr().unwrap());
+        let server = serve_once(listener, r#"{"errors": false, "items": []}"#);
+
+        ElasticSink::new(url, HttpClient::new(false))
+            .write(&report())
+            .unwrap();
+        let (request_line, _, body) = server.join().unwrap();
+        assert_eq!(request_line, "POST /_bulk HTTP/1.1");
+        assert_eq!(body.lines().count(), 4);
+    }
+}
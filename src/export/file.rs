@@ -0,0 +1,526 @@
+//! Writes file outputs, compressing and rotating them as their name asks.
+//!
+//! File targets can contain `strftime` patterns, expanded with the start
+//! of the scan: `results-%Y%m%d.json` starts a new file every day. A `.gz`
+//! or `.zst` extension compresses the file with gzip or zstd.
+//!
+//! Outputs that append to their file (`.jsonl`) can also be rotated by size
+//! with `--output-rotate-size`: once the file grows past it, it's renamed
+//! to `<name>.1` (shifting older ones up to `<name>.5`) and a new file is
+//! started. Both gzip and zstd allow appending compressed frames, so
+//! compressed files can be appended to as well.
+//!
+//! With `--encrypt-output age:<recipient>`, files are encrypted to an age
+//! X25519 recipient after compression and get an `.age` extension. They can
+//! only be read with the recipient's identity (`age -d -i key.txt`), so
+//! results left on shared scan servers don't give away the infrastructure
+//! they describe. Appended files can't be encrypted.
+//!
+//! Every file output is locked for the whole run, with an advisory lock on
+//! `<name>.lock`, so two runs writing the same file (for example a `.jsonl`
+//! fed by cron) don't corrupt it. `--force` writes it anyway.
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Utc};
+#[cfg(feature = "reports")]
+use flate2::read::MultiGzDecoder;
+#[cfg(feature = "reports")]
+use flate2::write::GzEncoder;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::fs::{self, File, OpenOptions, TryLockError};
+#[cfg(feature = "reports")]
+use std::io::Read;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Number of rotated files kept next to the current one.
+const ROTATED_FILES: usize = 5;
+
+/// Extension of files encrypted with age.
+const AGE_EXTENSION: &str = "age";
+
+/// Settings shared by every output writing a file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileOptions {
+    /// Size in bytes past which appended files are rotated.
+    pub rotate_size: Option<u64>,
+    /// Who files are encrypted to, if anyone.
+    pub encrypt: Option<Encryption>,
+    /// Template html outputs render, if any.
+    pub template: Option<PathBuf>,
+}
+
+impl FileOptions {
+    pub fn from_opts(opts: &crate::input::Opts) -> Self {
+        Self {
+            rotate_size: opts.output_rotate_size.map(|size| size.0),
+            encrypt: opts.encrypt_output.clone(),
+            template: opts.report_template.clone(),
+        }
+    }
+
+    /// The file written for `target` at `time`: with `strftime` patterns
+    /// expanded, and an `.age` extension if it's encrypted.
+    pub fn path(&self, target: &str, time: DateTime<Utc>) -> PathBuf {
+        self.encrypted(expand_path(target, time))
+    }
+
+    /// `path` with an `.age` extension if files are encrypted.
+    pub fn encrypted(&self, path: PathBuf) -> PathBuf {
+        if self.encrypt.is_none() || is_encrypted(&path) {
+            return path;
+        }
+        let mut name = path.into_os_string();
+        name.push(format!(".{AGE_EXTENSION}"));
+        PathBuf::from(name)
+    }
+}
+
+/// An age recipient files are encrypted to, given as `age:<recipient>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encryption {
+    /// An X25519 public key, as in `age1...`.
+    pub recipient: String,
+}
+
+/// The error of what RustScan was built without.
+#[cfg(not(feature = "reports"))]
+fn without_reports(what: &str) -> String {
+    format!("{what} needs RustScan built with the reports feature")
+}
+
+impl Encryption {
+    #[cfg(feature = "reports")]
+    pub fn encrypt(&self, content: &[u8]) -> Result<Vec<u8>> {
+        let recipient = age::x25519::Recipient::from_str(&self.recipient)
+            .map_err(|e| anyhow!("Invalid age recipient: {e}"))?;
+        age::encrypt(&recipient, content).map_err(|e| anyhow!("Could not encrypt: {e}"))
+    }
+
+    #[cfg(not(feature = "reports"))]
+    pub fn encrypt(&self, _content: &[u8]) -> Result<Vec<u8>> {
+        Err(anyhow!(without_reports("Encrypting files")))
+    }
+}
+
+impl FromStr for Encryption {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let recipient = input
+            .strip_prefix("age:")
+            .ok_or_else(|| format!("encryption {input:?} should be age:<recipient>"))?;
+        #[cfg(feature = "reports")]
+        age::x25519::Recipient::from_str(recipient)
+            .map_err(|e| format!("{recipient:?} is not an age X25519 recipient: {e}"))?;
+        Ok(Self {
+            recipient: recipient.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "age:{}", self.recipient)
+    }
+}
+
+impl<'de> Deserialize<'de> for Encryption {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Encryption {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// How a file is compressed, going by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    pub fn compress(self, content: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            Compression::None => content.to_vec(),
+            #[cfg(feature = "reports")]
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(content)?;
+                encoder.finish()?
+            }
+            #[cfg(feature = "reports")]
+            Compression::Zstd => zstd::encode_all(content, 0)?,
+            #[cfg(not(feature = "reports"))]
+            Compression::Gzip | Compression::Zstd => {
+                return Err(anyhow!(without_reports("Compressing files")))
+            }
+        })
+    }
+
+    pub fn decompress(self, content: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            Compression::None => content.to_vec(),
+            #[cfg(feature = "reports")]
+            Compression::Gzip => {
+                let mut decompressed = Vec::new();
+                MultiGzDecoder::new(content).read_to_end(&mut decompressed)?;
+                decompressed
+            }
+            #[cfg(feature = "reports")]
+            Compression::Zstd => zstd::decode_all(content)?,
+            #[cfg(not(feature = "reports"))]
+            Compression::Gzip | Compression::Zstd => {
+                return Err(anyhow!(without_reports("Decompressing files")))
+            }
+        })
+    }
+}
+
+/// The path `target` stands for at `time`, with `strftime` patterns
+/// expanded. Targets with invalid patterns are taken literally.
+pub fn expand_path(target: &str, time: DateTime<Utc>) -> PathBuf {
+    if !target.contains('%') || StrftimeItems::new(target).any(|item| item == Item::Error) {
+        return PathBuf::from(target);
+    }
+    PathBuf::from(time.format(target).to_string())
+}
+
+fn is_encrypted(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == AGE_EXTENSION)
+}
+
+/// `path` without its `.age` extension.
+fn decrypted(path: &Path) -> PathBuf {
+    if is_encrypted(path) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Whether the file at `path` collects a record per line, going by its
+/// extension once compression and encryption extensions are stripped.
+pub fn is_appended(path: &Path) -> bool {
+    let path = &decrypted(path);
+    let path = match Compression::of(path) {
+        Compression::None => path.to_path_buf(),
+        _ => path.with_extension(""),
+    };
+    path.extension()
+        .is_some_and(|extension| extension == "jsonl")
+}
+
+/// Writes `content` to `path`, compressed as its extension says and
+/// encrypted if `options` ask for it. Appended files get `content` added to
+/// their end, after a rotation if they grew past the size in `options`.
+pub fn write(path: &Path, content: &[u8], options: &FileOptions) -> Result<()> {
+    let mut compressed = Compression::of(&decrypted(path)).compress(content)?;
+    if let Some(encryption) = &options.encrypt {
+        if is_appended(path) {
+            return Err(anyhow!(
+                "{} can't be appended to once encrypted, write to a .json file instead",
+                path.display()
+            ));
+        }
+        compressed = encryption.encrypt(&compressed)?;
+    }
+    let result = if is_appended(path) {
+        if let Some(limit) = options.rotate_size {
+            if fs::metadata(path).is_ok_and(|metadata| metadata.len() >= limit) {
+                rotate(path)?;
+            }
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(&compressed))
+    } else {
+        fs::write(path, compressed)
+    };
+    result.map_err(|e| anyhow!("Could not write {}: {e}", path.display()))
+}
+
+/// The advisory lock of the output file `path`.
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Locks `path` against other runs writing it, until the returned file is
+/// dropped. Fails if another run holds the lock; a lock that can't be
+/// created at all, say in a directory that doesn't exist, is skipped and
+/// left for writing the output to report.
+pub fn lock(path: &Path) -> Result<Option<File>> {
+    let lock_path = lock_path(path);
+    let Ok(mut file) = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&lock_path)
+    else {
+        return Ok(None);
+    };
+    match file.try_lock() {
+        Ok(()) => {
+            // The holder's PID, for the error other runs get.
+            file.set_len(0)?;
+            write!(file, "{}", std::process::id())?;
+            Ok(Some(file))
+        }
+        Err(TryLockError::WouldBlock) => {
+            let holder = fs::read_to_string(&lock_path).unwrap_or_default();
+            Err(anyhow!(
+                "{} is being written by another RustScan run (PID {})",
+                path.display(),
+                holder.trim()
+            ))
+        }
+        Err(TryLockError::Error(_)) => Ok(None),
+    }
+}
+
+/// Reads the file at `path`, decompressing it as its extension says.
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    if is_encrypted(path) {
+        return Err(anyhow!(
+            "{} is encrypted, decrypt it with age -d first",
+            path.display()
+        ));
+    }
+    let content = fs::read(path).map_err(|e| anyhow!("Could not read {}: {e}", path.display()))?;
+    Compression::of(path)
+        .decompress(&content)
+        .map_err(|e| anyhow!("Could not decompress {}: {e}", path.display()))
+}
+
+/// The path of the `index`th rotated file of `path`.
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+/// Moves `path` to `<path>.1`, shifting the files rotated before it and
+/// dropping the oldest.
+fn rotate(path: &Path) -> Result<()> {
+    let _ = fs::remove_file(rotated(path, ROTATED_FILES));
+    for index in (1..ROTATED_FILES).rev() {
+        let from = rotated(path, index);
+        if from.exists() {
+            fs::rename(&from, rotated(path, index + 1))?;
+        }
+    }
+    fs::rename(path, rotated(path, 1))
+        .map_err(|e| anyhow!("Could not rotate {}: {e}", path.display()))
+}
+
+/// A size such as `500K`, `100M` or `1G`, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileSize(pub u64);
+
+impl FromStr for FileSize {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let (number, unit) = input
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or((input, ""), |index| input.split_at(index));
+        let multiplier: u64 = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+            "" => 1,
+            "K" => 1 << 10,
+            "M" => 1 << 20,
+            "G" => 1 << 30,
+            _ => return Err(format!("unknown size unit in {input:?}, use K, M or G")),
+        };
+        number
+            .parse::<u64>()
+            .ok()
+            .and_then(|number| number.checked_mul(multiplier))
+            .filter(|size| *size > 0)
+            .map(FileSize)
+            .ok_or_else(|| format!("{input:?} is not a size, as in 100M"))
+    }
+}
+
+impl fmt::Display for FileSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            size if size % (1 << 30) == 0 => write!(f, "{}G", size >> 30),
+            size if size % (1 << 20) == 0 => write!(f, "{}M", size >> 20),
+            size if size % (1 << 10) == 0 => write!(f, "{}K", size >> 10),
+            size => write!(f, "{size}"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FileSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for FileSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_path, is_appended, lock, Compression, FileSize};
+    #[cfg(feature = "reports")]
+    use super::{read, write, Encryption, FileOptions};
+    use chrono::{TimeZone, Utc};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn expands_time_patterns() {
+        let time = Utc.with_ymd_and_hms(2024, 3, 9, 14, 0, 0).unwrap();
+        assert_eq!(
+            expand_path("results-%Y%m%d.json.zst", time),
+            PathBuf::from("results-20240309.json.zst")
+        );
+        assert_eq!(
+            expand_path("results.json", time),
+            PathBuf::from("results.json")
+        );
+        assert_eq!(expand_path("100%.json", time), PathBuf::from("100%.json"));
+    }
+
+    #[test]
+    fn compression_by_extension() {
+        assert_eq!(Compression::of(Path::new("a.json.gz")), Compression::Gzip);
+        assert_eq!(Compression::of(Path::new("a.json.zst")), Compression::Zstd);
+        assert_eq!(Compression::of(Path::new("a.json")), Compression::None);
+        assert!(is_appended(Path::new("a.jsonl.zst")));
+        assert!(is_appended(Path::new("a.jsonl")));
+        assert!(!is_appended(Path::new("a.json.gz")));
+
+        #[cfg(feature = "reports")]
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let compressed = compression.compress(b"open ports").unwrap();
+            assert_ne!(compressed, b"open ports");
+            assert_eq!(compression.decompress(&compressed).unwrap(), b"open ports");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "reports")]
+    fn appends_and_rotates() {
+        let dir = std::env::temp_dir().join(format!("rustscan-rotate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for extension in ["jsonl.gz", "jsonl.zst"] {
+            let path = dir.join(format!("results.{extension}"));
+            let options = FileOptions {
+                rotate_size: Some(1 << 20),
+                ..FileOptions::default()
+            };
+            write(&path, b"first\n", &options).unwrap();
+            write(&path, b"second\n", &options).unwrap();
+            assert_eq!(read(&path).unwrap(), b"first\nsecond\n");
+
+            let options = FileOptions {
+                rotate_size: Some(1),
+                ..FileOptions::default()
+            };
+            write(&path, b"third\n", &options).unwrap();
+            assert_eq!(read(&path).unwrap(), b"third\n");
+            assert_eq!(
+                Compression::of(&path)
+                    .decompress(&fs::read(dir.join(format!("results.{extension}.1"))).unwrap())
+                    .unwrap(),
+                b"first\nsecond\n"
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "reports")]
+    fn encrypts_to_age_recipient() {
+        let identity = age::x25519::Identity::generate();
+        let encryption: Encryption = format!("age:{}", identity.to_public()).parse().unwrap();
+        let options = FileOptions {
+            encrypt: Some(encryption),
+            ..FileOptions::default()
+        };
+        let path = options.path(
+            &std::env::temp_dir()
+                .join(format!("rustscan-encrypted-{}.json.gz", std::process::id()))
+                .to_string_lossy(),
+            Utc::now(),
+        );
+        assert!(path.to_string_lossy().ends_with(".json.gz.age"));
+
+        write(&path, b"open ports", &options).unwrap();
+        let encrypted = fs::read(&path).unwrap();
+        assert!(read(&path).is_err());
+        fs::remove_file(&path).unwrap();
+        let compressed = age::decrypt(&identity, &encrypted).unwrap();
+        assert_eq!(
+            Compression::Gzip.decompress(&compressed).unwrap(),
+            b"open ports"
+        );
+
+        assert!(write(Path::new("results.jsonl.age"), b"{}", &options).is_err());
+        assert!("age:nope".parse::<Encryption>().is_err());
+        assert!("gpg:key".parse::<Encryption>().is_err());
+    }
+
+    #[test]
+    fn locks_outputs_once() {
+        let dir = std::env::temp_dir().join(format!("rustscan-lock-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("results.jsonl");
+
+        let held = lock(&path).unwrap();
+        assert!(held.is_some());
+        let error = lock(&path).unwrap_err().to_string();
+        assert!(error.contains(&format!("PID {}", std::process::id())));
+        drop(held);
+        assert!(lock(&path).unwrap().is_some());
+
+        assert!(lock(&dir.join("missing").join("results.json"))
+            .unwrap()
+            .is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!("512".parse(), Ok(FileSize(512)));
+        assert_eq!("100M".parse(), Ok(FileSize(100 << 20)));
+        assert_eq!("1gb".parse(), Ok(FileSize(1 << 30)));
+        assert!("0".parse::<FileSize>().is_err());
+        assert!("10T".parse::<FileSize>().is_err());
+        assert!("M".parse::<FileSize>().is_err());
+    }
+}
@@ -0,0 +1,426 @@
+//! Writes the report as a self-contained HTML page with charts.
+//!
+//! The page draws its charts as inline SVG, with no scripts, stylesheets or
+//! fonts to fetch, so it opens offline and can be mailed around as is:
+//!
+//! - a histogram of the ports open on the most hosts,
+//! - a timeline of the open ports found over the course of the scan,
+//! - a heatmap of the most common ports per subnet (`/24` for IPv4, `/64`
+//!   for IPv6), darker where more hosts have the port open,
+//!
+//! followed by the open ports of every host.
+//!
+//! ## Templates
+//!
+//! With the `reports` feature and `--report-template custom.html.tera`, the
+//! page is rendered from a
+//! [Tera](https://keats.github.io/tera/docs/) template instead, so reports
+//! can carry their own branding and layout. Templates whose name ends with
+//! `.html`, `.htm` or `.xml` (optionally followed by `.tera`) escape what
+//! they print. The template gets:
+//!
+//! - every field of the JSON report: `scan_id`, `started`, `finished`,
+//!   `protocol`, `version`, `args`, `labels`, `target_count`, `hosts` (each
+//!   with `ip`, `ports` and `script_output`), and `known`, `devices`,
+//!   `distances`, `rollups`, `notes`, `discovered` when they're set,
+//! - `findings`, a record per open port as sent to Elasticsearch,
+//! - `open_ports`, the number of open ports,
+//! - `charts.histogram`, `charts.timeline` and `charts.heatmap`, the charts
+//!   of the built-in page as SVG, to print with `| safe`.
+//!
+//! ```text
+//! <h1>ACME Security: {{ target_count }} hosts scanned</h1>
+//! {{ charts.histogram | safe }}
+//! {% for host in hosts %}<p>{{ host.ip }}: {{ host.ports | join(sep=", ") }}</p>{% endfor %}
+//! ```
+use super::file::{self, FileOptions};
+use super::view::subnet;
+use super::{OutputSink, ScanReport};
+use crate::units::Separators;
+#[cfg(feature = "reports")]
+use anyhow::Context as _;
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+#[cfg(feature = "reports")]
+use std::fs;
+#[cfg(feature = "reports")]
+use std::path::Path;
+#[cfg(feature = "reports")]
+use tera::{Context, Tera};
+
+/// Ports shown in the histogram and heatmap.
+const CHART_PORTS: usize = 20;
+/// Subnets shown in the heatmap.
+const HEATMAP_SUBNETS: usize = 30;
+const WIDTH: usize = 720;
+
+pub struct HtmlSink {
+    path: String,
+    options: FileOptions,
+}
+
+impl HtmlSink {
+    pub fn new(path: String, options: FileOptions) -> Self {
+        Self { path, options }
+    }
+}
+
+impl OutputSink for HtmlSink {
+    fn write(&mut self, report: &ScanReport) -> Result<()> {
+        let path = self.options.path(&self.path, report.started);
+        let page = match &self.options.template {
+            #[cfg(feature = "reports")]
+            Some(template) => ReportTemplate::load(template)?.render(report)?,
+            #[cfg(not(feature = "reports"))]
+            Some(_) => {
+                return Err(anyhow!(
+                    "report templates need RustScan built with the reports feature"
+                ))
+            }
+            None => render(report),
+        };
+        file::write(&path, page.as_bytes(), &self.options)
+    }
+}
+
+/// A report template given with `--report-template`.
+#[cfg(feature = "reports")]
+pub struct ReportTemplate {
+    tera: Tera,
+    name: String,
+}
+
+#[cfg(feature = "reports")]
+impl ReportTemplate {
+    /// Reads and compiles the template at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("could not read template {}", path.display()))?;
+        let name = path.file_name().map_or_else(
+            || "report".to_owned(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        let mut tera = Tera::default();
+        tera.autoescape_on(vec![
+            ".html",
+            ".htm",
+            ".xml",
+            ".html.tera",
+            ".htm.tera",
+            ".xml.tera",
+        ]);
+        tera.add_raw_template(&name, &source)
+            .map_err(|e| anyhow!("template {}: {}", path.display(), error_chain(&e)))?;
+        Ok(Self { tera, name })
+    }
+
+    pub fn render(&self, report: &ScanReport) -> Result<String> {
+        let mut context = Context::from_serialize(report)?;
+        let counts = port_counts(report);
+        let mut charts = BTreeMap::new();
+        charts.insert("histogram", histogram(&counts));
+        charts.insert("timeline", timeline(report));
+        charts.insert("heatmap", heatmap(report, &counts));
+        context.insert("charts", &charts);
+        context.insert("findings", &report.findings());
+        context.insert(
+            "open_ports",
+            &report
+                .hosts
+                .iter()
+                .map(|host| host.ports.len())
+                .sum::<usize>(),
+        );
+        self.tera
+            .render(&self.name, &context)
+            .map_err(|e| anyhow!("template {}: {}", self.name, error_chain(&e)))
+    }
+}
+
+/// Tera puts what went wrong in the sources of its errors.
+#[cfg(feature = "reports")]
+fn error_chain(error: &tera::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        let _ = write!(message, ": {cause}");
+        source = cause.source();
+    }
+    message
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// How many hosts have each port open, most common first.
+fn port_counts(report: &ScanReport) -> Vec<(u16, usize)> {
+    let mut counts: BTreeMap<u16, usize> = BTreeMap::new();
+    for host in &report.hosts {
+        for port in &host.ports {
+            *counts.entry(*port).or_insert(0) += 1;
+        }
+    }
+    let mut counts: Vec<(u16, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+}
+
+fn histogram(counts: &[(u16, usize)]) -> String {
+    let counts = &counts[..counts.len().min(CHART_PORTS)];
+    let most = counts.first().map_or(1, |(_, hosts)| *hosts).max(1);
+    let row = 22;
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{}" role="img" aria-label="Hosts per open port">"#,
+        counts.len() * row + 10
+    );
+    for (index, (port, hosts)) in counts.iter().enumerate() {
+        let y = index * row + 5;
+        let length = (WIDTH - 160) * hosts / most;
+        let _ = write!(
+            svg,
+            r##"<text x="50" y="{}" text-anchor="end">{port}</text><rect x="60" y="{y}" width="{}" height="{}" fill="#4a7bd0"/><text x="{}" y="{}">{hosts}</text>"##,
+            y + 15,
+            length.max(1),
+            row - 6,
+            length + 66,
+            y + 15,
+        );
+    }
+    svg + "</svg>"
+}
+
+fn timeline(report: &ScanReport) -> String {
+    let height = 200;
+    let mut times: Vec<i64> = report
+        .discovered
+        .values()
+        .map(|time| (*time - report.started).num_milliseconds().max(0))
+        .collect();
+    times.sort_unstable();
+    let duration = (report.finished - report.started)
+        .num_milliseconds()
+        .max(times.last().copied().unwrap_or(0))
+        .max(1);
+    let total = times.len().max(1);
+    let x = |millis: i64| 50 + (WIDTH - 70) as i64 * millis / duration;
+    let y = |found: usize| 10 + (height - 40) * (total - found) / total;
+    let mut points = format!("{},{}", x(0), y(0));
+    for (index, millis) in times.iter().enumerate() {
+        let _ = write!(
+            points,
+            " {},{} {},{}",
+            x(*millis),
+            y(index),
+            x(*millis),
+            y(index + 1)
+        );
+    }
+    let _ = write!(points, " {},{}", x(duration), y(times.len()));
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{height}" role="img" aria-label="Open ports found over time"><polyline points="{points}" fill="none" stroke="#4a7bd0" stroke-width="2"/><text x="45" y="{}" text-anchor="end">{}</text><text x="45" y="{}" text-anchor="end">0</text><text x="50" y="{}">0s</text><text x="{}" y="{}" text-anchor="end">{}</text></svg>"##,
+        y(times.len()) + 5,
+        times.len(),
+        y(0),
+        height - 10,
+        WIDTH - 20,
+        height - 10,
+        Separators::current().duration(std::time::Duration::from_millis(duration.unsigned_abs())),
+    )
+}
+
+fn heatmap(report: &ScanReport, counts: &[(u16, usize)]) -> String {
+    let ports: Vec<u16> = counts
+        .iter()
+        .take(CHART_PORTS)
+        .map(|(port, _)| *port)
+        .collect();
+    let mut subnets: BTreeMap<String, BTreeMap<u16, usize>> = BTreeMap::new();
+    for host in &report.hosts {
+        let cells = subnets.entry(subnet(host.ip)).or_default();
+        for port in &host.ports {
+            *cells.entry(*port).or_insert(0) += 1;
+        }
+    }
+    let mut subnets: Vec<(String, BTreeMap<u16, usize>)> = subnets.into_iter().collect();
+    subnets.sort_by_key(|(_, cells)| std::cmp::Reverse(cells.values().sum::<usize>()));
+    subnets.truncate(HEATMAP_SUBNETS);
+    let most = subnets
+        .iter()
+        .flat_map(|(_, cells)| cells.values())
+        .max()
+        .copied()
+        .unwrap_or(1);
+
+    let (label, cell) = (260, 26);
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" role="img" aria-label="Open ports per subnet">"#,
+        label + ports.len() * cell + 10,
+        (subnets.len() + 1) * cell + 10
+    );
+    for (column, port) in ports.iter().enumerate() {
+        let _ = write!(
+            svg,
+            r#"<text x="{}" y="18" text-anchor="middle" font-size="10">{port}</text>"#,
+            label + column * cell + cell / 2
+        );
+    }
+    for (row, (name, cells)) in subnets.iter().enumerate() {
+        let y = (row + 1) * cell;
+        let _ = write!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="end">{}</text>"#,
+            label - 6,
+            y + cell / 2 + 4,
+            escape(name)
+        );
+        for (column, port) in ports.iter().enumerate() {
+            let hosts = cells.get(port).copied().unwrap_or(0);
+            let shade = 0.08 + 0.92 * hosts as f64 / most as f64;
+            let _ = write!(
+                svg,
+                r##"<rect x="{}" y="{y}" width="{}" height="{}" fill="#4a7bd0" fill-opacity="{}"><title>{} port {port}: {hosts} hosts</title></rect>"##,
+                label + column * cell,
+                cell - 2,
+                cell - 2,
+                if hosts == 0 { 0.03 } else { shade },
+                escape(name),
+            );
+        }
+    }
+    svg + "</svg>"
+}
+
+/// The whole page.
+pub fn render(report: &ScanReport) -> String {
+    let counts = port_counts(report);
+    let open: usize = report.hosts.iter().map(|host| host.ports.len()).sum();
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\"><head><meta charset=\"utf-8\"><title>RustScan report {id}</title>\
+         <style>body{{font-family:sans-serif;margin:2em;color:#222}}svg text{{font-size:12px}}\
+         table{{border-collapse:collapse}}td,th{{border:1px solid #ccc;padding:4px 8px;text-align:left}}</style></head><body>\
+         <h1>RustScan report</h1><p>Scan {id}, {protocol}, from {started} to {finished}: {open} open ports on {hosts} of {targets} hosts.</p>",
+        id = escape(&report.scan_id),
+        protocol = escape(&report.protocol),
+        started = report.started.to_rfc3339(),
+        finished = report.finished.to_rfc3339(),
+        hosts = report.hosts.len(),
+        targets = report.target_count,
+    );
+    if !counts.is_empty() {
+        page.push_str("<h2>Hosts per open port</h2>");
+        page.push_str(&histogram(&counts));
+    }
+    if !report.discovered.is_empty() {
+        page.push_str("<h2>Open ports found over time</h2>");
+        page.push_str(&timeline(report));
+    }
+    if !counts.is_empty() {
+        page.push_str("<h2>Open ports per subnet</h2>");
+        page.push_str(&heatmap(report, &counts));
+    }
+    page.push_str("<h2>Hosts</h2><table><tr><th>Host</th><th>Open ports</th></tr>");
+    for host in &report.hosts {
+        let ports: Vec<String> = host.ports.iter().map(ToString::to_string).collect();
+        let _ = write!(
+            page,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            host.ip,
+            ports.join(", ")
+        );
+    }
+    page + "</table></body></html>\n"
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "reports")]
+    use super::ReportTemplate;
+    use super::{render, HtmlSink};
+    use crate::export::file::FileOptions;
+    use crate::export::{HostReport, OutputSink, ScanReport};
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn pages_draw_charts_without_fetching_anything() {
+        let mut report = ScanReport::new(Utc::now(), false);
+        report.finished = report.started + Duration::seconds(10);
+        for (ip, ports) in [("10.0.0.1", vec![22, 80]), ("10.0.1.7", vec![22])] {
+            report.hosts.push(HostReport {
+                ip: ip.parse().unwrap(),
+                ports: ports.clone(),
+                script_output: vec![],
+            });
+            for port in ports {
+                report.discovered.insert(
+                    std::net::SocketAddr::new(ip.parse().unwrap(), port),
+                    report.started + Duration::seconds(2),
+                );
+            }
+        }
+        let page = render(&report);
+        assert_eq!(page.matches("<svg").count(), 3);
+        assert!(page.contains("10.0.1.0/24"));
+        assert!(page.contains("<td>10.0.0.1</td><td>22, 80</td>"));
+        assert!(!page.contains("<script") && !page.contains("src="));
+
+        let path = std::env::temp_dir().join(format!("rustscan-{}.html", report.scan_id));
+        HtmlSink::new(path.display().to_string(), FileOptions::default())
+            .write(&report)
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), page);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "reports")]
+    fn templates_render_the_report() {
+        let dir = std::env::temp_dir().join(format!("rustscan-template-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let template = dir.join("custom.html.tera");
+        std::fs::write(
+            &template,
+            "<h1>{{ labels.client }}: {{ open_ports }} open</h1>{{ charts.histogram | safe }}\
+             {% for host in hosts %}<p>{{ host.ip }} {{ host.ports | join(sep=\",\") }}</p>{% endfor %}\
+             {% for finding in findings %}{{ finding.port }};{% endfor %}",
+        )
+        .unwrap();
+        let mut report = ScanReport::new(Utc::now(), false);
+        report
+            .labels
+            .insert("client".to_owned(), "<ACME>".to_owned());
+        report.hosts.push(HostReport {
+            ip: "10.0.0.1".parse().unwrap(),
+            ports: vec![22, 443],
+            script_output: vec![],
+        });
+
+        let page = ReportTemplate::load(&template)
+            .unwrap()
+            .render(&report)
+            .unwrap();
+        assert!(page.starts_with("<h1>&lt;ACME&gt;: 2 open</h1><svg"));
+        assert!(page.ends_with("<p>10.0.0.1 22,443</p>22;443;"));
+
+        let output = dir.join("report.html");
+        let options = FileOptions {
+            template: Some(template.clone()),
+            ..FileOptions::default()
+        };
+        HtmlSink::new(output.display().to_string(), options)
+            .write(&report)
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), page);
+
+        std::fs::write(&template, "{{ hosts | no_such_filter }}").unwrap();
+        let error = ReportTemplate::load(&template)
+            .and_then(|template| template.render(&report))
+            .unwrap_err();
+        assert!(error.to_string().contains("no_such_filter"), "{}", error);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+//! Writes the report as a JSON file.
+use super::file::{self, FileOptions};
+use super::{OutputSink, ScanReport};
+use anyhow::Result;
+
+/// Writes the whole report, pretty printed, to a file, or appends it as a
+/// line to `.jsonl` files.
+pub struct JsonSink {
+    path: String,
+    options: FileOptions,
+}
+
+impl JsonSink {
+    pub fn new(path: String, options: FileOptions) -> Self {
+        Self { path, options }
+    }
+}
+
+impl OutputSink for JsonSink {
+    fn write(&mut self, report: &ScanReport) -> Result<()> {
+        let path = self.options.path(&self.path, report.started);
+        let json = if file::is_appended(&path) {
+            serde_json::to_string(report)? + "\n"
+        } else {
+            serde_json::to_string_pretty(report)?
+        };
+        file::write(&path, json.as_bytes(), &self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonSink;
+    #[cfg(feature = "reports")]
+    use crate::export::file;
+    use crate::export::file::FileOptions;
+    use crate::export::{HostReport, OutputSink, ScanReport};
+    use chrono::Utc;
+    use std::fs;
+
+    #[test]
+    fn report_round_trips() {
+        let mut report = ScanReport::new(Utc::now(), false);
+        report.hosts.push(HostReport {
+            ip: "127.0.0.1".parse().unwrap(),
+            ports: vec![22],
+            script_output: vec!["ssh".to_owned()],
+        });
+        let path = std::env::temp_dir().join(format!("rustscan-{}.json", report.scan_id));
+
+        JsonSink::new(path.display().to_string(), FileOptions::default())
+            .write(&report)
+            .unwrap();
+        let read: ScanReport = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read, report);
+    }
+
+    #[test]
+    #[cfg(feature = "reports")]
+    fn appends_reports_to_jsonl() {
+        let first = ScanReport::new(Utc::now(), false);
+        let second = ScanReport::new(Utc::now(), false);
+        let path = std::env::temp_dir().join(format!("rustscan-{}.jsonl.gz", first.scan_id));
+
+        let mut sink = JsonSink::new(path.display().to_string(), FileOptions::default());
+        sink.write(&first).unwrap();
+        sink.write(&second).unwrap();
+        let content = String::from_utf8(file::read(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        let reports: Vec<ScanReport> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(reports, vec![first, second]);
+    }
+}
@@ -0,0 +1,125 @@
+//! Publishes open ports to a Kafka topic through the Kafka REST proxy.
+//!
+//! Kafka's own binary protocol isn't spoken, so the brokers need a REST
+//! proxy (the v2 API of Confluent's, or a compatible one) in front of them;
+//! `--kafka-proxy` or `--output kafka=` point at the proxy, not a broker.
+use super::{OutputSink, ScanReport};
+use crate::http::HttpClient;
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+/// Content type of JSON records for the REST proxy's v2 API.
+const CONTENT_TYPE: &str = "application/vnd.kafka.json.v2+json";
+
+/// Publishes a message per open port, keyed by `ip:port`.
+pub struct KafkaSink {
+    url: String,
+    client: HttpClient,
+}
+
+impl KafkaSink {
+    pub fn new(url: String, client: HttpClient) -> Self {
+        Self { url, client }
+    }
+}
+
+impl OutputSink for KafkaSink {
+    fn write(&mut self, report: &ScanReport) -> Result<()> {
+        let records = records(report);
+        if records["records"].as_array().is_some_and(Vec::is_empty) {
+            return Ok(());
+        }
+        let url = topic_url(&self.url)?;
+        let response = self
+            .client
+            .post(&url, CONTENT_TYPE, &[], records.to_string().as_bytes())?;
+        let response: Value = serde_json::from_str(&response)?;
+        let failed = response["offsets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|offset| !offset["error_code"].is_null())
+            .count();
+        if failed > 0 {
+            return Err(anyhow!("{failed} messages were rejected: {response}"));
+        }
+        Ok(())
+    }
+}
+
+/// Turns `http://proxy:8082/topic` into the REST proxy URL of the topic.
+fn topic_url(url: &str) -> Result<String> {
+    let url = url.trim_end_matches('/');
+    let (base, topic) = url
+        .rsplit_once('/')
+        .filter(|(base, topic)| !topic.is_empty() && !base.ends_with('/'))
+        .ok_or_else(|| anyhow!("{url} should end with the topic, as in http://proxy:8082/topic"))?;
+    let base = base.trim_end_matches("/topics");
+    Ok(format!("{base}/topics/{topic}"))
+}
+
+fn records(report: &ScanReport) -> Value {
+    let records: Vec<Value> = report
+        .findings()
+        .into_iter()
+        .map(|finding| {
+            json!({
+                "key": format!("{}:{}", finding["ip"].as_str().unwrap_or_default(), finding["port"]),
+                "value": finding,
+            })
+        })
+        .collect();
+    json!({ "records": records })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{topic_url, KafkaSink};
+    use crate::export::{serve_once, HostReport, OutputSink, ScanReport};
+    use crate::http::HttpClient;
+    use chrono::Utc;
+    use serde_json::Value;
+    use std::net::TcpListener;
+
+    #[test]
+    fn topic_url_from_target() {
+        assert_eq!(
+            topic_url("http://proxy:8082/scans").unwrap(),
+            "http://proxy:8082/topics/scans"
+        );
+        assert_eq!(
+            topic_url("http://proxy:8082/topics/scans/").unwrap(),
+            "http://proxy:8082/topics/scans"
+        );
+        assert!(topic_url("http://proxy:8082").is_err());
+    }
+
+    #[test]
+    fn publishes_a_record_per_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/scans", listener.local_addr().unwrap());
+        let server = serve_once(
+            listener,
+            r#"{"offsets": [{"partition": 0, "offset": 1, "error_code": null}]}"#,
+        );
+
+        let mut report = ScanReport::new(Utc::now(), false);
+        report.hosts.push(HostReport {
+            ip: "127.0.0.1".parse().unwrap(),
+            ports: vec![22, 80],
+            script_output: vec![],
+        });
+        KafkaSink::new(url, HttpClient::new(false))
+            .write(&report)
+            .unwrap();
+
+        let (request_line, headers, body) = server.join().unwrap();
+        assert_eq!(request_line, "POST /topics/scans HTTP/1.1");
+        assert!(headers
+            .iter()
+            .any(|header| header.ends_with("application/vnd.kafka.json.v2+json")));
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["records"][1]["key"], "127.0.0.1:80");
+        assert_eq!(body["records"][1]["value"]["port"], 80);
+    }
+}
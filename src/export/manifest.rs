@@ -0,0 +1,110 @@
+//! Describes a scan run so its results can be correlated with other runs.
+use super::file::{self, FileOptions};
+use super::ScanReport;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The metadata of a scan, everything in the report but the results.
+#[derive(Debug, Serialize)]
+pub struct Manifest<'a> {
+    pub scan_id: &'a str,
+    pub version: &'a str,
+    pub args: &'a [String],
+    pub target_count: usize,
+    pub config_hash: Option<&'a str>,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    pub labels: &'a BTreeMap<String, String>,
+    /// Number of hosts with open ports.
+    pub hosts_up: usize,
+    pub open_ports: usize,
+}
+
+impl ScanReport {
+    pub fn manifest(&self) -> Manifest<'_> {
+        Manifest {
+            scan_id: &self.scan_id,
+            version: &self.version,
+            args: &self.args,
+            target_count: self.target_count,
+            config_hash: self.config_hash.as_deref(),
+            started: self.started,
+            finished: self.finished,
+            labels: &self.labels,
+            hosts_up: self.hosts.len(),
+            open_ports: self.hosts.iter().map(|host| host.ports.len()).sum(),
+        }
+    }
+}
+
+/// Writes the manifest of `report` into `dir`, encrypted like the other
+/// files if `options` ask for it, returning its path.
+pub fn write(report: &ScanReport, dir: &Path, options: &FileOptions) -> Result<PathBuf> {
+    let path = options.encrypted(dir.join(format!("rustscan-{}.manifest.json", report.scan_id)));
+    let json = serde_json::to_string_pretty(&report.manifest())?;
+    file::write(&path, json.as_bytes(), options)?;
+    Ok(path)
+}
+
+/// SHA-256 of the file at `path` as hex, if it can be read.
+pub fn file_hash(path: &Path) -> Option<String> {
+    let content = fs::read(path).ok()?;
+    Some(
+        Sha256::digest(content)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{file_hash, write};
+    use crate::export::file::FileOptions;
+    use crate::export::{HostReport, ScanReport};
+    use chrono::Utc;
+    use serde_json::Value;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn manifest_describes_the_run() {
+        let mut report = ScanReport::new(Utc::now(), false);
+        report.args = vec![
+            "rustscan".to_owned(),
+            "-a".to_owned(),
+            "127.0.0.1".to_owned(),
+        ];
+        report.target_count = 1;
+        report.hosts.push(HostReport {
+            ip: "127.0.0.1".parse().unwrap(),
+            ports: vec![22, 80],
+            script_output: vec![],
+        });
+
+        let path = write(&report, &std::env::temp_dir(), &FileOptions::default()).unwrap();
+        let manifest: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(path.ends_with(format!("rustscan-{}.manifest.json", report.scan_id)));
+        assert_eq!(manifest["scan_id"], report.scan_id.as_str());
+        assert_eq!(manifest["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(manifest["args"][2], "127.0.0.1");
+        assert_eq!(manifest["open_ports"], 2);
+        assert!(manifest.get("hosts").is_none());
+    }
+
+    #[test]
+    fn hashes_files() {
+        assert_eq!(
+            file_hash(Path::new("fixtures/empty_hosts.txt")).unwrap(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert!(file_hash(Path::new("fixtures/does_not_exist")).is_none());
+    }
+}
@@ -0,0 +1,390 @@
+//! Merges the JSON reports of several runs into one.
+//!
+//! Scans sharded across machines each write their own
+//! `--output json=...` report. `rustscan merge` unions them: a port is open
+//! in the merged report if any run found it open, and every port records
+//! which runs saw it and how many of the runs that reached the host did, as
+//! a measure of confidence. With `--latest`, recency wins instead: a port is
+//! dropped when the most recent run that reported its host didn't find it.
+use super::file::{self, FileOptions};
+use super::{HostReport, ScanReport};
+use crate::input::Opts;
+use crate::{output, warning};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+/// A run that contributed to a merged report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub scan_id: String,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    #[serde(default)]
+    pub target_count: usize,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Which runs found a port open.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortProvenance {
+    pub ip: IpAddr,
+    pub port: u16,
+    /// IDs of the runs that found the port open.
+    pub seen_in: Vec<String>,
+    /// End of the most recent run that found the port open.
+    pub last_seen: DateTime<Utc>,
+    /// Number of runs that reported the host at all.
+    pub runs_with_host: usize,
+}
+
+impl PortProvenance {
+    /// Share of the runs that reached the host which found the port open.
+    pub fn confidence(&self) -> f64 {
+        #[allow(clippy::cast_precision_loss)]
+        let confidence = self.seen_in.len() as f64 / self.runs_with_host.max(1) as f64;
+        confidence.min(1.0)
+    }
+}
+
+impl ScanReport {
+    /// The runs this report covers: the ones it was merged from, or itself.
+    fn run_summaries(&self) -> Vec<RunSummary> {
+        if !self.runs.is_empty() {
+            return self.runs.clone();
+        }
+        vec![RunSummary {
+            scan_id: self.scan_id.clone(),
+            started: self.started,
+            finished: self.finished,
+            target_count: self.target_count,
+            labels: self.labels.clone(),
+        }]
+    }
+}
+
+#[derive(Default)]
+struct HostState {
+    runs_with_host: usize,
+    /// End of the most recent run that reported the host.
+    latest: Option<DateTime<Utc>>,
+    ports: BTreeMap<u16, (Vec<String>, DateTime<Utc>)>,
+    script_output: Vec<String>,
+}
+
+/// Merges `reports` into a new report with its own scan ID.
+pub fn merge(reports: &[ScanReport], latest: bool) -> Result<ScanReport> {
+    let first = reports.first().ok_or_else(|| anyhow!("Nothing to merge"))?;
+    if let Some(other) = reports.iter().find(|r| r.protocol != first.protocol) {
+        return Err(anyhow!(
+            "Cannot merge {} results of {} with {} results of {}",
+            first.protocol,
+            first.scan_id,
+            other.protocol,
+            other.scan_id
+        ));
+    }
+
+    let mut merged = ScanReport::new(first.started, first.protocol == "udp");
+    merged.labels = first.labels.clone();
+    let mut hosts: BTreeMap<IpAddr, HostState> = BTreeMap::new();
+    for report in reports {
+        merged.started = merged.started.min(report.started);
+        merged.finished = merged.finished.max(report.finished);
+        merged.target_count += report.target_count;
+        // Only labels every run agrees on describe the merged report.
+        merged
+            .labels
+            .retain(|key, value| report.labels.get(key) == Some(value));
+        merged.runs.extend(report.run_summaries());
+        for note in &report.notes {
+            if !merged.notes.contains(note) {
+                merged.notes.push(note.clone());
+            }
+        }
+        for socket in &report.known {
+            if !merged.known.contains(socket) {
+                merged.known.push(*socket);
+            }
+        }
+        // Later runs saw the newer MAC address.
+        merged.devices.extend(report.devices.clone());
+        merged.responses.extend(report.responses.clone());
+        merged.banners.extend(report.banners.clone());
+        merged.distances.extend(report.distances.clone());
+        for warning in &report.warnings {
+            if !merged.warnings.contains(warning) {
+                merged.warnings.push(warning.clone());
+            }
+        }
+        for finding in &report.nuclei {
+            if !merged.nuclei.contains(finding) {
+                merged.nuclei.push(finding.clone());
+            }
+        }
+        for hint in &report.vuln_hints {
+            if !merged.vuln_hints.contains(hint) {
+                merged.vuln_hints.push(hint.clone());
+            }
+        }
+        for attempt in &report.default_creds {
+            if !merged.default_creds.contains(attempt) {
+                merged.default_creds.push(attempt.clone());
+            }
+        }
+        for probe in &report.service_probes {
+            if !merged.service_probes.contains(probe) {
+                merged.service_probes.push(probe.clone());
+            }
+        }
+        for device in &report.ot_devices {
+            if !merged.ot_devices.contains(device) {
+                merged.ot_devices.push(device.clone());
+            }
+        }
+        for (socket, time) in &report.discovered {
+            let first = merged.discovered.entry(*socket).or_insert(*time);
+            *first = (*first).min(*time);
+        }
+        for ip in &report.wildcards {
+            if !merged.wildcards.contains(ip) {
+                merged.wildcards.push(*ip);
+            }
+        }
+        // Rollups are counted again from the merged hosts below.
+        for rollup in &report.rollups {
+            match merged
+                .rollups
+                .iter_mut()
+                .find(|other| other.group == rollup.group)
+            {
+                Some(other) => {
+                    other.targets = other.targets.max(rollup.targets);
+                    for ip in &rollup.addresses {
+                        if !other.addresses.contains(ip) {
+                            other.addresses.push(*ip);
+                        }
+                    }
+                }
+                None => merged.rollups.push(rollup.clone()),
+            }
+        }
+
+        let provenance: HashMap<(IpAddr, u16), &PortProvenance> = report
+            .provenance
+            .iter()
+            .map(|entry| ((entry.ip, entry.port), entry))
+            .collect();
+        for host in &report.hosts {
+            let state = hosts.entry(host.ip).or_default();
+            state.runs_with_host += report
+                .provenance
+                .iter()
+                .filter(|entry| entry.ip == host.ip)
+                .map(|entry| entry.runs_with_host)
+                .max()
+                .unwrap_or(1);
+            state.latest = state.latest.max(Some(report.finished));
+            for port in &host.ports {
+                let (seen_in, last_seen) = match provenance.get(&(host.ip, *port)) {
+                    Some(entry) => (entry.seen_in.clone(), entry.last_seen),
+                    None => (vec![report.scan_id.clone()], report.finished),
+                };
+                let (all_seen_in, all_last_seen) = state
+                    .ports
+                    .entry(*port)
+                    .or_insert_with(|| (Vec::new(), last_seen));
+                all_seen_in.extend(seen_in);
+                *all_last_seen = (*all_last_seen).max(last_seen);
+            }
+            for line in &host.script_output {
+                if !state.script_output.contains(line) {
+                    state.script_output.push(line.clone());
+                }
+            }
+        }
+    }
+
+    for (ip, state) in hosts {
+        let mut ports = Vec::new();
+        for (port, (seen_in, last_seen)) in state.ports {
+            if latest && Some(last_seen) < state.latest {
+                continue;
+            }
+            ports.push(port);
+            merged.provenance.push(PortProvenance {
+                ip,
+                port,
+                seen_in,
+                last_seen,
+                runs_with_host: state.runs_with_host,
+            });
+        }
+        if !ports.is_empty() {
+            merged.hosts.push(HostReport {
+                ip,
+                ports,
+                script_output: state.script_output,
+            });
+        }
+    }
+    for rollup in &mut merged.rollups {
+        rollup.count_hosts(&merged.hosts);
+    }
+    Ok(merged)
+}
+
+/// Reads the reports in a file written by `--output json=...`: one, or one
+/// per line for `.jsonl` files. Compressed files are decompressed.
+pub fn read(path: &Path) -> Result<Vec<ScanReport>> {
+    let content = String::from_utf8(file::read(path)?)?;
+    let invalid = |e| anyhow!("{} is not a RustScan JSON report: {e}", path.display());
+    if file::is_appended(path) {
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(invalid))
+            .collect()
+    } else {
+        Ok(vec![serde_json::from_str(&content).map_err(invalid)?])
+    }
+}
+
+/// Merges the reports in `files` into `output`. Prints what it did and
+/// returns whether it succeeded.
+#[cfg(not(tarpaulin_include))]
+pub fn run(files: &[PathBuf], output: &Path, latest: bool, opts: &Opts) -> bool {
+    let reports: Result<Vec<Vec<ScanReport>>> = files.iter().map(|path| read(path)).collect();
+    let merged = reports.and_then(|reports| merge(&reports.concat(), latest));
+    let written = merged.and_then(|merged| {
+        let json = serde_json::to_string_pretty(&merged)?;
+        file::write(output, json.as_bytes(), &FileOptions::default())?;
+        Ok(merged)
+    });
+    match written {
+        Ok(merged) => {
+            output!(
+                format!(
+                    "Merged {} runs into {}: {} hosts, {} open ports",
+                    merged.runs.len(),
+                    output.display(),
+                    merged.hosts.len(),
+                    merged.provenance.len()
+                ),
+                false,
+                opts.accessible
+            );
+            true
+        }
+        Err(e) => {
+            warning!(format!("{e}"), false, opts.accessible);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge;
+    use crate::export::{HostReport, ScanReport};
+    use chrono::{Duration, Utc};
+
+    fn report(minutes_ago: i64, hosts: &[(&str, &[u16])]) -> ScanReport {
+        let started = Utc::now() - Duration::minutes(minutes_ago);
+        let mut report = ScanReport::new(started, false);
+        report.finished = started + Duration::seconds(30);
+        report.target_count = hosts.len();
+        for (ip, ports) in hosts {
+            report.hosts.push(HostReport {
+                ip: ip.parse().unwrap(),
+                ports: ports.to_vec(),
+                script_output: vec![],
+            });
+        }
+        report
+    }
+
+    #[test]
+    fn unions_runs_with_provenance() {
+        let old = report(10, &[("10.0.0.1", &[22, 80]), ("10.0.0.2", &[443])]);
+        let new = report(5, &[("10.0.0.1", &[22])]);
+        let merged = merge(&[old.clone(), new.clone()], false).unwrap();
+
+        assert_eq!(merged.runs.len(), 2);
+        assert_eq!(merged.started, old.started);
+        assert_eq!(merged.finished, new.finished);
+        assert_eq!(merged.target_count, 3);
+        assert_eq!(merged.hosts.len(), 2);
+        assert_eq!(merged.hosts[0].ports, vec![22, 80]);
+
+        let ssh = &merged.provenance[0];
+        assert_eq!(ssh.port, 22);
+        assert_eq!(ssh.seen_in, vec![old.scan_id.clone(), new.scan_id.clone()]);
+        assert_eq!(ssh.last_seen, new.finished);
+        assert!((ssh.confidence() - 1.0).abs() < f64::EPSILON);
+        let http = &merged.provenance[1];
+        assert_eq!(http.seen_in, vec![old.scan_id.clone()]);
+        assert!((http.confidence() - 0.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn recounts_rollups_of_merged_hosts() {
+        use crate::export::rollup::Rollup;
+
+        let rollup = |report: &ScanReport| Rollup {
+            group: "10.0.0.0/24".to_owned(),
+            addresses: vec![],
+            targets: 256,
+            hosts_up: report.hosts.len(),
+            ports: std::collections::BTreeMap::new(),
+        };
+        let mut first = report(10, &[("10.0.0.1", &[22])]);
+        first.rollups = vec![rollup(&first)];
+        let mut second = report(5, &[("10.0.0.2", &[22, 443])]);
+        second.rollups = vec![rollup(&second)];
+        let merged = merge(&[first, second], false).unwrap();
+
+        assert_eq!(merged.rollups.len(), 1);
+        assert_eq!(
+            merged.rollups[0].to_string(),
+            "10.0.0.0/24: 2 of 256 hosts up, 2 with SSH, 1 with HTTPS"
+        );
+    }
+
+    #[test]
+    fn latest_run_wins_conflicts() {
+        let old = report(10, &[("10.0.0.1", &[22, 80]), ("10.0.0.2", &[443])]);
+        let new = report(5, &[("10.0.0.1", &[22])]);
+        let merged = merge(&[new, old], true).unwrap();
+
+        assert_eq!(merged.hosts[0].ports, vec![22]);
+        // No later run reached 10.0.0.2, so its port stands.
+        assert_eq!(merged.hosts[1].ports, vec![443]);
+    }
+
+    #[test]
+    fn merged_reports_merge_again() {
+        let a = report(10, &[("10.0.0.1", &[22])]);
+        let b = report(5, &[("10.0.0.1", &[22])]);
+        let c = report(1, &[("10.0.0.1", &[80])]);
+        let ab = merge(&[a, b], false).unwrap();
+        let abc = merge(&[ab, c], false).unwrap();
+
+        assert_eq!(abc.runs.len(), 3);
+        assert_eq!(abc.provenance[0].seen_in.len(), 2);
+        assert_eq!(abc.provenance[0].runs_with_host, 3);
+        assert_eq!(abc.provenance[1].runs_with_host, 3);
+    }
+
+    #[test]
+    fn refuses_mixed_protocols() {
+        let tcp = report(10, &[]);
+        let udp = ScanReport::new(Utc::now(), true);
+        assert!(merge(&[tcp, udp], false).is_err());
+        assert!(merge(&[], false).is_err());
+    }
+}
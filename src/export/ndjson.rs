@@ -0,0 +1,163 @@
+//! Streams scan events to stdout as newline-delimited JSON.
+//!
+//! Unlike the other outputs, which get the report once the scan is over,
+//! `--output ndjson` writes a JSON object per line as things happen, for
+//! piping into `jq` or anything reading lines:
+//!
+//! - `host-start` when the first probe is sent to a host,
+//! - `port-open` for every open port, as it's found,
+//! - `script-result` with the output of every script run against a host,
+//! - `host-done` with a host's open ports, once its scripts have run,
+//! - `scan-done` with how many hosts and ports were found, last.
+//!
+//! Every event has the `event` name, the `time` it happened and the
+//! `scan_id` of the report. Nothing else is printed to stdout meanwhile.
+//! Events are written as they happen, before `--redact` applies.
+//!
+//! ```text
+//! {"event":"port-open","ip":"10.0.0.5","port":22,"scan_id":"…","time":"2024-05-01T09:00:02.113Z"}
+//! ```
+use super::{OutputSink, ScanReport};
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+use log::debug;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+/// Something that happened during a scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    HostStart { ip: IpAddr },
+    PortOpen { ip: IpAddr, port: u16 },
+    ScriptResult { ip: IpAddr, output: &'a str },
+    HostDone { ip: IpAddr, ports: &'a [u16] },
+    ScanDone { hosts_up: usize, open_ports: usize },
+}
+
+/// Where the events of a scan are written, a line each.
+pub struct EventStream {
+    scan_id: String,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl EventStream {
+    pub fn new(scan_id: String, out: Box<dyn Write + Send>) -> Self {
+        Self {
+            scan_id,
+            out: Mutex::new(out),
+        }
+    }
+
+    /// The events of a new scan, written to stdout.
+    pub fn stdout() -> Self {
+        Self::new(uuid::Uuid::new_v4().to_string(), Box::new(io::stdout()))
+    }
+
+    pub fn scan_id(&self) -> &str {
+        &self.scan_id
+    }
+
+    /// Writes `event` and flushes it, so readers get it right away.
+    pub fn emit(&self, event: &Event<'_>) {
+        let line = line(&self.scan_id, event);
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(out, "{line}").and_then(|()| out.flush()) {
+            debug!("Could not write event {line}: {e}");
+        }
+    }
+}
+
+impl fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventStream")
+            .field("scan_id", &self.scan_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// `event` as a JSON line, with when it happened and its scan.
+fn line(scan_id: &str, event: &Event<'_>) -> String {
+    let mut value = serde_json::to_value(event).unwrap_or_default();
+    if let Value::Object(fields) = &mut value {
+        fields.insert("scan_id".to_owned(), Value::from(scan_id));
+        fields.insert(
+            "time".to_owned(),
+            Value::from(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+        );
+    }
+    value.to_string()
+}
+
+/// Ends the stream with a `scan-done` event once the report is complete.
+pub struct NdjsonSink;
+
+impl OutputSink for NdjsonSink {
+    fn write(&mut self, report: &ScanReport) -> Result<()> {
+        let stream = EventStream::new(report.scan_id.clone(), Box::new(io::stdout()));
+        stream.emit(&Event::ScanDone {
+            hosts_up: report.hosts.len(),
+            open_ports: report.hosts.iter().map(|host| host.ports.len()).sum(),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, EventStream};
+    use serde_json::Value;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn events_are_written_a_line_each() {
+        let buffer = Buffer::default();
+        let stream = EventStream::new("scan".to_owned(), Box::new(buffer.clone()));
+        let ip = "10.0.0.5".parse().unwrap();
+        stream.emit(&Event::HostStart { ip });
+        stream.emit(&Event::PortOpen { ip, port: 22 });
+        stream.emit(&Event::ScriptResult {
+            ip,
+            output: "22/tcp open ssh\n",
+        });
+        stream.emit(&Event::HostDone { ip, ports: &[22] });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let names: Vec<&str> = events
+            .iter()
+            .map(|event| event["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            ["host-start", "port-open", "script-result", "host-done"]
+        );
+        assert!(events.iter().all(|event| event["scan_id"] == "scan"
+            && event["ip"] == "10.0.0.5"
+            && event["time"].is_string()));
+        assert_eq!(events[1]["port"], 22);
+        assert_eq!(events[2]["output"], "22/tcp open ssh\n");
+        assert_eq!(events[3]["ports"], serde_json::json!([22]));
+    }
+}
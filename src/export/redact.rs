@@ -0,0 +1,407 @@
+//! Masks addresses and hostnames in reports meant to be shared.
+//!
+//! With `--redact`, the report handed to every `--output` keeps the subnets
+//! of the hosts but not the hosts themselves: the last octet of IPv4
+//! addresses and the interface identifier of IPv6 ones are replaced with a
+//! counter per subnet (`10.0.3.17` and `10.0.3.42` become `10.0.3.1` and
+//! `10.0.3.2`), so ports of different hosts aren't mixed up. Addresses
+//! found in text (the command line, script output, notes, probe answers,
+//! warnings, nuclei findings, service banners) are replaced the same way, or with their
+//! subnet's address if they aren't hosts of the report. Hostnames given as
+//! targets are replaced with `[redacted]` wherever they appear. What's
+//! printed to the terminal is left as is.
+use super::ScanReport;
+use crate::input::Opts;
+use cidr_utils::cidr::{IpCidr, IpInet};
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::str::FromStr;
+
+/// What hostnames are replaced with.
+pub const REDACTED: &str = "[redacted]";
+
+/// Replaces the hosts of a report with redacted ones, consistently.
+#[derive(Debug, Default)]
+pub struct Redactor {
+    /// Lowercased, longest first.
+    hostnames: Vec<String>,
+    hosts: BTreeMap<IpAddr, IpAddr>,
+    counters: BTreeMap<IpAddr, u64>,
+}
+
+/// The address of the subnet of `ip`: its `/24` or `/64`.
+fn subnet(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & !u128::from(u64::MAX))),
+    }
+}
+
+fn is_address_char(c: char) -> bool {
+    c.is_ascii_hexdigit() || c == '.' || c == ':'
+}
+
+impl Redactor {
+    pub fn new(hostnames: &[String]) -> Self {
+        let mut hostnames: Vec<String> = hostnames
+            .iter()
+            .map(|hostname| hostname.trim().to_ascii_lowercase())
+            .filter(|hostname| !hostname.is_empty())
+            .collect();
+        hostnames.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        hostnames.dedup();
+        Self {
+            hostnames,
+            ..Self::default()
+        }
+    }
+
+    /// The redacted address of host `ip`, numbered in its subnet in the
+    /// order hosts are first seen.
+    pub fn host(&mut self, ip: IpAddr) -> IpAddr {
+        if let Some(redacted) = self.hosts.get(&ip) {
+            return *redacted;
+        }
+        let network = subnet(ip);
+        let counter = self.counters.entry(network).or_insert(0);
+        *counter += 1;
+        let redacted = match network {
+            IpAddr::V4(network) => {
+                let [a, b, c, _] = network.octets();
+                IpAddr::V4(Ipv4Addr::new(a, b, c, (*counter % 256) as u8))
+            }
+            IpAddr::V6(network) => {
+                IpAddr::V6(Ipv6Addr::from(u128::from(network) | u128::from(*counter)))
+            }
+        };
+        self.hosts.insert(ip, redacted);
+        redacted
+    }
+
+    /// A host's redacted address, or the subnet of any other address.
+    fn address(&self, ip: IpAddr) -> IpAddr {
+        self.hosts.get(&ip).copied().unwrap_or_else(|| subnet(ip))
+    }
+
+    /// `token` redacted, if it's an address or an IPv4 socket.
+    fn token(&self, token: &str) -> Option<String> {
+        if let Ok(ip) = IpAddr::from_str(token) {
+            return Some(self.address(ip).to_string());
+        }
+        match SocketAddr::from_str(token) {
+            Ok(socket) => {
+                Some(SocketAddr::new(self.address(socket.ip()), socket.port()).to_string())
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// `text` without the hostnames and addresses in it.
+    pub fn text(&self, text: &str) -> String {
+        let mut text = text.to_owned();
+        for hostname in &self.hostnames {
+            let mut from = 0;
+            // Lowercasing ASCII keeps byte offsets.
+            while let Some(found) = text.to_ascii_lowercase()[from..].find(hostname.as_str()) {
+                let start = from + found;
+                text.replace_range(start..start + hostname.len(), REDACTED);
+                from = start + REDACTED.len();
+            }
+        }
+
+        let mut redacted = String::with_capacity(text.len());
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find(is_address_char) {
+            redacted.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest.find(|c| !is_address_char(c)).unwrap_or(rest.len());
+            let run = &rest[..end];
+            // Separators around an address, as in `host:10.0.0.1.`
+            let trimmed = run.trim_matches(['.', ':']);
+            match self.token(run) {
+                Some(address) => redacted.push_str(&address),
+                None => match self.token(trimmed) {
+                    Some(address) => {
+                        let prefix = run.len() - run.trim_start_matches(['.', ':']).len();
+                        redacted.push_str(&run[..prefix]);
+                        redacted.push_str(&address);
+                        redacted.push_str(&run[prefix + trimmed.len()..]);
+                    }
+                    None => redacted.push_str(run),
+                },
+            }
+            rest = &rest[end..];
+        }
+        redacted.push_str(rest);
+        redacted
+    }
+
+    /// Redacts everything in `report` that tells its hosts apart.
+    pub fn redact(&mut self, report: &mut ScanReport) {
+        // Number the hosts with open ports first, in order, leaving them
+        // as `--sort-by` put them.
+        let mut open: Vec<IpAddr> = report.hosts.iter().map(|host| host.ip).collect();
+        open.sort();
+        for ip in open {
+            self.host(ip);
+        }
+        for host in &mut report.hosts {
+            host.ip = self.host(host.ip);
+        }
+        let mut others: Vec<IpAddr> = report.probes.keys().copied().collect();
+        others.extend(report.devices.keys());
+        others.extend(report.distances.keys());
+        others.extend(&report.wildcards);
+        others.sort();
+        for ip in others {
+            self.host(ip);
+        }
+
+        for host in &mut report.hosts {
+            host.script_output = host
+                .script_output
+                .iter()
+                .map(|line| self.text(line))
+                .collect();
+        }
+        report.args = report.args.iter().map(|arg| self.text(arg)).collect();
+        report.warnings = report
+            .warnings
+            .iter()
+            .map(|warning| self.text(warning))
+            .collect();
+        for provenance in &mut report.provenance {
+            provenance.ip = self.host(provenance.ip);
+        }
+        for note in &mut report.notes {
+            note.ip = self.host(note.ip);
+            note.text = self.text(&note.text);
+        }
+        for socket in &mut report.known {
+            socket.set_ip(self.host(socket.ip()));
+        }
+        report.probes = std::mem::take(&mut report.probes)
+            .into_iter()
+            .map(|(ip, stats)| (self.host(ip), stats))
+            .collect();
+        report.devices = std::mem::take(&mut report.devices)
+            .into_iter()
+            .map(|(ip, device)| (self.host(ip), device))
+            .collect();
+        report.responses = std::mem::take(&mut report.responses)
+            .into_iter()
+            .map(|(socket, mut response)| {
+                response.snippet = self.text(&response.snippet);
+                (
+                    SocketAddr::new(self.host(socket.ip()), socket.port()),
+                    response,
+                )
+            })
+            .collect();
+        report.banners = std::mem::take(&mut report.banners)
+            .into_iter()
+            .map(|(socket, banner)| {
+                (
+                    SocketAddr::new(self.host(socket.ip()), socket.port()),
+                    self.text(&banner),
+                )
+            })
+            .collect();
+        for ip in &mut report.wildcards {
+            *ip = self.host(*ip);
+        }
+        report.distances = std::mem::take(&mut report.distances)
+            .into_iter()
+            .map(|(ip, distance)| (self.host(ip), distance))
+            .collect();
+        for rollup in &mut report.rollups {
+            rollup.group = self.text(&rollup.group);
+            rollup.addresses = rollup
+                .addresses
+                .iter()
+                .map(|ip| self.address(*ip))
+                .collect();
+        }
+        for finding in &mut report.nuclei {
+            finding.socket.set_ip(self.host(finding.socket.ip()));
+            finding.matched_at = self.text(&finding.matched_at);
+            finding.extracted = finding
+                .extracted
+                .iter()
+                .map(|value| self.text(value))
+                .collect();
+        }
+        for hint in &mut report.vuln_hints {
+            hint.socket.set_ip(self.host(hint.socket.ip()));
+        }
+        for attempt in &mut report.default_creds {
+            attempt.socket.set_ip(self.host(attempt.socket.ip()));
+        }
+        for probe in &mut report.service_probes {
+            probe.socket.set_ip(self.host(probe.socket.ip()));
+            probe.answer = self.text(&probe.answer);
+        }
+        for drift in &mut report.policy_drift {
+            drift.socket.set_ip(self.host(drift.socket.ip()));
+        }
+        for alert in &mut report.alerts {
+            alert.socket.set_ip(self.host(alert.socket.ip()));
+            alert.rule = self.text(&alert.rule);
+        }
+        for device in &mut report.ot_devices {
+            device.socket.set_ip(self.host(device.socket.ip()));
+            for value in device.identity.values_mut() {
+                *value = self.text(value);
+            }
+        }
+        report.discovered = std::mem::take(&mut report.discovered)
+            .into_iter()
+            .map(|(socket, time)| (SocketAddr::new(self.host(socket.ip()), socket.port()), time))
+            .collect();
+        for socket in &mut report.other_protocol {
+            socket.set_ip(self.host(socket.ip()));
+        }
+        report.latencies_us = std::mem::take(&mut report.latencies_us)
+            .into_iter()
+            .map(|(socket, us)| (SocketAddr::new(self.host(socket.ip()), socket.port()), us))
+            .collect();
+        for group in &mut report.groups {
+            group.name = self.text(&group.name);
+            for socket in &mut group.sockets {
+                socket.set_ip(self.host(socket.ip()));
+            }
+        }
+        report.port_states = std::mem::take(&mut report.port_states)
+            .into_iter()
+            .map(|(socket, state)| {
+                (
+                    SocketAddr::new(self.host(socket.ip()), socket.port()),
+                    state,
+                )
+            })
+            .collect();
+    }
+}
+
+/// The hostnames among the targets, including those listed in files.
+pub fn hostnames(opts: &Opts) -> Vec<String> {
+    let is_hostname = |address: &str| {
+        !address.is_empty()
+            && IpAddr::from_str(address).is_err()
+            && IpInet::from_str(address).is_err()
+            && IpCidr::from_str(address).is_err()
+    };
+    let mut hostnames = Vec::new();
+    for address in &opts.addresses {
+        let path = Path::new(address);
+        if path.is_file() {
+            let lines = fs::read_to_string(path).unwrap_or_default();
+            hostnames.extend(
+                lines
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| is_hostname(line))
+                    .map(ToOwned::to_owned),
+            );
+        } else if is_hostname(address) {
+            hostnames.push(address.clone());
+        }
+    }
+    hostnames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Redactor, REDACTED};
+    use crate::export::rollup::Rollup;
+    use crate::export::{HostReport, ScanReport};
+    use chrono::Utc;
+    use std::collections::BTreeMap;
+    use std::net::IpAddr;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn hosts_are_numbered_per_subnet() {
+        let mut redactor = Redactor::new(&[]);
+        assert_eq!(redactor.host(ip("10.0.3.42")), ip("10.0.3.1"));
+        assert_eq!(redactor.host(ip("10.0.4.9")), ip("10.0.4.1"));
+        assert_eq!(redactor.host(ip("10.0.3.17")), ip("10.0.3.2"));
+        assert_eq!(redactor.host(ip("10.0.3.42")), ip("10.0.3.1"));
+        assert_eq!(
+            redactor.host(ip("2001:db8:1:2:a00:27ff:fe4e:66a1")),
+            ip("2001:db8:1:2::1")
+        );
+    }
+
+    #[test]
+    fn text_loses_addresses_and_hostnames() {
+        let mut redactor = Redactor::new(&["DB.corp.example".to_owned()]);
+        redactor.host(ip("10.0.3.42"));
+        assert_eq!(
+            redactor.text("rustscan -a db.corp.example,10.0.3.42,192.168.1.0/24"),
+            format!("rustscan -a {REDACTED},10.0.3.1,192.168.1.0/24")
+        );
+        assert_eq!(
+            redactor.text("Nmap scan report for DB.Corp.Example (10.0.3.42): 10.0.3.42:22 open, peer 172.16.5.9."),
+            format!("Nmap scan report for {REDACTED} (10.0.3.1): 10.0.3.1:22 open, peer 172.16.5.0.")
+        );
+        assert_eq!(
+            redactor.text("via [2001:db8::5]:443"),
+            "via [2001:db8::]:443"
+        );
+        assert_eq!(
+            redactor.text("deadbeef cafe 12:30 v1.2"),
+            "deadbeef cafe 12:30 v1.2"
+        );
+    }
+
+    #[test]
+    fn reports_keep_hosts_apart() {
+        let mut report = ScanReport::new(Utc::now(), false);
+        report.args = vec![
+            "rustscan".to_owned(),
+            "-a".to_owned(),
+            "web.example,10.0.3.17".to_owned(),
+        ];
+        for (address, ports) in [("10.0.3.42", vec![443]), ("10.0.3.17", vec![22, 80])] {
+            report.hosts.push(HostReport {
+                ip: ip(address),
+                ports,
+                script_output: vec![format!("{address} is web.example")],
+            });
+        }
+        report.known = vec!["10.0.3.17:22".parse().unwrap()];
+        report.rollups = vec![Rollup {
+            group: "web.example".to_owned(),
+            addresses: vec![ip("10.0.3.17"), ip("10.0.3.42")],
+            targets: 2,
+            hosts_up: 2,
+            ports: BTreeMap::new(),
+        }];
+
+        Redactor::new(&["web.example".to_owned()]).redact(&mut report);
+        // Numbered by IP, left in the order they were sorted by.
+        assert_eq!(report.hosts[1].ip, ip("10.0.3.1"));
+        assert_eq!(report.hosts[1].ports, vec![22, 80]);
+        assert_eq!(
+            report.hosts[1].script_output,
+            vec![format!("10.0.3.1 is {REDACTED}")]
+        );
+        assert_eq!(report.hosts[0].ip, ip("10.0.3.2"));
+        assert_eq!(report.args[2], format!("{REDACTED},10.0.3.1"));
+        assert!(report.is_known(ip("10.0.3.1"), 22));
+        assert_eq!(report.rollups[0].group, REDACTED);
+        assert_eq!(
+            report.rollups[0].addresses,
+            vec![ip("10.0.3.1"), ip("10.0.3.2")]
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Sums up findings per network or hostname given as a target.
+//!
+//! The list of open ports of a large scan says little at a glance. For
+//! every CIDR and hostname among the targets covering more than one IP, a
+//! [`Rollup`] counts the hosts with open ports and how many of them have
+//! each port open, printed after the scan as
+//! `10.1.0.0/16: 2,041 of 65,536 hosts up, 312 with SSH, 97 with HTTPS`
+//! and kept in reports as `rollups`.
+use super::HostReport;
+use crate::address::{get_resolver, parse_address, IpPreference};
+use crate::input::Opts;
+use crate::units::thousands;
+use cidr_utils::cidr::{IpCidr, IpInet};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Ports listed in a rollup's summary line.
+const SUMMARY_PORTS: usize = 3;
+
+/// Names of well-known ports, for summaries.
+pub(crate) const SERVICES: [(u16, &str); 18] = [
+    (21, "FTP"),
+    (22, "SSH"),
+    (23, "Telnet"),
+    (25, "SMTP"),
+    (53, "DNS"),
+    (80, "HTTP"),
+    (110, "POP3"),
+    (143, "IMAP"),
+    (161, "SNMP"),
+    (389, "LDAP"),
+    (443, "HTTPS"),
+    (445, "SMB"),
+    (1433, "MSSQL"),
+    (3306, "MySQL"),
+    (3389, "RDP"),
+    (5432, "PostgreSQL"),
+    (5900, "VNC"),
+    (6379, "Redis"),
+];
+
+/// The findings of a network or hostname given as a target.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rollup {
+    /// The CIDR or hostname, as given.
+    pub group: String,
+    /// What a hostname resolved to; networks are matched by their CIDR.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<IpAddr>,
+    /// Scanned IPs in the group.
+    pub targets: usize,
+    /// Hosts of the group with open ports.
+    pub hosts_up: usize,
+    /// How many hosts of the group have each port open.
+    pub ports: BTreeMap<u16, usize>,
+}
+
+impl Rollup {
+    fn new(group: String, addresses: Vec<IpAddr>) -> Self {
+        Self {
+            group,
+            addresses,
+            targets: 0,
+            hosts_up: 0,
+            ports: BTreeMap::new(),
+        }
+    }
+
+    /// The empty rollups of the CIDRs and hostnames among the addresses.
+    pub fn groups(opts: &Opts) -> Vec<Self> {
+        let resolver = get_resolver(&opts.resolver);
+        opts.addresses
+            .iter()
+            .filter(|address| IpAddr::from_str(address).is_err() && !Path::new(address).is_file())
+            .map(|address| {
+                if IpInet::from_str(address).is_ok() {
+                    Self::new(address.clone(), Vec::new())
+                } else {
+                    let addresses =
+                        parse_address(address, &resolver, IpPreference::from_opts(opts));
+                    Self::new(address.clone(), addresses)
+                }
+            })
+            .collect()
+    }
+
+    /// Matches the IPs of the group.
+    fn matcher(&self) -> impl Fn(&IpAddr) -> bool {
+        let network = IpCidr::from_str(&self.group).ok().or_else(|| {
+            IpInet::from_str(&self.group)
+                .ok()
+                .map(|inet| inet.network())
+        });
+        let addresses = self.addresses.clone();
+        move |ip| match &network {
+            Some(network) => network.contains(ip),
+            None => addresses.contains(ip),
+        }
+    }
+
+    /// Counts the hosts of the group among `hosts`.
+    pub fn count_hosts(&mut self, hosts: &[HostReport]) {
+        let contains = self.matcher();
+        let mut hosts_up = 0;
+        let mut ports = BTreeMap::new();
+        for host in hosts.iter().filter(|host| contains(&host.ip)) {
+            if host.ports.is_empty() {
+                continue;
+            }
+            hosts_up += 1;
+            for port in &host.ports {
+                *ports.entry(*port).or_insert(0) += 1;
+            }
+        }
+        self.hosts_up = hosts_up;
+        self.ports = ports;
+    }
+
+    /// The rollups of every group covering more than one of `targets`.
+    pub fn summarize(groups: Vec<Self>, targets: &[IpAddr], hosts: &[HostReport]) -> Vec<Self> {
+        groups
+            .into_iter()
+            .filter_map(|mut rollup| {
+                let contains = rollup.matcher();
+                let count = targets.iter().filter(|ip| contains(ip)).count();
+                rollup.targets = count;
+                if count < 2 {
+                    return None;
+                }
+                rollup.count_hosts(hosts);
+                Some(rollup)
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Rollup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} of {} hosts up",
+            self.group,
+            thousands(self.hosts_up as u64),
+            thousands(self.targets as u64)
+        )?;
+        let mut ports: Vec<(&u16, &usize)> = self.ports.iter().collect();
+        ports.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (port, hosts) in ports.into_iter().take(SUMMARY_PORTS) {
+            match SERVICES.iter().find(|(known, _)| known == port) {
+                Some((_, service)) => write!(f, ", {} with {service}", thousands(*hosts as u64))?,
+                None => write!(f, ", {} with port {port}", thousands(*hosts as u64))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rollup;
+    use crate::export::HostReport;
+    use std::net::IpAddr;
+
+    fn host(ip: &str, ports: &[u16]) -> HostReport {
+        HostReport {
+            ip: ip.parse().unwrap(),
+            ports: ports.to_vec(),
+            script_output: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn rollups_count_hosts_per_group() {
+        let targets: Vec<IpAddr> = ["10.1.0.1", "10.1.0.2", "10.1.0.3", "192.0.2.1", "192.0.2.2"]
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        let hosts = vec![
+            host("10.1.0.1", &[22, 443]),
+            host("10.1.0.2", &[22, 8080]),
+            host("192.0.2.1", &[80]),
+        ];
+        let groups = vec![
+            Rollup::new("10.1.0.0/16".to_owned(), Vec::new()),
+            Rollup::new(
+                "web.example".to_owned(),
+                vec!["192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap()],
+            ),
+            Rollup::new("10.9.0.0/16".to_owned(), Vec::new()),
+        ];
+        let rollups = Rollup::summarize(groups, &targets, &hosts);
+        assert_eq!(rollups.len(), 2);
+        assert_eq!((rollups[0].targets, rollups[0].hosts_up), (3, 2));
+        assert_eq!(
+            rollups[0].to_string(),
+            "10.1.0.0/16: 2 of 3 hosts up, 2 with SSH, 1 with HTTPS, 1 with port 8080"
+        );
+        assert_eq!(
+            rollups[1].to_string(),
+            "web.example: 1 of 2 hosts up, 1 with HTTP"
+        );
+    }
+}
@@ -0,0 +1,357 @@
+//! Orders and groups the results of text and report outputs.
+//!
+//! `--sort-by` orders the hosts and their open ports, by IP (the default),
+//! port, service or latency. `--group-by` gathers the open ports by
+//! subnet (`/24` for IPv4, `/64` for IPv6) or service, printed after the
+//! results as `10.0.1.0/24 -> [10.0.1.4:22,10.0.1.9:443]` and kept in
+//! reports as `groups`.
+//!
+//! The service of a port is the one `--service-probes` found there, or else
+//! the usual one of the port. Latency is how long the port took to accept
+//! the connection, or to answer over UDP.
+//!
+//! `--show` and `--only-service` pick what's rendered without changing what
+//! is scanned, see [`Filter`]: `--show closed,filtered` lists the ports that
+//! refused the probes or never answered them, printed as
+//! `10.0.0.5 -> closed [23,25]` and kept in reports as `port_states`.
+use super::rollup::SERVICES;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+/// How hosts and their ports are ordered.
+#[derive(Deserialize, Serialize, Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    Host,
+    Port,
+    Service,
+    Latency,
+}
+
+/// How open ports are gathered.
+#[derive(Deserialize, Serialize, Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Subnet,
+    Service,
+}
+
+/// What the probes of a port found.
+#[derive(Deserialize, Serialize, Debug, ValueEnum, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum PortState {
+    Open,
+    /// Refused the connection, or answered with an ICMP port unreachable.
+    Closed,
+    /// Didn't answer.
+    Filtered,
+}
+
+impl fmt::Display for PortState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+        })
+    }
+}
+
+/// Which ports outputs render, by state and service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub states: Vec<PortState>,
+    /// Services to keep, every one when empty.
+    pub services: Vec<String>,
+}
+
+impl Filter {
+    /// Whether a port of `state` on `socket` is rendered.
+    pub fn shows(&self, view: &View, socket: SocketAddr, state: PortState) -> bool {
+        self.states.contains(&state)
+            && (self.services.is_empty()
+                || view.service(socket).is_some_and(|service| {
+                    self.services
+                        .iter()
+                        .any(|wanted| wanted.eq_ignore_ascii_case(&service))
+                }))
+    }
+
+    /// Keeps the ports of `hosts` that are shown as open, and the hosts
+    /// left with some.
+    pub fn retain_open(&self, view: &View, hosts: &mut Vec<(IpAddr, Vec<u16>)>) {
+        for (ip, ports) in hosts.iter_mut() {
+            ports.retain(|&port| self.shows(view, SocketAddr::new(*ip, port), PortState::Open));
+        }
+        hosts.retain(|(_, ports)| !ports.is_empty());
+    }
+
+    /// Keeps the closed and filtered ports of `states` that are shown.
+    pub fn retain_states(
+        &self,
+        view: &View,
+        mut states: BTreeMap<SocketAddr, PortState>,
+    ) -> BTreeMap<SocketAddr, PortState> {
+        states.retain(|socket, state| self.shows(view, *socket, *state));
+        states
+    }
+
+    /// Whether closed or filtered ports are asked for, which the scanner
+    /// then has to keep.
+    pub fn wants_unanswered(&self) -> bool {
+        self.states.iter().any(|state| *state != PortState::Open)
+    }
+}
+
+/// Open ports gathered under the same subnet or service.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Group {
+    pub name: String,
+    pub sockets: Vec<SocketAddr>,
+}
+
+/// What's known of the open ports beyond their numbers.
+pub struct View<'a> {
+    services: &'a BTreeMap<SocketAddr, String>,
+    latencies: &'a BTreeMap<SocketAddr, Duration>,
+}
+
+impl<'a> View<'a> {
+    pub fn new(
+        services: &'a BTreeMap<SocketAddr, String>,
+        latencies: &'a BTreeMap<SocketAddr, Duration>,
+    ) -> Self {
+        Self {
+            services,
+            latencies,
+        }
+    }
+
+    /// The service found on `socket`, or else the usual one of its port.
+    pub fn service(&self, socket: SocketAddr) -> Option<String> {
+        self.services.get(&socket).cloned().or_else(|| {
+            SERVICES
+                .iter()
+                .find(|(port, _)| *port == socket.port())
+                .map(|(_, name)| name.to_lowercase())
+        })
+    }
+
+    /// Orders the ports of every host, then the hosts by their first port.
+    /// Ties are broken by IP and port.
+    pub fn sort(&self, hosts: &mut [(IpAddr, Vec<u16>)], by: SortBy) {
+        let key = |ip: IpAddr, port: u16| {
+            let socket = SocketAddr::new(ip, port);
+            match by {
+                SortBy::Host | SortBy::Port => (None, None),
+                // Ports of unknown service and latency come last.
+                SortBy::Service => (
+                    Some(self.service(socket).unwrap_or_else(|| "~".to_owned())),
+                    None,
+                ),
+                SortBy::Latency => (
+                    None,
+                    Some(
+                        self.latencies
+                            .get(&socket)
+                            .copied()
+                            .unwrap_or(Duration::MAX),
+                    ),
+                ),
+            }
+        };
+        for (ip, ports) in hosts.iter_mut() {
+            ports.sort_by_cached_key(|&port| (key(*ip, port), port));
+        }
+        hosts.sort_by_cached_key(|(ip, ports)| match (by, ports.first()) {
+            (SortBy::Host, _) | (_, None) => (None, *ip),
+            (_, Some(&port)) => (Some((key(*ip, port), port)), *ip),
+        });
+    }
+
+    /// Gathers the open ports of `hosts`, keeping their order within each
+    /// group. Groups are sorted by name, ports of unknown service under
+    /// `unknown`.
+    pub fn group(&self, hosts: &[(IpAddr, Vec<u16>)], by: GroupBy) -> Vec<Group> {
+        let mut groups: BTreeMap<String, Vec<SocketAddr>> = BTreeMap::new();
+        for (ip, ports) in hosts {
+            for port in ports {
+                let socket = SocketAddr::new(*ip, *port);
+                let name = match by {
+                    GroupBy::Subnet => subnet(*ip),
+                    GroupBy::Service => {
+                        self.service(socket).unwrap_or_else(|| "unknown".to_owned())
+                    }
+                };
+                groups.entry(name).or_default().push(socket);
+            }
+        }
+        groups
+            .into_iter()
+            .map(|(name, sockets)| Group { name, sockets })
+            .collect()
+    }
+}
+
+/// The subnet `ip` is counted in.
+pub fn subnet(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{a}.{b}.{c}.0/24")
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            let network = std::net::Ipv6Addr::new(
+                segments[0],
+                segments[1],
+                segments[2],
+                segments[3],
+                0,
+                0,
+                0,
+                0,
+            );
+            format!("{network}/64")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{subnet, Filter, GroupBy, PortState, SortBy, View};
+    use std::collections::BTreeMap;
+    use std::net::{IpAddr, SocketAddr};
+    use std::time::Duration;
+
+    fn hosts() -> Vec<(IpAddr, Vec<u16>)> {
+        vec![
+            ("10.0.0.9".parse().unwrap(), vec![443, 22]),
+            ("10.0.1.2".parse().unwrap(), vec![8080, 21]),
+            ("10.0.0.3".parse().unwrap(), vec![3306]),
+        ]
+    }
+
+    #[test]
+    fn sorts_hosts_and_ports() {
+        let services = BTreeMap::new();
+        let latencies: BTreeMap<SocketAddr, Duration> = [
+            ("10.0.0.9:443", 5),
+            ("10.0.0.9:22", 40),
+            ("10.0.1.2:21", 2),
+            ("10.0.0.3:3306", 9),
+        ]
+        .iter()
+        .map(|&(socket, ms)| (socket.parse().unwrap(), Duration::from_millis(ms)))
+        .collect();
+        let view = View::new(&services, &latencies);
+        let order = |by| {
+            let mut hosts = hosts();
+            view.sort(&mut hosts, by);
+            hosts
+                .into_iter()
+                .map(|(ip, ports)| format!("{ip} {ports:?}"))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            order(SortBy::Host),
+            [
+                "10.0.0.3 [3306]",
+                "10.0.0.9 [22, 443]",
+                "10.0.1.2 [21, 8080]"
+            ]
+        );
+        assert_eq!(
+            order(SortBy::Port),
+            [
+                "10.0.1.2 [21, 8080]",
+                "10.0.0.9 [22, 443]",
+                "10.0.0.3 [3306]"
+            ]
+        );
+        assert_eq!(
+            order(SortBy::Service),
+            [
+                "10.0.1.2 [21, 8080]",
+                "10.0.0.9 [443, 22]",
+                "10.0.0.3 [3306]"
+            ]
+        );
+        assert_eq!(
+            order(SortBy::Latency),
+            [
+                "10.0.1.2 [21, 8080]",
+                "10.0.0.9 [443, 22]",
+                "10.0.0.3 [3306]"
+            ]
+        );
+    }
+
+    #[test]
+    fn groups_ports_by_subnet_and_service() {
+        let services = BTreeMap::from([("10.0.1.2:8080".parse().unwrap(), "http".to_owned())]);
+        let latencies = BTreeMap::new();
+        let view = View::new(&services, &latencies);
+        let groups: Vec<String> = view
+            .group(&hosts(), GroupBy::Service)
+            .into_iter()
+            .map(|group| format!("{} {:?}", group.name, group.sockets))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                "ftp [10.0.1.2:21]",
+                "http [10.0.1.2:8080]",
+                "https [10.0.0.9:443]",
+                "mysql [10.0.0.3:3306]",
+                "ssh [10.0.0.9:22]"
+            ]
+        );
+        let subnets = view.group(&hosts(), GroupBy::Subnet);
+        assert_eq!(subnets[0].name, "10.0.0.0/24");
+        assert_eq!(subnets[0].sockets.len(), 3);
+    }
+
+    #[test]
+    fn filters_ports_by_state_and_service() {
+        let services = BTreeMap::from([("10.0.1.2:8080".parse().unwrap(), "http".to_owned())]);
+        let latencies = BTreeMap::new();
+        let view = View::new(&services, &latencies);
+        let filter = Filter {
+            states: vec![PortState::Open, PortState::Closed],
+            services: vec!["HTTP".to_owned(), "ssh".to_owned()],
+        };
+        let mut hosts = hosts();
+        filter.retain_open(&view, &mut hosts);
+        assert_eq!(
+            hosts,
+            [
+                ("10.0.0.9".parse().unwrap(), vec![22]),
+                ("10.0.1.2".parse().unwrap(), vec![8080]),
+            ]
+        );
+        assert!(filter.wants_unanswered());
+        assert!(filter.shows(&view, "10.0.0.1:22".parse().unwrap(), PortState::Closed));
+        assert!(!filter.shows(&view, "10.0.0.1:22".parse().unwrap(), PortState::Filtered));
+        assert!(!filter.shows(&view, "10.0.0.1:9999".parse().unwrap(), PortState::Open));
+
+        let filter = Filter {
+            states: vec![PortState::Open],
+            services: Vec::new(),
+        };
+        assert!(filter.shows(&view, "10.0.0.1:9999".parse().unwrap(), PortState::Open));
+        assert!(!filter.wants_unanswered());
+    }
+
+    #[test]
+    fn subnets_of_hosts() {
+        assert_eq!(subnet("192.168.4.20".parse().unwrap()), "192.168.4.0/24");
+        assert_eq!(
+            subnet("2001:db8:1:2:3::9".parse().unwrap()),
+            "2001:db8:1:2::/64"
+        );
+    }
+}
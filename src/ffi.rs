@@ -0,0 +1,279 @@
+//! C bindings to the scan engine, for embedding RustScan in other languages.
+//!
+//! Built with the `ffi` feature, as a shared library:
+//!
+//! ```text
+//! cargo rustc --release --lib --features ffi --crate-type cdylib
+//! ```
+//!
+//! The C side is described in `include/rustscan.h`. A scan is started with
+//! `rustscan_start`, which returns at once and scans on a thread of its own;
+//! `rustscan_poll` tells whether it's done, `rustscan_next` hands out the
+//! open ports found so far one at a time, and `rustscan_cancel` ends it the
+//! way a [`CancelToken`](crate::CancelToken) does, see [`BackgroundScan`].
+//! `rustscan_free` releases the scan. When a call fails,
+//! `rustscan_last_error` says why.
+//!
+//! ```c
+//! RustScanScan *scan = rustscan_start("192.168.1.0/24", "22,80,8000-8100", 4500, 1500, 1);
+//! RustScanOpenPort open;
+//! while (!rustscan_poll(scan)) {
+//!     while (rustscan_next(scan, &open) == 1) {
+//!         printf("%s:%u\n", open.ip, open.port);
+//!     }
+//!     usleep(100000);
+//! }
+//! while (rustscan_next(scan, &open) == 1) {
+//!     printf("%s:%u\n", open.ip, open.port);
+//! }
+//! rustscan_free(scan);
+//! ```
+use crate::address::parse_targets;
+use crate::port_strategy::parse_ports;
+use crate::scanner::background::BackgroundScan;
+use crate::{ScanTiming, ScannerBuilder};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::net::SocketAddr;
+use std::os::raw::{c_char, c_int};
+use std::time::Duration;
+
+/// Room for the longest IPv6 address and its terminating NUL, like
+/// `INET6_ADDRSTRLEN`.
+pub const IP_LENGTH: usize = 46;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// An open port, mirroring `RustScanOpenPort` in the C header.
+#[repr(C)]
+pub struct OpenPort {
+    /// The address, NUL terminated.
+    pub ip: [c_char; IP_LENGTH],
+    pub port: u16,
+}
+
+impl OpenPort {
+    fn write(&mut self, socket: SocketAddr) {
+        let ip = socket.ip().to_string();
+        self.ip = [0; IP_LENGTH];
+        for (byte, c) in ip.bytes().take(IP_LENGTH - 1).zip(self.ip.iter_mut()) {
+            *c = byte as c_char;
+        }
+        self.port = socket.port();
+    }
+}
+
+/// # Safety
+///
+/// `string` must be NULL or a NUL terminated string.
+unsafe fn str_arg<'a>(string: *const c_char, name: &str) -> Result<Option<&'a str>, String> {
+    if string.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map(Some)
+        .map_err(|_| format!("{name} isn't valid UTF-8"))
+}
+
+/// # Safety
+///
+/// See `rustscan_start`.
+unsafe fn start(
+    targets: *const c_char,
+    ports: *const c_char,
+    batch_size: u32,
+    timeout_ms: u32,
+    tries: u8,
+) -> Result<BackgroundScan, String> {
+    let targets = str_arg(targets, "targets")?.ok_or("no targets given")?;
+    let ips = parse_targets(targets.split(','));
+    if ips.is_empty() {
+        return Err(format!("no addresses found in {targets:?}"));
+    }
+
+    let default = ScanTiming::default();
+    let mut builder = ScannerBuilder::new().targets(ips).timing(ScanTiming {
+        batch_size: match batch_size {
+            0 => default.batch_size,
+            size => size as usize,
+        },
+        timeout: match timeout_ms {
+            0 => default.timeout,
+            timeout => Duration::from_millis(u64::from(timeout)),
+        },
+        tries,
+    });
+    if let Some(ports) = str_arg(ports, "ports")?.filter(|ports| !ports.trim().is_empty()) {
+        builder = builder.ports(parse_ports(ports)?);
+    }
+    BackgroundScan::start(builder.build()).map_err(|e| format!("could not start the scan: {e}"))
+}
+
+/// Starts scanning `targets`, comma separated IPs, CIDRs or hosts, on
+/// `ports`, like `22,80,8000-8100`, or on every port if it's NULL or empty.
+/// A `batch_size` or `timeout_ms` of 0 picks the command line's default.
+/// Returns NULL if the arguments are invalid, see `rustscan_last_error`.
+///
+/// # Safety
+///
+/// `targets` and `ports` must be NULL or NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rustscan_start(
+    targets: *const c_char,
+    ports: *const c_char,
+    batch_size: u32,
+    timeout_ms: u32,
+    tries: u8,
+) -> *mut BackgroundScan {
+    match start(targets, ports, batch_size, timeout_ms, tries) {
+        Ok(scan) => Box::into_raw(Box::new(scan)),
+        Err(e) => {
+            set_last_error(&e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Returns 1 once the scan is done, 0 while it runs.
+///
+/// # Safety
+///
+/// `scan` must come from `rustscan_start` and not be freed.
+#[no_mangle]
+pub unsafe extern "C" fn rustscan_poll(scan: *mut BackgroundScan) -> c_int {
+    match scan.as_mut() {
+        Some(scan) => c_int::from(scan.is_done()),
+        None => {
+            set_last_error("scan is NULL");
+            -1
+        }
+    }
+}
+
+/// Writes an open port not handed out yet to `open` and returns 1, or
+/// returns 0 if there's none right now.
+///
+/// # Safety
+///
+/// `scan` must come from `rustscan_start` and not be freed, `open` must
+/// point to a `RustScanOpenPort`.
+#[no_mangle]
+pub unsafe extern "C" fn rustscan_next(scan: *mut BackgroundScan, open: *mut OpenPort) -> c_int {
+    let (Some(scan), Some(open)) = (scan.as_mut(), open.as_mut()) else {
+        set_last_error("scan or open is NULL");
+        return -1;
+    };
+    match scan.next_open() {
+        Some(socket) => {
+            open.write(socket);
+            1
+        }
+        None => 0,
+    }
+}
+
+/// Stops the scan once the probes in flight are done, the ports found so
+/// far are still handed out by `rustscan_next`.
+///
+/// # Safety
+///
+/// `scan` must come from `rustscan_start` and not be freed.
+#[no_mangle]
+pub unsafe extern "C" fn rustscan_cancel(scan: *mut BackgroundScan) {
+    if let Some(scan) = scan.as_ref() {
+        scan.cancel_token().cancel();
+    }
+}
+
+/// Cancels the scan, waits for it to stop and frees it.
+///
+/// # Safety
+///
+/// `scan` must be NULL or come from `rustscan_start`, and not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rustscan_free(scan: *mut BackgroundScan) {
+    if scan.is_null() {
+        return;
+    }
+    drop(Box::from_raw(scan));
+}
+
+/// Why the last call on this thread failed, or NULL. The string stays valid
+/// until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn rustscan_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_ports, rustscan_free, rustscan_last_error, rustscan_next, rustscan_poll,
+        rustscan_start, OpenPort, IP_LENGTH,
+    };
+    use std::ffi::{CStr, CString};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn ports_and_ranges() {
+        assert_eq!(
+            parse_ports("22, 80,8000-8002").unwrap(),
+            [22, 80, 8000, 8001, 8002]
+        );
+        assert_eq!(parse_ports("0").unwrap(), [0]);
+        assert!(parse_ports("90-80").is_err());
+        assert!(parse_ports("http").is_err());
+    }
+
+    #[test]
+    fn scans_through_the_c_interface() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let targets = CString::new("127.0.0.1").unwrap();
+        let ports = CString::new(format!("{port},{}", port.wrapping_add(1))).unwrap();
+
+        unsafe {
+            let scan = rustscan_start(targets.as_ptr(), ports.as_ptr(), 10, 500, 1);
+            assert!(!scan.is_null());
+            let started = Instant::now();
+            while rustscan_poll(scan) == 0 {
+                assert!(started.elapsed() < Duration::from_secs(10));
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            let mut open = OpenPort {
+                ip: [0; IP_LENGTH],
+                port: 0,
+            };
+            let mut found = Vec::new();
+            while rustscan_next(scan, &mut open) == 1 {
+                let ip = CStr::from_ptr(open.ip.as_ptr())
+                    .to_str()
+                    .unwrap()
+                    .to_owned();
+                found.push((ip, open.port));
+            }
+            rustscan_free(scan);
+            assert_eq!(found, [("127.0.0.1".to_owned(), port)]);
+
+            let nothing = CString::new(",").unwrap();
+            assert!(rustscan_start(nothing.as_ptr(), ports.as_ptr(), 0, 0, 1).is_null());
+            assert!(CStr::from_ptr(rustscan_last_error())
+                .to_str()
+                .unwrap()
+                .contains("no addresses"));
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! The history of past scans, for `rustscan history` and `rustscan rerun`.
+//!
+//! Every scan appends its manifest, along with the directory it ran in and
+//! the files it wrote, as a JSON line to `rustscan/history.jsonl` in the
+//! local data directory, unless run with `--no-history`. Like shell history,
+//! scans are numbered by their position in the file:
+//!
+//! ```text
+//! rustscan history
+//!    1  2024-05-01 09:00  3 open ports on 1 of 1 hosts  -a 10.0.0.5 --ulimit 5000
+//! rustscan rerun 1
+//! ```
+use crate::export::manifest::Manifest;
+use crate::input::Opts;
+use crate::{detail, output, warning};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, Utc};
+use serde_derive::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A scan in the history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub scan_id: String,
+    pub version: String,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    /// The command line, the binary first.
+    pub args: Vec<String>,
+    /// The working directory, where a rerun starts so relative paths hold.
+    pub dir: Option<PathBuf>,
+    pub target_count: usize,
+    pub hosts_up: usize,
+    pub open_ports: usize,
+    /// Reports and manifests the scan wrote.
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+}
+
+impl Entry {
+    pub fn new(manifest: &Manifest<'_>) -> Self {
+        Self {
+            scan_id: manifest.scan_id.to_owned(),
+            version: manifest.version.to_owned(),
+            started: manifest.started,
+            finished: manifest.finished,
+            args: manifest.args.to_vec(),
+            dir: std::env::current_dir().ok(),
+            target_count: manifest.target_count,
+            hosts_up: manifest.hosts_up,
+            open_ports: manifest.open_ports,
+            files: Vec::new(),
+        }
+    }
+
+    /// The arguments after the binary, quoted where they need to be.
+    pub fn command_line(&self) -> String {
+        self.args
+            .iter()
+            .skip(1)
+            .map(|arg| {
+                if arg.is_empty() || arg.contains(char::is_whitespace) {
+                    format!("'{arg}'")
+                } else {
+                    arg.clone()
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+/// Where the history is kept.
+pub fn path() -> Result<PathBuf> {
+    dirs::data_local_dir()
+        .map(|dir| dir.join("rustscan").join("history.jsonl"))
+        .ok_or_else(|| anyhow!("Could not infer the local data directory"))
+}
+
+/// Adds `entry` to the history.
+pub fn record(entry: &Entry) -> Result<()> {
+    append(&path()?, entry)
+}
+
+/// Appends `entry` to the history at `path`. A single write of a line, so
+/// scans finishing at the same time don't interleave theirs.
+pub fn append(path: &Path, entry: &Entry) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|e| anyhow!("Could not write {}: {e}", path.display()))
+}
+
+/// The scans in the history at `path`, oldest first. Lines that don't parse,
+/// cut short by a crash for example, are skipped but keep their number.
+pub fn read(path: &Path) -> Result<Vec<Option<Entry>>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content
+            .lines()
+            .map(|line| serde_json::from_str(line).ok())
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(anyhow!("Could not read {}: {e}", path.display())),
+    }
+}
+
+/// The scan numbered `id`, or the last one.
+pub fn find(entries: &[Option<Entry>], id: Option<usize>) -> Result<&Entry> {
+    match id {
+        Some(id) => id
+            .checked_sub(1)
+            .and_then(|index| entries.get(index))
+            .ok_or_else(|| anyhow!("There is no scan {id} in the history, see 'rustscan history'"))?
+            .as_ref()
+            .ok_or_else(|| anyhow!("Scan {id} in the history can't be read")),
+        None => entries
+            .iter()
+            .rev()
+            .flatten()
+            .next()
+            .ok_or_else(|| anyhow!("The history is empty")),
+    }
+}
+
+/// `rustscan history`: prints the last `limit` scans, returns whether the
+/// history could be read.
+pub fn list(limit: usize, opts: &Opts) -> bool {
+    let entries = match path().and_then(|path| read(&path)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warning!(format!("{e}"), false, opts.accessible);
+            return false;
+        }
+    };
+    if entries.is_empty() {
+        detail!("No scans in the history yet.", false, opts.accessible);
+    }
+    let skip = entries.len().saturating_sub(limit);
+    for (index, entry) in entries.iter().enumerate().skip(skip) {
+        let Some(entry) = entry else {
+            continue;
+        };
+        output!(
+            format!(
+                "{:>4}  {}  {} open ports on {} of {} hosts  {}",
+                index + 1,
+                entry.started.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                entry.open_ports,
+                entry.hosts_up,
+                entry.target_count,
+                entry.command_line()
+            ),
+            false,
+            opts.accessible
+        );
+        for file in &entry.files {
+            detail!(format!("      {}", file.display()), false, opts.accessible);
+        }
+    }
+    true
+}
+
+/// `rustscan rerun`: runs the scan numbered `id`, or the last one, again
+/// with the same arguments and in the same directory. Returns whether it
+/// succeeded.
+pub fn rerun(id: Option<usize>, opts: &Opts) -> bool {
+    let rerun = path().and_then(|path| {
+        let entries = read(&path)?;
+        let entry = find(&entries, id)?;
+        detail!(
+            format!("Running again: rustscan {}", entry.command_line()),
+            false,
+            opts.accessible
+        );
+        let mut command = Command::new(std::env::current_exe()?);
+        command.args(entry.args.iter().skip(1));
+        if let Some(dir) = entry.dir.as_ref().filter(|dir| dir.is_dir()) {
+            command.current_dir(dir);
+        }
+        Ok(command.status()?)
+    });
+    match rerun {
+        Ok(status) => status.success(),
+        Err(e) => {
+            warning!(format!("{e}"), false, opts.accessible);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append, find, read, Entry};
+    use crate::export::ScanReport;
+    use chrono::Utc;
+    use std::fs;
+
+    fn entry(args: &[&str]) -> Entry {
+        let mut report = ScanReport::new(Utc::now(), false);
+        report.args = args.iter().map(|arg| (*arg).to_owned()).collect();
+        Entry::new(&report.manifest())
+    }
+
+    #[test]
+    fn history_is_appended_and_numbered() {
+        let path =
+            std::env::temp_dir().join(format!("rustscan-history-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert!(read(&path).unwrap().is_empty());
+        assert!(find(&[], None).is_err());
+
+        append(&path, &entry(&["rustscan", "-a", "10.0.0.1"])).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| std::io::Write::write_all(&mut file, b"{\"scan_id\":\n"))
+            .unwrap();
+        append(&path, &entry(&["rustscan", "-a", "10.0.0.2"])).unwrap();
+
+        let entries = read(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            find(&entries, Some(1)).unwrap().command_line(),
+            "-a 10.0.0.1"
+        );
+        assert!(find(&entries, Some(2)).is_err());
+        assert_eq!(find(&entries, None).unwrap().args[2], "10.0.0.2");
+        assert!(find(&entries, Some(0)).is_err());
+        assert!(find(&entries, Some(4)).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn quotes_arguments_with_spaces() {
+        let entry = entry(&["rustscan", "-a", "10.0.0.1", "--", "-sV --reason"]);
+        assert_eq!(entry.command_line(), "-a 10.0.0.1 -- '-sV --reason'");
+    }
+}
@@ -0,0 +1,170 @@
+//! Shared HTTP client for everything RustScan sends over HTTP(S).
+//!
+//! Update checks, exports and notifications all go through [`HttpClient`]
+//! so they behave the same way behind a proxy: `ALL_PROXY`, `HTTPS_PROXY`
+//! and `HTTP_PROXY` (and their lowercase variants) are honoured, hosts in
+//! `NO_PROXY` are reached directly, and `--no-proxy` ignores all of them.
+use crate::input::Opts;
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+use ureq::{Agent, Proxy};
+
+/// How long a single request may take before it's abandoned.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A blocking HTTP client configured from the user's proxy settings.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    agent: Agent,
+    proxy: Option<String>,
+}
+
+impl HttpClient {
+    /// Builds a client, picking up the proxy from the environment unless
+    /// `use_proxy` is false.
+    pub fn new(use_proxy: bool) -> Self {
+        Self::new_with_timeout(use_proxy, DEFAULT_TIMEOUT)
+    }
+
+    /// Like [`HttpClient::new`], with a custom timeout for every request.
+    pub fn new_with_timeout(use_proxy: bool, timeout: Duration) -> Self {
+        let proxy = if use_proxy {
+            Proxy::try_from_env()
+        } else {
+            None
+        };
+        Self::with_proxy(proxy, timeout)
+    }
+
+    /// Builds a client honouring `--no-proxy`.
+    pub fn from_opts(opts: &Opts) -> Self {
+        Self::new(!opts.no_proxy)
+    }
+
+    fn with_proxy(proxy: Option<Proxy>, timeout: Duration) -> Self {
+        let description = proxy
+            .as_ref()
+            .map(|proxy| format!("{}:{}", proxy.host(), proxy.port()));
+        let agent = Agent::config_builder()
+            .proxy(proxy)
+            .timeout_global(Some(timeout))
+            .user_agent(concat!("RustScan/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .into();
+        Self {
+            agent,
+            proxy: description,
+        }
+    }
+
+    /// The `host:port` of the proxy requests go through, if any.
+    pub fn proxy(&self) -> Option<&str> {
+        self.proxy.as_deref()
+    }
+
+    /// Sends a GET request and returns the response body.
+    pub fn get(&self, url: &str) -> Result<String> {
+        self.agent
+            .get(url)
+            .call()
+            .and_then(|mut response| response.body_mut().read_to_string())
+            .map_err(|e| anyhow!("GET {url} failed: {e}"))
+    }
+
+    /// Sends `body` as a POST request with the given extra headers and
+    /// returns the response body.
+    pub fn post(
+        &self,
+        url: &str,
+        content_type: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<String> {
+        let mut request = self.agent.post(url).header("Content-Type", content_type);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request
+            .send(body)
+            .and_then(|mut response| response.body_mut().read_to_string())
+            .map_err(|e| anyhow!("POST {url} failed: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpClient;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+    use ureq::Proxy;
+
+    /// Serves a single request with `body`, acting as a proxy if it's
+    /// asked to CONNECT first. Returns the first request line seen.
+    fn serve_once(listener: TcpListener, body: &'static str) -> thread::JoinHandle<String> {
+        fn read_head(reader: &mut BufReader<std::net::TcpStream>) -> String {
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            request_line
+        }
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let request_line = read_head(&mut reader);
+            if request_line.starts_with("CONNECT") {
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 Connection established\r\n\r\n"
+                )
+                .unwrap();
+                read_head(&mut reader);
+            }
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            request_line
+        })
+    }
+
+    #[test]
+    fn get_without_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/version.json", listener.local_addr().unwrap());
+        let server = serve_once(listener, "{}");
+
+        let client = HttpClient::new(false);
+        assert_eq!(client.proxy(), None);
+        assert_eq!(client.get(&url).unwrap(), "{}");
+        assert_eq!(server.join().unwrap().trim(), "GET /version.json HTTP/1.1");
+    }
+
+    #[test]
+    fn requests_go_through_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+        let server = serve_once(listener, "proxied");
+
+        let proxy = Proxy::new(&format!("http://{proxy_addr}")).unwrap();
+        let client = HttpClient::with_proxy(Some(proxy), Duration::from_secs(5));
+        assert_eq!(client.proxy(), Some(proxy_addr.to_string().as_str()));
+        assert_eq!(
+            client.get("http://rustscan.invalid/version.json").unwrap(),
+            "proxied"
+        );
+        // The proxy is asked to tunnel to the target host.
+        assert_eq!(
+            server.join().unwrap().trim(),
+            "CONNECT rustscan.invalid:80 HTTP/1.1"
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Discovers the endpoints a Kubernetes cluster declares as reachable.
+//!
+//! `kubectl` is run with the given kubeconfig to list nodes, services and
+//! pods, from which NodePorts (on every node), LoadBalancer ingresses and
+//! the ports of `hostNetwork` pods are derived. After the scan, declared
+//! endpoints that weren't found open are reported, so operators can compare
+//! actual reachability with what the cluster declares.
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::Path;
+use std::process::Command;
+
+/// A port the cluster declares as reachable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    /// An IP, or a hostname for some LoadBalancer ingresses.
+    pub host: String,
+    pub port: u16,
+    /// What declared it, for example `NodePort default/web`.
+    pub source: String,
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{} ({})", self.host, self.port, self.source)
+    }
+}
+
+/// Lists the endpoints of the cluster in `kubeconfig`.
+#[cfg(not(tarpaulin_include))]
+pub fn discover(kubeconfig: &Path) -> Result<Vec<Endpoint>> {
+    let output = Command::new("kubectl")
+        .arg("--kubeconfig")
+        .arg(kubeconfig)
+        .args([
+            "get",
+            "nodes,services,pods",
+            "--all-namespaces",
+            "-o",
+            "json",
+        ])
+        .output()
+        .map_err(|e| anyhow!("Could not run kubectl, is it installed? {e}"))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "kubectl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_endpoints(&serde_json::from_slice(&output.stdout)?))
+}
+
+/// Extracts the endpoints from the `kubectl get -o json` list of nodes,
+/// services and pods.
+pub fn parse_endpoints(list: &Value) -> Vec<Endpoint> {
+    let items: Vec<&Value> = list["items"].as_array().into_iter().flatten().collect();
+    let of_kind = |kind: &'static str| items.iter().filter(move |item| item["kind"] == kind);
+
+    let node_ips: Vec<&str> = of_kind("Node").filter_map(|node| node_ip(node)).collect();
+    let mut endpoints = Vec::new();
+
+    for service in of_kind("Service") {
+        let name = qualified_name(service);
+        for port in tcp_ports(&service["spec"]["ports"]) {
+            if let Some(node_port) = port_number(&port["nodePort"]) {
+                endpoints.extend(node_ips.iter().map(|ip| Endpoint {
+                    host: (*ip).to_owned(),
+                    port: node_port,
+                    source: format!("NodePort {name}"),
+                }));
+            }
+            let Some(service_port) = port_number(&port["port"]) else {
+                continue;
+            };
+            let ingresses = service["status"]["loadBalancer"]["ingress"]
+                .as_array()
+                .into_iter()
+                .flatten();
+            for ingress in ingresses {
+                if let Some(host) = ingress["ip"].as_str().or(ingress["hostname"].as_str()) {
+                    endpoints.push(Endpoint {
+                        host: host.to_owned(),
+                        port: service_port,
+                        source: format!("LoadBalancer {name}"),
+                    });
+                }
+            }
+        }
+    }
+
+    for pod in of_kind("Pod").filter(|pod| pod["spec"]["hostNetwork"] == true) {
+        let Some(ip) = pod["status"]["podIP"].as_str() else {
+            continue;
+        };
+        let name = qualified_name(pod);
+        let containers = pod["spec"]["containers"].as_array().into_iter().flatten();
+        for port in containers.flat_map(|container| tcp_ports(&container["ports"])) {
+            if let Some(container_port) = port_number(&port["containerPort"]) {
+                endpoints.push(Endpoint {
+                    host: ip.to_owned(),
+                    port: container_port,
+                    source: format!("hostNetwork pod {name}"),
+                });
+            }
+        }
+    }
+
+    endpoints
+}
+
+/// The address a node is reached on, its external IP if it has one.
+fn node_ip(node: &Value) -> Option<&str> {
+    let addresses: Vec<&Value> = node["status"]["addresses"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .collect();
+    ["ExternalIP", "InternalIP"].iter().find_map(|kind| {
+        addresses
+            .iter()
+            .find(|address| address["type"] == *kind)
+            .and_then(|address| address["address"].as_str())
+    })
+}
+
+fn qualified_name(item: &Value) -> String {
+    format!(
+        "{}/{}",
+        item["metadata"]["namespace"].as_str().unwrap_or("default"),
+        item["metadata"]["name"].as_str().unwrap_or_default()
+    )
+}
+
+/// Port entries using TCP, which is the default protocol.
+fn tcp_ports(ports: &Value) -> impl Iterator<Item = &Value> {
+    ports
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|port| port["protocol"].as_str().unwrap_or("TCP") == "TCP")
+}
+
+fn port_number(value: &Value) -> Option<u16> {
+    value.as_u64().and_then(|port| u16::try_from(port).ok())
+}
+
+/// Endpoints whose port wasn't found open, resolving hostnames first.
+pub fn unreachable<'a>(
+    endpoints: &'a [Endpoint],
+    open_ports: &HashMap<IpAddr, Vec<u16>>,
+) -> Vec<&'a Endpoint> {
+    endpoints
+        .iter()
+        .filter(|endpoint| {
+            let ips: Vec<IpAddr> = match endpoint.host.parse::<IpAddr>() {
+                Ok(ip) => vec![ip],
+                Err(_) => (endpoint.host.as_str(), 0)
+                    .to_socket_addrs()
+                    .map(|addrs| addrs.map(|addr| addr.ip()).collect())
+                    .unwrap_or_default(),
+            };
+            !ips.iter().any(|ip| {
+                open_ports
+                    .get(ip)
+                    .is_some_and(|ports| ports.contains(&endpoint.port))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_endpoints, unreachable, Endpoint};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn cluster() -> serde_json::Value {
+        json!({"kind": "List", "items": [
+            {"kind": "Node", "metadata": {"name": "node-1"}, "status": {"addresses": [
+                {"type": "InternalIP", "address": "10.0.0.1"},
+                {"type": "ExternalIP", "address": "203.0.113.1"}
+            ]}},
+            {"kind": "Node", "metadata": {"name": "node-2"}, "status": {"addresses": [
+                {"type": "InternalIP", "address": "10.0.0.2"}
+            ]}},
+            {"kind": "Service", "metadata": {"name": "web", "namespace": "shop"},
+             "spec": {"type": "LoadBalancer", "ports": [
+                {"port": 80, "nodePort": 30080, "protocol": "TCP"},
+                {"port": 53, "nodePort": 30053, "protocol": "UDP"}
+             ]},
+             "status": {"loadBalancer": {"ingress": [{"ip": "198.51.100.1"}]}}},
+            {"kind": "Service", "metadata": {"name": "internal", "namespace": "shop"},
+             "spec": {"type": "ClusterIP", "ports": [{"port": 8080}]}},
+            {"kind": "Pod", "metadata": {"name": "agent", "namespace": "kube-system"},
+             "spec": {"hostNetwork": true, "containers": [{"ports": [{"containerPort": 9100}]}]},
+             "status": {"podIP": "10.0.0.2"}},
+            {"kind": "Pod", "metadata": {"name": "app", "namespace": "shop"},
+             "spec": {"containers": [{"ports": [{"containerPort": 8080}]}]},
+             "status": {"podIP": "10.1.0.5"}}
+        ]})
+    }
+
+    #[test]
+    fn parse_cluster_endpoints() {
+        let endpoints: Vec<String> = parse_endpoints(&cluster())
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            endpoints,
+            vec![
+                "203.0.113.1:30080 (NodePort shop/web)",
+                "10.0.0.2:30080 (NodePort shop/web)",
+                "198.51.100.1:80 (LoadBalancer shop/web)",
+                "10.0.0.2:9100 (hostNetwork pod kube-system/agent)",
+            ]
+        );
+    }
+
+    #[test]
+    fn closed_endpoints_are_unreachable() {
+        let endpoints = vec![
+            Endpoint {
+                host: "10.0.0.2".to_owned(),
+                port: 30080,
+                source: "NodePort shop/web".to_owned(),
+            },
+            Endpoint {
+                host: "10.0.0.2".to_owned(),
+                port: 9100,
+                source: "hostNetwork pod kube-system/agent".to_owned(),
+            },
+        ];
+        let mut open_ports = HashMap::new();
+        open_ports.insert("10.0.0.2".parse().unwrap(), vec![9100]);
+        assert_eq!(unreachable(&endpoints, &open_ports), vec![&endpoints[0]]);
+    }
+}
@@ -0,0 +1,254 @@
+//! Lenient reading of the text files RustScan is handed: host lists, script
+//! headers and config files.
+//!
+//! They come from other tools, editors and operating systems, so instead of
+//! giving up on the first odd byte, or dropping the file without a word,
+//! every line is read on its own:
+//!
+//! - a UTF-8 byte order mark at the start of the file is dropped,
+//! - `\r\n` line endings are read as `\n`,
+//! - a line that isn't valid UTF-8, or is longer than [`MAX_LINE_LENGTH`],
+//!   is skipped with an [`Issue`] naming it. Long lines are never read into
+//!   memory whole.
+//!
+//! The parsers built on it are fuzzed with the targets in `fuzz/`:
+//!
+//! ```text
+//! cargo +nightly fuzz run script_header
+//! ```
+use crate::config::Issue;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+/// Lines longer than this many bytes are skipped.
+pub const MAX_LINE_LENGTH: usize = 1 << 20;
+
+const BOM: &str = "\u{feff}";
+
+/// A line of a file, without its line ending.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    /// Counted from 1, like editors do.
+    pub number: usize,
+    pub text: String,
+}
+
+/// The lines of a file, see [`lines`].
+#[derive(Debug)]
+pub struct Lines<R> {
+    reader: R,
+    file: PathBuf,
+    number: usize,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+/// Reads `reader` line by line. Lines that can't be used come out as
+/// issues, `file` is the path they name.
+pub fn lines<R: BufRead>(reader: R, file: &Path) -> Lines<R> {
+    Lines {
+        reader,
+        file: file.to_path_buf(),
+        number: 0,
+        buffer: Vec::new(),
+        done: false,
+    }
+}
+
+/// Reads `reader` whole, like [`std::fs::read_to_string`], but with the
+/// lines that can't be used left empty, so the others keep their numbers.
+pub fn read_text<R: BufRead>(reader: R, file: &Path) -> (String, Vec<Issue>) {
+    let mut text = String::new();
+    let mut issues = Vec::new();
+    for line in lines(reader, file) {
+        match line {
+            Ok(line) => text.push_str(&line.text),
+            Err(issue) => issues.push(issue),
+        }
+        text.push('\n');
+    }
+    (text, issues)
+}
+
+impl<R: BufRead> Lines<R> {
+    fn issue(&self, message: String, fatal: bool) -> Issue {
+        Issue {
+            file: self.file.clone(),
+            line: Some(self.number),
+            key: String::new(),
+            message,
+            suggestion: None,
+            fatal,
+        }
+    }
+
+    /// Reads past the rest of a line that's too long.
+    fn skip_line(&mut self) -> io::Result<()> {
+        loop {
+            let available = self.reader.fill_buf()?;
+            if available.is_empty() {
+                return Ok(());
+            }
+            if let Some(end) = available.iter().position(|byte| *byte == b'\n') {
+                self.reader.consume(end + 1);
+                return Ok(());
+            }
+            let length = available.len();
+            self.reader.consume(length);
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Lines<R> {
+    type Item = Result<Line, Issue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        self.number += 1;
+        self.buffer.clear();
+        // Room for the longest line and its `\r\n`.
+        let limit = MAX_LINE_LENGTH as u64 + 2;
+        let read = io::Read::take(&mut self.reader, limit).read_until(b'\n', &mut self.buffer);
+        match read {
+            Ok(0) => {
+                self.done = true;
+                return None;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                self.done = true;
+                return Some(Err(self.issue(format!("could not be read: {e}"), true)));
+            }
+        }
+
+        let complete = self.buffer.ends_with(b"\n") || self.buffer.len() < limit as usize;
+        if !complete {
+            if let Err(e) = self.skip_line() {
+                self.done = true;
+                return Some(Err(self.issue(format!("could not be read: {e}"), true)));
+            }
+        }
+        if self.buffer.ends_with(b"\n") {
+            self.buffer.pop();
+            if self.buffer.ends_with(b"\r") {
+                self.buffer.pop();
+            }
+        }
+        if !complete || self.buffer.len() > MAX_LINE_LENGTH {
+            let message = format!("a line longer than {MAX_LINE_LENGTH} bytes");
+            return Some(Err(self.issue(message, false)));
+        }
+
+        match String::from_utf8(std::mem::take(&mut self.buffer)) {
+            Ok(mut text) => {
+                if self.number == 1 && text.starts_with(BOM) {
+                    text.drain(..BOM.len());
+                }
+                Some(Ok(Line {
+                    number: self.number,
+                    text,
+                }))
+            }
+            Err(e) => {
+                let message = format!(
+                    "a line that isn't valid UTF-8, from byte {}",
+                    e.utf8_error().valid_up_to() + 1
+                );
+                Some(Err(self.issue(message, false)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lines, read_text, Line, MAX_LINE_LENGTH};
+    use std::io::{BufRead, BufReader, Cursor};
+    use std::path::Path;
+
+    fn read(bytes: &[u8]) -> Vec<Result<String, String>> {
+        lines(Cursor::new(bytes), Path::new("hosts.txt"))
+            .map(|line| line.map(|line| line.text).map_err(|e| e.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn boms_and_line_endings() {
+        assert_eq!(
+            read(b"\xef\xbb\xbf10.0.0.1\r\n10.0.0.2\n\r\n\xef\xbb\xbf10.0.0.3"),
+            [
+                Ok("10.0.0.1".to_owned()),
+                Ok("10.0.0.2".to_owned()),
+                Ok(String::new()),
+                // Only at the start of the file.
+                Ok("\u{feff}10.0.0.3".to_owned())
+            ]
+        );
+        assert!(read(b"").is_empty());
+        assert_eq!(read(b"\n"), [Ok(String::new())]);
+    }
+
+    #[test]
+    fn skips_invalid_and_long_lines() {
+        let mut bytes = b"10.0.0.1\nscanme.\xff.org\n".to_vec();
+        bytes.extend(vec![b'a'; MAX_LINE_LENGTH * 3]);
+        bytes.extend(b"\n10.0.0.2\n");
+        assert_eq!(
+            read(&bytes),
+            [
+                Ok("10.0.0.1".to_owned()),
+                Err("hosts.txt:2: a line that isn't valid UTF-8, from byte 8".to_owned()),
+                Err(format!(
+                    "hosts.txt:3: a line longer than {MAX_LINE_LENGTH} bytes"
+                )),
+                Ok("10.0.0.2".to_owned()),
+            ]
+        );
+
+        let longest = "a".repeat(MAX_LINE_LENGTH);
+        let read_back = read(format!("{longest}\r\n{longest}").as_bytes());
+        assert_eq!(read_back, [Ok(longest.clone()), Ok(longest)]);
+
+        let (text, issues) = read_text(Cursor::new(&bytes), Path::new("hosts.txt"));
+        assert_eq!(text, "10.0.0.1\n\n\n10.0.0.2\n");
+        assert_eq!(issues.len(), 2);
+    }
+
+    #[test]
+    fn survives_random_bytes() {
+        // xorshift, so failures can be replayed.
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..200 {
+            let mut bytes = Vec::new();
+            for _ in 0..(state % 4096) {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                // Mostly text, with line endings and stray bytes.
+                bytes.push(match state % 16 {
+                    0 => b'\n',
+                    1 => b'\r',
+                    2 => (state >> 8) as u8,
+                    _ => b'0' + (state >> 8) as u8 % 75,
+                });
+            }
+            // Small buffers, so lines are split across reads.
+            let reader = BufReader::with_capacity(7, Cursor::new(&bytes));
+            let mut previous = 0;
+            for line in lines(reader, Path::new("fuzz")) {
+                let number = match line {
+                    Ok(Line { number, text }) => {
+                        assert!(!text.contains('\n'));
+                        number
+                    }
+                    Err(issue) => issue.line.unwrap(),
+                };
+                assert_eq!(number, previous + 1);
+                previous = number;
+            }
+            assert_eq!(previous, Cursor::new(&bytes).split(b'\n').count());
+        }
+    }
+}
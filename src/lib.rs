@@ -4,45 +4,77 @@
 //! ## Example: perform a scan against localhost
 //!
 //! The core scanning behaviour is managed by
-//! [`Scanner`](crate::scanner::Scanner) which in turn requires a
-//! [`PortStrategy`](crate::port_strategy::PortStrategy):
+//! [`Scanner`](crate::scanner::Scanner), which
+//! [`ScannerBuilder`] builds without any of the
+//! command line options:
 //!
 //! ```rust
 //! use async_std::task::block_on;
-//! use std::{net::IpAddr, time::Duration};
+//! use std::time::Duration;
 //!
-//! use rustscan::input::{PortRange, ScanOrder};
-//! use rustscan::port_strategy::PortStrategy;
-//! use rustscan::scanner::Scanner;
+//! use rustscan::input::ScanOrder;
+//! use rustscan::{ScanTiming, ScannerBuilder};
 //!
 //! fn main() {
-//!     let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
-//!     let range = PortRange {
-//!         start: 1,
-//!         end: 1_000,
-//!     };
-//!     let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random); // can be serial, random or manual https://github.com/RustScan/RustScan/blob/master/src/port_strategy/mod.rs
-//!     let scanner = Scanner::new(
-//!         &addrs, // the addresses to scan
-//!         10, // batch_size is how many ports at a time should be scanned
-//!         Duration::from_millis(100), //T imeout is the time RustScan should wait before declaring a port closed. As datatype Duration.
-//!         1, // Tries, how many retries should RustScan do?
-//!         true, // greppable is whether or not RustScan should print things, or wait until the end to print only the ip
-//!         strategy, // the port strategy used
-//!         true, // accessible, should the output be A11Y compliant?
-//!         vec![9000], // What ports should RustScan exclude?
-//!         false, // is this a UDP scan?
-//!     );
+//!     let scanner = ScannerBuilder::new()
+//!         .targets(["127.0.0.1".parse().unwrap()]) // the addresses to scan
+//!         .range(1, 1_000) // or .ports([22, 80, 443])
+//!         .order(ScanOrder::Serial) // random by default
+//!         .timing(ScanTiming {
+//!             batch_size: 10, // how many ports at a time should be scanned
+//!             timeout: Duration::from_millis(100), // how long until a port is closed
+//!             tries: 1, // how many tries before a port is closed
+//!         })
+//!         .exclude_ports([9000]) // what ports should RustScan exclude?
+//!         .build();
 //!
 //!     let scan_result = block_on(scanner.run());
 //!
 //!     println!("{:?}", scan_result);
 //! }
 //! ```
+//!
+//! ## Features
+//!
+//! The heavier parts of RustScan are behind cargo features, all on by
+//! default. The `rustscan` binary needs all of them but `tls`; for a slim
+//! library, turn off the defaults and pick what's needed:
+//!
+//! - `scripts`: running scripts on open ports, [`scripts`].
+//! - `raw-sockets`: SYN scans and [`notrack`].
+//! - `reports`: HTML report templates, and compressed or encrypted result
+//!   files.
+//! - `tls`: TLS probes, [`tls`].
+//!
+//! `ffi`, off by default, adds C bindings to build RustScan as a shared
+//! library, see `ffi`. `ssh`, off by default too as it links libssh2 and
+//! OpenSSL, scans through SSH jump hosts, see [`scanner::ssh`].
+//!
+//! ```toml
+//! rustscan = { version = "2", default-features = false }
+//! ```
+//!
+//! ## WebAssembly
+//!
+//! Without the default features, the library builds for `wasm32`, so a web
+//! UI can check scopes and render reports with the same code as the command
+//! line:
+//!
+//! ```text
+//! cargo build --lib --target wasm32-unknown-unknown --no-default-features
+//! ```
+//!
+//! That build has what needs no sockets: [`address`] parsing, where only IPs
+//! and CIDRs are accepted since hostnames can't be resolved, [`input`],
+//! [`export`] with its JSON results and HTML reports, [`baseline`] diffs and
+//! [`policy`] checks. Scanning, DNS and the modules that talk to other hosts,
+//! like [`daemon`] or [`update`], are left out.
 #![allow(clippy::needless_doctest_main)]
 
 pub mod tui;
 
+pub mod units;
+
 pub mod input;
 
 pub mod scanner;
@@ -51,8 +83,89 @@ pub mod port_strategy;
 
 pub mod benchmark;
 
+#[cfg(feature = "scripts")]
 pub mod scripts;
 
 pub mod address;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod system;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod doctor;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod self_audit;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod daemon;
+
+pub mod explain;
+
+pub mod config;
+
+pub mod lenient;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod http;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod update;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod plugins;
+
+pub mod cloud;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod k8s;
+
+pub mod export;
+
+pub mod upload;
+
+pub mod baseline;
+
+pub mod policy;
+
+pub mod alerts;
+
+pub mod neighbors;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod wol;
+
+#[cfg(feature = "raw-sockets")]
+pub mod notrack;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod netns;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod privileges;
+
+pub mod template;
+
+pub mod history;
+
+#[cfg(feature = "tls")]
+pub mod tls;
+
+pub mod nuclei;
+
+pub mod vulns;
+
+pub mod creds;
+
+pub mod services;
+
+pub mod ot;
+
 pub mod generated;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use scanner::builder::{ScanTiming, ScannerBuilder};
+pub use scanner::cancel::CancelToken;
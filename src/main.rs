@@ -2,21 +2,64 @@
 #![warn(clippy::pedantic)]
 #![allow(clippy::doc_markdown, clippy::if_not_else, clippy::non_ascii_literal)]
 
+use rustscan::alerts;
+use rustscan::baseline::Baseline;
 use rustscan::benchmark::{Benchmark, NamedTimer};
-use rustscan::input::{self, Config, Opts, ScriptsRequired};
-use rustscan::port_strategy::PortStrategy;
+use rustscan::export::file::{self, FileOptions};
+use rustscan::export::html::ReportTemplate;
+use rustscan::export::ndjson::{Event, EventStream};
+use rustscan::export::redact::{self, Redactor};
+use rustscan::export::rollup::Rollup;
+use rustscan::export::view::{PortState, View};
+use rustscan::export::{annotate, manifest, merge, HostReport, OutputTarget, ScanReport};
+use rustscan::http::HttpClient;
+use rustscan::input::{
+    self, Commands, Config, ConfigAction, Opts, ScanMode, ScriptsAction, ScriptsRequired,
+    TcpEngine, TemplateAction, UdpEngine,
+};
+use rustscan::neighbors::{self, Device};
+use rustscan::netns;
+use rustscan::notrack::{self, NotrackRules};
+use rustscan::plugins::{self, Plugin};
+use rustscan::policy::{self, Policy};
+use rustscan::port_strategy::{self, PortStrategy};
+use rustscan::privileges::{self, Feature};
+use rustscan::scanner::controls::Controls;
+use rustscan::scanner::payload::ProbeResponse;
+use rustscan::scanner::priority::TargetPriorities;
+use rustscan::scanner::progress::Progress;
+use rustscan::scanner::shards::{self, ResultShards};
+#[cfg(feature = "ssh")]
+use rustscan::scanner::ssh::SshTunnel;
+use rustscan::scanner::stats::ProbeStats;
+use rustscan::scanner::syn::SynProbe;
+use rustscan::scanner::wildcard::WildcardPolicy;
 use rustscan::scanner::Scanner;
-use rustscan::scripts::{init_scripts, Script, ScriptFile};
+use rustscan::scripts::nmap_output::NmapOutput;
+use rustscan::scripts::{self, init_scripts, Invocation, Script, ScriptFile, ScriptPhase};
+use rustscan::system::SystemLimits;
+#[cfg(feature = "tls")]
+use rustscan::tls;
+use rustscan::units::{self, Timing};
+use rustscan::wol::{self, WakeList};
+use rustscan::{
+    cloud, config, creds, daemon, doctor, explain, history, k8s, nuclei, ot, self_audit, services,
+    template, tui, update, upload, vulns,
+};
 use rustscan::{detail, funny_opening, output, warning};
 
 use colorful::{Color, Colorful};
 use futures::executor::block_on;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::io;
 use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::string::ToString;
+use std::sync::Arc;
 use std::time::Duration;
 
-use rustscan::address::parse_addresses;
+use rustscan::address::{self, parse_addresses};
 
 extern crate colorful;
 extern crate dirs;
@@ -25,6 +68,7 @@ extern crate dirs;
 #[cfg(unix)]
 const DEFAULT_FILE_DESCRIPTORS_LIMIT: usize = 8000;
 // Safest batch size based on experimentation
+#[cfg(unix)]
 const AVERAGE_BATCH_SIZE: usize = 3000;
 
 #[macro_use]
@@ -43,193 +87,2141 @@ fn main() {
     let mut rustscan_bench = NamedTimer::start("RustScan");
 
     let mut opts: Opts = Opts::read();
+
+    if let Some(subcommand) = opts.subcommand.clone() {
+        // Subcommands report a broken config file rather than abort on it.
+        if let Ok(config) = Config::try_read(opts.config_path.clone()) {
+            opts.merge(&config);
+        }
+        std::process::exit(run_subcommand(&subcommand, &opts));
+    }
+
     let config = Config::read(opts.config_path.clone());
     opts.merge(&config);
 
+    if !opts.no_config {
+        for issue in config.issues() {
+            warning!(
+                format!("Ignoring {issue} in configuration file."),
+                opts.greppable,
+                opts.accessible
+            );
+        }
+    }
+
+    // Printed as merged, before the steps below derive options from others,
+    // so the file replays through them the same way.
+    if opts.print_effective_config {
+        match Config::effective_toml(&opts) {
+            Ok(toml) => print!("{toml}"),
+            Err(e) => {
+                warning!(
+                    format!("Could not print the configuration: {e}"),
+                    false,
+                    opts.accessible
+                );
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    opts.apply_mode();
+    if let Err(e) = opts.add_stream_outputs() {
+        warning!(e, opts.greppable, opts.accessible);
+        std::process::exit(1);
+    }
+    let other_protocol_ports = opts.split_protocol_ports();
+
     debug!("Main() `opts` arguments are {opts:?}");
+    units::set_raw_durations(opts.raw_durations);
+
+    let skip_scripts = opts.greppable || opts.scripts == ScriptsRequired::None;
+    let events = opts
+        .output
+        .contains(&OutputTarget::Ndjson)
+        .then(|| Arc::new(EventStream::stdout()));
+    if events.is_some() {
+        // Events are the only thing written to stdout.
+        opts.greppable = true;
+    }
+
+    if opts.detach {
+        std::process::exit(i32::from(!daemon::detach(&opts)));
+    }
+
+    if let Some(target) = &opts.explain {
+        std::process::exit(i32::from(!explain::run(target, &opts)));
+    }
+
+    let update_check = update::spawn_check(&opts);
+
+    if opts.system_check {
+        print_system_check(&opts);
+        return;
+    }
+
+    let scripts_to_run: Vec<ScriptFile> = match init_scripts(&opts.scripts) {
+        Ok((scripts_to_run, issues)) => {
+            scripts::report_skipped(&issues, &opts);
+            scripts_to_run
+        }
+        Err(e) => {
+            warning!(
+                format!("Initiating scripts failed!\n{e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    };
+
+    debug!("Scripts initialized {:?}", &scripts_to_run);
+
+    let (generators, scripts_to_run): (Vec<ScriptFile>, Vec<ScriptFile>) = scripts_to_run
+        .into_iter()
+        .partition(ScriptFile::is_generator);
+    for generator in &generators {
+        match generator.generate_targets() {
+            Ok(targets) => {
+                debug!("Generator {:?} produced {targets:?}", generator.path);
+                opts.addresses.extend(targets);
+            }
+            Err(e) => warning!(
+                format!("Target generator {:?} failed: {e}", generator.path),
+                opts.greppable,
+                opts.accessible
+            ),
+        }
+    }
+    let (post_scan_scripts, scripts_to_run): (Vec<ScriptFile>, Vec<ScriptFile>) = scripts_to_run
+        .into_iter()
+        .partition(|script| script.phase == ScriptPhase::PostScan);
+    let (pre_scan_scripts, scripts_to_run): (Vec<ScriptFile>, Vec<ScriptFile>) = scripts_to_run
+        .into_iter()
+        .partition(|script| script.phase == ScriptPhase::PreScan);
+    let (batch_scripts, scripts_to_run): (Vec<ScriptFile>, Vec<ScriptFile>) = scripts_to_run
+        .into_iter()
+        .partition(|script| script.invocation == Invocation::Batch);
+    let (group_scripts, scripts_to_run): (Vec<ScriptFile>, Vec<ScriptFile>) = scripts_to_run
+        .into_iter()
+        .partition(|script| opts.group_scripts && script.group_call_format.is_some());
+    for script in &pre_scan_scripts {
+        match script.run_pre_scan(&opts.addresses) {
+            Ok(output) => detail!(output, opts.greppable, opts.accessible),
+            Err(e) => {
+                warning!(
+                    format!(
+                        "Pre-scan script {:?} failed, not scanning: {e}",
+                        script.path
+                    ),
+                    opts.greppable,
+                    opts.accessible
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut cloud_assets = Vec::new();
+    for source in &opts.cloud {
+        match cloud::discover(source) {
+            Ok(assets) => {
+                detail!(
+                    format!("Found {} public assets in {source}", assets.len()),
+                    opts.greppable,
+                    opts.accessible
+                );
+                cloud_assets.extend(assets);
+            }
+            Err(e) => warning!(
+                format!("Cloud discovery for {source} failed: {e}"),
+                opts.greppable,
+                opts.accessible
+            ),
+        }
+    }
+    opts.addresses
+        .extend(cloud_assets.iter().map(|asset| asset.target.clone()));
+    let cloud_tags = cloud::tags_by_ip(&cloud_assets);
+
+    let k8s_endpoints = match &opts.k8s {
+        Some(kubeconfig) => k8s::discover(kubeconfig).unwrap_or_else(|e| {
+            warning!(
+                format!("Kubernetes discovery failed: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+    for endpoint in &k8s_endpoints {
+        if !opts.addresses.contains(&endpoint.host) {
+            opts.addresses.push(endpoint.host.clone());
+        }
+        // Explicit port lists are widened to cover the declared ports.
+        if let Some(ports) = opts.ports.as_mut() {
+            if !ports.contains(&endpoint.port) {
+                ports.push(endpoint.port);
+            }
+        }
+    }
+
+    let plugins = load_plugins(&opts);
+    for plugin in &plugins {
+        opts.addresses.extend(plugin.generate_targets());
+    }
+
+    if !opts.greppable && !opts.accessible && !opts.no_banner {
+        print_opening(&opts);
+    }
+
+    if let Some(name) = &opts.netns {
+        // Before resolving targets, so every socket is made in it.
+        if let Err(e) = netns::enter(name) {
+            warning!(
+                format!("Can't scan from network namespace {name}: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    }
+    let ips: Vec<IpAddr> = parse_addresses(&opts);
+
+    if ips.is_empty() {
+        warning!(
+            "No IPs could be resolved, aborting scan.",
+            opts.greppable,
+            opts.accessible
+        );
+        std::process::exit(1);
+    }
+
+    #[cfg(unix)]
+    let batch_size: usize = infer_batch_size(&opts, adjust_ulimit_size(&opts));
+
+    #[cfg(not(unix))]
+    let batch_size: usize = infer_batch_size_from_limits(&opts, &SystemLimits::detect());
+
+    let baseline = read_baseline(&opts);
+    let policy = read_policy(&opts);
+    if opts.tcp_engine == TcpEngine::Syn && !opts.udp {
+        let raw_sockets = privileges::check(Feature::Syn).and_then(|()| SynProbe::new(0).map(drop));
+        match syn_fallback(&opts, raw_sockets) {
+            Ok(engine) => opts.tcp_engine = engine,
+            Err(explanation) => {
+                warning!(explanation, opts.greppable, opts.accessible);
+                std::process::exit(1);
+            }
+        }
+    }
+    if opts.tcp_engine == TcpEngine::Syn && !opts.udp {
+        if opts.stateless && opts.shard.is_some() && opts.shuffle_seed.is_none() {
+            warning!(
+                "Machines splitting a stateless scan with --shard need the same --shuffle-seed",
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    } else if opts.stateless {
+        warning!(
+            "--stateless only applies to TCP scans with --tcp-engine syn",
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    if opts.ot_safe
+        && ((opts.udp && opts.udp_engine == UdpEngine::Batched)
+            || (!opts.udp && opts.tcp_engine == TcpEngine::Syn))
+    {
+        warning!(
+            "--ot-safe only applies to the connect TCP engine and the socket UDP engine",
+            opts.greppable,
+            opts.accessible
+        );
+        std::process::exit(1);
+    }
+    if opts.paranoid
+        && ((opts.udp && opts.udp_engine == UdpEngine::Batched)
+            || (!opts.udp && opts.tcp_engine == TcpEngine::Syn))
+    {
+        warning!(
+            "--paranoid only applies to the connect TCP engine and the socket UDP engine",
+            opts.greppable,
+            opts.accessible
+        );
+        std::process::exit(1);
+    }
+    if opts.banner && (opts.udp || opts.tcp_engine == TcpEngine::Syn) {
+        warning!(
+            "--banner only applies to TCP scans with the connect engine",
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    let advisories = read_advisories(&opts);
+    if opts.default_creds {
+        warning!(creds::WARNING, opts.greppable, opts.accessible);
+    }
+    wake_targets(&opts, &ips);
+    if let Some(check) = &opts.health_check {
+        if !block_on(check.is_up(Duration::from_millis(opts.timeout.into()))) {
+            warning!(
+                format!(
+                    "{check} doesn't answer, check the route to the targets (VPN?) before scanning"
+                ),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    }
+    #[cfg(feature = "ssh")]
+    let ssh_tunnel = connect_via(&opts);
+    #[cfg(not(feature = "ssh"))]
+    connect_via(&opts);
+    // Scanning both protocols, each gets half the sockets.
+    let scan_batch_size = if other_protocol_ports.is_empty() {
+        batch_size
+    } else {
+        (batch_size / 2).max(1)
+    };
+    let mut port_strategy = PortStrategy::pick(&opts.range, opts.ports.clone(), opts.scan_order);
+    skip_dangerous_ports(&opts, &mut port_strategy);
+    let scanner = Scanner::new(
+        &ips,
+        scan_batch_size,
+        Duration::from_millis(opts.timeout.into()),
+        opts.tries,
+        opts.greppable,
+        port_strategy,
+        opts.accessible,
+        opts.exclude_ports.clone().unwrap_or_default(),
+        opts.udp,
+    )
+    .with_linger(linger(&opts))
+    .with_paranoid(paranoid_delay(&opts))
+    .with_max_open_per_host(opts.max_open_per_host)
+    .with_port_states(opts.filter().wants_unanswered())
+    .with_local_port_range(opts.local_port_range.clone())
+    .with_priorities(read_priorities(&opts))
+    .with_window(opts.window.clone())
+    .with_max_bandwidth(opts.max_bandwidth)
+    .with_health_check(opts.health_check.clone())
+    .with_probe_payloads(opts.probe_payloads.clone())
+    .with_banners(opts.banner.then_some(opts.banner_bytes))
+    .with_wildcard_check(wildcard_policy(&opts))
+    .with_threads(opts.threads, opts.cpu_affinity.clone())
+    .with_tcp_engine(opts.tcp_engine)
+    .with_stateless(opts.stateless)
+    .with_shuffle(opts.shuffle_seed, opts.resume_index)
+    .with_shard(opts.shard)
+    .with_result_shards(create_result_shards(&opts))
+    .with_events(events.clone())
+    .with_progress(Progress::interactive(opts.greppable, opts.accessible))
+    .with_controls(Controls::interactive(
+        batch_size,
+        SystemLimits::detect().socket_ceiling().max(batch_size),
+        opts.greppable,
+        opts.accessible,
+    ))
+    .with_udp_engine(opts.udp_engine)
+    .with_fragile_ports(if opts.ot_safe {
+        ot::OT_PORTS.to_vec()
+    } else {
+        Vec::new()
+    });
+    #[cfg(feature = "ssh")]
+    let scanner = scanner.with_ssh_tunnel(ssh_tunnel.clone());
+    debug!("Scanner finished building: {scanner:?}");
+    let other_scanner = other_protocol_scanner(&opts, &ips, scan_batch_size, other_protocol_ports);
+
+    let started = chrono::Utc::now();
+    let files = FileOptions::from_opts(&opts);
+    check_report_template(&opts);
+    let _output_locks = lock_outputs(&opts, started, &files);
+    if opts.stateless && opts.tcp_engine == TcpEngine::Syn && !opts.udp {
+        let seed = scanner.shuffle_seed();
+        detail!(
+            format!("Targets are shuffled with seed {seed}, --shuffle-seed {seed} --resume-index N resumes the scan after its first N sockets"),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    let notrack_rules = notrack_rules(&opts, &scanner);
+    let mut portscan_bench = NamedTimer::start("Portscan");
+    let scan_started = std::time::Instant::now();
+    // Both protocols are probed at once, each scanner taking its turns.
+    let (scan_result, other_protocol) = block_on(futures::future::join(scanner.run(), async {
+        match &other_scanner {
+            Some(other) => other.run().await,
+            None => Vec::new(),
+        }
+    }));
+    let scan_time = scan_started.elapsed();
+    let other_name = if opts.udp { "TCP" } else { "UDP" };
+    for socket in &other_protocol {
+        output!(
+            format!("Open {other_name} {socket}"),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    #[cfg(feature = "ssh")]
+    if let (Some(tunnel), Some(jump)) = (&ssh_tunnel, &opts.via) {
+        if tunnel.forwarding_prohibited() {
+            warning!(
+                format!("{jump} refused to forward some connections, ports behind it may be missing. See AllowTcpForwarding in its sshd_config."),
+                opts.greppable,
+                opts.accessible
+            );
+        }
+    }
+    let timing = Timing::new(
+        scan_time,
+        scanner
+            .probe_stats()
+            .into_values()
+            .chain(
+                other_scanner
+                    .iter()
+                    .flat_map(|other| other.probe_stats().into_values()),
+            )
+            .map(|stats| stats.sent)
+            .sum(),
+    );
+    drop(notrack_rules);
+    portscan_bench.end();
+    benchmarks.push(portscan_bench);
+    #[allow(clippy::cast_precision_loss)]
+    let probe_rate = timing.probes as f64 / scan_time.as_secs_f64().max(0.001);
+    detail!(
+        format!(
+            "Sent {} probes in {} ({})",
+            units::thousands(timing.probes),
+            units::duration(scan_time),
+            units::rate(probe_rate, "pps")
+        ),
+        opts.greppable,
+        opts.accessible
+    );
+    let probe_stats = scanner.probe_stats();
+    let responses = scanner.probe_responses();
+    let wildcards = scanner.wildcard_hosts();
+    let distances = scanner.distances();
+    let discovered = scanner.discoveries();
+    let latencies = scanner.latencies();
+    let port_states = scanner.port_states();
+    let banners = scanner.banners();
+    for (ip, distance) in &distances {
+        detail!(
+            format!("{ip} is {distance}"),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    for ip in &wildcards {
+        let outcome = if opts.verify_wildcards {
+            "only its ports that answered are reported"
+        } else {
+            "it was skipped, --verify-wildcards reports its ports that answer"
+        };
+        warning!(
+            format!("{ip} accepts connections on every port (load balancer or tarpit?), {outcome}"),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    print_responses(&opts, &responses);
+    if opts.debug {
+        for (ip, stats) in &probe_stats {
+            detail!(
+                format!("Probes of {ip}: {stats}"),
+                opts.greppable,
+                opts.accessible
+            );
+        }
+    }
+
+    if let Some(dir) = &opts.result_shards {
+        print_sharded_results(&opts, dir, &plugins, &scan_result);
+        return;
+    }
+
+    let mut ports_per_ip = HashMap::new();
+
+    for socket in scan_result {
+        for plugin in &plugins {
+            plugin.on_open_port(socket);
+        }
+        ports_per_ip
+            .entry(socket.ip())
+            .or_insert_with(Vec::new)
+            .push(socket.port());
+    }
+
+    let target_count = ips.len();
+    if opts.mode == ScanMode::Discovery {
+        print_discovery(&opts, &ips, &ports_per_ip, &probe_stats);
+    }
+    for ip in &ips {
+        if opts.mode == ScanMode::Discovery || ports_per_ip.contains_key(ip) {
+            continue;
+        }
+
+        // If we got here it means the IP was not found within the HashMap, this
+        // means the scan couldn't find any open ports for it.
+
+        let x = format!("Looks like I didn't find any open ports for {:?}. This is usually caused by a high batch size.
+        \n*I used {} batch size, consider lowering it with {} or a comfortable number for your system.
+        \n Alternatively, increase the timeout if your ping is high. Rustscan -t 2000 for 2000 milliseconds (2s) timeout.\n",
+        ip,
+        opts.batch_size,
+        "'rustscan -b <batch_size> -a <ip address>'");
+        warning!(x, opts.greppable, opts.accessible);
+    }
+
+    if !k8s_endpoints.is_empty() {
+        let unreachable = k8s::unreachable(&k8s_endpoints, &ports_per_ip);
+        for endpoint in &unreachable {
+            warning!(
+                format!("Declared endpoint {endpoint} is not reachable"),
+                opts.greppable,
+                opts.accessible
+            );
+        }
+        if unreachable.is_empty() {
+            detail!(
+                format!(
+                    "All {} declared Kubernetes endpoints are reachable",
+                    k8s_endpoints.len()
+                ),
+                opts.greppable,
+                opts.accessible
+            );
+        }
+    }
+
+    print_dual_stack(&opts, &ports_per_ip);
+
+    let devices = if opts.mac {
+        lookup_devices(&opts, &ips)
+    } else {
+        BTreeMap::new()
+    };
+
+    let policy_drift = match &policy {
+        Some(policy) => print_policy_drift(&opts, policy, &ports_per_ip),
+        None => Vec::new(),
+    };
+    let alerts = print_alerts(&opts, &ports_per_ip);
+
+    let mut known = Vec::new();
+    if let Some(baseline) = &baseline {
+        for (ip, ports) in &mut ports_per_ip {
+            known.extend(
+                ports
+                    .iter()
+                    .map(|port| std::net::SocketAddr::new(*ip, *port))
+                    .filter(|socket| baseline.contains(socket)),
+            );
+            if opts.hide_known {
+                ports.retain(|port| !baseline.contains(&std::net::SocketAddr::new(*ip, *port)));
+            }
+        }
+        let hidden = if opts.hide_known { ", hiding them" } else { "" };
+        detail!(
+            format!("{} open ports are in the baseline{hidden}", known.len()),
+            opts.greppable,
+            opts.accessible
+        );
+        if opts.hide_known {
+            ports_per_ip.retain(|_, ports| !ports.is_empty());
+            known.clear();
+        }
+    }
+
+    if opts.tls {
+        print_tls_probes(&opts, &ports_per_ip);
+    }
+    let rollups = print_rollups(&opts, &ips, &ports_per_ip);
+    let nuclei_findings = if opts.nuclei {
+        run_nuclei(&opts, &ports_per_ip)
+    } else {
+        Vec::new()
+    };
+    let vuln_hints = match &advisories {
+        Some(advisories) => print_vuln_hints(&opts, &ports_per_ip, advisories),
+        None => Vec::new(),
+    };
+    let default_creds = if opts.default_creds {
+        print_default_creds(&opts, &ports_per_ip)
+    } else {
+        Vec::new()
+    };
+    let service_probes = if opts.service_probes
+        || (!skip_scripts && scripts_to_run.iter().any(|script| script.service.is_some()))
+    {
+        print_service_probes(&opts, &ports_per_ip)
+    } else {
+        Vec::new()
+    };
+    let ot_devices = if opts.ot_probes {
+        print_ot_devices(&opts, &ports_per_ip)
+    } else {
+        Vec::new()
+    };
+
+    let mut script_bench = NamedTimer::start("Scripts");
+    let mut script_outputs: HashMap<IpAddr, Vec<String>> = HashMap::new();
+    let found_services: BTreeMap<std::net::SocketAddr, String> = service_probes
+        .iter()
+        .map(|probe| (probe.socket, probe.service.clone()))
+        .collect();
+    let view = View::new(&found_services, &latencies);
+    let mut ordered: Vec<(IpAddr, Vec<u16>)> = ports_per_ip.clone().into_iter().collect();
+    view.sort(&mut ordered, opts.sort_by);
+    let filter = opts.filter();
+    let mut shown = ordered.clone();
+    filter.retain_open(&view, &mut shown);
+    let shown_ports: HashMap<IpAddr, &Vec<u16>> =
+        shown.iter().map(|(ip, ports)| (*ip, ports)).collect();
+    let shown_states = filter.retain_states(&view, port_states);
+    let groups = opts
+        .group_by
+        .map(|by| view.group(&shown, by))
+        .unwrap_or_default();
+    let mut nmap_output = match &opts.nmap_output_dir {
+        Some(dir) if !skip_scripts => {
+            if opts.scripts != ScriptsRequired::Default {
+                warning!(
+                    "--nmap-output-dir only applies to the default nmap script.",
+                    opts.greppable,
+                    opts.accessible
+                );
+            }
+            match NmapOutput::create(dir) {
+                Ok(nmap_output) => Some(nmap_output),
+                Err(e) => {
+                    warning!(format!("{e}"), opts.greppable, opts.accessible);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    if !skip_scripts {
+        let mut hosts: Vec<(&IpAddr, &Vec<u16>)> = ports_per_ip.iter().collect();
+        hosts.sort_unstable();
+        for mut script_f in group_scripts {
+            if !opts.command.is_empty() {
+                if let Some(call_f) = &mut script_f.group_call_format {
+                    call_f.push(' ');
+                    call_f.push_str(&opts.command.join(" "));
+                }
+            }
+            let script_ports: Vec<(IpAddr, Vec<u16>)> = hosts
+                .iter()
+                .map(|(ip, ports)| (**ip, script_f.ports_for(**ip, ports, &found_services)))
+                .filter(|(_, ports)| !ports.is_empty())
+                .collect();
+            let groups = scripts::group_hosts(
+                script_ports
+                    .iter()
+                    .map(|(ip, ports)| (*ip, ports.as_slice())),
+            );
+            for (group, ports) in groups {
+                output!(
+                    format!(
+                        "Running script {:?} on {} hosts with open ports {ports:?}",
+                        script_f.path,
+                        group.len()
+                    ),
+                    opts.greppable,
+                    opts.accessible
+                );
+                let mut group_f = script_f.clone();
+                if let (Some(nmap_output), None, Some(call_f)) = (
+                    &mut nmap_output,
+                    &group_f.path,
+                    &mut group_f.group_call_format,
+                ) {
+                    call_f.push(' ');
+                    call_f.push_str(&nmap_output.group_args());
+                }
+                match group_f.run_group(&group, &ports) {
+                    Ok(script_result) => {
+                        for ip in &group {
+                            if let Some(events) = &events {
+                                events.emit(&Event::ScriptResult {
+                                    ip: *ip,
+                                    output: &script_result,
+                                });
+                            }
+                            script_outputs
+                                .entry(*ip)
+                                .or_default()
+                                .push(script_result.clone());
+                        }
+                        detail!(script_result, opts.greppable, opts.accessible);
+                    }
+                    Err(e) => {
+                        warning!(
+                            &format!("Error in script {:?}: {e}", script_f.path),
+                            opts.greppable,
+                            opts.accessible
+                        );
+                    }
+                }
+            }
+        }
+    }
+    for (ip, ports) in &ordered {
+        if let Some(tags) = cloud_tags.get(ip) {
+            detail!(
+                format!("{ip} is tagged {}", tags.join(" ")),
+                opts.greppable,
+                opts.accessible
+            );
+        }
+
+        // if option scripts is none, no script will be spawned
+        if skip_scripts {
+            match (&events, shown_ports.get(ip)) {
+                (Some(events), _) => events.emit(&Event::HostDone { ip: *ip, ports }),
+                // Discovery already printed the hosts up.
+                (None, Some(_)) if opts.greppable && opts.mode == ScanMode::Discovery => {}
+                (None, Some(shown)) => {
+                    let vec_str_ports: Vec<String> =
+                        shown.iter().map(ToString::to_string).collect();
+
+                    // nmap port style is 80,443. Comma separated with no spaces.
+                    let ports_str = vec_str_ports.join(",");
+                    println!("{} -> [{}]", &ip, ports_str);
+                }
+                (None, None) => {}
+            }
+            continue;
+        }
+        detail!("Starting Script(s)", opts.greppable, opts.accessible);
+
+        // Run all the scripts we found and parsed based on the script config file tags field.
+        for mut script_f in scripts_to_run.clone() {
+            let script_ports = script_f.ports_for(*ip, ports, &found_services);
+            if script_ports.is_empty() {
+                continue;
+            }
+            // This part allows us to add commandline arguments to the Script call_format, appending them to the end of the command.
+            if !opts.command.is_empty() {
+                let user_extra_args = &opts.command.join(" ");
+                debug!("Extra args vec {user_extra_args:?}");
+                if script_f.call_format.is_some() {
+                    let mut call_f = script_f.call_format.unwrap();
+                    call_f.push(' ');
+                    call_f.push_str(user_extra_args);
+                    output!(
+                        format!("Running script {:?} on ip {}\nDepending on the complexity of the script, results may take some time to appear.", call_f, &ip),
+                        opts.greppable,
+                        opts.accessible
+                    );
+                    debug!("Call format {call_f}");
+                    script_f.call_format = Some(call_f);
+                }
+            }
+            if let (Some(nmap_output), None, Some(call_f)) =
+                (&mut nmap_output, &script_f.path, &mut script_f.call_format)
+            {
+                call_f.push(' ');
+                call_f.push_str(&nmap_output.host_args(*ip));
+            }
+
+            // Building the script with the arguments from the ScriptFile, and ip-ports.
+            let script = Script::build(
+                script_f.path,
+                *ip,
+                script_ports,
+                script_f.port,
+                script_f.ports_separator,
+                script_f.tags,
+                script_f.call_format,
+            );
+            match script.run() {
+                Ok(script_result) => {
+                    if let Some(events) = &events {
+                        events.emit(&Event::ScriptResult {
+                            ip: *ip,
+                            output: &script_result,
+                        });
+                    }
+                    detail!(script_result.clone(), opts.greppable, opts.accessible);
+                    script_outputs.entry(*ip).or_default().push(script_result);
+                }
+                Err(e) => {
+                    warning!(&format!("Error {e}"), opts.greppable, opts.accessible);
+                }
+            }
+        }
+        if let Some(events) = &events {
+            events.emit(&Event::HostDone { ip: *ip, ports });
+        }
+    }
+    if events.is_none() {
+        print_banners(&shown, &banners);
+        print_port_states(&shown_states);
+    }
+    for group in &groups {
+        let sockets: Vec<String> = group.sockets.iter().map(ToString::to_string).collect();
+        println!("{} -> [{}]", group.name, sockets.join(","));
+    }
+    if !skip_scripts && !ports_per_ip.is_empty() {
+        let open_ports: BTreeMap<IpAddr, Vec<u16>> = ports_per_ip
+            .iter()
+            .map(|(ip, ports)| (*ip, ports.clone()))
+            .collect();
+        for mut script_f in batch_scripts {
+            if !opts.command.is_empty() {
+                if let Some(call_f) = &mut script_f.call_format {
+                    call_f.push(' ');
+                    call_f.push_str(&opts.command.join(" "));
+                }
+            }
+            match script_f.run_batch(&open_ports) {
+                Ok(script_result) => detail!(script_result, opts.greppable, opts.accessible),
+                Err(e) => {
+                    warning!(
+                        &format!("Error in batch script {:?}: {e}", script_f.path),
+                        opts.greppable,
+                        opts.accessible
+                    );
+                }
+            }
+        }
+    }
+    if let Some(nmap_output) = &nmap_output {
+        match nmap_output.merge() {
+            Ok(Some(merged)) => output!(
+                format!("Merged the nmap results into {}", merged.display()),
+                opts.greppable,
+                opts.accessible
+            ),
+            Ok(None) => {}
+            Err(e) => warning!(format!("{e}"), opts.greppable, opts.accessible),
+        }
+    }
+    if let Some(events) = &events {
+        for ip in ips.iter().filter(|ip| !ports_per_ip.contains_key(ip)) {
+            events.emit(&Event::HostDone {
+                ip: *ip,
+                ports: &[],
+            });
+        }
+    }
+
+    if !plugins.is_empty() {
+        let results_json = plugins::results_json(&ports_per_ip);
+        for plugin in &plugins {
+            if let Some(report) = plugin.render_report(&results_json) {
+                println!("{report}");
+            }
+        }
+    }
+
+    let mut report = ScanReport::new(started, opts.udp);
+    if let Some(events) = &events {
+        events.scan_id().clone_into(&mut report.scan_id);
+    }
+    report.finished = chrono::Utc::now();
+    report.args = std::env::args().collect();
+    report.target_count = target_count;
+    if !opts.no_config {
+        report.config_hash =
+            manifest::file_hash(&input::resolve_config_path(opts.config_path.clone()));
+    }
+    report.labels = opts
+        .labels
+        .iter()
+        .map(|label| (label.key.clone(), label.value.clone()))
+        .collect();
+    for (ip, ports) in &shown {
+        report.hosts.push(HostReport {
+            ip: *ip,
+            ports: ports.clone(),
+            script_output: script_outputs.remove(ip).unwrap_or_default(),
+        });
+    }
+    // Taken before --redact masks the arguments a rerun needs.
+    let mut history_entry = history::Entry::new(&report.manifest());
+
+    let mut written_files = Vec::new();
+    if !opts.output.is_empty() || opts.upload.is_some() {
+        known.sort();
+        report.known = known;
+        report.devices = devices;
+        report.responses = responses;
+        report.banners = banners
+            .into_iter()
+            .filter(|(socket, _)| report.is_open(socket.ip(), socket.port()))
+            .collect();
+        report.wildcards = wildcards;
+        report.distances = distances;
+        report.rollups = rollups;
+        report.discovered = discovered
+            .into_iter()
+            .filter(|(socket, _)| report.is_open(socket.ip(), socket.port()))
+            .collect();
+        if opts.debug {
+            report.probes = probe_stats;
+        }
+        report.warnings = tui::warnings();
+        report.timing = Some(timing);
+        report.nuclei = nuclei_findings;
+        report.vuln_hints = vuln_hints;
+        report.default_creds = default_creds;
+        report.service_probes = service_probes;
+        report.ot_devices = ot_devices;
+        report.policy_drift = policy_drift;
+        report.alerts = alerts;
+        report.other_protocol = other_protocol;
+        report.latencies_us = latencies
+            .iter()
+            .filter(|(socket, _)| report.is_open(socket.ip(), socket.port()))
+            .map(|(socket, latency)| {
+                (
+                    *socket,
+                    u64::try_from(latency.as_micros()).unwrap_or(u64::MAX),
+                )
+            })
+            .collect();
+        report.groups = groups;
+        report.port_states = shown_states;
+        if opts.redact {
+            Redactor::new(&redact::hostnames(&opts)).redact(&mut report);
+        }
+
+        let client = HttpClient::from_opts(&opts);
+        for target in &opts.output {
+            match target.sink(&client, &files).write(&report) {
+                Ok(()) => written_files.extend(target.file(report.started, &files)),
+                Err(e) => warning!(
+                    format!("Exporting to {target} failed: {e}"),
+                    opts.greppable,
+                    opts.accessible
+                ),
+            }
+        }
+
+        // The manifest goes next to every file output, or is only uploaded.
+        let mut manifest_dirs: Vec<PathBuf> = written_files
+            .iter()
+            .map(|file| file.parent().unwrap_or(Path::new("")).to_path_buf())
+            .collect();
+        manifest_dirs.sort();
+        manifest_dirs.dedup();
+        if manifest_dirs.is_empty() && opts.upload.is_some() {
+            manifest_dirs.push(std::env::temp_dir());
+        }
+        for dir in manifest_dirs {
+            match manifest::write(&report, &dir, &files) {
+                Ok(path) => written_files.push(path),
+                Err(e) => warning!(format!("{e}"), opts.greppable, opts.accessible),
+            }
+        }
+
+        if let Some(target) = &opts.upload {
+            match upload::upload(target, &report, &written_files, opts.upload_sse.as_deref()) {
+                Ok(uploaded) => {
+                    for url in uploaded {
+                        detail!(format!("Uploaded {url}"), opts.greppable, opts.accessible);
+                    }
+                }
+                Err(e) => warning!(
+                    format!("Uploading to {target} failed: {e}"),
+                    opts.greppable,
+                    opts.accessible
+                ),
+            }
+        }
+    }
+
+    if !opts.no_history {
+        history_entry.files = written_files;
+        if let Err(e) = history::record(&history_entry) {
+            warning!(
+                format!("Could not add the scan to the history: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+        }
+    }
+
+    if !post_scan_scripts.is_empty() {
+        let results: BTreeMap<&IpAddr, &Vec<u16>> = ports_per_ip.iter().collect();
+        let hosts: Vec<IpAddr> = results.keys().map(|ip| **ip).collect();
+        let results_json = plugins::results_json(results);
+        for script in &post_scan_scripts {
+            match script.run_post_scan(&opts.addresses, &hosts, &results_json) {
+                Ok(output) => detail!(output, opts.greppable, opts.accessible),
+                Err(e) => warning!(
+                    format!("Post-scan script {:?} failed: {e}", script.path),
+                    opts.greppable,
+                    opts.accessible
+                ),
+            }
+        }
+    }
+
+    if let Some(Ok(Some(latest))) = update_check.map(std::thread::JoinHandle::join) {
+        detail!(
+            format!(
+                "RustScan {latest} is available, you are running {}. See https://github.com/RustScan/RustScan/releases",
+                env!("CARGO_PKG_VERSION")
+            ),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+
+    // To use the runtime benchmark, run the process as: RUST_LOG=info ./rustscan
+    script_bench.end();
+    benchmarks.push(script_bench);
+    rustscan_bench.end();
+    benchmarks.push(rustscan_bench);
+    debug!("Benchmarks raw {benchmarks:?}");
+    info!("{}", benchmarks.summary());
+}
+
+/// Prints the opening title of RustScan
+#[allow(clippy::items_after_statements, clippy::needless_raw_string_hashes)]
+fn print_opening(opts: &Opts) {
+    debug!("Printing opening");
+    let s = r#".----. .-. .-. .----..---.  .----. .---.   .--.  .-. .-.
+| {}  }| { } |{ {__ {_   _}{ {__  /  ___} / {} \ |  `| |
+| .-. \| {_} |.-._} } | |  .-._} }\     }/  /\  \| |\  |
+`-' `-'`-----'`----'  `-'  `----'  `---' `-'  `-'`-' `-'
+The Modern Day Port Scanner."#;
+
+    println!("{}", s.gradient(Color::Green).bold());
+    let info = r#"________________________________________
+: http://discord.skerritt.blog         :
+: https://github.com/RustScan/RustScan :
+ --------------------------------------"#;
+    println!("{}", info.gradient(Color::Yellow).bold());
+    funny_opening!();
+
+    let config_path = opts
+        .config_path
+        .clone()
+        .unwrap_or_else(input::default_config_path);
+
+    detail!(
+        format!("The config file is expected to be at {config_path:?}"),
+        opts.greppable,
+        opts.accessible
+    );
+
+    if opts.config_path.is_none() {
+        let old_config_path = input::old_default_config_path();
+        detail!(
+            format!(
+                "For backwards compatibility, the config file may also be at {old_config_path:?}"
+            ),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+}
+
+/// Loads the plugins from `--plugins-dir`, reporting those that fail.
+fn load_plugins(opts: &Opts) -> Vec<Plugin> {
+    let Some(dir) = &opts.plugins_dir else {
+        return Vec::new();
+    };
+    let (loaded, errors) = match plugins::load_plugins(dir) {
+        Ok(result) => result,
+        Err(e) => {
+            warning!(format!("{e}"), opts.greppable, opts.accessible);
+            return Vec::new();
+        }
+    };
+    for e in errors {
+        warning!(
+            format!("Skipping plugin, {e}"),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    for plugin in &loaded {
+        detail!(
+            format!("Loaded plugin {}", plugin.name()),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    loaded
+}
+
+/// Probes the open ports for TLS and prints what the ones speaking it serve.
+#[cfg(feature = "tls")]
+fn print_tls_probes(opts: &Opts, ports_per_ip: &HashMap<IpAddr, Vec<u16>>) {
+    let mut sockets: Vec<std::net::SocketAddr> = ports_per_ip
+        .iter()
+        .flat_map(|(ip, ports)| {
+            ports
+                .iter()
+                .map(move |port| std::net::SocketAddr::new(*ip, *port))
+        })
+        .collect();
+    sockets.sort_unstable();
+    let timeout = Duration::from_millis(opts.timeout.into());
+    for (socket, info) in tls::probe_all(&sockets, timeout) {
+        detail!(
+            format!("{socket} speaks {info}"),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+fn print_tls_probes(opts: &Opts, _ports_per_ip: &HashMap<IpAddr, Vec<u16>>) {
+    warning!(
+        "This build of RustScan has no TLS probes, rebuild it with the tls feature to use --tls.",
+        opts.greppable,
+        opts.accessible
+    );
+}
+
+/// Runs nuclei against the open ports serving HTTP, printing and returning
+/// what it found.
+fn run_nuclei(opts: &Opts, ports_per_ip: &HashMap<IpAddr, Vec<u16>>) -> Vec<nuclei::Finding> {
+    let mut sockets: Vec<std::net::SocketAddr> = ports_per_ip
+        .iter()
+        .flat_map(|(ip, ports)| {
+            ports
+                .iter()
+                .map(move |port| std::net::SocketAddr::new(*ip, *port))
+        })
+        .collect();
+    sockets.sort_unstable();
+    let urls = nuclei::web_urls(&sockets, Duration::from_millis(opts.timeout.into()));
+    if urls.is_empty() {
+        detail!(
+            "No open port serves HTTP, nuclei isn't run",
+            opts.greppable,
+            opts.accessible
+        );
+        return Vec::new();
+    }
+    detail!(
+        format!("Running nuclei against {} web servers", urls.len()),
+        opts.greppable,
+        opts.accessible
+    );
+    match nuclei::run(
+        &urls,
+        opts.nuclei_templates.as_deref(),
+        opts.nuclei_config.as_deref(),
+    ) {
+        Ok(findings) => {
+            for finding in &findings {
+                detail!(
+                    format!(
+                        "[{}] {} ({}) at {}",
+                        finding.severity, finding.name, finding.template, finding.matched_at
+                    ),
+                    opts.greppable,
+                    opts.accessible
+                );
+            }
+            findings
+        }
+        Err(e) => {
+            warning!(
+                format!("nuclei didn't run: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// The advisories --vuln-hints matches banners against, read before the
+/// scan so a broken --vuln-data file doesn't waste it.
+fn read_advisories(opts: &Opts) -> Option<Vec<vulns::Advisory>> {
+    if !opts.vuln_hints {
+        return None;
+    }
+    match vulns::advisories(opts.vuln_data.as_deref()) {
+        Ok(advisories) => Some(advisories),
+        Err(e) => {
+            warning!(
+                format!("Invalid vulnerability data: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Grabs the banners of the open ports and prints the services whose
+/// version may be vulnerable, returning them.
+fn print_vuln_hints(
+    opts: &Opts,
+    ports_per_ip: &HashMap<IpAddr, Vec<u16>>,
+    advisories: &[vulns::Advisory],
+) -> Vec<vulns::Hint> {
+    let sockets: Vec<std::net::SocketAddr> = ports_per_ip
+        .iter()
+        .flat_map(|(ip, ports)| {
+            ports
+                .iter()
+                .map(move |port| std::net::SocketAddr::new(*ip, *port))
+        })
+        .collect();
+    let services = vulns::services(&sockets, Duration::from_millis(opts.timeout.into()));
+    let hints = vulns::check(&services, advisories);
+    for hint in &hints {
+        detail!(
+            format!(
+                "{} runs {} {}, which may be affected by {} [{}]: {} ({})",
+                hint.socket,
+                hint.product,
+                hint.version,
+                hint.cve,
+                hint.severity,
+                hint.summary,
+                hint.basis
+            ),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    if !services.is_empty() && hints.is_empty() {
+        detail!(
+            format!(
+                "No known vulnerabilities for the versions of {} services recognised",
+                services.len()
+            ),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    hints
+}
+
+/// Tries logging in without credentials to the open ports of the protocols
+/// --default-creds knows, printing and returning the outcomes.
+fn print_default_creds(
+    opts: &Opts,
+    ports_per_ip: &HashMap<IpAddr, Vec<u16>>,
+) -> Vec<creds::Attempt> {
+    let mut sockets: Vec<std::net::SocketAddr> = ports_per_ip
+        .iter()
+        .flat_map(|(ip, ports)| {
+            ports
+                .iter()
+                .map(move |port| std::net::SocketAddr::new(*ip, *port))
+        })
+        .collect();
+    sockets.sort_unstable();
+    let attempts = creds::check(
+        &sockets,
+        Duration::from_millis(opts.timeout.into()),
+        creds::ATTEMPT_INTERVAL,
+    );
+    for attempt in attempts.iter().filter(|attempt| attempt.success) {
+        warning!(
+            format!(
+                "{} ({}) lets anyone in, credentials: {}",
+                attempt.socket, attempt.protocol, attempt.credentials
+            ),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    let failed = attempts.iter().filter(|attempt| !attempt.success).count();
+    if failed > 0 {
+        detail!(
+            format!("{failed} login attempts without credentials failed"),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    attempts
+}
+
+/// Probes the FTP servers among the open ports and TFTP on the hosts found,
+/// printing and returning what they answered.
+fn print_service_probes(
+    opts: &Opts,
+    ports_per_ip: &HashMap<IpAddr, Vec<u16>>,
+) -> Vec<services::ServiceProbe> {
+    let mut sockets: Vec<std::net::SocketAddr> = ports_per_ip
+        .iter()
+        .flat_map(|(ip, ports)| {
+            ports
+                .iter()
+                .map(move |port| std::net::SocketAddr::new(*ip, *port))
+        })
+        .collect();
+    sockets.sort_unstable();
+    let mut probes = services::probe_all(
+        &sockets,
+        opts.udp,
+        Duration::from_millis(opts.timeout.into()),
+    );
+    probes.sort_by_key(|probe| probe.socket);
+    for probe in &probes {
+        let message = match (probe.service.as_str(), probe.anonymous) {
+            ("ftp", Some(true)) => format!(
+                "{} (ftp) allows anonymous logins: {}",
+                probe.socket, probe.answer
+            ),
+            ("ftp", _) => format!("{} (ftp) greets with: {}", probe.socket, probe.answer),
+            ("tftp", _) => format!(
+                "{} (tftp) answered a read request for {} with {}, anyone can read its files",
+                probe.socket,
+                services::TFTP_FILENAME,
+                probe.answer
+            ),
+            (service, _) => format!(
+                "{} ({service}{}) {}",
+                probe.socket,
+                probe
+                    .version
+                    .as_ref()
+                    .map(|version| format!(" {version}"))
+                    .unwrap_or_default(),
+                probe.answer
+            ),
+        };
+        if probe.is_open() {
+            warning!(message, opts.greppable, opts.accessible);
+        } else {
+            detail!(message, opts.greppable, opts.accessible);
+        }
+    }
+    probes
+}
+
+/// Asks the industrial devices among the open ports to identify
+/// themselves, printing and returning what they said.
+fn print_ot_devices(opts: &Opts, ports_per_ip: &HashMap<IpAddr, Vec<u16>>) -> Vec<ot::Device> {
+    let mut sockets: Vec<std::net::SocketAddr> = ports_per_ip
+        .iter()
+        .flat_map(|(ip, ports)| {
+            ports
+                .iter()
+                .map(move |port| std::net::SocketAddr::new(*ip, *port))
+        })
+        .collect();
+    sockets.sort_unstable();
+    let devices = ot::identify_all(
+        &sockets,
+        opts.udp,
+        Duration::from_millis(opts.timeout.into()),
+        ot::PROBE_INTERVAL,
+    );
+    for device in &devices {
+        let identity: Vec<String> = device
+            .identity
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}"))
+            .collect();
+        detail!(
+            format!(
+                "{} ({}) {}",
+                device.socket,
+                device.protocol,
+                identity.join(", ")
+            ),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    devices
+}
+
+/// Reads the priority file, if any. A broken one aborts the scan rather
+/// than scanning in the wrong order.
+fn read_priorities(opts: &Opts) -> Option<TargetPriorities> {
+    let path = opts.priorities.as_ref()?;
+    match TargetPriorities::read(path, &opts.resolver) {
+        Ok(priorities) => Some(priorities),
+        Err(e) => {
+            warning!(
+                format!("Invalid priority file: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// How hosts answering on every port are handled: skipped by default,
+/// verified with --verify-wildcards, or not checked for at all.
+fn wildcard_policy(opts: &Opts) -> Option<WildcardPolicy> {
+    if opts.no_wildcard_check {
+        None
+    } else if opts.verify_wildcards {
+        Some(WildcardPolicy::Verify)
+    } else {
+        Some(WildcardPolicy::Skip)
+    }
+}
+
+/// Prints the closed and filtered ports `--show` asks for, a line per host
+/// and state such as `10.0.0.5 -> closed [23,25]`.
+fn print_port_states(states: &BTreeMap<std::net::SocketAddr, PortState>) {
+    let mut lines: BTreeMap<(IpAddr, PortState), Vec<String>> = BTreeMap::new();
+    for (socket, state) in states {
+        lines
+            .entry((socket.ip(), *state))
+            .or_default()
+            .push(socket.port().to_string());
+    }
+    for ((ip, state), ports) in lines {
+        println!("{ip} -> {state} [{}]", ports.join(","));
+    }
+}
+
+/// The hosts up, in order, with their open ports. A host refusing
+/// connections (a RST, or an ICMP port unreachable over UDP) is up too, only
+/// its ports are closed.
+fn hosts_up<'a>(
+    ports_per_ip: &'a HashMap<IpAddr, Vec<u16>>,
+    probe_stats: &'a BTreeMap<IpAddr, ProbeStats>,
+) -> Vec<(&'a IpAddr, Option<&'a Vec<u16>>)> {
+    let mut up: Vec<(&IpAddr, Option<&Vec<u16>>)> = ports_per_ip
+        .iter()
+        .map(|(ip, ports)| (ip, Some(ports)))
+        .chain(
+            probe_stats
+                .iter()
+                .filter(|(ip, stats)| stats.refused > 0 && !ports_per_ip.contains_key(ip))
+                .map(|(ip, _)| (ip, None)),
+        )
+        .collect();
+    up.sort_unstable();
+    up
+}
+
+/// Prints the hosts found up by `--mode discovery` with the port that
+/// answered, and how many weren't. Greppable output is the IPs of the
+/// hosts up.
+fn print_discovery(
+    opts: &Opts,
+    ips: &[IpAddr],
+    ports_per_ip: &HashMap<IpAddr, Vec<u16>>,
+    probe_stats: &BTreeMap<IpAddr, ProbeStats>,
+) {
+    let up = hosts_up(ports_per_ip, probe_stats);
+    for (ip, ports) in &up {
+        if opts.greppable {
+            println!("{ip}");
+            continue;
+        }
+        let answer = match ports {
+            Some(ports) => {
+                let ports: Vec<String> = ports.iter().map(ToString::to_string).collect();
+                format!("port {} answered", ports.join(", "))
+            }
+            None => "it refused connections".to_owned(),
+        };
+        output!(format!("{ip} is up, {answer}"), false, opts.accessible);
+    }
+    detail!(
+        format!(
+            "{} of {} hosts are up, the others didn't answer on any of the ports probed",
+            units::thousands(up.len() as u64),
+            units::thousands(ips.len() as u64)
+        ),
+        opts.greppable,
+        opts.accessible
+    );
+}
+
+/// Leaves the dangerous ports out of `strategy` unless
+/// `--allow-dangerous-ports`, telling which were.
+fn skip_dangerous_ports(opts: &Opts, strategy: &mut PortStrategy) {
+    if opts.allow_dangerous_ports {
+        return;
+    }
+    for skipped in strategy.skip_dangerous() {
+        warning!(
+            format!("Skipping {skipped}, --allow-dangerous-ports scans it"),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+}
+
+/// The SO_LINGER timeout of open ports: `--linger`, or with `--paranoid` the
+/// timeout, of at least a second since zero would reset connections.
+fn linger(opts: &Opts) -> Option<Duration> {
+    opts.linger
+        .map(|secs| Duration::from_secs(secs.into()))
+        .or_else(|| {
+            opts.paranoid
+                .then(|| Duration::from_millis(opts.timeout.into()).max(Duration::from_secs(1)))
+        })
+}
+
+/// The wait between the ports of a host with `--paranoid`.
+fn paranoid_delay(opts: &Opts) -> Option<Duration> {
+    opts.paranoid
+        .then(|| Duration::from_millis(opts.paranoid_delay.into()))
+}
+
+/// A scanner of the ports tagged with the other protocol than the scan's in
+/// `--ports`, or none without them. It prints nothing itself, its open ports
+/// are told apart from the others after the scan.
+fn other_protocol_scanner(
+    opts: &Opts,
+    ips: &[IpAddr],
+    batch_size: usize,
+    ports: Vec<u16>,
+) -> Option<Scanner> {
+    if ports.is_empty() {
+        return None;
+    }
+    let mut port_strategy = PortStrategy::pick(&None, Some(ports), opts.scan_order);
+    skip_dangerous_ports(opts, &mut port_strategy);
+    let scanner = Scanner::new(
+        ips,
+        batch_size,
+        Duration::from_millis(opts.timeout.into()),
+        opts.tries,
+        true,
+        port_strategy,
+        opts.accessible,
+        opts.exclude_ports.clone().unwrap_or_default(),
+        !opts.udp,
+    )
+    .with_linger(linger(opts))
+    .with_paranoid(paranoid_delay(opts))
+    .with_max_open_per_host(opts.max_open_per_host)
+    .with_local_port_range(opts.local_port_range.clone())
+    .with_window(opts.window.clone())
+    .with_max_bandwidth(opts.max_bandwidth)
+    .with_udp_engine(opts.udp_engine);
+    debug!("Other protocol scanner finished building: {scanner:?}");
+    Some(scanner)
+}
+
+/// Prints what open ports answered to --probe-payload.
+fn print_responses(opts: &Opts, responses: &BTreeMap<std::net::SocketAddr, ProbeResponse>) {
+    for (socket, response) in responses {
+        if response.length == 0 {
+            warning!(
+                format!("{socket} accepted the connection but didn't answer the payload, it may not be a service"),
+                opts.greppable,
+                opts.accessible
+            );
+        } else {
+            detail!(
+                format!(
+                    "{socket} answered {} bytes: {}",
+                    response.length, response.snippet
+                ),
+                opts.greppable,
+                opts.accessible
+            );
+        }
+    }
+}
+
+/// Prints the `--banner` of every open port shown, a line each such as
+/// `10.0.0.5:22 -> SSH-2.0-OpenSSH_9.6`.
+fn print_banners(shown: &[(IpAddr, Vec<u16>)], banners: &BTreeMap<std::net::SocketAddr, String>) {
+    for (ip, ports) in shown {
+        for port in ports {
+            let socket = std::net::SocketAddr::new(*ip, *port);
+            if let Some(banner) = banners.get(&socket) {
+                println!("{socket} -> {banner}");
+            }
+        }
+    }
+}
+
+/// Prints the MAC address and vendor of the targets in the neighbor cache.
+fn lookup_devices(opts: &Opts, ips: &[IpAddr]) -> BTreeMap<IpAddr, Device> {
+    let devices = neighbors::devices(ips, opts.oui_file.as_deref());
+    if devices.is_empty() {
+        detail!(
+            "No target is in the neighbor cache, MAC addresses are only known on the local network",
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    for (ip, device) in &devices {
+        detail!(
+            format!(
+                "{ip} is {} ({})",
+                device.mac,
+                device.vendor.as_deref().unwrap_or("unknown vendor")
+            ),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    devices
+}
+
+/// Sums up the findings of every network and hostname among the targets.
+fn print_rollups(
+    opts: &Opts,
+    ips: &[IpAddr],
+    ports_per_ip: &HashMap<IpAddr, Vec<u16>>,
+) -> Vec<Rollup> {
+    let groups = Rollup::groups(opts);
+    if groups.is_empty() {
+        return Vec::new();
+    }
+    let hosts: Vec<HostReport> = ports_per_ip
+        .iter()
+        .map(|(ip, ports)| HostReport {
+            ip: *ip,
+            ports: ports.clone(),
+            script_output: Vec::new(),
+        })
+        .collect();
+    let rollups = Rollup::summarize(groups, ips, &hosts);
+    for rollup in &rollups {
+        detail!(rollup.to_string(), opts.greppable, opts.accessible);
+    }
+    rollups
+}
+
+/// Compares the open ports of hostnames on IPv4 and IPv6, warning about
+/// ports open on only one of them.
+fn print_dual_stack(opts: &Opts, ports_per_ip: &HashMap<IpAddr, Vec<u16>>) {
+    if opts.greppable {
+        return;
+    }
+    for (host, v4, v6) in address::dual_stack_hosts(opts) {
+        let v4_ports = ports_per_ip.get(&v4).map_or(&[][..], Vec::as_slice);
+        let v6_ports = ports_per_ip.get(&v6).map_or(&[][..], Vec::as_slice);
+        let (only_v4, only_v6) = address::stack_differences(v4_ports, v6_ports);
+        if only_v4.is_empty() && only_v6.is_empty() {
+            detail!(
+                format!("{host} has the same ports open on {v4} and {v6}"),
+                opts.greppable,
+                opts.accessible
+            );
+            continue;
+        }
+        let join = |ports: &[u16]| {
+            ports
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if !only_v4.is_empty() {
+            warning!(
+                format!("{host}: {} open on IPv4 ({v4}) only", join(&only_v4)),
+                opts.greppable,
+                opts.accessible
+            );
+        }
+        if !only_v6.is_empty() {
+            warning!(
+                format!("{host}: {} open on IPv6 ({v6}) only", join(&only_v6)),
+                opts.greppable,
+                opts.accessible
+            );
+        }
+    }
+}
+
+/// Locks the file outputs against other runs writing them. A locked one
+/// aborts the scan, unless `--force` is given.
+fn lock_outputs(
+    opts: &Opts,
+    started: chrono::DateTime<chrono::Utc>,
+    files: &FileOptions,
+) -> Vec<std::fs::File> {
+    let mut paths: Vec<PathBuf> = opts
+        .output
+        .iter()
+        .filter_map(|target| target.file(started, files))
+        .collect();
+    paths.sort();
+    paths.dedup();
+    let mut locks = Vec::new();
+    for path in paths {
+        match file::lock(&path) {
+            Ok(lock) => locks.extend(lock),
+            Err(e) if opts.force => warning!(
+                format!("{e}, writing it anyway (--force)"),
+                opts.greppable,
+                opts.accessible
+            ),
+            Err(e) => {
+                warning!(
+                    format!("{e}. Wait for it to finish, or use --force to write it anyway."),
+                    opts.greppable,
+                    opts.accessible
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+    locks
+}
+
+/// Creates the --result-shards directory, if any. Failing to aborts the
+/// scan rather than losing its results.
+fn create_result_shards(opts: &Opts) -> Option<ResultShards> {
+    let dir = opts.result_shards.as_ref()?;
+    match ResultShards::create(dir) {
+        Ok(shards) => {
+            if !opts.output.is_empty() || opts.scripts != ScriptsRequired::None {
+                detail!(
+                    "Results are written to shards, scripts and --output are skipped",
+                    opts.greppable,
+                    opts.accessible
+                );
+            }
+            Some(shards)
+        }
+        Err(e) => {
+            warning!(
+                format!("Could not write shards to {}: {e}", dir.display()),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Fails before the scan if the `--report-template` can't be compiled,
+/// rather than after it when the report is written.
+fn check_report_template(opts: &Opts) {
+    let Some(path) = &opts.report_template else {
+        return;
+    };
+    if let Err(e) = ReportTemplate::load(path) {
+        warning!(format!("{e:#}"), opts.greppable, opts.accessible);
+        std::process::exit(1);
+    }
+}
+
+/// Merges the shards of the scan, printing every host with its open ports,
+/// then the `unsharded` ones that couldn't be written to a shard.
+fn print_sharded_results(
+    opts: &Opts,
+    dir: &Path,
+    plugins: &[Plugin],
+    unsharded: &[std::net::SocketAddr],
+) {
+    let print_host = |ip: IpAddr, ports: &[u16]| {
+        for plugin in plugins {
+            for port in ports {
+                plugin.on_open_port(std::net::SocketAddr::new(ip, *port));
+            }
+        }
+        let ports: Vec<String> = ports.iter().map(ToString::to_string).collect();
+        println!("{} -> [{}]", ip, ports.join(","));
+    };
+    let merged = shards::aggregate(dir, |ip, ports| print_host(ip, &ports));
+    if !unsharded.is_empty() {
+        warning!(
+            format!(
+                "{} open ports could not be written to shards",
+                unsharded.len()
+            ),
+            opts.greppable,
+            opts.accessible
+        );
+        let mut hosts: BTreeMap<IpAddr, Vec<u16>> = BTreeMap::new();
+        for socket in unsharded {
+            hosts.entry(socket.ip()).or_default().push(socket.port());
+        }
+        for (ip, ports) in hosts {
+            print_host(ip, &ports);
+        }
+    }
+    match merged {
+        Ok(hosts) => detail!(
+            format!(
+                "{hosts} hosts with open ports, sharded in {}",
+                dir.display()
+            ),
+            opts.greppable,
+            opts.accessible
+        ),
+        Err(e) => warning!(
+            format!("Could not merge the shards in {}: {e}", dir.display()),
+            opts.greppable,
+            opts.accessible
+        ),
+    }
+}
 
-    let scripts_to_run: Vec<ScriptFile> = match init_scripts(&opts.scripts) {
-        Ok(scripts_to_run) => scripts_to_run,
+/// The TCP engine a SYN scan runs with. Without raw sockets it connects
+/// instead, unless `--stateless` needs the SYNs.
+fn syn_fallback(opts: &Opts, raw_sockets: io::Result<()>) -> Result<TcpEngine, String> {
+    match raw_sockets {
+        Ok(()) => Ok(TcpEngine::Syn),
+        Err(e) if opts.stateless => Err(privileges::explain(Feature::Syn, &e)),
         Err(e) => {
             warning!(
-                format!("Initiating scripts failed!\n{e}"),
+                format!(
+                    "{} Falling back to connect scans.",
+                    privileges::explain(Feature::Syn, &e)
+                ),
                 opts.greppable,
                 opts.accessible
             );
-            std::process::exit(1);
+            Ok(TcpEngine::Connect)
         }
-    };
-
-    debug!("Scripts initialized {:?}", &scripts_to_run);
-
-    if !opts.greppable && !opts.accessible && !opts.no_banner {
-        print_opening(&opts);
     }
+}
 
-    let ips: Vec<IpAddr> = parse_addresses(&opts);
-
-    if ips.is_empty() {
+/// Installs NOTRACK rules for the SYN engine's source ports if asked to,
+/// otherwise points out `--notrack` when connection tracking is active.
+fn notrack_rules(opts: &Opts, scanner: &Scanner) -> Option<NotrackRules> {
+    let syn = opts.tcp_engine == TcpEngine::Syn && !opts.udp;
+    if !opts.notrack {
+        if syn && notrack::conntrack_active() {
+            detail!(
+                "Connection tracking is active, a large SYN scan can fill its table and get packets dropped. --notrack bypasses it for the scan",
+                opts.greppable,
+                opts.accessible
+            );
+        }
+        return None;
+    }
+    if !syn {
         warning!(
-            "No IPs could be resolved, aborting scan.",
+            "--notrack only applies to TCP scans with --tcp-engine syn",
             opts.greppable,
             opts.accessible
         );
-        std::process::exit(1);
+        return None;
     }
-
-    #[cfg(unix)]
-    let batch_size: usize = infer_batch_size(&opts, adjust_ulimit_size(&opts));
-
-    #[cfg(not(unix))]
-    let batch_size: usize = AVERAGE_BATCH_SIZE;
-
-    let scanner = Scanner::new(
-        &ips,
-        batch_size,
-        Duration::from_millis(opts.timeout.into()),
-        opts.tries,
-        opts.greppable,
-        PortStrategy::pick(&opts.range, opts.ports, opts.scan_order),
-        opts.accessible,
-        opts.exclude_ports.unwrap_or_default(),
-        opts.udp,
-    );
-    debug!("Scanner finished building: {scanner:?}");
-
-    let mut portscan_bench = NamedTimer::start("Portscan");
-    let scan_result = block_on(scanner.run());
-    portscan_bench.end();
-    benchmarks.push(portscan_bench);
-
-    let mut ports_per_ip = HashMap::new();
-
-    for socket in scan_result {
-        ports_per_ip
-            .entry(socket.ip())
-            .or_insert_with(Vec::new)
-            .push(socket.port());
+    let ports = scanner.syn_source_ports();
+    match NotrackRules::install(ports.clone()) {
+        Ok(rules) => {
+            detail!(
+                format!(
+                    "Added NOTRACK rules for source ports {}-{}",
+                    ports.start(),
+                    ports.end()
+                ),
+                opts.greppable,
+                opts.accessible
+            );
+            Some(rules)
+        }
+        Err(e) => {
+            warning!(
+                format!("Could not add NOTRACK rules, scanning with connection tracking: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            None
+        }
     }
+}
 
-    for ip in ips {
-        if ports_per_ip.contains_key(&ip) {
-            continue;
+/// Sends magic packets to the targets in the --wol file and waits for them
+/// to boot.
+fn wake_targets(opts: &Opts, ips: &[IpAddr]) {
+    let Some(path) = &opts.wol else {
+        return;
+    };
+    let targets = match WakeList::read(path, &opts.resolver) {
+        Ok(list) => list.targets(ips),
+        Err(e) => {
+            warning!(
+                format!("Invalid Wake-on-LAN file: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
         }
-
-        // If we got here it means the IP was not found within the HashMap, this
-        // means the scan couldn't find any open ports for it.
-
-        let x = format!("Looks like I didn't find any open ports for {:?}. This is usually caused by a high batch size.
-        \n*I used {} batch size, consider lowering it with {} or a comfortable number for your system.
-        \n Alternatively, increase the timeout if your ping is high. Rustscan -t 2000 for 2000 milliseconds (2s) timeout.\n",
-        ip,
-        opts.batch_size,
-        "'rustscan -b <batch_size> -a <ip address>'");
-        warning!(x, opts.greppable, opts.accessible);
+    };
+    if targets.is_empty() {
+        warning!(
+            "No target is in the Wake-on-LAN file",
+            opts.greppable,
+            opts.accessible
+        );
+        return;
     }
+    match wol::wake(&targets) {
+        Ok(sent) => {
+            detail!(
+                format!(
+                    "Sent {sent} magic packets, waiting {}s for the targets to wake up",
+                    opts.wol_wait
+                ),
+                opts.greppable,
+                opts.accessible
+            );
+            std::thread::sleep(Duration::from_secs(opts.wol_wait.into()));
+        }
+        Err(e) => warning!(format!("{e}"), opts.greppable, opts.accessible),
+    }
+}
 
-    let mut script_bench = NamedTimer::start("Scripts");
-    for (ip, ports) in &ports_per_ip {
-        let vec_str_ports: Vec<String> = ports.iter().map(ToString::to_string).collect();
+/// Reads the baseline file, if any. A broken one aborts the scan rather
+/// than reporting accepted ports as new.
+fn read_baseline(opts: &Opts) -> Option<Baseline> {
+    let path = opts.baseline.as_ref()?;
+    match Baseline::read(path, &opts.resolver) {
+        Ok(baseline) => Some(baseline),
+        Err(e) => {
+            warning!(
+                format!("Invalid baseline file: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    }
+}
 
-        // nmap port style is 80,443. Comma separated with no spaces.
-        let ports_str = vec_str_ports.join(",");
+/// Reads the firewall policy, if any. A broken one aborts the scan rather
+/// than reporting every open port as drift.
+fn read_policy(opts: &Opts) -> Option<Policy> {
+    let path = opts.policy.as_ref()?;
+    match Policy::read(path) {
+        Ok(policy) => Some(policy),
+        Err(e) => {
+            warning!(
+                format!("Invalid policy file: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    }
+}
 
-        // if option scripts is none, no script will be spawned
-        if opts.greppable || opts.scripts == ScriptsRequired::None {
-            println!("{} -> [{}]", &ip, ports_str);
-            continue;
+/// Compares the open ports to the firewall policy, printing and returning
+/// how they differ.
+fn print_policy_drift(
+    opts: &Opts,
+    policy: &Policy,
+    ports_per_ip: &HashMap<IpAddr, Vec<u16>>,
+) -> Vec<policy::Drift> {
+    let mut open: Vec<std::net::SocketAddr> = ports_per_ip
+        .iter()
+        .flat_map(|(ip, ports)| {
+            ports
+                .iter()
+                .map(move |port| std::net::SocketAddr::new(*ip, *port))
+        })
+        .collect();
+    open.sort_unstable();
+    let excluded = opts.exclude_ports.clone().unwrap_or_default();
+    let mut scanned = PortStrategy::pick(&opts.range, opts.ports.clone(), opts.scan_order).order();
+    scanned.retain(|&port| {
+        !excluded.contains(&port)
+            && (opts.allow_dangerous_ports || !port_strategy::is_dangerous(port))
+    });
+    let drift = policy.drift(&open, &scanned, opts.udp);
+    for entry in &drift {
+        match entry.kind {
+            policy::DriftKind::OpenNotAllowed => warning!(
+                format!("{} is open, but the policy doesn't allow it", entry.socket),
+                opts.greppable,
+                opts.accessible
+            ),
+            policy::DriftKind::AllowedNotOpen => detail!(
+                format!("{} is allowed by the policy, but closed", entry.socket),
+                opts.greppable,
+                opts.accessible
+            ),
         }
-        detail!("Starting Script(s)", opts.greppable, opts.accessible);
+    }
+    detail!(
+        format!("{} ports differ from the policy", drift.len()),
+        opts.greppable,
+        opts.accessible
+    );
+    drift
+}
 
-        // Run all the scripts we found and parsed based on the script config file tags field.
-        for mut script_f in scripts_to_run.clone() {
-            // This part allows us to add commandline arguments to the Script call_format, appending them to the end of the command.
-            if !opts.command.is_empty() {
-                let user_extra_args = &opts.command.join(" ");
-                debug!("Extra args vec {user_extra_args:?}");
-                if script_f.call_format.is_some() {
-                    let mut call_f = script_f.call_format.unwrap();
-                    call_f.push(' ');
-                    call_f.push_str(user_extra_args);
-                    output!(
-                        format!("Running script {:?} on ip {}\nDepending on the complexity of the script, results may take some time to appear.", call_f, &ip),
-                        opts.greppable,
-                        opts.accessible
-                    );
-                    debug!("Call format {call_f}");
-                    script_f.call_format = Some(call_f);
-                }
-            }
+/// Checks the open ports against the `--alert` rules, printing and
+/// returning those that break one.
+fn print_alerts(opts: &Opts, ports_per_ip: &HashMap<IpAddr, Vec<u16>>) -> Vec<alerts::Alert> {
+    if opts.alerts.is_empty() {
+        return Vec::new();
+    }
+    let mut open: Vec<std::net::SocketAddr> = ports_per_ip
+        .iter()
+        .flat_map(|(ip, ports)| {
+            ports
+                .iter()
+                .map(move |port| std::net::SocketAddr::new(*ip, *port))
+        })
+        .collect();
+    open.sort_unstable();
+    let alerts = alerts::check(&opts.alerts, &open);
+    for alert in &alerts {
+        warning!(
+            format!(
+                "{} is open, outside the alert rule {}",
+                alert.socket, alert.rule
+            ),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    detail!(
+        format!("{} open ports raised alerts", alerts.len()),
+        opts.greppable,
+        opts.accessible
+    );
+    alerts
+}
 
-            // Building the script with the arguments from the ScriptFile, and ip-ports.
-            let script = Script::build(
-                script_f.path,
-                *ip,
-                ports.clone(),
-                script_f.port,
-                script_f.ports_separator,
-                script_f.tags,
-                script_f.call_format,
+/// Logs in to the `--via` jump host, exits when that fails or the scan
+/// can't go through it.
+#[cfg(feature = "ssh")]
+fn connect_via(opts: &Opts) -> Option<Arc<SshTunnel>> {
+    let jump = opts.via.as_ref()?;
+    if opts.udp || opts.tcp_engine == TcpEngine::Syn {
+        warning!(
+            "--via only applies to TCP scans with the connect engine",
+            opts.greppable,
+            opts.accessible
+        );
+        std::process::exit(1);
+    }
+    let timeout = Duration::from_millis(opts.timeout.into());
+    match SshTunnel::connect(jump, opts.via_channels, timeout) {
+        Ok(tunnel) => {
+            detail!(
+                format!(
+                    "Connecting to ports through {jump}, {} at a time",
+                    tunnel.channels()
+                ),
+                opts.greppable,
+                opts.accessible
             );
-            match script.run() {
-                Ok(script_result) => {
-                    detail!(script_result.clone(), opts.greppable, opts.accessible);
-                }
-                Err(e) => {
-                    warning!(&format!("Error {e}"), opts.greppable, opts.accessible);
-                }
+            if opts.scripts != ScriptsRequired::None {
+                warning!(
+                    "Scripts run from this machine, not through the jump host",
+                    opts.greppable,
+                    opts.accessible
+                );
             }
+            Some(Arc::new(tunnel))
+        }
+        Err(e) => {
+            warning!(
+                format!("Could not scan through {jump}: {e:#}"),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
         }
     }
+}
 
-    // To use the runtime benchmark, run the process as: RUST_LOG=info ./rustscan
-    script_bench.end();
-    benchmarks.push(script_bench);
-    rustscan_bench.end();
-    benchmarks.push(rustscan_bench);
-    debug!("Benchmarks raw {benchmarks:?}");
-    info!("{}", benchmarks.summary());
+/// `--via` without the tunnel built in.
+#[cfg(not(feature = "ssh"))]
+fn connect_via(opts: &Opts) {
+    if opts.via.is_some() {
+        warning!(
+            "--via needs RustScan built with the ssh feature",
+            opts.greppable,
+            opts.accessible
+        );
+        std::process::exit(1);
+    }
 }
 
-/// Prints the opening title of RustScan
-#[allow(clippy::items_after_statements, clippy::needless_raw_string_hashes)]
-fn print_opening(opts: &Opts) {
-    debug!("Printing opening");
-    let s = r#".----. .-. .-. .----..---.  .----. .---.   .--.  .-. .-.
-| {}  }| { } |{ {__ {_   _}{ {__  /  ___} / {} \ |  `| |
-| .-. \| {_} |.-._} } | |  .-._} }\     }/  /\  \| |\  |
-`-' `-'`-----'`----'  `-'  `----'  `---' `-'  `-'`-' `-'
-The Modern Day Port Scanner."#;
+/// Runs a subcommand instead of a scan, returns the process exit code.
+fn run_subcommand(subcommand: &Commands, opts: &Opts) -> i32 {
+    match subcommand {
+        Commands::Doctor => i32::from(!doctor::run(opts)),
+        Commands::Config {
+            action: ConfigAction::Validate,
+        } => i32::from(!config::validate(opts)),
+        Commands::Config {
+            action: ConfigAction::Init { force },
+        } => i32::from(!config::init(opts, *force)),
+        Commands::Merge {
+            files,
+            output,
+            latest,
+        } => i32::from(!merge::run(files, output, *latest, opts)),
+        Commands::Annotate {
+            file,
+            host,
+            port,
+            note,
+        } => i32::from(!annotate::run(file, *host, *port, note, opts)),
+        Commands::Expand { count } => i32::from(!address::expand(opts, *count)),
+        Commands::Status => i32::from(!daemon::status(opts)),
+        Commands::Stop { pid } => i32::from(!daemon::stop(*pid, opts)),
+        Commands::SelfAudit => i32::from(!self_audit::run(opts)),
+        Commands::SetupCaps { yes } => {
+            privileges::print(opts);
+            i32::from(!privileges::setup_caps(*yes, opts))
+        }
+        Commands::Scripts {
+            action: ScriptsAction::Lint { directory },
+        } => i32::from(!scripts::lint(directory.as_deref(), opts)),
+        Commands::History { limit } => i32::from(!history::list(*limit, opts)),
+        Commands::Rerun { id } => i32::from(!history::rerun(*id, opts)),
+        Commands::Template {
+            action: TemplateAction::Save { name, force, args },
+        } => i32::from(!template::save(name, args, *force, opts)),
+        Commands::Template {
+            action: TemplateAction::List,
+        } => i32::from(!template::list(opts)),
+    }
+}
 
-    println!("{}", s.gradient(Color::Green).bold());
-    let info = r#"________________________________________
-: http://discord.skerritt.blog         :
-: https://github.com/RustScan/RustScan :
- --------------------------------------"#;
-    println!("{}", info.gradient(Color::Yellow).bold());
-    funny_opening!();
+/// Prints the limits derived from the OS and the batch size they lead to.
+fn print_system_check(opts: &Opts) {
+    let limits = SystemLimits::detect();
+    for line in limits.summary() {
+        detail!(line, false, opts.accessible);
+    }
 
-    let config_path = opts
-        .config_path
-        .clone()
-        .unwrap_or_else(input::default_config_path);
+    #[cfg(unix)]
+    let batch_size = infer_batch_size(opts, adjust_ulimit_size(opts));
+    #[cfg(not(unix))]
+    let batch_size = infer_batch_size_from_limits(opts, &limits);
 
-    detail!(
-        format!("The config file is expected to be at {config_path:?}"),
-        opts.greppable,
+    output!(
+        format!("Batch size RustScan will use: {batch_size}"),
+        false,
         opts.accessible
     );
+}
 
-    if opts.config_path.is_none() {
-        let old_config_path = input::old_default_config_path();
-        detail!(
-            format!(
-                "For backwards compatibility, the config file may also be at {old_config_path:?}"
-            ),
+/// Without file descriptor limits, the batch size is bound by how many
+/// sockets the OS can have open at once, which on Windows comes down to
+/// the number of dynamic ports.
+#[cfg(not(unix))]
+fn infer_batch_size_from_limits(opts: &Opts, limits: &SystemLimits) -> usize {
+    let ceiling = limits.socket_ceiling();
+    if opts.batch_size > ceiling {
+        warning!(
+            format!("Batch size is higher than the {ceiling} sockets the OS can have open at once, lowering it to {ceiling}."),
             opts.greppable,
             opts.accessible
         );
+        return ceiling;
     }
+    opts.batch_size
 }
 
 #[cfg(unix)]
@@ -299,7 +2291,12 @@ fn infer_batch_size(opts: &Opts, ulimit: usize) -> usize {
 mod tests {
     #[cfg(unix)]
     use super::{adjust_ulimit_size, infer_batch_size};
-    use super::{print_opening, Opts};
+    use super::{hosts_up, print_opening, syn_fallback, Opts};
+    use rustscan::input::TcpEngine;
+    use rustscan::scanner::stats::ProbeStats;
+    use std::collections::{BTreeMap, HashMap};
+    use std::io;
+    use std::net::IpAddr;
 
     #[test]
     #[cfg(unix)]
@@ -365,6 +2362,22 @@ mod tests {
         assert!(batch_size == opts.batch_size);
     }
 
+    #[test]
+    fn syn_scans_connect_without_raw_sockets() {
+        let denied = || Err(io::Error::from(io::ErrorKind::PermissionDenied));
+        let opts = Opts::default();
+        assert_eq!(syn_fallback(&opts, Ok(())), Ok(TcpEngine::Syn));
+        assert_eq!(syn_fallback(&opts, denied()), Ok(TcpEngine::Connect));
+
+        let opts = Opts {
+            stateless: true,
+            ..Default::default()
+        };
+        assert!(syn_fallback(&opts, denied())
+            .unwrap_err()
+            .starts_with("SYN is unavailable"));
+    }
+
     #[test]
     fn test_print_opening_no_panic() {
         let opts = Opts {
@@ -374,4 +2387,24 @@ mod tests {
         // print opening should not panic
         print_opening(&opts);
     }
+
+    #[test]
+    fn hosts_refusing_connections_are_up() {
+        let ip = |text: &str| text.parse::<IpAddr>().unwrap();
+        let ports_per_ip = HashMap::from([(ip("10.0.0.2"), vec![22])]);
+        let stats = |refused| ProbeStats {
+            refused,
+            ..ProbeStats::default()
+        };
+        let probe_stats = BTreeMap::from([
+            (ip("10.0.0.1"), stats(3)),
+            (ip("10.0.0.2"), stats(1)),
+            (ip("10.0.0.3"), stats(0)),
+        ]);
+
+        assert_eq!(
+            hosts_up(&ports_per_ip, &probe_stats),
+            [(&ip("10.0.0.1"), None), (&ip("10.0.0.2"), Some(&vec![22]))]
+        );
+    }
 }
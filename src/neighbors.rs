@@ -0,0 +1,322 @@
+//! MAC addresses and vendors of targets on the local network.
+//!
+//! Scanning a host on the same network segment makes the OS resolve its
+//! MAC address, which stays in the ARP (IPv4) or neighbor discovery (IPv6)
+//! cache. With `--mac`, RustScan reads that cache after the scan (with
+//! `ip neigh` on Linux, `arp -an` and `ndp -an` on macOS and the BSDs,
+//! `arp -a` on Windows) and looks up the vendor of every MAC address, to
+//! tell what the mystery devices on a network are.
+//!
+//! Vendors come from the first OUI database found: `--oui-file`, nmap's
+//! `nmap-mac-prefixes`, Wireshark's `manuf` or the IEEE `oui.txt`. Without
+//! any, only virtual machines and Raspberry Pis are recognised.
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// OUI databases installed by nmap, Wireshark and the ieee-data package.
+const OUI_FILES: [&str; 7] = [
+    "/usr/share/nmap/nmap-mac-prefixes",
+    "/usr/local/share/nmap/nmap-mac-prefixes",
+    "/opt/homebrew/share/nmap/nmap-mac-prefixes",
+    "C:\\Program Files (x86)\\Nmap\\nmap-mac-prefixes",
+    "/usr/share/wireshark/manuf",
+    "/usr/share/ieee-data/oui.txt",
+    "/usr/share/hwdata/oui.txt",
+];
+
+/// Vendors recognised without an OUI database.
+const KNOWN_VENDORS: [(&str, &str); 12] = [
+    ("000569", "VMware"),
+    ("000c29", "VMware"),
+    ("005056", "VMware"),
+    ("080027", "VirtualBox"),
+    ("525400", "QEMU/KVM"),
+    ("00155d", "Microsoft Hyper-V"),
+    ("00163e", "Xen"),
+    ("001c42", "Parallels"),
+    ("b827eb", "Raspberry Pi Foundation"),
+    ("dca632", "Raspberry Pi Trading"),
+    ("e45f01", "Raspberry Pi Trading"),
+    ("d83add", "Raspberry Pi Trading"),
+];
+
+/// A MAC address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mac(pub [u8; 6]);
+
+impl Mac {
+    /// The vendor part of the address.
+    pub fn oui(self) -> [u8; 3] {
+        [self.0[0], self.0[1], self.0[2]]
+    }
+
+    /// Whether the address was made up rather than assigned by a vendor,
+    /// as with randomized Wi-Fi addresses and container interfaces.
+    pub fn is_locally_administered(self) -> bool {
+        self.0[0] & 0b10 != 0
+    }
+}
+
+impl FromStr for Mac {
+    type Err = String;
+
+    /// Parses `aa:bb:cc:dd:ee:ff` and `aa-bb-cc-dd-ee-ff`, including the
+    /// unpadded `0:1b:...` macOS prints.
+    fn from_str(mac: &str) -> Result<Self, Self::Err> {
+        let groups: Vec<&str> = mac.split([':', '-']).collect();
+        let mut bytes = [0; 6];
+        if groups.len() != bytes.len() {
+            return Err(format!("{mac} is not a MAC address"));
+        }
+        for (byte, group) in bytes.iter_mut().zip(groups) {
+            if group.is_empty() || group.len() > 2 {
+                return Err(format!("{mac} is not a MAC address"));
+            }
+            *byte =
+                u8::from_str_radix(group, 16).map_err(|_| format!("{mac} is not a MAC address"))?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl fmt::Display for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+/// A target found in the neighbor cache.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Device {
+    pub mac: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+}
+
+/// The IPs and MAC addresses in the output of `ip neigh`, `arp` or `ndp`,
+/// or in `/proc/net/arp`. Whatever the format, an entry is a line with an
+/// IP and a MAC address; incomplete entries have no MAC and are skipped.
+pub fn parse_neighbors(output: &str) -> HashMap<IpAddr, Mac> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut tokens = line.split_whitespace();
+            let ip = tokens.by_ref().find_map(|token| {
+                let token = token.trim_start_matches('(').trim_end_matches(')');
+                // Link-local IPv6 addresses carry their interface.
+                let token = token.split('%').next().unwrap_or_default();
+                IpAddr::from_str(token).ok()
+            })?;
+            let mac = tokens.find_map(|token| Mac::from_str(token).ok())?;
+            (mac.0 != [0; 6]).then_some((ip, mac))
+        })
+        .collect()
+}
+
+/// Reads the neighbor cache of the OS.
+#[cfg(not(tarpaulin_include))]
+pub fn read_cache() -> HashMap<IpAddr, Mac> {
+    let commands: &[&[&str]] = if cfg!(target_os = "linux") {
+        &[&["ip", "neigh", "show"]]
+    } else if cfg!(windows) {
+        &[&["arp", "-a"]]
+    } else {
+        &[&["arp", "-an"], &["ndp", "-an"]]
+    };
+    let mut neighbors = HashMap::new();
+    for command in commands {
+        if let Ok(output) = Command::new(command[0]).args(&command[1..]).output() {
+            neighbors.extend(parse_neighbors(&String::from_utf8_lossy(&output.stdout)));
+        }
+    }
+    if neighbors.is_empty() && cfg!(target_os = "linux") {
+        if let Ok(arp) = fs::read_to_string("/proc/net/arp") {
+            neighbors = parse_neighbors(&arp);
+        }
+    }
+    neighbors
+}
+
+/// Vendors by OUI.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OuiDatabase {
+    vendors: HashMap<[u8; 3], String>,
+}
+
+impl OuiDatabase {
+    /// Parses nmap's `nmap-mac-prefixes`, Wireshark's `manuf` or the IEEE
+    /// `oui.txt`. Prefixes longer than an OUI are skipped.
+    pub fn parse(content: &str) -> Self {
+        let mut vendors = HashMap::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((prefix, vendor)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let hex: String = prefix
+                .chars()
+                .filter(|c| !matches!(c, ':' | '-' | '.'))
+                .collect();
+            let Ok(oui) = u32::from_str_radix(&hex, 16) else {
+                continue;
+            };
+            if hex.len() != 6 {
+                continue;
+            }
+            // oui.txt marks the prefix with (hex) or (base 16), manuf has a
+            // short and a long name, the long one last.
+            let vendor = vendor.replace("(hex)", "").replace("(base 16)", "");
+            let vendor = vendor.rsplit('\t').next().unwrap_or_default().trim();
+            if vendor.is_empty() {
+                continue;
+            }
+            let [_, a, b, c] = oui.to_be_bytes();
+            vendors
+                .entry([a, b, c])
+                .or_insert_with(|| vendor.to_owned());
+        }
+        Self { vendors }
+    }
+
+    /// The database in `path`, or in the first OUI file installed, or the
+    /// built-in vendors.
+    pub fn load(path: Option<&Path>) -> Self {
+        let mut candidates: Vec<PathBuf> = path.map(Path::to_path_buf).into_iter().collect();
+        candidates.extend(OUI_FILES.iter().map(PathBuf::from));
+        candidates
+            .iter()
+            .find_map(|path| fs::read_to_string(path).ok())
+            .map(|content| Self::parse(&content))
+            .filter(|database| !database.vendors.is_empty())
+            .unwrap_or_else(Self::built_in)
+    }
+
+    fn built_in() -> Self {
+        let content: String = KNOWN_VENDORS
+            .iter()
+            .map(|(oui, vendor)| format!("{oui} {vendor}\n"))
+            .collect();
+        Self::parse(&content)
+    }
+
+    pub fn vendor(&self, mac: Mac) -> Option<String> {
+        if let Some(vendor) = self.vendors.get(&mac.oui()) {
+            return Some(vendor.clone());
+        }
+        mac.is_locally_administered()
+            .then(|| "Locally administered (randomized or virtual)".to_owned())
+    }
+}
+
+/// The MAC address and vendor of the `ips` found in the neighbor cache.
+#[cfg(not(tarpaulin_include))]
+pub fn devices(ips: &[IpAddr], oui_file: Option<&Path>) -> BTreeMap<IpAddr, Device> {
+    let cache = read_cache();
+    let database = OuiDatabase::load(oui_file);
+    ips.iter()
+        .filter_map(|ip| {
+            let mac = *cache.get(ip)?;
+            Some((
+                *ip,
+                Device {
+                    mac: mac.to_string(),
+                    vendor: database.vendor(mac),
+                },
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_neighbors, Mac, OuiDatabase};
+    use std::net::IpAddr;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_macs() {
+        let mac: Mac = "0:1B:2c:3d:4e:5f".parse().unwrap();
+        assert_eq!(mac.to_string(), "00:1b:2c:3d:4e:5f");
+        assert_eq!("00-1b-2c-3d-4e-5f".parse::<Mac>().unwrap(), mac);
+        assert!("00:1b:2c:3d:4e".parse::<Mac>().is_err());
+        assert!("fe80::1:2:3".parse::<Mac>().is_err());
+        assert!("0x1".parse::<Mac>().is_err());
+        assert!(!mac.is_locally_administered());
+        assert!("02:42:ac:11:00:02"
+            .parse::<Mac>()
+            .unwrap()
+            .is_locally_administered());
+    }
+
+    #[test]
+    fn parse_neighbor_caches() {
+        let linux = "192.168.1.1 dev eth0 lladdr 00:1b:2c:3d:4e:5f REACHABLE\n\
+            192.168.1.9 dev eth0 FAILED\n\
+            fe80::1 dev eth0 lladdr 00:1b:2c:3d:4e:60 router STALE\n";
+        let neighbors = parse_neighbors(linux);
+        assert_eq!(neighbors.len(), 2);
+        assert_eq!(neighbors[&ip("fe80::1")].to_string(), "00:1b:2c:3d:4e:60");
+
+        let proc_arp =
+            "IP address       HW type     Flags       HW address            Mask     Device\n\
+            192.168.1.1      0x1         0x2         00:1b:2c:3d:4e:5f     *        eth0\n\
+            192.168.1.9      0x1         0x0         00:00:00:00:00:00     *        eth0\n";
+        assert_eq!(parse_neighbors(proc_arp).len(), 1);
+
+        let macos = "? (192.168.1.1) at 0:1b:2c:3d:4e:5f on en0 ifscope [ethernet]\n\
+            ? (192.168.1.9) at (incomplete) on en0 ifscope [ethernet]\n";
+        let neighbors = parse_neighbors(macos);
+        assert_eq!(neighbors.len(), 1);
+        assert_eq!(
+            neighbors[&ip("192.168.1.1")].to_string(),
+            "00:1b:2c:3d:4e:5f"
+        );
+
+        let ndp = "fe80::1%en0    0:1b:2c:3d:4e:60  en0 23h59m58s S R\n";
+        assert!(parse_neighbors(ndp).contains_key(&ip("fe80::1")));
+
+        let windows = "Interface: 192.168.1.20 --- 0xb\n  \
+            Internet Address      Physical Address      Type\n  \
+            192.168.1.1           00-1b-2c-3d-4e-5f     dynamic\n";
+        let neighbors = parse_neighbors(windows);
+        assert_eq!(neighbors.len(), 1);
+        assert!(neighbors.contains_key(&ip("192.168.1.1")));
+    }
+
+    #[test]
+    fn vendors_from_oui_files() {
+        let mac: Mac = "00:00:0c:12:34:56".parse().unwrap();
+        for database in [
+            "000000 Xerox\n00000C Cisco Systems\n",
+            "# manuf\n00:00:0C\tCisco\tCisco Systems, Inc\n00:1B:C5:00:00:00/36\tConverge\n",
+            "00-00-0C   (hex)\t\tCisco Systems, Inc\n00000C     (base 16)\t\tCisco Systems, Inc\n",
+        ] {
+            let vendor = OuiDatabase::parse(database).vendor(mac).unwrap();
+            assert!(vendor.starts_with("Cisco Systems"), "{}", vendor);
+        }
+
+        let built_in = OuiDatabase::built_in();
+        assert_eq!(
+            built_in.vendor("08:00:27:aa:bb:cc".parse().unwrap()),
+            Some("VirtualBox".to_owned())
+        );
+        assert_eq!(built_in.vendor(mac), None);
+        assert!(built_in
+            .vendor("02:42:ac:11:00:02".parse().unwrap())
+            .unwrap()
+            .starts_with("Locally administered"));
+    }
+}
@@ -0,0 +1,79 @@
+//! Scans from inside a named network namespace.
+//!
+//! `--netns NAME` moves RustScan into the namespace `ip netns add NAME`
+//! created, `/var/run/netns/NAME`, before targets are resolved, so a
+//! container's or a VPN's network can be scanned without wrapping RustScan
+//! in `ip netns exec`. Namespaces belong to threads: the main thread enters
+//! it, and the threads, scripts and sockets created after that are in it
+//! too. Unlike `ip netns exec`, `/etc/netns/NAME/resolv.conf` isn't mounted
+//! over `/etc/resolv.conf`, use `--resolver` for the namespace's DNS server.
+//!
+//! Entering a namespace needs root (`CAP_SYS_ADMIN`), and Linux.
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where `ip netns` keeps named namespaces.
+pub const NETNS_DIR: &str = "/var/run/netns";
+
+/// The file of the namespace `name`, which must be a plain file name.
+pub fn path(name: &str) -> io::Result<PathBuf> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name:?} isn't a network namespace name"),
+        ));
+    }
+    Ok(Path::new(NETNS_DIR).join(name))
+}
+
+/// Moves the calling thread into the network namespace `name`.
+#[cfg(target_os = "linux")]
+pub fn enter(name: &str) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let path = path(name)?;
+    let namespace = std::fs::File::open(&path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => io::Error::new(
+            e.kind(),
+            format!("no network namespace {name} in {NETNS_DIR}, see `ip netns list`"),
+        ),
+        _ => io::Error::new(e.kind(), format!("{}: {e}", path.display())),
+    })?;
+    // SAFETY: the descriptor is open for the duration of the call.
+    if unsafe { libc::setns(namespace.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+        let e = io::Error::last_os_error();
+        return Err(io::Error::new(
+            e.kind(),
+            format!("entering network namespace {name} needs root: {e}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Moves the calling thread into the network namespace `name`.
+#[cfg(not(target_os = "linux"))]
+pub fn enter(name: &str) -> io::Result<()> {
+    path(name)?;
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "network namespaces are only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{enter, path};
+    use std::path::Path;
+
+    #[test]
+    fn namespaces_are_named_not_paths() {
+        assert_eq!(
+            path("vpn").unwrap(),
+            Path::new("/var/run/netns/vpn").to_path_buf()
+        );
+        assert!(path("../../proc/1/ns/net").is_err());
+        assert!(path("..").is_err());
+        assert!(path("").is_err());
+        assert!(enter("rustscan-no-such-namespace").is_err());
+    }
+}
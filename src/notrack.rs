@@ -0,0 +1,256 @@
+//! Keeps SYN scans out of the kernel's connection tracking.
+//!
+//! On a Linux box running a stateful firewall, every SYN a raw-socket scan
+//! sends, and every answer to it, gets an entry in the conntrack table. A
+//! large scan fills it up, after which the kernel drops new packets for
+//! everything on the machine, scan included. `--notrack` adds NOTRACK rules
+//! to the `raw` table for the scan's source ports before it starts:
+//!
+//! ```text
+//! iptables -t raw -I OUTPUT -p tcp --sport 41000:41003 -j NOTRACK
+//! iptables -t raw -I PREROUTING -p tcp --dport 41000:41003 -j NOTRACK
+//! ```
+//!
+//! The same rules go to `ip6tables`, or to an `inet rustscan_notrack` nft
+//! table where iptables isn't installed. They're removed when the scan
+//! ends, and by a small watchdog process if RustScan is killed before it
+//! can clean up. Changing the firewall is never done without the flag.
+use anyhow::{anyhow, Result};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+
+/// The nft table holding the rules.
+const NFT_TABLE: &str = "rustscan_notrack";
+
+/// A command line, program first.
+type Rule = Vec<String>;
+
+/// Whether connection tracking is loaded, so scans fill its table.
+pub fn conntrack_active() -> bool {
+    Path::new("/proc/sys/net/netfilter/nf_conntrack_max").exists()
+}
+
+/// NOTRACK rules installed for the lifetime of the value.
+#[derive(Debug)]
+pub struct NotrackRules {
+    undo: Vec<Rule>,
+    watchdog: Option<Child>,
+}
+
+impl NotrackRules {
+    /// Adds NOTRACK rules for TCP from and to `ports`, with iptables or
+    /// else nft. Needs root.
+    pub fn install(ports: RangeInclusive<u16>) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            return Err(anyhow!("NOTRACK rules are only supported on Linux"));
+        }
+        let iptables = apply(&iptables_rules(&ports));
+        let undo = match iptables {
+            Ok(()) => iptables_undo(&ports),
+            Err(iptables) => apply(&nft_rules(&ports))
+                .map(|()| nft_undo())
+                .map_err(|nft| anyhow!("{iptables}; {nft}"))?,
+        };
+        let watchdog = spawn_watchdog(&undo).ok();
+        Ok(Self { undo, watchdog })
+    }
+}
+
+impl Drop for NotrackRules {
+    fn drop(&mut self) {
+        if let Some(mut watchdog) = self.watchdog.take() {
+            let _ = watchdog.kill();
+            let _ = watchdog.wait();
+        }
+        for rule in &self.undo {
+            let _ = run(rule);
+        }
+    }
+}
+
+fn rule(words: &[&str]) -> Rule {
+    words.iter().map(|word| (*word).to_owned()).collect()
+}
+
+/// `action` (`-I` or `-D`) of the rules for both address families.
+fn iptables(action: &str, ports: &RangeInclusive<u16>) -> Vec<Rule> {
+    let ports = format!("{}:{}", ports.start(), ports.end());
+    let mut rules = Vec::new();
+    for program in ["iptables", "ip6tables"].iter().copied() {
+        for (chain, side) in [("OUTPUT", "--sport"), ("PREROUTING", "--dport")]
+            .iter()
+            .copied()
+        {
+            rules.push(rule(&[
+                program, "-t", "raw", action, chain, "-p", "tcp", side, &ports, "-j", "NOTRACK",
+            ]));
+        }
+    }
+    rules
+}
+
+fn iptables_rules(ports: &RangeInclusive<u16>) -> Vec<Rule> {
+    iptables("-I", ports)
+}
+
+fn iptables_undo(ports: &RangeInclusive<u16>) -> Vec<Rule> {
+    iptables("-D", ports)
+}
+
+fn nft_rules(ports: &RangeInclusive<u16>) -> Vec<Rule> {
+    let ports = format!("{}-{}", ports.start(), ports.end());
+    let mut rules = vec![rule(&["nft", "add", "table", "inet", NFT_TABLE])];
+    for (chain, side) in [("output", "sport"), ("prerouting", "dport")]
+        .iter()
+        .copied()
+    {
+        rules.push(rule(&[
+            "nft", "add", "chain", "inet", NFT_TABLE, chain, "{", "type", "filter", "hook", chain,
+            "priority", "raw", ";", "}",
+        ]));
+        rules.push(rule(&[
+            "nft", "add", "rule", "inet", NFT_TABLE, chain, "tcp", side, &ports, "notrack",
+        ]));
+    }
+    rules
+}
+
+fn nft_undo() -> Vec<Rule> {
+    vec![rule(&["nft", "delete", "table", "inet", NFT_TABLE])]
+}
+
+fn run(rule: &Rule) -> Result<()> {
+    let output = Command::new(&rule[0])
+        .args(&rule[1..])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| anyhow!("Could not run {}: {e}", rule[0]))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} failed: {}",
+            rule.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Runs every rule, or none: those already run are undone on failure.
+fn apply(rules: &[Rule]) -> Result<()> {
+    for (applied, rule) in rules.iter().enumerate() {
+        if let Err(e) = run(rule) {
+            for undo in rules[..applied].iter().rev().filter_map(reverse) {
+                let _ = run(&undo);
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// The rule undoing `rule`, for those that have one.
+fn reverse(rule: &Rule) -> Option<Rule> {
+    let mut undo = rule.clone();
+    match rule.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [_, "-t", "raw", "-I", ..] => undo[3] = "-D".to_owned(),
+        ["nft", "add", "table", ..] => undo[1] = "delete".to_owned(),
+        _ => return None,
+    }
+    Some(undo)
+}
+
+fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// A shell script waiting for `pid` to exit, then running `undo`.
+fn watchdog_script(pid: u32, undo: &[Rule]) -> String {
+    let undo: Vec<String> = undo
+        .iter()
+        .map(|rule| {
+            let words: Vec<String> = rule.iter().map(|word| shell_quote(word)).collect();
+            format!("{} >/dev/null 2>&1", words.join(" "))
+        })
+        .collect();
+    format!(
+        "while kill -0 {pid} 2>/dev/null; do sleep 1; done; {}",
+        undo.join("; ")
+    )
+}
+
+/// Starts the watchdog in its own process group, so a Ctrl-C meant for
+/// RustScan doesn't take it down too.
+#[cfg(unix)]
+fn spawn_watchdog(undo: &[Rule]) -> std::io::Result<Child> {
+    use std::os::unix::process::CommandExt;
+
+    Command::new("sh")
+        .arg("-c")
+        .arg(watchdog_script(std::process::id(), undo))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+}
+
+#[cfg(not(unix))]
+fn spawn_watchdog(_undo: &[Rule]) -> std::io::Result<Child> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{iptables_rules, iptables_undo, nft_rules, reverse, rule, watchdog_script};
+
+    #[test]
+    fn iptables_rules_cover_both_directions_and_families() {
+        let rules: Vec<String> = iptables_rules(&(41000..=41003))
+            .iter()
+            .map(|rule| rule.join(" "))
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                "iptables -t raw -I OUTPUT -p tcp --sport 41000:41003 -j NOTRACK",
+                "iptables -t raw -I PREROUTING -p tcp --dport 41000:41003 -j NOTRACK",
+                "ip6tables -t raw -I OUTPUT -p tcp --sport 41000:41003 -j NOTRACK",
+                "ip6tables -t raw -I PREROUTING -p tcp --dport 41000:41003 -j NOTRACK",
+            ]
+        );
+        let undo: Vec<_> = iptables_rules(&(41000..=41003))
+            .iter()
+            .filter_map(reverse)
+            .collect();
+        assert_eq!(undo, iptables_undo(&(41000..=41003)));
+    }
+
+    #[test]
+    fn nft_rules_live_in_their_own_table() {
+        let rules: Vec<String> = nft_rules(&(5000..=5000))
+            .iter()
+            .map(|rule| rule.join(" "))
+            .collect();
+        assert_eq!(rules[0], "nft add table inet rustscan_notrack");
+        assert!(rules.contains(
+            &"nft add rule inet rustscan_notrack prerouting tcp dport 5000-5000 notrack".to_owned()
+        ));
+        assert_eq!(
+            reverse(&nft_rules(&(5000..=5000))[0]).unwrap().join(" "),
+            "nft delete table inet rustscan_notrack"
+        );
+        assert_eq!(reverse(&nft_rules(&(5000..=5000))[1]), None);
+    }
+
+    #[test]
+    fn watchdog_undoes_rules_once_the_scan_exits() {
+        let script = watchdog_script(42, &[rule(&["nft", "delete", "table", "it's"])]);
+        assert_eq!(
+            script,
+            "while kill -0 42 2>/dev/null; do sleep 1; done; \
+             'nft' 'delete' 'table' 'it'\\''s' >/dev/null 2>&1"
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Runs nuclei against the web servers among the open ports.
+//!
+//! With `--nuclei`, once the scan is done, every open port gets a plain
+//! HTTP request. Ports answering it are handed to nuclei as `http://` URLs,
+//! and ports answering like a TLS server (an alert, or the "plain HTTP
+//! request was sent to HTTPS port" page of nginx and Apache) as `https://`
+//! ones. With the `tls` feature, ports that don't answer at all get a TLS
+//! handshake to tell.
+//!
+//! nuclei has to be on the `PATH`. It runs with `-jsonl -silent -no-color
+//! -disable-update-check -no-interactsh`, so the scan doesn't reach out to
+//! third-party servers, with the templates of `--nuclei-templates` if given
+//! (its own otherwise) and the nuclei config file of `--nuclei-config`.
+//! What it finds is printed and kept in `nuclei` in reports, next to the
+//! port it was found on.
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// Number of ports checked for HTTP at the same time.
+const PARALLEL_PROBES: usize = 64;
+
+/// Ports served over TLS often enough to assume it of those that don't say.
+const HTTPS_PORTS: [u16; 5] = [443, 4443, 8443, 9443, 10443];
+
+/// Pages web servers answer plain HTTP with on TLS ports.
+const PLAIN_HTTP_ON_TLS: [&str; 3] = [
+    "plain http request was sent to https port",
+    "speaking plain http to an ssl-enabled server",
+    "client sent an http request to an https server",
+];
+
+/// Something a nuclei template matched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Finding {
+    pub socket: SocketAddr,
+    pub template: String,
+    pub name: String,
+    pub severity: String,
+    /// The URL the template matched at.
+    pub matched_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matcher: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extracted: Vec<String>,
+}
+
+/// Whether `answer`, what `port` sent back to a plain HTTP request, comes
+/// from an `http` or `https` server.
+fn scheme_of(answer: &[u8], port: u16) -> Option<&'static str> {
+    let text = String::from_utf8_lossy(answer).to_lowercase();
+    if text.starts_with("http/") {
+        if PLAIN_HTTP_ON_TLS.iter().any(|page| text.contains(page)) {
+            Some("https")
+        } else {
+            Some("http")
+        }
+    } else if answer.starts_with(&[0x15, 0x03]) {
+        // A TLS alert record.
+        Some("https")
+    } else if answer.is_empty() && HTTPS_PORTS.contains(&port) {
+        Some("https")
+    } else {
+        None
+    }
+}
+
+/// The URL nuclei is given for `socket`, if it serves HTTP.
+fn url_of(socket: SocketAddr, timeout: Duration) -> Option<String> {
+    let answer = plain_request(socket, timeout).unwrap_or_default();
+    let scheme = scheme_of(&answer, socket.port())
+        .or_else(|| (answer.is_empty() && speaks_tls(socket, timeout)).then_some("https"))?;
+    Some(format!("{scheme}://{socket}"))
+}
+
+#[cfg(feature = "tls")]
+fn speaks_tls(socket: SocketAddr, timeout: Duration) -> bool {
+    crate::tls::probe(socket, timeout).is_ok()
+}
+
+#[cfg(not(feature = "tls"))]
+fn speaks_tls(_socket: SocketAddr, _timeout: Duration) -> bool {
+    false
+}
+
+/// Sends a plain HTTP request to `socket`, returns the start of the answer,
+/// or of the banner of services that speak first.
+pub(crate) fn plain_request(socket: SocketAddr, timeout: Duration) -> std::io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect_timeout(&socket, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    // One write, so the request isn't split over several segments servers
+    // may read one at a time.
+    let request = format!(
+        "GET / HTTP/1.0\r\nHost: {socket}\r\nUser-Agent: RustScan/{}\r\n\r\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    stream.write_all(request.as_bytes())?;
+    let mut answer = Vec::new();
+    stream.take(2048).read_to_end(&mut answer).or_else(|e| {
+        // Servers that keep the connection open answer until the timeout.
+        if answer.is_empty() {
+            Err(e)
+        } else {
+            Ok(answer.len())
+        }
+    })?;
+    Ok(answer)
+}
+
+/// The URLs of the sockets serving HTTP, a batch at a time, in the order
+/// given.
+pub fn web_urls(sockets: &[SocketAddr], timeout: Duration) -> Vec<(SocketAddr, String)> {
+    let mut urls = Vec::new();
+    for batch in sockets.chunks(PARALLEL_PROBES) {
+        thread::scope(|scope| {
+            let probes: Vec<_> = batch
+                .iter()
+                .map(|socket| scope.spawn(move || (*socket, url_of(*socket, timeout))))
+                .collect();
+            for probe in probes {
+                if let Ok((socket, Some(url))) = probe.join() {
+                    urls.push((socket, url));
+                }
+            }
+        });
+    }
+    urls
+}
+
+/// The nuclei command scanning the URLs listed in `list`.
+pub fn command(list: &Path, templates: Option<&Path>, config: Option<&Path>) -> Command {
+    let mut command = Command::new("nuclei");
+    command.arg("-list").arg(list).args([
+        "-jsonl",
+        "-silent",
+        "-no-color",
+        "-disable-update-check",
+        "-no-interactsh",
+    ]);
+    if let Some(templates) = templates {
+        command.arg("-templates").arg(templates);
+    }
+    if let Some(config) = config {
+        command.arg("-config").arg(config);
+    }
+    command
+}
+
+/// The socket of `url`, among the `urls` nuclei was given.
+fn socket_of(url: &str, urls: &[(SocketAddr, String)]) -> Option<SocketAddr> {
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let socket = authority.parse::<SocketAddr>().ok().or_else(|| {
+        // Default ports may have been dropped from the URL.
+        let port = if scheme == "https" { 443 } else { 80 };
+        let host = authority.trim_start_matches('[').trim_end_matches(']');
+        Some(SocketAddr::new(host.parse().ok()?, port))
+    })?;
+    urls.iter()
+        .any(|(known, _)| *known == socket)
+        .then_some(socket)
+}
+
+/// The findings in the JSON lines nuclei printed, for the `urls` it was
+/// given.
+pub fn parse_findings(output: &str, urls: &[(SocketAddr, String)]) -> Vec<Finding> {
+    let text = |value: &Value| value.as_str().map(ToOwned::to_owned);
+    output
+        .lines()
+        .filter_map(|line| match serde_json::from_str::<Value>(line) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                debug!("Ignoring nuclei output {line:?}: {e}");
+                None
+            }
+        })
+        .filter_map(|result| {
+            let matched_at = text(&result["matched-at"])?;
+            let socket = text(&result["host"])
+                .and_then(|host| socket_of(&host, urls))
+                .or_else(|| socket_of(&matched_at, urls))?;
+            Some(Finding {
+                socket,
+                template: text(&result["template-id"]).unwrap_or_default(),
+                name: text(&result["info"]["name"]).unwrap_or_default(),
+                severity: text(&result["info"]["severity"]).unwrap_or_else(|| "unknown".to_owned()),
+                matched_at,
+                matcher: text(&result["matcher-name"]),
+                extracted: result["extracted-results"]
+                    .as_array()
+                    .map(|values| values.iter().filter_map(text).collect())
+                    .unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// Runs nuclei against `urls`, returns what it found.
+pub fn run(
+    urls: &[(SocketAddr, String)],
+    templates: Option<&Path>,
+    config: Option<&Path>,
+) -> Result<Vec<Finding>> {
+    let list: PathBuf =
+        std::env::temp_dir().join(format!("rustscan-nuclei-{}.txt", std::process::id()));
+    let mut lines: Vec<&str> = urls.iter().map(|(_, url)| url.as_str()).collect();
+    lines.push("");
+    fs::write(&list, lines.join("\n"))?;
+    let output = command(&list, templates, config)
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .output();
+    if let Err(e) = fs::remove_file(&list) {
+        debug!("Could not remove {}: {e}", list.display());
+    }
+    let output = output.map_err(|e| anyhow!("Could not run nuclei: {e}"))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "nuclei failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_findings(
+        &String::from_utf8_lossy(&output.stdout),
+        urls,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{command, parse_findings, scheme_of, web_urls};
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn answers_tell_http_from_https() {
+        assert_eq!(scheme_of(b"HTTP/1.1 200 OK\r\n", 8080), Some("http"));
+        assert_eq!(
+            scheme_of(
+                b"HTTP/1.1 400 Bad Request\r\n\r\n<h1>The plain HTTP request was sent to HTTPS port</h1>",
+                8000
+            ),
+            Some("https")
+        );
+        assert_eq!(
+            scheme_of(&[0x15, 0x03, 0x01, 0x00, 0x02], 5000),
+            Some("https")
+        );
+        assert_eq!(scheme_of(b"", 8443), Some("https"));
+        assert_eq!(scheme_of(b"", 22), None);
+        assert_eq!(scheme_of(b"SSH-2.0-OpenSSH_9.6\r\n", 22), None);
+    }
+
+    #[test]
+    fn web_servers_are_found() {
+        let web = TcpListener::bind("127.0.0.1:0").unwrap();
+        let ssh = TcpListener::bind("127.0.0.1:0").unwrap();
+        let web_socket = web.local_addr().unwrap();
+        let ssh_socket = ssh.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = web.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 512];
+            while !request.ends_with(b"\r\n\r\n") {
+                let length = stream.read(&mut buffer).unwrap();
+                assert_ne!(length, 0, "the request ended before its headers");
+                request.extend_from_slice(&buffer[..length]);
+            }
+            stream.write_all(b"HTTP/1.0 200 OK\r\n\r\nhi").unwrap();
+        });
+        thread::spawn(move || {
+            let (mut stream, _) = ssh.accept().unwrap();
+            stream.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").unwrap();
+        });
+
+        let urls = web_urls(&[web_socket, ssh_socket], Duration::from_secs(2));
+        assert_eq!(urls, vec![(web_socket, format!("http://{web_socket}"))]);
+    }
+
+    #[test]
+    fn findings_are_read_from_json_lines() {
+        let web: SocketAddr = "10.0.0.5:8080".parse().unwrap();
+        let tls: SocketAddr = "10.0.0.5:443".parse().unwrap();
+        let urls = vec![
+            (web, "http://10.0.0.5:8080".to_owned()),
+            (tls, "https://10.0.0.5:443".to_owned()),
+        ];
+        let output = r#"{"template-id":"exposed-panel","info":{"name":"Exposed panel","severity":"medium"},"host":"http://10.0.0.5:8080","matched-at":"http://10.0.0.5:8080/admin","matcher-name":"title","extracted-results":["Admin"]}
+[INF] not json
+{"template-id":"tls-version","info":{"name":"TLS version","severity":"info"},"matched-at":"https://10.0.0.5/"}
+{"template-id":"elsewhere","info":{"name":"Not ours","severity":"high"},"matched-at":"http://10.9.9.9:80/"}"#;
+
+        let findings = parse_findings(output, &urls);
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].socket, web);
+        assert_eq!(findings[0].template, "exposed-panel");
+        assert_eq!(findings[0].severity, "medium");
+        assert_eq!(findings[0].matched_at, "http://10.0.0.5:8080/admin");
+        assert_eq!(findings[0].matcher.as_deref(), Some("title"));
+        assert_eq!(findings[0].extracted, vec!["Admin"]);
+        assert_eq!(findings[1].socket, tls);
+        assert_eq!(findings[1].matcher, None);
+    }
+
+    #[test]
+    fn nuclei_runs_quietly_with_the_templates_given() {
+        let command = command(
+            Path::new("urls.txt"),
+            Some(Path::new("/templates")),
+            Some(Path::new("nuclei.yaml")),
+        );
+        let args: Vec<_> = command
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect();
+        assert_eq!(command.get_program(), "nuclei");
+        assert_eq!(
+            args,
+            [
+                "-list",
+                "urls.txt",
+                "-jsonl",
+                "-silent",
+                "-no-color",
+                "-disable-update-check",
+                "-no-interactsh",
+                "-templates",
+                "/templates",
+                "-config",
+                "nuclei.yaml"
+            ]
+        );
+    }
+}
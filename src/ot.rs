@@ -0,0 +1,454 @@
+//! Identifies industrial devices behind open OT (operational technology)
+//! ports.
+//!
+//! With `--ot-probes`, once the scan is done, open ports of these protocols
+//! get the requests their devices answer with who they are, and nothing
+//! else, so PLCs and controllers can be inventoried without being written
+//! to or stopped:
+//!
+//! - Modbus/TCP (502): Read Device Identification, vendor, product code
+//!   and revision,
+//! - S7comm (102): a COTP connection and S7 setup, then the module
+//!   identification and component identification lists (SZL `0x0011` and
+//!   `0x001c`), order number, module name and serial number,
+//! - BACnet/IP (UDP 47808, on every host with open ports, or those with it
+//!   open in UDP scans): ReadProperty of the object name, vendor, model and
+//!   firmware revision of the device object,
+//! - DNP3 (20000): a link status request to link address 1, the usual
+//!   default of outstations, which tells the address it answers from.
+//!
+//! Devices are probed one at a time, at most one per [`PROBE_INTERVAL`],
+//! since some of them don't cope with more. With `--ot-safe`, the scan
+//! itself also probes these ports one at a time and only once, see
+//! [`OT_PORTS`].
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const S7_PORT: u16 = 102;
+pub const MODBUS_PORT: u16 = 502;
+pub const DNP3_PORT: u16 = 20000;
+pub const BACNET_PORT: u16 = 47808;
+
+/// The ports of OT protocols, which `--ot-safe` scans one at a time and
+/// without retries.
+pub const OT_PORTS: [u16; 4] = [S7_PORT, MODBUS_PORT, DNP3_PORT, BACNET_PORT];
+
+/// The time between the start of two device probes.
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Modbus Read Device Identification (function 0x2b, MEI 0x0e), basic
+/// objects from the first, to the unit that is the device itself.
+const MODBUS_IDENTIFICATION: [u8; 11] = [
+    0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0xff, 0x2b, 0x0e, 0x01, 0x00,
+];
+
+/// COTP connection request to rack 0, slot 2.
+const S7_CONNECT: [u8; 22] = [
+    0x03, 0x00, 0x00, 0x16, 0x11, 0xe0, 0x00, 0x00, 0x00, 0x14, 0x00, 0xc1, 0x02, 0x01, 0x00, 0xc2,
+    0x02, 0x01, 0x02, 0xc0, 0x01, 0x0a,
+];
+
+/// S7 setup communication.
+const S7_SETUP: [u8; 25] = [
+    0x03, 0x00, 0x00, 0x19, 0x02, 0xf0, 0x80, 0x32, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00,
+    0x00, 0xf0, 0x00, 0x00, 0x01, 0x00, 0x01, 0x01, 0xe0,
+];
+
+/// The names of the objects Modbus devices identify themselves with.
+const MODBUS_OBJECTS: [&str; 7] = [
+    "vendor",
+    "product_code",
+    "revision",
+    "vendor_url",
+    "product_name",
+    "model_name",
+    "application_name",
+];
+
+/// The BACnet device properties read, and their names.
+const BACNET_PROPERTIES: [(u8, &str); 4] = [
+    (77, "object_name"),
+    (121, "vendor"),
+    (70, "model_name"),
+    (44, "firmware_revision"),
+];
+
+/// What an industrial device said about itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Device {
+    pub socket: SocketAddr,
+    /// `modbus`, `s7`, `bacnet` or `dnp3`.
+    pub protocol: String,
+    /// What it answered, such as its vendor, order number or revision.
+    pub identity: BTreeMap<String, String>,
+}
+
+/// Keeps the printable part of `value`, trimmed.
+fn text(value: &[u8]) -> String {
+    value
+        .iter()
+        .take_while(|byte| byte.is_ascii_graphic() || **byte == b' ')
+        .map(|byte| char::from(*byte))
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
+
+/// The identification objects in a Modbus answer, or the exception code
+/// the device refused with.
+fn modbus_identity(answer: &[u8]) -> Option<BTreeMap<String, String>> {
+    let mut identity = BTreeMap::new();
+    match answer.get(7..)? {
+        [0x2b, 0x0e, _, _, _, _, count, objects @ ..] => {
+            let mut objects = objects;
+            for _ in 0..*count {
+                let [id, length, rest @ ..] = objects else {
+                    break;
+                };
+                let value = rest.get(..usize::from(*length))?;
+                let name = MODBUS_OBJECTS
+                    .get(usize::from(*id))
+                    .map_or_else(|| format!("object_{id}"), |name| (*name).to_owned());
+                identity.insert(name, text(value));
+                objects = &rest[usize::from(*length)..];
+            }
+        }
+        [0xab, _, code, ..] | [0xab, code] => {
+            identity.insert("exception".to_owned(), code.to_string());
+        }
+        _ => return None,
+    }
+    Some(identity)
+}
+
+/// Reads one TPKT packet, as S7comm sends them.
+fn read_tpkt(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut header = [0; 4];
+    stream.read_exact(&mut header)?;
+    let length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+    let mut packet = header.to_vec();
+    packet.resize(length.max(header.len()), 0);
+    stream.read_exact(&mut packet[header.len()..])?;
+    Ok(packet)
+}
+
+/// An S7 request for the system status list `id`.
+fn szl_request(id: u16) -> Vec<u8> {
+    let mut request = vec![
+        0x03, 0x00, 0x00, 0x21, 0x02, 0xf0, 0x80, 0x32, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08,
+        0x00, 0x08, 0x00, 0x01, 0x12, 0x04, 0x11, 0x44, 0x01, 0x00, 0xff, 0x09, 0x00, 0x04,
+    ];
+    request.extend_from_slice(&id.to_be_bytes());
+    request.extend_from_slice(&[0x00, 0x01]);
+    request
+}
+
+/// The records of a system status list answer, by index.
+fn szl_records(answer: &[u8]) -> Vec<(u16, String)> {
+    // After the return code and transport size: the data length, the list
+    // ID and index, then the length and count of its records.
+    let Some(start) = answer.windows(2).position(|window| window == [0xff, 0x09]) else {
+        return Vec::new();
+    };
+    let Some([_, _, _, _, _, _, length_high, length_low, count_high, count_low, records @ ..]) =
+        answer.get(start + 2..)
+    else {
+        return Vec::new();
+    };
+    let length = usize::from(u16::from_be_bytes([*length_high, *length_low]));
+    let count = usize::from(u16::from_be_bytes([*count_high, *count_low]));
+    if length < 2 {
+        return Vec::new();
+    }
+    records
+        .chunks(length)
+        .take(count)
+        .filter_map(|record| {
+            let [index_high, index_low, value @ ..] = record else {
+                return None;
+            };
+            Some((u16::from_be_bytes([*index_high, *index_low]), text(value)))
+        })
+        .collect()
+}
+
+fn s7_identity(socket: SocketAddr, timeout: Duration) -> io::Result<BTreeMap<String, String>> {
+    let mut stream = connect(socket, timeout)?;
+    stream.write_all(&S7_CONNECT)?;
+    let confirm = read_tpkt(&mut stream)?;
+    if confirm.get(5) != Some(&0xd0) {
+        return Err(io::Error::other("no COTP connection confirm"));
+    }
+    stream.write_all(&S7_SETUP)?;
+    let setup = read_tpkt(&mut stream)?;
+    if setup.get(7) != Some(&0x32) {
+        return Err(io::Error::other("no S7 setup answer"));
+    }
+    let mut identity = BTreeMap::new();
+    stream.write_all(&szl_request(0x0011))?;
+    for (index, value) in szl_records(&read_tpkt(&mut stream)?) {
+        if index == 1 && !value.is_empty() {
+            identity.insert("order_number".to_owned(), value);
+        }
+    }
+    stream.write_all(&szl_request(0x001c))?;
+    for (index, value) in szl_records(&read_tpkt(&mut stream)?) {
+        let name = match index {
+            1 => "system_name",
+            2 => "module_name",
+            3 => "plant_id",
+            4 => "copyright",
+            5 => "serial_number",
+            7 => "module_type",
+            _ => continue,
+        };
+        if !value.is_empty() {
+            identity.insert(name.to_owned(), value);
+        }
+    }
+    Ok(identity)
+}
+
+/// A BACnet ReadProperty of `property` of any device object.
+fn bacnet_request(property: u8) -> [u8; 17] {
+    [
+        // BVLC: BACnet/IP, original unicast, length.
+        0x81, 0x0a, 0x00, 0x11, // NPDU: version 1, expecting a reply.
+        0x01, 0x04, // APDU: confirmed ReadProperty, invoke ID 1.
+        0x00, 0x05, 0x01, 0x0c,
+        // Device object, instance 4194303 (any), then the property.
+        0x0c, 0x02, 0x3f, 0xff, 0xff, 0x19, property,
+    ]
+}
+
+/// The character string in a BACnet ReadProperty answer.
+fn bacnet_string(answer: &[u8]) -> Option<String> {
+    // The value is after the opening tag 3, in a ReadProperty complex ACK.
+    let apdu = answer.get(6..)?;
+    if apdu.first()? & 0xf0 != 0x30 {
+        return None;
+    }
+    let start = apdu.iter().position(|byte| *byte == 0x3e)? + 1;
+    let tag = *apdu.get(start)?;
+    if tag >> 4 != 7 {
+        return None;
+    }
+    let (length, value) = match tag & 0x07 {
+        5 => (usize::from(*apdu.get(start + 1)?), start + 2),
+        length => (usize::from(length), start + 1),
+    };
+    // The first byte is the character set.
+    let value = apdu.get(value + 1..value + length)?;
+    Some(text(value))
+}
+
+fn bacnet_identity(ip: IpAddr, timeout: Duration) -> io::Result<BTreeMap<String, String>> {
+    let local: SocketAddr = match ip {
+        IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.connect((ip, BACNET_PORT))?;
+    let mut identity = BTreeMap::new();
+    for (property, name) in BACNET_PROPERTIES {
+        socket.send(&bacnet_request(property))?;
+        let mut answer = [0; 1500];
+        let length = match socket.recv(&mut answer) {
+            Ok(length) => length,
+            // Devices that don't answer the first property aren't asked
+            // for the others.
+            Err(e) if identity.is_empty() && property == BACNET_PROPERTIES[0].0 => return Err(e),
+            Err(_) => continue,
+        };
+        if let Some(value) = bacnet_string(&answer[..length]) {
+            identity.insert(name.to_owned(), value);
+        }
+    }
+    Ok(identity)
+}
+
+/// The DNP3 CRC of `data`, as sent, low byte first.
+fn dnp3_crc(data: &[u8]) -> [u8; 2] {
+    let mut crc: u16 = 0;
+    for byte in data {
+        crc ^= u16::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xa6bc
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    (!crc).to_le_bytes()
+}
+
+/// A DNP3 link status request to `destination`, from master address 0.
+fn dnp3_link_status(destination: u16) -> Vec<u8> {
+    let mut frame = vec![0x05, 0x64, 0x05, 0xc9];
+    frame.extend_from_slice(&destination.to_le_bytes());
+    frame.extend_from_slice(&0_u16.to_le_bytes());
+    let crc = dnp3_crc(&frame);
+    frame.extend_from_slice(&crc);
+    frame
+}
+
+/// The link address a DNP3 outstation answered from.
+fn dnp3_source(answer: &[u8]) -> Option<u16> {
+    match answer {
+        [0x05, 0x64, _, _, _, _, low, high, ..] => Some(u16::from_le_bytes([*low, *high])),
+        _ => None,
+    }
+}
+
+fn dnp3_identity(socket: SocketAddr, timeout: Duration) -> io::Result<BTreeMap<String, String>> {
+    let mut stream = connect(socket, timeout)?;
+    stream.write_all(&dnp3_link_status(1))?;
+    let mut answer = [0; 10];
+    stream.read_exact(&mut answer)?;
+    let source = dnp3_source(&answer).ok_or_else(|| io::Error::other("not a DNP3 frame"))?;
+    let mut identity = BTreeMap::new();
+    identity.insert("address".to_owned(), source.to_string());
+    Ok(identity)
+}
+
+fn modbus_device(socket: SocketAddr, timeout: Duration) -> io::Result<BTreeMap<String, String>> {
+    let mut stream = connect(socket, timeout)?;
+    stream.write_all(&MODBUS_IDENTIFICATION)?;
+    let mut answer = vec![0; 260];
+    let length = stream.read(&mut answer)?;
+    modbus_identity(&answer[..length]).ok_or_else(|| io::Error::other("not a Modbus answer"))
+}
+
+fn connect(socket: SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(&socket, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    Ok(stream)
+}
+
+/// Identifies the devices behind the open `sockets` of OT protocols, and
+/// BACnet on their hosts, or on those with it open for UDP scans, one at a
+/// time, at most one per `interval`.
+pub fn identify_all(
+    sockets: &[SocketAddr],
+    udp: bool,
+    timeout: Duration,
+    interval: Duration,
+) -> Vec<Device> {
+    let mut targets: Vec<(SocketAddr, &str)> = Vec::new();
+    if !udp {
+        for socket in sockets {
+            match socket.port() {
+                MODBUS_PORT => targets.push((*socket, "modbus")),
+                S7_PORT => targets.push((*socket, "s7")),
+                DNP3_PORT => targets.push((*socket, "dnp3")),
+                _ => {}
+            }
+        }
+    }
+    let hosts: BTreeSet<IpAddr> = sockets
+        .iter()
+        .filter(|socket| !udp || socket.port() == BACNET_PORT)
+        .map(SocketAddr::ip)
+        .collect();
+    targets.extend(
+        hosts
+            .into_iter()
+            .map(|ip| (SocketAddr::new(ip, BACNET_PORT), "bacnet")),
+    );
+
+    let mut devices = Vec::new();
+    let mut last: Option<Instant> = None;
+    for (socket, protocol) in targets {
+        if let Some(last) = last {
+            thread::sleep(interval.saturating_sub(last.elapsed()));
+        }
+        last = Some(Instant::now());
+        let identity = match protocol {
+            "modbus" => modbus_device(socket, timeout),
+            "s7" => s7_identity(socket, timeout),
+            "dnp3" => dnp3_identity(socket, timeout),
+            _ => bacnet_identity(socket.ip(), timeout),
+        };
+        match identity {
+            Ok(identity) if !identity.is_empty() => devices.push(Device {
+                socket,
+                protocol: protocol.to_owned(),
+                identity,
+            }),
+            Ok(_) => debug!("{socket} didn't identify itself over {protocol}"),
+            Err(e) => debug!("{protocol} identification of {socket} failed: {e}"),
+        }
+    }
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        bacnet_request, bacnet_string, dnp3_crc, dnp3_link_status, dnp3_source, modbus_identity,
+        szl_records,
+    };
+
+    #[test]
+    fn modbus_identification_objects() {
+        let mut answer = vec![0x00, 0x01, 0x00, 0x00, 0x00, 0x1d, 0xff];
+        answer.extend_from_slice(&[0x2b, 0x0e, 0x01, 0x01, 0x00, 0x00, 0x03]);
+        answer.extend_from_slice(b"\x00\x0dSchneider Ele");
+        answer.extend_from_slice(b"\x01\x04BMXP");
+        answer.extend_from_slice(b"\x02\x04v2.7");
+        let identity = modbus_identity(&answer).unwrap();
+        assert_eq!(identity["vendor"], "Schneider Ele");
+        assert_eq!(identity["product_code"], "BMXP");
+        assert_eq!(identity["revision"], "v2.7");
+
+        let refused = [0x00, 0x01, 0x00, 0x00, 0x00, 0x03, 0xff, 0xab, 0x01];
+        assert_eq!(modbus_identity(&refused).unwrap()["exception"], "1");
+        assert_eq!(modbus_identity(b"HTTP/1.1 400"), None);
+    }
+
+    #[test]
+    fn s7_module_identification() {
+        let mut answer = vec![0x03, 0x00, 0x00, 0x00, 0x02, 0xf0, 0x80, 0x32, 0x07];
+        answer.extend_from_slice(&[0; 20]);
+        answer.extend_from_slice(&[0xff, 0x09, 0x00, 0x40, 0x00, 0x11, 0x00, 0x00]);
+        answer.extend_from_slice(&[0x00, 0x1c, 0x00, 0x02]);
+        let mut record = vec![0x00, 0x01];
+        record.extend_from_slice(b"6ES7 315-2EH14-0AB0 ");
+        record.extend_from_slice(&[0x00, 0xc0, 0x00, 0x04, 0x00, 0x01]);
+        answer.extend_from_slice(&record);
+        record[1] = 0x06;
+        answer.extend_from_slice(&record);
+        let records = szl_records(&answer);
+        assert_eq!(records[0], (1, "6ES7 315-2EH14-0AB0".to_owned()));
+        assert_eq!(records.len(), 2);
+        assert!(szl_records(b"\x03\x00\x00\x07").is_empty());
+    }
+
+    #[test]
+    fn bacnet_and_dnp3_frames() {
+        assert_eq!(bacnet_request(121).len(), 0x11);
+        let mut answer = vec![0x81, 0x0a, 0x00, 0x00, 0x01, 0x00];
+        answer.extend_from_slice(&[0x30, 0x01, 0x0c, 0x0c, 0x02, 0x3f, 0xff, 0xff, 0x19, 0x79]);
+        answer.extend_from_slice(&[0x3e, 0x75, 0x0b, 0x00]);
+        answer.extend_from_slice(b"Acme Corp.");
+        answer.push(0x3f);
+        assert_eq!(bacnet_string(&answer).as_deref(), Some("Acme Corp."));
+
+        assert_eq!(dnp3_crc(b"123456789"), 0xea82_u16.to_le_bytes());
+        let request = dnp3_link_status(1);
+        assert_eq!(
+            &request[..8],
+            [0x05, 0x64, 0x05, 0xc9, 0x01, 0x00, 0x00, 0x00]
+        );
+        let answer = [0x05, 0x64, 0x05, 0x0b, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00];
+        assert_eq!(dnp3_source(&answer), Some(10));
+    }
+}
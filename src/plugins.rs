@@ -0,0 +1,300 @@
+//! Loads native plugins that hook into a scan.
+//!
+//! Plugins are shared libraries exporting `rustscan_plugin_init`, which
+//! returns a [`PluginVTable`] of optional hooks: one to generate targets
+//! before the scan, one called for every open port and one that renders a
+//! report from the results. The C side of the interface is described in
+//! `include/rustscan_plugin.h`.
+//!
+//! This lets organizations integrate RustScan with proprietary systems
+//! without maintaining a fork.
+use anyhow::{anyhow, Result};
+use libloading::Library;
+use log::debug;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::net::SocketAddr;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+
+/// Version of the plugin interface, plugins built for another version are
+/// refused rather than risking undefined behaviour.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// The symbol every plugin must export.
+const PLUGIN_INIT_SYMBOL: &[u8] = b"rustscan_plugin_init\0";
+
+/// The hooks a plugin provides, mirroring `RustScanPlugin` in the C header.
+#[repr(C)]
+pub struct PluginVTable {
+    pub abi_version: u32,
+    pub name: *const c_char,
+    pub generate_targets: Option<unsafe extern "C" fn() -> *mut c_char>,
+    pub on_open_port: Option<unsafe extern "C" fn(ip: *const c_char, port: u16)>,
+    pub render_report: Option<unsafe extern "C" fn(results_json: *const c_char) -> *mut c_char>,
+    pub free_string: Option<unsafe extern "C" fn(string: *mut c_char)>,
+}
+
+type PluginInit = unsafe extern "C" fn() -> *const PluginVTable;
+
+/// A loaded plugin.
+pub struct Plugin {
+    name: String,
+    path: PathBuf,
+    vtable: *const PluginVTable,
+    // Declared last so it's dropped after everything pointing into it.
+    _library: Option<Library>,
+}
+
+impl std::fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Plugin")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl Plugin {
+    /// Loads the shared library at `path` and initializes the plugin.
+    pub fn load(path: &Path) -> Result<Self> {
+        // SAFETY: loading a library runs its initializers, plugins are
+        // trusted code the user explicitly pointed RustScan to.
+        let library = unsafe { Library::new(path) }
+            .map_err(|e| anyhow!("{} could not be loaded: {e}", path.display()))?;
+        // SAFETY: the symbol is declared with this signature in the header.
+        let vtable = unsafe {
+            let init = library
+                .get::<PluginInit>(PLUGIN_INIT_SYMBOL)
+                .map_err(|e| anyhow!("{} is not a RustScan plugin: {e}", path.display()))?;
+            init()
+        };
+        // SAFETY: the library stays loaded for as long as the plugin lives.
+        unsafe { Self::from_vtable(vtable, path, Some(library)) }
+    }
+
+    /// Wraps a vtable, validating its version and name.
+    ///
+    /// # Safety
+    ///
+    /// `vtable` must be null or point to a `PluginVTable` (and name) that
+    /// stays valid while `library` is loaded, or forever if it's `None`.
+    unsafe fn from_vtable(
+        vtable: *const PluginVTable,
+        path: &Path,
+        library: Option<Library>,
+    ) -> Result<Self> {
+        let Some(table) = vtable.as_ref() else {
+            return Err(anyhow!("{} returned no plugin", path.display()));
+        };
+        if table.abi_version != PLUGIN_ABI_VERSION {
+            return Err(anyhow!(
+                "{} was built for plugin ABI version {}, RustScan supports {}",
+                path.display(),
+                table.abi_version,
+                PLUGIN_ABI_VERSION
+            ));
+        }
+        let name = if table.name.is_null() {
+            path.display().to_string()
+        } else {
+            CStr::from_ptr(table.name).to_string_lossy().into_owned()
+        };
+        Ok(Self {
+            name,
+            path: path.to_path_buf(),
+            vtable,
+            _library: library,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn vtable(&self) -> &PluginVTable {
+        // SAFETY: checked for null in from_vtable, valid while loaded.
+        unsafe { &*self.vtable }
+    }
+
+    /// Copies a string returned by a hook and hands it back to the plugin.
+    fn take_string(&self, string: *mut c_char) -> Option<String> {
+        if string.is_null() {
+            return None;
+        }
+        // SAFETY: hooks return nul terminated strings they own.
+        let owned = unsafe { CStr::from_ptr(string) }
+            .to_string_lossy()
+            .into_owned();
+        if let Some(free_string) = self.vtable().free_string {
+            // SAFETY: the string came from this plugin and isn't used again.
+            unsafe { free_string(string) };
+        }
+        Some(owned)
+    }
+
+    /// Extra targets the plugin wants scanned.
+    pub fn generate_targets(&self) -> Vec<String> {
+        let Some(generate_targets) = self.vtable().generate_targets else {
+            return Vec::new();
+        };
+        // SAFETY: the hook takes no arguments.
+        let targets = unsafe { generate_targets() };
+        self.take_string(targets)
+            .map(|targets| {
+                targets
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(ToOwned::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Tells the plugin about an open port.
+    pub fn on_open_port(&self, socket: SocketAddr) {
+        let Some(on_open_port) = self.vtable().on_open_port else {
+            return;
+        };
+        let ip = CString::new(socket.ip().to_string()).expect("IPs contain no nul bytes");
+        // SAFETY: ip is a valid nul terminated string for the whole call.
+        unsafe { on_open_port(ip.as_ptr(), socket.port()) };
+    }
+
+    /// Lets the plugin render a report from the results JSON.
+    pub fn render_report(&self, results_json: &str) -> Option<String> {
+        let render_report = self.vtable().render_report?;
+        let results_json = CString::new(results_json).ok()?;
+        // SAFETY: results_json is a valid nul terminated string for the call.
+        let report = unsafe { render_report(results_json.as_ptr()) };
+        self.take_string(report)
+    }
+}
+
+/// Loads every shared library in `dir`. Libraries that fail to load are
+/// returned as errors so the caller can report them and carry on.
+pub fn load_plugins(dir: &Path) -> Result<(Vec<Plugin>, Vec<anyhow::Error>)> {
+    let mut plugins = Vec::new();
+    let mut errors = Vec::new();
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| anyhow!("Can't read plugins folder {}: {e}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == std::env::consts::DLL_EXTENSION)
+        })
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        debug!("Loading plugin {}", path.display());
+        match Plugin::load(&path) {
+            Ok(plugin) => plugins.push(plugin),
+            Err(e) => errors.push(e),
+        }
+    }
+    Ok((plugins, errors))
+}
+
+/// Serializes open ports per IP into the JSON handed to `render_report`.
+pub fn results_json<'a>(
+    results: impl IntoIterator<Item = (&'a std::net::IpAddr, &'a Vec<u16>)>,
+) -> String {
+    let hosts: Vec<serde_json::Value> = results
+        .into_iter()
+        .map(|(ip, ports)| serde_json::json!({ "ip": ip.to_string(), "ports": ports }))
+        .collect();
+    serde_json::Value::Array(hosts).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_plugins, results_json, Plugin, PluginVTable, PLUGIN_ABI_VERSION};
+    use std::collections::BTreeMap;
+    use std::ffi::{CStr, CString};
+    use std::net::IpAddr;
+    use std::os::raw::c_char;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static OPEN_PORTS: AtomicUsize = AtomicUsize::new(0);
+    static FREED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn generate_targets() -> *mut c_char {
+        CString::new("10.0.0.1\n\n10.0.0.0/30\n")
+            .unwrap()
+            .into_raw()
+    }
+
+    unsafe extern "C" fn on_open_port(ip: *const c_char, port: u16) {
+        assert_eq!(CStr::from_ptr(ip).to_str().unwrap(), "127.0.0.1");
+        OPEN_PORTS.fetch_add(usize::from(port), Ordering::SeqCst);
+    }
+
+    unsafe extern "C" fn render_report(results_json: *const c_char) -> *mut c_char {
+        let results = CStr::from_ptr(results_json).to_str().unwrap();
+        CString::new(format!("report of {results}"))
+            .unwrap()
+            .into_raw()
+    }
+
+    unsafe extern "C" fn free_string(string: *mut c_char) {
+        drop(CString::from_raw(string));
+        FREED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    struct StaticVTable(PluginVTable);
+    // SAFETY: the table only holds function pointers and a static string.
+    unsafe impl Sync for StaticVTable {}
+
+    static VTABLE: StaticVTable = StaticVTable(PluginVTable {
+        abi_version: PLUGIN_ABI_VERSION,
+        name: b"test plugin\0".as_ptr().cast(),
+        generate_targets: Some(generate_targets),
+        on_open_port: Some(on_open_port),
+        render_report: Some(render_report),
+        free_string: Some(free_string),
+    });
+
+    #[test]
+    fn hooks_are_called() {
+        let plugin = unsafe { Plugin::from_vtable(&VTABLE.0, Path::new("test"), None) }.unwrap();
+        assert_eq!(plugin.name(), "test plugin");
+        assert_eq!(plugin.generate_targets(), vec!["10.0.0.1", "10.0.0.0/30"]);
+
+        plugin.on_open_port("127.0.0.1:80".parse().unwrap());
+        plugin.on_open_port("127.0.0.1:443".parse().unwrap());
+        assert_eq!(OPEN_PORTS.load(Ordering::SeqCst), 523);
+
+        let mut results = BTreeMap::new();
+        results.insert("127.0.0.1".parse::<IpAddr>().unwrap(), vec![80, 443]);
+        assert_eq!(
+            plugin.render_report(&results_json(&results)).unwrap(),
+            r#"report of [{"ip":"127.0.0.1","ports":[80,443]}]"#
+        );
+        assert_eq!(FREED.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn wrong_abi_version_is_refused() {
+        let table = PluginVTable {
+            abi_version: PLUGIN_ABI_VERSION + 1,
+            name: std::ptr::null(),
+            generate_targets: None,
+            on_open_port: None,
+            render_report: None,
+            free_string: None,
+        };
+        assert!(unsafe { Plugin::from_vtable(&table, Path::new("test"), None) }.is_err());
+        assert!(unsafe { Plugin::from_vtable(std::ptr::null(), Path::new("test"), None) }.is_err());
+    }
+
+    #[test]
+    fn non_libraries_are_skipped_or_reported() {
+        let (plugins, errors) = load_plugins(Path::new("fixtures")).unwrap();
+        assert!(plugins.is_empty());
+        assert!(errors.is_empty());
+        assert!(load_plugins(Path::new("fixtures/does_not_exist")).is_err());
+    }
+}
@@ -0,0 +1,602 @@
+//! Compares the ports a firewall policy declares open to the scan's.
+//!
+//! `--policy FILE` reads one of:
+//!
+//! - the output of `iptables-save` or `ip6tables-save`, its `filter` table,
+//! - the output of `nft list ruleset`,
+//! - a JSON export of cloud firewall rules: AWS security groups
+//!   (`aws ec2 describe-security-groups`), GCP firewall rules
+//!   (`gcloud compute firewall-rules list --format json`) or Azure network
+//!   security groups (`az network nsg show`).
+//!
+//! The ports the policy allows are the union of what its accept rules
+//! allow to come in, on any host or on the destinations they name; rules
+//! for loopback, for established connections only or that can't be read
+//! (negations, nft named sets) are left out, and so are drop rules. Once
+//! the scan is done, the drift is reported:
+//!
+//! - open ports the policy doesn't allow, whatever let them through,
+//! - ports the policy allows by name, scanned but closed, on hosts that had
+//!   other ports open. Ranges of allowed ports aren't expected to be open,
+//!   they usually are for dynamic ports.
+//!
+//! Drift is printed and kept in `policy_drift` in reports.
+use anyhow::{anyhow, Result};
+use cidr_utils::cidr::IpCidr;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A transport protocol a rule applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    Tcp,
+    Udp,
+}
+
+impl Transport {
+    /// The transport named `name`, `None` for every transport, or an error
+    /// for protocols without ports, such as ICMP.
+    fn parse(name: &str) -> std::result::Result<Option<Self>, ()> {
+        match name.to_ascii_lowercase().as_str() {
+            "tcp" | "6" => Ok(Some(Self::Tcp)),
+            "udp" | "17" => Ok(Some(Self::Udp)),
+            "all" | "-1" | "*" | "any" => Ok(None),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What an accept rule lets in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    /// The destinations, every host if `None`.
+    network: Option<IpCidr>,
+    /// Every transport if `None`.
+    transport: Option<Transport>,
+    /// Every port if `None`.
+    ports: Option<RangeInclusive<u16>>,
+}
+
+impl Rule {
+    const ANY: Self = Self {
+        network: None,
+        transport: None,
+        ports: None,
+    };
+
+    fn allows(&self, socket: &SocketAddr, udp: bool) -> bool {
+        self.network
+            .as_ref()
+            .is_none_or(|network| network.contains(&socket.ip()))
+            && self.transport.is_none_or(|transport| {
+                transport == if udp { Transport::Udp } else { Transport::Tcp }
+            })
+            && self
+                .ports
+                .as_ref()
+                .is_none_or(|ports| ports.contains(&socket.port()))
+    }
+
+    /// The single port the rule names, if it names one.
+    fn named_port(&self) -> Option<u16> {
+        let ports = self.ports.as_ref()?;
+        (ports.start() == ports.end()).then_some(*ports.start())
+    }
+}
+
+/// How an open or closed port differs from the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DriftKind {
+    /// Open, but no accept rule allows it.
+    OpenNotAllowed,
+    /// Allowed by name, but closed.
+    AllowedNotOpen,
+}
+
+/// A port that differs from the policy.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Drift {
+    pub socket: SocketAddr,
+    pub kind: DriftKind,
+}
+
+/// The ports a firewall policy allows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    rules: Vec<Rule>,
+}
+
+/// A destination network, as a CIDR or an IP.
+pub(crate) fn network(text: &str) -> Result<IpCidr> {
+    let text = text.trim_matches('"');
+    IpCidr::from_str(text)
+        .ok()
+        .or_else(|| IpAddr::from_str(text).ok().map(IpCidr::new_host))
+        .ok_or_else(|| anyhow!("{text} is not an address or a network"))
+}
+
+/// A port or a range of them, `first` then `last` separated by `separator`.
+pub(crate) fn port_range(text: &str, separator: char) -> Result<RangeInclusive<u16>> {
+    let text = text.trim();
+    let parse = |port: &str| {
+        port.trim()
+            .parse::<u16>()
+            .map_err(|_| anyhow!("{port} is not a port"))
+    };
+    match text.split_once(separator) {
+        // iptables leaves out the ends of open ranges.
+        Some((first, last)) => Ok(if first.is_empty() { 0 } else { parse(first)? }
+            ..=if last.is_empty() {
+                u16::MAX
+            } else {
+                parse(last)?
+            }),
+        None => parse(text).map(|port| port..=port),
+    }
+}
+
+/// `rule` once per range in `ports`, or as is without any.
+fn with_ports(rule: &Rule, ports: Option<Vec<RangeInclusive<u16>>>) -> Vec<Rule> {
+    match ports {
+        Some(ports) => ports
+            .into_iter()
+            .map(|ports| Rule {
+                ports: Some(ports),
+                ..rule.clone()
+            })
+            .collect(),
+        None => vec![rule.clone()],
+    }
+}
+
+fn parse_iptables(content: &str) -> Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    let mut table = "";
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix('*') {
+            table = name;
+            continue;
+        }
+        if table != "filter" {
+            continue;
+        }
+        // Chain policies: `:INPUT ACCEPT [0:0]`.
+        if let Some(chain) = line.strip_prefix(':') {
+            let mut fields = chain.split_whitespace();
+            if matches!(fields.next(), Some("INPUT" | "FORWARD")) && fields.next() == Some("ACCEPT")
+            {
+                rules.push(Rule::ANY);
+            }
+            continue;
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.first() != Some(&"-A") || tokens.get(1) == Some(&"OUTPUT") {
+            continue;
+        }
+        let option = |name: &str| {
+            tokens
+                .iter()
+                .position(|token| *token == name)
+                .and_then(|index| tokens.get(index + 1))
+                .copied()
+        };
+        if option("-j") != Some("ACCEPT")
+            || option("-i") == Some("lo")
+            || tokens.contains(&"!")
+            || option("--state")
+                .or_else(|| option("--ctstate"))
+                .is_some_and(|states| !states.split(',').any(|state| state == "NEW"))
+        {
+            continue;
+        }
+        let Ok(transport) = option("-p").map_or(Ok(None), Transport::parse) else {
+            continue;
+        };
+        let line_error = |e: anyhow::Error| anyhow!("Line {}: {e}", number + 1);
+        let rule = Rule {
+            network: option("-d").map(network).transpose().map_err(line_error)?,
+            transport,
+            ports: None,
+        };
+        let ports = option("--dport")
+            .or_else(|| option("--dports"))
+            .map(|ports| {
+                ports
+                    .split(',')
+                    .map(|ports| port_range(ports, ':'))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()
+            .map_err(line_error)?;
+        rules.extend(with_ports(&rule, ports));
+    }
+    Ok(rules)
+}
+
+/// The values after `tokens[index]`, a single one or a `{ a, b }` set.
+fn nft_values(tokens: &[&str], index: usize) -> Vec<String> {
+    match tokens.get(index) {
+        Some(&"{") => tokens[index + 1..]
+            .iter()
+            .take_while(|token| **token != "}")
+            .flat_map(|token| token.split(','))
+            .filter(|value| !value.is_empty())
+            .map(ToOwned::to_owned)
+            .collect(),
+        Some(value) => vec![(*value).to_owned()],
+        None => Vec::new(),
+    }
+}
+
+fn parse_nftables(content: &str) -> Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    let mut hook: Option<String> = None;
+    for (number, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let line = line.split(" comment ").next().unwrap_or_default();
+        let tokens: Vec<&str> = line
+            .split_whitespace()
+            .map(|token| token.trim_end_matches(';'))
+            .collect();
+        match tokens.first() {
+            Some(&"chain") => {
+                hook = None;
+                continue;
+            }
+            Some(&"type") => {
+                hook = tokens
+                    .iter()
+                    .position(|token| *token == "hook")
+                    .and_then(|index| tokens.get(index + 1))
+                    .map(|hook| (*hook).to_owned());
+                let policy = tokens
+                    .iter()
+                    .position(|token| *token == "policy")
+                    .and_then(|index| tokens.get(index + 1));
+                if matches!(hook.as_deref(), Some("input" | "forward")) && policy == Some(&"accept")
+                {
+                    rules.push(Rule::ANY);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if tokens.last() != Some(&"accept")
+            || !matches!(hook.as_deref(), None | Some("input" | "forward"))
+            || tokens
+                .iter()
+                .any(|token| *token == "!=" || token.starts_with('@'))
+            || tokens.windows(2).any(|pair| {
+                matches!(pair[0], "iif" | "iifname") && pair[1].trim_matches('"') == "lo"
+            })
+            || (line.contains("ct state") && !line.contains("new"))
+        {
+            continue;
+        }
+        let line_error = |e: anyhow::Error| anyhow!("Line {}: {e}", number + 1);
+        let mut transports = vec![None];
+        let mut networks = vec![None];
+        let mut ports = None;
+        for (index, pair) in tokens.windows(2).enumerate() {
+            match pair {
+                ["ip" | "ip6", "daddr"] => {
+                    networks = nft_values(&tokens, index + 2)
+                        .iter()
+                        .map(|value| network(value).map(Some))
+                        .collect::<Result<_>>()
+                        .map_err(line_error)?;
+                }
+                ["protocol" | "l4proto" | "nexthdr", _] => {
+                    transports = nft_values(&tokens, index + 1)
+                        .iter()
+                        .filter_map(|name| Transport::parse(name).ok())
+                        .collect();
+                }
+                [transport @ ("tcp" | "udp" | "th"), "dport"] => {
+                    if *transport != "th" {
+                        transports = vec![Transport::parse(transport).ok().flatten()];
+                    }
+                    ports = Some(
+                        nft_values(&tokens, index + 2)
+                            .iter()
+                            .map(|ports| port_range(ports, '-'))
+                            .collect::<Result<Vec<_>>>()
+                            .map_err(line_error)?,
+                    );
+                }
+                _ => {}
+            }
+        }
+        for network in &networks {
+            for transport in &transports {
+                let rule = Rule {
+                    network: *network,
+                    transport: *transport,
+                    ports: None,
+                };
+                rules.extend(with_ports(&rule, ports.clone()));
+            }
+        }
+    }
+    Ok(rules)
+}
+
+/// The ports of a cloud rule, `None` for every port: a string or a list
+/// of `22` and `8000-9000`, `*` being all of them.
+fn cloud_ports(value: Option<&Value>) -> Result<Option<Vec<RangeInclusive<u16>>>> {
+    let texts: Vec<&str> = match value {
+        Some(Value::String(text)) => vec![text.as_str()],
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+        _ => return Ok(None),
+    };
+    if texts.is_empty() || texts.contains(&"*") {
+        return Ok(None);
+    }
+    texts
+        .into_iter()
+        .map(|ports| port_range(ports, '-'))
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+/// The accept rules in `value`, wherever they are in the document.
+fn parse_cloud(value: &Value, rules: &mut Vec<Rule>) -> Result<()> {
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                parse_cloud(value, rules)?;
+            }
+        }
+        // AWS security groups.
+        Value::Object(object) if object.contains_key("IpPermissions") => {
+            for permission in object["IpPermissions"].as_array().into_iter().flatten() {
+                let protocol = permission["IpProtocol"].as_str().unwrap_or("-1");
+                let Ok(transport) = Transport::parse(protocol) else {
+                    continue;
+                };
+                let port = |name: &str| {
+                    permission[name]
+                        .as_i64()
+                        .filter(|port| *port >= 0)
+                        .and_then(|port| u16::try_from(port).ok())
+                };
+                let ports = match (port("FromPort"), port("ToPort")) {
+                    (Some(from), Some(to)) if transport.is_some() => Some(from..=to),
+                    _ => None,
+                };
+                rules.push(Rule {
+                    network: None,
+                    transport,
+                    ports,
+                });
+            }
+        }
+        // GCP firewall rules.
+        Value::Object(object) if object.contains_key("allowed") => {
+            if object
+                .get("direction")
+                .and_then(Value::as_str)
+                .is_some_and(|direction| direction != "INGRESS")
+                || object.get("disabled") == Some(&Value::Bool(true))
+            {
+                return Ok(());
+            }
+            for allowed in object["allowed"].as_array().into_iter().flatten() {
+                let protocol = allowed["IPProtocol"].as_str().unwrap_or("all");
+                let Ok(transport) = Transport::parse(protocol) else {
+                    continue;
+                };
+                let rule = Rule {
+                    network: None,
+                    transport,
+                    ports: None,
+                };
+                rules.extend(with_ports(&rule, cloud_ports(allowed.get("ports"))?));
+            }
+        }
+        // Azure network security groups, their rules being in
+        // `properties` as exported by the API.
+        Value::Object(object) if object.contains_key("securityRules") => {
+            for rule in object["securityRules"].as_array().into_iter().flatten() {
+                let rule = rule.get("properties").unwrap_or(rule);
+                let field = |name: &str| rule.get(name).and_then(Value::as_str).unwrap_or_default();
+                if !field("direction").eq_ignore_ascii_case("inbound")
+                    || !field("access").eq_ignore_ascii_case("allow")
+                {
+                    continue;
+                }
+                let Ok(transport) = Transport::parse(field("protocol")) else {
+                    continue;
+                };
+                // Service tags such as VirtualNetwork aren't addresses.
+                let network = network(field("destinationAddressPrefix")).ok();
+                let ports = match rule.get("destinationPortRanges") {
+                    Some(Value::Array(ranges)) if !ranges.is_empty() => {
+                        cloud_ports(rule.get("destinationPortRanges"))?
+                    }
+                    _ => cloud_ports(rule.get("destinationPortRange"))?,
+                };
+                let rule = Rule {
+                    network,
+                    transport,
+                    ports: None,
+                };
+                rules.extend(with_ports(&rule, ports));
+            }
+        }
+        Value::Object(object) => {
+            for value in object.values() {
+                parse_cloud(value, rules)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+impl Policy {
+    /// Reads the policy at `path`, whichever format it's in.
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read {}: {e}", path.display()))?;
+        Self::parse(&content)
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let trimmed = content.trim_start();
+        let rules = if trimmed.starts_with('{') || trimmed.starts_with('[') {
+            let value: Value = serde_json::from_str(content)?;
+            let mut rules = Vec::new();
+            parse_cloud(&value, &mut rules)?;
+            rules
+        } else if content
+            .lines()
+            .any(|line| line.trim_start().starts_with("table "))
+        {
+            parse_nftables(content)?
+        } else if content.lines().any(|line| line.starts_with("*filter")) {
+            parse_iptables(content)?
+        } else {
+            return Err(anyhow!(
+                "not an iptables-save, nft ruleset or cloud firewall rules export"
+            ));
+        };
+        Ok(Self { rules })
+    }
+
+    /// Whether the policy lets connections to `socket` in.
+    pub fn allows(&self, socket: &SocketAddr, udp: bool) -> bool {
+        self.rules.iter().any(|rule| rule.allows(socket, udp))
+    }
+
+    /// How the `open` sockets, out of the `scanned` ports, differ from the
+    /// policy.
+    pub fn drift(&self, open: &[SocketAddr], scanned: &[u16], udp: bool) -> Vec<Drift> {
+        let mut drift = BTreeSet::new();
+        for socket in open {
+            if !self.allows(socket, udp) {
+                drift.insert(Drift {
+                    socket: *socket,
+                    kind: DriftKind::OpenNotAllowed,
+                });
+            }
+        }
+        let open: HashSet<&SocketAddr> = open.iter().collect();
+        let hosts: BTreeSet<IpAddr> = open.iter().map(|socket| socket.ip()).collect();
+        let scanned: HashSet<&u16> = scanned.iter().collect();
+        for ip in hosts {
+            for port in self.rules.iter().filter_map(Rule::named_port) {
+                let socket = SocketAddr::new(ip, port);
+                if scanned.contains(&port) && !open.contains(&socket) && self.allows(&socket, udp) {
+                    drift.insert(Drift {
+                        socket,
+                        kind: DriftKind::AllowedNotOpen,
+                    });
+                }
+            }
+        }
+        drift.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DriftKind, Policy};
+    use std::net::SocketAddr;
+
+    fn socket(socket: &str) -> SocketAddr {
+        socket.parse().unwrap()
+    }
+
+    #[test]
+    fn iptables_save_accept_rules() {
+        let policy = Policy::parse(
+            "*nat\n:PREROUTING ACCEPT [0:0]\n-A PREROUTING -p tcp --dport 8080 -j ACCEPT\nCOMMIT\n\
+             *filter\n:INPUT DROP [0:0]\n:OUTPUT ACCEPT [0:0]\n\
+             -A INPUT -i lo -j ACCEPT\n\
+             -A INPUT -m state --state RELATED,ESTABLISHED -j ACCEPT\n\
+             -A INPUT -p tcp -m tcp --dport 22 -j ACCEPT\n\
+             -A INPUT -d 10.0.0.5/32 -p tcp -m multiport --dports 80,443,8000:8010 -j ACCEPT\n\
+             -A INPUT -p udp --dport 53 -j ACCEPT\n\
+             -A INPUT -p icmp -j ACCEPT\n\
+             -A OUTPUT -p tcp --dport 25 -j ACCEPT\nCOMMIT\n",
+        )
+        .unwrap();
+        assert!(policy.allows(&socket("10.0.0.9:22"), false));
+        assert!(policy.allows(&socket("10.0.0.5:443"), false));
+        assert!(policy.allows(&socket("10.0.0.5:8005"), false));
+        assert!(!policy.allows(&socket("10.0.0.9:443"), false));
+        assert!(!policy.allows(&socket("10.0.0.9:53"), false));
+        assert!(policy.allows(&socket("10.0.0.9:53"), true));
+        assert!(!policy.allows(&socket("10.0.0.9:8080"), false));
+        assert!(!policy.allows(&socket("10.0.0.9:25"), false));
+    }
+
+    #[test]
+    fn nft_ruleset_accept_rules() {
+        let policy = Policy::parse(
+            "table inet filter {\n\tchain input {\n\t\ttype filter hook input priority filter; policy drop;\n\
+             \t\tct state established,related accept\n\t\tiif \"lo\" accept\n\
+             \t\ttcp dport { 22, 80, 443 } accept\n\
+             \t\tip daddr 10.0.0.0/24 udp dport 5000-5010 accept\n\
+             \t\ttcp dport @admin_ports accept\n\t}\n\
+             \tchain output {\n\t\ttype filter hook output priority filter; policy accept;\n\
+             \t\ttcp dport 3306 accept\n\t}\n}\n",
+        )
+        .unwrap();
+        assert!(policy.allows(&socket("192.0.2.1:80"), false));
+        assert!(!policy.allows(&socket("192.0.2.1:80"), true));
+        assert!(policy.allows(&socket("10.0.0.3:5005"), true));
+        assert!(!policy.allows(&socket("10.0.1.3:5005"), true));
+        assert!(!policy.allows(&socket("192.0.2.1:3306"), false));
+    }
+
+    #[test]
+    fn cloud_exports_and_drift() {
+        let aws = r#"{"SecurityGroups": [{"GroupId": "sg-1", "IpPermissions": [
+            {"IpProtocol": "tcp", "FromPort": 22, "ToPort": 22},
+            {"IpProtocol": "tcp", "FromPort": 443, "ToPort": 443},
+            {"IpProtocol": "icmp", "FromPort": -1, "ToPort": -1}]}]}"#;
+        let policy = Policy::parse(aws).unwrap();
+        let open = [socket("10.0.0.1:22"), socket("10.0.0.1:3389")];
+        let drift: Vec<(String, DriftKind)> = policy
+            .drift(&open, &[22, 443, 3389], false)
+            .into_iter()
+            .map(|drift| (drift.socket.to_string(), drift.kind))
+            .collect();
+        assert_eq!(
+            drift,
+            vec![
+                ("10.0.0.1:443".to_owned(), DriftKind::AllowedNotOpen),
+                ("10.0.0.1:3389".to_owned(), DriftKind::OpenNotAllowed),
+            ]
+        );
+
+        let gcp = r#"[{"name": "allow-web", "direction": "INGRESS",
+            "allowed": [{"IPProtocol": "tcp", "ports": ["80", "8000-8100"]}]},
+            {"name": "egress", "direction": "EGRESS", "allowed": [{"IPProtocol": "all"}]}]"#;
+        let policy = Policy::parse(gcp).unwrap();
+        assert!(policy.allows(&socket("10.0.0.1:8050"), false));
+        assert!(!policy.allows(&socket("10.0.0.1:22"), false));
+
+        let azure = r#"{"name": "nsg", "securityRules": [
+            {"name": "rdp", "properties": {"direction": "Inbound", "access": "Allow",
+             "protocol": "Tcp", "destinationAddressPrefix": "*", "destinationPortRange": "3389"}},
+            {"name": "deny", "properties": {"direction": "Inbound", "access": "Deny",
+             "protocol": "*", "destinationPortRange": "*"}}]}"#;
+        let policy = Policy::parse(azure).unwrap();
+        assert!(policy.allows(&socket("10.0.0.1:3389"), false));
+        assert!(!policy.allows(&socket("10.0.0.1:22"), false));
+
+        assert!(Policy::parse("10.0.0.1:22\n").is_err());
+    }
+}
@@ -4,6 +4,224 @@ use crate::input::{PortRange, ScanOrder};
 use rand::rng;
 use rand::seq::SliceRandom;
 use range_iterator::RangeIterator;
+use std::fmt;
+
+/// The ports `--mode discovery` probes, those most often open on hosts
+/// that are up: web, SSH, Windows, remote desktop and mail.
+pub const DISCOVERY_PORTS: [u16; 12] = [80, 443, 22, 445, 3389, 135, 139, 21, 23, 25, 8080, 5900];
+
+/// Ports known to make some devices misbehave when merely probed, left out
+/// of scans unless `--allow-dangerous-ports`.
+pub const DANGEROUS_PORTS: [DangerousPorts; 2] = [
+    DangerousPorts {
+        ports: PortRange {
+            start: 9100,
+            end: 9107,
+        },
+        reason: "raw printing (JetDirect), printers print whatever reaches them",
+    },
+    DangerousPorts {
+        ports: PortRange {
+            start: 44818,
+            end: 44818,
+        },
+        reason: "EtherNet/IP, some older PLCs fault on unexpected connections",
+    },
+];
+
+/// A range of [`DANGEROUS_PORTS`] and why it's left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DangerousPorts {
+    pub ports: PortRange,
+    pub reason: &'static str,
+}
+
+impl DangerousPorts {
+    pub fn contains(&self, port: u16) -> bool {
+        (self.ports.start..=self.ports.end).contains(&port)
+    }
+}
+
+impl fmt::Display for DangerousPorts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.ports.start, self.ports.end) {
+            (start, end) if start == end => write!(f, "port {start} ({})", self.reason),
+            (start, end) => write!(f, "ports {start}-{end} ({})", self.reason),
+        }
+    }
+}
+
+/// Whether `port` is among the [`DANGEROUS_PORTS`].
+pub fn is_dangerous(port: u16) -> bool {
+    DANGEROUS_PORTS.iter().any(|range| range.contains(port))
+}
+
+/// Parses a list of ports and `start-end` ranges such as `0,22,80-90`.
+/// Ranges include both ends. Ports given more than once, by overlapping
+/// ranges for example, are only kept the first time.
+///
+/// Ports and ranges starting with `!` are left out wherever they are, as in
+/// `1-1024,8000-9000,!8080,!8443`. Given only those, every port from 1 is
+/// kept but them.
+pub fn parse_ports(spec: &str) -> Result<Vec<u16>, String> {
+    let mut included = Vec::new();
+    let mut excluded = vec![false; usize::from(u16::MAX) + 1];
+    for item in spec.split(',').map(str::trim) {
+        match item.strip_prefix('!') {
+            Some(item) => {
+                let range = parse_item(item)?;
+                excluded[usize::from(range.start)..=usize::from(range.end)].fill(true);
+            }
+            None => included.push(parse_item(item)?),
+        }
+    }
+    if included.is_empty() {
+        included.push(PortRange {
+            start: 1,
+            end: u16::MAX,
+        });
+    }
+
+    // Ports excluded or already kept are skipped alike.
+    let mut skipped = excluded;
+    let mut ports = Vec::new();
+    for range in included {
+        for port in range.start..=range.end {
+            if !std::mem::replace(&mut skipped[usize::from(port)], true) {
+                ports.push(port);
+            }
+        }
+    }
+    if ports.is_empty() {
+        return Err(format!("{spec:?} leaves out every port it lists"));
+    }
+    Ok(ports)
+}
+
+/// The ports of a list with nmap's `T:` and `U:` prefixes, as in
+/// `T:22,80,U:53,161`. A prefix applies to the ports after it until the
+/// next one, and ports before any are left to the protocol of the scan.
+/// Items may be separated by spaces too.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProtocolPorts {
+    pub untagged: Option<Vec<u16>>,
+    pub tcp: Option<Vec<u16>>,
+    pub udp: Option<Vec<u16>>,
+}
+
+impl ProtocolPorts {
+    /// The ports to probe over UDP if `udp`, over TCP otherwise: the
+    /// untagged ones, then those tagged with that protocol.
+    pub fn scanned(&self, udp: bool) -> Vec<u16> {
+        let tagged = if udp { &self.udp } else { &self.tcp };
+        let mut ports = self.untagged.clone().unwrap_or_default();
+        for &port in tagged.iter().flatten() {
+            if !ports.contains(&port) {
+                ports.push(port);
+            }
+        }
+        ports
+    }
+}
+
+/// Parses a list of ports with `T:` and `U:` prefixes, see
+/// [`ProtocolPorts`]. Each protocol's ports are parsed like
+/// [`parse_ports`], exclusions only applying to that protocol's.
+pub fn parse_protocol_ports(spec: &str) -> Result<ProtocolPorts, String> {
+    let mut sections: [Vec<&str>; 3] = Default::default();
+    let mut current = 0;
+    for item in spec
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|item| !item.is_empty())
+    {
+        let item = match item.get(..2).map(str::to_ascii_uppercase).as_deref() {
+            Some("T:") => {
+                current = 1;
+                &item[2..]
+            }
+            Some("U:") => {
+                current = 2;
+                &item[2..]
+            }
+            _ => item,
+        };
+        sections[current].push(item);
+    }
+    if sections.iter().all(Vec::is_empty) {
+        return Err(String::from("no ports given"));
+    }
+    let parse = |section: &[&str]| match section {
+        [] => Ok(None),
+        items => parse_ports(&items.join(",")).map(Some),
+    };
+    Ok(ProtocolPorts {
+        untagged: parse(&sections[0])?,
+        tcp: parse(&sections[1])?,
+        udp: parse(&sections[2])?,
+    })
+}
+
+/// The value of `--ports`: the ports given without a prefix or with `T:`.
+/// Those with `U:` are sorted out once every option is known, see
+/// [`ProtocolPorts`].
+pub fn parse_ports_arg(spec: &str) -> Result<Vec<u16>, String> {
+    parse_protocol_ports(spec).map(|ports| ports.scanned(false))
+}
+
+/// A port or a range, as a range.
+fn parse_item(item: &str) -> Result<PortRange, String> {
+    match item.split_once('-') {
+        Some(_) => parse_range(item),
+        None => {
+            let port = parse_port(item)?;
+            Ok(PortRange {
+                start: port,
+                end: port,
+            })
+        }
+    }
+}
+
+/// Parses a `start-end` range, both ends included, as in `0-65535`.
+pub fn parse_range(input: &str) -> Result<PortRange, String> {
+    let Some((start, end)) = input.split_once('-') else {
+        return Err(format!(
+            "{input:?} isn't a range, the format is 'start-end', as in 1-1000"
+        ));
+    };
+    let range = PortRange {
+        start: parse_port(start)?,
+        end: parse_port(end)?,
+    };
+    range.validate()?;
+    Ok(range)
+}
+
+fn parse_port(input: &str) -> Result<u16, String> {
+    let input = input.trim();
+    input.parse().map_err(|_| {
+        if input.is_empty() {
+            String::from("a port is missing, ports are separated by ',' and ranges are 'start-end'")
+        } else if input.bytes().all(|byte| byte.is_ascii_digit()) {
+            format!("{input} is past the last port, 65535")
+        } else {
+            format!("{input:?} isn't a port number")
+        }
+    })
+}
+
+impl PortRange {
+    /// Checks the range doesn't count down.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.start > self.end {
+            return Err(format!(
+                "{}-{} counts down, the start of a range can't be after its end",
+                self.start, self.end
+            ));
+        }
+        Ok(())
+    }
+}
 
 /// Represents options of port scanning.
 ///
@@ -43,6 +261,25 @@ impl PortStrategy {
         }
     }
 
+    /// Leaves out the [`DANGEROUS_PORTS`], returning the ranges of them it
+    /// had ports of. The order of the other ports is kept.
+    pub fn skip_dangerous(&mut self) -> Vec<&'static DangerousPorts> {
+        let ports = self.order();
+        let skipped: Vec<_> = DANGEROUS_PORTS
+            .iter()
+            .filter(|range| ports.iter().any(|&port| range.contains(port)))
+            .collect();
+        if !skipped.is_empty() {
+            *self = PortStrategy::Manual(
+                ports
+                    .into_iter()
+                    .filter(|&port| !is_dangerous(port))
+                    .collect(),
+            );
+        }
+        skipped
+    }
+
     pub fn order(&self) -> Vec<u16> {
         match self {
             PortStrategy::Manual(ports) => ports.clone(),
@@ -97,9 +334,119 @@ impl RangeOrder for RandomRange {
 
 #[cfg(test)]
 mod tests {
-    use super::PortStrategy;
+    use super::{
+        parse_ports, parse_ports_arg, parse_protocol_ports, parse_range, PortStrategy,
+        DANGEROUS_PORTS,
+    };
     use crate::input::{PortRange, ScanOrder};
 
+    #[test]
+    fn parses_ports_and_inclusive_ranges() {
+        assert_eq!(parse_ports("0,22, 80-82").unwrap(), vec![0, 22, 80, 81, 82]);
+        assert_eq!(
+            parse_ports("443,80-82,81-83,443").unwrap(),
+            vec![443, 80, 81, 82, 83]
+        );
+        assert_eq!(parse_ports("0-65535").unwrap().len(), 65_536);
+        assert_eq!(
+            parse_range("0-65535").unwrap(),
+            PortRange {
+                start: 0,
+                end: 65_535
+            }
+        );
+        assert_eq!(parse_range("7-7").unwrap(), PortRange { start: 7, end: 7 });
+
+        assert_eq!(
+            parse_range("80-70").unwrap_err(),
+            "80-70 counts down, the start of a range can't be after its end"
+        );
+        assert!(parse_ports("22,80-70").is_err());
+        assert_eq!(
+            parse_ports("65536").unwrap_err(),
+            "65536 is past the last port, 65535"
+        );
+        assert!(parse_ports("22,,80").is_err());
+        assert!(parse_ports("http").is_err());
+        assert!(parse_range("80").is_err());
+        assert!(parse_range("1-2-3").is_err());
+    }
+
+    #[test]
+    fn leaves_out_excluded_ports() {
+        let ports = parse_ports("1-1024,8000-9000,!8080,!8443").unwrap();
+        assert_eq!(ports.len(), 1024 + 1001 - 2);
+        assert!(!ports.contains(&8080) && !ports.contains(&8443));
+        assert_eq!(
+            parse_ports("!1-100,90-110,!105").unwrap(),
+            [101, 102, 103, 104, 106, 107, 108, 109, 110]
+        );
+
+        let ports = parse_ports("!22").unwrap();
+        assert_eq!(ports.len(), 65_534);
+        assert_eq!(ports[..3], [1, 2, 3]);
+        assert!(!ports.contains(&0) && !ports.contains(&22));
+
+        assert_eq!(
+            parse_ports("80,!80").unwrap_err(),
+            "\"80,!80\" leaves out every port it lists"
+        );
+        assert!(parse_ports("!").is_err());
+        assert!(parse_ports("!90-80").is_err());
+    }
+
+    #[test]
+    fn sorts_ports_by_protocol() {
+        let ports = parse_protocol_ports("T:22,80 U:53,161").unwrap();
+        assert_eq!(ports.untagged, None);
+        assert_eq!(ports.tcp, Some(vec![22, 80]));
+        assert_eq!(ports.udp, Some(vec![53, 161]));
+
+        let ports = parse_protocol_ports("443,u:50-55,!54,T:8080").unwrap();
+        assert_eq!(ports.untagged, Some(vec![443]));
+        assert_eq!(ports.tcp, Some(vec![8080]));
+        assert_eq!(ports.udp, Some(vec![50, 51, 52, 53, 55]));
+
+        assert_eq!(parse_ports_arg("22,T:22,80,U:53").unwrap(), [22, 80]);
+        assert!(parse_ports_arg("U:53").unwrap().is_empty());
+        assert!(parse_protocol_ports("T:").is_err());
+        assert!(parse_protocol_ports("").is_err());
+        assert!(parse_protocol_ports("U:70-60").is_err());
+    }
+
+    #[test]
+    fn dangerous_ports_are_skipped() {
+        let range = PortRange {
+            start: 9000,
+            end: 9200,
+        };
+        let mut strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+        assert_eq!(strategy.skip_dangerous(), [&DANGEROUS_PORTS[0]]);
+        let ports = strategy.order();
+        assert_eq!(ports.len(), 201 - 8);
+        assert!(!ports.contains(&9100) && !ports.contains(&9107));
+        assert!(ports.contains(&9099) && ports.contains(&9108));
+
+        let mut strategy = PortStrategy::pick(&None, Some(vec![443, 22]), ScanOrder::Serial);
+        assert!(strategy.skip_dangerous().is_empty());
+        assert_eq!(strategy.order(), [443, 22]);
+        assert_eq!(
+            DANGEROUS_PORTS[1].to_string(),
+            "port 44818 (EtherNet/IP, some older PLCs fault on unexpected connections)"
+        );
+    }
+
+    #[test]
+    fn strategies_include_port_zero() {
+        let range = PortRange {
+            start: 0,
+            end: 65_535,
+        };
+        let mut result = PortStrategy::pick(&Some(range), None, ScanOrder::Random).order();
+        result.sort_unstable();
+        assert_eq!(result, (0..=65_535).collect::<Vec<u16>>());
+    }
+
     #[test]
     fn serial_strategy_with_range() {
         let range = PortRange { start: 1, end: 100 };
@@ -0,0 +1,285 @@
+//! What the current privileges allow, and `rustscan setup-caps`.
+//!
+//! Connect and UDP scans work as any user, but SYN scans, ICMP and ARP
+//! need raw sockets, which only root, or on Linux a binary with
+//! `CAP_NET_RAW`, can open. Each is checked by opening the socket it needs,
+//! so the answer holds whatever granted it: root, a capability, or a
+//! container's settings. A scan asking for one that isn't available stops
+//! with [`explain`], saying which and how to enable it.
+//!
+//! `rustscan setup-caps` grants the binary `CAP_NET_RAW` once, so SYN scans
+//! work without sudo from then on:
+//!
+//! ```text
+//! sudo setcap cap_net_raw+ep /usr/local/bin/rustscan
+//! ```
+use crate::input::Opts;
+use crate::{detail, output, warning};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::fmt;
+use std::io;
+use std::net::UdpSocket;
+
+/// A feature that may need more privileges than a connect scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Syn,
+    Udp,
+    Icmp,
+    Arp,
+}
+
+impl Feature {
+    pub const ALL: [Self; 4] = [Self::Syn, Self::Udp, Self::Icmp, Self::Arp];
+
+    /// What needs it.
+    pub fn used_by(self) -> &'static str {
+        match self {
+            Self::Syn => "SYN scans (--tcp-engine syn, --stateless)",
+            Self::Udp => "UDP scans (--udp)",
+            Self::Icmp => "ICMP echo requests and errors",
+            Self::Arp => "ARP requests on the local network",
+        }
+    }
+
+    /// Opens the socket the feature needs.
+    fn probe(self) -> io::Result<()> {
+        match self {
+            Self::Syn => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::TCP)).map(drop),
+            Self::Udp => UdpSocket::bind("0.0.0.0:0").map(drop),
+            Self::Icmp => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)).map(drop),
+            Self::Arp => arp_socket(),
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Syn => "SYN",
+            Self::Udp => "UDP",
+            Self::Icmp => "ICMP",
+            Self::Arp => "ARP",
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn arp_socket() -> io::Result<()> {
+    const ETH_P_ARP: u16 = 0x0806;
+    Socket::new(
+        Domain::PACKET,
+        Type::RAW,
+        Some(Protocol::from(i32::from(ETH_P_ARP.to_be()))),
+    )
+    .map(drop)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn arp_socket() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "raw packet sockets are only opened on Linux",
+    ))
+}
+
+/// Whether a feature is available, and why not.
+#[derive(Debug)]
+pub struct Availability {
+    pub feature: Feature,
+    pub error: Option<io::Error>,
+}
+
+/// Checks every [`Feature`].
+pub fn detect() -> Vec<Availability> {
+    Feature::ALL
+        .iter()
+        .map(|feature| Availability {
+            feature: *feature,
+            error: feature.probe().err(),
+        })
+        .collect()
+}
+
+/// Checks a single [`Feature`].
+pub fn check(feature: Feature) -> io::Result<()> {
+    feature.probe()
+}
+
+/// How to get the privileges raw sockets need on this OS.
+pub fn how_to_enable() -> &'static str {
+    if cfg!(target_os = "linux") {
+        "run as root, or grant RustScan CAP_NET_RAW once with 'rustscan setup-caps'"
+    } else if cfg!(windows) {
+        "run from an Administrator prompt, though Windows doesn't allow sending raw TCP"
+    } else {
+        "run with sudo"
+    }
+}
+
+/// Why `feature` is unavailable and how to enable it.
+pub fn explain(feature: Feature, error: &io::Error) -> String {
+    format!(
+        "{feature} is unavailable at the current privilege level ({error}), which {} need. To enable it, {}.",
+        feature.used_by(),
+        how_to_enable()
+    )
+}
+
+/// Prints which features are available, returns whether all are.
+pub fn print(opts: &Opts) -> bool {
+    let availability = detect();
+    for Availability { feature, error } in &availability {
+        match error {
+            None => output!(
+                format!("{feature}: available, for {}", feature.used_by()),
+                false,
+                opts.accessible
+            ),
+            Some(e) => warning!(explain(*feature, e), false, opts.accessible),
+        }
+    }
+    availability
+        .iter()
+        .all(|availability| availability.error.is_none())
+}
+
+/// The command that grants `binary` `CAP_NET_RAW`, through sudo unless
+/// running as root.
+#[cfg(target_os = "linux")]
+fn setcap_command(binary: &std::path::Path, root: bool) -> Vec<String> {
+    let mut command = Vec::new();
+    if !root {
+        command.push("sudo".to_owned());
+    }
+    command.push("setcap".to_owned());
+    command.push("cap_net_raw+ep".to_owned());
+    command.push(binary.display().to_string());
+    command
+}
+
+/// `rustscan setup-caps`: grants the running binary `CAP_NET_RAW` after
+/// asking, unless `yes`. Returns whether it was granted.
+#[cfg(target_os = "linux")]
+pub fn setup_caps(yes: bool, opts: &Opts) -> bool {
+    use std::io::BufRead;
+    use std::process::Command;
+
+    let binary = match std::env::current_exe().and_then(std::fs::canonicalize) {
+        Ok(binary) => binary,
+        Err(e) => {
+            warning!(
+                format!("Could not find the RustScan binary: {e}"),
+                false,
+                opts.accessible
+            );
+            return false;
+        }
+    };
+    // SAFETY: geteuid can't fail.
+    let root = unsafe { libc::geteuid() } == 0;
+    let command = setcap_command(&binary, root);
+    detail!(
+        format!(
+            "This lets anyone who can run {} open raw sockets, for SYN scans without root. \
+             It lasts until the binary is replaced, by an update for example.",
+            binary.display()
+        ),
+        false,
+        opts.accessible
+    );
+    output!(
+        format!("This runs: {}", command.join(" ")),
+        false,
+        opts.accessible
+    );
+    if !yes {
+        print!("Continue? [y/N] ");
+        let _ = io::Write::flush(&mut io::stdout());
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer).is_err()
+            || !matches!(answer.trim(), "y" | "Y" | "yes")
+        {
+            detail!("Nothing changed.", false, opts.accessible);
+            return false;
+        }
+    }
+    match Command::new(&command[0]).args(&command[1..]).status() {
+        Ok(status) if status.success() => {
+            output!(
+                "Granted CAP_NET_RAW. Check what's available with 'rustscan doctor'.",
+                false,
+                opts.accessible
+            );
+            true
+        }
+        Ok(status) => {
+            warning!(
+                format!("setcap failed ({status}). It needs the libcap tools, and a file system that keeps capabilities."),
+                false,
+                opts.accessible
+            );
+            false
+        }
+        Err(e) => {
+            warning!(
+                format!(
+                    "Could not run {}: {e}. Install the libcap tools (libcap2-bin on Debian).",
+                    command[0]
+                ),
+                false,
+                opts.accessible
+            );
+            false
+        }
+    }
+}
+
+/// `rustscan setup-caps`: capabilities are Linux only.
+#[cfg(not(target_os = "linux"))]
+pub fn setup_caps(_yes: bool, opts: &Opts) -> bool {
+    warning!(
+        format!(
+            "setup-caps only works on Linux. For raw sockets here, {}.",
+            how_to_enable()
+        ),
+        false,
+        opts.accessible
+    );
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect, explain, Feature};
+    use std::io;
+
+    #[test]
+    fn explains_what_is_missing() {
+        let error = io::Error::from(io::ErrorKind::PermissionDenied);
+        let explanation = explain(Feature::Syn, &error);
+        assert!(explanation.starts_with("SYN is unavailable at the current privilege level"));
+        assert!(explanation.contains("--tcp-engine syn"));
+        assert!(explanation.contains("To enable it, "));
+    }
+
+    #[test]
+    fn udp_needs_no_privileges() {
+        let availability = detect();
+        assert_eq!(availability.len(), Feature::ALL.len());
+        assert!(availability.iter().any(
+            |availability| availability.feature == Feature::Udp && availability.error.is_none()
+        ));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn setcap_goes_through_sudo_unless_root() {
+        let binary = std::path::Path::new("/usr/local/bin/rustscan");
+        assert_eq!(
+            super::setcap_command(binary, false).join(" "),
+            "sudo setcap cap_net_raw+ep /usr/local/bin/rustscan"
+        );
+        assert_eq!(super::setcap_command(binary, true)[0], "setcap");
+    }
+}
@@ -0,0 +1,139 @@
+//! Runs a scan on a thread of its own, for callers that can't block on
+//! [`Scanner::run`], like the C bindings or a Python interpreter.
+//!
+//! The open ports are handed out one at a time as they're found, and the
+//! scan is cancelled when the [`BackgroundScan`] is dropped.
+//!
+//! ```rust
+//! use rustscan::scanner::background::BackgroundScan;
+//! use rustscan::ScannerBuilder;
+//! use std::time::Duration;
+//!
+//! let scanner = ScannerBuilder::new()
+//!     .targets(["127.0.0.1".parse().unwrap()])
+//!     .ports([22, 80, 443])
+//!     .build();
+//! let mut scan = BackgroundScan::start(scanner).unwrap();
+//! while !scan.is_done() {
+//!     while let Some(open) = scan.next_open() {
+//!         println!("{open}");
+//!     }
+//!     std::thread::sleep(Duration::from_millis(50));
+//! }
+//! while let Some(open) = scan.next_open() {
+//!     println!("{open}");
+//! }
+//! ```
+use super::cancel::CancelToken;
+use super::Scanner;
+use std::collections::HashSet;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// A scan running on its own thread.
+#[derive(Debug)]
+pub struct BackgroundScan {
+    scanner: Arc<Scanner>,
+    thread: Option<JoinHandle<Vec<SocketAddr>>>,
+    /// Every open port, sorted, once the scan is done.
+    open: Option<Vec<SocketAddr>>,
+    handed_out: HashSet<SocketAddr>,
+}
+
+impl BackgroundScan {
+    /// Starts `scanner` on a new thread.
+    pub fn start(scanner: Scanner) -> io::Result<Self> {
+        let scanner = Arc::new(scanner);
+        let thread = {
+            let scanner = Arc::clone(&scanner);
+            std::thread::Builder::new()
+                .name("rustscan".to_owned())
+                .spawn(move || async_std::task::block_on(scanner.run()))?
+        };
+        Ok(Self {
+            scanner,
+            thread: Some(thread),
+            open: None,
+            handed_out: HashSet::new(),
+        })
+    }
+
+    /// Ends the scan from anywhere, see [`cancel`](super::cancel).
+    pub fn cancel_token(&self) -> CancelToken {
+        self.scanner.cancel_token()
+    }
+
+    /// Whether the scan is over, finished or cancelled.
+    pub fn is_done(&mut self) -> bool {
+        if self.open.is_none() && self.thread.as_ref().is_some_and(JoinHandle::is_finished) {
+            let thread = self.thread.take().expect("checked above");
+            let mut open = thread.join().unwrap_or_default();
+            open.sort_unstable();
+            self.open = Some(open);
+        }
+        self.open.is_some()
+    }
+
+    /// Every open port once the scan is done.
+    pub fn open_ports(&mut self) -> Option<&[SocketAddr]> {
+        self.is_done();
+        self.open.as_deref()
+    }
+
+    /// An open port not handed out yet, if one was found so far.
+    pub fn next_open(&mut self) -> Option<SocketAddr> {
+        self.is_done();
+        let found = match &self.open {
+            Some(open) => open.clone(),
+            None => self.scanner.discoveries().into_keys().collect(),
+        };
+        let next = found
+            .into_iter()
+            .find(|socket| !self.handed_out.contains(socket))?;
+        self.handed_out.insert(next);
+        Some(next)
+    }
+}
+
+impl Drop for BackgroundScan {
+    /// Cancels the scan and waits for its probes in flight.
+    fn drop(&mut self) {
+        self.scanner.cancel_token().cancel();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BackgroundScan;
+    use crate::ScannerBuilder;
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn hands_out_open_ports_once() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap();
+        let scanner = ScannerBuilder::new()
+            .targets([open.ip()])
+            .ports([open.port(), open.port().wrapping_add(1)])
+            .build();
+        let mut scan = BackgroundScan::start(scanner).unwrap();
+
+        let started = Instant::now();
+        let mut found = Vec::new();
+        while !scan.is_done() {
+            assert!(started.elapsed() < Duration::from_secs(10));
+            found.extend(scan.next_open());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        found.extend(scan.next_open());
+        assert_eq!(found, [open]);
+        assert_eq!(scan.next_open(), None);
+        assert_eq!(scan.open_ports(), Some(&[open][..]));
+    }
+}
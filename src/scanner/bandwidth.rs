@@ -0,0 +1,199 @@
+//! Estimates the traffic a scan generates and caps it.
+//!
+//! Sockets don't tell how many bytes go over the wire, so every probe is
+//! charged what its packets typically weigh: a TCP probe is a SYN with
+//! options and the SYN-ACK or RST answering it, an open port adds the ACK
+//! and the FIN exchange closing the connection, and a UDP probe is its
+//! payload with the UDP and IP headers. With `--max-bandwidth`, probes wait
+//! until the estimated rate drops below the cap, so scans over constrained
+//! VPN links don't saturate them.
+use anyhow::{anyhow, Result};
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// TCP header with the options a SYN usually carries.
+const TCP_SYN_HEADER: u64 = 40;
+const TCP_HEADER: u64 = 20;
+const UDP_HEADER: u64 = 8;
+
+fn ip_header(ip: IpAddr) -> u64 {
+    match ip {
+        IpAddr::V4(_) => 20,
+        IpAddr::V6(_) => 40,
+    }
+}
+
+/// Bytes of a TCP connection attempt: the SYN and its answer.
+pub fn tcp_probe_bytes(ip: IpAddr) -> u64 {
+    2 * (ip_header(ip) + TCP_SYN_HEADER)
+}
+
+/// Bytes an open TCP port adds: the ACK and the FIN exchange.
+pub fn tcp_open_bytes(ip: IpAddr) -> u64 {
+    4 * (ip_header(ip) + TCP_HEADER)
+}
+
+/// Bytes of a UDP probe carrying `payload` bytes.
+pub fn udp_probe_bytes(ip: IpAddr, payload: usize) -> u64 {
+    ip_header(ip) + UDP_HEADER + payload as u64
+}
+
+/// A rate in bits per second, such as `5mbps`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bandwidth(pub u64);
+
+impl Bandwidth {
+    pub fn bytes_per_second(self) -> u64 {
+        self.0 / 8
+    }
+}
+
+impl FromStr for Bandwidth {
+    type Err = anyhow::Error;
+
+    fn from_str(rate: &str) -> Result<Self> {
+        let lower = rate.trim().to_lowercase();
+        let digits = lower
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(lower.len());
+        let (number, unit) = lower.split_at(digits);
+        let multiplier = match unit.trim() {
+            "" | "bps" => 1.0,
+            "kbps" => 1e3,
+            "mbps" => 1e6,
+            "gbps" => 1e9,
+            _ => {
+                return Err(anyhow!(
+                    "{rate} should be a rate like 500kbps, 5mbps or 1gbps"
+                ))
+            }
+        };
+        let number: f64 = number
+            .parse()
+            .map_err(|_| anyhow!("{rate} should be a rate like 500kbps, 5mbps or 1gbps"))?;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let bits = (number * multiplier) as u64;
+        // Anything below a byte per second would never let a probe through.
+        if bits < 8 {
+            return Err(anyhow!("{rate} is too low to scan with"));
+        }
+        Ok(Self(bits))
+    }
+}
+
+impl fmt::Display for Bandwidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[allow(clippy::cast_precision_loss)]
+        let bits = self.0 as f64;
+        match self.0 {
+            rate if rate >= 1_000_000_000 => write!(f, "{}gbps", bits / 1e9),
+            rate if rate >= 1_000_000 => write!(f, "{}mbps", bits / 1e6),
+            rate if rate >= 1_000 => write!(f, "{}kbps", bits / 1e3),
+            _ => write!(f, "{bits}bps"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Bandwidth {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rate = String::deserialize(deserializer)?;
+        rate.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Bandwidth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A token bucket holding a tenth of a second of traffic.
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    bandwidth: Bandwidth,
+    bytes_per_second: f64,
+    burst: f64,
+    /// Bytes that can be sent right away, negative when probes are waiting,
+    /// and when it was last topped up.
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(bandwidth: Bandwidth) -> Self {
+        #[allow(clippy::cast_precision_loss)]
+        let bytes_per_second = bandwidth.bytes_per_second() as f64;
+        let burst = bytes_per_second / 10.0;
+        Self {
+            bandwidth,
+            bytes_per_second,
+            burst,
+            bucket: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    pub fn bandwidth(&self) -> Bandwidth {
+        self.bandwidth
+    }
+
+    /// Reserves `bytes` and returns how long to wait before sending them.
+    pub fn reserve(&self, bytes: u64) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let (available, last) = &mut *bucket;
+        let now = Instant::now();
+        *available = (*available + now.duration_since(*last).as_secs_f64() * self.bytes_per_second)
+            .min(self.burst);
+        *last = now;
+        #[allow(clippy::cast_precision_loss)]
+        let bytes = bytes as f64;
+        *available -= bytes;
+        if *available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-*available / self.bytes_per_second)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tcp_probe_bytes, udp_probe_bytes, Bandwidth, BandwidthLimiter};
+    use std::time::Duration;
+
+    #[test]
+    fn parse_rates() {
+        assert_eq!("5mbps".parse::<Bandwidth>().unwrap(), Bandwidth(5_000_000));
+        assert_eq!("1.5Gbps".parse::<Bandwidth>().unwrap().0, 1_500_000_000);
+        assert_eq!("500 kbps".parse::<Bandwidth>().unwrap().0, 500_000);
+        assert_eq!("64000".parse::<Bandwidth>().unwrap().0, 64_000);
+        assert_eq!(Bandwidth(5_000_000).to_string(), "5mbps");
+        assert_eq!(Bandwidth(1_500_000_000).to_string(), "1.5gbps");
+        assert!("5mb".parse::<Bandwidth>().is_err());
+        assert!("fast".parse::<Bandwidth>().is_err());
+        assert!("0bps".parse::<Bandwidth>().is_err());
+    }
+
+    #[test]
+    fn estimates_probes() {
+        let v4 = "10.0.0.1".parse().unwrap();
+        let v6 = "::1".parse().unwrap();
+        assert_eq!(tcp_probe_bytes(v4), 120);
+        assert_eq!(tcp_probe_bytes(v6), 160);
+        assert_eq!(udp_probe_bytes(v4, 12), 40);
+    }
+
+    #[test]
+    fn waits_once_the_burst_is_spent() {
+        // 8 kbps is 1000 bytes per second, with a burst of 100 bytes.
+        let limiter = BandwidthLimiter::new(Bandwidth(8_000));
+        assert_eq!(limiter.reserve(100), Duration::ZERO);
+        let wait = limiter.reserve(500);
+        assert!(wait > Duration::from_millis(450) && wait <= Duration::from_millis(500));
+        let wait = limiter.reserve(500);
+        assert!(wait > Duration::from_millis(950) && wait <= Duration::from_millis(1000));
+    }
+}
@@ -0,0 +1,162 @@
+//! A keyed pseudo-random permutation of the sockets of a stateless scan.
+//!
+//! Stateless scans keep nothing about the sockets they've probed, so their
+//! order has to be computable: `--stateless` goes through the sockets in
+//! the order of a keyed permutation of their indexes, as masscan's
+//! blackrock shuffle does. Index `i` maps to a unique index below the
+//! number of sockets through a small Feistel network over `a * b >= range`,
+//! cycle-walking past the values out of range. Every socket is probed
+//! exactly once, spread over hosts and ports rather than hammering one host
+//! at a time, and with the same `--shuffle-seed` a scan can be resumed from
+//! any `--resume-index` without going through the sockets before it.
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
+
+/// Feistel rounds, an even number.
+const ROUNDS: u32 = 4;
+
+/// A permutation of `0..range`, keyed by a seed.
+#[derive(Debug, Clone, Copy)]
+pub struct Blackrock {
+    range: u64,
+    a: u64,
+    b: u64,
+    seed: u64,
+}
+
+impl Blackrock {
+    pub fn new(range: u64, seed: u64) -> Self {
+        // Two factors close to the square root, a bit over the range.
+        let root = (range as f64).sqrt() as u64;
+        let a = root.max(1);
+        let mut b = root.max(1);
+        while a * b < range {
+            b += 1;
+        }
+        Self { range, a, b, seed }
+    }
+
+    fn round(&self, round: u32, value: u64) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (self.seed, round, value).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// A permutation of `0..a * b`, the halves ranging over `0..a` and
+    /// `0..b` in turn, an even number of rounds bringing them back.
+    fn encrypt(&self, value: u64) -> u64 {
+        let (mut left, mut right) = (value % self.a, value / self.a);
+        for round in 1..=ROUNDS {
+            let modulus = if round % 2 == 1 { self.a } else { self.b };
+            let mixed = (left + self.round(round, right) % modulus) % modulus;
+            left = right;
+            right = mixed;
+        }
+        left + self.a * right
+    }
+
+    fn decrypt(&self, value: u64) -> u64 {
+        let (mut left, mut right) = (value % self.a, value / self.a);
+        for round in (1..=ROUNDS).rev() {
+            let modulus = if round % 2 == 1 { self.a } else { self.b };
+            let previous = left;
+            left = (right + modulus - self.round(round, previous) % modulus) % modulus;
+            right = previous;
+        }
+        left + self.a * right
+    }
+
+    /// Where `index` goes, below the range.
+    pub fn shuffle(&self, index: u64) -> u64 {
+        let mut value = self.encrypt(index);
+        while value >= self.range {
+            value = self.encrypt(value);
+        }
+        value
+    }
+
+    /// The index that shuffles to `value`.
+    pub fn unshuffle(&self, value: u64) -> u64 {
+        let mut index = self.decrypt(value);
+        while index >= self.range {
+            index = self.decrypt(index);
+        }
+        index
+    }
+}
+
+/// The sockets of `ips` by `ports`, in shuffled order from `start`.
+pub struct ShuffledSockets<'s> {
+    ips: &'s [IpAddr],
+    ports: &'s [u16],
+    order: Blackrock,
+    next: u64,
+}
+
+impl<'s> ShuffledSockets<'s> {
+    pub fn new(ips: &'s [IpAddr], ports: &'s [u16], seed: u64, start: u64) -> Self {
+        let range = ips.len() as u64 * ports.len() as u64;
+        Self {
+            ips,
+            ports,
+            order: Blackrock::new(range, seed),
+            next: start.min(range),
+        }
+    }
+}
+
+impl Iterator for ShuffledSockets<'_> {
+    type Item = SocketAddr;
+
+    /// The shuffled index picks the host by its remainder and the port by
+    /// its quotient.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.order.range {
+            return None;
+        }
+        let index = self.order.shuffle(self.next);
+        self.next += 1;
+        let hosts = self.ips.len() as u64;
+        Some(SocketAddr::new(
+            self.ips[(index % hosts) as usize],
+            self.ports[(index / hosts) as usize],
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Blackrock, ShuffledSockets};
+    use std::collections::HashSet;
+    use std::net::IpAddr;
+
+    #[test]
+    fn shuffles_are_permutations() {
+        for range in [1, 2, 7, 10, 100, 1000, 65535].iter().copied() {
+            let order = Blackrock::new(range, 42);
+            let shuffled: HashSet<u64> = (0..range).map(|index| order.shuffle(index)).collect();
+            assert_eq!(shuffled.len() as u64, range);
+            assert!(shuffled.iter().all(|value| *value < range));
+            for index in (0..range).step_by(7) {
+                assert_eq!(order.unshuffle(order.shuffle(index)), index);
+            }
+        }
+        let (one, other) = (Blackrock::new(1000, 1), Blackrock::new(1000, 2));
+        assert!((0..1000).any(|index| one.shuffle(index) != other.shuffle(index)));
+        assert!((0..1000).any(|index| one.shuffle(index) != index));
+    }
+
+    #[test]
+    fn shuffled_sockets_cover_everything_and_resume() {
+        let ips: Vec<IpAddr> = (1..=5).map(|host| IpAddr::from([10, 0, 0, host])).collect();
+        let ports: Vec<u16> = (1..=20).collect();
+        let all: Vec<_> = ShuffledSockets::new(&ips, &ports, 7, 0).collect();
+        assert_eq!(all.len(), 100);
+        assert_eq!(all.iter().collect::<HashSet<_>>().len(), 100);
+
+        let resumed: Vec<_> = ShuffledSockets::new(&ips, &ports, 7, 60).collect();
+        assert_eq!(resumed, all[60..]);
+        assert_eq!(ShuffledSockets::new(&ips, &ports, 7, 200).next(), None);
+    }
+}
@@ -0,0 +1,196 @@
+//! Builds a [`Scanner`] for library use, without going through [`Opts`].
+//!
+//! ```rust
+//! use async_std::task::block_on;
+//! use rustscan::{ScanTiming, ScannerBuilder};
+//! use std::time::Duration;
+//!
+//! let scanner = ScannerBuilder::new()
+//!     .targets(["127.0.0.1".parse().unwrap()])
+//!     .ports([22, 80, 443])
+//!     .timing(ScanTiming {
+//!         timeout: Duration::from_millis(200),
+//!         ..ScanTiming::default()
+//!     })
+//!     .build();
+//! let open = block_on(scanner.run());
+//! # let _ = open;
+//! ```
+//!
+//! Anything not set defaults to what the command line uses: ports 1-65535
+//! in random order, the default timing, and no output while scanning. The
+//! scanner's `with_*` methods take it from there.
+//!
+//! [`Opts`]: crate::input::Opts
+use super::Scanner;
+use crate::input::{PortRange, ScanOrder};
+use crate::port_strategy::PortStrategy;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// How fast, and how patiently, ports are scanned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanTiming {
+    /// Ports scanned at the same time.
+    pub batch_size: usize,
+    /// How long a port may take to answer before it's closed.
+    pub timeout: Duration,
+    /// Tries before a port is closed, at least 1.
+    pub tries: u8,
+}
+
+impl Default for ScanTiming {
+    /// The defaults of `--batch-size`, `--timeout` and `--tries`.
+    fn default() -> Self {
+        Self {
+            batch_size: 4500,
+            timeout: Duration::from_millis(1500),
+            tries: 1,
+        }
+    }
+}
+
+/// Builder of a [`Scanner`], see the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct ScannerBuilder {
+    targets: Vec<IpAddr>,
+    ports: Option<Vec<u16>>,
+    range: PortRange,
+    order: ScanOrder,
+    timing: ScanTiming,
+    exclude_ports: Vec<u16>,
+    udp: bool,
+    greppable: bool,
+    accessible: bool,
+}
+
+impl Default for ScannerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScannerBuilder {
+    pub fn new() -> Self {
+        Self {
+            targets: Vec::new(),
+            ports: None,
+            range: PortRange {
+                start: 1,
+                end: 65_535,
+            },
+            order: ScanOrder::Random,
+            timing: ScanTiming::default(),
+            exclude_ports: Vec::new(),
+            udp: false,
+            greppable: true,
+            accessible: false,
+        }
+    }
+
+    /// Adds addresses to scan.
+    pub fn targets(mut self, targets: impl IntoIterator<Item = IpAddr>) -> Self {
+        self.targets.extend(targets);
+        self
+    }
+
+    /// Scans these ports instead of a range.
+    pub fn ports(mut self, ports: impl IntoIterator<Item = u16>) -> Self {
+        self.ports.get_or_insert_with(Vec::new).extend(ports);
+        self
+    }
+
+    /// Scans the ports `start..=end`, unless [`ports`](Self::ports) are set.
+    pub fn range(mut self, start: u16, end: u16) -> Self {
+        self.range = PortRange { start, end };
+        self
+    }
+
+    /// The order ports are scanned in.
+    pub fn order(mut self, order: ScanOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn timing(mut self, timing: ScanTiming) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Ports never scanned, even if they're in the range.
+    pub fn exclude_ports(mut self, ports: impl IntoIterator<Item = u16>) -> Self {
+        self.exclude_ports.extend(ports);
+        self
+    }
+
+    /// Scans UDP instead of TCP.
+    pub fn udp(mut self, udp: bool) -> Self {
+        self.udp = udp;
+        self
+    }
+
+    /// Whether the scanner stays quiet, the default, instead of printing
+    /// open ports as they're found.
+    pub fn greppable(mut self, greppable: bool) -> Self {
+        self.greppable = greppable;
+        self
+    }
+
+    /// Prints without colors and symbols, for screen readers.
+    pub fn accessible(mut self, accessible: bool) -> Self {
+        self.accessible = accessible;
+        self
+    }
+
+    pub fn build(self) -> Scanner {
+        let strategy = PortStrategy::pick(&Some(self.range), self.ports, self.order);
+        Scanner::new(
+            &self.targets,
+            self.timing.batch_size,
+            self.timing.timeout,
+            self.timing.tries,
+            self.greppable,
+            strategy,
+            self.accessible,
+            self.exclude_ports,
+            self.udp,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ScanTiming, ScannerBuilder};
+    use crate::input::ScanOrder;
+    use async_std::task::block_on;
+    use std::net::{IpAddr, Ipv4Addr, TcpListener};
+    use std::time::Duration;
+
+    #[test]
+    fn builds_a_scanner_without_opts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let scanner = ScannerBuilder::new()
+            .targets([localhost])
+            .ports([port, port.wrapping_add(1)])
+            .order(ScanOrder::Serial)
+            .timing(ScanTiming {
+                batch_size: 10,
+                timeout: Duration::from_millis(500),
+                ..ScanTiming::default()
+            })
+            .build();
+        let open = block_on(scanner.run());
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].port(), port);
+
+        let scanner = ScannerBuilder::new()
+            .targets([localhost])
+            .range(port, port)
+            .exclude_ports([port])
+            .build();
+        assert!(block_on(scanner.run()).is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+//! Cancels a running scan from outside of it.
+//!
+//! Every [`Scanner`](super::Scanner) has a [`CancelToken`]; a clone taken
+//! with `cancel_token()` before the scan starts can be moved to another task
+//! or thread. Cancelling stops new probes from being sent, lets the ones in
+//! flight finish, and makes `run` return the ports found so far, with result
+//! shards flushed, the way `q` ends an interactive scan. Waits for a scan
+//! window or for `--health-check` to answer again are cut short as well.
+//!
+//! ```rust
+//! use async_std::task;
+//! use rustscan::ScannerBuilder;
+//! use std::time::Duration;
+//!
+//! let scanner = ScannerBuilder::new()
+//!     .targets(["127.0.0.1".parse().unwrap()])
+//!     .build();
+//! let cancel = scanner.cancel_token();
+//! task::spawn(async move {
+//!     task::sleep(Duration::from_millis(100)).await;
+//!     cancel.cancel();
+//! });
+//! let open_so_far = task::block_on(scanner.run());
+//! # let _ = open_so_far;
+//! ```
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often a cancelled wait notices it.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Ends the scan it belongs to, clones share the same scan.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the scan to stop, once its probes in flight are done.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Sleeps for `duration`, or until cancelled. Returns whether the whole
+    /// duration passed.
+    pub async fn sleep(&self, duration: Duration) -> bool {
+        let end = Instant::now() + duration;
+        loop {
+            if self.is_cancelled() {
+                return false;
+            }
+            let remaining = end.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return true;
+            }
+            async_std::task::sleep(remaining.min(POLL_INTERVAL)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CancelToken;
+    use async_std::task::block_on;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn cancelling_cuts_sleeps_short() {
+        let token = CancelToken::new();
+        assert!(block_on(token.sleep(Duration::from_millis(10))));
+
+        let clone = token.clone();
+        let started = Instant::now();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            clone.cancel();
+        });
+        assert!(!block_on(token.sleep(Duration::from_secs(30))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(token.is_cancelled());
+    }
+}
@@ -0,0 +1,413 @@
+//! Keys that steer a scan while it runs, like nmap's runtime interaction.
+//!
+//! When stdin is a terminal and neither `--greppable` nor `--accessible` is
+//! given, the scan reads single keypresses:
+//!
+//! - `+` and `-` raise and lower how many sockets are probed at once, which
+//!   sets how fast the scan goes, in steps of a tenth of the batch size:
+//!   down to a tenth of it, and up to as many sockets as the open file
+//!   limit and the ephemeral ports allow,
+//! - `p` pauses the scan once the probes in flight are done, and resumes it,
+//! - `s` prints how far the scan got,
+//! - `q` finishes the probes in flight and ends the scan there, reporting
+//!   what was found so far, and so does Ctrl-C, through the scan's
+//!   [`CancelToken`].
+//!
+//! The terminal is put in non-canonical mode for the scan, so keys don't
+//! need Enter, and restored afterwards. Only Ctrl-C is read as a key,
+//! Ctrl-Z and `Ctrl-\` still suspend and quit.
+use super::cancel::CancelToken;
+use super::progress::Progress;
+use crate::{detail, units};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The share of the batch size `+` and `-` add or remove, in percent.
+const STEP: u16 = 10;
+
+/// What the keys pressed so far asked for.
+#[derive(Debug)]
+pub struct Controls {
+    batch_size: usize,
+    /// The share of the batch size probed at once, in percent.
+    percent: AtomicU16,
+    /// How high `+` takes `percent`, for as many sockets as can be open.
+    max_percent: u16,
+    paused: AtomicBool,
+    finishing: AtomicBool,
+    summary: AtomicBool,
+    started: OnceLock<Instant>,
+}
+
+impl Controls {
+    /// Controls starting at `batch_size`, which `+` raises up to `ceiling`
+    /// sockets.
+    pub fn new(batch_size: usize, ceiling: usize) -> Self {
+        let max_percent = ceiling.saturating_mul(100) / batch_size.max(1);
+        Self {
+            batch_size,
+            percent: AtomicU16::new(100),
+            max_percent: u16::try_from(max_percent).unwrap_or(u16::MAX).max(100),
+            paused: AtomicBool::new(false),
+            finishing: AtomicBool::new(false),
+            summary: AtomicBool::new(false),
+            started: OnceLock::new(),
+        }
+    }
+
+    /// Controls read from stdin, if it's a terminal the scan runs in the
+    /// foreground of and the output is meant for people.
+    pub fn interactive(
+        batch_size: usize,
+        ceiling: usize,
+        greppable: bool,
+        accessible: bool,
+    ) -> Option<Self> {
+        use std::io::IsTerminal;
+        (cfg!(unix)
+            && !greppable
+            && !accessible
+            && std::io::stdin().is_terminal()
+            && terminal::is_foreground())
+        .then(|| Self::new(batch_size, ceiling))
+    }
+
+    /// `batch_size` scaled by `+` and `-`, at least 1.
+    pub fn batch_size(&self, batch_size: usize) -> usize {
+        (batch_size * usize::from(self.percent.load(Ordering::Relaxed)) / 100).max(1)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn is_finishing(&self) -> bool {
+        self.finishing.load(Ordering::Relaxed)
+    }
+
+    /// Whether a summary was asked for since the last call.
+    pub fn take_summary(&self) -> bool {
+        self.summary.swap(false, Ordering::Relaxed)
+    }
+
+    /// When the scan started reading keys.
+    pub fn started(&self) -> Instant {
+        *self.started.get_or_init(Instant::now)
+    }
+
+    /// Applies `key`, returns what to tell the user about it.
+    pub fn press(&self, key: u8) -> Option<String> {
+        match key {
+            b'+' | b'=' | b'-' | b'_' => {
+                let percent = self.percent.load(Ordering::Relaxed);
+                let percent = if matches!(key, b'+' | b'=') {
+                    percent.saturating_add(STEP).min(self.max_percent)
+                } else {
+                    percent.saturating_sub(STEP).max(STEP)
+                };
+                self.percent.store(percent, Ordering::Relaxed);
+                Some(format!(
+                    "Probing {} sockets at once ({percent}% of the batch size)",
+                    units::thousands(self.batch_size(self.batch_size) as u64)
+                ))
+            }
+            b'p' | b'P' => {
+                let paused = !self.paused.fetch_xor(true, Ordering::Relaxed);
+                Some(if paused {
+                    "Pausing once the probes in flight are done, press p to resume".to_owned()
+                } else {
+                    "Resuming the scan".to_owned()
+                })
+            }
+            b's' | b'S' => {
+                self.summary.store(true, Ordering::Relaxed);
+                None
+            }
+            b'q' | b'Q' => {
+                self.finishing.store(true, Ordering::Relaxed);
+                Some("Finishing the probes in flight, then ending the scan".to_owned())
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads keys from the terminal until the returned handle is dropped,
+    /// cancelling the scan with `cancel` on Ctrl-C.
+    pub fn listen(self: &Arc<Self>, progress: Option<Arc<Progress>>, cancel: CancelToken) -> Keys {
+        self.started();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = terminal::RawMode::enable().map(|raw_mode| {
+            let controls = Arc::clone(self);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    // Put in the background with Ctrl-Z and bg, reading
+                    // would stop the scan with SIGTTIN.
+                    if !terminal::is_foreground() {
+                        thread::sleep(Duration::from_millis(100));
+                        continue;
+                    }
+                    let key = match terminal::read_key() {
+                        Ok(Some(key)) => key,
+                        Ok(None) => continue,
+                        // EIO once the terminal is gone, or the scan is
+                        // in a background process group.
+                        Err(_) => break,
+                    };
+                    if key == terminal::CTRL_C {
+                        cancel.cancel();
+                        announce(
+                            progress.as_deref(),
+                            "Cancelling the scan once the probes in flight are done",
+                        );
+                        continue;
+                    }
+                    if let Some(message) = controls.press(key) {
+                        announce(progress.as_deref(), &message);
+                    }
+                }
+                drop(raw_mode);
+            })
+        });
+        Keys { stop, thread }
+    }
+}
+
+/// Prints `message` for the user, above the progress lines if any.
+pub fn announce(progress: Option<&Progress>, message: &str) {
+    let print = || {
+        detail!(message);
+    };
+    match progress {
+        Some(progress) => progress.above(print),
+        None => print(),
+    }
+}
+
+/// Keeps reading keys, stops and restores the terminal when dropped.
+#[derive(Debug)]
+pub struct Keys {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Keys {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(unix)]
+mod terminal {
+    use std::io;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Once, OnceLock};
+
+    /// What Ctrl-C reads as once the terminal doesn't turn it into SIGINT.
+    pub const CTRL_C: u8 = 0x03;
+
+    /// Whether the scan is the foreground process group of the terminal on
+    /// stdin. A background one setting the terminal up or reading from it
+    /// is stopped with SIGTTOU or SIGTTIN, as in `rustscan ... &`.
+    pub fn is_foreground() -> bool {
+        // SAFETY: both only read process group IDs, a closed or non-terminal
+        // stdin fails tcgetpgrp with -1, which no process group has.
+        unsafe { libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp() }
+    }
+
+    /// The terminal as it was before the first scan set it up.
+    static ORIGINAL: OnceLock<libc::termios> = OnceLock::new();
+    /// Whether the terminal is set up for reading keys, and needs restoring.
+    static RAW: AtomicBool = AtomicBool::new(false);
+
+    /// Puts the terminal back as it was, once. Only calls functions that are
+    /// async-signal-safe, so signal handlers can too.
+    fn restore() {
+        // In the background, the shell has set the terminal up for itself,
+        // and restoring it would stop the scan with SIGTTOU.
+        if !RAW.swap(false, Ordering::SeqCst) || !is_foreground() {
+            return;
+        }
+        if let Some(original) = ORIGINAL.get() {
+            // SAFETY: `original` is the valid termios tcgetattr gave.
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original) };
+        }
+    }
+
+    /// Restores the terminal when the process panics, which aborts without
+    /// running destructors in release builds, or is ended by a signal.
+    fn restore_on_exit() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            let hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                restore();
+                hook(info);
+            }));
+            let handler = on_signal as extern "C" fn(libc::c_int);
+            for signal in [libc::SIGTERM, libc::SIGHUP, libc::SIGINT, libc::SIGQUIT] {
+                // SAFETY: the handler only calls async-signal-safe
+                // functions. A signal ignored before, as under nohup, stays
+                // ignored.
+                unsafe {
+                    if libc::signal(signal, handler as libc::sighandler_t) == libc::SIG_IGN {
+                        libc::signal(signal, libc::SIG_IGN);
+                    }
+                }
+            }
+        });
+    }
+
+    extern "C" fn on_signal(signal: libc::c_int) {
+        restore();
+        // SAFETY: signal and raise are async-signal-safe. The signal is
+        // blocked until the handler returns, then ends the process as it
+        // would have without the handler.
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+
+    /// Stdin without line buffering or echo, and with Ctrl-C read as a key
+    /// rather than sent as SIGINT, restored on drop, on panics and on the
+    /// signals ending the process.
+    pub struct RawMode(());
+
+    impl RawMode {
+        pub fn enable() -> Option<Self> {
+            if !is_foreground() {
+                return None;
+            }
+            // SAFETY: termios is plain integers and arrays, for which all
+            // zeroes is a valid value, overwritten by tcgetattr anyway.
+            let mut original: libc::termios = unsafe { std::mem::zeroed() };
+            // SAFETY: `original` is a valid termios to write to, and stdin
+            // stays open for the whole process.
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+                return None;
+            }
+            let original = *ORIGINAL.get_or_init(|| original);
+            restore_on_exit();
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            // The other signal keys, Ctrl-Z and Ctrl-\, keep working.
+            raw.c_cc[libc::VINTR] = libc::_POSIX_VDISABLE;
+            // Reads return after a tenth of a second without a key, so the
+            // thread notices when the scan is over.
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 1;
+            RAW.store(true, Ordering::SeqCst);
+            // SAFETY: `raw` is a valid termios, read from the terminal.
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+                RAW.store(false, Ordering::SeqCst);
+                return None;
+            }
+            Some(Self(()))
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            restore();
+        }
+    }
+
+    /// The next key pressed, `None` if none was within a tenth of a second.
+    pub fn read_key() -> io::Result<Option<u8>> {
+        let mut key = 0_u8;
+        // SAFETY: `key` is a writable byte and at most one byte is read.
+        let read = unsafe { libc::read(libc::STDIN_FILENO, (&raw mut key).cast(), 1) };
+        match read {
+            1 => Ok(Some(key)),
+            0 => Ok(None),
+            _ => {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    Ok(None)
+                } else {
+                    Err(error)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    pub const CTRL_C: u8 = 0x03;
+
+    /// Keys aren't read outside of Unix, see [`super::Controls::interactive`].
+    pub struct RawMode;
+
+    impl RawMode {
+        pub fn enable() -> Option<Self> {
+            None
+        }
+    }
+
+    pub fn is_foreground() -> bool {
+        false
+    }
+
+    pub fn read_key() -> std::io::Result<Option<u8>> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Controls;
+
+    #[test]
+    fn keys_steer_the_scan() {
+        let controls = Controls::new(4_000, 4_000);
+        assert_eq!(controls.batch_size(4_000), 4_000);
+        controls.press(b'+');
+        assert_eq!(controls.batch_size(4_000), 4_000);
+        for _ in 0..20 {
+            controls.press(b'-');
+        }
+        assert_eq!(controls.batch_size(4_000), 400);
+        assert_eq!(controls.batch_size(5), 1);
+        assert_eq!(
+            controls.press(b'+').as_deref(),
+            Some("Probing 800 sockets at once (20% of the batch size)")
+        );
+
+        controls.press(b'p');
+        assert!(controls.is_paused());
+        controls.press(b'p');
+        assert!(!controls.is_paused());
+
+        assert!(!controls.take_summary());
+        assert_eq!(controls.press(b's'), None);
+        assert!(controls.take_summary());
+        assert!(!controls.take_summary());
+
+        assert!(!controls.is_finishing());
+        controls.press(b'q');
+        assert!(controls.is_finishing());
+    }
+
+    #[test]
+    fn plus_goes_past_the_batch_size_up_to_the_ceiling() {
+        let controls = Controls::new(4_000, 6_000);
+        assert_eq!(
+            controls.press(b'+').as_deref(),
+            Some("Probing 4,400 sockets at once (110% of the batch size)")
+        );
+        for _ in 0..20 {
+            controls.press(b'+');
+        }
+        assert_eq!(controls.batch_size(4_000), 6_000);
+        // Each protocol of a mixed scan gets its share.
+        assert_eq!(controls.batch_size(2_000), 3_000);
+    }
+}
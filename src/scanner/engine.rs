@@ -0,0 +1,2498 @@
+//! The [`Scanner`] itself, which needs sockets and so isn't built for wasm32.
+use crate::export::ndjson::{Event, EventStream};
+use crate::export::view::PortState;
+use crate::generated::get_parsed_data;
+use crate::input::{PortRange, TcpEngine, UdpEngine};
+use crate::port_strategy::PortStrategy;
+use crate::{detail, warning};
+use log::debug;
+
+#[cfg(feature = "ssh")]
+use super::ssh::SshTunnel;
+use super::{
+    bandwidth, blackrock, cancel, controls, health, payload, priority, progress, shards,
+    socket_iterator, split, stats, threads, ttl, udp_batch, wildcard, window,
+};
+#[cfg(feature = "raw-sockets")]
+use super::{stateless, syn};
+use bandwidth::{Bandwidth, BandwidthLimiter};
+use blackrock::ShuffledSockets;
+use cancel::CancelToken;
+use controls::Controls;
+use health::{HealthCheck, HEALTH_CHECK_INTERVAL};
+use payload::{escape, ProbePayload, ProbeResponse, RESPONSE_LIMIT};
+use priority::TargetPriorities;
+use progress::Progress;
+use shards::ResultShards;
+use socket_iterator::SocketIterator;
+use split::Shard;
+use stats::ProbeStats;
+#[cfg(feature = "raw-sockets")]
+use syn::{Reply, SynProbe, SEND_BURST};
+use threads::CpuSet;
+use ttl::Distance;
+use wildcard::WildcardPolicy;
+use window::ScanWindow;
+
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::{io, net::UdpSocket};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use futures::stream::FuturesUnordered;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr},
+    num::NonZeroU8,
+    time::Duration,
+};
+
+/// How long to back off when the OS runs out of local (ephemeral) ports.
+const EXHAUSTION_PAUSE: Duration = Duration::from_millis(250);
+/// How many times a single socket may back off before the error is reported.
+const MAX_EXHAUSTION_PAUSES: u32 = 8;
+
+/// The class for the scanner
+/// IP is data type IpAddr and is the IP address
+/// start & end is where the port scan starts and ends
+/// batch_size is how many ports at a time should be scanned
+/// Timeout is the time RustScan should wait before declaring a port closed. As datatype Duration.
+/// greppable is whether or not RustScan should print things, or wait until the end to print only the ip and open ports.
+#[cfg(not(tarpaulin_include))]
+#[derive(Debug)]
+pub struct Scanner {
+    ips: Vec<IpAddr>,
+    batch_size: usize,
+    timeout: Duration,
+    tries: NonZeroU8,
+    greppable: bool,
+    port_strategy: PortStrategy,
+    accessible: bool,
+    exclude_ports: Vec<u16>,
+    udp: bool,
+    linger: Option<Duration>,
+    local_port_range: Option<PortRange>,
+    priorities: Option<TargetPriorities>,
+    window: Option<ScanWindow>,
+    bandwidth: Option<BandwidthLimiter>,
+    health_check: Option<HealthCheck>,
+    /// Estimated bytes the probes put on the wire.
+    bytes_sent: AtomicU64,
+    stats: Mutex<HashMap<IpAddr, ProbeStats>>,
+    payloads: Vec<ProbePayload>,
+    banner_bytes: Option<usize>,
+    banners: Mutex<BTreeMap<SocketAddr, String>>,
+    responses: Mutex<BTreeMap<SocketAddr, ProbeResponse>>,
+    wildcard_policy: Option<WildcardPolicy>,
+    wildcards: Mutex<HashSet<IpAddr>>,
+    distances: Mutex<BTreeMap<IpAddr, Distance>>,
+    next_local_port: AtomicU32,
+    ports_exhausted: AtomicBool,
+    threads: usize,
+    cpu_affinity: Option<CpuSet>,
+    tcp_engine: TcpEngine,
+    stateless: bool,
+    shuffle_seed: u64,
+    resume_index: u64,
+    shard: Option<Shard>,
+    result_shards: Option<ResultShards>,
+    events: Option<Arc<EventStream>>,
+    started_hosts: Mutex<HashSet<IpAddr>>,
+    progress: Option<Arc<Progress>>,
+    controls: Option<Arc<Controls>>,
+    cancel: CancelToken,
+    discovered: Mutex<BTreeMap<SocketAddr, DateTime<Utc>>>,
+    latencies: Mutex<BTreeMap<SocketAddr, Duration>>,
+    /// The closed and filtered ports, only kept when asked for.
+    port_states: Option<Mutex<BTreeMap<SocketAddr, PortState>>>,
+    syn_source_port: u16,
+    udp_engine: UdpEngine,
+    fragile_ports: Vec<u16>,
+    /// Held while a fragile port is probed, so they're probed one at a time.
+    fragile: async_std::sync::Mutex<()>,
+    paranoid: Option<Duration>,
+    /// Held while a port of the host is probed in paranoid mode.
+    host_turns: Mutex<HashMap<IpAddr, Arc<async_std::sync::Mutex<()>>>>,
+    max_open_per_host: Option<usize>,
+    open_per_host: Mutex<HashMap<IpAddr, usize>>,
+    #[cfg(feature = "ssh")]
+    ssh_tunnel: Option<Arc<SshTunnel>>,
+}
+
+// Allowing too many arguments for clippy.
+#[allow(clippy::too_many_arguments)]
+impl Scanner {
+    pub fn new(
+        ips: &[IpAddr],
+        batch_size: usize,
+        timeout: Duration,
+        tries: u8,
+        greppable: bool,
+        port_strategy: PortStrategy,
+        accessible: bool,
+        exclude_ports: Vec<u16>,
+        udp: bool,
+    ) -> Self {
+        Self {
+            batch_size,
+            timeout,
+            tries: NonZeroU8::new(std::cmp::max(tries, 1)).unwrap(),
+            greppable,
+            port_strategy,
+            ips: ips.iter().map(ToOwned::to_owned).collect(),
+            accessible,
+            exclude_ports,
+            udp,
+            linger: None,
+            local_port_range: None,
+            priorities: None,
+            window: None,
+            bandwidth: None,
+            health_check: None,
+            bytes_sent: AtomicU64::new(0),
+            stats: Mutex::new(HashMap::new()),
+            payloads: Vec::new(),
+            banner_bytes: None,
+            banners: Mutex::new(BTreeMap::new()),
+            responses: Mutex::new(BTreeMap::new()),
+            wildcard_policy: None,
+            wildcards: Mutex::new(HashSet::new()),
+            distances: Mutex::new(BTreeMap::new()),
+            next_local_port: AtomicU32::new(0),
+            ports_exhausted: AtomicBool::new(false),
+            threads: 1,
+            cpu_affinity: None,
+            tcp_engine: TcpEngine::Connect,
+            stateless: false,
+            shuffle_seed: rand::random(),
+            resume_index: 0,
+            shard: None,
+            result_shards: None,
+            events: None,
+            started_hosts: Mutex::new(HashSet::new()),
+            progress: None,
+            controls: None,
+            cancel: CancelToken::new(),
+            discovered: Mutex::new(BTreeMap::new()),
+            latencies: Mutex::new(BTreeMap::new()),
+            port_states: None,
+            syn_source_port: rand::RngExt::random_range(&mut rand::rng(), 40_000..60_000),
+            udp_engine: UdpEngine::Socket,
+            fragile_ports: Vec::new(),
+            fragile: async_std::sync::Mutex::new(()),
+            paranoid: None,
+            host_turns: Mutex::new(HashMap::new()),
+            max_open_per_host: None,
+            open_per_host: Mutex::new(HashMap::new()),
+            #[cfg(feature = "ssh")]
+            ssh_tunnel: None,
+        }
+    }
+
+    /// Sets the SO_LINGER timeout applied to sockets of open ports before
+    /// they are closed. A zero duration resets the connection.
+    pub fn with_linger(mut self, linger: Option<Duration>) -> Self {
+        self.linger = linger;
+        self
+    }
+
+    /// Makes every TCP connection originate from a local port within `range`
+    /// instead of letting the OS pick an ephemeral port.
+    pub fn with_local_port_range(mut self, range: Option<PortRange>) -> Self {
+        self.local_port_range = range;
+        self
+    }
+
+    /// Scans the targets in tiers of equal weight, heaviest first, instead
+    /// of in the order they were given.
+    pub fn with_priorities(mut self, priorities: Option<TargetPriorities>) -> Self {
+        self.priorities = priorities;
+        self
+    }
+
+    /// Only sends probes while `window` is open, pausing outside it.
+    pub fn with_window(mut self, window: Option<ScanWindow>) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Caps the estimated traffic of the probes to `bandwidth`.
+    pub fn with_max_bandwidth(mut self, bandwidth: Option<Bandwidth>) -> Self {
+        self.bandwidth = bandwidth.map(BandwidthLimiter::new);
+        self
+    }
+
+    /// Counts `bytes` of traffic, returning how long to wait before sending
+    /// them to stay under `--max-bandwidth`.
+    fn account(&self, bytes: u64) -> Duration {
+        self.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+        self.bandwidth
+            .as_ref()
+            .map_or(Duration::ZERO, |limiter| limiter.reserve(bytes))
+    }
+
+    /// Waits until `bytes` more can be sent.
+    async fn throttle(&self, bytes: u64) {
+        let wait = self.account(bytes);
+        if !wait.is_zero() {
+            async_std::task::sleep(wait).await;
+        }
+    }
+
+    /// Checks `endpoint` answers every few seconds, pausing the scan while
+    /// it doesn't.
+    pub fn with_health_check(mut self, endpoint: Option<HealthCheck>) -> Self {
+        self.health_check = endpoint;
+        self
+    }
+
+    /// Waits until the health check endpoint answers, if it doesn't.
+    async fn wait_for_route(&self) {
+        let Some(check) = &self.health_check else {
+            return;
+        };
+        if check.is_up(self.timeout).await {
+            return;
+        }
+        warning!(
+            format!("{check} stopped answering, the route to the targets may be down. Pausing the scan until it's back"),
+            self.greppable,
+            self.accessible
+        );
+        while !check.is_up(self.timeout).await {
+            if !self.cancel.sleep(HEALTH_CHECK_INTERVAL).await {
+                return;
+            }
+        }
+        detail!(
+            format!("{check} answers again, resuming the scan"),
+            self.greppable,
+            self.accessible
+        );
+    }
+
+    /// Sends a payload to every open TCP port and records what it answers,
+    /// see [`ProbePayload`].
+    pub fn with_probe_payloads(mut self, payloads: Vec<ProbePayload>) -> Self {
+        self.payloads = payloads;
+        self
+    }
+
+    /// Reads up to `bytes` of what open TCP ports send once connected, or
+    /// answer to their payload, waiting up to the timeout for it.
+    pub fn with_banners(mut self, bytes: Option<usize>) -> Self {
+        self.banner_bytes = bytes.map(|bytes| bytes.clamp(1, RESPONSE_LIMIT));
+        self
+    }
+
+    /// The banners of the open ports that sent one, escaped like
+    /// [`ProbeResponse::snippet`].
+    pub fn banners(&self) -> BTreeMap<SocketAddr, String> {
+        let banners = self.banners.lock().unwrap_or_else(|e| e.into_inner());
+        banners.clone()
+    }
+
+    /// What the open ports answered to their payload.
+    pub fn probe_responses(&self) -> BTreeMap<SocketAddr, ProbeResponse> {
+        let responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+        responses.clone()
+    }
+
+    /// Sends `payload` over `stream` and waits up to the timeout for the
+    /// first bytes of the answer.
+    async fn exchange_payload(&self, stream: &TcpStream, payload: &[u8]) -> Vec<u8> {
+        let mut answer = [0; RESPONSE_LIMIT];
+        let exchange = async {
+            let mut stream = stream;
+            stream.write_all(payload).await?;
+            stream.read(&mut answer).await
+        };
+        let length = match async_std::future::timeout(self.timeout, exchange).await {
+            Ok(Ok(length)) => length,
+            _ => 0,
+        };
+        answer[..length].to_vec()
+    }
+
+    /// Probes a few random ports of every host before the scan to find the
+    /// ones accepting connections on every port, and applies `policy` to
+    /// them.
+    pub fn with_wildcard_check(mut self, policy: Option<WildcardPolicy>) -> Self {
+        self.wildcard_policy = policy;
+        self
+    }
+
+    /// Deals the sockets out to `threads` threads, each with its share of
+    /// the batch, pinned in turn to the CPUs of `affinity`.
+    pub fn with_threads(mut self, threads: usize, affinity: Option<CpuSet>) -> Self {
+        self.threads = threads.clamp(1, self.batch_size.max(1));
+        self.cpu_affinity = affinity;
+        self
+    }
+
+    /// Probes TCP ports with `engine`, see [`syn`]. Without the
+    /// `raw-sockets` feature, every scan is a connect scan.
+    pub fn with_tcp_engine(mut self, engine: TcpEngine) -> Self {
+        if cfg!(feature = "raw-sockets") {
+            self.tcp_engine = engine;
+        }
+        self
+    }
+
+    /// Keeps no state about SYNs in flight, see [`stateless`].
+    pub fn with_stateless(mut self, stateless: bool) -> Self {
+        self.stateless = stateless;
+        self
+    }
+
+    /// Orders the sockets of stateless scans with `seed`, random if not
+    /// given, skipping the first `resume_index`, see [`blackrock`].
+    pub fn with_shuffle(mut self, seed: Option<u64>, resume_index: u64) -> Self {
+        if let Some(seed) = seed {
+            self.shuffle_seed = seed;
+        }
+        self.resume_index = resume_index;
+        self
+    }
+
+    /// Scans only `shard` of the sockets, see [`split`].
+    pub fn with_shard(mut self, shard: Option<Shard>) -> Self {
+        self.shard = shard;
+        self
+    }
+
+    /// The seed stateless scans are shuffled with, to resume them with.
+    pub fn shuffle_seed(&self) -> u64 {
+        self.shuffle_seed
+    }
+
+    /// Writes open ports to shard files as they're found instead of
+    /// returning them, see [`shards`].
+    pub fn with_result_shards(mut self, shards: Option<ResultShards>) -> Self {
+        self.result_shards = shards;
+        self
+    }
+
+    /// Streams hosts started and open ports to `events` as they happen.
+    pub fn with_events(mut self, events: Option<Arc<EventStream>>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Draws the hosts being scanned on the terminal, see [`progress`].
+    pub fn with_progress(mut self, progress: Option<Progress>) -> Self {
+        self.progress = progress.map(Arc::new);
+        self
+    }
+
+    /// Lets keys pressed during the scan steer it, see [`controls`].
+    pub fn with_controls(mut self, controls: Option<Controls>) -> Self {
+        self.controls = controls.map(Arc::new);
+        self
+    }
+
+    /// Ends the scan when `token` is cancelled, see [`cancel`].
+    pub fn with_cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// A handle that cancels this scan from another task, see [`cancel`].
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// The source ports SYNs are sent from, one per thread, starting at
+    /// the start of --local-port-range if given.
+    pub fn syn_source_ports(&self) -> std::ops::RangeInclusive<u16> {
+        let first = self
+            .local_port_range
+            .as_ref()
+            .map_or(self.syn_source_port, |range| range.start);
+        let others = u16::try_from(self.threads - 1).unwrap_or(u16::MAX);
+        first..=first.saturating_add(others)
+    }
+
+    /// Sends UDP probes with `engine`, see [`udp_batch`].
+    pub fn with_udp_engine(mut self, engine: UdpEngine) -> Self {
+        self.udp_engine = engine;
+        self
+    }
+
+    /// Probes `ports` one at a time and only once, for devices that don't
+    /// cope with more, see `--ot-safe`.
+    pub fn with_fragile_ports(mut self, ports: Vec<u16>) -> Self {
+        self.fragile_ports = ports;
+        self
+    }
+
+    /// Probes one port of a host at a time, waiting for the host to close
+    /// every connection and then for `delay` before its next port, see
+    /// `--paranoid`. Other hosts are scanned meanwhile.
+    pub fn with_paranoid(mut self, delay: Option<Duration>) -> Self {
+        self.paranoid = delay;
+        self
+    }
+
+    /// Stops probing the ports of a host once `max` of them were found open.
+    /// The probes in flight still finish, so a few more may be reported.
+    pub fn with_max_open_per_host(mut self, max: Option<usize>) -> Self {
+        self.max_open_per_host = max;
+        self
+    }
+
+    /// Whether `ip` has had as many open ports as `--max-open-per-host`.
+    fn host_is_full(&self, ip: IpAddr) -> bool {
+        self.max_open_per_host.is_some_and(|max| {
+            let open = self.open_per_host.lock().unwrap_or_else(|e| e.into_inner());
+            open.get(&ip).is_some_and(|&count| count >= max)
+        })
+    }
+
+    /// Connects to ports through an SSH jump host rather than from here,
+    /// see [`super::ssh`]. Only applies to TCP connect scans.
+    #[cfg(feature = "ssh")]
+    pub fn with_ssh_tunnel(mut self, tunnel: Option<Arc<SshTunnel>>) -> Self {
+        self.ssh_tunnel = tunnel;
+        self
+    }
+
+    /// How many times the port of `socket` is probed.
+    fn tries_for(&self, socket: SocketAddr) -> u8 {
+        if self.fragile_ports.contains(&socket.port()) {
+            1
+        } else {
+            self.tries.get()
+        }
+    }
+
+    /// The hosts found to accept connections on every port.
+    pub fn wildcard_hosts(&self) -> Vec<IpAddr> {
+        let wildcards = self.wildcards.lock().unwrap_or_else(|e| e.into_inner());
+        let mut hosts: Vec<IpAddr> = wildcards.iter().copied().collect();
+        hosts.sort();
+        hosts
+    }
+
+    /// How many hops away the hosts that answered with a readable TTL are.
+    pub fn distances(&self) -> BTreeMap<IpAddr, Distance> {
+        let distances = self.distances.lock().unwrap_or_else(|e| e.into_inner());
+        distances.clone()
+    }
+
+    /// When each open port was found. Not kept with `--result-shards`.
+    pub fn discoveries(&self) -> BTreeMap<SocketAddr, DateTime<Utc>> {
+        let discovered = self.discovered.lock().unwrap_or_else(|e| e.into_inner());
+        discovered.clone()
+    }
+
+    /// How long each open port took to accept the connection, or to answer
+    /// over UDP. Only the connect TCP engine and the socket UDP engine
+    /// measure it.
+    pub fn latencies(&self) -> BTreeMap<SocketAddr, Duration> {
+        let latencies = self.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        latencies.clone()
+    }
+
+    /// Keeps the closed and filtered ports, for `--show`. Only the connect
+    /// TCP engine and the socket UDP engine tell them apart.
+    pub fn with_port_states(mut self, keep: bool) -> Self {
+        self.port_states = keep.then(|| Mutex::new(BTreeMap::new()));
+        self
+    }
+
+    /// The closed and filtered ports, if kept.
+    pub fn port_states(&self) -> BTreeMap<SocketAddr, PortState> {
+        self.port_states
+            .as_ref()
+            .map(|states| states.lock().unwrap_or_else(|e| e.into_inner()).clone())
+            .unwrap_or_default()
+    }
+
+    /// Keeps the state of a port whose last probe failed with `error`: a
+    /// refused one is closed, one without an answer filtered.
+    fn record_state(&self, socket: SocketAddr, error: &io::Error) {
+        let Some(states) = &self.port_states else {
+            return;
+        };
+        let state = match error.kind() {
+            io::ErrorKind::ConnectionRefused => PortState::Closed,
+            _ => PortState::Filtered,
+        };
+        states
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(socket, state);
+    }
+
+    fn record_latency(&self, socket: SocketAddr, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        latencies.insert(socket, latency);
+    }
+
+    fn is_wildcard(&self, ip: IpAddr) -> bool {
+        let wildcards = self.wildcards.lock().unwrap_or_else(|e| e.into_inner());
+        wildcards.contains(&ip)
+    }
+
+    /// Finds the hosts where every sampled port accepts a connection.
+    async fn detect_wildcards(&self) -> HashSet<IpAddr> {
+        let samples = wildcard::sample_ports();
+        let mut probes = self
+            .ips
+            .iter()
+            .flat_map(|ip| samples.iter().map(move |port| SocketAddr::new(*ip, *port)));
+        let mut accepted: HashMap<IpAddr, usize> = HashMap::new();
+        let mut ftrs = FuturesUnordered::new();
+        loop {
+            while ftrs.len() < self.batch_limit(self.batch_size) {
+                let Some(socket) = probes.next() else {
+                    break;
+                };
+                ftrs.push(async move { (socket, self.accepts(socket).await) });
+            }
+            match ftrs.next().await {
+                Some((socket, true)) => *accepted.entry(socket.ip()).or_default() += 1,
+                Some((_, false)) => {}
+                None => break,
+            }
+        }
+        accepted
+            .into_iter()
+            .filter(|(_, count)| *count == samples.len())
+            .map(|(ip, _)| ip)
+            .collect()
+    }
+
+    /// Whether `socket` accepts a connection, made through the `--via`
+    /// tunnel if there's one, for [`Self::detect_wildcards`].
+    async fn accepts(&self, socket: SocketAddr) -> bool {
+        #[cfg(feature = "ssh")]
+        if let Some(tunnel) = &self.ssh_tunnel {
+            let tunnel = Arc::clone(tunnel);
+            return async_std::task::spawn_blocking(move || tunnel.probe(socket))
+                .await
+                .is_ok();
+        }
+        self.throttle(bandwidth::tcp_probe_bytes(socket.ip())).await;
+        match self.connect(socket).await {
+            Ok(stream) => {
+                let _ = stream.shutdown(Shutdown::Both);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// What the probes of every host ran into so far.
+    pub fn probe_stats(&self) -> BTreeMap<IpAddr, ProbeStats> {
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        stats.iter().map(|(ip, stats)| (*ip, *stats)).collect()
+    }
+
+    fn record(&self, ip: IpAddr, update: impl FnOnce(&mut ProbeStats)) {
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        update(stats.entry(ip).or_default());
+    }
+
+    /// `batch_size` as lowered with `-` during the scan.
+    fn batch_limit(&self, batch_size: usize) -> usize {
+        let limit = self
+            .controls
+            .as_ref()
+            .map_or(batch_size, |controls| controls.batch_size(batch_size));
+        #[cfg(feature = "ssh")]
+        if let Some(tunnel) = &self.ssh_tunnel {
+            return limit.min(tunnel.channels());
+        }
+        limit
+    }
+
+    fn is_paused(&self) -> bool {
+        self.controls
+            .as_ref()
+            .is_some_and(|controls| controls.is_paused())
+    }
+
+    /// Answers the keys pressed during the scan: prints a summary if asked
+    /// and, once `idle`, waits while paused. False once the scan is to end,
+    /// with `q` or its [`CancelToken`].
+    async fn follow_controls(&self, idle: bool) -> bool {
+        let Some(controls) = &self.controls else {
+            return !self.cancel.is_cancelled();
+        };
+        loop {
+            if controls.take_summary() {
+                self.print_summary(controls);
+            }
+            if self.cancel.is_cancelled() {
+                return false;
+            }
+            if !idle || !controls.is_paused() || controls.is_finishing() {
+                return !controls.is_finishing();
+            }
+            async_std::task::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Prints how far the scan got, for the `s` key.
+    fn print_summary(&self, controls: &Controls) {
+        let stats = self.probe_stats();
+        let probes: u64 = stats.values().map(|stats| stats.sent).sum();
+        let open: u64 = stats.values().map(|stats| stats.open).sum();
+        let elapsed = controls.started().elapsed();
+        #[allow(clippy::cast_precision_loss)]
+        let rate = probes as f64 / elapsed.as_secs_f64().max(0.001);
+        let message = format!(
+            "After {}: {} probes sent ({}), {} open ports on {} host{} probed{}",
+            crate::units::duration(elapsed),
+            crate::units::thousands(probes),
+            crate::units::rate(rate, "pps"),
+            crate::units::thousands(open),
+            crate::units::thousands(stats.len() as u64),
+            if stats.len() == 1 { "" } else { "s" },
+            if controls.is_paused() { ", paused" } else { "" }
+        );
+        controls::announce(self.progress.as_deref(), &message);
+    }
+
+    fn in_window(&self) -> bool {
+        self.window
+            .as_ref()
+            .is_none_or(|window| window.is_open(chrono::Utc::now()))
+    }
+
+    /// Sleeps until the scan window opens again.
+    async fn wait_for_window(&self) {
+        let Some(window) = &self.window else {
+            return;
+        };
+        let now = chrono::Utc::now();
+        let opens = window.next_open(now);
+        warning!(
+            format!(
+                "Outside the scan window {window}, pausing until {}",
+                opens.to_rfc3339()
+            ),
+            self.greppable,
+            self.accessible
+        );
+        if self
+            .cancel
+            .sleep((opens - now).to_std().unwrap_or_default())
+            .await
+        {
+            debug!("Scan window opened, resuming");
+        }
+    }
+
+    /// The targets grouped in the order they're scanned: every port of a
+    /// group is scanned before moving on to the next one.
+    fn target_tiers(&self) -> Vec<Vec<IpAddr>> {
+        match &self.priorities {
+            Some(priorities) => priorities.tiers(&self.ips),
+            None => vec![self.ips.clone()],
+        }
+    }
+
+    /// Runs scan_range with chunk sizes
+    /// If you want to run RustScan normally, this is the entry point used
+    /// Returns all open ports as `Vec<u16>`
+    pub async fn run(&self) -> Vec<SocketAddr> {
+        let ports: Vec<u16> = self
+            .port_strategy
+            .order()
+            .iter()
+            .filter(|&port| !self.exclude_ports.contains(port))
+            .copied()
+            .collect();
+        let tiers = self.target_tiers();
+        let mut skipped = HashSet::new();
+        let policy = self
+            .wildcard_policy
+            .filter(|_| ports.len() >= wildcard::MIN_SCANNED_PORTS);
+        if let Some(policy) = policy {
+            let wildcards = self.detect_wildcards().await;
+            if policy == WildcardPolicy::Skip {
+                skipped.clone_from(&wildcards);
+            }
+            *self.wildcards.lock().unwrap_or_else(|e| e.into_inner()) = wildcards;
+        }
+        // Stateless scans go through each tier in shuffled order, the
+        // resume index counting over the tiers in turn.
+        let shuffle = self.stateless && !self.udp && self.tcp_engine == TcpEngine::Syn;
+        let mut starts = Vec::new();
+        let mut skip = self.resume_index;
+        for tier in &tiers {
+            let size = tier.len() as u64 * ports.len() as u64;
+            starts.push(skip.min(size));
+            skip = skip.saturating_sub(size);
+        }
+        // Machines splitting the scan count positions from the start of the
+        // order, whatever they resumed from.
+        let skipped_positions = if shuffle { self.resume_index } else { 0 };
+        let sockets = |shard: usize| {
+            tiers
+                .iter()
+                .zip(&starts)
+                .flat_map(
+                    |(tier, start)| -> Box<dyn Iterator<Item = SocketAddr> + Send + '_> {
+                        if shuffle {
+                            Box::new(ShuffledSockets::new(
+                                tier,
+                                &ports,
+                                self.shuffle_seed,
+                                *start,
+                            ))
+                        } else {
+                            Box::new(SocketIterator::new(tier, &ports))
+                        }
+                    },
+                )
+                .enumerate()
+                .filter(|(position, _)| {
+                    self.shard
+                        .is_none_or(|slice| slice.contains(*position as u64 + skipped_positions))
+                })
+                .map(|(_, socket)| socket)
+                .filter(|socket| !skipped.contains(&socket.ip()))
+                .enumerate()
+                .filter(move |(index, _)| index % self.threads == shard)
+                .map(|(_, socket)| socket)
+                // Skipped sockets count as done, so the progress completes.
+                .filter(|socket| {
+                    let full = self.host_is_full(socket.ip());
+                    if full {
+                        self.ports_done(&[*socket]);
+                    }
+                    !full
+                })
+                .inspect(move |socket| self.start_host(socket.ip()))
+        };
+        let started = std::time::Instant::now();
+        if let Some(progress) = &self.progress {
+            let known = self.shard.is_none() && self.resume_index == 0;
+            progress.set_ports(known.then_some(ports.len() as u64));
+        }
+        let redraws = self.progress.as_ref().map(Progress::show);
+        let keys = self
+            .controls
+            .as_ref()
+            .map(|controls| controls.listen(self.progress.clone(), self.cancel.clone()));
+
+        debug!("Start scanning sockets. \nBatch size {}\nNumber of ip-s {}\nNumber of ports {}\nTargets all together {} ",
+            self.batch_size,
+            self.ips.len(),
+            &ports.len(),
+            (self.ips.len() * ports.len()));
+
+        let (open_sockets, errors) = if self.threads == 1 {
+            self.scan_sockets(sockets(0), self.batch_size, 0).await
+        } else {
+            let batch_size = self.batch_size / self.threads;
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = (0..self.threads)
+                    .map(|shard| {
+                        let sockets = sockets(shard);
+                        scope.spawn(move || {
+                            if let Some(affinity) = &self.cpu_affinity {
+                                let cpu = affinity.cpu_for(shard);
+                                if let Err(e) = threads::pin_current_thread(cpu) {
+                                    debug!("Could not pin scan thread {shard} to CPU {cpu}: {e}");
+                                }
+                            }
+                            async_std::task::block_on(self.scan_sockets(sockets, batch_size, shard))
+                        })
+                    })
+                    .collect();
+                let mut open_sockets = Vec::new();
+                let mut errors = HashSet::new();
+                for handle in handles {
+                    let (open, shard_errors) = handle
+                        .join()
+                        .unwrap_or_else(|e| std::panic::resume_unwind(e));
+                    open_sockets.extend(open);
+                    errors.extend(shard_errors);
+                }
+                (open_sockets, errors)
+            })
+        };
+        drop(keys);
+        drop(redraws);
+        if self
+            .controls
+            .as_ref()
+            .is_some_and(|controls| controls.is_finishing())
+        {
+            warning!(
+                "The scan was ended with q before every port was probed",
+                self.greppable,
+                self.accessible
+            );
+        } else if self.cancel.is_cancelled() {
+            warning!(
+                "The scan was cancelled before every port was probed",
+                self.greppable,
+                self.accessible
+            );
+        }
+        if let Some(shards) = &self.result_shards {
+            if let Err(e) = shards.finish() {
+                warning!(
+                    format!(
+                        "Could not write the last shard to {}: {e}",
+                        shards.dir().display()
+                    ),
+                    self.greppable,
+                    self.accessible
+                );
+            }
+        }
+        if let Some(max) = self.max_open_per_host {
+            let open = self.open_per_host.lock().unwrap_or_else(|e| e.into_inner());
+            let full = open.values().filter(|&&count| count >= max).count();
+            if full > 0 {
+                detail!(
+                    format!("Stopped scanning {full} hosts after {max} open ports, see --max-open-per-host"),
+                    self.greppable,
+                    self.accessible
+                );
+            }
+        }
+        if self.ports_exhausted.load(Ordering::Relaxed) {
+            warning!(
+                "The OS ran out of local ports during the scan, so RustScan paused and closed sockets with SO_LINGER. Consider lowering the batch size or widening --local-port-range.",
+                self.greppable,
+                self.accessible
+            );
+        }
+        if let Some(limiter) = &self.bandwidth {
+            let bytes = self.bytes_sent.load(Ordering::Relaxed);
+            #[allow(
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss,
+                clippy::cast_precision_loss
+            )]
+            let average =
+                Bandwidth((bytes as f64 * 8.0 / started.elapsed().as_secs_f64().max(1.0)) as u64);
+            detail!(
+                format!(
+                    "Sent about {} KB, {average} on average (capped at {})",
+                    bytes / 1000,
+                    limiter.bandwidth()
+                ),
+                self.greppable,
+                self.accessible
+            );
+        }
+        debug!(
+            "Estimated traffic {} bytes",
+            self.bytes_sent.load(Ordering::Relaxed)
+        );
+        for (ip, stats) in self.probe_stats() {
+            debug!("Probes of {ip}: {stats}");
+        }
+        debug!("Typical socket connection errors {errors:?}");
+        debug!("Open Sockets found: {:?}", &open_sockets);
+        open_sockets
+    }
+
+    /// Scans `sockets` with up to `batch_size` of them in flight, returns
+    /// the open ones and the distinct errors. `shard` numbers the thread.
+    async fn scan_sockets(
+        &self,
+        sockets: impl Iterator<Item = SocketAddr>,
+        batch_size: usize,
+        shard: usize,
+    ) -> (Vec<SocketAddr>, HashSet<String>) {
+        if self.udp && self.udp_engine == UdpEngine::Batched {
+            return self.scan_udp_batches(sockets, batch_size).await;
+        }
+        #[cfg(feature = "raw-sockets")]
+        if !self.udp && self.tcp_engine == TcpEngine::Syn {
+            return self.scan_syn_batches(sockets, batch_size, shard).await;
+        }
+        #[cfg(not(feature = "raw-sockets"))]
+        let _ = shard;
+        let mut socket_iterator = sockets.peekable();
+        let mut open_sockets: Vec<SocketAddr> = Vec::new();
+        let mut ftrs = FuturesUnordered::new();
+        let mut errors: HashSet<String> = HashSet::new();
+        let udp_map = get_parsed_data();
+        let mut last_health_check = std::time::Instant::now();
+
+        loop {
+            if self.health_check.is_some() && last_health_check.elapsed() >= HEALTH_CHECK_INTERVAL {
+                self.wait_for_route().await;
+                last_health_check = std::time::Instant::now();
+            }
+            let sending = self.follow_controls(ftrs.is_empty()).await;
+            if !sending && ftrs.is_empty() {
+                break;
+            }
+            // Outside the window, the probes in flight finish before pausing.
+            let open = sending && self.in_window() && !self.is_paused();
+            if !open && ftrs.is_empty() && socket_iterator.peek().is_some() {
+                self.wait_for_window().await;
+                continue;
+            }
+            while open && ftrs.len() < self.batch_limit(batch_size) {
+                let Some(socket) = socket_iterator.next() else {
+                    break;
+                };
+                let udp_map = udp_map.clone();
+                ftrs.push(async move { (socket, self.scan_socket(socket, udp_map).await) });
+            }
+            let Some((socket, result)) = ftrs.next().await else {
+                break;
+            };
+            self.ports_done(&[socket]);
+
+            match result {
+                Ok(socket) => self.keep_open(&mut open_sockets, socket),
+                Err(e) => {
+                    self.record_state(socket, &e);
+                    let error_string = e.to_string();
+                    if errors.len() < self.ips.len() * 1000 {
+                        errors.insert(error_string);
+                    }
+                }
+            }
+        }
+        (open_sockets, errors)
+    }
+
+    /// Scans `sockets` over UDP a batch at a time, sending a batch of probes
+    /// from one socket per address family and collecting the answers until
+    /// the timeout, then probing the silent ports again for every try.
+    async fn scan_udp_batches(
+        &self,
+        sockets: impl Iterator<Item = SocketAddr>,
+        batch_size: usize,
+    ) -> (Vec<SocketAddr>, HashSet<String>) {
+        let mut sockets = sockets.peekable();
+        let mut open_sockets = Vec::new();
+        let mut errors = HashSet::new();
+        let udp_map = get_parsed_data();
+        let payload_for = |port: u16| {
+            udp_map
+                .iter()
+                .rfind(|(ports, _)| ports.contains(&port))
+                .map(|(_, payload)| payload.as_slice())
+                .unwrap_or_default()
+        };
+        let mut last_health_check = std::time::Instant::now();
+        let mut engines: HashMap<bool, std::net::UdpSocket> = HashMap::new();
+
+        while sockets.peek().is_some() {
+            if self.health_check.is_some() && last_health_check.elapsed() >= HEALTH_CHECK_INTERVAL {
+                self.wait_for_route().await;
+                last_health_check = std::time::Instant::now();
+            }
+            if !self.follow_controls(true).await {
+                break;
+            }
+            if !self.in_window() {
+                self.wait_for_window().await;
+                continue;
+            }
+            let batch: Vec<SocketAddr> = sockets
+                .by_ref()
+                .take(self.batch_limit(batch_size))
+                .collect();
+            let mut pending: HashSet<SocketAddr> = batch.iter().copied().collect();
+            for nr_try in 1..=self.tries.get() {
+                for socket in &pending {
+                    self.throttle(bandwidth::udp_probe_bytes(
+                        socket.ip(),
+                        payload_for(socket.port()).len(),
+                    ))
+                    .await;
+                    self.record(socket.ip(), |stats| {
+                        stats.sent += 1;
+                        stats.retries += u64::from(nr_try > 1);
+                    });
+                }
+                for ipv6 in [false, true] {
+                    let datagrams: Vec<(SocketAddr, &[u8])> = pending
+                        .iter()
+                        .filter(|socket| socket.is_ipv6() == ipv6)
+                        .map(|socket| (*socket, payload_for(socket.port())))
+                        .collect();
+                    if datagrams.is_empty() {
+                        continue;
+                    }
+                    let engine = match engines.entry(ipv6) {
+                        std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                        std::collections::hash_map::Entry::Vacant(entry) => {
+                            let local: SocketAddr = if ipv6 {
+                                (Ipv6Addr::UNSPECIFIED, 0).into()
+                            } else {
+                                (Ipv4Addr::UNSPECIFIED, 0).into()
+                            };
+                            match std::net::UdpSocket::bind(local) {
+                                Ok(engine) => entry.insert(engine),
+                                Err(e) => {
+                                    errors.insert(format!("Could not bind a UDP socket: {e}"));
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+                    if let Err(e) = udp_batch::send_batch(engine, &datagrams) {
+                        errors.insert(format!("Could not send UDP probes: {e}"));
+                    }
+                }
+
+                let deadline = std::time::Instant::now() + self.timeout;
+                while !pending.is_empty() {
+                    let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                    if remaining.is_zero() {
+                        break;
+                    }
+                    let mut answered = false;
+                    // Every socket gets its share of the time left.
+                    let share = remaining / u32::try_from(engines.len().max(1)).unwrap_or(1);
+                    for engine in engines.values() {
+                        let _ = engine.set_read_timeout(Some(share.max(Duration::from_millis(1))));
+                        match udp_batch::recv_batch(engine) {
+                            Ok(sources) => {
+                                for source in sources {
+                                    if pending.remove(&source) {
+                                        answered = true;
+                                        self.record(source.ip(), |stats| stats.open += 1);
+                                        self.fmt_ports(source);
+                                        self.keep_open(&mut open_sockets, source);
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                errors.insert(format!("Could not receive UDP answers: {e}"));
+                            }
+                        }
+                    }
+                    if !answered && engines.len() < 2 {
+                        // A single socket waited out its read timeout.
+                        break;
+                    }
+                }
+                for socket in &pending {
+                    self.record(socket.ip(), |stats| stats.timeouts += 1);
+                }
+                if pending.is_empty() {
+                    break;
+                }
+            }
+            self.ports_done(&batch);
+        }
+        (open_sockets, errors)
+    }
+
+    /// Scans `sockets` with raw SYNs a batch at a time, collecting the
+    /// answers until the timeout, then probing the silent ports again for
+    /// every try.
+    #[cfg(feature = "raw-sockets")]
+    async fn scan_syn_batches(
+        &self,
+        sockets: impl Iterator<Item = SocketAddr>,
+        batch_size: usize,
+        shard: usize,
+    ) -> (Vec<SocketAddr>, HashSet<String>) {
+        let mut sockets = sockets.peekable();
+        let mut open_sockets = Vec::new();
+        let mut errors = HashSet::new();
+        let source_port = self
+            .syn_source_ports()
+            .start()
+            .saturating_add(u16::try_from(shard).unwrap_or(u16::MAX));
+        let mut probe = match SynProbe::new(source_port) {
+            Ok(probe) => probe,
+            Err(e) => {
+                errors.insert(format!("Could not open raw sockets: {e}"));
+                return (open_sockets, errors);
+            }
+        };
+        if self.stateless {
+            return self.scan_syn_stateless(sockets, batch_size, probe).await;
+        }
+        let sequence: u32 = rand::random();
+        let mut last_health_check = std::time::Instant::now();
+
+        // Reads the answers that arrived within `wait`, returns false if the
+        // sockets can't be read.
+        let mut receive = |probe: &SynProbe,
+                           wait: Duration,
+                           pending: &mut HashSet<SocketAddr>,
+                           errors: &mut HashSet<String>| {
+            let segments = match probe.recv(wait) {
+                Ok(segments) => segments,
+                Err(e) => {
+                    errors.insert(format!("Could not read SYN answers: {e}"));
+                    return false;
+                }
+            };
+            for (socket, segment) in segments {
+                let Some(reply) = segment.reply(sequence) else {
+                    continue;
+                };
+                if !pending.remove(&socket) {
+                    continue;
+                }
+                match reply {
+                    Reply::Open => {
+                        self.record(socket.ip(), |stats| stats.open += 1);
+                        self.fmt_ports(socket);
+                        self.keep_open(&mut open_sockets, socket);
+                    }
+                    Reply::Closed => self.record(socket.ip(), |stats| stats.refused += 1),
+                }
+            }
+            true
+        };
+
+        while sockets.peek().is_some() {
+            if self.health_check.is_some() && last_health_check.elapsed() >= HEALTH_CHECK_INTERVAL {
+                self.wait_for_route().await;
+                last_health_check = std::time::Instant::now();
+            }
+            if !self.follow_controls(true).await {
+                break;
+            }
+            if !self.in_window() {
+                self.wait_for_window().await;
+                continue;
+            }
+            let batch: Vec<SocketAddr> = sockets
+                .by_ref()
+                .take(self.batch_limit(batch_size))
+                .collect();
+            let mut pending: HashSet<SocketAddr> = batch.iter().copied().collect();
+            for nr_try in 1..=self.tries.get() {
+                let unanswered: Vec<SocketAddr> = pending.iter().copied().collect();
+                for (sent, socket) in unanswered.iter().enumerate() {
+                    self.throttle(bandwidth::tcp_probe_bytes(socket.ip())).await;
+                    self.record(socket.ip(), |stats| {
+                        stats.sent += 1;
+                        stats.retries += u64::from(nr_try > 1);
+                    });
+                    if let Err(e) = probe.send(*socket, sequence) {
+                        self.record(socket.ip(), |stats| stats.failed(&e));
+                        errors.insert(format!("{e} {}", socket.ip()));
+                    }
+                    if (sent + 1).is_multiple_of(SEND_BURST) {
+                        receive(&probe, Duration::ZERO, &mut pending, &mut errors);
+                    }
+                }
+
+                let deadline = std::time::Instant::now() + self.timeout;
+                while !pending.is_empty() {
+                    let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                    if remaining.is_zero() || !receive(&probe, remaining, &mut pending, &mut errors)
+                    {
+                        break;
+                    }
+                }
+                for socket in &pending {
+                    self.record(socket.ip(), |stats| stats.timeouts += 1);
+                }
+                if pending.is_empty() {
+                    break;
+                }
+            }
+            self.ports_done(&batch);
+        }
+        (open_sockets, errors)
+    }
+
+    /// Sends SYNs to `sockets` with cookies as sequence numbers, `batch_size`
+    /// at a time, reading answers in between, see [`stateless`].
+    #[cfg(feature = "raw-sockets")]
+    async fn scan_syn_stateless(
+        &self,
+        sockets: impl Iterator<Item = SocketAddr>,
+        batch_size: usize,
+        mut probe: SynProbe,
+    ) -> (Vec<SocketAddr>, HashSet<String>) {
+        let mut sockets = sockets.peekable();
+        let mut open_sockets = Vec::new();
+        let mut found = HashSet::new();
+        let mut errors = HashSet::new();
+        let secret: u64 = rand::random();
+        let source_port = probe.source_port();
+        let mut last_health_check = std::time::Instant::now();
+
+        let mut receive = |probe: &SynProbe, wait: Duration, errors: &mut HashSet<String>| {
+            let segments = match probe.recv(wait) {
+                Ok(segments) => segments,
+                Err(e) => {
+                    errors.insert(format!("Could not read SYN answers: {e}"));
+                    return false;
+                }
+            };
+            for (socket, segment) in segments {
+                match segment.reply(stateless::cookie(secret, socket, source_port)) {
+                    // Shards are deduplicated when they're merged.
+                    Some(Reply::Open) if self.result_shards.is_some() || found.insert(socket) => {
+                        self.record(socket.ip(), |stats| stats.open += 1);
+                        self.fmt_ports(socket);
+                        self.keep_open(&mut open_sockets, socket);
+                    }
+                    Some(Reply::Closed) => self.record(socket.ip(), |stats| stats.refused += 1),
+                    _ => {}
+                }
+            }
+            true
+        };
+
+        while sockets.peek().is_some() {
+            if self.health_check.is_some() && last_health_check.elapsed() >= HEALTH_CHECK_INTERVAL {
+                self.wait_for_route().await;
+                last_health_check = std::time::Instant::now();
+            }
+            if !self.follow_controls(true).await {
+                break;
+            }
+            if !self.in_window() {
+                self.wait_for_window().await;
+                continue;
+            }
+            // Only the batch being sent is kept, to send it --tries times.
+            let batch: Vec<SocketAddr> = sockets
+                .by_ref()
+                .take(self.batch_limit(batch_size))
+                .collect();
+            for nr_try in 1..=self.tries.get() {
+                for (sent, socket) in batch.iter().enumerate() {
+                    self.throttle(bandwidth::tcp_probe_bytes(socket.ip())).await;
+                    self.record(socket.ip(), |stats| {
+                        stats.sent += 1;
+                        stats.retries += u64::from(nr_try > 1);
+                    });
+                    let sequence = stateless::cookie(secret, *socket, source_port);
+                    if let Err(e) = probe.send(*socket, sequence) {
+                        self.record(socket.ip(), |stats| stats.failed(&e));
+                        errors.insert(format!("{e} {}", socket.ip()));
+                    }
+                    if (sent + 1).is_multiple_of(SEND_BURST) {
+                        receive(&probe, Duration::ZERO, &mut errors);
+                    }
+                }
+            }
+            self.ports_done(&batch);
+        }
+
+        // Answers to the last SYNs arrive up to a timeout later.
+        let deadline = std::time::Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() || !receive(&probe, remaining, &mut errors) {
+                break;
+            }
+        }
+        (open_sockets, errors)
+    }
+
+    /// Given a socket, scan it self.tries times.
+    /// Turns the address into a SocketAddr
+    /// Deals with the `<result>` type
+    /// If it experiences error ErrorKind::Other then too many files are open and it Panics!
+    /// Else any other error, it returns the error in Result as a string
+    /// If no errors occur, it returns the port number in Result to signify the port is open.
+    /// This function mainly deals with the logic of Results handling.
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// scanner.scan_socket(socket)
+    /// ```
+    ///
+    /// Note: `self` must contain `self.ip`.
+    async fn scan_socket(
+        &self,
+        socket: SocketAddr,
+        udp_map: BTreeMap<Vec<u16>, Vec<u8>>,
+    ) -> io::Result<SocketAddr> {
+        let Some(delay) = self.paranoid else {
+            return self.probe_socket(socket, udp_map).await;
+        };
+        let turn = Arc::clone(
+            self.host_turns
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(socket.ip())
+                .or_default(),
+        );
+        let _turn = turn.lock().await;
+        let result = self.probe_socket(socket, udp_map).await;
+        async_std::task::sleep(delay).await;
+        result
+    }
+
+    /// Probes `socket` for [`Self::scan_socket`].
+    async fn probe_socket(
+        &self,
+        socket: SocketAddr,
+        udp_map: BTreeMap<Vec<u16>, Vec<u8>>,
+    ) -> io::Result<SocketAddr> {
+        let _fragile = if self.fragile_ports.contains(&socket.port()) {
+            Some(self.fragile.lock().await)
+        } else {
+            None
+        };
+        if self.udp {
+            return self.scan_udp_socket(socket, udp_map).await;
+        }
+        #[cfg(feature = "ssh")]
+        if let Some(tunnel) = &self.ssh_tunnel {
+            return self.scan_tunneled_socket(tunnel, socket).await;
+        }
+
+        let tries = self.tries_for(socket);
+        for nr_try in 1..=tries {
+            self.throttle(bandwidth::tcp_probe_bytes(socket.ip())).await;
+            self.record(socket.ip(), |stats| {
+                stats.sent += 1;
+                stats.retries += u64::from(nr_try > 1);
+            });
+            let connecting = std::time::Instant::now();
+            match self.connect_with_backoff(socket).await {
+                Ok(tcp_stream) => {
+                    self.record_latency(socket, connecting.elapsed());
+                    // Closing the connection is paid for by the next probes.
+                    self.account(bandwidth::tcp_open_bytes(socket.ip()));
+                    debug!(
+                        "Connection was successful, shutting down stream {}",
+                        &socket
+                    );
+                    let payload = ProbePayload::pick(&self.payloads, socket.port());
+                    let wildcard = self.is_wildcard(socket.ip());
+                    if payload.is_some() || wildcard || self.banner_bytes.is_some() {
+                        // Without a payload, the port may still send a banner.
+                        let sent = payload.unwrap_or_default();
+                        self.throttle(sent.len() as u64).await;
+                        let answer = self.exchange_payload(&tcp_stream, sent).await;
+                        let answered = !answer.is_empty();
+                        if payload.is_some() || (wildcard && answered) {
+                            let mut responses =
+                                self.responses.lock().unwrap_or_else(|e| e.into_inner());
+                            responses.insert(socket, ProbeResponse::new(&answer));
+                        }
+                        if let (Some(bytes), true) = (self.banner_bytes, answered) {
+                            let mut banners =
+                                self.banners.lock().unwrap_or_else(|e| e.into_inner());
+                            banners.insert(socket, escape(&answer[..answer.len().min(bytes)]));
+                        }
+                        if wildcard && !answered {
+                            let _ = tcp_stream.shutdown(Shutdown::Both);
+                            return Err(io::Error::other(format!(
+                                "{socket} accepted the connection like every port of its host, but didn't answer"
+                            )));
+                        }
+                    }
+                    self.record(socket.ip(), |stats| stats.open += 1);
+                    self.apply_linger(&tcp_stream);
+                    if self.paranoid.is_some() {
+                        self.close_fully(&tcp_stream, socket).await;
+                    } else if let Err(e) = tcp_stream.shutdown(Shutdown::Both) {
+                        debug!("Shutdown stream error {}", &e);
+                    }
+                    self.fmt_ports(socket);
+
+                    debug!("Return Ok after {nr_try} tries");
+                    return Ok(socket);
+                }
+                Err(e) => {
+                    self.record(socket.ip(), |stats| stats.failed(&e));
+                    let mut error_string = e.to_string();
+
+                    assert!(!error_string.to_lowercase().contains("too many open files"), "Too many open files. Please reduce batch size. The default is 5000. Try -b 2500.");
+
+                    if nr_try == tries {
+                        error_string.push(' ');
+                        error_string.push_str(&socket.ip().to_string());
+                        return Err(io::Error::new(e.kind(), error_string));
+                    }
+                }
+            };
+        }
+        unreachable!();
+    }
+
+    /// Has the SSH jump host connect to the socket, retrying when it timed
+    /// out.
+    #[cfg(feature = "ssh")]
+    async fn scan_tunneled_socket(
+        &self,
+        tunnel: &Arc<SshTunnel>,
+        socket: SocketAddr,
+    ) -> io::Result<SocketAddr> {
+        let tries = self.tries_for(socket);
+        for nr_try in 1..=tries {
+            self.record(socket.ip(), |stats| {
+                stats.sent += 1;
+                stats.retries += u64::from(nr_try > 1);
+            });
+            let tunnel = Arc::clone(tunnel);
+            match async_std::task::spawn_blocking(move || tunnel.probe(socket)).await {
+                Ok(()) => {
+                    self.record(socket.ip(), |stats| stats.open += 1);
+                    self.fmt_ports(socket);
+                    return Ok(socket);
+                }
+                Err(e) => {
+                    self.record(socket.ip(), |stats| stats.failed(&e));
+                    if e.kind() != io::ErrorKind::TimedOut || nr_try == tries {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        unreachable!();
+    }
+
+    async fn scan_udp_socket(
+        &self,
+        socket: SocketAddr,
+        udp_map: BTreeMap<Vec<u16>, Vec<u8>>,
+    ) -> io::Result<SocketAddr> {
+        let mut payload: Vec<u8> = Vec::new();
+        for (key, value) in udp_map {
+            if key.contains(&socket.port()) {
+                payload = value;
+            }
+        }
+
+        let tries = self.tries_for(socket);
+        for nr_try in 1..=tries {
+            self.throttle(bandwidth::udp_probe_bytes(socket.ip(), payload.len()))
+                .await;
+            self.record(socket.ip(), |stats| {
+                stats.sent += 1;
+                stats.retries += u64::from(nr_try > 1);
+            });
+            match self.udp_scan(socket, &payload, self.timeout).await {
+                Ok(true) => {
+                    self.record(socket.ip(), |stats| stats.open += 1);
+                    return Ok(socket);
+                }
+                Ok(false) => {
+                    self.record(socket.ip(), |stats| stats.timeouts += 1);
+                    continue;
+                }
+                Err(e) => {
+                    self.record(socket.ip(), |stats| stats.failed(&e));
+                    return Err(e);
+                }
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("UDP scan timed-out for all tries on socket {socket}"),
+        ))
+    }
+
+    /// Performs the connection to the socket with timeout
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// # use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+    /// let port: u16 = 80;
+    /// // ip is an IpAddr type
+    /// let ip = IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1));
+    /// let socket = SocketAddr::new(ip, port);
+    /// scanner.connect(socket);
+    /// // returns Result which is either Ok(stream) for port is open, or Er for port is closed.
+    /// // Timeout occurs after self.timeout seconds
+    /// ```
+    ///
+    async fn connect(&self, socket: SocketAddr) -> io::Result<TcpStream> {
+        let stream = io::timeout(self.timeout, async move {
+            match &self.local_port_range {
+                Some(range) => self.connect_from_range(socket, range).await,
+                None => TcpStream::connect(socket).await,
+            }
+        })
+        .await?;
+        Ok(stream)
+    }
+
+    /// Connects to the socket, pausing and retrying whenever the OS reports
+    /// that it ran out of local ports. Running out of ephemeral ports says
+    /// nothing about the target, so these errors must not use up a try.
+    async fn connect_with_backoff(&self, socket: SocketAddr) -> io::Result<TcpStream> {
+        let mut pauses = 0;
+        loop {
+            match self.connect(socket).await {
+                Err(e) if is_port_exhaustion(&e) && pauses < MAX_EXHAUSTION_PAUSES => {
+                    pauses += 1;
+                    self.ports_exhausted.store(true, Ordering::Relaxed);
+                    debug!("Local ports exhausted connecting to {socket}, pausing ({e})");
+                    async_std::task::sleep(EXHAUSTION_PAUSE * pauses).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Connects to the socket from the next local port of `range`.
+    async fn connect_from_range(
+        &self,
+        socket: SocketAddr,
+        range: &PortRange,
+    ) -> io::Result<TcpStream> {
+        let span = u32::from(range.end.saturating_sub(range.start)) + 1;
+        let offset = self.next_local_port.fetch_add(1, Ordering::Relaxed) % span;
+        // offset < span <= u16::MAX + 1, so the sum always fits within range.end
+        let local_port = range.start + u16::try_from(offset).unwrap_or_default();
+        let local_ip = match socket {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+
+        let sock = Socket::new(
+            Domain::for_address(socket),
+            Type::STREAM,
+            Some(Protocol::TCP),
+        )?;
+        sock.set_reuse_address(true)?;
+        sock.bind(&SocketAddr::new(local_ip, local_port).into())?;
+        sock.set_nonblocking(true)?;
+        if let Err(e) = sock.connect(&socket.into()) {
+            if !is_connect_in_progress(&e) {
+                return Err(e);
+            }
+        }
+
+        let stream = async_io::Async::new(std::net::TcpStream::from(sock))?;
+        stream.writable().await?;
+        if let Some(e) = stream.get_ref().take_error()? {
+            return Err(e);
+        }
+        Ok(TcpStream::from(stream.into_inner()?))
+    }
+
+    /// Closes our side of `stream` and waits up to the timeout for the host
+    /// to close its own, so the connection is gone before the next probe.
+    async fn close_fully(&self, stream: &TcpStream, socket: SocketAddr) {
+        if let Err(e) = stream.shutdown(Shutdown::Write) {
+            debug!("Shutdown stream error {e}");
+            return;
+        }
+        let drain = async {
+            let mut stream = stream;
+            let mut buf = [0; 512];
+            while stream.read(&mut buf).await? > 0 {}
+            io::Result::Ok(())
+        };
+        match async_std::future::timeout(self.timeout, drain).await {
+            Ok(Ok(())) => debug!("{socket} closed the connection"),
+            Ok(Err(e)) => debug!("{socket} reset the connection while closing: {e}"),
+            Err(_) => debug!("{socket} didn't close the connection within the timeout"),
+        }
+    }
+
+    /// Sets SO_LINGER on a connected stream before it gets closed. Once the
+    /// OS reported local port exhaustion, streams are reset so their port is
+    /// released immediately instead of sitting in TIME_WAIT.
+    fn apply_linger(&self, stream: &TcpStream) {
+        let linger = self.linger.or_else(|| {
+            self.ports_exhausted
+                .load(Ordering::Relaxed)
+                .then_some(Duration::ZERO)
+        });
+        if let Some(linger) = linger {
+            if let Err(e) = SockRef::from(stream).set_linger(Some(linger)) {
+                debug!("Failed to set SO_LINGER {e}");
+            }
+        }
+    }
+
+    /// Binds to a UDP socket so we can send and receive packets
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// # use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+    /// let port: u16 = 80;
+    /// // ip is an IpAddr type
+    /// let ip = IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1));
+    /// let socket = SocketAddr::new(ip, port);
+    /// scanner.udp_bind(socket);
+    /// // returns Result which is either Ok(stream) for port is open, or Err for port is closed.
+    /// // Timeout occurs after self.timeout seconds
+    /// ```
+    async fn udp_bind(&self, socket: SocketAddr) -> io::Result<UdpSocket> {
+        let local_addr = match socket {
+            SocketAddr::V4(_) => "0.0.0.0:0".parse::<SocketAddr>().unwrap(),
+            SocketAddr::V6(_) => "[::]:0".parse::<SocketAddr>().unwrap(),
+        };
+
+        UdpSocket::bind(local_addr).await
+    }
+
+    /// Receives the answer waiting on `udp_socket`, noting the distance to
+    /// the host where its TTL can be read.
+    async fn recv_udp(
+        &self,
+        udp_socket: &UdpSocket,
+        socket: SocketAddr,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        #[cfg(target_os = "linux")]
+        if let Ok((size, ttl)) = ttl::recv_with_ttl(udp_socket, buf) {
+            if let Some(ttl) = ttl {
+                let mut distances = self.distances.lock().unwrap_or_else(|e| e.into_inner());
+                distances.insert(socket.ip(), Distance::from_ttl(ttl));
+            }
+            return Ok(size);
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = socket;
+        udp_socket.recv(buf).await
+    }
+
+    /// Performs a UDP scan on the specified socket with a payload and wait duration
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// # use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+    /// # use std::time::Duration;
+    /// let port: u16 = 123;
+    /// // ip is an IpAddr type
+    /// let ip = IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1));
+    /// let socket = SocketAddr::new(ip, port);
+    /// let payload = vec![0, 1, 2, 3];
+    /// let wait = Duration::from_secs(1);
+    /// let result = scanner.udp_scan(socket, payload, wait).await;
+    /// // returns Result which is either Ok(true) if response received, or Ok(false) if timed out.
+    /// // Err is returned for other I/O errors.
+    /// ```
+    ///
+    /// The socket is connected, so an ICMP port unreachable answering the
+    /// probe fails it with `ConnectionRefused`: the port is closed and isn't
+    /// probed again, while a port that stays silent may be open behind a
+    /// firewall dropping the probes, and is retried.
+    async fn udp_scan(
+        &self,
+        socket: SocketAddr,
+        payload: &[u8],
+        wait: Duration,
+    ) -> io::Result<bool> {
+        match self.udp_bind(socket).await {
+            Ok(udp_socket) => {
+                let mut buf = [0u8; 1024];
+
+                udp_socket.connect(socket).await?;
+                #[cfg(target_os = "linux")]
+                let _ = ttl::report_ttl(&udp_socket, socket.is_ipv6());
+                udp_socket.send(payload).await?;
+                let sent = std::time::Instant::now();
+
+                match io::timeout(wait, udp_socket.peek(&mut buf)).await {
+                    Ok(_) => {
+                        self.record_latency(socket, sent.elapsed());
+                        let size = self.recv_udp(&udp_socket, socket, &mut buf).await?;
+                        debug!("Received {size} bytes");
+                        self.fmt_ports(socket);
+                        Ok(true)
+                    }
+                    Err(e) => {
+                        if e.kind() == io::ErrorKind::TimedOut {
+                            Ok(false)
+                        } else {
+                            Err(e)
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                println!("Err E binding sock {e:?}");
+                Err(e)
+            }
+        }
+    }
+
+    /// Streams a `host-start` event the first time `ip` is probed.
+    fn start_host(&self, ip: IpAddr) {
+        if let Some(progress) = &self.progress {
+            progress.start(ip);
+        }
+        let Some(events) = &self.events else {
+            return;
+        };
+        let mut started = self.started_hosts.lock().unwrap_or_else(|e| e.into_inner());
+        if started.insert(ip) {
+            events.emit(&Event::HostStart { ip });
+        }
+    }
+
+    /// Counts the ports of `sockets` as done for the progress lines.
+    fn ports_done(&self, sockets: &[SocketAddr]) {
+        if let Some(progress) = &self.progress {
+            progress.done(sockets);
+        }
+    }
+
+    /// Keeps an open port for the results, in a shard if sharding.
+    fn keep_open(&self, open_sockets: &mut Vec<SocketAddr>, socket: SocketAddr) {
+        if self.max_open_per_host.is_some() {
+            let mut open = self.open_per_host.lock().unwrap_or_else(|e| e.into_inner());
+            *open.entry(socket.ip()).or_default() += 1;
+        }
+        if let Some(progress) = &self.progress {
+            progress.open(socket);
+        }
+        if let Some(events) = &self.events {
+            events.emit(&Event::PortOpen {
+                ip: socket.ip(),
+                port: socket.port(),
+            });
+        }
+        match &self.result_shards {
+            Some(shards) => {
+                if let Err(e) = shards.push(socket) {
+                    debug!("Could not write {socket} to a shard, keeping it: {e}");
+                    open_sockets.push(socket);
+                }
+            }
+            None => {
+                let mut discovered = self.discovered.lock().unwrap_or_else(|e| e.into_inner());
+                discovered.entry(socket).or_insert_with(Utc::now);
+                open_sockets.push(socket);
+            }
+        }
+    }
+
+    /// Formats and prints the port status
+    fn fmt_ports(&self, socket: SocketAddr) {
+        let print = || {
+            if !self.greppable {
+                if self.accessible {
+                    println!("Open {socket}");
+                } else {
+                    println!("Open {}", socket.to_string().purple());
+                }
+            }
+        };
+        match &self.progress {
+            Some(progress) => progress.above(print),
+            None => print(),
+        }
+    }
+}
+
+/// Whether the error means the OS has no local port left to connect from.
+fn is_port_exhaustion(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::AddrNotAvailable | io::ErrorKind::AddrInUse
+    )
+}
+
+/// Whether a non-blocking connect is still in progress rather than failed.
+fn is_connect_in_progress(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    if error.raw_os_error() == Some(libc::EINPROGRESS) {
+        return true;
+    }
+    error.kind() == io::ErrorKind::WouldBlock
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{PortRange, ScanOrder};
+    use async_std::task::block_on;
+    use std::{net::IpAddr, time::Duration};
+
+    #[test]
+    fn scanner_runs() {
+        // Makes sure the program still runs and doesn't panic
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: 1,
+            end: 1_000,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            false,
+        );
+        block_on(scanner.run());
+        // if the scan fails, it wouldn't be able to assert_eq! as it panicked!
+        assert_eq!(1, 1);
+    }
+    #[test]
+    fn ipv6_scanner_runs() {
+        // Makes sure the program still runs and doesn't panic
+        let addrs = vec!["::1".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: 1,
+            end: 1_000,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            false,
+        );
+        block_on(scanner.run());
+        // if the scan fails, it wouldn't be able to assert_eq! as it panicked!
+        assert_eq!(1, 1);
+    }
+    #[test]
+    fn quad_zero_scanner_runs() {
+        let addrs = vec!["0.0.0.0".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: 1,
+            end: 1_000,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            false,
+        );
+        block_on(scanner.run());
+        assert_eq!(1, 1);
+    }
+    #[test]
+    fn google_dns_runs() {
+        let addrs = vec!["8.8.8.8".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: 400,
+            end: 445,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            false,
+        );
+        block_on(scanner.run());
+        assert_eq!(1, 1);
+    }
+    #[test]
+    fn infer_ulimit_lowering_no_panic() {
+        // Test behaviour on MacOS where ulimit is not automatically lowered
+        let addrs = vec!["8.8.8.8".parse::<IpAddr>().unwrap()];
+
+        // mac should have this automatically scaled down
+        let range = PortRange {
+            start: 400,
+            end: 600,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            false,
+        );
+        block_on(scanner.run());
+        assert_eq!(1, 1);
+    }
+
+    #[test]
+    fn udp_scan_runs() {
+        // Makes sure the program still runs and doesn't panic
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: 1,
+            end: 1_000,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            true,
+        );
+        block_on(scanner.run());
+        // if the scan fails, it wouldn't be able to assert_eq! as it panicked!
+        assert_eq!(1, 1);
+    }
+    #[test]
+    fn udp_scans_tell_answers_from_port_unreachable() {
+        let open = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let open_port = open.local_addr().unwrap().port();
+        let answering = std::thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (_, from) = open.recv_from(&mut buf).unwrap();
+            open.send_to(b"pong", from).unwrap();
+        });
+        let closed = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![open_port, closed]), ScanOrder::Serial);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            3,
+            true,
+            strategy,
+            true,
+            vec![],
+            true,
+        );
+        let open_sockets = block_on(scanner.run());
+        answering.join().unwrap();
+        assert_eq!(open_sockets, [SocketAddr::new(addrs[0], open_port)]);
+        let stats = scanner.probe_stats()[&addrs[0]];
+        // The closed port got its ICMP port unreachable and wasn't retried.
+        assert_eq!((stats.sent, stats.open, stats.refused), (2, 1, 1));
+    }
+
+    #[test]
+    fn udp_ipv6_runs() {
+        // Makes sure the program still runs and doesn't panic
+        let addrs = vec!["::1".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: 1,
+            end: 1_000,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            true,
+        );
+        block_on(scanner.run());
+        // if the scan fails, it wouldn't be able to assert_eq! as it panicked!
+        assert_eq!(1, 1);
+    }
+    #[test]
+    fn udp_quad_zero_scanner_runs() {
+        let addrs = vec!["0.0.0.0".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: 1,
+            end: 1_000,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            true,
+        );
+        block_on(scanner.run());
+        assert_eq!(1, 1);
+    }
+    #[test]
+    fn udp_google_dns_runs() {
+        let addrs = vec!["8.8.8.8".parse::<IpAddr>().unwrap()];
+        let range = PortRange {
+            start: 100,
+            end: 150,
+        };
+        let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![9000],
+            true,
+        );
+        block_on(scanner.run());
+        assert_eq!(1, 1);
+    }
+
+    #[test]
+    fn targets_are_scanned_by_priority() {
+        let addrs: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap(), "127.0.0.2".parse().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![80]), ScanOrder::Serial);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(100),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        );
+        assert_eq!(scanner.target_tiers(), vec![addrs.clone()]);
+
+        let priorities = priority::TargetPriorities::read(
+            std::path::Path::new("fixtures/priorities.txt"),
+            &None,
+        )
+        .unwrap();
+        let scanner = scanner.with_priorities(Some(priorities));
+        assert_eq!(scanner.target_tiers(), vec![vec![addrs[1]], vec![addrs[0]]]);
+    }
+
+    #[test]
+    fn port_exhaustion_errors_are_detected() {
+        assert!(is_port_exhaustion(&io::Error::from(
+            io::ErrorKind::AddrNotAvailable
+        )));
+        assert!(is_port_exhaustion(&io::Error::from(
+            io::ErrorKind::AddrInUse
+        )));
+        assert!(!is_port_exhaustion(&io::Error::from(
+            io::ErrorKind::ConnectionRefused
+        )));
+    }
+
+    #[test]
+    fn local_port_range_scan_finds_open_port() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_linger(Some(Duration::ZERO))
+        .with_local_port_range(Some(PortRange {
+            start: 40_000,
+            end: 40_100,
+        }));
+        let open = block_on(scanner.run());
+        assert_eq!(open, vec![SocketAddr::new(addrs[0], port)]);
+    }
+
+    #[test]
+    fn probe_stats_count_open_and_refused_ports() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap().port();
+        // Bound and dropped, so nothing listens there anymore.
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![open, closed]), ScanOrder::Serial);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            2,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        );
+        block_on(scanner.run());
+        let stats = scanner.probe_stats()[&addrs[0]];
+        assert_eq!((stats.sent, stats.retries), (3, 1));
+        assert_eq!((stats.open, stats.refused), (1, 2));
+    }
+
+    #[test]
+    fn fragile_ports_are_probed_once() {
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![closed]), ScanOrder::Serial);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            3,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_fragile_ports(vec![closed]);
+        block_on(scanner.run());
+        let stats = scanner.probe_stats()[&addrs[0]];
+        assert_eq!((stats.sent, stats.retries), (1, 0));
+    }
+
+    #[test]
+    fn hosts_stop_being_scanned_after_max_open_ports() {
+        let listeners: Vec<_> = (0..3)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let ports = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().port())
+            .collect();
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(ports), ScanOrder::Serial);
+        let scanner = Scanner::new(
+            &addrs,
+            1,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_max_open_per_host(Some(2));
+        assert_eq!(block_on(scanner.run()).len(), 2);
+        assert_eq!(scanner.probe_stats()[&addrs[0]].sent, 2);
+    }
+
+    #[test]
+    fn paranoid_scans_wait_between_the_ports_of_a_host() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let accepting = std::thread::spawn(move || drop(listener.accept()));
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![open, closed]), ScanOrder::Serial);
+        let scanner = Scanner::new(
+            &addrs,
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_paranoid(Some(Duration::from_millis(200)));
+        let started = std::time::Instant::now();
+        let open_sockets = block_on(scanner.run());
+        accepting.join().unwrap();
+        assert_eq!(open_sockets, [SocketAddr::new(addrs[0], open)]);
+        assert!(started.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[test]
+    fn cancelled_scans_return_early() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let scanner = |window: Option<ScanWindow>| {
+            let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial);
+            Scanner::new(
+                &addrs,
+                10,
+                Duration::from_millis(500),
+                1,
+                true,
+                strategy,
+                true,
+                vec![],
+                false,
+            )
+            .with_window(window)
+        };
+
+        let cancelled = scanner(None);
+        cancelled.cancel_token().cancel();
+        assert!(block_on(cancelled.run()).is_empty());
+
+        // Waiting for a window hours away ends when cancelled too.
+        use chrono::Timelike;
+        let hour = (chrono::Utc::now().hour() + 12) % 24;
+        let window = format!("{hour:02}:00-{hour:02}:30").parse().unwrap();
+        let waiting = scanner(Some(window));
+        let cancel = waiting.cancel_token();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            cancel.cancel();
+        });
+        let started = std::time::Instant::now();
+        assert!(block_on(waiting.run()).is_empty());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn probe_payloads_record_answers() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4];
+            stream.read_exact(&mut request).unwrap();
+            stream.write_all(b"PONG\r\n").unwrap();
+        });
+        let strategy = PortStrategy::pick(&None, Some(vec![socket.port()]), ScanOrder::Serial);
+        let scanner = Scanner::new(
+            &[socket.ip()],
+            10,
+            Duration::from_millis(1000),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_probe_payloads(vec!["text:PING".parse().unwrap()]);
+        block_on(scanner.run());
+        server.join().unwrap();
+        let response = &scanner.probe_responses()[&socket];
+        assert_eq!(response.length, 6);
+        assert_eq!(response.snippet, r"PONG\x0d\x0a");
+    }
+
+    #[test]
+    fn banners_are_read_from_open_ports() {
+        use std::io::Write;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").unwrap();
+        });
+        let strategy = PortStrategy::pick(&None, Some(vec![socket.port()]), ScanOrder::Serial);
+        let scanner = Scanner::new(
+            &[socket.ip()],
+            10,
+            Duration::from_millis(1000),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_banners(Some(11));
+        assert_eq!(block_on(scanner.run()), vec![socket]);
+        server.join().unwrap();
+        assert_eq!(scanner.banners()[&socket], "SSH-2.0-Ope");
+        // Banners alone aren't payload answers.
+        assert!(scanner.probe_responses().is_empty());
+    }
+
+    #[test]
+    fn threaded_scan_finds_open_ports() {
+        let listeners: Vec<std::net::TcpListener> = (0..3)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let mut open: Vec<SocketAddr> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap())
+            .collect();
+        let ports = open.iter().map(SocketAddr::port).collect();
+        let strategy = PortStrategy::pick(&None, Some(ports), ScanOrder::Serial);
+        let scanner = Scanner::new(
+            &[open[0].ip()],
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_threads(2, Some("0".parse().unwrap()));
+        let mut found = block_on(scanner.run());
+        found.sort();
+        open.sort();
+        assert_eq!(found, open);
+    }
+
+    #[test]
+    fn batched_udp_scan_finds_answering_ports() {
+        let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let open = server.local_addr().unwrap();
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let responder = std::thread::spawn(move || {
+            let mut buffer = [0; 64];
+            let (_, source) = server.recv_from(&mut buffer).unwrap();
+            server.send_to(b"answer", source).unwrap();
+        });
+        let ports = vec![open.port(), silent.local_addr().unwrap().port()];
+        let strategy = PortStrategy::pick(&None, Some(ports), ScanOrder::Serial);
+        let scanner = Scanner::new(
+            &[open.ip()],
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            true,
+        )
+        .with_udp_engine(UdpEngine::Batched);
+        assert_eq!(block_on(scanner.run()), vec![open]);
+        responder.join().unwrap();
+        let stats = scanner.probe_stats()[&open.ip()];
+        assert_eq!((stats.sent, stats.open, stats.timeouts), (2, 1, 1));
+    }
+
+    #[test]
+    #[cfg(feature = "raw-sockets")]
+    fn stateless_syn_scan_finds_listeners() {
+        // Raw sockets need root, skip without it.
+        if syn::SynProbe::new(0).is_err() {
+            return;
+        }
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap();
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let strategy = PortStrategy::pick(
+            &None,
+            Some(vec![open.port(), closed.port()]),
+            ScanOrder::Serial,
+        );
+        let scanner = Scanner::new(
+            &[open.ip()],
+            1,
+            Duration::from_millis(500),
+            2,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_tcp_engine(TcpEngine::Syn)
+        .with_stateless(true);
+        assert_eq!(block_on(scanner.run()), vec![open]);
+        let stats = scanner.probe_stats()[&open.ip()];
+        assert_eq!((stats.sent, stats.retries, stats.open), (4, 2, 1));
+    }
+
+    #[test]
+    fn sharded_scan_streams_open_ports_to_disk() {
+        let listeners: Vec<std::net::TcpListener> = (0..2)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let mut ports: Vec<u16> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().port())
+            .collect();
+        let dir = std::env::temp_dir().join(format!("rustscan-sharded-{}", std::process::id()));
+        let strategy = PortStrategy::pick(&None, Some(ports.clone()), ScanOrder::Serial);
+        let scanner = Scanner::new(
+            &["127.0.0.1".parse().unwrap()],
+            10,
+            Duration::from_millis(500),
+            1,
+            true,
+            strategy,
+            true,
+            vec![],
+            false,
+        )
+        .with_result_shards(Some(ResultShards::create(&dir).unwrap()));
+        assert!(block_on(scanner.run()).is_empty());
+
+        let mut hosts = Vec::new();
+        shards::aggregate(&dir, |ip, ports| hosts.push((ip, ports))).unwrap();
+        ports.sort_unstable();
+        assert_eq!(hosts, vec![("127.0.0.1".parse().unwrap(), ports)]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn shards_split_the_scan_between_them() {
+        let listeners: Vec<std::net::TcpListener> = (0..4)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let mut open: Vec<SocketAddr> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap())
+            .collect();
+        let ports: Vec<u16> = open.iter().map(SocketAddr::port).collect();
+        let mut found = Vec::new();
+        for shard in &["1/2", "2/2"] {
+            let strategy = PortStrategy::pick(&None, Some(ports.clone()), ScanOrder::Serial);
+            let scanner = Scanner::new(
+                &[open[0].ip()],
+                10,
+                Duration::from_millis(500),
+                1,
+                true,
+                strategy,
+                true,
+                vec![],
+                false,
+            )
+            .with_shard(Some(shard.parse().unwrap()));
+            let slice = block_on(scanner.run());
+            assert_eq!(slice.len(), 2);
+            found.extend(slice);
+        }
+        found.sort();
+        open.sort();
+        assert_eq!(found, open);
+    }
+}
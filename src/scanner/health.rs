@@ -0,0 +1,109 @@
+//! Checks the route to the targets is up before and during a scan.
+//!
+//! With `--health-check host:port`, RustScan connects to an endpoint known
+//! to be alive, such as a service behind the VPN the targets are reached
+//! through, before scanning and then every few seconds. If it stops
+//! answering, the route is most likely down, and rather than marking every
+//! port as closed the scanner pauses until the endpoint answers again.
+use anyhow::{anyhow, Result};
+#[cfg(not(target_arch = "wasm32"))]
+use async_std::net::TcpStream;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How often the endpoint is checked during a scan.
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// An endpoint that should always accept connections, see `--health-check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheck {
+    host: String,
+    port: u16,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HealthCheck {
+    /// Whether the endpoint accepts a connection within `timeout`.
+    pub async fn is_up(&self, timeout: Duration) -> bool {
+        let connect = TcpStream::connect((self.host.as_str(), self.port));
+        matches!(
+            async_std::future::timeout(timeout, connect).await,
+            Ok(Ok(_))
+        )
+    }
+}
+
+impl FromStr for HealthCheck {
+    type Err = anyhow::Error;
+
+    fn from_str(endpoint: &str) -> Result<Self> {
+        let (host, port) = endpoint
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("{endpoint} should be host:port"))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = port.parse().map_err(|_| anyhow!("{port} is not a port"))?;
+        if host.is_empty() {
+            return Err(anyhow!("{endpoint} should be host:port"));
+        }
+        Ok(Self {
+            host: host.to_owned(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for HealthCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for HealthCheck {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let endpoint = String::deserialize(deserializer)?;
+        endpoint.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for HealthCheck {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HealthCheck;
+    use async_std::task::block_on;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    #[test]
+    fn parse_endpoints() {
+        let check: HealthCheck = "gateway.internal:443".parse().unwrap();
+        assert_eq!(check.to_string(), "gateway.internal:443");
+        let check: HealthCheck = "[fd00::1]:22".parse().unwrap();
+        assert_eq!(check.host, "fd00::1");
+        assert_eq!(check.to_string(), "[fd00::1]:22");
+        assert!("gateway.internal".parse::<HealthCheck>().is_err());
+        assert!(":22".parse::<HealthCheck>().is_err());
+        assert!("10.0.0.1:ssh".parse::<HealthCheck>().is_err());
+    }
+
+    #[test]
+    fn checks_endpoints() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let check: HealthCheck = format!("127.0.0.1:{port}").parse().unwrap();
+        assert!(block_on(check.is_up(Duration::from_secs(1))));
+        drop(listener);
+        assert!(!block_on(check.is_up(Duration::from_secs(1))));
+    }
+}
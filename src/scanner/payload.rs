@@ -0,0 +1,206 @@
+//! Payloads sent to open TCP ports to confirm something answers behind them.
+//!
+//! A completed handshake doesn't always mean a service: load balancers,
+//! proxies and some firewalls accept every connection and only then fail to
+//! find a backend. With `--probe-payload`, the scanner sends a payload once
+//! connected and records how many bytes came back and the start of the
+//! answer. Ports that accept the connection but stay silent are likely such
+//! wrappers.
+//!
+//! A payload is `hex:<bytes>` or `text:<string>` (with `\r`, `\n`, `\t`,
+//! `\\` and `\xNN` escapes), optionally for a single port, `443=hex:1603`.
+//! Ports without a payload of their own get the one without a port.
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// How much of an answer is read, and kept in its snippet.
+pub const RESPONSE_LIMIT: usize = 1024;
+const SNIPPET_LENGTH: usize = 64;
+
+/// A payload for every port, or for `port` only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbePayload {
+    pub port: Option<u16>,
+    pub bytes: Vec<u8>,
+}
+
+impl ProbePayload {
+    /// The payload to send to `port`: its own, or the one for every port.
+    pub fn pick(payloads: &[Self], port: u16) -> Option<&[u8]> {
+        payloads
+            .iter()
+            .find(|payload| payload.port == Some(port))
+            .or_else(|| payloads.iter().find(|payload| payload.port.is_none()))
+            .map(|payload| payload.bytes.as_slice())
+    }
+}
+
+impl FromStr for ProbePayload {
+    type Err = String;
+
+    fn from_str(payload: &str) -> Result<Self, Self::Err> {
+        let (port, payload) = match payload.split_once('=') {
+            Some((port, rest)) if !port.contains(':') => (
+                Some(port.parse().map_err(|_| format!("{port} is not a port"))?),
+                rest,
+            ),
+            _ => (None, payload),
+        };
+        let bytes = if let Some(hex) = payload.strip_prefix("hex:") {
+            parse_hex(hex)?
+        } else if let Some(text) = payload.strip_prefix("text:") {
+            unescape(text)?
+        } else {
+            return Err(format!("{payload} should start with hex: or text:"));
+        };
+        if bytes.is_empty() {
+            return Err("The payload is empty".to_owned());
+        }
+        Ok(Self { port, bytes })
+    }
+}
+
+impl fmt::Display for ProbePayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(port) = self.port {
+            write!(f, "{port}=")?;
+        }
+        write!(f, "hex:")?;
+        self.bytes
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl<'de> Deserialize<'de> for ProbePayload {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let payload = String::deserialize(deserializer)?;
+        payload.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for ProbePayload {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(format!("{hex} has an odd number of hex digits"));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("{hex} is not hex"))
+        })
+        .collect()
+}
+
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => bytes.push(b'\r'),
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.extend(parse_hex(&hex).map_err(|_| format!("\\x{hex} is not a byte"))?);
+            }
+            other => return Err(format!("Unknown escape \\{}", other.unwrap_or(' '))),
+        }
+    }
+    Ok(bytes)
+}
+
+/// What an open port answered to its payload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeResponse {
+    /// Bytes received, up to [`RESPONSE_LIMIT`].
+    pub length: usize,
+    /// The start of the answer, non-printable bytes escaped as `\xNN`.
+    pub snippet: String,
+}
+
+impl ProbeResponse {
+    pub fn new(answer: &[u8]) -> Self {
+        Self {
+            length: answer.len(),
+            snippet: escape(&answer[..answer.len().min(SNIPPET_LENGTH)]),
+        }
+    }
+}
+
+/// `bytes` as text, non-printable bytes escaped as `\xNN`.
+pub fn escape(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for byte in bytes {
+        if byte.is_ascii_graphic() || *byte == b' ' {
+            text.push(char::from(*byte));
+        } else {
+            let _ = write!(text, "\\x{byte:02x}");
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProbePayload, ProbeResponse};
+
+    #[test]
+    fn parse_payloads() {
+        let payload: ProbePayload = "hex:474554".parse().unwrap();
+        assert_eq!(
+            (payload.port, payload.bytes.as_slice()),
+            (None, &b"GET"[..])
+        );
+        let payload: ProbePayload = r"80=text:HEAD / HTTP/1.0\r\n\r\n".parse().unwrap();
+        assert_eq!(payload.port, Some(80));
+        assert_eq!(payload.bytes, b"HEAD / HTTP/1.0\r\n\r\n");
+        assert_eq!(
+            payload.to_string(),
+            "80=hex:48454144202f20485454502f312e300d0a0d0a"
+        );
+        let payload: ProbePayload = r"text:\x00ping=1".parse().unwrap();
+        assert_eq!(payload.bytes, b"\x00ping=1");
+
+        assert!("474554".parse::<ProbePayload>().is_err());
+        assert!("hex:4745f".parse::<ProbePayload>().is_err());
+        assert!("hex:zz".parse::<ProbePayload>().is_err());
+        assert!("http=hex:47".parse::<ProbePayload>().is_err());
+        assert!("text:".parse::<ProbePayload>().is_err());
+        assert!(r"text:\q".parse::<ProbePayload>().is_err());
+    }
+
+    #[test]
+    fn pick_port_payloads() {
+        let payloads: Vec<ProbePayload> = ["hex:00", "443=hex:1603"]
+            .iter()
+            .map(|payload| payload.parse().unwrap())
+            .collect();
+        assert_eq!(ProbePayload::pick(&payloads, 443), Some(&[0x16, 0x03][..]));
+        assert_eq!(ProbePayload::pick(&payloads, 80), Some(&[0][..]));
+        assert_eq!(ProbePayload::pick(&payloads[1..], 80), None);
+    }
+
+    #[test]
+    fn snippets_escape_binary() {
+        let response = ProbeResponse::new(b"SSH-2.0\r\n\x00");
+        assert_eq!(response.length, 10);
+        assert_eq!(response.snippet, r"SSH-2.0\x0d\x0a\x00");
+    }
+}
@@ -0,0 +1,137 @@
+//! Weights that decide which targets are scanned first.
+//!
+//! A priority file lists a host, IP or CIDR and a weight per line:
+//!
+//! ```text
+//! # crown jewels first
+//! 10.0.0.0/24      100
+//! db.example.com   50
+//! 192.168.0.0/16   -10
+//! ```
+//!
+//! Targets are scanned in tiers of equal weight, highest first, so every
+//! port of the high-value assets is scanned before the scanner moves on to
+//! the rest. Targets the file doesn't mention weigh 0, and when several
+//! entries match an IP, the most specific network wins.
+use crate::address::{get_resolver, parse_single_excluded_address};
+use anyhow::{anyhow, Result};
+use cidr_utils::cidr::IpCidr;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Weights of the targets, read from a priority file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetPriorities {
+    weights: Vec<(IpCidr, i32)>,
+}
+
+impl TargetPriorities {
+    /// Reads the priority file at `path`, resolving hostnames with
+    /// `resolver` (see `--resolver`).
+    pub fn read(path: &Path, resolver: &Option<String>) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read {}: {e}", path.display()))?;
+        Self::parse(&content, resolver)
+    }
+
+    fn parse(content: &str, resolver: &Option<String>) -> Result<Self> {
+        let mut resolver_cache = None;
+        let mut weights = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty());
+            let (Some(target), Some(weight), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(anyhow!(
+                    "Line {} should be a target and a weight: {line}",
+                    number + 1
+                ));
+            };
+            let weight: i32 = weight
+                .parse()
+                .map_err(|_| anyhow!("Line {}: {weight} is not a weight", number + 1))?;
+            let resolver = resolver_cache.get_or_insert_with(|| get_resolver(resolver));
+            let networks = parse_single_excluded_address(target, resolver);
+            if networks.is_empty() {
+                return Err(anyhow!(
+                    "Line {}: {target} could not be resolved",
+                    number + 1
+                ));
+            }
+            weights.extend(networks.into_iter().map(|network| (network, weight)));
+        }
+        Ok(Self { weights })
+    }
+
+    /// The weight of `ip`, from the most specific entry containing it.
+    pub fn weight(&self, ip: &IpAddr) -> i32 {
+        self.weights
+            .iter()
+            .filter(|(network, _)| network.contains(ip))
+            .max_by_key(|(network, _)| network.network_length())
+            .map_or(0, |(_, weight)| *weight)
+    }
+
+    /// Groups `ips` by weight, heaviest first, keeping their order within a
+    /// group.
+    pub fn tiers(&self, ips: &[IpAddr]) -> Vec<Vec<IpAddr>> {
+        let mut tiers: BTreeMap<i32, Vec<IpAddr>> = BTreeMap::new();
+        for ip in ips {
+            tiers.entry(self.weight(ip)).or_default().push(*ip);
+        }
+        tiers.into_values().rev().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TargetPriorities;
+    use std::net::IpAddr;
+
+    fn ips(ips: &[&str]) -> Vec<IpAddr> {
+        ips.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn weights_from_most_specific_entry() {
+        let priorities = TargetPriorities::parse(
+            "# crown jewels\n10.0.0.0/8 10\n10.0.0.5, 100 # db\n\n192.168.0.0/16 -5\n",
+            &None,
+        )
+        .unwrap();
+        assert_eq!(priorities.weight(&"10.0.0.5".parse().unwrap()), 100);
+        assert_eq!(priorities.weight(&"10.1.2.3".parse().unwrap()), 10);
+        assert_eq!(priorities.weight(&"192.168.1.1".parse().unwrap()), -5);
+        assert_eq!(priorities.weight(&"1.1.1.1".parse().unwrap()), 0);
+
+        assert_eq!(
+            priorities.tiers(&ips(&[
+                "192.168.1.1",
+                "1.1.1.1",
+                "10.1.2.3",
+                "10.0.0.5",
+                "10.0.0.6"
+            ])),
+            vec![
+                ips(&["10.0.0.5"]),
+                ips(&["10.1.2.3", "10.0.0.6"]),
+                ips(&["1.1.1.1"]),
+                ips(&["192.168.1.1"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(TargetPriorities::parse("10.0.0.0/8", &None).is_err());
+        assert!(TargetPriorities::parse("10.0.0.0/8 high", &None).is_err());
+        assert!(TargetPriorities::parse("10.0.0.0/8 1 2", &None).is_err());
+    }
+}
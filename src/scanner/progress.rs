@@ -0,0 +1,244 @@
+//! A live status of the hosts being scanned, for the terminal.
+//!
+//! When stderr is a terminal and neither `--greppable` nor `--accessible` is
+//! given, the hosts currently being scanned get a line each at the bottom of
+//! the terminal, redrawn in place a few times a second, with how many of
+//! their ports are done, how many are open and how long they've been
+//! scanned for. Hosts with the fewest ports done come first, so the one
+//! holding the scan back is at the top:
+//!
+//! ```text
+//! 10.0.0.7    212/1,000 ports  0 open  41s
+//! 10.0.0.5    998/1,000 ports  2 open  41s
+//! … and 14 more hosts
+//! ```
+//!
+//! The lines are cleared when the scan ends, and before an open port is
+//! printed so it doesn't end up among them.
+use crate::units;
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the lines are redrawn.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+/// How many hosts get a line, the others are counted in the last one.
+const MAX_HOSTS: usize = 6;
+
+#[derive(Debug, Clone, Copy)]
+struct HostProgress {
+    started: Instant,
+    done: u64,
+    open: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    hosts: BTreeMap<IpAddr, HostProgress>,
+    /// Lines currently on the terminal.
+    drawn: usize,
+}
+
+/// How far the scan of every host got.
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// Ports scanned per host, unknown when resuming or splitting the scan.
+    ports: Mutex<Option<u64>>,
+    state: Mutex<State>,
+}
+
+impl Progress {
+    /// A status to draw on stderr, if it's a terminal and the output is
+    /// meant for people.
+    pub fn interactive(greppable: bool, accessible: bool) -> Option<Self> {
+        (!greppable && !accessible && io::stderr().is_terminal()).then(Self::default)
+    }
+
+    /// Sets how many ports of each host are scanned, `None` if unknown.
+    pub fn set_ports(&self, ports: Option<u64>) {
+        *self.ports.lock().unwrap_or_else(|e| e.into_inner()) = ports;
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Counts `ip` as being scanned from now on.
+    pub fn start(&self, ip: IpAddr) {
+        self.state().hosts.entry(ip).or_insert(HostProgress {
+            started: Instant::now(),
+            done: 0,
+            open: 0,
+        });
+    }
+
+    /// Counts the ports of `sockets` as done.
+    pub fn done(&self, sockets: &[SocketAddr]) {
+        let mut state = self.state();
+        for socket in sockets {
+            if let Some(host) = state.hosts.get_mut(&socket.ip()) {
+                host.done += 1;
+            }
+        }
+    }
+
+    /// Counts `socket` as open.
+    pub fn open(&self, socket: SocketAddr) {
+        if let Some(host) = self.state().hosts.get_mut(&socket.ip()) {
+            host.open += 1;
+        }
+    }
+
+    /// The lines for the hosts still being scanned at `now`.
+    fn lines(&self, now: Instant) -> Vec<String> {
+        let ports = *self.ports.lock().unwrap_or_else(|e| e.into_inner());
+        let state = self.state();
+        let mut hosts: Vec<(&IpAddr, &HostProgress)> = state
+            .hosts
+            .iter()
+            .filter(|(_, host)| ports.is_none_or(|ports| host.done < ports))
+            .collect();
+        hosts.sort_by_key(|(ip, host)| (host.done, **ip));
+        let more = hosts.len().saturating_sub(MAX_HOSTS);
+        hosts.truncate(MAX_HOSTS);
+
+        let width = hosts
+            .iter()
+            .map(|(ip, _)| ip.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let mut lines: Vec<String> = hosts
+            .iter()
+            .map(|(ip, host)| {
+                let done = match ports {
+                    Some(ports) => format!(
+                        "{}/{}",
+                        units::thousands(host.done),
+                        units::thousands(ports)
+                    ),
+                    None => units::thousands(host.done),
+                };
+                format!(
+                    "{:<width$}  {done} ports  {} open  {}",
+                    ip.to_string(),
+                    host.open,
+                    units::duration(now.saturating_duration_since(host.started))
+                )
+            })
+            .collect();
+        if more > 0 {
+            lines.push(format!(
+                "… and {more} more host{}",
+                if more == 1 { "" } else { "s" }
+            ));
+        }
+        lines
+    }
+
+    /// Replaces the lines on the terminal with the current ones.
+    fn redraw(&self) {
+        let lines = self.lines(Instant::now());
+        let mut state = self.state();
+        let mut text = erase(state.drawn);
+        for line in &lines {
+            text.push_str(line);
+            text.push('\n');
+        }
+        state.drawn = lines.len();
+        let _ = io::stderr().lock().write_all(text.as_bytes());
+    }
+
+    /// Clears the lines and calls `print`, before they're drawn again below
+    /// what it printed.
+    pub fn above(&self, print: impl FnOnce()) {
+        let mut state = self.state();
+        if state.drawn > 0 {
+            let _ = io::stderr().lock().write_all(erase(state.drawn).as_bytes());
+            state.drawn = 0;
+        }
+        print();
+    }
+
+    /// Redraws the lines until the returned handle is dropped.
+    pub fn show(self: &Arc<Self>) -> Redraws {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let progress = Arc::clone(self);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    progress.redraw();
+                    thread::park_timeout(REDRAW_INTERVAL);
+                }
+                progress.above(|| {});
+            })
+        };
+        Redraws {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Moves the cursor up `lines` lines and clears everything below it.
+fn erase(lines: usize) -> String {
+    if lines == 0 {
+        String::new()
+    } else {
+        format!("\x1b[{lines}A\x1b[J")
+    }
+}
+
+/// Keeps redrawing a [`Progress`], stops and clears it when dropped.
+#[derive(Debug)]
+pub struct Redraws {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Redraws {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Progress;
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+
+    fn sockets(ip: &str, count: u16) -> Vec<SocketAddr> {
+        (1..=count)
+            .map(|port| SocketAddr::new(ip.parse().unwrap(), port))
+            .collect()
+    }
+
+    #[test]
+    fn slowest_hosts_come_first() {
+        let progress = Progress::default();
+        progress.set_ports(Some(3));
+        for host in 1..=8 {
+            progress.start(format!("10.0.0.{host}").parse().unwrap());
+        }
+        progress.done(&sockets("10.0.0.1", 3));
+        progress.done(&sockets("10.0.0.2", 2));
+        progress.done(&sockets("10.0.0.3", 1));
+        progress.open("10.0.0.3:1".parse().unwrap());
+
+        let lines = progress.lines(Instant::now() + Duration::from_secs(42));
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], "10.0.0.4  0/3 ports  0 open  42s");
+        assert_eq!(lines[5], "10.0.0.3  1/3 ports  1 open  42s");
+        assert_eq!(lines[6], "… and 1 more host");
+        assert!(!lines.iter().any(|line| line.starts_with("10.0.0.1 ")));
+    }
+}
@@ -0,0 +1,206 @@
+//! Streams open ports to shard files on disk instead of keeping them.
+//!
+//! A scan collects every open port it finds before reporting them, so on
+//! internet-scale sweeps the results alone can run the machine out of
+//! memory. With `--result-shards DIR`, open ports are buffered up to
+//! [`SHARD_ENTRIES`] at a time, then sorted and written to the next
+//! `shard-N.txt` file in the directory, one `ip:port` per line. Once the
+//! scan is over, [`aggregate`] merges the sorted shards, reading a line of
+//! each at a time, into one list of ports per host, so memory stays
+//! bounded by the buffer whatever the number of findings.
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Open ports buffered before they're written out as a shard.
+pub const SHARD_ENTRIES: usize = 100_000;
+
+/// The shard files of a scan, written as open ports are found.
+#[derive(Debug)]
+pub struct ResultShards {
+    dir: PathBuf,
+    state: Mutex<ShardState>,
+}
+
+#[derive(Debug, Default)]
+struct ShardState {
+    buffer: Vec<SocketAddr>,
+    written: usize,
+}
+
+impl ResultShards {
+    /// Creates `dir` if needed, removing the shards of an earlier scan.
+    pub fn create(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        for shard in shard_files(dir)? {
+            fs::remove_file(shard)?;
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            state: Mutex::new(ShardState::default()),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Records an open port, writing a shard once the buffer is full.
+    pub fn push(&self, socket: SocketAddr) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.buffer.push(socket);
+        if state.buffer.len() >= SHARD_ENTRIES {
+            self.write_shard(&mut state)?;
+        }
+        Ok(())
+    }
+
+    /// Writes what's left in the buffer, returns how many shards there are.
+    pub fn finish(&self) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if !state.buffer.is_empty() {
+            self.write_shard(&mut state)?;
+        }
+        Ok(state.written)
+    }
+
+    fn write_shard(&self, state: &mut ShardState) -> io::Result<()> {
+        state.buffer.sort_unstable();
+        state.buffer.dedup();
+        let path = self.dir.join(format!("shard-{}.txt", state.written));
+        let mut file = BufWriter::new(File::create(path)?);
+        for socket in state.buffer.drain(..) {
+            writeln!(file, "{socket}")?;
+        }
+        file.flush()?;
+        state.written += 1;
+        Ok(())
+    }
+}
+
+/// The shard files in `dir`.
+fn shard_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut shards = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        if name.starts_with("shard-") && name.ends_with(".txt") {
+            shards.push(path);
+        }
+    }
+    Ok(shards)
+}
+
+/// A sorted shard, read a line at a time.
+struct ShardReader {
+    lines: io::Lines<BufReader<File>>,
+}
+
+impl ShardReader {
+    fn next_socket(&mut self) -> io::Result<Option<SocketAddr>> {
+        match self.lines.next().transpose()? {
+            Some(line) => line
+                .trim()
+                .parse()
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{line}: {e}"))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Merges the shards in `dir`, calling `host` with every host and its
+/// open ports in order. Returns how many hosts had open ports.
+pub fn aggregate(dir: &Path, mut host: impl FnMut(IpAddr, Vec<u16>)) -> io::Result<usize> {
+    let mut readers = Vec::new();
+    for path in shard_files(dir)? {
+        readers.push(ShardReader {
+            lines: BufReader::new(File::open(path)?).lines(),
+        });
+    }
+    let mut heads = BinaryHeap::new();
+    for (index, reader) in readers.iter_mut().enumerate() {
+        if let Some(socket) = reader.next_socket()? {
+            heads.push(Reverse((socket, index)));
+        }
+    }
+
+    let mut hosts = 0;
+    let mut current: Option<(IpAddr, Vec<u16>)> = None;
+    while let Some(Reverse((socket, index))) = heads.pop() {
+        if let Some(next) = readers[index].next_socket()? {
+            heads.push(Reverse((next, index)));
+        }
+        match &mut current {
+            Some((ip, ports)) if *ip == socket.ip() => {
+                // The same port can be in several shards.
+                if ports.last() != Some(&socket.port()) {
+                    ports.push(socket.port());
+                }
+            }
+            _ => {
+                if let Some((ip, ports)) = current.take() {
+                    host(ip, ports);
+                    hosts += 1;
+                }
+                current = Some((socket.ip(), vec![socket.port()]));
+            }
+        }
+    }
+    if let Some((ip, ports)) = current {
+        host(ip, ports);
+        hosts += 1;
+    }
+    Ok(hosts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aggregate, ResultShards};
+    use std::net::{IpAddr, SocketAddr};
+
+    #[test]
+    fn shards_merge_into_sorted_hosts() {
+        let dir = std::env::temp_dir().join(format!("rustscan-shards-{}", std::process::id()));
+        let shards = ResultShards::create(&dir).unwrap();
+        let sockets = [
+            "10.0.0.2:443",
+            "10.0.0.1:80",
+            "[2001:db8::1]:22",
+            "10.0.0.1:22",
+        ];
+        for socket in &sockets {
+            shards.push(socket.parse().unwrap()).unwrap();
+        }
+        shards.finish().unwrap();
+        // A second shard, repeating a port found in the first.
+        for socket in &["10.0.0.1:80", "10.0.0.1:8080"] {
+            shards.push(socket.parse::<SocketAddr>().unwrap()).unwrap();
+        }
+        assert_eq!(shards.finish().unwrap(), 2);
+
+        let mut hosts: Vec<(IpAddr, Vec<u16>)> = Vec::new();
+        let count = aggregate(&dir, |ip, ports| hosts.push((ip, ports))).unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(
+            hosts,
+            vec![
+                ("10.0.0.1".parse().unwrap(), vec![22, 80, 8080]),
+                ("10.0.0.2".parse().unwrap(), vec![443]),
+                ("2001:db8::1".parse().unwrap(), vec![22]),
+            ]
+        );
+
+        // A new scan starts from no shards.
+        ResultShards::create(&dir).unwrap();
+        assert_eq!(aggregate(&dir, |_, _| unreachable!()).unwrap(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+//! Splits a scan across machines without a coordinator.
+//!
+//! `--shard 3/10` scans the third of ten slices of the sockets: those
+//! whose index in the scan's order leaves a remainder of 2 divided by 10.
+//! Ten machines given the same targets, ports and options, each with its
+//! own slice, cover every socket exactly once between them. Stateless
+//! scans are shuffled, so they also need the same `--shuffle-seed`.
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// One of `count` slices of a scan, numbered from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl Shard {
+    /// Whether the socket at `position` in the scan's order is in this
+    /// slice.
+    pub fn contains(self, position: u64) -> bool {
+        position % self.count == self.index - 1
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    /// Parses `index/count`, as in `3/10`.
+    fn from_str(shard: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{shard} is not a shard like 3/10");
+        let (index, count) = shard.split_once('/').ok_or_else(invalid)?;
+        let index: u64 = index.trim().parse().map_err(|_| invalid())?;
+        let count: u64 = count.trim().parse().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(format!("shard {index} should be between 1 and {count}"));
+        }
+        Ok(Self { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+impl<'de> Deserialize<'de> for Shard {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let shard = String::deserialize(deserializer)?;
+        shard.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Shard {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Shard;
+
+    #[test]
+    fn shards_partition_the_scan() {
+        let shard: Shard = "3/10".parse().unwrap();
+        assert_eq!(shard.to_string(), "3/10");
+        assert!(shard.contains(2) && shard.contains(12));
+        assert!(!shard.contains(3));
+        let shards: Vec<Shard> = (1..=4)
+            .map(|index| format!("{index}/4").parse().unwrap())
+            .collect();
+        for position in 0..100 {
+            assert_eq!(
+                shards
+                    .iter()
+                    .filter(|shard| shard.contains(position))
+                    .count(),
+                1
+            );
+        }
+        assert!("0/10".parse::<Shard>().is_err());
+        assert!("11/10".parse::<Shard>().is_err());
+        assert!("3".parse::<Shard>().is_err());
+        assert!("a/b".parse::<Shard>().is_err());
+    }
+}
@@ -0,0 +1,401 @@
+//! Connect scans through an SSH jump host.
+//!
+//! With `--via ssh://user@bastion`, RustScan logs in to the jump host and
+//! asks it to connect to every port instead of connecting itself, with SSH
+//! `direct-tcpip` channels, like `ssh -W` does. A port is open when the jump
+//! host's connection succeeds, so the targets only have to be reachable
+//! from it, with no SOCKS proxy to set up.
+//!
+//! - The jump host's key has to be in `~/.ssh/known_hosts`, connect to it
+//!   with `ssh` once to add it.
+//! - Logging in tries ssh-agent, then `~/.ssh/id_ed25519`, `id_ecdsa` and
+//!   `id_rsa`, which must not have a passphrase.
+//! - The jump host has to allow forwarding, `AllowTcpForwarding` in sshd.
+//!
+//! An SSH connection opens one channel at a time, so `--via-channels`
+//! connections to the jump host probe ports side by side. A port the jump
+//! host can't reach before the timeout leaves its connection waiting, it's
+//! dropped and a new one is made. Targets are resolved on this machine, and
+//! scripts run from it too, not from the jump host.
+//!
+//! The tunnel needs RustScan built with the `ssh` feature, which links
+//! libssh2 and OpenSSL.
+use anyhow::{anyhow, Result};
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// The SSH port, when the URL has none.
+const SSH_PORT: u16 = 22;
+
+/// A jump host to scan through, `ssh://user@host:port`, see `--via`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JumpHost {
+    /// Who to log in as, the current user when `None`.
+    pub user: Option<String>,
+    pub host: String,
+    pub port: u16,
+}
+
+impl FromStr for JumpHost {
+    type Err = anyhow::Error;
+
+    fn from_str(url: &str) -> Result<Self> {
+        let invalid = || anyhow!("{url} should be ssh://[user@]host[:port]");
+        let rest = url.strip_prefix("ssh://").ok_or_else(invalid)?;
+        let (user, address) = match rest.rsplit_once('@') {
+            Some((user, address)) if !user.is_empty() => (Some(user.to_owned()), address),
+            Some(_) => return Err(invalid()),
+            None => (None, rest),
+        };
+        let address = address.trim_end_matches('/');
+        let (host, port) = match address.strip_prefix('[') {
+            // [v6] or [v6]:port
+            Some(bracketed) => {
+                let (host, rest) = bracketed.split_once(']').ok_or_else(invalid)?;
+                match rest.strip_prefix(':') {
+                    Some(port) => (host, Some(port)),
+                    None if rest.is_empty() => (host, None),
+                    None => return Err(invalid()),
+                }
+            }
+            None => match address.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (address, None),
+            },
+        };
+        if host.is_empty() || host.contains(['/', '@']) {
+            return Err(invalid());
+        }
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| anyhow!("{port} is not a port"))?,
+            None => SSH_PORT,
+        };
+        Ok(Self {
+            user,
+            host: host.to_owned(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for JumpHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ssh://")?;
+        if let Some(user) = &self.user {
+            write!(f, "{user}@")?;
+        }
+        if self.host.contains(':') {
+            write!(f, "[{}]", self.host)?;
+        } else {
+            f.write_str(&self.host)?;
+        }
+        if self.port != SSH_PORT {
+            write!(f, ":{}", self.port)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for JumpHost {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let url = String::deserialize(deserializer)?;
+        url.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for JumpHost {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "ssh")]
+pub use tunnel::SshTunnel;
+
+#[cfg(feature = "ssh")]
+mod tunnel {
+    use super::JumpHost;
+    use anyhow::{anyhow, Context, Result};
+    use log::debug;
+    use ssh2::{CheckResult, ErrorCode, KnownHostFileKind, Session};
+    use std::convert::TryFrom;
+    use std::io;
+    use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Condvar, Mutex};
+    use std::time::Duration;
+
+    /// How long connecting and logging in to the jump host may take.
+    const LOGIN_TIMEOUT: Duration = Duration::from_secs(15);
+    /// `LIBSSH2_ERROR_TIMEOUT`.
+    const ERROR_TIMEOUT: i32 = -9;
+    /// Keys tried after ssh-agent, in `~/.ssh`.
+    const IDENTITIES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+    /// The connections to the jump host not probing a port right now.
+    #[derive(Default)]
+    struct Pool {
+        idle: Vec<Session>,
+        /// Connections made or being made, idle or not.
+        open: usize,
+    }
+
+    /// Connections to a jump host, probing ports with `direct-tcpip`
+    /// channels, at most one per connection at a time.
+    pub struct SshTunnel {
+        jump: JumpHost,
+        user: String,
+        channels: usize,
+        timeout: Duration,
+        pool: Mutex<Pool>,
+        returned: Condvar,
+        /// Set once the jump host refused to forward a connection.
+        prohibited: AtomicBool,
+    }
+
+    impl std::fmt::Debug for SshTunnel {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("SshTunnel")
+                .field("jump", &self.jump)
+                .field("channels", &self.channels)
+                .field("timeout", &self.timeout)
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl SshTunnel {
+        /// Logs in to `jump` once, to fail early on a wrong host key or
+        /// login, and probes up to `channels` ports at a time, each for
+        /// `timeout`.
+        pub fn connect(jump: &JumpHost, channels: usize, timeout: Duration) -> Result<Self> {
+            let user = match &jump.user {
+                Some(user) => user.clone(),
+                None => std::env::var("USER")
+                    .or_else(|_| std::env::var("USERNAME"))
+                    .map_err(|_| anyhow!("no user in {jump}, and $USER isn't set"))?,
+            };
+            let tunnel = Self {
+                jump: jump.clone(),
+                user,
+                channels: channels.max(1),
+                timeout,
+                pool: Mutex::new(Pool::default()),
+                returned: Condvar::new(),
+                prohibited: AtomicBool::new(false),
+            };
+            let session = tunnel.login()?;
+            let mut pool = tunnel.lock();
+            pool.idle.push(session);
+            pool.open = 1;
+            drop(pool);
+            Ok(tunnel)
+        }
+
+        /// How many ports are probed at a time.
+        pub fn channels(&self) -> usize {
+            self.channels
+        }
+
+        /// Whether the jump host refused to forward a connection, as it does
+        /// when sshd has `AllowTcpForwarding no`.
+        pub fn forwarding_prohibited(&self) -> bool {
+            self.prohibited.load(Ordering::Relaxed)
+        }
+
+        fn lock(&self) -> std::sync::MutexGuard<'_, Pool> {
+            self.pool.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        /// Connects and logs in to the jump host.
+        fn login(&self) -> Result<Session> {
+            let jump = &self.jump;
+            let address = (jump.host.as_str(), jump.port)
+                .to_socket_addrs()
+                .with_context(|| format!("could not resolve {jump}"))?
+                .next()
+                .ok_or_else(|| anyhow!("{} resolved to no addresses", jump.host))?;
+            let stream = TcpStream::connect_timeout(&address, LOGIN_TIMEOUT)
+                .with_context(|| format!("could not connect to {jump}"))?;
+            let mut session = Session::new()?;
+            session.set_timeout(millis(LOGIN_TIMEOUT));
+            session.set_tcp_stream(stream);
+            session
+                .handshake()
+                .with_context(|| format!("SSH handshake with {jump} failed"))?;
+            self.verify_host_key(&session)?;
+            self.authenticate(&session)?;
+            session.set_timeout(millis(self.timeout));
+            Ok(session)
+        }
+
+        fn verify_host_key(&self, session: &Session) -> Result<()> {
+            let jump = &self.jump;
+            let (key, _) = session
+                .host_key()
+                .ok_or_else(|| anyhow!("{jump} sent no host key"))?;
+            let known_hosts = ssh_dir()?.join("known_hosts");
+            let mut hosts = session.known_hosts()?;
+            if known_hosts.exists() {
+                hosts
+                    .read_file(&known_hosts, KnownHostFileKind::OpenSSH)
+                    .with_context(|| format!("could not read {}", known_hosts.display()))?;
+            }
+            match hosts.check_port(&jump.host, jump.port, key) {
+                CheckResult::Match => Ok(()),
+                CheckResult::Mismatch => Err(anyhow!(
+                    "the host key of {jump} doesn't match the one in {}, it may be an impostor",
+                    known_hosts.display()
+                )),
+                CheckResult::NotFound | CheckResult::Failure => Err(anyhow!(
+                    "the host key of {jump} isn't in {}, connect with ssh once to check and add it",
+                    known_hosts.display()
+                )),
+            }
+        }
+
+        fn authenticate(&self, session: &Session) -> Result<()> {
+            if let Err(e) = session.userauth_agent(&self.user) {
+                debug!("ssh-agent login to {} failed: {e}", self.jump);
+            }
+            let ssh_dir = ssh_dir()?;
+            for identity in IDENTITIES {
+                if session.authenticated() {
+                    break;
+                }
+                let key = ssh_dir.join(identity);
+                if key.exists() {
+                    if let Err(e) = session.userauth_pubkey_file(&self.user, None, &key, None) {
+                        debug!("Login to {} with {} failed: {e}", self.jump, key.display());
+                    }
+                }
+            }
+            if session.authenticated() {
+                Ok(())
+            } else {
+                Err(anyhow!(
+                    "could not log in to {} as {}, tried ssh-agent and {}/{{{}}}",
+                    self.jump,
+                    self.user,
+                    ssh_dir.display(),
+                    IDENTITIES.join(",")
+                ))
+            }
+        }
+
+        /// An idle connection, a new one if fewer than `channels` are open,
+        /// or the next one returned.
+        fn checkout(&self) -> io::Result<Session> {
+            let mut pool = self.lock();
+            loop {
+                if let Some(session) = pool.idle.pop() {
+                    return Ok(session);
+                }
+                if pool.open < self.channels {
+                    pool.open += 1;
+                    drop(pool);
+                    return self.login().map_err(|e| {
+                        self.checkin(None);
+                        io::Error::other(format!("{e:#}"))
+                    });
+                }
+                pool = self.returned.wait(pool).unwrap_or_else(|e| e.into_inner());
+            }
+        }
+
+        /// Returns a connection, or `None` for one that's no longer usable.
+        fn checkin(&self, session: Option<Session>) {
+            let mut pool = self.lock();
+            match session {
+                Some(session) => pool.idle.push(session),
+                None => pool.open -= 1,
+            }
+            drop(pool);
+            self.returned.notify_one();
+        }
+
+        /// Asks the jump host to connect to `socket`. Blocks until it did,
+        /// failed, or the timeout passed.
+        pub fn probe(&self, socket: SocketAddr) -> io::Result<()> {
+            let session = self.checkout()?;
+            let channel =
+                session.channel_direct_tcpip(&socket.ip().to_string(), socket.port(), None);
+            match channel {
+                Ok(channel) => {
+                    // Dropping it closes the channel without waiting.
+                    drop(channel);
+                    self.checkin(Some(session));
+                    Ok(())
+                }
+                Err(e) if e.code() == ErrorCode::Session(ERROR_TIMEOUT) => {
+                    // The channel is still being opened, so the connection
+                    // can't open another one.
+                    self.checkin(None);
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("{socket} via {}: timed out", self.jump),
+                    ))
+                }
+                Err(e) => {
+                    self.checkin(Some(session));
+                    if e.message().contains("prohibited") {
+                        self.prohibited.store(true, Ordering::Relaxed);
+                    }
+                    Err(io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        format!("{socket} via {}: {}", self.jump, e.message()),
+                    ))
+                }
+            }
+        }
+    }
+
+    fn ssh_dir() -> Result<PathBuf> {
+        dirs::home_dir()
+            .map(|home| home.join(".ssh"))
+            .ok_or_else(|| anyhow!("no home directory to find .ssh in"))
+    }
+
+    fn millis(duration: Duration) -> u32 {
+        u32::try_from(duration.as_millis()).unwrap_or(u32::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JumpHost;
+
+    #[test]
+    fn parses_jump_hosts() {
+        let jump: JumpHost = "ssh://admin@bastion.example.org".parse().unwrap();
+        assert_eq!(
+            jump,
+            JumpHost {
+                user: Some("admin".to_owned()),
+                host: "bastion.example.org".to_owned(),
+                port: 22,
+            }
+        );
+        assert_eq!(jump.to_string(), "ssh://admin@bastion.example.org");
+
+        let jump: JumpHost = "ssh://[2001:db8::1]:2222".parse().unwrap();
+        assert_eq!(jump.user, None);
+        assert_eq!(jump.host, "2001:db8::1");
+        assert_eq!(jump.port, 2222);
+        assert_eq!(jump.to_string(), "ssh://[2001:db8::1]:2222");
+
+        assert_eq!("ssh://10.0.0.1:22/".parse::<JumpHost>().unwrap().port, 22);
+        for invalid in [
+            "bastion",
+            "socks5://bastion",
+            "ssh://",
+            "ssh://@bastion",
+            "ssh://bastion:ssh",
+            "ssh://[::1",
+        ] {
+            assert!(invalid.parse::<JumpHost>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Stateless SYN scanning, with the target encoded in the sequence number.
+//!
+//! The SYN engine keeps the sockets of a batch it's waiting on, so memory
+//! grows with the batch size. With `--stateless`, nothing is kept about a
+//! probe once it's sent: its sequence number is a cookie, a keyed hash of
+//! the target address, port and source port, and an answer counts only if
+//! it acknowledges the cookie of the address it came from, plus one. SYNs
+//! go out continuously while answers are read in between, then the scan
+//! waits one timeout for the last ones. Memory stays flat however many
+//! probes are in flight, as needed to sweep large parts of the internet.
+//!
+//! Without state, a target that doesn't answer isn't told apart from one
+//! that hasn't answered yet, so timeouts aren't counted, and every
+//! `--tries` sends the probe again regardless of an earlier answer.
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+
+/// The sequence number to probe `destination` from `source_port` with,
+/// under the scan's random `secret`, so answers can't be forged without it.
+pub fn cookie(secret: u64, destination: SocketAddr, source_port: u16) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    (secret, destination, source_port).hash(&mut hasher);
+    let hash = hasher.finish();
+    (hash ^ (hash >> 32)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cookie;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn cookies_identify_targets() {
+        let target: SocketAddr = "198.51.100.7:443".parse().unwrap();
+        assert_eq!(cookie(1, target, 40000), cookie(1, target, 40000));
+        assert_ne!(cookie(1, target, 40000), cookie(2, target, 40000));
+        assert_ne!(cookie(1, target, 40000), cookie(1, target, 40001));
+        let other: SocketAddr = "198.51.100.7:444".parse().unwrap();
+        assert_ne!(cookie(1, target, 40000), cookie(1, other, 40000));
+    }
+}
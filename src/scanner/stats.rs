@@ -0,0 +1,68 @@
+//! Per-host counters of what the probes ran into.
+//!
+//! A host without open ports can be down, firewalled, or overwhelmed by the
+//! batch size. Which one shows in how its probes ended: refused connections
+//! come from a host that's up and answering with RSTs, while timeouts mean
+//! the probes or their answers were dropped. `--debug` prints these counters
+//! after the scan and adds them to structured outputs.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What the probes sent to a host ran into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeStats {
+    /// Probes sent, a SYN for TCP, including retries.
+    pub sent: u64,
+    /// Probes sent again after the previous try failed.
+    pub retries: u64,
+    /// Open ports found.
+    pub open: u64,
+    /// Connections refused, a RST for TCP and an ICMP port unreachable for
+    /// UDP.
+    pub refused: u64,
+    /// Probes nothing answered within the timeout.
+    pub timeouts: u64,
+    /// Probes that failed for any other reason, such as unreachable routes.
+    pub errors: u64,
+}
+
+impl ProbeStats {
+    /// Counts a failed probe by the kind of its error.
+    pub fn failed(&mut self, error: &std::io::Error) {
+        match error.kind() {
+            std::io::ErrorKind::ConnectionRefused => self.refused += 1,
+            std::io::ErrorKind::TimedOut => self.timeouts += 1,
+            _ => self.errors += 1,
+        }
+    }
+}
+
+impl fmt::Display for ProbeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} probes sent ({} retries), {} open, {} refused, {} timed out, {} other errors",
+            self.sent, self.retries, self.open, self.refused, self.timeouts, self.errors
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProbeStats;
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn counts_failures_by_kind() {
+        let mut stats = ProbeStats::default();
+        stats.failed(&Error::from(ErrorKind::ConnectionRefused));
+        stats.failed(&Error::from(ErrorKind::TimedOut));
+        stats.failed(&Error::from(ErrorKind::TimedOut));
+        stats.failed(&Error::other("No route to host"));
+        assert_eq!((stats.refused, stats.timeouts, stats.errors), (1, 2, 1));
+        assert_eq!(
+            stats.to_string(),
+            "0 probes sent (0 retries), 0 open, 1 refused, 2 timed out, 1 other errors"
+        );
+    }
+}
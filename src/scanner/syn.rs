@@ -0,0 +1,415 @@
+//! Half-open TCP scanning with raw sockets.
+//!
+//! `--tcp-engine syn` sends bare SYN segments from a raw socket instead of
+//! connecting, and reads the answers from a raw socket too: a SYN-ACK means
+//! open, a RST closed. No connection is ever set up, so the scan skips the
+//! kernel's connect path, its socket and file descriptor limits, and the
+//! full handshake; the kernel answers the SYN-ACKs it doesn't know about
+//! with a RST, closing the half-open connection on the target's side.
+//!
+//! Answers are read from raw sockets rather than captured by an eBPF or XDP
+//! program, which would need a BPF toolchain and loader; they still go
+//! through the kernel's network stack, matched on the source port and the
+//! acknowledgement number. They're read after every [`SEND_BURST`] SYNs,
+//! not only once a batch has been sent.
+//!
+//! Raw sockets need root or `CAP_NET_RAW`, and only Linux hands incoming
+//! TCP segments to them, so the engine is Linux only.
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+const TCP_HEADER: usize = 20;
+/// How many packets a socket is drained of per read, so one busy socket
+/// doesn't starve the other.
+#[cfg(target_os = "linux")]
+const RECV_BURST: usize = 256;
+/// SYNs sent between reads of the answers, so a large batch doesn't
+/// overflow the receive buffers before they're read.
+pub const SEND_BURST: usize = 64;
+/// How many times a SYN is sent again while the send buffer is full.
+const SEND_RETRIES: usize = 100;
+/// Routes remembered before starting over, so sweeps of many hosts don't
+/// grow the cache without bound.
+const SOURCE_CACHE: usize = 4096;
+/// An MSS option, as real stacks send, which some firewalls check for.
+const SYN_OPTIONS: [u8; 4] = [2, 4, 0x05, 0xb4];
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const ACK: u8 = 0x10;
+
+/// What a target answered to a SYN.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    /// A SYN-ACK, something listens.
+    Open,
+    /// A RST, nothing listens.
+    Closed,
+}
+
+/// Ones' complement sum of `chunks`, as in IP, TCP and UDP checksums.
+fn checksum(chunks: &[&[u8]]) -> u16 {
+    let mut sum: u32 = 0;
+    let mut odd: Option<u8> = None;
+    for byte in chunks.iter().flat_map(|chunk| chunk.iter()) {
+        match odd.take() {
+            Some(high) => sum += u32::from(u16::from_be_bytes([high, *byte])),
+            None => odd = Some(*byte),
+        }
+    }
+    if let Some(high) = odd {
+        sum += u32::from(u16::from_be_bytes([high, 0]));
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let sum = sum as u16;
+    !sum
+}
+
+/// The pseudo header TCP checksums cover.
+fn pseudo_header(source: IpAddr, destination: IpAddr, length: usize) -> Vec<u8> {
+    let mut header = Vec::with_capacity(40);
+    match (source, destination) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            header.extend_from_slice(&source.octets());
+            header.extend_from_slice(&destination.octets());
+            header.extend_from_slice(&[0, 6]);
+            #[allow(clippy::cast_possible_truncation)]
+            header.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        (source, destination) => {
+            let v6 = |ip: IpAddr| match ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+            header.extend_from_slice(&v6(source).octets());
+            header.extend_from_slice(&v6(destination).octets());
+            #[allow(clippy::cast_possible_truncation)]
+            header.extend_from_slice(&(length as u32).to_be_bytes());
+            header.extend_from_slice(&[0, 0, 0, 6]);
+        }
+    }
+    header
+}
+
+/// A SYN segment from `source` to `destination`, checksum included.
+pub fn syn_segment(source: SocketAddr, destination: SocketAddr, sequence: u32) -> Vec<u8> {
+    let mut segment = Vec::with_capacity(TCP_HEADER + SYN_OPTIONS.len());
+    segment.extend_from_slice(&source.port().to_be_bytes());
+    segment.extend_from_slice(&destination.port().to_be_bytes());
+    segment.extend_from_slice(&sequence.to_be_bytes());
+    segment.extend_from_slice(&0u32.to_be_bytes());
+    // Data offset in 32-bit words, the header and its options.
+    segment.push(6 << 4);
+    segment.push(SYN);
+    segment.extend_from_slice(&1024u16.to_be_bytes());
+    segment.extend_from_slice(&[0, 0, 0, 0]);
+    segment.extend_from_slice(&SYN_OPTIONS);
+    let pseudo = pseudo_header(source.ip(), destination.ip(), segment.len());
+    let sum = checksum(&[&pseudo, &segment]);
+    segment[16..18].copy_from_slice(&sum.to_be_bytes());
+    segment
+}
+
+/// A TCP segment received by a raw socket, the fields that matter here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub source_port: u16,
+    pub destination_port: u16,
+    pub acknowledgement: u32,
+    pub flags: u8,
+}
+
+impl Segment {
+    pub fn parse(tcp: &[u8]) -> Option<Self> {
+        if tcp.len() < TCP_HEADER {
+            return None;
+        }
+        let word = |at: usize| u32::from_be_bytes([tcp[at], tcp[at + 1], tcp[at + 2], tcp[at + 3]]);
+        Some(Self {
+            source_port: u16::from_be_bytes([tcp[0], tcp[1]]),
+            destination_port: u16::from_be_bytes([tcp[2], tcp[3]]),
+            acknowledgement: word(8),
+            flags: tcp[13],
+        })
+    }
+
+    /// What the segment says about the port, if it answers a SYN sent with
+    /// `sequence`.
+    pub fn reply(&self, sequence: u32) -> Option<Reply> {
+        if self.acknowledgement != sequence.wrapping_add(1) {
+            return None;
+        }
+        if self.flags & RST != 0 {
+            Some(Reply::Closed)
+        } else if self.flags & (SYN | ACK) == SYN | ACK {
+            Some(Reply::Open)
+        } else {
+            None
+        }
+    }
+}
+
+/// The TCP segment in what an IPv4 raw socket received, which includes the
+/// IP header, if the packet is TCP.
+pub fn ipv4_payload(packet: &[u8]) -> Option<(IpAddr, &[u8])> {
+    let header = usize::from(packet.first()? & 0x0f) * 4;
+    if packet.len() < header.max(20) || packet[9] != 6 {
+        return None;
+    }
+    let source = IpAddr::from([packet[12], packet[13], packet[14], packet[15]]);
+    Some((source, &packet[header..]))
+}
+
+/// Raw sockets sending SYNs from one source port and reading the answers.
+#[derive(Debug)]
+pub struct SynProbe {
+    v4: Option<socket2::Socket>,
+    v6: Option<socket2::Socket>,
+    source_port: u16,
+    sources: HashMap<IpAddr, IpAddr>,
+}
+
+impl SynProbe {
+    /// Opens raw sockets for both address families, sending from
+    /// `source_port`. Fails without the privileges to open raw sockets.
+    #[cfg(target_os = "linux")]
+    pub fn new(source_port: u16) -> io::Result<Self> {
+        use socket2::{Domain, Protocol, Socket, Type};
+
+        let open = |domain| {
+            let socket = Socket::new(domain, Type::RAW, Some(Protocol::TCP))?;
+            socket.set_nonblocking(true)?;
+            Ok::<_, io::Error>(socket)
+        };
+        let v4 = open(Domain::IPV4)?;
+        // Hosts without IPv6 still scan IPv4.
+        let v6 = open(Domain::IPV6).ok();
+        Ok(Self {
+            v4: Some(v4),
+            v6,
+            source_port,
+            sources: HashMap::new(),
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(_source_port: u16) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "SYN scans are only supported on Linux",
+        ))
+    }
+
+    pub fn source_port(&self) -> u16 {
+        self.source_port
+    }
+
+    /// The local address the OS routes packets to `destination` from.
+    fn source_for(&mut self, destination: IpAddr) -> io::Result<IpAddr> {
+        if let Some(source) = self.sources.get(&destination) {
+            return Ok(*source);
+        }
+        let unspecified: SocketAddr = if destination.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        // Connecting a UDP socket picks the route without sending anything.
+        let socket = std::net::UdpSocket::bind(unspecified)?;
+        socket.connect((destination, 9))?;
+        let source = socket.local_addr()?.ip();
+        if self.sources.len() >= SOURCE_CACHE {
+            self.sources.clear();
+        }
+        self.sources.insert(destination, source);
+        Ok(source)
+    }
+
+    /// Sends a SYN with `sequence` to `destination`.
+    pub fn send(&mut self, destination: SocketAddr, sequence: u32) -> io::Result<()> {
+        let source = SocketAddr::new(self.source_for(destination.ip())?, self.source_port);
+        let segment = syn_segment(source, destination, sequence);
+        let socket = if destination.is_ipv4() {
+            self.v4.as_ref()
+        } else {
+            self.v6.as_ref()
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "IPv6 raw sockets are unavailable",
+            )
+        })?;
+        // Raw sockets take the protocol, not a port, in the address.
+        let address = socket2::SockAddr::from(SocketAddr::new(destination.ip(), 0));
+        // A full send buffer drains in a moment, the socket doesn't block.
+        for _ in 0..SEND_RETRIES {
+            match socket.send_to(&segment, &address) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                result => return result.map(drop),
+            }
+        }
+        Err(io::Error::from(io::ErrorKind::WouldBlock))
+    }
+
+    /// The TCP segments addressed to the source port that arrived, waiting
+    /// up to `wait` for the first one. Returns an empty list once nothing
+    /// arrives.
+    #[cfg(target_os = "linux")]
+    pub fn recv(&self, wait: Duration) -> io::Result<Vec<(SocketAddr, Segment)>> {
+        use std::convert::TryFrom;
+        use std::os::unix::io::AsRawFd;
+
+        let sockets: Vec<&socket2::Socket> = self.v4.iter().chain(self.v6.iter()).collect();
+        let mut fds: Vec<libc::pollfd> = sockets
+            .iter()
+            .map(|socket| libc::pollfd {
+                fd: socket.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        let timeout = libc::c_int::try_from(wait.as_millis()).unwrap_or(libc::c_int::MAX);
+        // SAFETY: fds is a live array of as many pollfd as passed.
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+        if ready < 0 {
+            let error = io::Error::last_os_error();
+            return match error.kind() {
+                io::ErrorKind::Interrupted => Ok(Vec::new()),
+                _ => Err(error),
+            };
+        }
+
+        let mut segments = Vec::new();
+        for (socket, fd) in sockets.iter().zip(&fds) {
+            if fd.revents & libc::POLLIN == 0 {
+                continue;
+            }
+            // Drain what's queued, the sockets don't block.
+            for _ in 0..RECV_BURST {
+                let mut buffer = [std::mem::MaybeUninit::<u8>::uninit(); 1500];
+                let (size, address) = match socket.recv_from(&mut buffer) {
+                    Ok(received) => received,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                };
+                // SAFETY: recv_from initialised the first `size` bytes.
+                let packet: Vec<u8> = buffer[..size]
+                    .iter()
+                    .map(|byte| unsafe { byte.assume_init() })
+                    .collect();
+                let received = match address.as_socket() {
+                    Some(SocketAddr::V6(source)) => Some((IpAddr::V6(*source.ip()), &packet[..])),
+                    _ => ipv4_payload(&packet),
+                };
+                let Some((source, tcp)) = received else {
+                    continue;
+                };
+                if let Some(segment) = Segment::parse(tcp) {
+                    if segment.destination_port == self.source_port {
+                        segments.push((SocketAddr::new(source, segment.source_port), segment));
+                    }
+                }
+            }
+        }
+        Ok(segments)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn recv(&self, _wait: Duration) -> io::Result<Vec<(SocketAddr, Segment)>> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checksum, ipv4_payload, syn_segment, Reply, Segment, SynProbe};
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn checksums_verify() {
+        // A segment with its checksum in sums to zero.
+        let source: SocketAddr = "192.0.2.1:40000".parse().unwrap();
+        let destination: SocketAddr = "198.51.100.7:443".parse().unwrap();
+        let segment = syn_segment(source, destination, 0x1234_5678);
+        let pseudo = super::pseudo_header(source.ip(), destination.ip(), segment.len());
+        assert_eq!(checksum(&[&pseudo, &segment]), 0);
+        assert_eq!(segment.len(), 24);
+        assert_eq!(segment[13], 0x02);
+
+        let source: SocketAddr = "[2001:db8::1]:40000".parse().unwrap();
+        let destination: SocketAddr = "[2001:db8::2]:22".parse().unwrap();
+        let segment = syn_segment(source, destination, 7);
+        let pseudo = super::pseudo_header(source.ip(), destination.ip(), segment.len());
+        assert_eq!(checksum(&[&pseudo, &segment]), 0);
+    }
+
+    #[test]
+    fn classify_replies() {
+        let mut tcp = [0u8; 20];
+        tcp[0..2].copy_from_slice(&443u16.to_be_bytes());
+        tcp[2..4].copy_from_slice(&40000u16.to_be_bytes());
+        tcp[8..12].copy_from_slice(&101u32.to_be_bytes());
+        tcp[13] = 0x12;
+        let segment = Segment::parse(&tcp).unwrap();
+        assert_eq!(
+            (segment.source_port, segment.destination_port),
+            (443, 40000)
+        );
+        assert_eq!(segment.reply(100), Some(Reply::Open));
+        assert_eq!(segment.reply(5), None);
+        tcp[13] = 0x14;
+        assert_eq!(
+            Segment::parse(&tcp).unwrap().reply(100),
+            Some(Reply::Closed)
+        );
+        assert!(Segment::parse(&tcp[..10]).is_none());
+
+        let mut packet = vec![
+            0x45, 0, 0, 40, 0, 0, 0, 0, 64, 6, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+        ];
+        packet.extend_from_slice(&tcp);
+        let (source, payload) = ipv4_payload(&packet).unwrap();
+        assert_eq!(
+            (source.to_string(), payload.len()),
+            ("10.0.0.1".to_owned(), 20)
+        );
+        packet[9] = 17;
+        assert!(ipv4_payload(&packet).is_none());
+    }
+
+    #[test]
+    fn syn_probes_find_listeners() {
+        // Raw sockets need root, skip without it.
+        let Ok(mut probe) = SynProbe::new(45_321) else {
+            return;
+        };
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap();
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        probe.send(open, 1000).unwrap();
+        probe.send(closed, 1000).unwrap();
+
+        let mut replies = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while replies.len() < 2 && Instant::now() < deadline {
+            for (socket, segment) in probe.recv(Duration::from_millis(200)).unwrap() {
+                if let Some(reply) = segment.reply(1000) {
+                    replies.push((socket, reply));
+                }
+            }
+        }
+        replies.sort_by_key(|(socket, _)| *socket == closed);
+        assert_eq!(replies, vec![(open, Reply::Open), (closed, Reply::Closed)]);
+    }
+}
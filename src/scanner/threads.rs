@@ -0,0 +1,120 @@
+//! Spreads a scan over several threads, optionally pinned to CPUs.
+//!
+//! A single thread drives every socket of a scan, and on many-core scan
+//! boxes it tops out long before the network does. With `--threads N`, the
+//! sockets are dealt out round-robin to N threads, each running its own
+//! event loop over its share of the batch. `--cpu-affinity 0-3,8` pins the
+//! threads to those CPUs in turn, keeping each on one core (and its NUMA
+//! node) instead of letting the OS move it around; pinning is only
+//! available on Linux.
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// CPUs to pin scan threads to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuSet(Vec<usize>);
+
+impl CpuSet {
+    /// The CPU for scan thread `thread`, going round the set.
+    pub fn cpu_for(&self, thread: usize) -> usize {
+        self.0[thread % self.0.len()]
+    }
+}
+
+impl FromStr for CpuSet {
+    type Err = String;
+
+    /// Parses a list of CPUs and ranges, as in `0-3,8`.
+    fn from_str(cpus: &str) -> Result<Self, Self::Err> {
+        let parse = |cpu: &str| {
+            cpu.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("{cpu} is not a CPU number"))
+        };
+        let mut set = Vec::new();
+        for part in cpus.split(',') {
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (parse(first)?, parse(last)?);
+                    if first > last {
+                        return Err(format!("{part} is not a range of CPUs"));
+                    }
+                    set.extend(first..=last);
+                }
+                None => set.push(parse(part)?),
+            }
+        }
+        set.dedup();
+        Ok(Self(set))
+    }
+}
+
+impl fmt::Display for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cpus: Vec<String> = self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", cpus.join(","))
+    }
+}
+
+impl<'de> Deserialize<'de> for CpuSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let cpus = String::deserialize(deserializer)?;
+        cpus.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for CpuSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Pins the calling thread to `cpu`.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpu: usize) -> std::io::Result<()> {
+    // SAFETY: cpu_set_t is a plain bitmask, zeroed is the empty set, and
+    // CPU_SET checks the index against its size.
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cpu: usize) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "CPU pinning is only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CpuSet;
+
+    #[test]
+    fn parse_cpu_sets() {
+        let set: CpuSet = "0-3,8".parse().unwrap();
+        assert_eq!(set.to_string(), "0,1,2,3,8");
+        assert_eq!((set.cpu_for(1), set.cpu_for(4), set.cpu_for(5)), (1, 8, 0));
+        assert!("3-1".parse::<CpuSet>().is_err());
+        assert!("a".parse::<CpuSet>().is_err());
+        assert!("".parse::<CpuSet>().is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn pins_threads() {
+        std::thread::spawn(|| super::pin_current_thread(0).unwrap())
+            .join()
+            .unwrap();
+    }
+}
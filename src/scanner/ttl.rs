@@ -0,0 +1,165 @@
+//! Estimates how many hops away hosts are from the TTL of their answers.
+//!
+//! Operating systems send packets with an initial TTL of 64 (Linux, macOS),
+//! 128 (Windows) or 255 (network gear), and every router on the way
+//! decrements it. The TTL an answer arrives with, subtracted from the
+//! initial TTL just above it, is the number of hops to the host. A host
+//! further away than its neighbours may sit behind an unexpected routing
+//! path.
+//!
+//! Connect scans don't see the IP header of TCP answers, so TTLs are read
+//! from the answers of UDP scans, on Linux where the kernel reports them.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Initial TTLs in use, in increasing order.
+const INITIAL_TTLS: [u8; 4] = [32, 64, 128, 255];
+
+/// How far away a host is, from the TTL of its answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Distance {
+    /// The TTL (IPv4) or hop limit (IPv6) an answer arrived with.
+    pub ttl: u8,
+    /// The TTL the host most likely sent it with.
+    pub initial_ttl: u8,
+    /// The routers in between.
+    pub hops: u8,
+}
+
+impl Distance {
+    pub fn from_ttl(ttl: u8) -> Self {
+        let initial_ttl = INITIAL_TTLS
+            .iter()
+            .copied()
+            .find(|initial| *initial >= ttl)
+            .unwrap_or(u8::MAX);
+        Self {
+            ttl,
+            initial_ttl,
+            hops: initial_ttl - ttl,
+        }
+    }
+}
+
+impl fmt::Display for Distance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "about {} hops away (TTL {}, sent with {})",
+            self.hops, self.ttl, self.initial_ttl
+        )
+    }
+}
+
+/// Asks the kernel to report the TTL of the datagrams `socket` receives.
+#[cfg(target_os = "linux")]
+pub fn report_ttl(socket: &impl std::os::unix::io::AsRawFd, ipv6: bool) -> std::io::Result<()> {
+    let (level, option) = if ipv6 {
+        (libc::IPPROTO_IPV6, libc::IPV6_RECVHOPLIMIT)
+    } else {
+        (libc::IPPROTO_IP, libc::IP_RECVTTL)
+    };
+    let enable: libc::c_int = 1;
+    // SAFETY: the option value is a c_int that outlives the call, with its
+    // size passed alongside.
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            option,
+            std::ptr::addr_of!(enable).cast(),
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Receives a datagram waiting on `socket` without blocking, with the TTL
+/// it arrived with if [`report_ttl`] was set.
+#[cfg(target_os = "linux")]
+pub fn recv_with_ttl(
+    socket: &impl std::os::unix::io::AsRawFd,
+    buf: &mut [u8],
+) -> std::io::Result<(usize, Option<u8>)> {
+    use std::convert::TryFrom;
+
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let mut control = [0u8; 64];
+    // SAFETY: msghdr is plain data, zeroed is a valid empty header.
+    let mut header: libc::msghdr = unsafe { std::mem::zeroed() };
+    header.msg_iov = std::ptr::addr_of_mut!(iov);
+    header.msg_iovlen = 1;
+    header.msg_control = control.as_mut_ptr().cast();
+    header.msg_controllen = control.len();
+    // SAFETY: the header points at the buffer and control buffer above,
+    // both outliving the call, with their lengths.
+    let size = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut header, libc::MSG_DONTWAIT) };
+    let size = usize::try_from(size).map_err(|_| std::io::Error::last_os_error())?;
+
+    let mut ttl = None;
+    // SAFETY: the CMSG macros walk the control messages recvmsg wrote into
+    // the control buffer, within msg_controllen.
+    unsafe {
+        let mut message = libc::CMSG_FIRSTHDR(&header);
+        while !message.is_null() {
+            let kind = ((*message).cmsg_level, (*message).cmsg_type);
+            if kind == (libc::IPPROTO_IP, libc::IP_TTL)
+                || kind == (libc::IPPROTO_IPV6, libc::IPV6_HOPLIMIT)
+            {
+                let value =
+                    std::ptr::read_unaligned(libc::CMSG_DATA(message).cast::<libc::c_int>());
+                ttl = u8::try_from(value).ok();
+            }
+            message = libc::CMSG_NXTHDR(&header, message);
+        }
+    }
+    Ok((size, ttl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Distance;
+
+    #[test]
+    fn hops_from_ttls() {
+        assert_eq!(Distance::from_ttl(64).hops, 0);
+        let distance = Distance::from_ttl(52);
+        assert_eq!((distance.initial_ttl, distance.hops), (64, 12));
+        assert_eq!(Distance::from_ttl(117).initial_ttl, 128);
+        assert_eq!(Distance::from_ttl(250).hops, 5);
+        assert_eq!(Distance::from_ttl(30).initial_ttl, 32);
+        assert_eq!(
+            Distance::from_ttl(52).to_string(),
+            "about 12 hops away (TTL 52, sent with 64)"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reads_ttl_of_datagrams() {
+        use std::net::UdpSocket;
+
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        super::report_ttl(&receiver, false).unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.set_ttl(42).unwrap();
+        sender
+            .send_to(b"ping", receiver.local_addr().unwrap())
+            .unwrap();
+        receiver
+            .set_read_timeout(Some(std::time::Duration::from_secs(1)))
+            .unwrap();
+        receiver.peek(&mut [0; 4]).unwrap();
+
+        let mut buf = [0; 16];
+        let (size, ttl) = super::recv_with_ttl(&receiver, &mut buf).unwrap();
+        assert_eq!((&buf[..size], ttl), (&b"ping"[..], Some(42)));
+    }
+}
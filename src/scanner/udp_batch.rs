@@ -0,0 +1,213 @@
+//! Sends and receives UDP probes in batches of datagrams per syscall.
+//!
+//! The default UDP engine opens a socket per probe, which costs several
+//! syscalls per port. `--udp-engine batched` sends a whole batch of probes
+//! from one socket per address family and matches answers by their source,
+//! on Linux with `sendmmsg`/`recvmmsg` so a syscall carries many datagrams.
+//! Elsewhere the same engine falls back to a `send_to`/`recv_from` loop.
+//!
+//! An unconnected socket doesn't see ICMP port unreachable errors, so like
+//! the default engine, a port only counts as open when it answers.
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+/// How many datagrams a single `recvmmsg` call can return.
+#[cfg(target_os = "linux")]
+const RECV_BATCH: usize = 64;
+/// Space for an answer; longer ones are truncated, which is fine to tell a
+/// port answered.
+pub const ANSWER_SIZE: usize = 1024;
+
+/// Sends every `(destination, payload)` datagram, returns how many went out.
+#[cfg(target_os = "linux")]
+pub fn send_batch(socket: &UdpSocket, datagrams: &[(SocketAddr, &[u8])]) -> io::Result<usize> {
+    use std::convert::TryFrom;
+    use std::os::unix::io::AsRawFd;
+
+    let addresses: Vec<socket2::SockAddr> = datagrams
+        .iter()
+        .map(|(destination, _)| socket2::SockAddr::from(*destination))
+        .collect();
+    let mut iovecs: Vec<libc::iovec> = datagrams
+        .iter()
+        .map(|(_, payload)| libc::iovec {
+            iov_base: payload.as_ptr() as *mut libc::c_void,
+            iov_len: payload.len(),
+        })
+        .collect();
+    let mut messages: Vec<libc::mmsghdr> = addresses
+        .iter()
+        .zip(iovecs.iter_mut())
+        .map(|(address, iovec)| {
+            // SAFETY: mmsghdr is plain data, zeroed is a valid empty header.
+            let mut message: libc::mmsghdr = unsafe { std::mem::zeroed() };
+            message.msg_hdr.msg_name = address.as_ptr() as *mut libc::c_void;
+            message.msg_hdr.msg_namelen = address.len();
+            message.msg_hdr.msg_iov = iovec;
+            message.msg_hdr.msg_iovlen = 1;
+            message
+        })
+        .collect();
+
+    let mut sent = 0;
+    while sent < messages.len() {
+        let count = libc::c_uint::try_from(messages.len() - sent).unwrap_or(libc::c_uint::MAX);
+        // SAFETY: the headers point at addresses and payloads that outlive
+        // the call, and count doesn't go past the end of the headers.
+        let result =
+            unsafe { libc::sendmmsg(socket.as_raw_fd(), messages[sent..].as_mut_ptr(), count, 0) };
+        match usize::try_from(result) {
+            Ok(0) => break,
+            Ok(count) => sent += count,
+            Err(_) => {
+                let error = io::Error::last_os_error();
+                if sent == 0 {
+                    return Err(error);
+                }
+                break;
+            }
+        }
+    }
+    Ok(sent)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn send_batch(socket: &UdpSocket, datagrams: &[(SocketAddr, &[u8])]) -> io::Result<usize> {
+    let mut sent = 0;
+    for (destination, payload) in datagrams {
+        match socket.send_to(payload, destination) {
+            Ok(_) => sent += 1,
+            Err(e) if sent == 0 => return Err(e),
+            Err(_) => break,
+        }
+    }
+    Ok(sent)
+}
+
+/// Receives the answers waiting on `socket`, waiting for the first one up
+/// to the socket's read timeout. Returns their sources, an empty list once
+/// nothing more arrives.
+#[cfg(target_os = "linux")]
+pub fn recv_batch(socket: &UdpSocket) -> io::Result<Vec<SocketAddr>> {
+    use std::convert::TryFrom;
+    use std::os::unix::io::AsRawFd;
+
+    let mut buffers = vec![[0u8; ANSWER_SIZE]; RECV_BATCH];
+    // SAFETY: sockaddr_storage is plain data, zeroed is valid.
+    let mut sources: Vec<libc::sockaddr_storage> = vec![unsafe { std::mem::zeroed() }; RECV_BATCH];
+    let mut iovecs: Vec<libc::iovec> = buffers
+        .iter_mut()
+        .map(|buffer| libc::iovec {
+            iov_base: buffer.as_mut_ptr().cast(),
+            iov_len: buffer.len(),
+        })
+        .collect();
+    let mut messages: Vec<libc::mmsghdr> = sources
+        .iter_mut()
+        .zip(iovecs.iter_mut())
+        .map(|(source, iovec)| {
+            // SAFETY: mmsghdr is plain data, zeroed is a valid empty header.
+            let mut message: libc::mmsghdr = unsafe { std::mem::zeroed() };
+            message.msg_hdr.msg_name = (source as *mut libc::sockaddr_storage).cast();
+            message.msg_hdr.msg_namelen =
+                std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            message.msg_hdr.msg_iov = iovec;
+            message.msg_hdr.msg_iovlen = 1;
+            message
+        })
+        .collect();
+
+    // SAFETY: the headers point at buffers and source addresses that
+    // outlive the call, RECV_BATCH of them. MSG_WAITFORONE blocks for the
+    // first datagram only, up to the read timeout.
+    let result = unsafe {
+        libc::recvmmsg(
+            socket.as_raw_fd(),
+            messages.as_mut_ptr(),
+            RECV_BATCH as libc::c_uint,
+            libc::MSG_WAITFORONE,
+            std::ptr::null_mut(),
+        )
+    };
+    let received = match usize::try_from(result) {
+        Ok(received) => received,
+        Err(_) => {
+            let error = io::Error::last_os_error();
+            return match error.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Ok(Vec::new()),
+                _ => Err(error),
+            };
+        }
+    };
+    Ok(messages[..received]
+        .iter()
+        .zip(&sources)
+        .filter_map(|(message, source)| {
+            // SAFETY: recvmmsg wrote a source address of msg_namelen bytes.
+            let address = unsafe { socket2::SockAddr::new(*source, message.msg_hdr.msg_namelen) };
+            address.as_socket()
+        })
+        .collect())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn recv_batch(socket: &UdpSocket) -> io::Result<Vec<SocketAddr>> {
+    let mut buffer = [0u8; ANSWER_SIZE];
+    match socket.recv_from(&mut buffer) {
+        Ok((_, source)) => Ok(vec![source]),
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            Ok(Vec::new())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{recv_batch, send_batch};
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    #[test]
+    fn batches_round_trip() {
+        let servers: Vec<UdpSocket> = (0..3)
+            .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        let datagrams: Vec<_> = servers
+            .iter()
+            .map(|server| (server.local_addr().unwrap(), &b"probe"[..]))
+            .collect();
+        assert_eq!(send_batch(&client, &datagrams).unwrap(), 3);
+
+        // Only the first two answer.
+        for server in &servers[..2] {
+            let mut buffer = [0; 16];
+            let (size, source) = server.recv_from(&mut buffer).unwrap();
+            assert_eq!(&buffer[..size], b"probe");
+            server.send_to(b"answer", source).unwrap();
+        }
+        let mut sources = Vec::new();
+        while sources.len() < 2 {
+            let answers = recv_batch(&client).unwrap();
+            assert!(!answers.is_empty());
+            sources.extend(answers);
+        }
+        sources.sort();
+        let mut expected: Vec<_> = servers[..2]
+            .iter()
+            .map(|server| server.local_addr().unwrap())
+            .collect();
+        expected.sort();
+        assert_eq!(sources, expected);
+        assert!(recv_batch(&client).unwrap().is_empty());
+    }
+}
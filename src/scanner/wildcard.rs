@@ -0,0 +1,47 @@
+//! Detects hosts that accept connections on every port.
+//!
+//! Some load balancers, firewalls and tarpits complete the handshake on all
+//! 65535 ports, which would make every scanned port look open. Before the
+//! scan, a few random ports from the dynamic range, where services rarely
+//! listen, are probed on every host; if they all accept, the host answers
+//! everything. Scans of a handful of ports skip the check, they can't be
+//! flooded with false positives anyway.
+//!
+//! Such a host is skipped, unless `--verify-wildcards` asks for its ports to
+//! be scanned anyway, reporting only those that send a banner or answer
+//! their `--probe-payload`.
+use rand::seq::IteratorRandom;
+
+/// How many random ports are probed on every host.
+pub const WILDCARD_SAMPLES: usize = 4;
+/// Scans of fewer ports than this are not worth the extra probes.
+pub const MIN_SCANNED_PORTS: usize = 100;
+
+/// What to do with hosts that accept connections on every port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WildcardPolicy {
+    /// Leave them out of the scan.
+    Skip,
+    /// Scan them, reporting only ports that answer.
+    Verify,
+}
+
+/// Distinct random ports from the dynamic range, where services rarely
+/// listen.
+pub fn sample_ports() -> Vec<u16> {
+    (49152..=u16::MAX).sample(&mut rand::rng(), WILDCARD_SAMPLES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sample_ports, WILDCARD_SAMPLES};
+    use std::collections::HashSet;
+
+    #[test]
+    fn samples_distinct_dynamic_ports() {
+        let ports = sample_ports();
+        assert_eq!(ports.len(), WILDCARD_SAMPLES);
+        assert!(ports.iter().all(|port| *port >= 49152));
+        assert_eq!(ports.iter().collect::<HashSet<_>>().len(), WILDCARD_SAMPLES);
+    }
+}
@@ -0,0 +1,259 @@
+//! Time windows the scanner is allowed to send probes in.
+//!
+//! A window is given as `[days] HH:MM-HH:MM [timezone]`, for example
+//! `Mon-Fri 22:00-06:00 Europe/Berlin`. Days are a comma separated list of
+//! days and ranges (`Mon-Wed,Sat`) and default to every day; the timezone is
+//! an IANA name and defaults to UTC. A window that ends before it starts runs
+//! past midnight and belongs to the day it starts on, so the example allows
+//! Friday night until Saturday 06:00 but not Monday morning. `00:00-00:00`
+//! allows the whole day.
+//!
+//! Outside the window, the scanner lets the probes in flight finish and
+//! waits for the window to open again before sending more.
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+const DAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// When probes may be sent, see `--window`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanWindow {
+    /// Days the window starts on, indexed from Monday.
+    days: [bool; 7],
+    start: NaiveTime,
+    end: NaiveTime,
+    timezone: Tz,
+}
+
+impl ScanWindow {
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days[day.num_days_from_monday() as usize]
+    }
+
+    /// Whether probes may be sent at `now`.
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&self.timezone);
+        let (day, time) = (local.weekday(), local.time());
+        if self.start < self.end {
+            self.starts_on(day) && self.start <= time && time < self.end
+        } else {
+            (self.starts_on(day) && time >= self.start)
+                || (self.starts_on(day.pred()) && time < self.end)
+        }
+    }
+
+    /// When the window opens next, `now` if it's open.
+    pub fn next_open(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        if self.is_open(now) {
+            return now;
+        }
+        let today = now.with_timezone(&self.timezone).date_naive();
+        (0..=7)
+            .filter_map(|offset| today.checked_add_signed(Duration::days(offset)))
+            .filter(|date| self.starts_on(date.weekday()))
+            .filter_map(|date| {
+                self.timezone
+                    .from_local_datetime(&date.and_time(self.start))
+                    .earliest()
+            })
+            .map(|start| start.with_timezone(&Utc))
+            .find(|start| *start > now)
+            .unwrap_or(now)
+    }
+}
+
+fn parse_day(day: &str) -> Result<Weekday> {
+    day.parse()
+        .map_err(|_| anyhow!("{day} is not a day, use Mon, Tue, ..."))
+}
+
+fn parse_days(spec: &str) -> Result<[bool; 7]> {
+    let mut days = [false; 7];
+    for part in spec.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (parse_day(first)?, parse_day(last)?),
+            None => (parse_day(part)?, parse_day(part)?),
+        };
+        let mut day = first;
+        loop {
+            days[day.num_days_from_monday() as usize] = true;
+            if day == last {
+                break;
+            }
+            day = day.succ();
+        }
+    }
+    Ok(days)
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| anyhow!("{time} is not a time, use HH:MM"))
+}
+
+impl FromStr for ScanWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let fields: Vec<&str> = spec.split_whitespace().collect();
+        // The hours are the only field with a colon.
+        let hours = fields
+            .iter()
+            .position(|field| field.contains(':'))
+            .ok_or_else(|| anyhow!("{spec} has no hours, e.g. 'Mon-Fri 22:00-06:00 UTC'"))?;
+        let days = match &fields[..hours] {
+            [] => [true; 7],
+            [days] => parse_days(days)?,
+            _ => return Err(anyhow!("{spec} should be '[days] HH:MM-HH:MM [timezone]'")),
+        };
+        let (start, end) = fields[hours]
+            .split_once('-')
+            .ok_or_else(|| anyhow!("{} should be HH:MM-HH:MM", fields[hours]))?;
+        let timezone = match &fields[hours + 1..] {
+            [] => Tz::UTC,
+            [timezone] => timezone
+                .parse()
+                .map_err(|_| anyhow!("{timezone} is not a timezone, e.g. Europe/Berlin"))?,
+            _ => return Err(anyhow!("{spec} should be '[days] HH:MM-HH:MM [timezone]'")),
+        };
+        Ok(Self {
+            days,
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+            timezone,
+        })
+    }
+}
+
+impl fmt::Display for ScanWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.days != [true; 7] {
+            let mut ranges = Vec::new();
+            let mut index = 0;
+            while index < DAYS.len() {
+                if !self.days[index] {
+                    index += 1;
+                    continue;
+                }
+                let first = index;
+                while index + 1 < DAYS.len() && self.days[index + 1] {
+                    index += 1;
+                }
+                ranges.push(if first == index {
+                    format!("{}", DAYS[first])
+                } else {
+                    format!("{}-{}", DAYS[first], DAYS[index])
+                });
+                index += 1;
+            }
+            write!(f, "{} ", ranges.join(","))?;
+        }
+        write!(
+            f,
+            "{}-{} {}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M"),
+            self.timezone
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for ScanWindow {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let spec = String::deserialize(deserializer)?;
+        spec.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for ScanWindow {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScanWindow;
+    use chrono::{DateTime, Utc};
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_windows() {
+        let window: ScanWindow = "Mon-Fri 22:00-06:00 Europe/Berlin".parse().unwrap();
+        assert_eq!(window.to_string(), "Mon-Fri 22:00-06:00 Europe/Berlin");
+        assert_eq!(
+            "Sat,Mon-Wed,Sun 08:00-17:00"
+                .parse::<ScanWindow>()
+                .unwrap()
+                .to_string(),
+            "Mon-Wed,Sat-Sun 08:00-17:00 UTC"
+        );
+        assert_eq!(
+            "Fri-Mon 00:00-00:00"
+                .parse::<ScanWindow>()
+                .unwrap()
+                .to_string(),
+            "Mon,Fri-Sun 00:00-00:00 UTC"
+        );
+        assert_eq!(
+            "09:00-10:00".parse::<ScanWindow>().unwrap().to_string(),
+            "09:00-10:00 UTC"
+        );
+
+        assert!("Mon-Fri".parse::<ScanWindow>().is_err());
+        assert!("Mon-Fri 22:00".parse::<ScanWindow>().is_err());
+        assert!("Funday 22:00-06:00".parse::<ScanWindow>().is_err());
+        assert!("Mon 25:00-06:00".parse::<ScanWindow>().is_err());
+        assert!("Mon 22:00-06:00 Mars/Olympus"
+            .parse::<ScanWindow>()
+            .is_err());
+    }
+
+    #[test]
+    fn open_across_midnight() {
+        // Berlin is UTC+2 in the summer.
+        let window: ScanWindow = "Mon-Fri 22:00-06:00 Europe/Berlin".parse().unwrap();
+        // Friday 23:00 and Saturday 05:00 in Berlin.
+        assert!(window.is_open(at("2024-05-03T21:00:00Z")));
+        assert!(window.is_open(at("2024-05-04T03:00:00Z")));
+        // Saturday 07:00, Saturday 23:00 and Monday 05:00 in Berlin.
+        assert!(!window.is_open(at("2024-05-04T05:00:00Z")));
+        assert!(!window.is_open(at("2024-05-04T21:00:00Z")));
+        assert!(!window.is_open(at("2024-05-06T03:00:00Z")));
+
+        // From Saturday morning, the window opens on Monday 22:00 in Berlin.
+        assert_eq!(
+            window.next_open(at("2024-05-04T05:00:00Z")),
+            at("2024-05-06T20:00:00Z")
+        );
+        let now = at("2024-05-03T21:00:00Z");
+        assert_eq!(window.next_open(now), now);
+    }
+
+    #[test]
+    fn whole_days() {
+        let window: ScanWindow = "Sat,Sun 00:00-00:00".parse().unwrap();
+        assert!(window.is_open(at("2024-05-04T00:00:00Z")));
+        assert!(window.is_open(at("2024-05-05T23:59:00Z")));
+        assert!(!window.is_open(at("2024-05-06T00:00:00Z")));
+        assert_eq!(
+            window.next_open(at("2024-05-06T12:00:00Z")),
+            at("2024-05-11T00:00:00Z")
+        );
+    }
+}
@@ -70,21 +70,117 @@
 //! This makes it easy to run a system installed command like `nmap`, and give
 //! any kind of arguments to it.
 //!
-//! If the format is different, the script will be silently discarded and will
-//! not run. With the `Debug` option it's possible to see where it goes wrong.
+//! ## Requirements
+//!
+//! Scripts can list the binaries they run, optionally with a minimum
+//! version, which are checked before the scan, see [`requires`]:
+//!
+//! ```toml
+//! requires = ["nmap>=7.80", "python3"]
+//! ```
+//!
+//! ## Problems
+//!
+//! If the header is invalid, has no `call_format` or requires something
+//! missing, the script will be skipped and will not run. The scan starts with a list of the skipped
+//! scripts and why, with the line of the script it's on, and
+//! `rustscan scripts lint [directory]` checks every script of a directory
+//! without scanning.
+//!
+//! ## Target generators
+//!
+//! Scripts tagged `generator` don't run against scan results. They run once
+//! before the scan, and every non-empty line they print becomes a target, as
+//! if it had been passed with `--addresses`. Their `call_format` may only use
+//! `{{script}}`. Like any custom script, they only run when all of their tags
+//! are listed in `.rustscan_scripts.toml`, so `generator` has to be added to
+//! its `tags`.
+//!
+//! - `fixtures/generators/test_generator.sh`
+//!
+//! ## Phases
+//!
+//! Scripts run against every host with open ports by default, which is the
+//! `post_host` phase. Setting `phase` in a script file runs it once instead:
+//!
+//! - `phase = "pre_scan"` before the scan, for setup such as checking the VPN
+//!   is up or creating output directories. If it fails, nothing is scanned.
+//! - `phase = "post_scan"` after the scan and the outputs are written, to
+//!   summarize the results. They are written to its stdin as JSON, an array
+//!   of `{"ip": "...", "ports": [...]}` like the one plugins get.
+//!
+//! Their `call_format` may use `{{script}}`, `{{targets}}` for the
+//! addresses as given, separated with commas, and for `post_scan` scripts,
+//! `{{hosts}}` for the hosts found with open ports, separated the same way.
+//!
+//! - `fixtures/phases/test_pre_scan.sh`
+//! - `fixtures/phases/test_post_scan.sh`
+//!
+//! ## Batches
+//!
+//! Tools such as massdns or nuclei are better fed every host at once than
+//! started once per host. With `invocation = "batch"`, a script runs once
+//! after the scan with every host that had open ports, which its
+//! `call_format` gets with:
+//!
+//! - `{{ip_list}}`, the hosts separated with spaces,
+//! - `{{ip_file}}`, a temporary file listing a host per line, removed once
+//!   the script is done,
+//! - `{{port}}`, the ports open on any of them, separated with
+//!   `ports_separator`,
+//! - `{{script}}`, as usual.
+//!
+//! - `fixtures/batches/test_batch.sh`
+//!
+//! ## Grouping
+//!
+//! Per-host scripts can also be given a `group_call_format`, used with
+//! `--group-scripts` to run them once for every set of hosts with the same
+//! open ports and IP version rather than once per host. It gets
+//! `{{ip_list}}`, `{{ip_file}}` and `{{script}}` like batches, and
+//! `{{port}}` and `{{ipversion}}` like `call_format`. The default nmap
+//! script has one, so a /24 where most hosts run the same services starts
+//! nmap a handful of times rather than once per host:
+//!
+//! ```toml
+//! group_call_format = "nmap -vvv -p {{port}} -{{ipversion}} -iL {{ip_file}}"
+//! ```
+//!
+//! - `fixtures/groups/test_group.sh`
+//!
+//! With `--nmap-output-dir`, the default script also keeps nmap's own
+//! output files and merges their XML, see [`nmap_output`].
+//!
+//! ## Services
+//!
+//! With `service = "postgres"`, a script only runs against the hosts where
+//! the service probes found that service, and `{{port}}` is only the ports
+//! it was found on. The names are those of [`crate::services`]: `ftp`,
+//! `tftp`, `mysql`, `postgres`, `redis`, `mongodb` and `mssql`. The probes
+//! run whenever a script has a `service`, even without `--service-probes`.
+//!
+//! - `fixtures/services/test_postgres.sh`
 
 #![allow(clippy::module_name_repetitions)]
 
-use crate::input::ScriptsRequired;
+pub mod nmap_output;
+pub mod requires;
+
+use crate::config::Issue;
+use crate::input::{Opts, ScriptsRequired};
+use crate::{detail, lenient, output, warning};
 use anyhow::{anyhow, Result};
 use log::debug;
+use requires::{Checker, Requirement};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::io::{self, prelude::*};
-use std::net::IpAddr;
-use std::path::PathBuf;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::string::ToString;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use text_placeholder::Template;
 
 #[cfg(unix)]
@@ -92,13 +188,48 @@ use std::os::unix::process::ExitStatusExt;
 
 static DEFAULT: &str = r#"tags = ["core_approved", "RustScan", "default"]
 developer = [ "RustScan", "https://github.com/RustScan" ]
+requires = ["nmap"]
 ports_separator = ","
 call_format = "nmap -vvv -p {{port}} -{{ipversion}} {{ip}}"
+group_call_format = "nmap -vvv -p {{port}} -{{ipversion}} -iL {{ip_file}}"
 "#;
 
+/// Tag marking a script as a target generator.
+pub const GENERATOR_TAG: &str = "generator";
+
+/// When a script runs, see [Phases](self#phases).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptPhase {
+    /// Once, before the scan.
+    PreScan,
+    /// For every host with open ports.
+    #[default]
+    PostHost,
+    /// Once, after the scan.
+    PostScan,
+}
+
+/// Whether a script runs once per host or once for all of them, see
+/// [Batches](self#batches).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Invocation {
+    #[default]
+    PerHost,
+    Batch,
+}
+
+/// Numbers the host files of batches, so scripts running at once don't
+/// share one.
+static IP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Reads the scripts to run, along with the problems found in the headers
+/// of custom scripts and the requirements not met, see [`report_skipped`].
 #[cfg(not(tarpaulin_include))]
-pub fn init_scripts(scripts: &ScriptsRequired) -> Result<Vec<ScriptFile>> {
+pub fn init_scripts(scripts: &ScriptsRequired) -> Result<(Vec<ScriptFile>, Vec<Issue>)> {
     let mut scripts_to_run: Vec<ScriptFile> = Vec::new();
+    let mut issues = Vec::new();
 
     match scripts {
         ScriptsRequired::None => {}
@@ -111,16 +242,11 @@ pub fn init_scripts(scripts: &ScriptsRequired) -> Result<Vec<ScriptFile>> {
             let script_config = ScriptConfig::read_config()?;
             debug!("Script config \n{script_config:?}");
 
-            let script_dir_base = if let Some(config_directory) = &script_config.directory {
-                PathBuf::from(config_directory)
-            } else {
-                dirs::home_dir().ok_or_else(|| anyhow!("Could not infer scripts path."))?
-            };
-
-            let script_paths = find_scripts(script_dir_base)?;
+            let script_paths = find_scripts(script_config.scripts_dir()?)?;
             debug!("Scripts paths \n{script_paths:?}");
 
-            let parsed_scripts = parse_scripts(script_paths);
+            let (parsed_scripts, header_issues) = parse_scripts(script_paths);
+            issues.extend(header_issues);
             debug!("Scripts parsed \n{parsed_scripts:?}");
 
             // Only Scripts that contain all the tags found in ScriptConfig will be selected.
@@ -146,18 +272,97 @@ pub fn init_scripts(scripts: &ScriptsRequired) -> Result<Vec<ScriptFile>> {
         }
     }
 
-    Ok(scripts_to_run)
+    let (scripts_to_run, unmet) = Checker::new().check(scripts_to_run);
+    issues.extend(unmet);
+    Ok((scripts_to_run, issues))
 }
 
-pub fn parse_scripts(scripts: Vec<PathBuf>) -> Vec<ScriptFile> {
+/// How many skipped scripts are listed, the others are only counted.
+const MAX_LISTED_SKIPPED: usize = 10;
+
+/// Tells which scripts were skipped, for their header or what they require,
+/// and why, from the issues of [`init_scripts`].
+pub fn report_skipped(issues: &[Issue], opts: &Opts) {
+    let skipped: Vec<&Issue> = issues.iter().filter(|issue| issue.fatal).collect();
+    if skipped.is_empty() {
+        return;
+    }
+    let hint = match opts.scripts {
+        ScriptsRequired::Custom => ", check them with 'rustscan scripts lint'",
+        _ => "",
+    };
+    warning!(
+        format!("Skipping {} scripts{hint}:", skipped.len()),
+        opts.greppable,
+        opts.accessible
+    );
+    for issue in skipped.iter().take(MAX_LISTED_SKIPPED) {
+        detail!(format!("  {issue}"), opts.greppable, opts.accessible);
+    }
+    if skipped.len() > MAX_LISTED_SKIPPED {
+        detail!(
+            format!("  and {} more", skipped.len() - MAX_LISTED_SKIPPED),
+            opts.greppable,
+            opts.accessible
+        );
+    }
+}
+
+/// Checks the headers of the scripts in `directory`, or in the scripts
+/// directory of `.rustscan_scripts.toml`, for `rustscan scripts lint`.
+/// Prints every problem found and returns whether there were none.
+pub fn lint(directory: Option<&Path>, opts: &Opts) -> bool {
+    let find = || -> Result<(PathBuf, Vec<PathBuf>)> {
+        let directory = match directory {
+            Some(directory) => directory.to_path_buf(),
+            None => ScriptConfig::read_config()?.scripts_dir()?,
+        };
+        let paths = find_scripts(directory.clone())?;
+        Ok((directory, paths))
+    };
+    let (directory, paths) = match find() {
+        Ok(found) => found,
+        Err(e) => {
+            warning!(e.to_string(), opts.greppable, opts.accessible);
+            return false;
+        }
+    };
+
+    let found = paths.len();
+    let (parsed, mut issues) = parse_scripts(paths);
+    let (ready, unmet) = Checker::new().check(parsed);
+    issues.extend(unmet);
+    for issue in &issues {
+        if issue.fatal {
+            warning!(issue, opts.greppable, opts.accessible);
+        } else {
+            detail!(issue, opts.greppable, opts.accessible);
+        }
+    }
+    output!(
+        format!(
+            "{} of {found} scripts in {} are ready to run",
+            ready.len(),
+            directory.display()
+        ),
+        opts.greppable,
+        opts.accessible
+    );
+    issues.is_empty()
+}
+
+/// Reads the headers of `scripts`, returns the scripts whose header is
+/// valid and the problems found in the others.
+pub fn parse_scripts(scripts: Vec<PathBuf>) -> (Vec<ScriptFile>, Vec<Issue>) {
     let mut parsed_scripts: Vec<ScriptFile> = Vec::with_capacity(scripts.len());
+    let mut issues = Vec::new();
     for script in scripts {
         debug!("Parsing script {}", &script.display());
-        if let Some(script_file) = ScriptFile::new(script) {
-            parsed_scripts.push(script_file);
-        }
+        let (script_file, script_issues) = ScriptFile::read(script);
+        parsed_scripts.extend(script_file);
+        issues.extend(script_issues);
     }
-    parsed_scripts
+    (parsed_scripts, issues)
 }
 
 #[derive(Clone, Debug)]
@@ -193,6 +398,35 @@ struct ExecPartsScript {
     ipversion: String,
 }
 
+#[derive(Serialize)]
+struct ExecPartsGenerator {
+    script: String,
+}
+
+#[derive(Serialize)]
+struct ExecPartsPhase {
+    script: String,
+    targets: String,
+    hosts: String,
+}
+
+#[derive(Serialize)]
+struct ExecPartsBatch {
+    script: String,
+    ip_list: String,
+    ip_file: String,
+    port: String,
+}
+
+#[derive(Serialize)]
+struct ExecPartsGroup {
+    script: String,
+    ip_list: String,
+    ip_file: String,
+    port: String,
+    ipversion: String,
+}
+
 #[derive(Serialize)]
 struct ExecParts {
     ip: String,
@@ -270,12 +504,13 @@ impl Script {
             to_run = default_template.fill_with_struct(&exec_parts)?;
         }
         debug!("\nScript format to run {to_run}");
-        execute_script(&to_run)
+        execute_script(&to_run, "")
     }
 }
 
+/// Runs `script` in a shell with `input` on its stdin, returns its stdout.
 #[cfg(not(tarpaulin_include))]
-fn execute_script(script: &str) -> Result<String> {
+fn execute_script(script: &str, input: &str) -> Result<String> {
     debug!("\nScript arguments {script}");
 
     let (cmd, arg) = if cfg!(unix) {
@@ -284,12 +519,21 @@ fn execute_script(script: &str) -> Result<String> {
         ("cmd.exe", "/c")
     };
 
-    match Command::new(cmd)
+    let output = Command::new(cmd)
         .args([arg, script])
         .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .output()
-    {
+        .spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                // Scripts that don't read their input close it early.
+                let input = input.to_owned();
+                std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+            }
+            child.wait_with_output()
+        });
+    match output {
         Ok(output) => {
             let status = output.status;
 
@@ -320,14 +564,53 @@ fn execute_script(script: &str) -> Result<String> {
     }
 }
 
+/// Calls `run` with a temporary file listing `hosts`, one per line, which
+/// is removed afterwards.
+fn with_ip_file<T>(hosts: &[String], run: impl FnOnce(&Path) -> Result<T>) -> Result<T> {
+    let ip_file = std::env::temp_dir().join(format!(
+        "rustscan-hosts-{}-{}.txt",
+        std::process::id(),
+        IP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&ip_file, hosts.join("\n") + "\n")?;
+    let result = run(&ip_file);
+    if let Err(e) = fs::remove_file(&ip_file) {
+        debug!("Could not remove {}: {e}", ip_file.display());
+    }
+    result
+}
+
+/// Groups hosts by their open ports and IP version, for
+/// [`ScriptFile::run_group`]. The groups come in the order of their first
+/// host.
+pub fn group_hosts<'a>(
+    ports_per_ip: impl IntoIterator<Item = (IpAddr, &'a [u16])>,
+) -> Vec<(Vec<IpAddr>, Vec<u16>)> {
+    let mut groups: Vec<(Vec<IpAddr>, Vec<u16>)> = Vec::new();
+    for (ip, ports) in ports_per_ip {
+        let mut ports = ports.to_vec();
+        ports.sort_unstable();
+        match groups.iter_mut().find(|(hosts, group_ports)| {
+            *group_ports == ports && hosts[0].is_ipv6() == ip.is_ipv6()
+        }) {
+            Some((hosts, _)) => hosts.push(ip),
+            None => groups.push((vec![ip], ports)),
+        }
+    }
+    groups
+}
+
 pub fn find_scripts(path: PathBuf) -> Result<Vec<PathBuf>> {
     if path.is_dir() {
         debug!("Scripts folder found {}", &path.display());
         let mut files_vec: Vec<PathBuf> = Vec::new();
         for entry in fs::read_dir(path)? {
             let entry = entry?;
-            files_vec.push(entry.path());
+            if entry.path().is_file() {
+                files_vec.push(entry.path());
+            }
         }
+        files_vec.sort();
         Ok(files_vec)
     } else {
         Err(anyhow!("Can't find scripts folder {}", path.display()))
@@ -342,42 +625,256 @@ pub struct ScriptFile {
     pub port: Option<String>,
     pub ports_separator: Option<String>,
     pub call_format: Option<String>,
+    #[serde(default)]
+    pub phase: ScriptPhase,
+    #[serde(default)]
+    pub invocation: Invocation,
+    /// Runs the script only where the service probes found this service.
+    #[serde(default)]
+    pub service: Option<String>,
+    /// Binaries the script needs, see [`requires`].
+    #[serde(default)]
+    pub requires: Vec<Requirement>,
+    /// Runs the script on hosts with the same open ports at once, see
+    /// [Grouping](self#grouping).
+    #[serde(default)]
+    pub group_call_format: Option<String>,
 }
 
 impl ScriptFile {
-    fn new(script: PathBuf) -> Option<ScriptFile> {
-        let real_path = script.clone();
-        let mut lines_buf = String::new();
-        if let Ok(file) = File::open(script) {
-            for mut line in io::BufReader::new(file).lines().skip(1).flatten() {
-                if line.starts_with('#') {
-                    line.retain(|c| c != '#');
-                    line = line.trim().to_string();
-                    line.push('\n');
-                    lines_buf.push_str(&line);
-                } else {
+    /// Reads the header of the script at `path`, see [`ScriptFile::parse`].
+    pub fn read(path: PathBuf) -> (Option<ScriptFile>, Vec<Issue>) {
+        match File::open(&path) {
+            Ok(file) => Self::parse(io::BufReader::new(file), path),
+            Err(e) => {
+                let issue = Issue {
+                    file: path,
+                    line: None,
+                    key: String::new(),
+                    message: format!("could not be read: {e}"),
+                    suggestion: None,
+                    fatal: true,
+                };
+                (None, vec![issue])
+            }
+        }
+    }
+
+    /// Parses the header of a script: the comment lines after its first
+    /// line, up to the first line that isn't a comment, hold TOML. Returns
+    /// the script, unless its header is invalid, and every problem found
+    /// with the line of the script it's on.
+    pub fn parse(script: impl BufRead, path: PathBuf) -> (Option<ScriptFile>, Vec<Issue>) {
+        let mut header = String::new();
+        let mut issues = Vec::new();
+        // The shebang, whatever it holds.
+        for line in lenient::lines(script, &path).skip(1) {
+            match line {
+                Ok(line) if line.text.starts_with('#') => {
+                    header.push_str(line.text.replace('#', "").trim());
+                    header.push('\n');
+                }
+                Ok(_) => break,
+                Err(issue) => {
+                    issues.push(issue);
                     break;
                 }
             }
-        } else {
-            debug!("Failed to read file: {}", &real_path.display());
-            return None;
         }
-        debug!("ScriptFile {} lines\n{}", &real_path.display(), &lines_buf);
+        debug!("ScriptFile {} lines\n{}", path.display(), header);
 
-        match toml::from_str::<ScriptFile>(&lines_buf) {
-            Ok(mut parsed) => {
-                debug!("Parsed ScriptFile{} \n{:?}", &real_path.display(), &parsed);
-                parsed.path = Some(real_path);
-                // parsed_scripts.push(parsed);
-                Some(parsed)
-            }
-            Err(e) => {
-                debug!("Failed to parse ScriptFile headers {e}");
+        let (parsed, header_issues) = crate::config::parse::<ScriptFile>(&header, &path);
+        // The header starts on the second line.
+        issues.extend(header_issues.into_iter().map(|mut issue| {
+            issue.line = issue.line.map(|line| line + 1);
+            issue
+        }));
+        for issue in &issues {
+            debug!("Script header {issue}");
+        }
+        let parsed = match parsed {
+            Some(parsed) if parsed.call_format.is_none() => {
+                issues.push(Issue {
+                    file: path,
+                    line: None,
+                    key: String::from("call_format"),
+                    message: String::from("is missing"),
+                    suggestion: None,
+                    fatal: true,
+                });
                 None
             }
+            Some(mut parsed) => {
+                parsed.path = Some(path);
+                Some(parsed)
+            }
+            None => None,
+        };
+        (parsed, issues)
+    }
+
+    /// The open `ports` of `ip` the script runs against: all of them, or
+    /// those `services` says run its `service`.
+    pub fn ports_for(
+        &self,
+        ip: IpAddr,
+        ports: &[u16],
+        services: &BTreeMap<SocketAddr, String>,
+    ) -> Vec<u16> {
+        match &self.service {
+            Some(wanted) => ports
+                .iter()
+                .copied()
+                .filter(|port| services.get(&SocketAddr::new(ip, *port)) == Some(wanted))
+                .collect(),
+            None => ports.to_vec(),
         }
     }
+
+    /// Whether the script is tagged as a target generator.
+    pub fn is_generator(&self) -> bool {
+        self.tags
+            .as_ref()
+            .is_some_and(|tags| tags.iter().any(|tag| tag == GENERATOR_TAG))
+    }
+
+    /// Runs a generator script and returns the targets it printed, one per
+    /// non-empty line.
+    pub fn generate_targets(&self) -> Result<Vec<String>> {
+        let call_format = self
+            .call_format
+            .as_ref()
+            .ok_or_else(|| anyhow!("Failed to parse execution format."))?;
+        let script = self
+            .path
+            .as_ref()
+            .and_then(|path| path.to_str())
+            .unwrap_or_default()
+            .to_string();
+        let to_run = Template::new(call_format).fill_with_struct(&ExecPartsGenerator { script })?;
+        debug!("\nGenerator format to run {to_run}");
+
+        Ok(execute_script(&to_run, "")?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(ToString::to_string)
+            .collect())
+    }
+
+    /// Runs a `pre_scan` script against the `targets` given, returns its
+    /// output.
+    pub fn run_pre_scan(&self, targets: &[String]) -> Result<String> {
+        self.run_phase(targets, &[], "")
+    }
+
+    /// Runs a `post_scan` script with the `results` JSON on its stdin,
+    /// returns its output.
+    pub fn run_post_scan(
+        &self,
+        targets: &[String],
+        hosts: &[IpAddr],
+        results: &str,
+    ) -> Result<String> {
+        self.run_phase(targets, hosts, results)
+    }
+
+    /// Runs a `batch` script once with every host of `open_ports`, returns
+    /// its output.
+    pub fn run_batch(&self, open_ports: &BTreeMap<IpAddr, Vec<u16>>) -> Result<String> {
+        let call_format = self
+            .call_format
+            .as_ref()
+            .ok_or_else(|| anyhow!("Failed to parse execution format."))?;
+        let hosts: Vec<String> = open_ports.keys().map(ToString::to_string).collect();
+        let mut ports: Vec<u16> = open_ports.values().flatten().copied().collect();
+        ports.sort_unstable();
+        ports.dedup();
+        let port = self.port.clone().unwrap_or_else(|| {
+            ports
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+                .join(self.ports_separator.as_deref().unwrap_or(","))
+        });
+        with_ip_file(&hosts, |ip_file| {
+            let parts = ExecPartsBatch {
+                script: self.script_path(),
+                ip_list: hosts.join(" "),
+                ip_file: ip_file.display().to_string(),
+                port,
+            };
+            let to_run = Template::new(call_format).fill_with_struct(&parts)?;
+            debug!("\nBatch script format to run {to_run}");
+            execute_script(&to_run, "")
+        })
+    }
+
+    /// Runs the script once on `hosts`, which share their open `ports` and
+    /// IP version, with its `group_call_format`, see
+    /// [Grouping](self#grouping).
+    pub fn run_group(&self, hosts: &[IpAddr], ports: &[u16]) -> Result<String> {
+        let call_format = self
+            .group_call_format
+            .as_ref()
+            .ok_or_else(|| anyhow!("The script can't run on groups of hosts."))?;
+        let ipversion = match hosts.first() {
+            Some(IpAddr::V6(_)) => "6",
+            _ => "4",
+        };
+        let hosts: Vec<String> = hosts.iter().map(ToString::to_string).collect();
+        let port = self.port.clone().unwrap_or_else(|| {
+            ports
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+                .join(self.ports_separator.as_deref().unwrap_or(","))
+        });
+        with_ip_file(&hosts, |ip_file| {
+            let parts = ExecPartsGroup {
+                script: self.script_path(),
+                ip_list: hosts.join(" "),
+                ip_file: ip_file.display().to_string(),
+                port,
+                ipversion: ipversion.to_owned(),
+            };
+            let to_run = Template::new(call_format).fill_with_struct(&parts)?;
+            debug!("\nGroup script format to run {to_run}");
+            execute_script(&to_run, "")
+        })
+    }
+
+    fn script_path(&self) -> String {
+        self.path
+            .as_ref()
+            .and_then(|path| path.to_str())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn run_phase(&self, targets: &[String], hosts: &[IpAddr], input: &str) -> Result<String> {
+        let call_format = self
+            .call_format
+            .as_ref()
+            .ok_or_else(|| anyhow!("Failed to parse execution format."))?;
+        let parts = ExecPartsPhase {
+            script: self
+                .path
+                .as_ref()
+                .and_then(|path| path.to_str())
+                .unwrap_or_default()
+                .to_string(),
+            targets: targets.join(","),
+            hosts: hosts
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+                .join(","),
+        };
+        let to_run = Template::new(call_format).fill_with_struct(&parts)?;
+        debug!("\n{:?} script format to run {to_run}", self.phase);
+        execute_script(&to_run, input)
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -390,15 +887,33 @@ pub struct ScriptConfig {
 
 #[cfg(not(tarpaulin_include))]
 impl ScriptConfig {
-    pub fn read_config() -> Result<ScriptConfig> {
+    /// Path of the script configuration file in the user's home dir.
+    pub fn config_path() -> Result<PathBuf> {
         let Some(mut home_dir) = dirs::home_dir() else {
             return Err(anyhow!("Could not infer ScriptConfig path."));
         };
         home_dir.push(".rustscan_scripts.toml");
+        Ok(home_dir)
+    }
 
-        let content = fs::read_to_string(home_dir)?;
-        let config = toml::from_str::<ScriptConfig>(&content)?;
-        Ok(config)
+    /// Where scripts are looked for: `directory`, or the home directory.
+    pub fn scripts_dir(&self) -> Result<PathBuf> {
+        match &self.directory {
+            Some(directory) => Ok(PathBuf::from(directory)),
+            None => dirs::home_dir().ok_or_else(|| anyhow!("Could not infer scripts path.")),
+        }
+    }
+
+    pub fn read_config() -> Result<ScriptConfig> {
+        let config_path = Self::config_path()?;
+        let (config, issues) = crate::config::parse_file::<ScriptConfig>(&config_path);
+        for issue in issues.iter().filter(|issue| !issue.fatal) {
+            debug!("Ignoring {issue}");
+        }
+        config.ok_or_else(|| {
+            let errors: Vec<String> = issues.iter().map(ToString::to_string).collect();
+            anyhow!(errors.join("\n"))
+        })
     }
 }
 
@@ -423,8 +938,11 @@ mod tests {
     #[test]
     fn find_and_parse_scripts() {
         let scripts = find_scripts("fixtures/.rustscan_scripts".into()).unwrap();
-        let scripts = parse_scripts(scripts);
+        let (scripts, issues) = parse_scripts(scripts);
         assert_eq!(scripts.len(), 4);
+        // An unknown key in test_script.py, and test_script_invalid_headers.txt.
+        assert_eq!(issues.len(), 2, "{issues:?}");
+        assert_eq!(issues.iter().filter(|issue| issue.fatal).count(), 1);
     }
 
     #[test]
@@ -434,17 +952,74 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn open_script_file_invalid_headers() {
-        ScriptFile::new("fixtures/.rustscan_scripts/test_script_invalid_headers.txt".into())
-            .unwrap();
+        let (script_f, issues) =
+            ScriptFile::read("fixtures/.rustscan_scripts/test_script_invalid_headers.txt".into());
+        assert!(script_f.is_none());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, Some(2));
+        assert_eq!(issues[0].key, "tags");
+    }
+
+    #[test]
+    fn parse_hostile_headers() {
+        let header = "\u{feff}#!/bin/sh\r\n#tags = [\"example\"]\r\n# call_format = \"{{ip}}\"\r\n";
+        let (script_f, issues) = ScriptFile::parse(header.as_bytes(), "crlf.sh".into());
+        assert!(issues.is_empty(), "{:?}", issues);
+        let script_f = script_f.unwrap();
+        assert_eq!(script_f.tags, Some(vec!["example".to_owned()]));
+        assert_eq!(script_f.call_format.as_deref(), Some("{{ip}}"));
+
+        // The header ends at a line that can't be read.
+        let mut binary = b"#!/bin/sh\n#call_format = \"a\"\n#\xff\xfe\n#port = \"80\"\n".to_vec();
+        let (script_f, issues) = ScriptFile::parse(&binary[..], "binary.sh".into());
+        assert_eq!(script_f.unwrap().port, None);
+        assert_eq!(
+            issues[0].to_string(),
+            "binary.sh:3: a line that isn't valid UTF-8, from byte 2"
+        );
+
+        binary.truncate(10);
+        binary.extend(vec![b'#'; lenient::MAX_LINE_LENGTH + 1]);
+        let (script_f, issues) = ScriptFile::parse(&binary[..], "long.sh".into());
+        assert!(script_f.is_none());
+        assert_eq!(issues[0].line, Some(2));
+        assert_eq!(issues[1].to_string(), "long.sh: `call_format` is missing");
+    }
+
+    #[test]
+    fn lint_scripts_directory() {
+        let dir = std::env::temp_dir().join(format!("rustscan-lint-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(
+            dir.join("good.sh"),
+            "#!/bin/sh\n#tags = [\"a\"]\n#call_format = \"{{ip}}\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("bad.sh"), "#!/bin/sh\n#tags = [\"a\"]\n").unwrap();
+        let opts = Opts {
+            greppable: true,
+            ..Opts::default()
+        };
+
+        let (scripts, issues) = parse_scripts(find_scripts(dir.clone()).unwrap());
+        assert_eq!(scripts.len(), 1);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].file, dir.join("bad.sh"));
+        assert!(!lint(Some(&dir), &opts));
+
+        fs::remove_file(dir.join("bad.sh")).unwrap();
+        assert!(lint(Some(&dir), &opts));
+        assert!(!lint(Some(&dir.join("missing")), &opts));
+        fs::remove_dir_all(dir).unwrap();
     }
 
     #[test]
     #[should_panic]
     fn open_script_file_invalid_call_format() {
-        let mut script_f =
-            ScriptFile::new("fixtures/.rustscan_scripts/test_script.txt".into()).unwrap();
+        let mut script_f = ScriptFile::read("fixtures/.rustscan_scripts/test_script.txt".into())
+            .0
+            .unwrap();
         script_f.call_format = Some("qwertyuiop".to_string());
         let script: Script = into_script(script_f);
         let _output = script.run().unwrap();
@@ -453,8 +1028,9 @@ mod tests {
     #[test]
     #[should_panic]
     fn open_script_file_missing_call_format() {
-        let mut script_f =
-            ScriptFile::new("fixtures/.rustscan_scripts/test_script.txt".into()).unwrap();
+        let mut script_f = ScriptFile::read("fixtures/.rustscan_scripts/test_script.txt".into())
+            .0
+            .unwrap();
         script_f.call_format = None;
         let script: Script = into_script(script_f);
         let _output = script.run().unwrap();
@@ -463,13 +1039,14 @@ mod tests {
     #[test]
     #[should_panic]
     fn open_nonexisting_script_file() {
-        ScriptFile::new("qwertyuiop.txt".into()).unwrap();
+        ScriptFile::read("qwertyuiop.txt".into()).0.unwrap();
     }
 
     #[test]
     fn parse_txt_script() {
-        let script_f =
-            ScriptFile::new("fixtures/.rustscan_scripts/test_script.txt".into()).unwrap();
+        let script_f = ScriptFile::read("fixtures/.rustscan_scripts/test_script.txt".into())
+            .0
+            .unwrap();
         assert_eq!(
             script_f.tags,
             Some(vec!["core_approved".to_string(), "example".to_string()])
@@ -491,7 +1068,9 @@ mod tests {
     #[test]
     #[cfg(unix)]
     fn run_bash_script() {
-        let script_f = ScriptFile::new("fixtures/.rustscan_scripts/test_script.sh".into()).unwrap();
+        let script_f = ScriptFile::read("fixtures/.rustscan_scripts/test_script.sh".into())
+            .0
+            .unwrap();
         let script: Script = into_script(script_f);
         let output = script.run().unwrap();
         // output has a newline at the end by default, .trim() trims it
@@ -500,7 +1079,9 @@ mod tests {
 
     #[test]
     fn run_python_script() {
-        let script_f = ScriptFile::new("fixtures/.rustscan_scripts/test_script.py".into()).unwrap();
+        let script_f = ScriptFile::read("fixtures/.rustscan_scripts/test_script.py".into())
+            .0
+            .unwrap();
         let script: Script = into_script(script_f);
         let output = script.run().unwrap();
         // output has a newline at the end by default, .trim() trims it
@@ -513,13 +1094,152 @@ mod tests {
     #[test]
     #[cfg(unix)]
     fn run_perl_script() {
-        let script_f = ScriptFile::new("fixtures/.rustscan_scripts/test_script.pl".into()).unwrap();
+        let script_f = ScriptFile::read("fixtures/.rustscan_scripts/test_script.pl".into())
+            .0
+            .unwrap();
         let script: Script = into_script(script_f);
         let output = script.run().unwrap();
         // output has a newline at the end by default, .trim() trims it
         assert_eq!(output.trim(), "Total args passed to fixtures/.rustscan_scripts/test_script.pl : 2\nArg # 1 : 127.0.0.1\nArg # 2 : 80,8080");
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn run_generator_script() {
+        let script_f = ScriptFile::read("fixtures/generators/test_generator.sh".into())
+            .0
+            .unwrap();
+        assert!(script_f.is_generator());
+        assert_eq!(
+            script_f.generate_targets().unwrap(),
+            vec!["127.0.0.1", "192.168.0.0/30", "localhost"]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_phase_scripts() {
+        let pre_scan = ScriptFile::read("fixtures/phases/test_pre_scan.sh".into())
+            .0
+            .unwrap();
+        assert_eq!(pre_scan.phase, ScriptPhase::PreScan);
+        let targets = vec!["10.0.0.0/30".to_owned(), "example.org".to_owned()];
+        assert_eq!(
+            pre_scan.run_pre_scan(&targets).unwrap().trim(),
+            "Scanning 10.0.0.0/30,example.org"
+        );
+
+        let post_scan = ScriptFile::read("fixtures/phases/test_post_scan.sh".into())
+            .0
+            .unwrap();
+        assert_eq!(post_scan.phase, ScriptPhase::PostScan);
+        let hosts = ["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+        let results = r#"[{"ip":"10.0.0.1","ports":[22]},{"ip":"10.0.0.2","ports":[80]}]"#;
+        assert_eq!(
+            post_scan
+                .run_post_scan(&targets, &hosts, results)
+                .unwrap()
+                .trim(),
+            format!("10.0.0.1,10.0.0.2 of 10.0.0.0/30,example.org\n{results}")
+        );
+    }
+
+    #[test]
+    fn scripts_run_per_host_by_default() {
+        let script_f = ScriptFile::read("fixtures/.rustscan_scripts/test_script.txt".into())
+            .0
+            .unwrap();
+        assert_eq!(script_f.phase, ScriptPhase::PostHost);
+        assert_eq!(script_f.invocation, Invocation::PerHost);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_batch_script() {
+        let script_f = ScriptFile::read("fixtures/batches/test_batch.sh".into())
+            .0
+            .unwrap();
+        assert_eq!(script_f.invocation, Invocation::Batch);
+        let mut open_ports: BTreeMap<IpAddr, Vec<u16>> = BTreeMap::new();
+        open_ports.insert("10.0.0.2".parse().unwrap(), vec![443, 80]);
+        open_ports.insert("10.0.0.1".parse().unwrap(), vec![22, 80]);
+        let output = script_f.run_batch(&open_ports).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "hosts 10.0.0.1 10.0.0.2");
+        assert_eq!(lines[1], "ports 22,80,443");
+        assert_eq!(&lines[2..], ["10.0.0.1", "10.0.0.2"]);
+    }
+
+    #[test]
+    fn group_hosts_by_open_ports() {
+        let ports: Vec<(IpAddr, Vec<u16>)> = vec![
+            ("10.0.0.1".parse().unwrap(), vec![80, 22]),
+            ("10.0.0.2".parse().unwrap(), vec![443]),
+            ("::1".parse().unwrap(), vec![22, 80]),
+            ("10.0.0.3".parse().unwrap(), vec![22, 80]),
+        ];
+        let groups = group_hosts(ports.iter().map(|(ip, ports)| (*ip, ports.as_slice())));
+        let groups: Vec<(Vec<String>, Vec<u16>)> = groups
+            .into_iter()
+            .map(|(hosts, ports)| (hosts.iter().map(ToString::to_string).collect(), ports))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                (
+                    vec!["10.0.0.1".to_owned(), "10.0.0.3".to_owned()],
+                    vec![22, 80]
+                ),
+                (vec!["10.0.0.2".to_owned()], vec![443]),
+                (vec!["::1".to_owned()], vec![22, 80]),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_group_script() {
+        let script_f = ScriptFile::read("fixtures/groups/test_group.sh".into())
+            .0
+            .unwrap();
+        let hosts: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap(), "10.0.0.3".parse().unwrap()];
+        let output = script_f.run_group(&hosts, &[22, 80]).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines, ["ports 22,80 ipv4", "10.0.0.1", "10.0.0.3"]);
+
+        let per_host = ScriptFile::read("fixtures/batches/test_batch.sh".into())
+            .0
+            .unwrap();
+        assert!(per_host.run_group(&hosts, &[22]).is_err());
+    }
+
+    #[test]
+    fn service_scripts_run_where_the_service_was_found() {
+        let script_f = ScriptFile::read("fixtures/services/test_postgres.sh".into())
+            .0
+            .unwrap();
+        assert_eq!(script_f.service.as_deref(), Some("postgres"));
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let mut services = BTreeMap::new();
+        services.insert(SocketAddr::new(ip, 5432), "postgres".to_owned());
+        services.insert(SocketAddr::new(ip, 6379), "redis".to_owned());
+        assert_eq!(script_f.ports_for(ip, &[22, 5432, 6379], &services), [5432]);
+        assert!(script_f.ports_for(ip, &[22, 6379], &services).is_empty());
+
+        let other = ScriptFile::read("fixtures/batches/test_batch.sh".into())
+            .0
+            .unwrap();
+        assert_eq!(other.ports_for(ip, &[22, 6379], &services), [22, 6379]);
+    }
+
+    #[test]
+    fn scan_scripts_are_not_generators() {
+        let script_f = ScriptFile::read("fixtures/.rustscan_scripts/test_script.txt".into())
+            .0
+            .unwrap();
+        assert!(!script_f.is_generator());
+    }
+
     #[test]
     fn test_custom_directory_config() {
         // Create test config
@@ -0,0 +1,214 @@
+//! `--nmap-output-dir`: keeping what the default nmap script finds.
+//!
+//! Every nmap run the default script starts writes its `-oA` files to the
+//! directory, `hosts/<ip>.{nmap,gnmap,xml}` for a host or
+//! `groups/group-<n>.*` for a group of them with `--group-scripts`. Once the
+//! scan is done, their XML is merged into `merged.xml`, a single nmap
+//! document with every host, which tools reading nmap XML take as is:
+//!
+//! ```text
+//! rustscan -a 192.168.1.0/24 --nmap-output-dir nmap-results -- -sV
+//! ```
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+/// Where the merged XML goes in the directory.
+pub const MERGED_FILE: &str = "merged.xml";
+
+/// The output directory of a scan, and the runs written to it so far.
+#[derive(Debug)]
+pub struct NmapOutput {
+    dir: PathBuf,
+    /// The `-oA` base names handed out, without extension.
+    written: Vec<PathBuf>,
+    groups: usize,
+}
+
+impl NmapOutput {
+    /// Creates `dir` and its `hosts` and `groups` directories.
+    pub fn create(dir: &Path) -> Result<Self> {
+        for sub in ["hosts", "groups"] {
+            fs::create_dir_all(dir.join(sub))
+                .map_err(|e| anyhow!("Could not create {}: {e}", dir.join(sub).display()))?;
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            written: Vec::new(),
+            groups: 0,
+        })
+    }
+
+    /// The arguments that have nmap write the files of `ip`.
+    pub fn host_args(&mut self, ip: IpAddr) -> String {
+        // Colons aren't allowed in file names on Windows.
+        let name = ip.to_string().replace(':', "_");
+        self.args(self.dir.join("hosts").join(name))
+    }
+
+    /// The arguments that have nmap write the files of the next group.
+    pub fn group_args(&mut self) -> String {
+        self.groups += 1;
+        self.args(
+            self.dir
+                .join("groups")
+                .join(format!("group-{}", self.groups)),
+        )
+    }
+
+    fn args(&mut self, base: PathBuf) -> String {
+        let args = format!("-oA {}", quote(&base.display().to_string()));
+        self.written.push(base);
+        args
+    }
+
+    /// Merges the XML of the runs into [`MERGED_FILE`] and returns its path,
+    /// or `None` if no run wrote any. Runs that failed before writing theirs
+    /// are left out.
+    pub fn merge(&self) -> Result<Option<PathBuf>> {
+        let documents: Vec<String> = self
+            .written
+            .iter()
+            .filter_map(|base| fs::read_to_string(base.with_extension("xml")).ok())
+            .collect();
+        let Some(merged) = merge_xml(&documents) else {
+            return Ok(None);
+        };
+        let path = self.dir.join(MERGED_FILE);
+        fs::write(&path, merged).map_err(|e| anyhow!("Could not write {}: {e}", path.display()))?;
+        Ok(Some(path))
+    }
+}
+
+/// Quotes `text` as a single argument for the shell scripts run in.
+fn quote(text: &str) -> String {
+    if cfg!(windows) {
+        format!("\"{text}\"")
+    } else {
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
+}
+
+/// The `<host>` elements of an nmap XML document. `<hosthint>` isn't one.
+fn hosts(document: &str) -> Vec<&str> {
+    let mut hosts = Vec::new();
+    let mut rest = document;
+    while let Some(start) = rest.find("<host ").or_else(|| rest.find("<host>")) {
+        let Some(length) = rest[start..].find("</host>") else {
+            break;
+        };
+        let end = start + length + "</host>".len();
+        hosts.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+    hosts
+}
+
+/// The value of `attribute` in the first `element` of `document`.
+fn attribute<'a>(document: &'a str, element: &str, attribute: &str) -> Option<&'a str> {
+    let start = document.find(&format!("<{element} "))?;
+    let tag = &document[start..start + document[start..].find('>')?];
+    let needle = format!(" {attribute}=\"");
+    let value = &tag[tag.find(&needle)? + needle.len()..];
+    Some(&value[..value.find('"')?])
+}
+
+/// Merges nmap XML documents into one: the prologue and `<nmaprun>` of the
+/// first, the hosts of all of them, and run stats counting them all.
+/// Documents without `<nmaprun>`, cut short by a failed run, are skipped.
+pub fn merge_xml(documents: &[String]) -> Option<String> {
+    let documents: Vec<&String> = documents
+        .iter()
+        .filter(|document| document.contains("<nmaprun"))
+        .collect();
+    let first = documents.first()?;
+    let run_start = first.find("<nmaprun")?;
+    let run_end = run_start + first[run_start..].find('>')? + 1;
+
+    let mut merged = first[..run_end].to_owned();
+    merged.push('\n');
+    let mut up = 0;
+    let mut total = 0;
+    for document in &documents {
+        for host in hosts(document) {
+            total += 1;
+            if attribute(host, "status", "state") == Some("up") {
+                up += 1;
+            }
+            merged.push_str(host);
+            merged.push('\n');
+        }
+    }
+    let finished = documents
+        .iter()
+        .filter_map(|document| attribute(document, "finished", "time")?.parse::<u64>().ok())
+        .max()
+        .unwrap_or_default();
+    merged.push_str(&format!(
+        "<runstats><finished time=\"{finished}\" summary=\"Merged from {} nmap runs by RustScan\" exit=\"success\"/>\
+         <hosts up=\"{up}\" down=\"{}\" total=\"{total}\"/></runstats>\n</nmaprun>\n",
+        documents.len(),
+        total - up
+    ));
+    Some(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_xml, NmapOutput, MERGED_FILE};
+    use std::fs;
+
+    fn run(ip: &str, state: &str, finished: u64) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE nmaprun>\n\
+             <nmaprun scanner=\"nmap\" args=\"nmap {ip}\" start=\"1\">\n\
+             <hosthint><status state=\"up\"/><address addr=\"{ip}\"/></hosthint>\n\
+             <host starttime=\"1\"><status state=\"{state}\" reason=\"syn-ack\"/>\
+             <address addr=\"{ip}\" addrtype=\"ipv4\"/></host>\n\
+             <runstats><finished time=\"{finished}\"/><hosts up=\"1\" down=\"0\" total=\"1\"/></runstats>\n\
+             </nmaprun>\n"
+        )
+    }
+
+    #[test]
+    fn merges_hosts_of_every_run() {
+        let merged = merge_xml(&[
+            run("10.0.0.1", "up", 20),
+            String::from("<?xml version=\"1.0\"?>\n"),
+            run("10.0.0.2", "down", 30),
+        ])
+        .unwrap();
+        assert!(merged.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE nmaprun>\n<nmaprun scanner=\"nmap\" args=\"nmap 10.0.0.1\" start=\"1\">\n<host "));
+        assert_eq!(merged.matches("<host ").count(), 2);
+        assert!(!merged.contains("hosthint"));
+        assert!(merged.contains("addr=\"10.0.0.2\""));
+        assert!(merged
+            .contains("<finished time=\"30\" summary=\"Merged from 2 nmap runs by RustScan\""));
+        assert!(
+            merged.ends_with("<hosts up=\"1\" down=\"1\" total=\"2\"/></runstats>\n</nmaprun>\n")
+        );
+        assert_eq!(merge_xml(&[]), None);
+    }
+
+    #[test]
+    fn writes_runs_to_the_directory() {
+        let dir = std::env::temp_dir().join(format!("rustscan-nmap-{}", std::process::id()));
+        let mut output = NmapOutput::create(&dir).unwrap();
+        let args = output.host_args("::1".parse().unwrap());
+        assert!(args.starts_with("-oA "));
+        assert!(args.contains("__1"));
+        assert!(output.group_args().contains("group-1"));
+        assert_eq!(output.merge().unwrap(), None);
+
+        fs::write(
+            dir.join("groups").join("group-1.xml"),
+            run("10.0.0.1", "up", 1),
+        )
+        .unwrap();
+        let merged = output.merge().unwrap().unwrap();
+        assert_eq!(merged, dir.join(MERGED_FILE));
+        assert!(fs::read_to_string(merged).unwrap().contains("10.0.0.1"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,302 @@
+//! The `requires` header of scripts: the binaries a script runs, checked
+//! before the scan rather than found missing when the script fails on the
+//! first host.
+//!
+//! ```toml
+//! requires = ["nmap>=7.80", "python3"]
+//! ```
+//!
+//! A binary has to be in `PATH`, or at the path given. With `>=`, it's run
+//! once with `--version` and the first version number it prints has to be
+//! at least the one given; binaries that print none are taken as new enough.
+use super::ScriptFile;
+use crate::config::Issue;
+use log::debug;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long `--version` may take.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A binary a script needs, `nmap>=7.80` or `python3`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Requirement {
+    pub binary: String,
+    pub min_version: Option<String>,
+}
+
+impl TryFrom<String> for Requirement {
+    type Error = String;
+
+    fn try_from(requirement: String) -> Result<Self, Self::Error> {
+        let (binary, min_version) = match requirement.split_once(">=") {
+            Some((binary, version)) => (binary.trim(), Some(version.trim())),
+            None => (requirement.trim(), None),
+        };
+        let invalid = || format!("{requirement:?} isn't a binary, optionally with >=version");
+        if binary.is_empty() || binary.contains(['<', '>', '=']) {
+            return Err(invalid());
+        }
+        if let Some(version) = min_version {
+            if numbers(version).is_empty() {
+                return Err(invalid());
+            }
+        }
+        Ok(Self {
+            binary: binary.to_owned(),
+            min_version: min_version.map(ToOwned::to_owned),
+        })
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.binary)?;
+        if let Some(version) = &self.min_version {
+            write!(f, ">={version}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The numbers in `version`, `7.94SVN` being `[7, 94]`.
+fn numbers(version: &str) -> Vec<u64> {
+    version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|number| number.parse().ok())
+        .collect()
+}
+
+/// The first version number in the output of `--version`, like `7.94SVN`
+/// in `Nmap version 7.94SVN ( https://nmap.org )`.
+fn find_version(output: &str) -> Option<&str> {
+    output
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+        .map(|word| word.trim_start_matches('v'))
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()) && word.contains('.'))
+}
+
+/// Where `binary` is: itself if it's a path, otherwise the first match in
+/// `PATH`.
+fn locate(binary: &str) -> Option<PathBuf> {
+    let candidates: Vec<PathBuf> = if binary.contains(std::path::is_separator) {
+        vec![PathBuf::from(binary)]
+    } else {
+        env::var_os("PATH")
+            .map(|path| {
+                env::split_paths(&path)
+                    .map(|dir| dir.join(binary))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    candidates.into_iter().find_map(|candidate| {
+        if cfg!(windows) && candidate.extension().is_none() {
+            let exe = candidate.with_extension("exe");
+            if is_executable(&exe) {
+                return Some(exe);
+            }
+        }
+        is_executable(&candidate).then_some(candidate)
+    })
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Runs `binary --version` and returns what it printed, unless it takes
+/// longer than [`VERSION_TIMEOUT`].
+fn version_output(binary: &Path) -> Option<String> {
+    let mut child = Command::new(binary)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let started = Instant::now();
+    while child.try_wait().ok()?.is_none() {
+        if started.elapsed() > VERSION_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let output = child.wait_with_output().ok()?;
+    Some(format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    ))
+}
+
+/// Checks requirements, looking up and running every binary once however
+/// many scripts need it.
+#[derive(Debug, Default)]
+pub struct Checker {
+    /// The version of every binary run with `--version` so far.
+    versions: HashMap<PathBuf, Option<String>>,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Why `requirement` isn't met, or `None` if it is.
+    pub fn unmet(&mut self, requirement: &Requirement) -> Option<String> {
+        let Some(path) = locate(&requirement.binary) else {
+            return Some(format!(
+                "{} is not installed or not in PATH",
+                requirement.binary
+            ));
+        };
+        let wanted = requirement.min_version.as_deref()?;
+        let version = self
+            .versions
+            .entry(path)
+            .or_insert_with_key(|path| {
+                version_output(path).and_then(|output| find_version(&output).map(ToOwned::to_owned))
+            })
+            .as_deref();
+        match version {
+            Some(version) if numbers(version) < numbers(wanted) => Some(format!(
+                "{} {version} is older than {wanted}",
+                requirement.binary
+            )),
+            Some(_) => None,
+            None => {
+                debug!("Could not tell the version of {}", requirement.binary);
+                None
+            }
+        }
+    }
+
+    /// Splits `scripts` into those whose requirements are met and an issue
+    /// for every requirement of the others that isn't.
+    pub fn check(&mut self, scripts: Vec<ScriptFile>) -> (Vec<ScriptFile>, Vec<Issue>) {
+        let mut ready = Vec::with_capacity(scripts.len());
+        let mut issues = Vec::new();
+        for script in scripts {
+            let unmet: Vec<Issue> = script
+                .requires
+                .iter()
+                .filter_map(|requirement| {
+                    let reason = self.unmet(requirement)?;
+                    Some(Issue {
+                        file: script
+                            .path
+                            .clone()
+                            .unwrap_or_else(|| PathBuf::from("default script")),
+                        line: None,
+                        key: String::from("requires"),
+                        message: format!("is not met, {reason}"),
+                        suggestion: None,
+                        fatal: true,
+                    })
+                })
+                .collect();
+            if unmet.is_empty() {
+                ready.push(script);
+            }
+            issues.extend(unmet);
+        }
+        (ready, issues)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_version, locate, Checker, Requirement};
+    use crate::scripts::ScriptFile;
+    use std::convert::TryFrom;
+
+    fn requirement(text: &str) -> Result<Requirement, String> {
+        Requirement::try_from(text.to_owned())
+    }
+
+    #[test]
+    fn parses_requirements() {
+        assert_eq!(
+            requirement("nmap >= 7.80").unwrap(),
+            Requirement {
+                binary: "nmap".to_owned(),
+                min_version: Some("7.80".to_owned()),
+            }
+        );
+        assert_eq!(requirement("python3").unwrap().to_string(), "python3");
+        assert!(requirement("").is_err());
+        assert!(requirement("nmap>=").is_err());
+        assert!(requirement("nmap<=7").is_err());
+    }
+
+    #[test]
+    fn finds_versions() {
+        assert_eq!(
+            find_version("Nmap version 7.94SVN ( https://nmap.org )"),
+            Some("7.94SVN")
+        );
+        assert_eq!(find_version("Python 3.11.4\n"), Some("3.11.4"));
+        assert_eq!(find_version("curl v8.5.0 (x86_64)"), Some("8.5.0"));
+        assert_eq!(find_version("usage: tool [-h]"), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn checks_scripts_before_they_run() {
+        let header = "#!/bin/sh\n\
+                      #requires = [\"sh\", \"no-such-binary-rustscan\"]\n\
+                      #call_format = \"{{ip}}\"\n";
+        let (missing, issues) = ScriptFile::parse(header.as_bytes(), "missing.sh".into());
+        assert!(issues.is_empty(), "{:?}", issues);
+        let header = "#!/bin/sh\n#requires = [\"sh\"]\n#call_format = \"{{ip}}\"\n";
+        let (present, _) = ScriptFile::parse(header.as_bytes(), "present.sh".into());
+
+        let mut checker = Checker::new();
+        let (ready, issues) = checker.check(vec![missing.unwrap(), present.unwrap()]);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].path.as_deref(), Some("present.sh".as_ref()));
+        assert_eq!(
+            issues[0].to_string(),
+            "missing.sh: `requires` is not met, no-such-binary-rustscan is not installed or not in PATH"
+        );
+
+        let (invalid, issues) = ScriptFile::parse(
+            "#!/bin/sh\n#requires = [\"nmap>=\"]\n".as_bytes(),
+            "invalid.sh".into(),
+        );
+        assert!(invalid.is_none());
+        assert_eq!(issues[0].line, Some(2));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn compares_versions() {
+        let mut checker = Checker::new();
+        checker
+            .versions
+            .insert(locate("sh").unwrap(), Some("5.2.15".to_owned()));
+        assert_eq!(checker.unmet(&requirement("sh>=5.2").unwrap()), None);
+        assert_eq!(
+            checker.unmet(&requirement("sh>=5.10").unwrap()).unwrap(),
+            "sh 5.2.15 is older than 5.10"
+        );
+    }
+}
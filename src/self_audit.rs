@@ -0,0 +1,412 @@
+//! Audits the listening sockets of the machine RustScan runs on.
+//!
+//! `rustscan self` lists the TCP sockets listening on this machine, from
+//! `/proc/net/tcp` and `/proc/net/tcp6` on Linux and `netstat -an`
+//! elsewhere, then scans their ports on the loopback addresses and on the
+//! address of every network interface. A socket that accepts connections
+//! on an interface address is reachable from other hosts, unless a firewall
+//! in front of the machine says otherwise; one that only does on loopback
+//! is local-only.
+//!
+//! Connections to the machine's own addresses don't leave it, so rules of
+//! a host firewall that only apply to packets from outside aren't seen.
+use crate::input::{Opts, ScanOrder};
+use crate::port_strategy::PortStrategy;
+use crate::scanner::Scanner;
+use crate::{detail, output, warning};
+
+use async_std::task::block_on;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The state of listening sockets in `/proc/net/tcp`.
+const PROC_LISTEN: &str = "0A";
+
+/// Where a listening socket accepted connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exposure {
+    /// The address the socket is bound to.
+    pub listener: SocketAddr,
+    /// Whether it accepted connections on loopback.
+    pub loopback: bool,
+    /// The interface addresses it accepted connections on.
+    pub reachable_on: Vec<IpAddr>,
+}
+
+impl Exposure {
+    /// Whether other hosts can connect to it.
+    pub fn is_external(&self) -> bool {
+        !self.reachable_on.is_empty()
+    }
+}
+
+/// An address of `/proc/net/tcp` or `/proc/net/tcp6`, hex words in host
+/// byte order then the port.
+fn proc_address(address: &str) -> Option<SocketAddr> {
+    let (ip, port) = address.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let mut bytes = Vec::new();
+    for word in ip.as_bytes().chunks(8) {
+        let word = u32::from_str_radix(std::str::from_utf8(word).ok()?, 16).ok()?;
+        bytes.extend_from_slice(&word.to_ne_bytes());
+    }
+    let ip = match bytes.len() {
+        4 => IpAddr::from(<[u8; 4]>::try_from(bytes.as_slice()).ok()?),
+        16 => IpAddr::from(<[u8; 16]>::try_from(bytes.as_slice()).ok()?),
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// The listening sockets in `/proc/net/tcp` or `/proc/net/tcp6`.
+pub fn parse_proc_net(content: &str) -> Vec<SocketAddr> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.get(3) != Some(&PROC_LISTEN) {
+                return None;
+            }
+            proc_address(fields.get(1)?)
+        })
+        .collect()
+}
+
+/// A local address as `netstat` prints it: `*.22`, `127.0.0.1.631` and
+/// `::1.631` on macOS and the BSDs, `0.0.0.0:135` and `[::]:135` on
+/// Windows.
+fn netstat_address(address: &str, ipv6: bool) -> Option<SocketAddr> {
+    if let Ok(socket) = SocketAddr::from_str(address) {
+        return Some(socket);
+    }
+    let (ip, port) = address.rsplit_once('.')?;
+    let port = port.parse().ok()?;
+    let ip = match ip {
+        "*" if ipv6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        "*" => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        // Link-local IPv6 addresses carry their interface.
+        ip => IpAddr::from_str(ip.split('%').next()?).ok()?,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// The listening TCP sockets in the output of `netstat -an`.
+pub fn parse_netstat(output: &str) -> Vec<SocketAddr> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let protocol = fields.first()?.to_ascii_lowercase();
+            if !protocol.starts_with("tcp")
+                || !matches!(fields.last(), Some(&("LISTEN" | "LISTENING")))
+            {
+                return None;
+            }
+            // Windows has no queue columns.
+            let local = if fields.len() == 4 {
+                fields[1]
+            } else {
+                *fields.get(3)?
+            };
+            netstat_address(local, protocol.contains('6'))
+        })
+        .collect()
+}
+
+/// The TCP sockets listening on this machine.
+#[cfg(not(tarpaulin_include))]
+pub fn listeners() -> io::Result<Vec<SocketAddr>> {
+    let mut listeners = BTreeSet::new();
+    if cfg!(target_os = "linux") {
+        listeners.extend(parse_proc_net(&std::fs::read_to_string("/proc/net/tcp")?));
+        // Without IPv6, there is no tcp6.
+        if let Ok(tcp6) = std::fs::read_to_string("/proc/net/tcp6") {
+            listeners.extend(parse_proc_net(&tcp6));
+        }
+    } else {
+        let output = Command::new("netstat").arg("-an").output()?;
+        listeners.extend(parse_netstat(&String::from_utf8_lossy(&output.stdout)));
+    }
+    Ok(listeners.into_iter().collect())
+}
+
+/// The addresses of the network interfaces, but loopback and link-local
+/// IPv6 ones, which need an interface to connect to.
+#[cfg(unix)]
+#[cfg(not(tarpaulin_include))]
+pub fn interface_addresses() -> Vec<IpAddr> {
+    let mut addresses = BTreeSet::new();
+    let mut first: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs fills `first` with a list freed below.
+    if unsafe { libc::getifaddrs(&raw mut first) } != 0 {
+        return Vec::new();
+    }
+    let mut current = first;
+    while !current.is_null() {
+        // SAFETY: the entries of the list are valid until it's freed.
+        let entry = unsafe { &*current };
+        current = entry.ifa_next;
+        if entry.ifa_addr.is_null() {
+            continue;
+        }
+        // SAFETY: ifa_addr points at a sockaddr of the family it starts
+        // with, read unaligned as the smaller sockaddr may not be aligned
+        // for the bigger one.
+        let ip = match i32::from(unsafe { (*entry.ifa_addr).sa_family }) {
+            libc::AF_INET => {
+                let address: libc::sockaddr_in =
+                    unsafe { entry.ifa_addr.cast::<libc::sockaddr_in>().read_unaligned() };
+                IpAddr::V4(Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr)))
+            }
+            libc::AF_INET6 => {
+                let address: libc::sockaddr_in6 =
+                    unsafe { entry.ifa_addr.cast::<libc::sockaddr_in6>().read_unaligned() };
+                IpAddr::V6(Ipv6Addr::from(address.sin6_addr.s6_addr))
+            }
+            _ => continue,
+        };
+        addresses.insert(ip);
+    }
+    // SAFETY: `first` came from getifaddrs and isn't used after this.
+    unsafe { libc::freeifaddrs(first) };
+    addresses
+        .into_iter()
+        .filter(|ip| !ip.is_loopback() && !is_link_local(*ip))
+        .collect()
+}
+
+/// The addresses the OS would send from to reach the internet, found
+/// without sending anything.
+#[cfg(not(unix))]
+#[cfg(not(tarpaulin_include))]
+pub fn interface_addresses() -> Vec<IpAddr> {
+    let routes: [SocketAddr; 2] = [
+        (Ipv4Addr::new(192, 0, 2, 1), 9).into(),
+        (Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 9).into(),
+    ];
+    routes
+        .iter()
+        .filter_map(|route| {
+            let local: SocketAddr = match route {
+                SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+            };
+            let socket = std::net::UdpSocket::bind(local).ok()?;
+            socket.connect(route).ok()?;
+            Some(socket.local_addr().ok()?.ip())
+        })
+        .filter(|ip| !ip.is_loopback() && !is_link_local(*ip))
+        .collect()
+}
+
+fn is_link_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(_) => false,
+        IpAddr::V6(ip) => ip.segments()[0] & 0xffc0 == 0xfe80,
+    }
+}
+
+/// Whether a socket bound to `listener` accepts connections to `ip`.
+fn accepts(listener: SocketAddr, ip: IpAddr) -> bool {
+    match listener.ip() {
+        // Sockets on :: usually take IPv4 too.
+        IpAddr::V6(bound) if bound.is_unspecified() => true,
+        IpAddr::V4(bound) if bound.is_unspecified() => ip.is_ipv4(),
+        bound => bound == ip,
+    }
+}
+
+/// Where each listener accepted connections, given the `open` sockets the
+/// scan found.
+pub fn exposures(listeners: &[SocketAddr], open: &[SocketAddr]) -> Vec<Exposure> {
+    listeners
+        .iter()
+        .map(|listener| {
+            let mut reachable = open.iter().filter(|socket| {
+                socket.port() == listener.port() && accepts(*listener, socket.ip())
+            });
+            let loopback = reachable.clone().any(|socket| socket.ip().is_loopback());
+            let reachable_on = reachable
+                .by_ref()
+                .map(SocketAddr::ip)
+                .filter(|ip| !ip.is_loopback())
+                .collect();
+            Exposure {
+                listener: *listener,
+                loopback,
+                reachable_on,
+            }
+        })
+        .collect()
+}
+
+/// Scans the listening sockets of this machine and prints which are
+/// reachable from other hosts. Returns whether they could be listed.
+#[cfg(not(tarpaulin_include))]
+pub fn run(opts: &Opts) -> bool {
+    let listeners = match listeners() {
+        Ok(listeners) => listeners,
+        Err(e) => {
+            warning!(
+                format!("Can't list the listening sockets: {e}"),
+                false,
+                opts.accessible
+            );
+            return false;
+        }
+    };
+    if listeners.is_empty() {
+        output!("Nothing is listening on TCP", false, opts.accessible);
+        return true;
+    }
+    let mut ips = vec![IpAddr::V4(Ipv4Addr::LOCALHOST)];
+    if listeners.iter().any(SocketAddr::is_ipv6) {
+        ips.push(IpAddr::V6(Ipv6Addr::LOCALHOST));
+    }
+    ips.extend(interface_addresses());
+    let ports: BTreeSet<u16> = listeners.iter().map(SocketAddr::port).collect();
+    detail!(
+        format!(
+            "Scanning {} listening ports on {}",
+            ports.len(),
+            ips.iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        false,
+        opts.accessible
+    );
+    let scanner = Scanner::new(
+        &ips,
+        opts.batch_size,
+        Duration::from_millis(opts.timeout.into()),
+        opts.tries,
+        true,
+        PortStrategy::pick(&None, Some(ports.into_iter().collect()), ScanOrder::Serial),
+        opts.accessible,
+        vec![],
+        false,
+    );
+    let open = block_on(scanner.run());
+
+    let exposures = exposures(&listeners, &open);
+    for exposure in &exposures {
+        if exposure.is_external() {
+            let ips: Vec<String> = exposure
+                .reachable_on
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            warning!(
+                format!(
+                    "{} is reachable from other hosts, on {}",
+                    exposure.listener,
+                    ips.join(", ")
+                ),
+                false,
+                opts.accessible
+            );
+        } else if exposure.loopback {
+            output!(
+                format!("{} is local-only", exposure.listener),
+                false,
+                opts.accessible
+            );
+        } else {
+            detail!(
+                format!(
+                    "{} didn't accept connections, it may be filtered or gone",
+                    exposure.listener
+                ),
+                false,
+                opts.accessible
+            );
+        }
+    }
+    let external = exposures
+        .iter()
+        .filter(|exposure| exposure.is_external())
+        .count();
+    detail!(
+        format!(
+            "{external} of {} listening sockets are reachable from other hosts",
+            exposures.len()
+        ),
+        false,
+        opts.accessible
+    );
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exposures, parse_netstat, parse_proc_net};
+    use std::net::SocketAddr;
+
+    fn socket(socket: &str) -> SocketAddr {
+        socket.parse().unwrap()
+    }
+
+    #[test]
+    fn listening_sockets_from_proc_and_netstat() {
+        let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0277 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 853 1
+   1: 00000000:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 662 1
+   2: 020200C0:D054 08080808:01BB 01 00000000:00000000 03:000002B4 00000000     0        0 0 3";
+        assert_eq!(
+            parse_proc_net(tcp),
+            vec![socket("127.0.0.1:631"), socket("0.0.0.0:22")]
+        );
+        let tcp6 =
+            "  sl  local_address                         remote_address                        st
+   0: 00000000000000000000000001000000:0019 00000000000000000000000000000000:0000 0A";
+        assert_eq!(parse_proc_net(tcp6), vec![socket("[::1]:25")]);
+
+        let bsd = "Active Internet connections (including servers)
+Proto Recv-Q Send-Q  Local Address          Foreign Address        (state)
+tcp4       0      0  127.0.0.1.631          *.*                    LISTEN
+tcp46      0      0  *.22                   *.*                    LISTEN
+tcp4       0      0  10.0.0.5.51234         1.1.1.1.443            ESTABLISHED
+udp4       0      0  *.5353                 *.*";
+        assert_eq!(
+            parse_netstat(bsd),
+            vec![socket("127.0.0.1:631"), socket("[::]:22")]
+        );
+        let windows = "  Proto  Local Address          Foreign Address        State
+  TCP    0.0.0.0:135            0.0.0.0:0              LISTENING
+  TCP    [::1]:5432             [::]:0                 LISTENING";
+        assert_eq!(
+            parse_netstat(windows),
+            vec![socket("0.0.0.0:135"), socket("[::1]:5432")]
+        );
+    }
+
+    #[test]
+    fn interface_addresses_make_sockets_external() {
+        let listeners = [
+            socket("0.0.0.0:22"),
+            socket("127.0.0.1:631"),
+            socket("10.0.0.5:8080"),
+            socket("127.0.0.1:9000"),
+        ];
+        let open = [
+            socket("127.0.0.1:22"),
+            socket("10.0.0.5:22"),
+            socket("127.0.0.1:631"),
+            socket("10.0.0.5:8080"),
+        ];
+        let exposures = exposures(&listeners, &open);
+        assert!(exposures[0].loopback && exposures[0].is_external());
+        assert_eq!(exposures[0].reachable_on, vec![socket("10.0.0.5:22").ip()]);
+        assert!(exposures[1].loopback && !exposures[1].is_external());
+        assert!(!exposures[2].loopback && exposures[2].is_external());
+        assert!(!exposures[3].loopback && !exposures[3].is_external());
+    }
+}
@@ -0,0 +1,573 @@
+//! Protocol-specific probes for services that are often left open.
+//!
+//! With `--service-probes`, once the scan is done:
+//!
+//! - open TCP ports 21 are greeted as FTP servers: their banner is kept and
+//!   an `anonymous` login is tried, see [`crate::creds`],
+//! - port 69 of every host with open ports (of the open UDP ports, in UDP
+//!   scans) is sent a TFTP read request for a file that shouldn't exist,
+//!   [`TFTP_FILENAME`]. TFTP has no logins, so a server answering at all,
+//!   even with "file not found", lets anyone on the network read and often
+//!   write its files,
+//! - open TCP ports of databases are sent the start of their handshake,
+//!   which tells their version and how they want clients to log in, without
+//!   logging in: MySQL (3306), PostgreSQL (5432, as user `rustscan`), Redis
+//!   (6379, `INFO server`), MongoDB (27017, `buildInfo`) and SQL Server
+//!   (1433, a TDS pre-login).
+//!
+//! Nothing is downloaded: the first answer is kept and the exchange is left
+//! there. Answers are printed and kept in `service_probes` in reports, with
+//! the name of the service (`ftp`, `tftp`, `mysql`, `postgres`, `redis`,
+//! `mongodb` or `mssql`), which scripts can be limited to with `service`,
+//! see [`crate::scripts`].
+use crate::creds;
+use crate::scanner::payload::ProbeResponse;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+/// Number of probes run at the same time.
+const PARALLEL_PROBES: usize = 64;
+
+pub const FTP_PORT: u16 = 21;
+pub const TFTP_PORT: u16 = 69;
+
+/// The ports databases are probed on.
+const DATABASES: [(u16, Database); 5] = [
+    (1433, Database::MsSql),
+    (3306, Database::MySql),
+    (5432, Database::Postgres),
+    (6379, Database::Redis),
+    (27017, Database::MongoDb),
+];
+
+/// The most read from a service before giving up on its answer.
+const ANSWER_LIMIT: usize = 64 * 1024;
+
+/// The file TFTP servers are asked for.
+pub const TFTP_FILENAME: &str = "rustscan-probe.txt";
+
+/// What a service answered its probe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceProbe {
+    pub socket: SocketAddr,
+    /// What runs there, `ftp`, `tftp` or a database.
+    pub service: String,
+    /// The version the service gave, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// The FTP greeting, what TFTP answered the read request, or how the
+    /// database wants clients to log in, with non-printable bytes escaped.
+    pub answer: String,
+    /// Whether FTP let `anonymous` in, absent for TFTP which has no logins.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anonymous: Option<bool>,
+}
+
+impl ServiceProbe {
+    /// Whether the service lets anyone read files: anonymous FTP, or TFTP.
+    pub fn is_open(&self) -> bool {
+        self.anonymous.unwrap_or(self.service == "tftp")
+    }
+}
+
+/// A TFTP read request (RRQ) for [`TFTP_FILENAME`], in octet mode.
+fn read_request() -> Vec<u8> {
+    let mut request = vec![0, 1];
+    request.extend_from_slice(TFTP_FILENAME.as_bytes());
+    request.push(0);
+    request.extend_from_slice(b"octet\0");
+    request
+}
+
+/// Describes a TFTP packet, `None` if it isn't an answer to a read request.
+fn tftp_answer(packet: &[u8]) -> Option<String> {
+    match packet {
+        [0, 3, _, _, ..] => Some("the file".to_owned()),
+        [0, 5, high, low, message @ ..] => {
+            let code = u16::from_be_bytes([*high, *low]);
+            let message = message.split(|byte| *byte == 0).next().unwrap_or_default();
+            Some(format!(
+                "error {code}: {}",
+                ProbeResponse::new(message).snippet
+            ))
+        }
+        [0, 6, ..] => Some("its options".to_owned()),
+        _ => None,
+    }
+}
+
+fn probe_tftp(server: SocketAddr, timeout: Duration) -> io::Result<Option<ServiceProbe>> {
+    let local: SocketAddr = match server.ip() {
+        IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.send_to(&read_request(), server)?;
+    // The largest TFTP packet, a data block and its header.
+    let mut packet = [0; 516];
+    loop {
+        // Servers answer from a port of their own, but from the same host.
+        let (length, from) = match socket.recv_from(&mut packet) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        };
+        if from.ip() != server.ip() {
+            continue;
+        }
+        if let Some(answer) = tftp_answer(&packet[..length]) {
+            if packet[1] == 3 {
+                // Tell the server to stop rather than acknowledge the block.
+                let mut error = vec![0, 5, 0, 0];
+                error.extend_from_slice(b"Probe done\0");
+                let _ = socket.send_to(&error, from);
+            }
+            return Ok(Some(ServiceProbe {
+                socket: server,
+                service: "tftp".to_owned(),
+                version: None,
+                answer,
+                anonymous: None,
+            }));
+        }
+    }
+}
+
+fn probe_ftp(socket: SocketAddr, timeout: Duration) -> io::Result<ServiceProbe> {
+    let stream = TcpStream::connect_timeout(&socket, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let (greeting, anonymous) = creds::ftp_anonymous(&stream)?;
+    Ok(ServiceProbe {
+        socket,
+        service: "ftp".to_owned(),
+        version: None,
+        answer: ProbeResponse::new(greeting.as_bytes()).snippet,
+        anonymous: Some(anonymous),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Database {
+    MsSql,
+    MySql,
+    Postgres,
+    Redis,
+    MongoDb,
+}
+
+impl Database {
+    fn of(port: u16) -> Option<Self> {
+        DATABASES
+            .iter()
+            .find(|(known, _)| *known == port)
+            .map(|(_, database)| *database)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::MsSql => "mssql",
+            Self::MySql => "mysql",
+            Self::Postgres => "postgres",
+            Self::Redis => "redis",
+            Self::MongoDb => "mongodb",
+        }
+    }
+
+    /// Runs the start of the handshake, returns the version and answer.
+    fn handshake(self, stream: &TcpStream) -> io::Result<(Option<String>, String)> {
+        match self {
+            Self::MsSql => {
+                let mut stream = stream;
+                stream.write_all(&PRELOGIN)?;
+                let mut header = [0; 8];
+                stream.read_exact(&mut header)?;
+                let length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+                let mut payload = vec![0; length.saturating_sub(header.len())];
+                stream.read_exact(&mut payload)?;
+                Ok(mssql_prelogin(&payload))
+            }
+            Self::MySql => {
+                let mut stream = stream;
+                let mut header = [0; 4];
+                stream.read_exact(&mut header)?;
+                let length = u32::from_le_bytes([header[0], header[1], header[2], 0]);
+                let mut payload = Vec::new();
+                stream
+                    .take(u64::from(length).min(ANSWER_LIMIT as u64))
+                    .read_to_end(&mut payload)?;
+                Ok(mysql_greeting(&payload))
+            }
+            Self::Postgres => postgres_startup(stream),
+            Self::Redis => {
+                let mut writer = stream;
+                writer.write_all(b"INFO server\r\n")?;
+                let mut reader = BufReader::new(stream.take(ANSWER_LIMIT as u64));
+                let mut line = String::new();
+                reader.read_line(&mut line)?;
+                let Some(length) = line.strip_prefix('$') else {
+                    return Ok((None, line.trim_end().to_owned()));
+                };
+                let length: usize = length.trim_end().parse().unwrap_or(0);
+                let mut info = vec![0; length.min(ANSWER_LIMIT)];
+                reader.read_exact(&mut info)?;
+                let version = String::from_utf8_lossy(&info)
+                    .lines()
+                    .find_map(|line| line.strip_prefix("redis_version:"))
+                    .map(ToOwned::to_owned);
+                Ok((version, "no authentication required".to_owned()))
+            }
+            Self::MongoDb => {
+                let reply = creds::mongodb_command(stream, "buildInfo")?;
+                Ok((
+                    bson_string(&reply, "version"),
+                    "answered buildInfo without logging in".to_owned(),
+                ))
+            }
+        }
+    }
+}
+
+/// A TDS pre-login packet offering no encryption, to learn the version.
+const PRELOGIN: [u8; 26] = [
+    // Header: pre-login, last packet, length, channel, packet, window.
+    0x12, 0x01, 0x00, 0x1a, 0x00, 0x00, 0x01, 0x00,
+    // VERSION at 11 for 6 bytes, ENCRYPTION at 17 for 1, terminator.
+    0x00, 0x00, 0x0b, 0x00, 0x06, 0x01, 0x00, 0x11, 0x00, 0x01, 0xff,
+    // Client version, then encryption not supported.
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+];
+
+/// The server version and encryption setting in a pre-login answer.
+fn mssql_prelogin(payload: &[u8]) -> (Option<String>, String) {
+    let mut version = None;
+    let mut encryption = None;
+    for option in payload.chunks(5).take_while(|option| option[0] != 0xff) {
+        let [token, offset_high, offset_low, length_high, length_low] = *option else {
+            break;
+        };
+        let offset = usize::from(u16::from_be_bytes([offset_high, offset_low]));
+        let length = usize::from(u16::from_be_bytes([length_high, length_low]));
+        let Some(value) = payload.get(offset..offset + length) else {
+            continue;
+        };
+        match (token, value) {
+            (0x00, [major, minor, build_high, build_low, ..]) => {
+                let build = u16::from_be_bytes([*build_high, *build_low]);
+                version = Some(format!("{major}.{minor}.{build}"));
+            }
+            (0x01, [setting, ..]) => {
+                encryption = Some(match setting {
+                    0 => "encryption available but off",
+                    1 => "encryption on",
+                    2 => "encryption not supported",
+                    3 => "encryption required",
+                    _ => "unknown encryption setting",
+                });
+            }
+            _ => {}
+        }
+    }
+    (
+        version,
+        encryption.unwrap_or("no encryption setting").to_owned(),
+    )
+}
+
+/// The server version and authentication plugin in a MySQL greeting, or
+/// the error it sent instead.
+fn mysql_greeting(payload: &[u8]) -> (Option<String>, String) {
+    match payload {
+        [0x0a, rest @ ..] => {
+            let mut fields = rest.splitn(2, |byte| *byte == 0);
+            let version = fields
+                .next()
+                .map(|version| ProbeResponse::new(version).snippet);
+            // Connection id, auth data, capabilities, charset, status and
+            // a reserved block come before the rest of the auth data.
+            let plugin = fields.next().and_then(|rest| {
+                let auth_length = usize::from(*rest.get(4 + 8 + 1 + 2 + 1 + 2 + 2)?);
+                let start =
+                    4 + 8 + 1 + 2 + 1 + 2 + 2 + 1 + 10 + auth_length.saturating_sub(8).max(13);
+                let plugin = rest.get(start..)?.split(|byte| *byte == 0).next()?;
+                (!plugin.is_empty()).then(|| ProbeResponse::new(plugin).snippet)
+            });
+            let answer = match plugin {
+                Some(plugin) => format!("logs in with {plugin}"),
+                None => "sent its greeting".to_owned(),
+            };
+            (version, answer)
+        }
+        [0xff, _, _, message @ ..] => (None, ProbeResponse::new(message).snippet),
+        _ => (None, "unknown greeting".to_owned()),
+    }
+}
+
+/// How PostgreSQL wants `rustscan` to log in, going by the code of its
+/// authentication request.
+fn postgres_authentication(code: u32, mechanisms: &[u8]) -> String {
+    match code {
+        0 => "lets anyone in without a password".to_owned(),
+        3 => "asks for a password in clear text".to_owned(),
+        5 => "asks for an MD5 password".to_owned(),
+        7 => "asks for GSSAPI".to_owned(),
+        9 => "asks for SSPI".to_owned(),
+        10 => {
+            let mechanisms: Vec<String> = mechanisms
+                .split(|byte| *byte == 0)
+                .filter(|mechanism| !mechanism.is_empty())
+                .map(|mechanism| ProbeResponse::new(mechanism).snippet)
+                .collect();
+            format!("asks for SASL ({})", mechanisms.join(", "))
+        }
+        code => format!("asks for authentication {code}"),
+    }
+}
+
+/// Starts a session as `rustscan`, returns the server version if it let
+/// us in and how it wants clients to log in.
+fn postgres_startup(stream: &TcpStream) -> io::Result<(Option<String>, String)> {
+    let mut parameters = Vec::new();
+    parameters.extend_from_slice(&196_608_u32.to_be_bytes());
+    parameters.extend_from_slice(b"user\0rustscan\0database\0postgres\0\0");
+    let length = u32::try_from(parameters.len() + 4).unwrap_or(u32::MAX);
+    let mut writer = stream;
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(&parameters)?;
+
+    let mut reader = stream;
+    let mut version = None;
+    let mut answer = None;
+    // Authentication, then parameters once in, until ready for queries.
+    for _ in 0..64 {
+        let mut header = [0; 5];
+        reader.read_exact(&mut header)?;
+        let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+        let mut body = vec![
+            0;
+            usize::try_from(length.saturating_sub(4))
+                .unwrap_or(0)
+                .min(ANSWER_LIMIT)
+        ];
+        reader.read_exact(&mut body)?;
+        match (header[0], body.as_slice()) {
+            (b'R', [a, b, c, d, mechanisms @ ..]) => {
+                let code = u32::from_be_bytes([*a, *b, *c, *d]);
+                answer = Some(postgres_authentication(code, mechanisms));
+                if code != 0 {
+                    break;
+                }
+            }
+            (b'S', parameter) => {
+                let mut fields = parameter.split(|byte| *byte == 0);
+                if fields.next() == Some(b"server_version") {
+                    version = fields.next().map(|value| ProbeResponse::new(value).snippet);
+                }
+            }
+            (b'E', fields) => {
+                let message = fields
+                    .split(|byte| *byte == 0)
+                    .find_map(|field| field.strip_prefix(b"M"))
+                    .unwrap_or_default();
+                answer = Some(format!("refused: {}", ProbeResponse::new(message).snippet));
+                break;
+            }
+            (b'Z', _) => break,
+            _ => {}
+        }
+    }
+    // Terminate.
+    let _ = writer.write_all(&[b'X', 0, 0, 0, 4]);
+    Ok((
+        version,
+        answer.unwrap_or_else(|| "sent no authentication request".to_owned()),
+    ))
+}
+
+/// The string `key` of the first BSON document in `reply`, found by name.
+fn bson_string(reply: &[u8], key: &str) -> Option<String> {
+    let mut marker = vec![0x02];
+    marker.extend_from_slice(key.as_bytes());
+    marker.push(0);
+    let start = reply
+        .windows(marker.len())
+        .position(|window| window == marker.as_slice())?
+        + marker.len();
+    let length = reply.get(start..start + 4)?;
+    let length = usize::try_from(u32::from_le_bytes([
+        length[0], length[1], length[2], length[3],
+    ]))
+    .ok()?;
+    let value = reply.get(start + 4..start + 4 + length.checked_sub(1)?)?;
+    Some(ProbeResponse::new(value).snippet)
+}
+
+fn probe_database(
+    socket: SocketAddr,
+    database: Database,
+    timeout: Duration,
+) -> io::Result<ServiceProbe> {
+    let stream = TcpStream::connect_timeout(&socket, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let (version, answer) = database.handshake(&stream)?;
+    Ok(ServiceProbe {
+        socket,
+        service: database.name().to_owned(),
+        version,
+        answer,
+        anonymous: None,
+    })
+}
+
+/// What to probe: FTP and databases on the open sockets, TFTP on the
+/// hosts.
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Ftp(SocketAddr),
+    Tftp(IpAddr),
+    Database(SocketAddr, Database),
+}
+
+/// Probes the FTP servers and databases among the open `sockets` and TFTP
+/// on their hosts, or on the hosts with 69 among them for UDP scans, a
+/// batch at a time.
+pub fn probe_all(sockets: &[SocketAddr], udp: bool, timeout: Duration) -> Vec<ServiceProbe> {
+    let mut targets: Vec<Target> = Vec::new();
+    if !udp {
+        targets.extend(
+            sockets
+                .iter()
+                .filter(|socket| socket.port() == FTP_PORT)
+                .map(|socket| Target::Ftp(*socket)),
+        );
+        targets.extend(sockets.iter().filter_map(|socket| {
+            Database::of(socket.port()).map(|database| Target::Database(*socket, database))
+        }));
+    }
+    let hosts: BTreeSet<IpAddr> = sockets
+        .iter()
+        .filter(|socket| !udp || socket.port() == TFTP_PORT)
+        .map(SocketAddr::ip)
+        .collect();
+    targets.extend(hosts.into_iter().map(Target::Tftp));
+
+    let mut probes = Vec::new();
+    for batch in targets.chunks(PARALLEL_PROBES) {
+        thread::scope(|scope| {
+            let running: Vec<_> = batch
+                .iter()
+                .map(|target| {
+                    scope.spawn(move || match *target {
+                        Target::Ftp(socket) => probe_ftp(socket, timeout).ok(),
+                        Target::Tftp(ip) => probe_tftp(SocketAddr::new(ip, TFTP_PORT), timeout)
+                            .ok()
+                            .flatten(),
+                        Target::Database(socket, database) => {
+                            probe_database(socket, database, timeout).ok()
+                        }
+                    })
+                })
+                .collect();
+            for probe in running {
+                if let Ok(Some(probe)) = probe.join() {
+                    probes.push(probe);
+                }
+            }
+        });
+    }
+    probes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        bson_string, mssql_prelogin, mysql_greeting, probe_tftp, read_request, tftp_answer,
+    };
+    use std::net::UdpSocket;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn tftp_answers() {
+        assert_eq!(
+            tftp_answer(b"\x00\x05\x00\x01File not found\x00").as_deref(),
+            Some("error 1: File not found")
+        );
+        assert_eq!(
+            tftp_answer(b"\x00\x03\x00\x01hello").as_deref(),
+            Some("the file")
+        );
+        assert_eq!(tftp_answer(b"\x00\x01nope"), None);
+    }
+
+    #[test]
+    fn database_greetings() {
+        let mut greeting = b"\x0a8.0.36\x00".to_vec();
+        greeting.extend_from_slice(&[1, 0, 0, 0]);
+        greeting.extend_from_slice(b"abcdefgh\x00");
+        greeting.extend_from_slice(&[0xff, 0xff, 0xff, 0x02, 0x00, 0xff, 0xdf, 21]);
+        greeting.extend_from_slice(&[0; 10]);
+        greeting.extend_from_slice(b"ijklmnopqrst\x00caching_sha2_password\x00");
+        assert_eq!(
+            mysql_greeting(&greeting),
+            (
+                Some("8.0.36".to_owned()),
+                "logs in with caching_sha2_password".to_owned()
+            )
+        );
+        assert_eq!(
+            mysql_greeting(b"\xffj\x04Host '10.0.0.9' is not allowed to connect"),
+            (None, "Host '10.0.0.9' is not allowed to connect".to_owned())
+        );
+
+        let prelogin = [
+            0x00, 0x00, 0x0b, 0x00, 0x06, 0x01, 0x00, 0x11, 0x00, 0x01, 0xff, 0x0f, 0x00, 0x07,
+            0xd0, 0x00, 0x00, 0x03,
+        ];
+        assert_eq!(
+            mssql_prelogin(&prelogin),
+            (
+                Some("15.0.2000".to_owned()),
+                "encryption required".to_owned()
+            )
+        );
+
+        let reply = b"\x00\x00\x00\x00\x02version\x00\x06\x00\x00\x007.0.4\x00";
+        assert_eq!(bson_string(reply, "version").as_deref(), Some("7.0.4"));
+        assert_eq!(bson_string(reply, "gitVersion"), None);
+    }
+
+    #[test]
+    fn tftp_servers_answer_from_their_own_port() {
+        let listening = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = listening.local_addr().unwrap();
+        let answering = thread::spawn(move || {
+            let mut request = [0; 64];
+            let (length, client) = listening.recv_from(&mut request).unwrap();
+            let transfer = UdpSocket::bind("127.0.0.1:0").unwrap();
+            transfer
+                .send_to(b"\x00\x05\x00\x01File not found\x00", client)
+                .unwrap();
+            request[..length].to_vec()
+        });
+        let probe = probe_tftp(server, Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(answering.join().unwrap(), read_request());
+        assert_eq!(probe.service, "tftp");
+        assert_eq!(probe.answer, "error 1: File not found");
+        assert!(probe.is_open());
+    }
+}
@@ -0,0 +1,223 @@
+//! Detects the limits the operating system puts on concurrent connections.
+//!
+//! On unix the practical ceiling is usually the open file limit, while on
+//! Windows (which has no such limit for sockets) it is the number of
+//! dynamic (ephemeral) ports available to outgoing connections.
+use crate::input::PortRange;
+use std::convert::TryFrom;
+#[cfg(any(target_os = "macos", target_os = "freebsd", windows))]
+use std::process::Command;
+
+/// The dynamic port range suggested by IANA, used when the OS can't be asked.
+const IANA_EPHEMERAL_RANGE: PortRange = PortRange {
+    start: 49152,
+    end: 65535,
+};
+
+/// Ports kept free for the rest of the system when deriving a batch size
+/// from the ephemeral range.
+const RESERVED_EPHEMERAL_PORTS: usize = 1024;
+
+/// The limits detected on the current system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemLimits {
+    /// Soft and hard open file limits, where the OS has them.
+    pub open_files: Option<(u64, u64)>,
+    /// The range the OS picks local ports for outgoing connections from.
+    pub ephemeral_ports: PortRange,
+    /// Whether `ephemeral_ports` was read from the OS or is the IANA default.
+    pub ephemeral_detected: bool,
+}
+
+impl SystemLimits {
+    /// Queries the OS for its limits, falling back to sane defaults.
+    pub fn detect() -> Self {
+        let detected = detect_ephemeral_ports();
+        Self {
+            open_files: detect_open_files(),
+            ephemeral_detected: detected.is_some(),
+            ephemeral_ports: detected.unwrap_or(IANA_EPHEMERAL_RANGE),
+        }
+    }
+
+    /// Number of local ports the OS can hand out to outgoing connections.
+    pub fn ephemeral_port_count(&self) -> usize {
+        usize::from(
+            self.ephemeral_ports
+                .end
+                .saturating_sub(self.ephemeral_ports.start),
+        ) + 1
+    }
+
+    /// The highest number of sockets that can realistically be open at once.
+    pub fn socket_ceiling(&self) -> usize {
+        let ports = self
+            .ephemeral_port_count()
+            .saturating_sub(RESERVED_EPHEMERAL_PORTS)
+            .max(1);
+        match self.open_files {
+            Some((soft, _)) => ports.min(usize::try_from(soft).unwrap_or(usize::MAX)),
+            None => ports,
+        }
+    }
+
+    /// Human readable lines describing the limits, for `--system-check`.
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = vec![format!("Operating system: {}", std::env::consts::OS)];
+        match self.open_files {
+            Some((soft, hard)) => {
+                lines.push(format!("Open file limit: {soft} (hard limit {hard})"));
+            }
+            None => lines.push(String::from("Open file limit: not enforced for sockets")),
+        }
+        lines.push(format!(
+            "Ephemeral port range: {}-{} ({} ports{})",
+            self.ephemeral_ports.start,
+            self.ephemeral_ports.end,
+            self.ephemeral_port_count(),
+            if self.ephemeral_detected {
+                ""
+            } else {
+                ", IANA default"
+            }
+        ));
+        lines.push(format!(
+            "Concurrent socket ceiling: {}",
+            self.socket_ceiling()
+        ));
+        lines
+    }
+}
+
+#[cfg(unix)]
+fn detect_open_files() -> Option<(u64, u64)> {
+    rlimit::Resource::NOFILE.get().ok()
+}
+
+#[cfg(not(unix))]
+fn detect_open_files() -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn detect_ephemeral_ports() -> Option<PortRange> {
+    let content = std::fs::read_to_string("/proc/sys/net/ipv4/ip_local_port_range").ok()?;
+    parse_linux_port_range(&content)
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+fn detect_ephemeral_ports() -> Option<PortRange> {
+    let output = Command::new("sysctl")
+        .args([
+            "-n",
+            "net.inet.ip.portrange.first",
+            "net.inet.ip.portrange.last",
+        ])
+        .output()
+        .ok()?;
+    parse_linux_port_range(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(windows)]
+fn detect_ephemeral_ports() -> Option<PortRange> {
+    let output = Command::new("netsh")
+        .args(["int", "ipv4", "show", "dynamicport", "tcp"])
+        .output()
+        .ok()?;
+    parse_netsh_dynamic_ports(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    windows
+)))]
+fn detect_ephemeral_ports() -> Option<PortRange> {
+    None
+}
+
+/// Parses the two whitespace separated numbers of
+/// `/proc/sys/net/ipv4/ip_local_port_range` or `sysctl -n` output.
+#[cfg_attr(windows, allow(dead_code))]
+fn parse_linux_port_range(content: &str) -> Option<PortRange> {
+    let mut numbers = content.split_whitespace().map(str::parse::<u16>);
+    let start = numbers.next()?.ok()?;
+    let end = numbers.next()?.ok()?;
+    (start <= end).then_some(PortRange { start, end })
+}
+
+/// Parses the output of `netsh int ipv4 show dynamicport tcp`:
+///
+/// ```text
+/// Start Port      : 49152
+/// Number of Ports : 16384
+/// ```
+#[cfg_attr(not(windows), allow(dead_code))]
+fn parse_netsh_dynamic_ports(content: &str) -> Option<PortRange> {
+    let value = |key: &str| -> Option<u32> {
+        content
+            .lines()
+            .find(|line| line.trim_start().starts_with(key))
+            .and_then(|line| line.split(':').nth(1))
+            .and_then(|value| value.trim().parse().ok())
+    };
+    let start = value("Start Port")?;
+    let count = value("Number of Ports")?;
+    let end = (start + count.checked_sub(1)?).min(u32::from(u16::MAX));
+    Some(PortRange {
+        start: u16::try_from(start).ok()?,
+        end: u16::try_from(end).ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_linux_port_range, parse_netsh_dynamic_ports, SystemLimits};
+    use crate::input::PortRange;
+
+    #[test]
+    fn parses_linux_port_range() {
+        assert_eq!(
+            parse_linux_port_range("32768\t60999\n"),
+            Some(PortRange {
+                start: 32768,
+                end: 60999
+            })
+        );
+        assert_eq!(parse_linux_port_range("60999 32768"), None);
+        assert_eq!(parse_linux_port_range("garbage"), None);
+    }
+
+    #[test]
+    fn parses_netsh_dynamic_ports() {
+        let output = "\nProtocol tcp Dynamic Port Range\n---------------------------------\nStart Port      : 49152\nNumber of Ports : 16384\n";
+        assert_eq!(
+            parse_netsh_dynamic_ports(output),
+            Some(PortRange {
+                start: 49152,
+                end: 65535
+            })
+        );
+        assert_eq!(parse_netsh_dynamic_ports("Start Port : 49152"), None);
+    }
+
+    #[test]
+    fn socket_ceiling_is_lowest_limit() {
+        let limits = SystemLimits {
+            open_files: Some((1024, 4096)),
+            ephemeral_ports: PortRange {
+                start: 49152,
+                end: 65535,
+            },
+            ephemeral_detected: true,
+        };
+        assert_eq!(limits.socket_ceiling(), 1024);
+
+        let windows = SystemLimits {
+            open_files: None,
+            ..limits
+        };
+        assert_eq!(windows.socket_ceiling(), 16384 - 1024);
+    }
+}
@@ -0,0 +1,252 @@
+//! Scan templates: named sets of flags saved once and replayed by name.
+//!
+//! `rustscan template save` keeps the flags given after the name, and only
+//! those, in `<name>.toml` in the templates directory, the `rustscan/templates`
+//! directory of the config directory unless `--templates-dir` points
+//! elsewhere, a checkout shared by a team for example:
+//!
+//! ```text
+//! rustscan template save web-external -p 80,443,8080 --scripts none -b 1000
+//! rustscan -T@web-external -a 203.0.113.7
+//! ```
+//!
+//! A template is replayed as if its flags were given on the command line,
+//! before those that were: flags given along with `-T` win over the ones the
+//! template sets.
+use crate::config::parse;
+use crate::input::{Config, Opts};
+use crate::{detail, output, warning};
+use anyhow::{anyhow, Result};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The directory templates are saved to and read from: `custom`, or
+/// `rustscan/templates` in the config directory.
+pub fn dir(custom: Option<&Path>) -> Result<PathBuf> {
+    match custom {
+        Some(dir) => Ok(dir.to_path_buf()),
+        None => dirs::config_dir()
+            .map(|dir| dir.join("rustscan").join("templates"))
+            .ok_or_else(|| anyhow!("Could not infer the config directory, use --templates-dir")),
+    }
+}
+
+/// The file of the template `name`, which may start with `@`.
+fn path(dir: &Path, name: &str) -> Result<PathBuf> {
+    let name = name.strip_prefix('@').unwrap_or(name);
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(anyhow!(
+            "{name:?} is not a template name, use letters, digits, - and _"
+        ));
+    }
+    Ok(dir.join(format!("{name}.toml")))
+}
+
+/// The config keys `args` set, in the config file format. Only flags given
+/// in `args` are kept, not the defaults of the others.
+pub fn capture(args: &[String]) -> Result<toml::Table> {
+    let matches = Opts::command()
+        .try_get_matches_from(std::iter::once("rustscan".to_owned()).chain(args.iter().cloned()))
+        .map_err(|e| anyhow!("{}", e.render().to_string().trim_end()))?;
+    let opts = Opts::from_arg_matches(&matches).map_err(|e| anyhow!("{e}"))?;
+    let given: Vec<&str> = matches
+        .ids()
+        .map(clap::Id::as_str)
+        .filter(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+        .collect();
+    if opts.subcommand.is_some() {
+        return Err(anyhow!("A template can't run a subcommand"));
+    }
+
+    let mut table = toml::Table::try_from(Config::effective(&opts))?;
+    table.retain(|key, _| given.contains(&key) || (key == "top" && opts.ports.is_some()));
+    if table.is_empty() {
+        return Err(anyhow!(
+            "None of the flags can be saved in a template, as they aren't config file options"
+        ));
+    }
+    Ok(table)
+}
+
+/// Reads the template `name`, leaving out what the flags in `given`, the
+/// ids of those given on the command line, set. Ports or a range given
+/// leave out both, as ports win over a range.
+pub fn load(name: &str, dir: &Path, given: &[&str]) -> Result<Config> {
+    let path = path(dir, name)?;
+    let content = fs::read_to_string(&path).map_err(|e| {
+        anyhow!(
+            "Could not read the template {}: {e}, see 'rustscan template list'",
+            path.display()
+        )
+    })?;
+    let (config, issues) = parse::<Config>(&content, &path);
+    if let Some(issue) = issues.first().filter(|_| config.is_none()) {
+        return Err(anyhow!("Found {issue} in the template"));
+    }
+
+    let mut table: toml::Table = toml::from_str(&content)?;
+    let ports = given.contains(&"ports") || given.contains(&"range");
+    table.retain(|key, _| {
+        let overridden = ports && matches!(key, "ports" | "range" | "top");
+        !overridden && !given.contains(&key)
+    });
+    Ok(table.try_into()?)
+}
+
+/// `rustscan template save`: saves the flags in `args` as the template
+/// `name`, returns whether it was written.
+pub fn save(name: &str, args: &[String], force: bool, opts: &Opts) -> bool {
+    let written = dir(opts.templates_dir.as_deref()).and_then(|dir| {
+        let path = path(&dir, name)?;
+        if path.exists() && !force {
+            return Err(anyhow!(
+                "{} already exists, use --force to overwrite it",
+                path.display()
+            ));
+        }
+        let table = capture(args)?;
+        fs::create_dir_all(&dir).map_err(|e| anyhow!("Could not create {}: {e}", dir.display()))?;
+        let content = format!(
+            "# RustScan scan template, saved with:\n# rustscan template save {name} {}\n{}",
+            args.join(" "),
+            toml::to_string(&table)?
+        );
+        fs::write(&path, content)
+            .map_err(|e| anyhow!("Could not write {}: {e}", path.display()))?;
+        Ok(path)
+    });
+    match written {
+        Ok(path) => {
+            output!(format!("Wrote {}", path.display()), false, opts.accessible);
+            detail!(
+                format!(
+                    "Replay it with 'rustscan -T@{} -a <addresses>'",
+                    name.trim_start_matches('@')
+                ),
+                false,
+                opts.accessible
+            );
+            true
+        }
+        Err(e) => {
+            warning!(
+                format!("Could not save the template: {e}"),
+                false,
+                opts.accessible
+            );
+            false
+        }
+    }
+}
+
+/// `rustscan template list`: prints the saved templates and the flags they
+/// were saved with, returns whether the directory could be read.
+pub fn list(opts: &Opts) -> bool {
+    let listed = dir(opts.templates_dir.as_deref()).and_then(|dir| {
+        if !dir.exists() {
+            return Ok((dir, Vec::new()));
+        }
+        let mut templates: Vec<(String, String)> = fs::read_dir(&dir)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "toml")
+            })
+            .filter_map(|path| {
+                let name = path.file_stem()?.to_string_lossy().into_owned();
+                let content = fs::read_to_string(&path).unwrap_or_default();
+                let flags = content
+                    .lines()
+                    .find_map(|line| line.strip_prefix("# rustscan template save "))
+                    .and_then(|line| line.split_once(' '))
+                    .map(|(_, flags)| flags.to_owned())
+                    .unwrap_or_default();
+                Some((name, flags))
+            })
+            .collect();
+        templates.sort();
+        Ok((dir, templates))
+    });
+    match listed {
+        Ok((dir, templates)) if templates.is_empty() => {
+            detail!(
+                format!(
+                    "No templates in {}, save one with 'rustscan template save <name> <flags>'",
+                    dir.display()
+                ),
+                false,
+                opts.accessible
+            );
+            true
+        }
+        Ok((_, templates)) => {
+            for (name, flags) in templates {
+                output!(format!("@{name}: {flags}"), false, opts.accessible);
+            }
+            true
+        }
+        Err(e) => {
+            warning!(
+                format!("Could not list the templates: {e}"),
+                false,
+                opts.accessible
+            );
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{capture, load, path};
+    use std::fs;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| (*arg).to_owned()).collect()
+    }
+
+    #[test]
+    fn keeps_only_the_flags_given() {
+        let table = capture(&args(&["-p", "80,443", "--scripts", "none", "-b", "1000"])).unwrap();
+        let mut keys: Vec<&str> = table.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, ["batch_size", "ports", "scripts", "top"]);
+        assert_eq!(table["scripts"].as_str(), Some("None"));
+
+        assert!(capture(&args(&["--no-config"])).is_err());
+        assert!(capture(&args(&["--bogus"])).is_err());
+    }
+
+    #[test]
+    fn flags_given_win_over_the_template() {
+        let dir = std::env::temp_dir().join(format!("rustscan-templates-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let table = capture(&args(&["-p", "80,443", "-b", "1000", "--udp"])).unwrap();
+        fs::write(dir.join("web.toml"), toml::to_string(&table).unwrap()).unwrap();
+
+        let config = load("@web", &dir, &[]).unwrap();
+        let mut opts = crate::input::Opts::default();
+        opts.merge_template(&config);
+        assert_eq!(opts.ports, Some(vec![80, 443]));
+        assert_eq!(opts.batch_size, 1000);
+        assert!(opts.udp);
+
+        let config = load("web", &dir, &["range", "batch_size"]).unwrap();
+        let mut opts = crate::input::Opts::default();
+        opts.merge_template(&config);
+        assert_eq!(opts.ports, None);
+        assert_eq!(opts.batch_size, 0);
+        assert!(opts.udp);
+
+        assert!(load("missing", &dir, &[]).is_err());
+        assert!(path(&dir, "../web").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
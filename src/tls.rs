@@ -0,0 +1,313 @@
+//! Probes open ports for TLS.
+//!
+//! With `--tls`, RustScan runs a TLS handshake against every open port and
+//! reports the protocol version, cipher suite, ALPN protocol and server
+//! certificate of those that answer it. The handshake is done with rustls,
+//! so no system OpenSSL is needed and static musl builds stay simple; the
+//! probes can be left out entirely by building without the `tls` feature.
+//!
+//! Certificates aren't verified, since the point is to see what's served,
+//! not to trust it.
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{self, ring, WebPkiSupportedAlgorithms};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, ProtocolVersion};
+use rustls::{SignatureScheme, StreamOwned};
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use x509_parser::extensions::GeneralName;
+
+/// Number of handshakes run at the same time.
+const PARALLEL_PROBES: usize = 64;
+
+/// ALPN protocols offered, to learn which one the server prefers.
+const ALPN_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// What a TLS handshake with a port negotiated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsInfo {
+    pub version: String,
+    pub cipher_suite: String,
+    pub alpn: Option<String>,
+    pub certificate: Option<Certificate>,
+}
+
+/// The parts of the server certificate worth reporting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    /// DNS names and IPs the certificate is valid for.
+    pub names: Vec<String>,
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+impl Certificate {
+    pub fn is_self_signed(&self) -> bool {
+        self.subject == self.issuer
+    }
+
+    fn parse(der: &[u8]) -> Result<Self> {
+        let (_, certificate) = x509_parser::parse_x509_certificate(der)
+            .map_err(|e| anyhow!("Invalid certificate: {e}"))?;
+        let names = certificate
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .map(|names| {
+                names
+                    .value
+                    .general_names
+                    .iter()
+                    .filter_map(|name| match name {
+                        GeneralName::DNSName(name) => Some((*name).to_owned()),
+                        GeneralName::IPAddress(ip) => ip_to_string(ip),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            subject: certificate.subject().to_string(),
+            issuer: certificate.issuer().to_string(),
+            names,
+            not_after: DateTime::from_timestamp(certificate.validity().not_after.timestamp(), 0),
+        })
+    }
+}
+
+fn ip_to_string(bytes: &[u8]) -> Option<String> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes)
+            .ok()
+            .map(|ip| std::net::Ipv4Addr::from(ip).to_string()),
+        16 => <[u8; 16]>::try_from(bytes)
+            .ok()
+            .map(|ip| std::net::Ipv6Addr::from(ip).to_string()),
+        _ => None,
+    }
+}
+
+impl fmt::Display for TlsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} with {}", self.version, self.cipher_suite)?;
+        if let Some(alpn) = &self.alpn {
+            write!(f, " ({alpn})")?;
+        }
+        if let Some(certificate) = &self.certificate {
+            write!(f, ", certificate for {}", certificate.subject)?;
+            if !certificate.names.is_empty() {
+                write!(f, " [{}]", certificate.names.join(", "))?;
+            }
+            if certificate.is_self_signed() {
+                write!(f, ", self-signed")?;
+            } else {
+                write!(f, " issued by {}", certificate.issuer)?;
+            }
+            if let Some(not_after) = certificate.not_after {
+                let expiry = if not_after < Utc::now() {
+                    "expired"
+                } else {
+                    "expires"
+                };
+                write!(f, ", {expiry} {}", not_after.format("%Y-%m-%d"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Accepts whatever certificate the server presents, while still checking
+/// the handshake signatures are made with it.
+#[derive(Debug)]
+struct AcceptAnyCertificate(WebPkiSupportedAlgorithms);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.0)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.0)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_schemes()
+    }
+}
+
+fn client_config() -> Result<Arc<ClientConfig>> {
+    let provider = Arc::new(ring::default_provider());
+    let algorithms = provider.signature_verification_algorithms;
+    let mut config = ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(rustls::ALL_VERSIONS)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(algorithms)))
+        .with_no_client_auth();
+    config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|p| p.to_vec()).collect();
+    Ok(Arc::new(config))
+}
+
+fn version_name(version: ProtocolVersion) -> String {
+    match version {
+        ProtocolVersion::TLSv1_2 => "TLS 1.2".to_owned(),
+        ProtocolVersion::TLSv1_3 => "TLS 1.3".to_owned(),
+        other => format!("{other:?}"),
+    }
+}
+
+/// Runs a TLS handshake with `socket`, failing if it doesn't speak TLS.
+pub fn probe(socket: SocketAddr, timeout: Duration) -> Result<TlsInfo> {
+    probe_with(&client_config()?, socket, timeout)
+}
+
+fn probe_with(
+    config: &Arc<ClientConfig>,
+    socket: SocketAddr,
+    timeout: Duration,
+) -> Result<TlsInfo> {
+    let stream = TcpStream::connect_timeout(&socket, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let connection = ClientConnection::new(config.clone(), ServerName::from(socket.ip()))?;
+    let mut stream = StreamOwned::new(connection, stream);
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+
+    let connection = &stream.conn;
+    Ok(TlsInfo {
+        version: connection
+            .protocol_version()
+            .map_or_else(|| "TLS".to_owned(), version_name),
+        cipher_suite: connection
+            .negotiated_cipher_suite()
+            .map(|suite| format!("{:?}", suite.suite()))
+            .unwrap_or_default(),
+        alpn: connection
+            .alpn_protocol()
+            .map(|alpn| String::from_utf8_lossy(alpn).into_owned()),
+        certificate: connection
+            .peer_certificates()
+            .and_then(|chain| chain.first())
+            .and_then(|certificate| Certificate::parse(certificate).ok()),
+    })
+}
+
+/// Probes every socket, a batch at a time, returning the ones that speak
+/// TLS in the order given.
+pub fn probe_all(sockets: &[SocketAddr], timeout: Duration) -> Vec<(SocketAddr, TlsInfo)> {
+    let Ok(config) = client_config() else {
+        return Vec::new();
+    };
+    let mut results = Vec::new();
+    for batch in sockets.chunks(PARALLEL_PROBES) {
+        thread::scope(|scope| {
+            let probes: Vec<_> = batch
+                .iter()
+                .map(|socket| {
+                    let config = &config;
+                    scope.spawn(move || (*socket, probe_with(config, *socket, timeout)))
+                })
+                .collect();
+            for probe in probes {
+                if let Ok((socket, Ok(info))) = probe.join() {
+                    results.push((socket, info));
+                }
+            }
+        });
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{probe, probe_all};
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+    use rustls::{ServerConfig, ServerConnection, StreamOwned};
+    use std::io::Read;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Serves a single TLS handshake with a self-signed certificate.
+    fn serve_tls() -> SocketAddr {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let key =
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+        let chain = vec![CertificateDer::from(certified.cert.der().to_vec())];
+        let mut config =
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(chain, key)
+                .unwrap();
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let connection = ServerConnection::new(Arc::new(config)).unwrap();
+            let mut stream = StreamOwned::new(connection, stream);
+            let _ = stream.read(&mut [0; 1]);
+        });
+        address
+    }
+
+    #[test]
+    fn probes_tls_ports() {
+        let info = probe(serve_tls(), Duration::from_secs(5)).unwrap();
+        assert_eq!(info.version, "TLS 1.3");
+        assert!(info.cipher_suite.starts_with("TLS13_"));
+        assert_eq!(info.alpn.as_deref(), Some("http/1.1"));
+        let certificate = info.certificate.clone().unwrap();
+        assert!(certificate.is_self_signed());
+        assert_eq!(certificate.names, vec!["localhost"]);
+        assert!(certificate.not_after.is_some());
+        assert!(info.to_string().contains("self-signed"));
+    }
+
+    #[test]
+    fn skips_ports_without_tls() {
+        let plain = TcpListener::bind("127.0.0.1:0").unwrap();
+        let plain_address = plain.local_addr().unwrap();
+        thread::spawn(move || {
+            let (_stream, _) = plain.accept().unwrap();
+        });
+        let tls_address = serve_tls();
+
+        let results = probe_all(&[plain_address, tls_address], Duration::from_secs(5));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, tls_address);
+    }
+}
@@ -1,23 +1,50 @@
 //! Utilities for terminal output during scanning.
+//!
+//! Warnings go to stderr, so they never end up among the results read from
+//! stdout, and are kept for the `warnings` of reports, see [`warnings`].
+use std::sync::Mutex;
+
+/// Warnings kept for reports, past which they're only printed.
+const MAX_WARNINGS: usize = 1_000;
+
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Keeps a warning for reports. Used by [`warning!`].
+#[doc(hidden)]
+pub fn record_warning(message: String) {
+    let mut warnings = WARNINGS.lock().unwrap_or_else(|e| e.into_inner());
+    if warnings.len() < MAX_WARNINGS {
+        warnings.push(message);
+    }
+}
+
+/// The warnings given so far, printed or not.
+pub fn warnings() -> Vec<String> {
+    WARNINGS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
 
 /// Terminal User Interface Module for RustScan
 /// Defines macros to use
 #[macro_export]
 macro_rules! warning {
-    ($name:expr) => {
-        println!("{} {}", ansi_term::Colour::Red.bold().paint("[!]"), $name);
-    };
-    ($name:expr, $greppable:expr, $accessible:expr) => {
+    ($name:expr) => {{
+        let message = $name;
+        $crate::tui::record_warning(message.to_string());
+        eprintln!("{} {}", ansi_term::Colour::Red.bold().paint("[!]"), message);
+    }};
+    ($name:expr, $greppable:expr, $accessible:expr) => {{
+        let message = $name;
+        $crate::tui::record_warning(message.to_string());
         // if not greppable then print, otherwise no else statement so do not print.
         if !$greppable {
             if $accessible {
                 // Don't print the ascii art
-                println!("{}", $name);
+                eprintln!("{}", message);
             } else {
-                println!("{} {}", ansi_term::Colour::Red.bold().paint("[!]"), $name);
+                eprintln!("{} {}", ansi_term::Colour::Red.bold().paint("[!]"), message);
             }
         }
-    };
+    }};
 }
 
 #[macro_export]
@@ -104,3 +131,17 @@ macro_rules! funny_opening {
         println!("{}\n", random_quote);
     };
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn warnings_are_kept_even_when_not_printed() {
+        crate::warning!(
+            "Host \"nowhere.invalid\" could not be resolved.",
+            true,
+            false
+        );
+        assert!(super::warnings()
+            .contains(&"Host \"nowhere.invalid\" could not be resolved.".to_owned()));
+    }
+}
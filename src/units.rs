@@ -0,0 +1,214 @@
+//! Formats durations, rates and counts for people or for scripts.
+//!
+//! Durations print as `850ms`, `4.2s` or `2m 13s`, rates as `14.3k pps` and
+//! counts grouped in thousands, with the separators of the locale named by
+//! `LC_ALL`, `LC_NUMERIC` or `LANG` (`1.234,5` in German, `1 234,5` in
+//! French). With `--raw-durations`, text output gives durations as whole
+//! milliseconds and rates as whole numbers instead, for scripts to parse.
+//! Reports carry both, see [`Timing`].
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+static RAW: AtomicBool = AtomicBool::new(false);
+
+/// Makes [`duration`] and [`rate`] give raw numbers, for `--raw-durations`.
+pub fn set_raw_durations(raw: bool) {
+    RAW.store(raw, Ordering::Relaxed);
+}
+
+/// How a locale groups thousands and separates decimals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Separators {
+    pub group: char,
+    pub decimal: char,
+}
+
+impl Separators {
+    pub const ENGLISH: Self = Self {
+        group: ',',
+        decimal: '.',
+    };
+
+    /// The separators of a locale name such as `de_DE.UTF-8`.
+    pub fn of_locale(locale: &str) -> Self {
+        let language = locale.split(['_', '.', '-', '@']).next().unwrap_or("");
+        match language {
+            "de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr" | "el" | "ro" => Self {
+                group: '.',
+                decimal: ',',
+            },
+            "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "no" | "uk" | "hu" => Self {
+                group: ' ',
+                decimal: ',',
+            },
+            _ => Self::ENGLISH,
+        }
+    }
+
+    /// The separators of the locale of the environment.
+    pub fn current() -> Self {
+        static CURRENT: OnceLock<Separators> = OnceLock::new();
+        *CURRENT.get_or_init(|| {
+            ["LC_ALL", "LC_NUMERIC", "LANG"]
+                .iter()
+                .filter_map(|name| std::env::var(name).ok())
+                .find(|locale| !locale.is_empty())
+                .map_or(Self::ENGLISH, |locale| Self::of_locale(&locale))
+        })
+    }
+
+    /// `count` grouped in thousands.
+    pub fn thousands(self, count: u64) -> String {
+        let digits = count.to_string();
+        let mut grouped = String::new();
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                grouped.push(self.group);
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+
+    /// `value` with a single decimal.
+    fn decimal(self, value: f64) -> String {
+        format!("{value:.1}").replace('.', &self.decimal.to_string())
+    }
+
+    /// `850ms`, `4.2s`, `42s`, `2m 13s` or `1h 02m`. Units are picked by
+    /// the rounded value, so 999.6ms is `1.0s` rather than `1000ms`.
+    pub fn duration(self, duration: Duration) -> String {
+        let millis = duration.as_secs_f64() * 1000.0;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let seconds = duration.as_secs_f64().round() as u64;
+        if tenths(millis) < 10.0 {
+            format!("{}ms", self.decimal(millis))
+        } else if millis.round() < 1000.0 {
+            format!("{millis:.0}ms")
+        } else if tenths(duration.as_secs_f64()) < 10.0 {
+            format!("{}s", self.decimal(duration.as_secs_f64()))
+        } else if seconds < 60 {
+            format!("{seconds}s")
+        } else if seconds < 3600 {
+            format!("{}m {}s", seconds / 60, seconds % 60)
+        } else {
+            format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60)
+        }
+    }
+
+    /// `870 pps`, `14.3k pps` or `1.2M pps`, picking units by the rounded
+    /// value as [`Self::duration`] does.
+    pub fn rate(self, per_second: f64, unit: &str) -> String {
+        if per_second.round() < 1e3 {
+            format!("{per_second:.0} {unit}")
+        } else if tenths(per_second / 1e3) < 1e3 {
+            format!("{}k {unit}", self.decimal(per_second / 1e3))
+        } else if tenths(per_second / 1e6) < 1e3 {
+            format!("{}M {unit}", self.decimal(per_second / 1e6))
+        } else {
+            format!("{}G {unit}", self.decimal(per_second / 1e9))
+        }
+    }
+}
+
+/// `value` rounded to a single decimal, as [`Separators::decimal`] prints it.
+fn tenths(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+/// `count` grouped in thousands, as the locale does.
+pub fn thousands(count: u64) -> String {
+    Separators::current().thousands(count)
+}
+
+/// `duration` for text output: readable, or whole milliseconds with
+/// `--raw-durations`.
+pub fn duration(duration: Duration) -> String {
+    if RAW.load(Ordering::Relaxed) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        Separators::current().duration(duration)
+    }
+}
+
+/// `per_second` for text output: readable, or a whole number with
+/// `--raw-durations`.
+pub fn rate(per_second: f64, unit: &str) -> String {
+    if RAW.load(Ordering::Relaxed) {
+        format!("{per_second:.0} {unit}")
+    } else {
+        Separators::current().rate(per_second, unit)
+    }
+}
+
+/// How long a scan took and how fast it went, for reports.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timing {
+    pub duration_ms: u64,
+    /// `duration_ms`, readable.
+    pub duration: String,
+    /// Probes sent, including retries.
+    pub probes: u64,
+    pub probes_per_second: u64,
+    /// `probes_per_second`, readable.
+    pub rate: String,
+}
+
+impl Timing {
+    pub fn new(elapsed: Duration, probes: u64) -> Self {
+        #[allow(clippy::cast_precision_loss)]
+        let per_second = probes as f64 / elapsed.as_secs_f64().max(0.001);
+        let separators = Separators::current();
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        Self {
+            duration_ms: u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX),
+            duration: separators.duration(elapsed),
+            probes,
+            probes_per_second: per_second.round() as u64,
+            rate: separators.rate(per_second, "pps"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Separators;
+    use std::time::Duration;
+
+    #[test]
+    fn durations_and_rates_read_well() {
+        let english = Separators::ENGLISH;
+        assert_eq!(english.duration(Duration::from_micros(240)), "0.2ms");
+        assert_eq!(english.duration(Duration::from_millis(850)), "850ms");
+        assert_eq!(english.duration(Duration::from_millis(4_240)), "4.2s");
+        assert_eq!(english.duration(Duration::from_secs(42)), "42s");
+        assert_eq!(english.duration(Duration::from_secs(133)), "2m 13s");
+        assert_eq!(english.duration(Duration::from_secs(3_720)), "1h 02m");
+        // Rounding up to the next unit switches to it.
+        assert_eq!(english.duration(Duration::from_micros(9_960)), "10ms");
+        assert_eq!(english.duration(Duration::from_micros(999_600)), "1.0s");
+        assert_eq!(english.duration(Duration::from_millis(9_960)), "10s");
+        assert_eq!(english.duration(Duration::from_millis(59_700)), "1m 0s");
+        assert_eq!(english.rate(999.6, "pps"), "1.0k pps");
+        assert_eq!(english.rate(999_960.0, "pps"), "1.0M pps");
+        assert_eq!(english.rate(870.2, "pps"), "870 pps");
+        assert_eq!(english.rate(14_321.0, "pps"), "14.3k pps");
+        assert_eq!(english.rate(1_240_000.0, "pps"), "1.2M pps");
+        assert_eq!(english.thousands(1_234_567), "1,234,567");
+        assert_eq!(english.thousands(999), "999");
+    }
+
+    #[test]
+    fn separators_follow_the_locale() {
+        let german = Separators::of_locale("de_DE.UTF-8");
+        assert_eq!(german.thousands(1_234_567), "1.234.567");
+        assert_eq!(german.duration(Duration::from_millis(4_240)), "4,2s");
+        assert_eq!(german.rate(14_321.0, "pps"), "14,3k pps");
+        assert_eq!(Separators::of_locale("fr_FR").thousands(2_041), "2 041");
+        assert_eq!(Separators::of_locale("C.UTF-8"), Separators::ENGLISH);
+        assert_eq!(Separators::of_locale("en_US"), Separators::ENGLISH);
+    }
+}
@@ -0,0 +1,100 @@
+//! Opt-in check for newer RustScan releases.
+//!
+//! Disabled unless `update_check = true` is set in the config file (or
+//! `--update-check` is passed). When enabled, a single anonymous HTTPS GET
+//! fetches the latest release tag, nothing about the scan or the machine is
+//! sent, and a notice is printed if a newer version exists.
+use crate::http::HttpClient;
+use crate::input::Opts;
+use anyhow::{anyhow, Result};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Where the latest release is described, its `tag_name` holds the version.
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/RustScan/RustScan/releases/latest";
+
+/// The check must never hold up a scan for long.
+const UPDATE_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Starts the update check in the background if it's enabled. Joining the
+/// handle yields the newer version, if there is one.
+pub fn spawn_check(opts: &Opts) -> Option<JoinHandle<Option<String>>> {
+    if !opts.update_check {
+        return None;
+    }
+    let client = HttpClient::new_with_timeout(!opts.no_proxy, UPDATE_CHECK_TIMEOUT);
+    Some(thread::spawn(move || {
+        match latest_version(&client, LATEST_RELEASE_URL) {
+            Ok(latest) if is_newer(&latest, env!("CARGO_PKG_VERSION")) => Some(latest),
+            Ok(_) => None,
+            Err(e) => {
+                log::debug!("Update check failed {e}");
+                None
+            }
+        }
+    }))
+}
+
+/// Fetches the version of the latest release from `url`.
+pub fn latest_version(client: &HttpClient, url: &str) -> Result<String> {
+    let body = client.get(url)?;
+    let release: serde_json::Value = serde_json::from_str(&body)?;
+    release["tag_name"]
+        .as_str()
+        .map(|tag| tag.trim_start_matches('v').to_owned())
+        .ok_or_else(|| anyhow!("no tag_name in the release from {url}"))
+}
+
+/// Whether the dotted version `latest` is greater than `current`.
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split(['.', '-'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+    parse(latest) > parse(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_newer, latest_version};
+    use crate::http::HttpClient;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn compares_versions() {
+        assert!(is_newer("2.4.2", "2.4.1"));
+        assert!(is_newer("v3.0.0", "2.4.1"));
+        assert!(is_newer("2.10.0", "2.9.0"));
+        assert!(!is_newer("2.4.1", "2.4.1"));
+        assert!(!is_newer("2.3.9", "2.4.1"));
+        assert!(!is_newer("garbage", "2.4.1"));
+    }
+
+    #[test]
+    fn reads_tag_name_of_release() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/latest", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+            let body = r#"{"tag_name": "v9.9.9", "name": "RustScan 9.9.9"}"#;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+
+        let version = latest_version(&HttpClient::new(false), &url).unwrap();
+        server.join().unwrap();
+        assert_eq!(version, "9.9.9");
+    }
+}
@@ -0,0 +1,284 @@
+//! Uploads the files a scan produced to S3 or Google Cloud Storage.
+//!
+//! With `--upload s3://bucket/prefix/` (or `gs://`), the file outputs and
+//! the output of every script are copied under `prefix/<scan id>/` once the
+//! scan is done, so disposable scan boxes don't lose their evidence. Like
+//! `--cloud`, this goes through the provider's CLI (`aws` or `gcloud`) and
+//! the credentials it's configured with.
+//!
+//! `--upload-sse` picks the server-side encryption: `AES256`, `aws:kms` or
+//! `aws:kms:<key id>` for S3, or the name of a Cloud KMS key for GCS.
+use crate::export::ScanReport;
+use anyhow::{anyhow, Result};
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// A bucket and prefix to upload to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadTarget {
+    /// `s3` or `gs`.
+    pub scheme: String,
+    pub bucket: String,
+    pub prefix: String,
+}
+
+impl FromStr for UploadTarget {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = input
+            .split_once("://")
+            .filter(|(scheme, _)| *scheme == "s3" || *scheme == "gs")
+            .ok_or_else(|| format!("upload target {input:?} should start with s3:// or gs://"))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(format!("upload target {input:?} has no bucket"));
+        }
+        Ok(Self {
+            scheme: scheme.to_owned(),
+            bucket: bucket.to_owned(),
+            prefix: prefix.trim_matches('/').to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for UploadTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}/", self.scheme, self.bucket)?;
+        if !self.prefix.is_empty() {
+            write!(f, "{}/", self.prefix)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for UploadTarget {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for UploadTarget {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl UploadTarget {
+    /// The URL a file named `name` of the scan `scan_id` is uploaded to.
+    fn object_url(&self, scan_id: &str, name: &str) -> String {
+        format!("{self}{scan_id}/{name}")
+    }
+
+    /// The CLI invocation copying `source` to `destination`.
+    fn copy_command(
+        &self,
+        source: &Path,
+        destination: &str,
+        recursive: bool,
+        sse: Option<&str>,
+    ) -> (&'static str, Vec<String>) {
+        let source = source.display().to_string();
+        if self.scheme == "s3" {
+            let mut args = vec![
+                "s3".to_owned(),
+                "cp".to_owned(),
+                source,
+                destination.to_owned(),
+            ];
+            if recursive {
+                args.push("--recursive".to_owned());
+            }
+            match sse {
+                Some(kms) if kms.starts_with("aws:kms") => {
+                    args.extend(["--sse".to_owned(), "aws:kms".to_owned()]);
+                    if let Some(key) = kms.strip_prefix("aws:kms:") {
+                        args.extend(["--sse-kms-key-id".to_owned(), key.to_owned()]);
+                    }
+                }
+                Some(algorithm) => args.extend(["--sse".to_owned(), algorithm.to_owned()]),
+                None => {}
+            }
+            ("aws", args)
+        } else {
+            let mut args = vec![
+                "storage".to_owned(),
+                "cp".to_owned(),
+                source,
+                destination.to_owned(),
+            ];
+            if recursive {
+                args.push("--recursive".to_owned());
+            }
+            if let Some(key) = sse {
+                args.push(format!("--encryption-key={key}"));
+            }
+            ("gcloud", args)
+        }
+    }
+}
+
+/// Uploads `files` and the script output of `report` under the scan's ID.
+#[cfg(not(tarpaulin_include))]
+pub fn upload(
+    target: &UploadTarget,
+    report: &ScanReport,
+    files: &[PathBuf],
+    sse: Option<&str>,
+) -> Result<Vec<String>> {
+    let mut uploaded = Vec::new();
+    for file in files {
+        let name = file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("{} is not a file", file.display()))?;
+        let destination = target.object_url(&report.scan_id, name);
+        copy(target, file, &destination, false, sse)?;
+        uploaded.push(destination);
+    }
+
+    if let Some(scripts) = write_script_output(report)? {
+        let destination = target.object_url(&report.scan_id, "scripts/");
+        let result = copy(target, &scripts, &destination, true, sse);
+        let _ = fs::remove_dir_all(&scripts);
+        result?;
+        uploaded.push(destination);
+    }
+    Ok(uploaded)
+}
+
+#[cfg(not(tarpaulin_include))]
+fn copy(
+    target: &UploadTarget,
+    source: &Path,
+    destination: &str,
+    recursive: bool,
+    sse: Option<&str>,
+) -> Result<()> {
+    let (program, args) = target.copy_command(source, destination, recursive, sse);
+    let output = Command::new(program)
+        .args(&args)
+        .output()
+        .map_err(|e| anyhow!("Could not run {program}, is its CLI installed? {e}"))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Uploading {} failed: {}",
+            source.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Writes the script output of every host to `<ip>.txt` in a temporary
+/// folder, returning it if there was any output.
+fn write_script_output(report: &ScanReport) -> Result<Option<PathBuf>> {
+    let hosts: Vec<_> = report
+        .hosts
+        .iter()
+        .filter(|host| !host.script_output.is_empty())
+        .collect();
+    if hosts.is_empty() {
+        return Ok(None);
+    }
+    let dir = std::env::temp_dir().join(format!("rustscan-scripts-{}", report.scan_id));
+    fs::create_dir_all(&dir)?;
+    for host in hosts {
+        fs::write(
+            dir.join(format!("{}.txt", host.ip)),
+            host.script_output.join("\n"),
+        )?;
+    }
+    Ok(Some(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_script_output, UploadTarget};
+    use crate::export::{HostReport, ScanReport};
+    use chrono::Utc;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn parse_upload_targets() {
+        let target: UploadTarget = "s3://evidence/scans/".parse().unwrap();
+        assert_eq!(target.bucket, "evidence");
+        assert_eq!(target.prefix, "scans");
+        assert_eq!(target.to_string(), "s3://evidence/scans/");
+        assert_eq!(
+            target.object_url("id", "results.json"),
+            "s3://evidence/scans/id/results.json"
+        );
+
+        let target: UploadTarget = "gs://evidence".parse().unwrap();
+        assert_eq!(target.object_url("id", "a.json"), "gs://evidence/id/a.json");
+
+        assert!("https://evidence".parse::<UploadTarget>().is_err());
+        assert!("s3://".parse::<UploadTarget>().is_err());
+    }
+
+    #[test]
+    fn copy_commands_with_encryption() {
+        let s3: UploadTarget = "s3://evidence".parse().unwrap();
+        let (program, args) = s3.copy_command(
+            Path::new("a.json"),
+            "s3://evidence/a.json",
+            false,
+            Some("aws:kms:key-1"),
+        );
+        assert_eq!(program, "aws");
+        assert_eq!(
+            args,
+            vec![
+                "s3",
+                "cp",
+                "a.json",
+                "s3://evidence/a.json",
+                "--sse",
+                "aws:kms",
+                "--sse-kms-key-id",
+                "key-1"
+            ]
+        );
+        let (_, args) =
+            s3.copy_command(Path::new("dir"), "s3://evidence/dir/", true, Some("AES256"));
+        assert_eq!(args[4..], ["--recursive", "--sse", "AES256"]);
+
+        let gs: UploadTarget = "gs://evidence".parse().unwrap();
+        let (program, args) = gs.copy_command(
+            Path::new("a.json"),
+            "gs://evidence/a.json",
+            false,
+            Some("projects/p/locations/l/keyRings/r/cryptoKeys/k"),
+        );
+        assert_eq!(program, "gcloud");
+        assert_eq!(
+            args.last().unwrap(),
+            "--encryption-key=projects/p/locations/l/keyRings/r/cryptoKeys/k"
+        );
+    }
+
+    #[test]
+    fn script_output_per_host() {
+        let mut report = ScanReport::new(Utc::now(), false);
+        assert!(write_script_output(&report).unwrap().is_none());
+
+        report.hosts.push(HostReport {
+            ip: "127.0.0.1".parse().unwrap(),
+            ports: vec![22],
+            script_output: vec!["first".to_owned(), "second".to_owned()],
+        });
+        let dir = write_script_output(&report).unwrap().unwrap();
+        let output = fs::read_to_string(dir.join("127.0.0.1.txt")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(output, "first\nsecond");
+    }
+}
@@ -0,0 +1,278 @@
+//! Flags services whose banner names a version with known vulnerabilities.
+//!
+//! With `--vuln-hints`, once the scan is done, every open port gets a plain
+//! HTTP request, which web servers answer with a `Server` header and
+//! services that speak first (SSH, FTP, SMTP) with their banner. Products
+//! and versions recognised there are matched against a small offline list
+//! of advisories built into RustScan, see `vulns.toml` in the repository,
+//! and the matches are printed and kept in `vuln_hints` in reports.
+//!
+//! These are heuristics from the version alone: distributions backport
+//! fixes without changing the version in the banner, and a vulnerable
+//! module may not be enabled, so every hint is marked as version-based and
+//! needs checking. `--vuln-data` adds advisories from a file in the same
+//! format, replacing the built-in ones with the same CVE.
+use crate::nuclei;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// The advisories built into RustScan.
+const ADVISORIES: &str = include_str!("../vulns.toml");
+
+/// Number of banners grabbed at the same time.
+const PARALLEL_PROBES: usize = 64;
+
+/// What a product's version follows in its banner, and the product's name.
+const PRODUCTS: [(&str, &str); 9] = [
+    ("SSH-2.0-OpenSSH_", "OpenSSH"),
+    ("SSH-2.0-dropbear_", "Dropbear"),
+    ("vsFTPd ", "vsftpd"),
+    ("ProFTPD ", "ProFTPD"),
+    ("Exim ", "Exim"),
+    ("Apache/", "Apache httpd"),
+    ("nginx/", "nginx"),
+    ("Microsoft-IIS/", "IIS"),
+    ("lighttpd/", "lighttpd"),
+];
+
+/// How every hint is labelled, in reports too.
+pub const BASIS: &str = "version-based heuristic";
+
+/// A product and version named in a banner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    pub product: String,
+    pub version: String,
+}
+
+/// A known vulnerability of a range of versions of a product.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Advisory {
+    pub product: String,
+    pub cve: String,
+    pub severity: String,
+    /// The first affected version, every version before `fixed` if absent.
+    #[serde(default)]
+    pub introduced: Option<String>,
+    pub fixed: String,
+    pub summary: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Advisories {
+    #[serde(default)]
+    advisory: Vec<Advisory>,
+}
+
+/// A service that may be affected by an advisory, going by its version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hint {
+    pub socket: SocketAddr,
+    pub product: String,
+    pub version: String,
+    pub cve: String,
+    pub severity: String,
+    pub summary: String,
+    /// Always [`BASIS`], so the hint isn't mistaken for a confirmed finding.
+    pub basis: String,
+}
+
+/// The product and version named in `answer`, if it's one of [`PRODUCTS`].
+pub fn identify(answer: &[u8]) -> Option<Service> {
+    let text = String::from_utf8_lossy(answer);
+    PRODUCTS.iter().find_map(|(marker, product)| {
+        let (_, rest) = text.split_once(marker)?;
+        let version: String = rest
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '.')
+            .collect();
+        version
+            .starts_with(|c: char| c.is_ascii_digit())
+            .then(|| Service {
+                product: (*product).to_owned(),
+                version,
+            })
+    })
+}
+
+/// The numbers in `version`, `7.4p1` being `[7, 4, 1]`.
+fn numbers(version: &str) -> Vec<u64> {
+    version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|number| number.parse().ok())
+        .collect()
+}
+
+fn compare(left: &str, right: &str) -> Ordering {
+    numbers(left).cmp(&numbers(right))
+}
+
+impl Advisory {
+    /// Whether `service` has a version this advisory is about.
+    pub fn affects(&self, service: &Service) -> bool {
+        self.product.eq_ignore_ascii_case(&service.product)
+            && self
+                .introduced
+                .as_deref()
+                .is_none_or(|introduced| compare(&service.version, introduced).is_ge())
+            && compare(&service.version, &self.fixed).is_lt()
+    }
+}
+
+fn parse(text: &str) -> Result<Vec<Advisory>, String> {
+    toml::from_str::<Advisories>(text)
+        .map(|advisories| advisories.advisory)
+        .map_err(|e| e.to_string())
+}
+
+/// The built-in advisories, with those of `extra` added and replacing the
+/// built-in ones of the same CVE.
+pub fn advisories(extra: Option<&Path>) -> Result<Vec<Advisory>, String> {
+    let mut advisories = parse(ADVISORIES).expect("the built-in advisories are valid");
+    if let Some(path) = extra {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let extra = parse(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        advisories.retain(|advisory| !extra.iter().any(|new| new.cve == advisory.cve));
+        advisories.extend(extra);
+    }
+    Ok(advisories)
+}
+
+/// The hints for the services found on each socket.
+pub fn check(services: &BTreeMap<SocketAddr, Service>, advisories: &[Advisory]) -> Vec<Hint> {
+    services
+        .iter()
+        .flat_map(|(socket, service)| {
+            advisories
+                .iter()
+                .filter(move |advisory| advisory.affects(service))
+                .map(move |advisory| Hint {
+                    socket: *socket,
+                    product: service.product.clone(),
+                    version: service.version.clone(),
+                    cve: advisory.cve.clone(),
+                    severity: advisory.severity.clone(),
+                    summary: advisory.summary.clone(),
+                    basis: BASIS.to_owned(),
+                })
+        })
+        .collect()
+}
+
+/// The services named in the banners of `sockets`, a batch at a time.
+pub fn services(sockets: &[SocketAddr], timeout: Duration) -> BTreeMap<SocketAddr, Service> {
+    let mut services = BTreeMap::new();
+    for batch in sockets.chunks(PARALLEL_PROBES) {
+        thread::scope(|scope| {
+            let probes: Vec<_> = batch
+                .iter()
+                .map(|socket| {
+                    scope.spawn(move || {
+                        let answer = nuclei::plain_request(*socket, timeout).unwrap_or_default();
+                        (*socket, identify(&answer))
+                    })
+                })
+                .collect();
+            for probe in probes {
+                if let Ok((socket, Some(service))) = probe.join() {
+                    services.insert(socket, service);
+                }
+            }
+        });
+    }
+    services
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{advisories, check, identify, Service};
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    fn service(product: &str, version: &str) -> Service {
+        Service {
+            product: product.to_owned(),
+            version: version.to_owned(),
+        }
+    }
+
+    #[test]
+    fn banners_name_products() {
+        assert_eq!(
+            identify(b"SSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.6\r\n"),
+            Some(service("OpenSSH", "8.9p1"))
+        );
+        assert_eq!(
+            identify(b"220 (vsFTPd 2.3.4)\r\n"),
+            Some(service("vsftpd", "2.3.4"))
+        );
+        assert_eq!(
+            identify(b"HTTP/1.1 400 Bad Request\r\nServer: Apache/2.4.49 (Unix)\r\n\r\n"),
+            Some(service("Apache httpd", "2.4.49"))
+        );
+        assert_eq!(identify(b"HTTP/1.1 200 OK\r\nServer: nginx\r\n\r\n"), None);
+        assert_eq!(identify(b"+PONG\r\n"), None);
+    }
+
+    #[test]
+    fn user_advisories_replace_built_in_ones() {
+        let path = std::env::temp_dir().join(format!("rustscan-vulns-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "[[advisory]]\nproduct = \"OpenSSH\"\ncve = \"CVE-2024-6387\"\nseverity = \"high\"\n\
+             introduced = \"8.5p1\"\nfixed = \"9.9p1\"\nsummary = \"regreSSHion\"\n\n\
+             [[advisory]]\nproduct = \"lighttpd\"\ncve = \"CVE-0000-0001\"\nseverity = \"low\"\n\
+             fixed = \"1.5\"\nsummary = \"Local test\"\n",
+        )
+        .unwrap();
+        let advisories = advisories(Some(&path));
+        fs::write(&path, "[[advisory]]\nproduct = \"nginx\"\n").unwrap();
+        assert!(super::advisories(Some(&path)).is_err());
+        fs::remove_file(&path).unwrap();
+
+        let advisories = advisories.unwrap();
+        let regresshion: Vec<_> = advisories
+            .iter()
+            .filter(|advisory| advisory.cve == "CVE-2024-6387")
+            .collect();
+        assert_eq!(regresshion.len(), 1);
+        assert!(regresshion[0].affects(&service("OpenSSH", "9.8p1")));
+        assert!(advisories
+            .iter()
+            .any(|advisory| advisory.affects(&service("lighttpd", "1.4.76"))));
+    }
+
+    #[test]
+    fn versions_in_range_get_hints() {
+        let advisories = advisories(None).unwrap();
+        let mut services = BTreeMap::new();
+        services.insert("10.0.0.1:22".parse().unwrap(), service("OpenSSH", "9.3p1"));
+        services.insert(
+            "10.0.0.1:80".parse().unwrap(),
+            service("Apache httpd", "2.4.50"),
+        );
+        services.insert("10.0.0.2:22".parse().unwrap(), service("OpenSSH", "9.8p1"));
+        let hints: Vec<String> = check(&services, &advisories)
+            .iter()
+            .map(|hint| format!("{} {}", hint.socket, hint.cve))
+            .collect();
+        assert_eq!(
+            hints,
+            vec![
+                "10.0.0.1:22 CVE-2024-6387",
+                "10.0.0.1:22 CVE-2023-38408",
+                "10.0.0.1:22 CVE-2023-48795",
+                "10.0.0.1:80 CVE-2021-42013",
+                "10.0.0.1:80 CVE-2021-44790",
+            ]
+        );
+    }
+}
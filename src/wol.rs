@@ -0,0 +1,150 @@
+//! Wakes sleeping targets with Wake-on-LAN before scanning them.
+//!
+//! A machine asleep doesn't answer, so an inventory scan of a lab or an
+//! office misses it. With `--wol`, RustScan sends a magic packet to every
+//! target listed in a mapping file, one `host mac` pair per line, then waits
+//! `--wol-wait` seconds for them to boot before scanning:
+//!
+//! ```text
+//! # lab machines
+//! 192.168.1.20 00:1b:2c:3d:4e:5f
+//! build-01.lab 00-1b-2c-3d-4e-60
+//! ```
+//!
+//! Magic packets are broadcast on the local network to UDP port 9, and sent
+//! to the target itself, which reaches it while its ARP entry is still
+//! cached. Waking machines on other networks needs directed broadcasts to be
+//! forwarded by the routers in between.
+use crate::address::{get_resolver, parse_single_excluded_address};
+use crate::neighbors::Mac;
+use anyhow::{anyhow, Result};
+use cidr_utils::cidr::IpCidr;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::path::Path;
+
+/// The discard port, where magic packets are conventionally sent.
+const WOL_PORT: u16 = 9;
+
+/// The MAC addresses of the machines that can be woken up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WakeList {
+    entries: Vec<(IpCidr, Mac)>,
+}
+
+impl WakeList {
+    /// Reads the mapping file at `path`, resolving hostnames with
+    /// `resolver` (see `--resolver`).
+    pub fn read(path: &Path, resolver: &Option<String>) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read {}: {e}", path.display()))?;
+        Self::parse(&content, resolver)
+    }
+
+    fn parse(content: &str, resolver: &Option<String>) -> Result<Self> {
+        let mut resolver_cache = None;
+        let mut entries = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (host, mac) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| anyhow!("Line {}: {line} should be 'host mac'", number + 1))?;
+            let mac: Mac = mac
+                .trim()
+                .parse()
+                .map_err(|e| anyhow!("Line {}: {e}", number + 1))?;
+            let resolver = resolver_cache.get_or_insert_with(|| get_resolver(resolver));
+            let networks = parse_single_excluded_address(host, resolver);
+            if networks.is_empty() {
+                return Err(anyhow!("Line {}: {host} could not be resolved", number + 1));
+            }
+            entries.extend(networks.into_iter().map(|network| (network, mac)));
+        }
+        Ok(Self { entries })
+    }
+
+    /// The targets among `ips` in the list, with their MAC address.
+    pub fn targets(&self, ips: &[IpAddr]) -> Vec<(IpAddr, Mac)> {
+        ips.iter()
+            .filter_map(|ip| {
+                self.entries
+                    .iter()
+                    .find(|(network, _)| network.contains(ip))
+                    .map(|(_, mac)| (*ip, *mac))
+            })
+            .collect()
+    }
+}
+
+/// Six `0xff` bytes, then the MAC address 16 times.
+pub fn magic_packet(mac: Mac) -> [u8; 102] {
+    let mut packet = [0xff; 102];
+    for chunk in packet[6..].chunks_exact_mut(6) {
+        chunk.copy_from_slice(&mac.0);
+    }
+    packet
+}
+
+/// Sends a magic packet for every target, returns how many were sent.
+#[cfg(not(tarpaulin_include))]
+pub fn wake(targets: &[(IpAddr, Mac)]) -> Result<usize> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+    let broadcast = SocketAddr::from((Ipv4Addr::BROADCAST, WOL_PORT));
+    for (ip, mac) in targets {
+        let packet = magic_packet(*mac);
+        socket
+            .send_to(&packet, broadcast)
+            .map_err(|e| anyhow!("Could not broadcast a magic packet: {e}"))?;
+        if ip.is_ipv4() {
+            // Unicast only gets there while the ARP cache still has the MAC.
+            let _ = socket.send_to(&packet, SocketAddr::new(*ip, WOL_PORT));
+        }
+    }
+    Ok(targets.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{magic_packet, WakeList};
+    use std::net::IpAddr;
+
+    #[test]
+    fn build_magic_packets() {
+        let packet = magic_packet("00:1b:2c:3d:4e:5f".parse().unwrap());
+        assert_eq!(packet[..6], [0xff; 6]);
+        assert_eq!(packet[6..12], [0x00, 0x1b, 0x2c, 0x3d, 0x4e, 0x5f]);
+        assert_eq!(packet[96..], packet[6..12]);
+    }
+
+    #[test]
+    fn targets_in_mapping_file() {
+        let list = WakeList::parse(
+            "# lab\n192.168.1.20 00:1b:2c:3d:4e:5f\n192.168.2.0/24\t00-1b-2c-3d-4e-60 # rack\n",
+            &None,
+        )
+        .unwrap();
+        let ips: Vec<IpAddr> = ["192.168.1.20", "192.168.1.21", "192.168.2.7"]
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        let targets: Vec<String> = list
+            .targets(&ips)
+            .iter()
+            .map(|(ip, mac)| format!("{ip} {mac}"))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                "192.168.1.20 00:1b:2c:3d:4e:5f",
+                "192.168.2.7 00:1b:2c:3d:4e:60"
+            ]
+        );
+
+        assert!(WakeList::parse("192.168.1.20\n", &None).is_err());
+        assert!(WakeList::parse("192.168.1.20 00:1b:2c\n", &None).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+/*
+ * Scans the local targets of the support module and checks how their
+ * ports are classified, how long silent ones take and how often they're
+ * retried. Nothing leaves the loopback interface.
+ */
+
+mod support;
+
+use futures::executor::block_on;
+use rustscan::export::view::PortState;
+use rustscan::input::ScanOrder;
+use rustscan::port_strategy::PortStrategy;
+use rustscan::scanner::Scanner;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+fn scanner(ports: &[u16], timeout: Duration, tries: u8, udp: bool) -> Scanner {
+    let strategy = PortStrategy::pick(&None, Some(ports.to_vec()), ScanOrder::Serial);
+    Scanner::new(
+        &[LOCALHOST],
+        10,
+        timeout,
+        tries,
+        true,
+        strategy,
+        true,
+        vec![],
+        udp,
+    )
+    .with_port_states(true)
+}
+
+#[test]
+fn tcp_ports_are_open_or_closed() {
+    let echo = support::tcp_echo();
+    let hang = support::tcp_hang();
+    let reset = support::tcp_reset();
+    let closed = support::tcp_closed();
+    let scanner = scanner(
+        &[echo.port(), hang.port(), reset.port(), closed.port()],
+        Duration::from_millis(500),
+        2,
+        false,
+    );
+
+    let mut open = block_on(scanner.run());
+    open.sort_unstable();
+    let mut expected = vec![echo.socket, hang.socket, reset.socket];
+    expected.sort_unstable();
+    // A completed handshake is an open port, whatever comes after it.
+    assert_eq!(open, expected);
+    assert_eq!(
+        scanner.port_states(),
+        BTreeMap::from([(closed, PortState::Closed)])
+    );
+    let stats = scanner.probe_stats()[&LOCALHOST];
+    assert_eq!((stats.open, stats.refused, stats.timeouts), (3, 2, 0));
+    // Open ports are connected to once.
+    assert_eq!((echo.hits(), hang.hits(), reset.hits()), (1, 1, 1));
+}
+
+#[test]
+fn udp_ports_are_open_closed_or_filtered() {
+    let responder = support::udp_responder(b"pong");
+    let silent = support::udp_silent();
+    let closed = support::udp_closed();
+    let scanner = scanner(
+        &[responder.port(), silent.port(), closed.port()],
+        Duration::from_millis(300),
+        2,
+        true,
+    );
+
+    assert_eq!(block_on(scanner.run()), [responder.socket]);
+    assert_eq!(
+        scanner.port_states(),
+        BTreeMap::from([
+            (silent.socket, PortState::Filtered),
+            (closed, PortState::Closed)
+        ])
+    );
+    let stats = scanner.probe_stats()[&LOCALHOST];
+    assert_eq!((stats.open, stats.refused), (1, 1));
+    // Only the silent port is tried again.
+    assert_eq!(silent.hits(), 2);
+    assert_eq!(stats.retries, 1);
+    assert_eq!(responder.hits(), 1);
+}
+
+#[test]
+fn silent_ports_cost_the_timeout() {
+    let hang = support::tcp_hang();
+    let timeout = Duration::from_millis(400);
+    let scanner = scanner(&[hang.port()], timeout, 1, false).with_banners(Some(64));
+
+    let started = Instant::now();
+    assert_eq!(block_on(scanner.run()), [hang.socket]);
+    let elapsed = started.elapsed();
+    assert!(elapsed >= timeout, "took {:?}", elapsed);
+    assert!(elapsed < timeout * 10, "took {:?}", elapsed);
+    assert!(scanner.banners().is_empty());
+}
+
+#[test]
+fn open_ports_answer_payloads_and_send_banners() {
+    let echo = support::tcp_echo();
+    let ssh = support::tcp_banner(b"SSH-2.0-OpenSSH_9.6\r\n");
+    let scanner = scanner(
+        &[echo.port(), ssh.port()],
+        Duration::from_millis(1000),
+        1,
+        false,
+    )
+    .with_probe_payloads(vec![format!("{}=text:PING", echo.port()).parse().unwrap()])
+    .with_banners(Some(64));
+
+    assert_eq!(block_on(scanner.run()).len(), 2);
+    assert_eq!(scanner.probe_responses()[&echo.socket].snippet, "PING");
+    assert_eq!(
+        scanner.banners(),
+        BTreeMap::from([
+            (echo.socket, "PING".to_owned()),
+            (ssh.socket, r"SSH-2.0-OpenSSH_9.6\x0d\x0a".to_owned())
+        ])
+    );
+    assert!(scanner.port_states().is_empty());
+}
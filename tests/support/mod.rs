@@ -0,0 +1,136 @@
+/*
+ * Local targets for scanner tests, each on 127.0.0.1 and a port of its own.
+ *
+ * They behave like what a scan runs into: services that answer, accept and
+ * then hang, reset every connection, or aren't there. Every target runs on
+ * its own threads until the test process exits, and counts the connections
+ * or datagrams it got, so retries can be checked too.
+ */
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// A listening target.
+pub struct Target {
+    pub socket: SocketAddr,
+    hits: Arc<AtomicUsize>,
+}
+
+impl Target {
+    /// Connections accepted over TCP, datagrams received over UDP.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::SeqCst)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.socket.port()
+    }
+}
+
+/// Accepts connections on a new port, handling each with `serve` on a
+/// thread of its own.
+fn tcp_target<F: Fn(TcpStream) + Clone + Send + 'static>(serve: F) -> Target {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let socket = listener.local_addr().unwrap();
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&hits);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            counted.fetch_add(1, Ordering::SeqCst);
+            let serve = serve.clone();
+            thread::spawn(move || serve(stream));
+        }
+    });
+    Target { socket, hits }
+}
+
+/// Sends back whatever it gets, until the client closes.
+pub fn tcp_echo() -> Target {
+    tcp_target(|mut stream| {
+        let mut buffer = [0; 1024];
+        while let Ok(length @ 1..) = stream.read(&mut buffer) {
+            if stream.write_all(&buffer[..length]).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+/// Sends `banner` as soon as a client connects, like SSH or SMTP.
+pub fn tcp_banner(banner: &'static [u8]) -> Target {
+    tcp_target(move |mut stream| {
+        let _ = stream.write_all(banner);
+    })
+}
+
+/// Accepts connections, then never reads, writes or closes them, like a
+/// slowloris victim or a tarpit.
+pub fn tcp_hang() -> Target {
+    tcp_target(|stream| {
+        thread::sleep(Duration::from_secs(3600));
+        drop(stream);
+    })
+}
+
+/// Accepts connections and resets them.
+pub fn tcp_reset() -> Target {
+    tcp_target(|stream| {
+        // Over loopback, a RST sent right away can beat the client to
+        // seeing its connection complete, which a network's latency
+        // doesn't allow.
+        thread::sleep(Duration::from_millis(50));
+        // Without lingering, closing sends a RST rather than a FIN.
+        let _ = socket2::SockRef::from(&stream).set_linger(Some(Duration::ZERO));
+        drop(stream);
+    })
+}
+
+/// A port nothing listens on, which refuses connections.
+pub fn tcp_closed() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+/// Answers every datagram with `answer`.
+pub fn udp_responder(answer: &'static [u8]) -> Target {
+    udp_target(Some(answer))
+}
+
+/// Receives datagrams without ever answering, like a port behind a
+/// firewall dropping packets.
+pub fn udp_silent() -> Target {
+    udp_target(None)
+}
+
+fn udp_target(answer: Option<&'static [u8]>) -> Target {
+    let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let socket = udp.local_addr().unwrap();
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&hits);
+    thread::spawn(move || {
+        let mut buffer = [0; 1024];
+        while let Ok((_, from)) = udp.recv_from(&mut buffer) {
+            counted.fetch_add(1, Ordering::SeqCst);
+            if let Some(answer) = answer {
+                let _ = udp.send_to(answer, from);
+            }
+        }
+    });
+    Target { socket, hits }
+}
+
+/// A UDP port nothing is bound to, which answers with an ICMP port
+/// unreachable.
+pub fn udp_closed() -> SocketAddr {
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}